@@ -0,0 +1,79 @@
+use std::{fs, path::Path, time::Duration};
+use clap::Parser;
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use gitqlite::cli::{AddArgs, GitCli, GitCommand};
+use gitqlite::git::cmds::{add::do_add, hash_object::construct_blob_from_file, init::do_init};
+use gitqlite::repo::db::HashAlgorithm;
+use rusqlite::Connection;
+
+const FILE_COUNT: usize = 2000;
+
+/// Create a repository holding FILE_COUNT files under `src`, and move into it
+fn setup_repository(dir: &Path) {
+  fs::write(dir.join(".gitconfig"), "[init]\ndefaultBranch = main\n").unwrap();
+  std::env::set_var("HOME", dir);
+  let repo_root = dir.join("repo");
+  fs::create_dir_all(&repo_root).unwrap();
+  std::env::set_current_dir(&repo_root).unwrap();
+  let GitCommand::Init(args) = GitCli::parse_from(["gitqlite", "init"]).command else {
+    unreachable!()
+  };
+  do_init(args).unwrap();
+
+  for i in 0..FILE_COUNT {
+    let subdir = repo_root.join("src").join(format!("{}", i % 20));
+    fs::create_dir_all(&subdir).unwrap();
+    fs::write(subdir.join(format!("file{}.txt", i)), format!("content of file {}\n", i).repeat(64)).unwrap();
+  }
+}
+
+/// Empty the blobs and the index so that every iteration writes them again
+fn reset(conn: &Connection) {
+  conn.execute_batch("DELETE FROM Blobs; DELETE FROM Index_;").unwrap();
+}
+
+fn add_benchmark(c: &mut Criterion) {
+  let dir = tempfile::tempdir().unwrap();
+  setup_repository(dir.path());
+  let repo_root = dir.path().join("repo");
+  let conn = gitqlite::git::utils::get_gitqlite_connection().unwrap();
+  let mut files = Vec::new();
+  for subdir in fs::read_dir(repo_root.join("src")).unwrap() {
+    for file in fs::read_dir(subdir.unwrap().path()).unwrap() {
+      files.push(file.unwrap().path());
+    }
+  }
+
+  let mut group = c.benchmark_group(format!("Add a directory of {} files", FILE_COUNT));
+  group
+    .sample_size(10)
+    .measurement_time(Duration::from_secs(30))
+    .bench_function(BenchmarkId::new("Baseline (Hash and execute per file)", ""), |bencher| {
+      bencher.iter_batched(
+        || reset(&conn),
+        |_| {
+          for file in &files {
+            construct_blob_from_file(file, HashAlgorithm::Sha1).unwrap().persist(&conn).unwrap();
+          }
+        },
+        BatchSize::PerIteration,
+      )
+    })
+    .bench_function(BenchmarkId::new("gitqlite add <dir>", ""), |bencher| {
+      bencher.iter_batched(
+        || reset(&conn),
+        |_| {
+          do_add(AddArgs {
+            path: repo_root.join("src"),
+            intent_to_add: false,
+          })
+          .unwrap()
+        },
+        BatchSize::PerIteration,
+      )
+    });
+  group.finish();
+}
+
+criterion_group!(benches, add_benchmark);
+criterion_main!(benches);
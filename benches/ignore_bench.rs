@@ -1,4 +1,4 @@
-use std::{collections::VecDeque, fs, hint::black_box, time::Duration};
+use std::{collections::VecDeque, fs, time::Duration};
 use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
 
 fn ignore_benchmark(c: &mut Criterion) {
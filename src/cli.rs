@@ -1,10 +1,20 @@
 use std::path::PathBuf;
 
-use clap::{Args, Parser, Subcommand, ValueEnum};
+use clap::{ArgAction, Args, Parser, Subcommand, ValueEnum};
 
 #[derive(Parser)]
 #[clap(version, about, long_about = None)]
 pub struct GitCli {
+    /// Report the time spent in database queries, hashing and directory walks on stderr (also
+    /// turned on by setting GITQLITE_TRACE_PERF)
+    #[arg(long, global = true)]
+    pub profile: bool,
+
+    /// Run the command without writing to the repository or the working tree, then show
+    /// what it would have changed. Commands writing elsewhere, such as push, refuse it.
+    #[arg(long)]
+    pub dry_run: bool,
+
     #[command(subcommand)]
     pub command: GitCommand,
 }
@@ -21,6 +31,10 @@ pub enum GitCommand {
     LsFiles(LsFilesArgs),
     /// Check whether the file is excluded by .gitignore (or other input files to the exclude mechanism) and output the path if it is excluded.
     CheckIgnore(CheckIgnoreArgs),
+    /// Display the gitattributes information of paths
+    CheckAttr(CheckAttrArgs),
+    /// Ensure that a reference name is well formed
+    CheckRefFormat(CheckRefFormatArgs),
     /// Show the working tree status
     Status(StatusArgs),
     /// Get and set repository or global options
@@ -31,6 +45,126 @@ pub enum GitCommand {
     Add(AddArgs),
     /// Record changes to the repository
     Commit(CommitArgs),
+    /// Show changes between the index and the working tree, or between HEAD and the index
+    Diff(DiffArgs),
+    /// Show changes using an external diff tool
+    Difftool(DifftoolArgs),
+    /// Resolve merge conflicts using an external merge tool
+    Mergetool(MergetoolArgs),
+    /// Join two development histories together
+    Merge(MergeArgs),
+    /// Reapply commits on top of another base tip
+    Rebase(RebaseArgs),
+    /// Apply the changes introduced by some existing commits
+    CherryPick(CherryPickArgs),
+    /// Put the local changes aside to get a clean working tree
+    Stash(StashArgs),
+    /// Create an archive of files from a named tree
+    Archive(ArchiveArgs),
+    /// Move objects and refs by archive
+    Bundle(BundleArgs),
+    /// Export the repository database as a plain-text SQL dump
+    Dump(DumpArgs),
+    /// Create the repository database in the current directory from an SQL dump
+    Load(LoadArgs),
+    /// Export the commit graph for visualization in external tools
+    Graph(GraphArgs),
+    /// Browse the repository in a web browser
+    Web(WebArgs),
+    /// Serve a read-only JSON API over the repository
+    Api(ApiArgs),
+    /// A really simple server for gitqlite repositories
+    Daemon(DaemonArgs),
+    /// Download objects and refs from another repository
+    Fetch(FetchArgs),
+    /// Update remote refs along with associated objects
+    Push(PushArgs),
+    /// Manage the remote-tracking refs of configured remotes
+    Remote(RemoteArgs),
+    /// Answer a fetch or push request read from stdin, run on the remote host by the SSH transport
+    Serve(ServeArgs),
+    /// Add or parse structured information in commit messages
+    InterpretTrailers(InterpretTrailersArgs),
+    /// Check the integrity of the repository database
+    Verify(VerifyArgs),
+    /// Summarize the space used by the repository database
+    Du(DuArgs),
+    /// Count the commits, contributors and changed lines of the history
+    Stats(StatsArgs),
+    /// Search the commit messages, highlighting the matches
+    Search(SearchArgs),
+    /// Summarize the authors of the history, the most active first
+    Contributors(ContributorsArgs),
+    /// Remove or list the objects that are not reachable from any ref
+    Prune(PruneArgs),
+    /// Remove unreachable objects and compact the repository database
+    Gc(GcArgs),
+    /// Keep objects through prune and gc even if no ref leads to them, or list the pinned ones
+    Pin(PinArgs),
+    /// Save HEAD, the refs and the index under a name to restore them later
+    Snapshot(SnapshotArgs),
+    /// Run the hooks of the repository by hand
+    Hook(HookArgs),
+    /// Compare two commit ranges (e.g. two versions of a branch)
+    RangeDiff(RangeDiffArgs),
+    /// Find commits yet to be applied upstream
+    Cherry(CherryArgs),
+    /// Compute unique IDs for patches
+    PatchId(PatchIdArgs),
+    /// Show commit logs
+    Log(LogArgs),
+    /// Show commits, or the content of files and directories named as <rev>:<path>
+    Show(ShowArgs),
+    /// List, create, rename or delete branches, refusing to rewrite protected ones
+    Branch(BranchArgs),
+    /// Switch to a branch, or start a new branch without history
+    Switch(SwitchArgs),
+    /// Switch branches or check out a commit, updating the index and the working tree
+    Checkout(CheckoutArgs),
+    /// Create, list or delete tags
+    Tag(TagArgs),
+    /// Move the current branch to a commit, resetting the index and working tree as asked
+    Reset(ResetArgs),
+    /// Record a new commit undoing the changes of an existing one
+    Revert(RevertArgs),
+    /// Copy files from the index or a tree to the working tree or any other directory
+    CheckoutIndex(CheckoutIndexArgs),
+    /// Helpers for scripts built around gitqlite, such as shell completions
+    #[command(hide = true)]
+    Internal(InternalArgs),
+}
+
+impl GitCommand {
+    /// Whether the command supports `--dry-run`: every write it makes goes to the database of
+    /// the repository or through [`crate::git::dry_run`]
+    pub fn supports_dry_run(&self) -> bool {
+        matches!(
+            self,
+            GitCommand::Add(_)
+                | GitCommand::Rm(_)
+                | GitCommand::Commit(_)
+                | GitCommand::Merge(_)
+                | GitCommand::Rebase(_)
+                | GitCommand::CherryPick(_)
+                | GitCommand::Stash(_)
+                | GitCommand::Archive(_)
+                | GitCommand::Dump(_)
+                | GitCommand::Fetch(_)
+                | GitCommand::InterpretTrailers(_)
+                | GitCommand::Prune(_)
+                | GitCommand::Gc(_)
+                | GitCommand::Pin(_)
+                | GitCommand::Snapshot(_)
+                | GitCommand::Hook(_)
+                | GitCommand::Branch(_)
+                | GitCommand::Switch(_)
+                | GitCommand::Checkout(_)
+                | GitCommand::Tag(_)
+                | GitCommand::Reset(_)
+                | GitCommand::Revert(_)
+                | GitCommand::CheckoutIndex(_)
+        )
+    }
 }
 
 #[derive(Args, Clone)]
@@ -38,14 +172,40 @@ pub struct InitArgs {
     /// Set the initial branch name of the new repository
     #[arg(long, short = 'b')]
     initial_branch: Option<String>,
+
+    /// Algorithm computing the object ids of the repository, sha1 or blake3
+    #[arg(long, default_value = "sha1")]
+    pub object_format: String,
 }
 
 #[derive(Args, Clone)]
 pub struct CatFileArgs {
     /// The type of the requested object
-    pub type_: ObjectType,
-    /// The name of the object to show
-    pub object: String,
+    #[arg(required_unless_present_any = ["show_type", "exists", "pretty", "batch_all_objects"])]
+    pub type_: Option<ObjectType>,
+    /// The name of the object to show: its hash, a revision, or <rev>:<path>
+    #[arg(required_unless_present_any = ["show_type", "exists", "pretty", "batch_all_objects"])]
+    pub object: Option<String>,
+
+    /// Print the type of the object instead of its content
+    #[arg(short = 't', value_name = "OBJECT", conflicts_with_all = ["type_", "object", "exists", "pretty"])]
+    pub show_type: Option<String>,
+
+    /// Print nothing, exiting with a zero status if the object exists
+    #[arg(short = 'e', value_name = "OBJECT", conflicts_with_all = ["type_", "object", "pretty"])]
+    pub exists: Option<String>,
+
+    /// Print the content of the object, whatever its type
+    #[arg(short = 'p', value_name = "OBJECT", conflicts_with_all = ["type_", "object"])]
+    pub pretty: Option<String>,
+
+    /// Print the id, type and size of every object in the database, ordered by type
+    #[arg(long, conflicts_with_all = ["type_", "object", "show_type", "exists", "pretty"])]
+    pub batch_all_objects: bool,
+
+    /// Only print a summary of blobs larger than the size, in bytes or with a k, m or g suffix
+    #[arg(long, value_name = "SIZE")]
+    pub size_limit: Option<String>,
 }
 
 #[derive(ValueEnum, Clone)]
@@ -53,6 +213,7 @@ pub enum ObjectType {
     Blob,
     Tree,
     Commit,
+    Tag,
 }
 
 #[derive(Args, Clone)]
@@ -74,6 +235,10 @@ pub struct LsFilesArgs {
     /// Show verbose output of the staged files
     #[arg(long, short)]
     pub verbose: bool,
+
+    /// Terminate the paths with NUL and write them without quoting
+    #[arg(short = 'z')]
+    pub null_terminated: bool,
 }
 
 #[derive(Args, Clone)]
@@ -82,21 +247,72 @@ pub struct CheckIgnoreArgs {
     pub path: PathBuf,
 }
 
+#[derive(Args, Clone)]
+pub struct CheckAttrArgs {
+    /// List all attributes specified for the paths
+    #[arg(short, long)]
+    pub all: bool,
+
+    /// Also show the attributes file, line number and pattern deciding each attribute
+    #[arg(short, long)]
+    pub verbose: bool,
+
+    /// The attribute to check followed by the paths, or only the paths with --all
+    #[arg(required = true, value_name = "ATTR|PATH")]
+    pub args: Vec<String>,
+}
+
+#[derive(Args, Clone)]
+pub struct CheckRefFormatArgs {
+    /// The reference name to check
+    pub refname: String,
+
+    /// allow a reference name with a single component (e.g., HEAD)
+    #[arg(long)]
+    pub allow_onelevel: bool,
+
+    /// remove leading slashes and collapse runs of slashes, and print the resulting name
+    #[arg(long, conflicts_with = "branch")]
+    pub normalize: bool,
+
+    /// check that the name is a valid branch name, and print it
+    #[arg(long)]
+    pub branch: bool,
+}
+
 #[derive(Args, Clone)]
 pub struct StatusArgs {}
 
 #[derive(Args, Clone)]
 pub struct ConfigArgs {
     /// config entry name (e.g., user.email)
-    pub name: String,
+    #[arg(required_unless_present_any = ["list", "rename_section"])]
+    pub name: Option<String>,
 
     /// config entry value (get config entry if none)
     pub value: Option<String>,
 
+    /// list all variables set in config files, along with their values
+    #[arg(short, long, conflicts_with_all = ["name", "value"])]
+    pub list: bool,
+
+    /// remove the variable from the config file
+    #[arg(long, conflicts_with_all = ["value", "list"])]
+    pub unset: bool,
+
+    /// rename the given section (e.g., remote.origin) to the new name
+    #[arg(long, num_args = 2, value_names = ["OLD_NAME", "NEW_NAME"], conflicts_with_all = ["name", "value", "list", "unset"])]
+    pub rename_section: Option<Vec<String>>,
+
     /// show origin of config (file)
     #[arg(long)]
     pub show_origin: bool,
 
+    /// show scope of config (system, global, local), listing the defaults of unset
+    /// core.sqlite.* variables too
+    #[arg(long)]
+    pub show_scope: bool,
+
     /// use system config file
     #[arg(long)]
     pub system: bool,
@@ -121,8 +337,13 @@ pub struct RmArgs {
 
 #[derive(Args, Clone)]
 pub struct AddArgs {
-    /// File to add (recursively adding directory is not supported yet)
+    /// File or directory to add, directories being added recursively
     pub path: PathBuf,
+
+    /// Record only that the untracked files will be added later: they are tracked, but their
+    /// content is not staged
+    #[arg(long, short = 'N')]
+    pub intent_to_add: bool,
 }
 
 /// Record changes to the repository
@@ -131,4 +352,1099 @@ pub struct CommitArgs {
     /// Use the given message as the commit message.
     #[arg(long, short)]
     pub message: String,
+
+    /// Add a trailer `<token>[(=|:)<value>]` to the message (may be repeated)
+    #[arg(long, value_name = "TRAILER")]
+    pub trailer: Vec<String>,
+
+    /// Skip the commit message policy and the pre-commit and commit-msg hooks
+    #[arg(long, short)]
+    pub no_verify: bool,
+}
+
+#[derive(Args, Clone)]
+pub struct DifftoolArgs {
+    /// Commits or trees to compare, as for diff
+    #[arg(value_name = "REV", num_args = 0..=2)]
+    pub revisions: Vec<String>,
+
+    /// Limit the diff to the given paths
+    #[arg(last = true, value_name = "PATHSPEC")]
+    pub paths: Vec<String>,
+
+    /// Compare HEAD (or the given revision) with the index instead of the working tree
+    #[arg(long, visible_alias = "staged")]
+    pub cached: bool,
+
+    /// Use the tool configured by difftool.<tool>.cmd instead of diff.tool
+    #[arg(long, short)]
+    pub tool: Option<String>,
+}
+
+#[derive(Args, Clone)]
+pub struct MergetoolArgs {
+    /// Conflicted files to resolve, all of them if none are given
+    #[arg(value_name = "FILE")]
+    pub paths: Vec<String>,
+
+    /// Use the tool configured by mergetool.<tool>.cmd instead of merge.tool
+    #[arg(long, short)]
+    pub tool: Option<String>,
+}
+
+#[derive(Args, Clone)]
+pub struct DiffArgs {
+    /// Commits or trees to compare: one is compared with the working tree (or the index with
+    /// --cached), two (or <rev1>..<rev2>) are compared with each other, and <rev1>...<rev2>
+    /// compares the merge base of both with <rev2>
+    #[arg(value_name = "REV", num_args = 0..=2)]
+    pub revisions: Vec<String>,
+
+    /// Limit the diff to the given paths
+    #[arg(last = true, value_name = "PATHSPEC")]
+    pub paths: Vec<String>,
+
+    /// Show the changes staged for the next commit (HEAD vs the index) instead of unstaged changes
+    #[arg(long, visible_alias = "staged")]
+    pub cached: bool,
+
+    /// Generate the patch (default unless a summary format is requested)
+    #[arg(long, short = 'p')]
+    pub patch: bool,
+
+    /// Generate a diffstat: per-file insertions and deletions with a histogram
+    #[arg(long)]
+    pub stat: bool,
+
+    /// Show the number of inserted and deleted lines per file in a machine-friendly format
+    #[arg(long)]
+    pub numstat: bool,
+
+    /// Only output the last line of --stat with the totals of changed files and lines
+    #[arg(long)]
+    pub shortstat: bool,
+
+    /// Show only the names of changed files
+    #[arg(long, group = "name_format")]
+    pub name_only: bool,
+
+    /// Show only the names and status (added, modified, renamed, ...) of changed files
+    #[arg(long, group = "name_format")]
+    pub name_status: bool,
+
+    /// Detect renames (default, overrides diff.renames)
+    #[arg(long = "find-renames", short = 'M', group = "renames")]
+    pub find_renames: bool,
+
+    /// Detect copies as well as renames
+    #[arg(long = "find-copies", short = 'C', group = "renames")]
+    pub find_copies: bool,
+
+    /// Turn off rename detection, even if diff.renames is set
+    #[arg(long, group = "renames")]
+    pub no_renames: bool,
+
+    /// Show a word diff, marking the changed words within lines (plain by default)
+    #[arg(long, value_enum, value_name = "MODE", num_args = 0..=1, default_missing_value = "plain")]
+    pub word_diff: Option<WordDiffMode>,
+
+    /// Do not run external diff programs (diff.external or diff.<driver>.command)
+    #[arg(long)]
+    pub no_ext_diff: bool,
+
+    /// Do not convert binary files to text with diff.<driver>.textconv filters
+    #[arg(long)]
+    pub no_textconv: bool,
+
+    /// Terminate the paths of --name-only, --name-status and --numstat with NUL and write
+    /// them without quoting
+    #[arg(short = 'z')]
+    pub null_terminated: bool,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WordDiffMode {
+    /// Wrap deleted words in [-...-] and inserted words in {+...+}
+    Plain,
+    /// Highlight deleted words in red and inserted words in green
+    Color,
+}
+
+#[derive(Args, Clone)]
+pub struct MergeArgs {
+    /// Commit to merge into the current branch (e.g., a branch name)
+    pub commit: String,
+
+    /// Use the given merge strategy
+    #[arg(long, short = 's', value_enum, default_value = "ort")]
+    pub strategy: MergeStrategy,
+
+    /// Pass the option to the merge strategy to auto-resolve conflicting changes in favor of one side
+    #[arg(long, short = 'X', value_enum)]
+    pub strategy_option: Option<StrategyOption>,
+
+    /// Update the index and working tree with the merge result, but do not make a commit or move HEAD
+    #[arg(long)]
+    pub squash: bool,
+
+    /// Use the given message as the merge commit message.
+    #[arg(long, short)]
+    pub message: Option<String>,
+
+    /// When the merge resolves as a fast-forward, only update the branch pointer (default, overrides merge.ff)
+    #[arg(long, group = "fast_forward")]
+    pub ff: bool,
+
+    /// Create a merge commit even when the merge resolves as a fast-forward
+    #[arg(long, group = "fast_forward")]
+    pub no_ff: bool,
+
+    /// Refuse to merge unless the current HEAD is up to date or the merge resolves as a fast-forward
+    #[arg(long, group = "fast_forward")]
+    pub ff_only: bool,
+
+    /// Stash local changes before the merge begins and apply them back after it ends (overrides merge.autoStash)
+    #[arg(long, overrides_with = "no_autostash")]
+    pub autostash: bool,
+
+    /// Do not stash local changes, even if merge.autoStash is set
+    #[arg(long, overrides_with = "autostash")]
+    pub no_autostash: bool,
+
+    /// Do not report the progress and the summary of the files updated in the working tree
+    #[arg(long, short)]
+    pub quiet: bool,
+}
+
+#[derive(Args, Clone)]
+pub struct RebaseArgs {
+    /// Upstream branch to compare against, and new base of the rebased commits unless --onto is given
+    #[arg(required_unless_present_any = ["continue_", "skip", "abort"])]
+    pub upstream: Option<String>,
+
+    /// Starting point at which to create the new commits, instead of upstream
+    #[arg(long)]
+    pub onto: Option<String>,
+
+    /// Make a list of the commits which are about to be rebased and let the user edit it before rebasing
+    #[arg(long, short)]
+    pub interactive: bool,
+
+    /// Stash local changes before the rebase begins and apply them back after it ends (overrides rebase.autoStash)
+    #[arg(long, overrides_with = "no_autostash")]
+    pub autostash: bool,
+
+    /// Refuse to rebase with local changes, even if rebase.autoStash is set
+    #[arg(long, overrides_with = "autostash")]
+    pub no_autostash: bool,
+
+    /// Replay commits whose change is already upstream instead of skipping them
+    #[arg(long)]
+    pub reapply_cherry_picks: bool,
+
+    /// Restart the rebasing process after having resolved a conflict or amended a commit
+    #[arg(long = "continue", group = "action")]
+    pub continue_: bool,
+
+    /// Restart the rebasing process by skipping the current commit
+    #[arg(long, group = "action")]
+    pub skip: bool,
+
+    /// Abort the rebase operation and reset HEAD to the original branch
+    #[arg(long, group = "action")]
+    pub abort: bool,
+
+    /// Rebase a protected branch, which must be at the given commit
+    #[arg(long, value_name = "COMMIT")]
+    pub force_protected: Option<String>,
+    /// Do not report the progress and the summary of the files updated in the working tree
+    #[arg(long, short)]
+    pub quiet: bool,
+}
+
+#[derive(Args, Clone)]
+pub struct CherryPickArgs {
+    /// Commits to cherry-pick, or ranges of commits (e.g., A..B)
+    #[arg(required_unless_present_any = ["continue_", "skip", "abort"])]
+    pub commits: Vec<String>,
+
+    /// Continue the operation in progress after having resolved a conflict
+    #[arg(long = "continue", group = "action")]
+    pub continue_: bool,
+
+    /// Skip the current commit and continue with the rest of the sequence
+    #[arg(long, group = "action")]
+    pub skip: bool,
+
+    /// Cancel the operation and return to the pre-sequence state
+    #[arg(long, group = "action")]
+    pub abort: bool,
+
+    /// Only apply the changes to the index and working tree, without committing them. The
+    /// index does not have to match HEAD, and the changes of every commit are added to it.
+    #[arg(long, short = 'n', conflicts_with = "action")]
+    pub no_commit: bool,
+
+    /// Do not report the progress and the summary of the files updated in the working tree
+    #[arg(long, short)]
+    pub quiet: bool,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MergeStrategy {
+    /// Three-way merge of both sides against their merge base
+    #[value(alias = "recursive")]
+    Ort,
+    /// Record a merge but keep the tree of the current branch, ignoring the other side
+    Ours,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StrategyOption {
+    /// Resolve conflicting changes with our version
+    Ours,
+    /// Resolve conflicting changes with their version
+    Theirs,
+}
+
+#[derive(Args, Clone)]
+pub struct ArchiveArgs {
+    /// The tree or commit to produce an archive for
+    pub tree_ish: String,
+
+    /// Format of the resulting archive (guessed from the --output file name, tar by default)
+    #[arg(long, value_enum)]
+    pub format: Option<ArchiveFormat>,
+
+    /// Prepend <PREFIX> to paths in the archive (use a trailing slash for a directory)
+    #[arg(long, default_value = "")]
+    pub prefix: String,
+
+    /// Write the archive to <FILE> instead of stdout
+    #[arg(long, short = 'o', value_name = "FILE")]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Tar,
+    Zip,
+}
+
+#[derive(Args, Clone)]
+pub struct BundleArgs {
+    #[command(subcommand)]
+    pub command: BundleCommand,
+}
+
+#[derive(Subcommand, Clone)]
+pub enum BundleCommand {
+    /// Create a bundle carrying the refs and history selected by the revisions
+    Create {
+        /// Path of the bundle to write
+        file: PathBuf,
+
+        /// Refs to carry; <rev1>..<rev2> and ^<rev> leave out the history of <rev1> and <rev>
+        #[arg(required_unless_present = "all")]
+        revisions: Vec<String>,
+
+        /// Carry every ref of the repository
+        #[arg(long)]
+        all: bool,
+    },
+    /// Check that the bundle is valid and applies cleanly to the current repository
+    Verify {
+        /// Path of the bundle
+        file: PathBuf,
+    },
+    /// List the refs carried by the bundle
+    ListHeads {
+        /// Path of the bundle
+        file: PathBuf,
+    },
+    /// Store the objects of the bundle in the repository and list its refs
+    Unbundle {
+        /// Path of the bundle
+        file: PathBuf,
+    },
+}
+
+#[derive(Args, Clone)]
+pub struct DumpArgs {
+    /// Write the dump to <FILE> instead of stdout
+    #[arg(long, short = 'o', value_name = "FILE")]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Args, Clone)]
+pub struct LoadArgs {
+    /// The dump to load (read from stdin if omitted)
+    pub file: Option<PathBuf>,
+
+    /// Replace the database of an existing repository
+    #[arg(long, short)]
+    pub force: bool,
+}
+
+#[derive(Args, Clone)]
+pub struct GraphArgs {
+    /// Output language of the graph
+    #[arg(long, value_enum, default_value_t = GraphFormat::Dot)]
+    pub format: GraphFormat,
+
+    /// Revisions or ranges (<rev1>..<rev2>) to draw, the history of every ref by default
+    pub revisions: Vec<String>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GraphFormat {
+    /// Graphviz's DOT language
+    Dot,
+    /// Mermaid flowchart
+    Mermaid,
+}
+
+#[derive(Args, Clone)]
+pub struct WebArgs {
+    /// Address for the web server to listen on
+    #[arg(long, value_name = "ADDR", default_value = "127.0.0.1:1234")]
+    pub listen: String,
+}
+
+#[derive(Args, Clone)]
+pub struct ApiArgs {
+    /// Address for the API server to listen on
+    #[arg(long, value_name = "ADDR", default_value = "127.0.0.1:8080")]
+    pub listen: String,
+
+    /// Require clients to send `Authorization: Bearer <TOKEN>` (defaults to $GITQLITE_API_TOKEN)
+    #[arg(long)]
+    pub token: Option<String>,
+}
+
+#[derive(Args, Clone)]
+pub struct DaemonArgs {
+    /// Serve the repositories under <DIR>, clients naming them by their path relative to it
+    #[arg(long, value_name = "DIR")]
+    pub base_path: PathBuf,
+
+    /// Address for the daemon to listen on (127.0.0.1:9418 by default, listen on 0.0.0.0 to
+    /// serve other hosts)
+    #[arg(long, value_name = "ADDR")]
+    pub listen: Option<String>,
+
+    /// Accept pushes to the repositories whose path matches <PATTERN> (may be repeated)
+    #[arg(long, value_name = "PATTERN")]
+    pub allow_push: Vec<String>,
+
+    /// Serve the protocol over HTTP, answering requests posted to the repository URLs
+    #[arg(long)]
+    pub http: bool,
+
+    /// Serve at most <N> clients at once, the others waiting for their turn
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 32,
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub max_connections: u64,
+
+    /// Drop clients which send or receive nothing for <SECONDS>
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        default_value_t = 30,
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub timeout: u64,
+
+    /// Refuse pushes whose pack is larger than <BYTES> (1 GiB by default)
+    #[arg(long, value_name = "BYTES", default_value_t = 1 << 30)]
+    pub max_pack_size: u64,
+}
+
+#[derive(Args, Clone)]
+pub struct FetchArgs {
+    /// The remote to fetch from: a configured remote name, a URL or a path
+    pub repository: String,
+
+    /// Branches to fetch, every branch of the remote by default
+    pub refs: Vec<String>,
+
+    /// Before fetching, remove the remote-tracking refs whose ref no longer exists on the
+    /// remote. Defaults to remote.<name>.prune, then fetch.prune.
+    #[arg(long, short, overrides_with = "no_prune")]
+    pub prune: bool,
+
+    /// Do not prune, whatever the config says
+    #[arg(long)]
+    pub no_prune: bool,
+}
+
+#[derive(Args, Clone)]
+pub struct RemoteArgs {
+    #[command(subcommand)]
+    pub command: RemoteCommand,
+}
+
+#[derive(Subcommand, Clone)]
+pub enum RemoteCommand {
+    /// Show the URLs of the remotes, their branches and the local branches pulling from or
+    /// pushing to them
+    Show {
+        /// The configured remotes to show
+        #[arg(required = true)]
+        names: Vec<String>,
+    },
+    /// Delete the remote-tracking refs whose ref no longer exists on the remote
+    Prune {
+        /// The configured remotes to prune
+        #[arg(required = true)]
+        names: Vec<String>,
+
+        /// List the refs that would be pruned without deleting them
+        #[arg(long, short = 'n')]
+        dry_run: bool,
+    },
+}
+
+#[derive(Args, Clone)]
+pub struct PushArgs {
+    /// The remote to push to: a configured remote name, a URL or a path
+    pub repository: String,
+
+    /// Refs to update, as `[+]<src>[:<dst>]`. An empty <src> deletes <dst>.
+    #[arg(required = true)]
+    pub refspecs: Vec<String>,
+
+    /// Update the remote refs even if they are not ancestors of the local ones
+    #[arg(long, short)]
+    pub force: bool,
+
+    /// Force the updates only if the remote refs are where we expect: at <EXPECT>, missing if
+    /// it is empty, or else at our remote-tracking branch for them. Naming <REFNAME> limits
+    /// the lease to that ref (may be repeated).
+    #[arg(
+        long,
+        value_name = "REFNAME[:EXPECT]",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = ""
+    )]
+    pub force_with_lease: Vec<String>,
+
+    /// Rewind or delete remote branches protected by the local branch.<name>.protect, which
+    /// must be at the given commit on the remote
+    #[arg(long, value_name = "COMMIT")]
+    pub force_protected: Option<String>,
+
+    /// Skip the pre-push hook
+    #[arg(long)]
+    pub no_verify: bool,
+}
+
+#[derive(Args, Clone)]
+pub struct ServeArgs {
+    /// The repository to serve, `~/` standing for the home directory
+    pub directory: String,
+}
+
+#[derive(Args, Clone)]
+pub struct InterpretTrailersArgs {
+    /// Messages to process, stdin if none is given
+    pub files: Vec<PathBuf>,
+
+    /// Add a trailer `<token>[(=|:)<value>]` (may be repeated)
+    #[arg(long, value_name = "TRAILER")]
+    pub trailer: Vec<String>,
+
+    /// Where to place the new trailers, overriding the configuration
+    #[arg(long = "where", value_enum, value_name = "PLACEMENT")]
+    pub placement: Option<TrailerWhere>,
+
+    /// What to do when a trailer with the same key exists, overriding the configuration
+    #[arg(long, value_enum, value_name = "ACTION")]
+    pub if_exists: Option<TrailerIfExists>,
+
+    /// What to do when no trailer with the same key exists, overriding the configuration
+    #[arg(long, value_enum, value_name = "ACTION")]
+    pub if_missing: Option<TrailerIfMissing>,
+
+    /// Output only the trailers, not the rest of the message
+    #[arg(long)]
+    pub only_trailers: bool,
+
+    /// Do not add the trailers given with --trailer, only show the existing ones
+    #[arg(long)]
+    pub only_input: bool,
+
+    /// Join the continuation lines of the trailers to their first line
+    #[arg(long)]
+    pub unfold: bool,
+
+    /// Same as --only-trailers --only-input --unfold
+    #[arg(long)]
+    pub parse: bool,
+
+    /// Edit the files in place
+    #[arg(long, requires = "files")]
+    pub in_place: bool,
+}
+
+#[derive(Args, Clone)]
+pub struct VerifyArgs {
+    /// Show the rows and checksum of each table
+    #[arg(long, short)]
+    pub verbose: bool,
+
+    /// Also check that every object the refs, commits and trees point to exists with the
+    /// expected type
+    #[arg(long)]
+    pub connectivity: bool,
+}
+
+#[derive(Args, Clone)]
+pub struct DuArgs {
+    /// Number of largest blobs to show
+    #[arg(long, short = 'n', default_value_t = 10)]
+    pub top: usize,
+
+    /// Show sizes in bytes instead of human-readable units
+    #[arg(long, short)]
+    pub bytes: bool,
+}
+
+#[derive(Args, Clone)]
+pub struct StatsArgs {
+    /// Break the statistics down by month
+    #[arg(long)]
+    pub history: bool,
+
+    /// Output format
+    #[arg(long, value_enum, default_value_t = StatsFormat::Table)]
+    pub format: StatsFormat,
+
+    /// The commit whose history to count, HEAD by default
+    pub revision: Option<String>,
+}
+
+#[derive(Args, Clone)]
+pub struct SearchArgs {
+    /// Words to find in the messages: "phrases", prefix* and AND, OR or NOT are understood
+    pub query: String,
+
+    /// Only commits whose author, as `Name <email>`, contains <AUTHOR>
+    #[arg(long)]
+    pub author: Option<String>,
+
+    /// Only commits written on or after <SINCE> (YYYY-MM-DD)
+    #[arg(long)]
+    pub since: Option<String>,
+
+    /// Only commits written on or before <UNTIL> (YYYY-MM-DD)
+    #[arg(long)]
+    pub until: Option<String>,
+
+    /// Output format
+    #[arg(long, value_enum, default_value_t = SearchFormat::Oneline)]
+    pub format: SearchFormat,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SearchFormat {
+    /// A line for each commit: its short id, date, author and the matched part of its message
+    Oneline,
+    /// An array of objects
+    Json,
+}
+
+#[derive(Args, Clone)]
+pub struct ContributorsArgs {
+    /// Show authors as they committed, ignoring the mailmap
+    #[arg(long)]
+    pub no_mailmap: bool,
+
+    /// The commit whose history to summarize, HEAD by default
+    pub revision: Option<String>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StatsFormat {
+    /// Aligned columns for reading
+    Table,
+    /// Comma-separated values with a header line
+    Csv,
+    /// An array of objects
+    Json,
+}
+
+#[derive(Args, Clone)]
+pub struct PruneArgs {
+    /// List the unreachable objects with their size and age, without removing them
+    #[arg(long, short = 'n')]
+    pub dry_run: bool,
+
+    /// Report the removed objects
+    #[arg(long, short)]
+    pub verbose: bool,
+}
+
+#[derive(Args, Clone)]
+pub struct GcArgs {
+    /// Also delete the extra copies of blobs stored more than once, and rebuild the indexes
+    /// and statistics of the database
+    #[arg(long)]
+    pub aggressive: bool,
+}
+
+#[derive(Args, Clone)]
+pub struct HookArgs {
+    #[command(subcommand)]
+    pub command: HookCommand,
+}
+
+#[derive(Subcommand, Clone)]
+pub enum HookCommand {
+    /// Run a hook as gitqlite would, with the staged files on its standard input
+    Run {
+        /// Name of the hook, e.g. pre-commit
+        name: String,
+
+        /// Arguments passed to the hook
+        #[arg(last = true)]
+        args: Vec<String>,
+
+        /// Succeed silently if the hook does not exist
+        #[arg(long)]
+        ignore_missing: bool,
+    },
+}
+
+#[derive(Args, Clone)]
+pub struct StashArgs {
+    /// Defaults to push
+    #[command(subcommand)]
+    pub command: Option<StashCommand>,
+
+    /// Do not report the progress and the summary of the files updated in the working tree
+    #[arg(long, short, global = true)]
+    pub quiet: bool,
+}
+
+#[derive(Subcommand, Clone)]
+pub enum StashCommand {
+    /// Record the local changes in a new stash entry and bring the working tree back to HEAD
+    Push {
+        /// Also stash the untracked files, which are then removed from the working tree
+        #[arg(long, short = 'u')]
+        include_untracked: bool,
+
+        /// Description of the stash entry
+        #[arg(long, short = 'm', default_value = "WIP")]
+        message: String,
+
+        /// Only stash the changes of the given paths
+        #[arg(last = true, value_name = "PATHSPEC")]
+        paths: Vec<String>,
+    },
+    /// Show the changes recorded in a stash entry, as a diffstat by default
+    Show {
+        /// The stash entry, as stash@{<n>} or <n> (the most recent one by default)
+        stash: Option<String>,
+
+        /// Show the changes as a patch
+        #[arg(long, short = 'p')]
+        patch: bool,
+
+        /// Also show the stashed untracked files
+        #[arg(long, short = 'u')]
+        include_untracked: bool,
+    },
+    /// List the stash entries, the most recent one first
+    List,
+    /// Apply the changes recorded in a stash entry on top of the working tree, keeping the entry
+    Apply {
+        /// The stash entry, as stash@{<n>} or <n> (the most recent one by default)
+        stash: Option<String>,
+    },
+    /// Apply the changes recorded in a stash entry, then drop the entry unless they conflict
+    Pop {
+        /// The stash entry, as stash@{<n>} or <n> (the most recent one by default)
+        stash: Option<String>,
+    },
+    /// Remove a stash entry from the stash
+    Drop {
+        /// The stash entry, as stash@{<n>} or <n> (the most recent one by default)
+        stash: Option<String>,
+    },
+}
+
+#[derive(Args, Clone)]
+pub struct InternalArgs {
+    #[command(subcommand)]
+    pub command: InternalCommand,
+}
+
+#[derive(Subcommand, Clone)]
+pub enum InternalCommand {
+    /// Print the candidates for completing an argument, one per line
+    Complete {
+        /// What the argument names
+        #[arg(value_enum)]
+        kind: CompletionKind,
+    },
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompletionKind {
+    /// Local branches
+    Branches,
+    /// Tags
+    Tags,
+    /// Configured remotes
+    Remotes,
+    /// Keys set in any config file
+    ConfigKeys,
+    /// Paths in the index
+    Paths,
+    /// Hooks that can be run
+    Hooks,
+}
+
+#[derive(Args, Clone)]
+pub struct PinArgs {
+    /// The objects to pin: hashes, revisions, or <rev>:<path>. Lists the pinned objects if
+    /// none is given
+    pub objects: Vec<String>,
+
+    /// Unpin the objects instead
+    #[arg(long, short)]
+    pub delete: bool,
+}
+
+#[derive(Args, Clone)]
+pub struct SnapshotArgs {
+    #[command(subcommand)]
+    pub command: SnapshotCommand,
+}
+
+#[derive(Subcommand, Clone)]
+pub enum SnapshotCommand {
+    /// Record HEAD, the refs and the index, printing the id of the snapshot, which only
+    /// depends on its content
+    Create {
+        /// Name of the snapshot
+        name: String,
+
+        /// Replace the snapshot if it exists
+        #[arg(long, short)]
+        force: bool,
+    },
+    /// Bring HEAD, the refs and the index back to the snapshot. The working tree is left
+    /// alone: `checkout-index -a -f` brings it to the restored index.
+    Restore {
+        /// Name of the snapshot
+        name: String,
+    },
+    /// List the snapshots with their ids
+    List,
+    /// Delete snapshots
+    Delete {
+        /// Names of the snapshots
+        #[arg(required = true)]
+        names: Vec<String>,
+    },
+}
+
+#[derive(Args, Clone)]
+pub struct RangeDiffArgs {
+    /// The range of the old version of the commits (e.g. main..old-topic)
+    pub old: String,
+
+    /// The range of the new version of the commits
+    pub new: String,
+
+    /// Percentage of the size of two patches their differences may reach for the commits
+    /// to be paired (default 60)
+    #[arg(long)]
+    pub creation_factor: Option<usize>,
+
+    /// Only list the pairs of commits, without the diff of their patches
+    #[arg(long, short = 's')]
+    pub no_patch: bool,
+}
+
+#[derive(Args, Clone)]
+pub struct CherryArgs {
+    /// Upstream branch to search for equivalent commits
+    pub upstream: String,
+
+    /// Working branch (defaults to HEAD)
+    pub head: Option<String>,
+
+    /// Do not report commits up to (and including) this commit
+    pub limit: Option<String>,
+
+    /// Show the commit subjects next to the hashes
+    #[arg(long, short)]
+    pub verbose: bool,
+}
+
+#[derive(Args, Clone)]
+pub struct PatchIdArgs {
+    /// Commits whose patch ids to compute (patches are read from stdin if none is given)
+    pub commits: Vec<String>,
+
+    /// Do not strip whitespace from the patches before hashing them
+    #[arg(long)]
+    pub verbatim: bool,
+}
+
+#[derive(Args, Clone)]
+pub struct LogArgs {
+    /// Commit to start the history from (defaults to HEAD)
+    pub revision: Option<String>,
+
+    /// Limit the number of commits to output
+    #[arg(short = 'n', long, value_name = "NUMBER")]
+    pub max_count: Option<usize>,
+
+    /// Only show commits that change the number of occurrences of the string
+    #[arg(short = 'S', value_name = "STRING", conflicts_with = "grep_diff")]
+    pub pickaxe: Option<String>,
+
+    /// Only show commits whose added or removed lines match the regex
+    #[arg(short = 'G', value_name = "REGEX")]
+    pub grep_diff: Option<String>,
+
+    /// Only show commits that change the file, following it through renames
+    #[arg(long, value_name = "PATH")]
+    pub follow: Option<String>,
+}
+
+#[derive(Args, Clone)]
+pub struct ShowArgs {
+    /// Objects to show: revisions, annotated tags (with the commit they tag), or <rev>:<path>
+    /// (:<path> for the index) to show a file or directory (defaults to HEAD)
+    pub objects: Vec<String>,
+}
+
+#[derive(Args, Clone)]
+pub struct SwitchArgs {
+    /// The branch to switch to, or the commit to check out with --detach
+    #[arg(required_unless_present = "orphan")]
+    pub branch: Option<String>,
+
+    /// Create a new branch without commits and switch to it. The index and the tracked files
+    /// are emptied, so that the first commit on the branch has no parent.
+    #[arg(long, value_name = "NEW_BRANCH", conflicts_with = "branch")]
+    pub orphan: Option<String>,
+
+    /// Check out the commit without being on a branch
+    #[arg(long, short)]
+    pub detach: bool,
+
+    /// Throw away the local changes, even those the switch would overwrite
+    #[arg(long, short, visible_alias = "discard-changes")]
+    pub force: bool,
+
+    /// Do not report the progress, the files updated in the working tree and the new HEAD
+    #[arg(long, short)]
+    pub quiet: bool,
+}
+
+#[derive(Args, Clone)]
+pub struct CheckoutArgs {
+    /// The branch to switch to, or the commit to check out without being on a branch
+    pub target: String,
+
+    /// Check out the commit of a branch without being on the branch
+    #[arg(long)]
+    pub detach: bool,
+
+    /// Throw away the local changes, even those the checkout would overwrite
+    #[arg(long, short)]
+    pub force: bool,
+
+    /// Do not report the progress, the files updated in the working tree and the new HEAD
+    #[arg(long, short)]
+    pub quiet: bool,
+}
+
+#[derive(Args, Clone)]
+pub struct ResetArgs {
+    /// Only move the branch, keeping the index and working tree
+    #[arg(long, conflicts_with_all = ["mixed", "hard"])]
+    pub soft: bool,
+
+    /// Move the branch and reset the index, keeping the working tree (the default)
+    #[arg(long, conflicts_with = "hard")]
+    pub mixed: bool,
+
+    /// Move the branch and reset the index and working tree, discarding the changes to
+    /// tracked files
+    #[arg(long)]
+    pub hard: bool,
+
+    /// The commit to reset to (defaults to HEAD)
+    pub commit: Option<String>,
+
+    /// Rewind a protected branch, which must be at the given commit
+    #[arg(long, value_name = "COMMIT")]
+    pub force_protected: Option<String>,
+
+    /// Do not report the progress, the files updated in the working tree and the new HEAD
+    #[arg(long, short)]
+    pub quiet: bool,
+}
+
+#[derive(Args, Clone)]
+pub struct RevertArgs {
+    /// The commit whose changes to undo
+    pub commit: String,
+}
+
+#[derive(Args, Clone)]
+pub struct BranchArgs {
+    /// List the remote-tracking branches
+    #[arg(long, short, conflicts_with = "all")]
+    pub remotes: bool,
+
+    /// List both the local and the remote-tracking branches
+    #[arg(long, short)]
+    pub all: bool,
+
+    /// Show the commit and subject of each branch, and how it compares with its upstream.
+    /// Given twice, also name the upstream branch
+    #[arg(long, short, action = ArgAction::Count)]
+    pub verbose: u8,
+
+    /// The branch to create and the commit it starts at (HEAD by default), the branches to
+    /// delete with -d, or the branch to rename (the current one by default) and its new name
+    /// with -m
+    #[arg(value_name = "NAME")]
+    pub names: Vec<String>,
+
+    /// Rename a branch, along with its config
+    #[arg(long = "move", short = 'm', conflicts_with_all = ["remotes", "all", "delete", "force_delete"])]
+    pub rename: bool,
+
+    /// Delete the given branches, which must be merged into HEAD
+    #[arg(long, short, conflicts_with_all = ["remotes", "all", "force_delete"])]
+    pub delete: bool,
+
+    /// Delete the given branches even if they are not merged
+    #[arg(short = 'D', conflicts_with_all = ["remotes", "all"])]
+    pub force_delete: bool,
+
+    /// Reset the branch to the start commit if it already exists, or with -m replace the
+    /// branch named like the new name
+    #[arg(long, short)]
+    pub force: bool,
+
+    /// Reset, rename or delete a protected branch, which must be at the given commit
+    #[arg(long, value_name = "COMMIT")]
+    pub force_protected: Option<String>,
+}
+
+#[derive(Args, Clone)]
+pub struct TagArgs {
+    /// The tag to create and the commit it points to (HEAD by default), the tags to delete
+    /// with -d, or glob patterns the listed tags must match with -l
+    #[arg(value_name = "NAME")]
+    pub names: Vec<String>,
+
+    /// List the tags, the default without names
+    #[arg(long, short, group = "tag_action")]
+    pub list: bool,
+
+    /// Delete the given tags
+    #[arg(long, short, group = "tag_action")]
+    pub delete: bool,
+
+    /// Create an annotated tag, recording the tagger, the date and a message
+    #[arg(long, short)]
+    pub annotate: bool,
+
+    /// Message of the annotated tag, instead of editing it (implies -a)
+    #[arg(long, short)]
+    pub message: Option<String>,
+
+    /// Replace an existing tag instead of failing
+    #[arg(long, short)]
+    pub force: bool,
+
+    /// Sort the listed tags by `refname` (default, overrides tag.sort) or `version:refname`,
+    /// prefixed with `-` to reverse the order
+    #[arg(long, value_name = "KEY")]
+    pub sort: Option<String>,
+
+    /// Only list the tags of commits which contain the given commit (HEAD by default)
+    #[arg(long, value_name = "COMMIT", num_args = 0..=1, default_missing_value = "HEAD")]
+    pub contains: Option<String>,
+}
+
+#[derive(Args, Clone)]
+pub struct CheckoutIndexArgs {
+    /// Files to copy, relative to the repository root. A directory copies the files under it.
+    pub paths: Vec<String>,
+
+    /// Copy every file
+    #[arg(long, short, conflicts_with = "paths")]
+    pub all: bool,
+
+    /// Copy the files of the given tree or commit instead of the index
+    #[arg(long, value_name = "TREE-ISH")]
+    pub source: Option<String>,
+
+    /// Write each file to <PREFIX><path> relative to the current directory instead of the
+    /// working tree (use a trailing slash for a directory)
+    #[arg(long, default_value = "")]
+    pub prefix: String,
+
+    /// Overwrite existing files
+    #[arg(long, short)]
+    pub force: bool,
+}
+
+/// Where a new trailer is placed
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrailerWhere {
+    /// After the last trailer
+    End,
+    /// Before the first trailer
+    Start,
+    /// After the last trailer with the same key
+    After,
+    /// Before the first trailer with the same key
+    Before,
+}
+
+/// What to do when a trailer with the same key already exists
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrailerIfExists {
+    /// Add it unless the trailer next to it has the same key and value
+    #[value(name = "addIfDifferentNeighbor")]
+    AddIfDifferentNeighbor,
+    /// Add it unless a trailer with the same key and value exists
+    #[value(name = "addIfDifferent")]
+    AddIfDifferent,
+    /// Add it anyway
+    Add,
+    /// Replace the existing trailer
+    Replace,
+    /// Leave the message unchanged
+    #[value(name = "doNothing")]
+    DoNothing,
+}
+
+/// What to do when no trailer with the same key exists
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrailerIfMissing {
+    /// Add the trailer
+    Add,
+    /// Leave the message unchanged
+    #[value(name = "doNothing")]
+    DoNothing,
 }
@@ -0,0 +1,388 @@
+//! This module answers the requests of the read-only JSON API served by `gitqlite api`:
+//!
+//! - `GET /refs` lists HEAD and the refs with the commits they point at
+//! - `GET /commits/<rev>` describes a commit
+//! - `GET /trees/<tree-ish>` lists the entries of a tree
+//! - `GET /blobs/<id>` returns the content of a blob, base64 encoded if it is not UTF-8
+//! - `GET /search?q=<text>` lists the commits whose message contains the text
+//! - `GET /history/<rev>?path=<path>` lists the changes to a file in the history of a commit,
+//!   the most recent first, following renames
+//!
+//! List endpoints are paginated with the `page` (starting at 1) and `per_page` parameters.
+//! When the server has a token, requests must carry it as `Authorization: Bearer <token>`.
+
+use base64::Engine;
+use rusqlite::Connection;
+use serde_json::{json, Value};
+
+use super::{
+    file_changes::path_history,
+    model::{Blob, Commit, Head, Ref, Sha1Id, Tree, TreeEntryType},
+    revision::{resolve_revision, resolve_tree},
+    web::{percent_decode, Page},
+};
+
+const DEFAULT_PER_PAGE: usize = 30;
+const MAX_PER_PAGE: usize = 100;
+
+const COUNT_COMMITS_MATCHING: &str = "SELECT count(*) FROM Commits WHERE instr(message, ?1) > 0";
+const READ_COMMIT_IDS_MATCHING: &str =
+    "SELECT commit_id FROM Commits WHERE instr(message, ?1) > 0 ORDER BY commit_id LIMIT ?2 OFFSET ?3";
+
+/// [`ApiRequest`] is the part of an HTTP request the API looks at
+#[derive(Debug, Clone, Copy)]
+pub struct ApiRequest<'a> {
+    pub method: &'a str,
+    pub url: &'a str,
+    /// Value of the `Authorization` header
+    pub authorization: Option<&'a str>,
+}
+
+/// [`ApiError`] is a failed request, reported as `{"error": <message>}` with the status code
+#[derive(Debug)]
+struct ApiError {
+    status: u16,
+    message: String,
+}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(err: anyhow::Error) -> ApiError {
+        ApiError {
+            status: 404,
+            message: err.to_string(),
+        }
+    }
+}
+
+fn api_error(status: u16, message: impl Into<String>) -> ApiError {
+    ApiError {
+        status,
+        message: message.into(),
+    }
+}
+
+/// Answer an API request, checking it carries `token` if one is required
+pub fn handle_api_request(conn: &Connection, request: ApiRequest, token: Option<&str>) -> Page {
+    let result = authorize(request, token).and_then(|_| route(conn, request));
+    match result {
+        Ok(value) => json_page(200, value),
+        Err(err) => api_error_page(err.status, &err.message),
+    }
+}
+
+/// The response to a request which failed with `message`
+pub fn api_error_page(status: u16, message: &str) -> Page {
+    json_page(status, json!({ "error": message }))
+}
+
+fn json_page(status: u16, value: Value) -> Page {
+    Page {
+        status,
+        content_type: "application/json",
+        body: format!("{:#}\n", value).into_bytes(),
+    }
+}
+
+fn authorize(request: ApiRequest, token: Option<&str>) -> Result<(), ApiError> {
+    let Some(token) = token else {
+        return Ok(());
+    };
+    match request
+        .authorization
+        .and_then(|h| h.strip_prefix("Bearer "))
+    {
+        Some(given) if constant_time_eq(given.trim().as_bytes(), token.as_bytes()) => Ok(()),
+        _ => Err(api_error(401, "Bad credentials")),
+    }
+}
+
+/// Compare without returning early at the first difference, which would tell how much of a
+/// guessed token is right through the response time
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+fn route(conn: &Connection, request: ApiRequest) -> Result<Value, ApiError> {
+    if request.method != "GET" {
+        return Err(api_error(405, "Method not allowed"));
+    }
+
+    let (path, query) = request.url.split_once('?').unwrap_or((request.url, ""));
+    let params = parse_query(query);
+    let path = percent_decode(path);
+    let (route, rest) = path
+        .trim_matches('/')
+        .split_once('/')
+        .unwrap_or((path.trim_matches('/'), ""));
+
+    match (route, rest) {
+        ("refs", "") => {
+            let mut refs = Vec::new();
+            if let Some(head_id) = Head::read_from_conn(conn)?.resolve(conn)? {
+                refs.push(json!({ "name": "HEAD", "commit_id": head_id }));
+            }
+            for reference in Ref::read_all(conn)? {
+                refs.push(json!({ "name": reference.name, "commit_id": reference.commit_id }));
+            }
+            let (page, per_page, offset) = pagination(&params)?;
+            let total = refs.len();
+            let items = refs.into_iter().skip(offset).take(per_page).collect();
+            Ok(paginated(items, page, per_page, total))
+        }
+        ("commits", rev) if !rev.is_empty() => {
+            let id = resolve_revision(conn, rev)?;
+            Ok(commit_json(&Commit::read_from_conn_with_id(conn, id)?))
+        }
+        ("trees", rev) if !rev.is_empty() => {
+            let tree = Tree::read_from_conn_with_id(conn, resolve_tree(conn, rev)?)?;
+            let entries: Vec<Value> = tree
+                .entries
+                .iter()
+                .map(|entry| {
+                    json!({
+                        "name": entry.name,
+                        "mode": format!("{:06o}", entry.mode.parse::<u32>().unwrap_or_default()),
+                        "type": match entry.type_ {
+                            TreeEntryType::Tree => "tree",
+                            TreeEntryType::Blob => "blob",
+                        },
+                        "id": entry.id,
+                    })
+                })
+                .collect();
+            Ok(json!({ "id": tree.tree_id, "entries": entries }))
+        }
+        ("blobs", id) if !id.is_empty() => {
+            let id = Sha1Id::try_from(id).map_err(|_| api_error(400, "Invalid blob id"))?;
+            let blob = Blob::read_from_conn_with_id(conn, id)?;
+            let (encoding, content) = match std::str::from_utf8(&blob.data) {
+                Ok(text) => ("utf-8", text.to_string()),
+                Err(_) => (
+                    "base64",
+                    base64::engine::general_purpose::STANDARD.encode(&blob.data),
+                ),
+            };
+            Ok(json!({
+                "id": id,
+                "size": blob.data.len(),
+                "encoding": encoding,
+                "content": content,
+            }))
+        }
+        ("search", "") => {
+            let text = params
+                .iter()
+                .find(|(key, _)| key == "q")
+                .map(|(_, value)| value.as_str())
+                .filter(|text| !text.is_empty())
+                .ok_or_else(|| api_error(400, "Missing search text: ?q=<text>"))?;
+            let (page, per_page, offset) = pagination(&params)?;
+            let total: usize = conn
+                .query_row(COUNT_COMMITS_MATCHING, [text], |row| row.get(0))
+                .map_err(anyhow::Error::from)?;
+            let ids = conn
+                .prepare(READ_COMMIT_IDS_MATCHING)
+                .and_then(|mut stmt| {
+                    stmt.query_map((text, per_page, offset), |row| row.get::<_, Sha1Id>(0))?
+                        .collect::<Result<Vec<_>, _>>()
+                })
+                .map_err(anyhow::Error::from)?;
+            let items = ids
+                .into_iter()
+                .map(|id| Ok(commit_json(&Commit::read_from_conn_with_id(conn, id)?)))
+                .collect::<crate::Result<Vec<_>>>()?;
+            Ok(paginated(items, page, per_page, total))
+        }
+        ("history", rev) if !rev.is_empty() => {
+            let file = params
+                .iter()
+                .find(|(key, _)| key == "path")
+                .map(|(_, value)| value.as_str())
+                .filter(|file| !file.is_empty())
+                .ok_or_else(|| api_error(400, "Missing file path: ?path=<path>"))?;
+            let (page, per_page, offset) = pagination(&params)?;
+            let history = path_history(conn, resolve_revision(conn, rev)?, file)?;
+            let total = history.len();
+            let items = history
+                .into_iter()
+                .skip(offset)
+                .take(per_page)
+                .map(|change| {
+                    let commit = Commit::read_from_conn_with_id(conn, change.commit_id)?;
+                    Ok(json!({
+                        "commit": commit_json(&commit),
+                        "path": change.path,
+                        "change_type": change.change_type,
+                        "old_path": change.old_path,
+                        "old_blob": change.old_blob,
+                        "new_blob": change.new_blob,
+                    }))
+                })
+                .collect::<crate::Result<Vec<_>>>()?;
+            Ok(paginated(items, page, per_page, total))
+        }
+        _ => Err(api_error(404, format!("Not found: {}", path))),
+    }
+}
+
+fn commit_json(commit: &Commit<Sha1Id>) -> Value {
+    json!({
+        "id": commit.commit_id,
+        "tree": commit.tree_id,
+        "parents": commit.parent_ids,
+        "author": { "name": commit.author_name, "email": commit.author_email },
+        "committer": { "name": commit.committer_name, "email": commit.committer_email },
+        "message": commit.message,
+    })
+}
+
+fn paginated(items: Vec<Value>, page: usize, per_page: usize, total: usize) -> Value {
+    json!({ "items": items, "page": page, "per_page": per_page, "total": total })
+}
+
+/// Read the page number and size from the query parameters, along with the number of items
+/// before the page
+fn pagination(params: &[(String, String)]) -> Result<(usize, usize, usize), ApiError> {
+    let get = |name: &str, default: usize| -> Result<usize, ApiError> {
+        match params.iter().find(|(key, _)| key == name) {
+            Some((_, value)) => value
+                .parse()
+                .ok()
+                .filter(|n| *n > 0)
+                .ok_or_else(|| api_error(400, format!("Invalid {}: {}", name, value))),
+            None => Ok(default),
+        }
+    };
+    let page = get("page", 1)?;
+    let per_page = get("per_page", DEFAULT_PER_PAGE)?.min(MAX_PER_PAGE);
+    // SQLite takes the offset as a signed 64-bit integer
+    let offset = (page - 1)
+        .checked_mul(per_page)
+        .filter(|offset| i64::try_from(*offset).is_ok())
+        .ok_or_else(|| api_error(400, format!("Invalid page: {}", page)))?;
+    Ok((page, per_page, offset))
+}
+
+/// Split a query string into decoded key-value pairs
+fn parse_query(query: &str) -> Vec<(String, String)> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let decode = |s: &str| percent_decode(&s.replace('+', " "));
+            (decode(key), decode(value))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::{
+        constants::BRANCH_PREFIX,
+        model::{CREATE_HEAD_TABLE, CREATE_REF_TABLE},
+        test_utils::{new_commit, object_database, persist_blob, persist_commit, FILE_MODE},
+        tree::{write_tree, FlatEntry, TreeView},
+    };
+
+    /// A repository with a commit per message on main, each holding the same binary file
+    fn setup(messages: &[&str]) -> (Connection, Sha1Id, Sha1Id) {
+        let conn = object_database(&[CREATE_HEAD_TABLE, CREATE_REF_TABLE]);
+
+        let blob_id = persist_blob(&conn, &[0xff, 0x00]);
+        let mut view = TreeView::new();
+        let mode = FILE_MODE.to_string();
+        view.insert("data.bin".to_string(), FlatEntry { id: blob_id, mode });
+        let tree_id = write_tree(&conn, &view).unwrap();
+
+        let mut parent_ids = vec![];
+        for message in messages {
+            let id = persist_commit(&conn, new_commit(tree_id, parent_ids, message));
+            parent_ids = vec![id];
+        }
+        Ref {
+            name: format!("{}main", BRANCH_PREFIX),
+            commit_id: parent_ids[0],
+        }
+        .persist_or_update(&conn)
+        .unwrap();
+        Head::Branch(format!("{}main", BRANCH_PREFIX))
+            .persist(&conn)
+            .unwrap();
+        (conn, parent_ids[0], blob_id)
+    }
+
+    fn get(conn: &Connection, url: &str, token: Option<&str>) -> (u16, Value) {
+        let request = ApiRequest {
+            method: "GET",
+            url,
+            authorization: Some("Bearer secret"),
+        };
+        let page = handle_api_request(conn, request, token);
+        (page.status, serde_json::from_slice(&page.body).unwrap())
+    }
+
+    #[test]
+    fn test_endpoints() {
+        let (conn, tip, blob_id) = setup(&["fix parser", "add lexer", "fix lexer"]);
+
+        let (status, refs) = get(&conn, "/refs", None);
+        assert_eq!(200, status);
+        assert_eq!(2, refs["total"]);
+        assert_eq!("HEAD", refs["items"][0]["name"]);
+        assert_eq!(tip.to_string(), refs["items"][1]["commit_id"]);
+
+        let (_, commit) = get(&conn, "/commits/main", None);
+        assert_eq!("fix lexer", commit["message"]);
+        assert_eq!("eikasia30@gmail.com", commit["author"]["email"]);
+        assert_eq!(1, commit["parents"].as_array().unwrap().len());
+
+        let (_, tree) = get(&conn, "/trees/main%5E%7Btree%7D", None);
+        assert_eq!("100644", tree["entries"][0]["mode"]);
+        assert_eq!("blob", tree["entries"][0]["type"]);
+
+        let (_, blob) = get(&conn, &format!("/blobs/{}", blob_id), None);
+        assert_eq!("base64", blob["encoding"]);
+        assert_eq!("/wA=", blob["content"]);
+
+        let (_, found) = get(&conn, "/search?q=fix+&per_page=1&page=2", None);
+        assert_eq!(2, found["total"]);
+        assert_eq!(1, found["items"].as_array().unwrap().len());
+        assert!(found["items"][0]["message"]
+            .as_str()
+            .unwrap()
+            .starts_with("fix "));
+    }
+
+    #[test]
+    fn test_errors() {
+        let (conn, _, _) = setup(&["root"]);
+        assert_eq!(404, get(&conn, "/commits/nope", None).0);
+        assert_eq!(400, get(&conn, "/blobs/xyz", None).0);
+        assert_eq!(400, get(&conn, "/search", None).0);
+        assert_eq!(400, get(&conn, "/history/main", None).0);
+        assert_eq!(400, get(&conn, "/refs?page=0", None).0);
+        let huge = format!("page={}&per_page=100", usize::MAX);
+        for endpoint in ["/refs?", "/search?q=fix&", "/history/main?path=a&"] {
+            assert_eq!(400, get(&conn, &format!("{}{}", endpoint, huge), None).0);
+        }
+        assert_eq!(404, get(&conn, "/unknown", None).0);
+
+        // Requests must carry the right token when the server has one
+        assert_eq!(200, get(&conn, "/refs", Some("secret")).0);
+        let (status, body) = get(&conn, "/refs", Some("other"));
+        assert_eq!(401, status);
+        assert_eq!("Bad credentials", body["error"]);
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secret2"));
+
+        let request = ApiRequest {
+            method: "POST",
+            url: "/refs",
+            authorization: None,
+        };
+        assert_eq!(405, handle_api_request(&conn, request, None).status);
+    }
+}
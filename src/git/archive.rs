@@ -0,0 +1,327 @@
+//! This module writes the snapshot of a tree as a tar or zip archive. The content of the
+//! files is streamed from the database, so the working tree is never touched and no file is
+//! held in memory at once.
+
+use std::{
+    collections::HashSet,
+    io::{Cursor, Write},
+};
+
+use rusqlite::Connection;
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+use crate::{cli::ArchiveFormat, repo::db::BlobReader};
+
+use super::{
+    model::{Blob, Sha1Id},
+    tree::TreeView,
+};
+
+/// Bits of `st_mode` telling the type of a file
+const FILE_TYPE_MASK: u32 = 0o170000;
+const SYMLINK_TYPE: u32 = 0o120000;
+const GITLINK_TYPE: u32 = 0o160000;
+
+/// Permissions of the entries written to archives
+const DIRECTORY_PERMS: u32 = 0o755;
+const FILE_PERMS: u32 = 0o644;
+const EXECUTABLE_PERMS: u32 = 0o755;
+
+/// [`ArchiveEntry`] is one entry of an archive, with its full path (including the prefix)
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ArchiveEntry {
+    /// Directories have a trailing slash
+    Directory(String),
+    File {
+        path: String,
+        perms: u32,
+        blob_id: Sha1Id,
+    },
+    Symlink {
+        path: String,
+        target: String,
+    },
+}
+
+/// Write the files of the snapshot as an archive in the given format. Each path is prefixed
+/// with `prefix` (use a trailing slash to put everything in a directory), and every entry
+/// has the modification time `mtime` (seconds since the epoch).
+pub fn write_archive(
+    conn: &Connection,
+    view: &TreeView,
+    format: ArchiveFormat,
+    prefix: &str,
+    mtime: i64,
+    out: &mut impl Write,
+) -> crate::Result<()> {
+    let entries = archive_entries(conn, view, prefix)?;
+    match format {
+        ArchiveFormat::Tar => write_tar(conn, &entries, mtime, out),
+        ArchiveFormat::Zip => write_zip(conn, &entries, mtime, out),
+    }
+}
+
+/// List the entries of the archive, each directory coming before its content. Submodules are
+/// skipped as their content is not in the repository.
+fn archive_entries(
+    conn: &Connection,
+    view: &TreeView,
+    prefix: &str,
+) -> crate::Result<Vec<ArchiveEntry>> {
+    let mut entries = Vec::new();
+    let mut directories = HashSet::new();
+
+    for (name, entry) in view {
+        let mode: u32 = entry.mode.parse()?;
+        if mode & FILE_TYPE_MASK == GITLINK_TYPE {
+            continue;
+        }
+
+        let path = format!("{}{}", prefix, name);
+        for (pos, _) in path.match_indices('/') {
+            let directory = &path[..=pos];
+            if directories.insert(directory.to_string()) {
+                entries.push(ArchiveEntry::Directory(directory.to_string()));
+            }
+        }
+
+        if mode & FILE_TYPE_MASK == SYMLINK_TYPE {
+            let data = Blob::read_from_conn_with_id(conn, entry.id)?.data;
+            entries.push(ArchiveEntry::Symlink {
+                path,
+                target: String::from_utf8_lossy(&data).to_string(),
+            });
+        } else {
+            let perms = if mode & 0o111 != 0 {
+                EXECUTABLE_PERMS
+            } else {
+                FILE_PERMS
+            };
+            entries.push(ArchiveEntry::File {
+                path,
+                perms,
+                blob_id: entry.id,
+            });
+        }
+    }
+
+    Ok(entries)
+}
+
+fn write_tar(
+    conn: &Connection,
+    entries: &[ArchiveEntry],
+    mtime: i64,
+    out: &mut impl Write,
+) -> crate::Result<()> {
+    let mut builder = tar::Builder::new(out);
+
+    for entry in entries {
+        let mut header = tar::Header::new_gnu();
+        header.set_mtime(mtime.max(0) as u64);
+        header.set_uid(0);
+        header.set_gid(0);
+        header.set_username("root")?;
+        header.set_groupname("root")?;
+        match entry {
+            ArchiveEntry::Directory(path) => {
+                header.set_entry_type(tar::EntryType::Directory);
+                header.set_mode(DIRECTORY_PERMS);
+                header.set_size(0);
+                builder.append_data(&mut header, path, std::io::empty())?;
+            }
+            ArchiveEntry::File {
+                path,
+                perms,
+                blob_id,
+            } => {
+                let reader = BlobReader::open(conn, (*blob_id).into())?;
+                header.set_entry_type(tar::EntryType::Regular);
+                header.set_mode(*perms);
+                header.set_size(reader.len() as u64);
+                builder.append_data(&mut header, path, reader)?;
+            }
+            ArchiveEntry::Symlink { path, target } => {
+                header.set_entry_type(tar::EntryType::Symlink);
+                header.set_mode(0o777);
+                header.set_size(0);
+                builder.append_link(&mut header, path, target)?;
+            }
+        }
+    }
+
+    builder.finish()?;
+    Ok(())
+}
+
+fn write_zip(
+    conn: &Connection,
+    entries: &[ArchiveEntry],
+    mtime: i64,
+    out: &mut impl Write,
+) -> crate::Result<()> {
+    // Zip archives are written with their central directory at the end, which needs seeking
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+
+    let options = SimpleFileOptions::default()
+        .compression_method(CompressionMethod::Deflated)
+        .last_modified_time(zip_time(mtime));
+    for entry in entries {
+        match entry {
+            ArchiveEntry::Directory(path) => {
+                writer.add_directory(path, options.unix_permissions(DIRECTORY_PERMS))?;
+            }
+            ArchiveEntry::File {
+                path,
+                perms,
+                blob_id,
+            } => {
+                writer.start_file(path, options.unix_permissions(*perms))?;
+                std::io::copy(&mut BlobReader::open(conn, (*blob_id).into())?, &mut writer)?;
+            }
+            ArchiveEntry::Symlink { path, target } => {
+                writer.add_symlink(path, target, options)?;
+            }
+        }
+    }
+
+    out.write_all(&writer.finish()?.into_inner())?;
+    Ok(())
+}
+
+/// Convert a timestamp to the MS-DOS date and time stored in zip archives, which cannot
+/// represent dates before 1980
+fn zip_time(mtime: i64) -> zip::DateTime {
+    use chrono::{Datelike, Timelike};
+
+    chrono::DateTime::from_timestamp(mtime, 0)
+        .and_then(|time| {
+            zip::DateTime::from_date_and_time(
+                time.year().try_into().ok()?,
+                time.month() as u8,
+                time.day() as u8,
+                time.hour() as u8,
+                time.minute() as u8,
+                time.second() as u8,
+            )
+            .ok()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+    use crate::git::{model::CREATE_BLOB_TABLE, test_utils::persist_blob, tree::FlatEntry};
+
+    fn setup() -> (Connection, TreeView) {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute(CREATE_BLOB_TABLE, ()).unwrap();
+
+        let mut view = TreeView::new();
+        for (name, mode, data) in [
+            ("README", 0o100644, "hello\n"),
+            ("bin/run", 0o100755, "#!/bin/sh\n"),
+            ("bin/link", 0o120777, "run"),
+            ("vendor", 0o160000, ""),
+        ] {
+            let entry = FlatEntry {
+                id: persist_blob(&conn, data.as_bytes()),
+                mode: mode.to_string(),
+            };
+            view.insert(name.to_string(), entry);
+        }
+        (conn, view)
+    }
+
+    #[test]
+    fn test_archive_entries() {
+        let (conn, view) = setup();
+        let entries = archive_entries(&conn, &view, "project/").unwrap();
+        assert_eq!(
+            vec![
+                ArchiveEntry::Directory("project/".to_string()),
+                ArchiveEntry::File {
+                    path: "project/README".to_string(),
+                    perms: 0o644,
+                    blob_id: view["README"].id
+                },
+                ArchiveEntry::Directory("project/bin/".to_string()),
+                ArchiveEntry::Symlink {
+                    path: "project/bin/link".to_string(),
+                    target: "run".to_string()
+                },
+                ArchiveEntry::File {
+                    path: "project/bin/run".to_string(),
+                    perms: 0o755,
+                    blob_id: view["bin/run"].id
+                },
+            ],
+            entries
+        );
+    }
+
+    #[test]
+    fn test_write_tar() {
+        let (conn, view) = setup();
+        let mut out = Vec::new();
+        write_archive(
+            &conn,
+            &view,
+            ArchiveFormat::Tar,
+            "",
+            1_700_000_000,
+            &mut out,
+        )
+        .unwrap();
+
+        let mut archive = tar::Archive::new(out.as_slice());
+        let mut seen = Vec::new();
+        for entry in archive.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let header = entry.header();
+            let path = entry.path().unwrap().to_string_lossy().to_string();
+            seen.push((path.clone(), header.mode().unwrap()));
+            assert_eq!(1_700_000_000, header.mtime().unwrap());
+            if path == "bin/run" {
+                let mut data = String::new();
+                entry.read_to_string(&mut data).unwrap();
+                assert_eq!("#!/bin/sh\n", data);
+            }
+        }
+        assert_eq!(
+            vec![
+                ("README".to_string(), 0o644),
+                ("bin/".to_string(), 0o755),
+                ("bin/link".to_string(), 0o777),
+                ("bin/run".to_string(), 0o755),
+            ],
+            seen
+        );
+    }
+
+    #[test]
+    fn test_write_zip() {
+        let (conn, view) = setup();
+        let mut out = Vec::new();
+        write_archive(
+            &conn,
+            &view,
+            ArchiveFormat::Zip,
+            "p/",
+            1_700_000_000,
+            &mut out,
+        )
+        .unwrap();
+
+        let mut archive = zip::ZipArchive::new(Cursor::new(out)).unwrap();
+        assert_eq!(5, archive.len());
+        let mut file = archive.by_name("p/bin/run").unwrap();
+        assert_eq!(Some(0o100755), file.unix_mode());
+        let mut data = String::new();
+        file.read_to_string(&mut data).unwrap();
+        assert_eq!("#!/bin/sh\n", data);
+    }
+}
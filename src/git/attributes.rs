@@ -0,0 +1,316 @@
+//! This module implements parsing .gitattributes files and looking up the attributes of paths.
+//!
+//! Each line of a .gitattributes file is a pattern followed by attributes: `name` sets the
+//! attribute, `-name` unsets it, `name=value` gives it a value and `!name` makes it
+//! unspecified again. A pattern without a slash matches the file name at any depth below the
+//! directory of the .gitattributes file; otherwise it matches the path relative to that
+//! directory. When several lines match, the last one wins, and files deeper in the tree take
+//! precedence over the ones above them.
+
+use std::{collections::BTreeMap, fmt, fs, path::Path};
+
+use super::{
+    constants::GITQLITE_DIRECTORY_PREFIX,
+    ignore::GitIgnore,
+    perf::{self, Category},
+};
+
+pub const ATTRIBUTES_FILE: &str = ".gitattributes";
+
+/// [`AttrValue`] is the state of an attribute for a path
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AttrValue {
+    Set,
+    Unset,
+    Value(String),
+    /// Reset by `!name`, as if no line mentioned the attribute
+    Unspecified,
+}
+
+/// As `check-attr` reports the state of an attribute
+impl fmt::Display for AttrValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AttrValue::Set => write!(f, "set"),
+            AttrValue::Unset => write!(f, "unset"),
+            AttrValue::Value(value) => write!(f, "{}", value),
+            AttrValue::Unspecified => write!(f, "unspecified"),
+        }
+    }
+}
+
+/// [`AttrRule`] is one line of a .gitattributes file
+#[derive(Debug)]
+pub struct AttrRule {
+    /// Directory of the .gitattributes file relative to the repository root ("" for the root)
+    dir: String,
+    /// Line number of the rule in its file, starting at 1
+    line: usize,
+    /// The pattern as written in the file
+    text: String,
+    pattern: glob::Pattern,
+    /// Whether the pattern is matched against the file name only
+    basename: bool,
+    attrs: Vec<(String, AttrValue)>,
+}
+
+impl AttrRule {
+    /// Path of the .gitattributes file of the rule, relative to the repository root
+    pub fn source(&self) -> String {
+        match self.dir.as_str() {
+            "" => ATTRIBUTES_FILE.to_string(),
+            dir => format!("{}/{}", dir, ATTRIBUTES_FILE),
+        }
+    }
+
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    pub fn pattern(&self) -> &str {
+        &self.text
+    }
+
+    /// Whether the rule applies to the path, relative to the repository root
+    fn matches(&self, path: &str) -> bool {
+        let options = glob::MatchOptions {
+            require_literal_separator: true,
+            ..Default::default()
+        };
+        let relative = if self.dir.is_empty() {
+            Some(path)
+        } else {
+            path.strip_prefix(self.dir.as_str())
+                .and_then(|rest| rest.strip_prefix('/'))
+        };
+        relative.is_some_and(|relative| {
+            let target = if self.basename {
+                relative.rsplit('/').next().unwrap_or(relative)
+            } else {
+                relative
+            };
+            self.pattern.matches_with(target, options)
+        })
+    }
+}
+
+/// [`Attributes`] holds the rules of all the .gitattributes files of the working tree, from
+/// the lowest to the highest priority
+#[derive(Debug, Default)]
+pub struct Attributes {
+    rules: Vec<AttrRule>,
+}
+
+/// Parse the rules of a .gitattributes file located in `dir`. Malformed patterns and macro
+/// definitions are skipped.
+pub fn parse_attributes(dir: &str, text: &str) -> Vec<AttrRule> {
+    let mut rules = Vec::new();
+
+    for (number, line) in text.lines().enumerate() {
+        let mut fields = line.split_whitespace();
+        let Some(pattern) = fields.next() else {
+            continue;
+        };
+        if pattern.starts_with('#') || pattern.starts_with("[attr]") {
+            continue;
+        }
+
+        let basename = !pattern.contains('/');
+        let Ok(compiled) = glob::Pattern::new(pattern.trim_start_matches('/')) else {
+            log::warn!("Skipping malformed gitattributes pattern {}", pattern);
+            continue;
+        };
+
+        let attrs = fields
+            .map(|field| {
+                if let Some(name) = field.strip_prefix('-') {
+                    (name.to_string(), AttrValue::Unset)
+                } else if let Some(name) = field.strip_prefix('!') {
+                    (name.to_string(), AttrValue::Unspecified)
+                } else if let Some((name, value)) = field.split_once('=') {
+                    (name.to_string(), AttrValue::Value(value.to_string()))
+                } else {
+                    (field.to_string(), AttrValue::Set)
+                }
+            })
+            .collect();
+
+        rules.push(AttrRule {
+            dir: dir.to_string(),
+            line: number + 1,
+            text: pattern.to_string(),
+            pattern: compiled,
+            basename,
+            attrs,
+        });
+    }
+
+    rules
+}
+
+/// Read the .gitattributes files of the working tree
+pub fn read_attributes(repo_root: impl AsRef<Path>) -> crate::Result<Attributes> {
+    let repo_root = repo_root.as_ref();
+
+    // Collect the directories containing a .gitattributes file, shallowest first. Symbolic
+    // links are not followed, and ignored directories hold no tracked file to give
+    // attributes to.
+    let walk = perf::span(Category::FsWalk);
+    let mut dirs = Vec::new();
+    let mut ignore = GitIgnore::empty();
+    let mut stack = vec![repo_root.to_path_buf()];
+    while let Some(current_dir) = stack.pop() {
+        ignore.read_directory(&current_dir)?;
+        if current_dir.join(ATTRIBUTES_FILE).is_file() {
+            dirs.push(current_dir.clone());
+        }
+        for entry in fs::read_dir(&current_dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            if entry.file_type()?.is_dir()
+                && !path.ends_with(GITQLITE_DIRECTORY_PREFIX)
+                && !ignore.should_ignore(&path)
+            {
+                stack.push(path);
+            }
+        }
+    }
+    drop(walk);
+    dirs.sort_by_key(|dir| (dir.components().count(), dir.clone()));
+
+    let mut rules = Vec::new();
+    for dir in dirs {
+        let text = fs::read_to_string(dir.join(ATTRIBUTES_FILE))?;
+        let relative = dir
+            .strip_prefix(repo_root)?
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        rules.extend(parse_attributes(&relative, &text));
+    }
+
+    Ok(Attributes::from_rules(rules))
+}
+
+impl Attributes {
+    /// Attributes made of the given rules, from the lowest to the highest priority
+    pub fn from_rules(rules: Vec<AttrRule>) -> Attributes {
+        Attributes { rules }
+    }
+
+    /// The state of the attribute for the path (relative to the repository root), None if
+    /// it is unspecified
+    pub fn get(&self, path: &str, name: &str) -> Option<&AttrValue> {
+        self.lookup(path, name).map(|(value, _)| value)
+    }
+
+    /// The state of the attribute for the path along with the rule deciding it, None if it
+    /// is unspecified
+    pub fn lookup(&self, path: &str, name: &str) -> Option<(&AttrValue, &AttrRule)> {
+        let (value, rule) = self
+            .rules
+            .iter()
+            .rev()
+            .filter(|rule| rule.matches(path))
+            .find_map(|rule| {
+                rule.attrs
+                    .iter()
+                    .rev()
+                    .find(|(attr, _)| attr == name)
+                    .map(|(_, value)| (value, rule))
+            })?;
+
+        match value {
+            AttrValue::Unspecified => None,
+            value => Some((value, rule)),
+        }
+    }
+
+    /// Every attribute specified for the path, by name, along with the rule deciding it
+    pub fn lookup_all(&self, path: &str) -> BTreeMap<&str, (&AttrValue, &AttrRule)> {
+        let mut found = BTreeMap::new();
+        for rule in self.rules.iter().rev().filter(|rule| rule.matches(path)) {
+            for (attr, value) in rule.attrs.iter().rev() {
+                found.entry(attr.as_str()).or_insert((value, rule));
+            }
+        }
+        found.retain(|_, (value, _)| **value != AttrValue::Unspecified);
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_attributes() {
+        let rules = parse_attributes("", "# comment\n\n*.png -diff\n[attr]bin -diff\n");
+        assert_eq!(1, rules.len());
+        assert!(rules[0].basename);
+        assert_eq!(vec![("diff".to_string(), AttrValue::Unset)], rules[0].attrs);
+    }
+
+    #[test]
+    fn test_get_attribute() {
+        let mut rules = parse_attributes(
+            "",
+            "*.ipynb diff=jupyter\n/docs/*.md text diff=markdown\n*.bin -diff\n",
+        );
+        rules.extend(parse_attributes("vendor", "*.ipynb !diff\nlib/* diff\n"));
+        let attributes = Attributes { rules };
+
+        let value = |path| attributes.get(path, "diff").cloned();
+        assert_eq!(
+            Some(AttrValue::Value("jupyter".to_string())),
+            value("a/b/c.ipynb")
+        );
+        assert_eq!(
+            Some(AttrValue::Value("markdown".to_string())),
+            value("docs/x.md")
+        );
+        assert_eq!(None, value("docs/sub/x.md"));
+        assert_eq!(Some(AttrValue::Unset), value("data.bin"));
+        assert_eq!(None, value("vendor/c.ipynb"));
+        assert_eq!(Some(AttrValue::Set), value("vendor/lib/x.bin"));
+        assert_eq!(None, value("README"));
+        assert_eq!(Some(&AttrValue::Set), attributes.get("docs/x.md", "text"));
+
+        let (_, rule) = attributes.lookup("vendor/lib/x.bin", "diff").unwrap();
+        assert_eq!(
+            ("vendor/.gitattributes", 2, "lib/*"),
+            (rule.source().as_str(), rule.line(), rule.pattern())
+        );
+
+        let all = attributes.lookup_all("docs/x.md");
+        assert_eq!(
+            vec!["diff", "text"],
+            all.keys().copied().collect::<Vec<_>>()
+        );
+        assert_eq!("/docs/*.md", all["text"].1.pattern());
+        assert!(attributes.lookup_all("vendor/c.ipynb").is_empty());
+    }
+
+    #[test]
+    fn test_read_attributes() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::write(root.join(ATTRIBUTES_FILE), "*.txt text\n").unwrap();
+        fs::create_dir(root.join("sub")).unwrap();
+        fs::write(root.join("sub").join(ATTRIBUTES_FILE), "*.txt -text\n").unwrap();
+        // Ignored directories are not searched
+        fs::write(root.join(".gitignore"), "build\n").unwrap();
+        fs::create_dir(root.join("build")).unwrap();
+        fs::write(root.join("build").join(ATTRIBUTES_FILE), "*.txt diff\n").unwrap();
+        // Neither are symbolic links, which would loop forever here
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(".", root.join("loop")).unwrap();
+
+        let attributes = read_attributes(root).unwrap();
+        let sources: Vec<String> = attributes.rules.iter().map(AttrRule::source).collect();
+        assert_eq!(vec![".gitattributes", "sub/.gitattributes"], sources);
+        assert_eq!(Some(&AttrValue::Set), attributes.get("a.txt", "text"));
+        assert_eq!(Some(&AttrValue::Unset), attributes.get("sub/a.txt", "text"));
+    }
+}
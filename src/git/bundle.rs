@@ -0,0 +1,476 @@
+//! This module implements bundles: single files carrying a set of refs along with the objects
+//! needed to use them, so that history can be moved between repositories without a network.
+//!
+//! A bundle is itself an SQLite database holding the object tables of a repository (Commits,
+//! Trees and Blobs), the refs it carries, and its prerequisites: the commits the history in the
+//! bundle builds on but does not contain, which the receiving repository must already have.
+
+use std::{
+    collections::{HashSet, VecDeque},
+    fs,
+    path::Path,
+};
+
+use anyhow::anyhow;
+use rusqlite::{config::DbConfig, Connection, OpenFlags};
+
+use crate::repo::db::{object_exists, read_object_type, HashAlgorithm, ObjectType};
+
+use super::{
+    file_changes::record_file_changes,
+    merge::ancestors,
+    model::{
+        commit_layout, hash_blob_data, Blob, Commit, Hashable, Sha1Id, Tree, TreeEntryType,
+        CREATE_BLOB_TABLE, CREATE_COMMIT_TABLE, CREATE_IDENTITIES_TABLE, CREATE_TREE_TABLE,
+        INSERT_TREE,
+    },
+    reachability::{missing_commits, record_reachability},
+    rebase::summary,
+    utils::WriteTransaction,
+};
+
+/// BundleRefs lists the refs carried by a bundle
+pub const CREATE_BUNDLE_REF_TABLE: &str =
+    "CREATE TABLE BundleRefs (ref_name TEXT PRIMARY KEY, commit_id BLOB NOT NULL);";
+/// BundlePrerequisites lists the commits a bundle builds on, with their summary line
+pub const CREATE_BUNDLE_PREREQUISITE_TABLE: &str =
+    "CREATE TABLE BundlePrerequisites (commit_id BLOB PRIMARY KEY, summary TEXT NOT NULL);";
+
+const READ_BUNDLE_REFS: &str = "SELECT ref_name, commit_id FROM BundleRefs ORDER BY ref_name";
+const READ_BUNDLE_PREREQUISITES: &str = "SELECT commit_id, summary FROM BundlePrerequisites";
+const INSERT_BUNDLE_REF: &str = "INSERT INTO BundleRefs (ref_name, commit_id) VALUES (?1, ?2);";
+const INSERT_BUNDLE_PREREQUISITE: &str =
+    "INSERT OR IGNORE INTO BundlePrerequisites (commit_id, summary) VALUES (?1, ?2);";
+const READ_BUNDLE_TREES: &str = "SELECT tree_id, data FROM Trees";
+const READ_BUNDLE_BLOBS: &str = "SELECT blob_id, data FROM Blobs";
+
+/// [`BundleHeader`] describes what a bundle carries and what it requires
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BundleHeader {
+    pub refs: Vec<(String, Sha1Id)>,
+    /// Commits the receiving repository must have, with their summary line
+    pub prerequisites: Vec<(Sha1Id, String)>,
+}
+
+/// Write a bundle to `path` carrying the given refs and the history reachable from them,
+/// leaving out the history reachable from `exclude`. Returns the number of commits written.
+pub fn create_bundle(
+    conn: &Connection,
+    path: impl AsRef<Path>,
+    refs: &[(String, Sha1Id)],
+    exclude: &[Sha1Id],
+) -> crate::Result<usize> {
+    let tips: Vec<Sha1Id> = refs.iter().map(|(_, id)| *id).collect();
+    let (commits, prerequisites) = bundled_history(conn, &tips, exclude)?;
+
+    let path = path.as_ref();
+    if path.exists() {
+        fs::remove_file(path)?;
+    }
+    let mut bundle = Connection::open(path)?;
+    let tx = bundle.transaction()?;
+    for create in [
+        CREATE_IDENTITIES_TABLE,
+        CREATE_COMMIT_TABLE,
+        CREATE_TREE_TABLE,
+        CREATE_BLOB_TABLE,
+        CREATE_BUNDLE_REF_TABLE,
+        CREATE_BUNDLE_PREREQUISITE_TABLE,
+    ] {
+        tx.execute(create, ())?;
+    }
+
+    for (name, id) in refs {
+        tx.execute(INSERT_BUNDLE_REF, (name, id))?;
+    }
+    for commit in &prerequisites {
+        tx.execute(
+            INSERT_BUNDLE_PREREQUISITE,
+            (commit.commit_id, summary(&commit.message)),
+        )?;
+    }
+
+    let mut written_trees = HashSet::new();
+    let mut written_blobs = HashSet::new();
+    for commit in &commits {
+        commit.persist(&tx)?;
+
+        let mut stack = vec![commit.tree_id];
+        while let Some(tree_id) = stack.pop() {
+            if !written_trees.insert(tree_id) {
+                continue;
+            }
+            let tree = Tree::read_from_conn_with_id(conn, tree_id)?;
+            for entry in &tree.entries {
+                match entry.type_ {
+                    TreeEntryType::Tree => stack.push(entry.id),
+                    TreeEntryType::Blob => {
+                        if written_blobs.insert(entry.id) {
+                            Blob::read_from_conn_with_id(conn, entry.id)?.persist(&tx)?;
+                        }
+                    }
+                }
+            }
+            tree.persist(&tx)?;
+        }
+    }
+    tx.commit()?;
+
+    Ok(commits.len())
+}
+
+/// The commits a bundle of the history reachable from `tips` but not from `exclude` carries,
+/// in no particular order
+pub fn bundled_commits(
+    conn: &Connection,
+    tips: &[Sha1Id],
+    exclude: &[Sha1Id],
+) -> crate::Result<Vec<Commit<Sha1Id>>> {
+    Ok(bundled_history(conn, tips, exclude)?.0)
+}
+
+/// Read the refs and prerequisites of the bundle at `path`
+pub fn read_bundle_header(path: impl AsRef<Path>) -> crate::Result<BundleHeader> {
+    let bundle = open_bundle(path.as_ref())?;
+
+    let refs = bundle
+        .prepare(READ_BUNDLE_REFS)?
+        .query_map((), |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<Vec<_>, _>>()?;
+    let prerequisites = bundle
+        .prepare(READ_BUNDLE_PREREQUISITES)?
+        .query_map((), |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(BundleHeader {
+        refs,
+        prerequisites,
+    })
+}
+
+/// The prerequisites of the bundle missing from the repository
+pub fn missing_prerequisites<'a>(
+    conn: &Connection,
+    header: &'a BundleHeader,
+) -> crate::Result<Vec<&'a (Sha1Id, String)>> {
+    let mut missing = Vec::new();
+    for prerequisite in &header.prerequisites {
+        if read_object_type(conn, prerequisite.0.into())? != Some(ObjectType::Commit) {
+            missing.push(prerequisite);
+        }
+    }
+    Ok(missing)
+}
+
+/// Copy the objects of the bundle at `path` into the repository. Refs are left untouched.
+pub fn unbundle(conn: &Connection, path: impl AsRef<Path>) -> crate::Result<BundleHeader> {
+    let path = path.as_ref();
+    let header = read_bundle_header(path)?;
+    let missing = missing_prerequisites(conn, &header)?;
+    if !missing.is_empty() {
+        return Err(lacking_prerequisites(&missing));
+    }
+
+    let commits = copy_bundle_objects(conn, &open_bundle(path)?)?;
+    record_file_changes(conn, &commits.iter().collect::<Vec<_>>())?;
+    record_reachability(
+        conn,
+        &commits
+            .iter()
+            .map(|commit| commit.commit_id)
+            .collect::<Vec<_>>(),
+    )?;
+
+    Ok(header)
+}
+
+/// Copy the objects of the bundle into the repository in a transaction of its own, returning
+/// the commits the repository lacked. Every object is hashed again, and the whole bundle is
+/// refused if one does not match its id: stored first, a forged object would shadow the real
+/// one for good.
+fn copy_bundle_objects(
+    conn: &Connection,
+    bundle: &Connection,
+) -> crate::Result<Vec<Commit<Sha1Id>>> {
+    let tx = WriteTransaction::new(conn)?;
+    let algorithm = HashAlgorithm::read(&tx)?;
+
+    let mut new_commits = Vec::new();
+    let select = commit_layout(bundle, "main")?.select_commits("main");
+    let mut stmt = bundle.prepare(&select)?;
+    let mut rows = stmt.query(())?;
+    while let Some(row) = rows.next()? {
+        let commit = Commit::from_row(row)?;
+        check_object_id(commit.commit_id, commit.hash(algorithm.hasher()))?;
+        if read_object_type(&tx, commit.commit_id.into())? != Some(ObjectType::Commit) {
+            commit.persist(&tx)?;
+            new_commits.push(commit);
+        }
+    }
+
+    let mut stmt = bundle.prepare(READ_BUNDLE_TREES)?;
+    let mut rows = stmt.query(())?;
+    while let Some(row) = rows.next()? {
+        let (tree_id, data): (Sha1Id, String) = (row.get(0)?, row.get(1)?);
+        if !is_tree_data(&data) {
+            return Err(anyhow!("fatal: malformed tree {} in the bundle", tree_id));
+        }
+        // Trees are stored as the text they are hashed from
+        check_object_id(tree_id, hash_blob_data(algorithm.hasher(), data.as_bytes()))?;
+        tx.execute(INSERT_TREE, (tree_id, data))?;
+    }
+
+    let mut stmt = bundle.prepare(READ_BUNDLE_BLOBS)?;
+    let mut rows = stmt.query(())?;
+    while let Some(row) = rows.next()? {
+        let blob = Blob {
+            blob_id: row.get(0)?,
+            data: row.get(1)?,
+        };
+        check_object_id(blob.blob_id, blob.hash(algorithm.hasher()))?;
+        if !object_exists(&tx, blob.blob_id.into())? {
+            blob.persist(&tx)?;
+        }
+    }
+
+    tx.commit()?;
+    Ok(new_commits)
+}
+
+fn check_object_id(id: Sha1Id, hashed: Sha1Id) -> crate::Result<()> {
+    if id != hashed {
+        return Err(anyhow!(
+            "fatal: object {} in the bundle does not match its content",
+            id
+        ));
+    }
+    Ok(())
+}
+
+/// Whether `data` is made of lines `<mode> <blob|tree> <id> <name>`, as tree readers expect
+fn is_tree_data(data: &str) -> bool {
+    data.split('\n')
+        .filter(|line| !line.is_empty())
+        .all(|line| {
+            let fields: Vec<&str> = line.splitn(4, ' ').collect();
+            matches!(fields[..], [_, "blob" | "tree", id, name]
+            if Sha1Id::try_from(id).is_ok() && !name.is_empty())
+        })
+}
+
+/// The commits to bundle, and the prerequisites
+type BundledHistory = (Vec<Commit<Sha1Id>>, Vec<Commit<Sha1Id>>);
+
+fn bundled_history(
+    conn: &Connection,
+    tips: &[Sha1Id],
+    exclude: &[Sha1Id],
+) -> crate::Result<BundledHistory> {
+    match missing_commits(conn, tips, exclude)? {
+        Some(ids) => split_at_boundary(conn, tips, ids),
+        None => walk_history(conn, tips, exclude),
+    }
+}
+
+/// Read the commits to bundle, and the prerequisites: the commits outside of them that the
+/// tips or the parents of the commits are
+fn split_at_boundary(
+    conn: &Connection,
+    tips: &[Sha1Id],
+    ids: Vec<Sha1Id>,
+) -> crate::Result<BundledHistory> {
+    let included: HashSet<Sha1Id> = ids.iter().copied().collect();
+    let mut commits = Vec::with_capacity(ids.len());
+    for id in ids {
+        commits.push(Commit::read_from_conn_with_id(conn, id)?);
+    }
+    let mut boundary = HashSet::new();
+    let mut prerequisites = Vec::new();
+    let parents = commits.iter().flat_map(|commit| commit.parent_ids.iter());
+    for id in tips.iter().chain(parents) {
+        if !included.contains(id) && boundary.insert(*id) {
+            prerequisites.push(Commit::read_from_conn_with_id(conn, *id)?);
+        }
+    }
+    Ok((commits, prerequisites))
+}
+
+/// Walk the history from the tips, stopping at excluded commits which become prerequisites
+fn walk_history(
+    conn: &Connection,
+    tips: &[Sha1Id],
+    exclude: &[Sha1Id],
+) -> crate::Result<BundledHistory> {
+    let mut excluded = HashSet::new();
+    for id in exclude {
+        excluded.extend(ancestors(conn, *id)?);
+    }
+
+    let mut commits = Vec::new();
+    let mut prerequisites = Vec::new();
+    let mut seen = HashSet::new();
+    let mut queue: VecDeque<Sha1Id> = tips.iter().copied().collect();
+    while let Some(id) = queue.pop_front() {
+        if !seen.insert(id) {
+            continue;
+        }
+        let commit = Commit::read_from_conn_with_id(conn, id)?;
+        if excluded.contains(&id) {
+            prerequisites.push(commit);
+            continue;
+        }
+        queue.extend(commit.parent_ids.iter().copied());
+        commits.push(commit);
+    }
+    Ok((commits, prerequisites))
+}
+
+/// The error reported when the repository lacks some prerequisites of a bundle
+pub fn lacking_prerequisites(missing: &[&(Sha1Id, String)]) -> anyhow::Error {
+    let lines: Vec<String> = missing
+        .iter()
+        .map(|(id, summary)| format!("error: {} {}", id, summary))
+        .collect();
+    anyhow!(
+        "error: Repository lacks these prerequisite commits:\n{}",
+        lines.join("\n")
+    )
+}
+
+fn open_bundle(path: &Path) -> crate::Result<Connection> {
+    let not_a_bundle = || {
+        anyhow!(
+            "error: '{}' does not look like a bundle file",
+            path.display()
+        )
+    };
+
+    let bundle = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|_| not_a_bundle())?;
+    // The bundle comes from elsewhere, so its schema may not run anything but plain SQL
+    bundle.set_db_config(DbConfig::SQLITE_DBCONFIG_DEFENSIVE, true)?;
+    bundle.pragma_update(None, "trusted_schema", false)?;
+    let is_bundle = bundle
+        .query_row(
+            "SELECT count(*) FROM sqlite_master WHERE type = 'table' AND name IN ('BundleRefs', 'BundlePrerequisites')",
+            (),
+            |row| row.get::<_, i64>(0),
+        )
+        .map_err(|_| not_a_bundle())?
+        == 2;
+    if !is_bundle {
+        return Err(not_a_bundle());
+    }
+    Ok(bundle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::{
+        file_changes::CREATE_FILE_CHANGES_TABLE,
+        reachability::CREATE_COMMIT_GENERATIONS_TABLE,
+        test_utils::{commit_content, object_database},
+    };
+
+    fn setup() -> Connection {
+        object_database(&[CREATE_COMMIT_GENERATIONS_TABLE, CREATE_FILE_CHANGES_TABLE])
+    }
+
+    fn count(conn: &Connection, table: &str) -> i64 {
+        conn.query_row(&format!("SELECT count(*) FROM {}", table), (), |row| {
+            row.get(0)
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_bundle_round_trip() {
+        let conn = setup();
+        let root = commit_content(&conn, vec![], "root");
+        let second = commit_content(&conn, vec![root], "second");
+        let tip = commit_content(&conn, vec![second], "tip");
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("repo.bundle");
+        let refs = vec![("refs/head/main".to_string(), tip)];
+        assert_eq!(2, create_bundle(&conn, &path, &refs, &[root]).unwrap());
+
+        let header = read_bundle_header(&path).unwrap();
+        assert_eq!(refs, header.refs);
+        assert_eq!(vec![(root, "root".to_string())], header.prerequisites);
+
+        // A repository without the prerequisite cannot take the bundle
+        let other = setup();
+        assert_eq!(1, missing_prerequisites(&other, &header).unwrap().len());
+        assert!(unbundle(&other, &path).is_err());
+
+        commit_content(&other, vec![], "root");
+        unbundle(&other, &path).unwrap();
+        assert_eq!(3, count(&other, "Commits"));
+        assert_eq!(3, count(&other, "Blobs"));
+        assert!(Commit::read_from_conn_with_id(&other, tip).is_ok());
+
+        // Unbundling again adds nothing
+        unbundle(&other, &path).unwrap();
+        assert_eq!(3, count(&other, "Blobs"));
+    }
+
+    #[test]
+    fn test_forged_bundle() {
+        let conn = setup();
+        let root = commit_content(&conn, vec![], "root");
+        let tip = commit_content(&conn, vec![root], "tip");
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("repo.bundle");
+        let refs = vec![("refs/head/main".to_string(), tip)];
+        create_bundle(&conn, &path, &refs, &[]).unwrap();
+
+        // An object which does not hash to its id refuses the whole bundle
+        let bundle = Connection::open(&path).unwrap();
+        bundle
+            .execute(
+                "UPDATE Blobs SET data = CAST('forged' AS BLOB) WHERE data = CAST('tip' AS BLOB)",
+                (),
+            )
+            .unwrap();
+        let other = setup();
+        assert!(unbundle(&other, &path).is_err());
+        assert_eq!(0, count(&other, "Commits"));
+        assert_eq!(0, count(&other, "Blobs"));
+
+        // So does a tree that cannot be read back, even if it hashes to its id
+        bundle
+            .execute(
+                "UPDATE Blobs SET data = CAST('tip' AS BLOB) WHERE data = CAST('forged' AS BLOB)",
+                (),
+            )
+            .unwrap();
+        let garbage = "not a tree";
+        bundle
+            .execute(
+                "INSERT INTO Trees (tree_id, data) VALUES (?1, ?2)",
+                (
+                    hash_blob_data(HashAlgorithm::Sha1.hasher(), garbage.as_bytes()),
+                    garbage,
+                ),
+            )
+            .unwrap();
+        assert!(unbundle(&other, &path).is_err());
+        assert_eq!(0, count(&other, "Commits"));
+
+        bundle
+            .execute("DELETE FROM Trees WHERE data = ?1", [garbage])
+            .unwrap();
+        unbundle(&other, &path).unwrap();
+        assert_eq!(2, count(&other, "Commits"));
+    }
+
+    #[test]
+    fn test_not_a_bundle() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("garbage");
+        fs::write(&path, "not a database").unwrap();
+        assert!(read_bundle_header(&path).is_err());
+    }
+}
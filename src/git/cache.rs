@@ -0,0 +1,124 @@
+//! This module memoizes the trees and commits read during one command invocation, so that
+//! commands flattening the trees of many commits, such as indexing the file changes of the
+//! whole history, do not re-read and re-parse the subtrees the commits share.
+
+use std::rc::Rc;
+
+use rusqlite::Connection;
+
+use super::model::{Commit, Sha1Id, Tree};
+use crate::repo::db::LruCache;
+
+/// Number of trees and of commits kept by a default [`ObjectCache`]
+pub const DEFAULT_CACHE_CAPACITY: usize = 1024;
+
+/// [`ObjectCache`] keeps the trees and commits recently read from the database.
+///
+/// Objects are content-addressed and never change once written, so cached objects never go
+/// stale. The cache must not be shared between connections to different databases though, as
+/// an object present in one of them may be missing from the other.
+#[derive(Debug)]
+pub struct ObjectCache {
+    trees: LruCache<Sha1Id, Rc<Tree<Sha1Id>>>,
+    commits: LruCache<Sha1Id, Rc<Commit<Sha1Id>>>,
+    /// Number of objects read from the database rather than from the cache
+    reads: usize,
+}
+
+impl Default for ObjectCache {
+    fn default() -> Self {
+        ObjectCache::new(DEFAULT_CACHE_CAPACITY)
+    }
+}
+
+impl ObjectCache {
+    /// Create a cache keeping up to `capacity` trees and `capacity` commits
+    pub fn new(capacity: usize) -> ObjectCache {
+        ObjectCache {
+            trees: LruCache::new(capacity),
+            commits: LruCache::new(capacity),
+            reads: 0,
+        }
+    }
+
+    /// Read a tree by id, from the cache if it was read recently
+    pub fn read_tree(&mut self, conn: &Connection, id: Sha1Id) -> crate::Result<Rc<Tree<Sha1Id>>> {
+        if let Some(tree) = self.trees.get(&id) {
+            return Ok(Rc::clone(tree));
+        }
+        // Missing objects are not cached, as they may be written later on
+        let tree = Rc::new(Tree::read_from_conn_with_id(conn, id)?);
+        self.reads += 1;
+        self.trees.insert(id, Rc::clone(&tree));
+        Ok(tree)
+    }
+
+    /// Read a commit by id, from the cache if it was read recently
+    pub fn read_commit(
+        &mut self,
+        conn: &Connection,
+        id: Sha1Id,
+    ) -> crate::Result<Rc<Commit<Sha1Id>>> {
+        if let Some(commit) = self.commits.get(&id) {
+            return Ok(Rc::clone(commit));
+        }
+        let commit = Rc::new(Commit::read_from_conn_with_id(conn, id)?);
+        self.reads += 1;
+        self.commits.insert(id, Rc::clone(&commit));
+        Ok(commit)
+    }
+
+    /// Number of objects the cache read from the database so far
+    #[cfg(test)]
+    pub fn reads(&self) -> usize {
+        self.reads
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::{
+        model::CREATE_TREE_TABLE,
+        tree::{flatten_tree, flatten_tree_cached, write_tree, FlatEntry, TreeView},
+    };
+    use crate::repo::db::HashAlgorithm;
+
+    fn entry(byte: u8) -> FlatEntry {
+        FlatEntry {
+            id: Sha1Id::try_from(vec![byte; 20]).unwrap(),
+            mode: "33188".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_flatten_shared_subtrees() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute(CREATE_TREE_TABLE, ()).unwrap();
+        HashAlgorithm::Sha1.persist(&conn).unwrap();
+
+        // Two snapshots differing by one top-level file share the trees of `src` and `src/bin`
+        let mut old = TreeView::new();
+        old.insert("README.md".to_string(), entry(1));
+        old.insert("src/lib.rs".to_string(), entry(2));
+        old.insert("src/bin/main.rs".to_string(), entry(3));
+        let mut new = old.clone();
+        new.insert("README.md".to_string(), entry(4));
+        let old_id = write_tree(&conn, &old).unwrap();
+        let new_id = write_tree(&conn, &new).unwrap();
+
+        let mut cache = ObjectCache::new(16);
+        assert_eq!(old, flatten_tree_cached(&conn, &mut cache, old_id).unwrap());
+        assert_eq!(3, cache.reads());
+        // Only the root tree of the second snapshot is read
+        assert_eq!(new, flatten_tree_cached(&conn, &mut cache, new_id).unwrap());
+        assert_eq!(4, cache.reads());
+        assert_eq!(new, flatten_tree_cached(&conn, &mut cache, new_id).unwrap());
+        assert_eq!(4, cache.reads());
+
+        // The cached trees are served even once the rows are gone
+        conn.execute("DELETE FROM Trees", ()).unwrap();
+        assert_eq!(old, flatten_tree_cached(&conn, &mut cache, old_id).unwrap());
+        assert!(flatten_tree(&conn, old_id).is_err());
+    }
+}
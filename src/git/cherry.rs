@@ -0,0 +1,80 @@
+//! This module finds which commits of a branch have already been applied upstream, as
+//! `git cherry` does, by comparing the patch ids of the commits on both sides.
+
+use std::collections::HashSet;
+
+use rusqlite::Connection;
+
+use super::{
+    merge::ancestors,
+    model::Sha1Id,
+    patchid::{commit_patch_id, patch_ids_between},
+    revision::commits_between,
+};
+
+/// The commits of `head` not in `upstream` (nor in `limit`), parents first, each marked with
+/// whether a commit of `upstream` not in `head` makes the same change
+pub fn cherry(
+    conn: &Connection,
+    upstream: Sha1Id,
+    head: Sha1Id,
+    limit: Option<Sha1Id>,
+) -> crate::Result<Vec<(Sha1Id, bool)>> {
+    let applied = patch_ids_between(conn, head, upstream)?;
+
+    let excluded = match limit {
+        Some(limit) => ancestors(conn, limit)?,
+        None => HashSet::new(),
+    };
+    let mut commits = Vec::new();
+    for id in commits_between(conn, Some(upstream), head)? {
+        if excluded.contains(&id) {
+            continue;
+        }
+        let equivalent =
+            commit_patch_id(conn, id, false)?.is_some_and(|patch_id| applied.contains(&patch_id));
+        commits.push((id, equivalent));
+    }
+    Ok(commits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::test_utils::{commit_change, object_database};
+
+    fn commit(conn: &Connection, parent: Option<Sha1Id>, file: &str, content: &str) -> Sha1Id {
+        commit_change(conn, parent, file, content, &format!("change {}", file))
+    }
+
+    #[test]
+    fn test_cherry() {
+        let conn = object_database(&[]);
+        let base = commit(&conn, None, "base", "base\n");
+
+        let a = commit(&conn, Some(base), "a", "a\n");
+        let b = commit(&conn, Some(a), "b", "b\n");
+        let c = commit(&conn, Some(b), "c", "c\n");
+        // Upstream picked the change to b, with different whitespace, on top of another change
+        let other = commit(&conn, Some(base), "other", "other\n");
+        let picked = commit(&conn, Some(other), "b", "b \n");
+
+        assert_eq!(
+            commit_patch_id(&conn, b, false).unwrap(),
+            commit_patch_id(&conn, picked, false).unwrap()
+        );
+        assert_ne!(
+            commit_patch_id(&conn, a, false).unwrap(),
+            commit_patch_id(&conn, b, false).unwrap()
+        );
+
+        assert_eq!(
+            vec![(a, false), (b, true), (c, false)],
+            cherry(&conn, picked, c, None).unwrap()
+        );
+        assert_eq!(
+            vec![(b, true), (c, false)],
+            cherry(&conn, picked, c, Some(a)).unwrap()
+        );
+    }
+}
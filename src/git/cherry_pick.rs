@@ -0,0 +1,201 @@
+//! This module implements the cherry-pick sequencer: it applies the changes of a list of
+//! commits on top of HEAD in order, committing each of them on the current branch. The
+//! progress is recorded in the [`CherryPickState`] table so that a cherry-pick stopped by a
+//! conflict can be resumed with `--continue`, `--skip` or `--abort`.
+
+use std::path::Path;
+
+use anyhow::anyhow;
+use rusqlite::Connection;
+
+use crate::repo::config::GitConfig;
+
+use super::{
+    cmds::commit::commit_tree,
+    constants::BRANCH_PREFIX,
+    merge::{pick_commit, pick_commit_into_index},
+    model::{CherryPickState, Commit, Head, Index, Sha1Id},
+    progress::Progress,
+    rebase::{head_commit, short_id, summary},
+    tree::{flatten_tree, index_view, write_tree},
+    worktree::reset_hard,
+};
+
+/// Pick the remaining commits until all of them are applied or one needs the user's attention
+pub fn run(
+    conn: &Connection,
+    repo_root: &Path,
+    config: &GitConfig,
+    mut state: CherryPickState,
+) -> crate::Result<()> {
+    while !state.todo.is_empty() {
+        let commit_id = state.todo.remove(0);
+        state.persist(conn)?;
+
+        let commit = Commit::read_from_conn_with_id(conn, commit_id)?;
+        let head_id = head_commit(conn)?;
+        let mut index = Index::read_from_conn(conn)?;
+
+        let label = format!("{} ({})", short_id(commit_id), summary(&commit.message));
+        let outcome = pick_commit(conn, repo_root, &mut index, &commit, head_id, &label)?;
+        index.persist(conn)?;
+
+        for conflict in &outcome.conflicts {
+            println!("{}", conflict.describe(("HEAD", &label)));
+        }
+        if !outcome.conflicts.is_empty() {
+            state.conflicted = Some(commit_id);
+            state.persist(conn)?;
+            return Err(anyhow!(
+                "error: could not apply {}... {}\nhint: After resolving the conflicts, mark them with \"gitqlite add <paths>\", then run \"gitqlite cherry-pick --continue\".\nhint: You can instead skip this commit with \"gitqlite cherry-pick --skip\".\nhint: To abort and get back to the state before \"gitqlite cherry-pick\", run \"gitqlite cherry-pick --abort\".",
+                short_id(commit_id),
+                summary(&commit.message)
+            ));
+        }
+
+        let tree_id = write_tree(conn, &outcome.tree)?;
+        if tree_id == Commit::read_from_conn_with_id(conn, head_id)?.tree_id {
+            state.conflicted = Some(commit_id);
+            state.persist(conn)?;
+            return Err(anyhow!(
+                "The previous cherry-pick is now empty, possibly due to conflict resolution.\nUse \"gitqlite cherry-pick --skip\" to skip this commit."
+            ));
+        }
+
+        commit_picked(conn, config, &commit, tree_id)?;
+    }
+
+    CherryPickState::clear(conn)
+}
+
+/// Apply the changes of the commits to the index and working tree one after another without
+/// committing them. Nothing is recorded to resume from, so a conflict stops at the commit
+/// that caused it.
+pub fn stage(conn: &Connection, repo_root: &Path, commits: &[Sha1Id]) -> crate::Result<()> {
+    for &commit_id in commits {
+        let commit = Commit::read_from_conn_with_id(conn, commit_id)?;
+        let mut index = Index::read_from_conn(conn)?;
+
+        let label = format!("{} ({})", short_id(commit_id), summary(&commit.message));
+        let outcome = pick_commit_into_index(conn, repo_root, &mut index, &commit, &label)?;
+        index.persist(conn)?;
+
+        for conflict in &outcome.conflicts {
+            println!("{}", conflict.describe(("HEAD", &label)));
+        }
+        if !outcome.conflicts.is_empty() {
+            return Err(anyhow!(
+                "error: could not apply {}... {}\nhint: After resolving the conflicts, mark them with \"gitqlite add <paths>\".",
+                short_id(commit_id),
+                summary(&commit.message)
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Commit the resolution of the commit that stopped the cherry-pick, then pick the remaining
+/// commits
+pub fn resume(
+    conn: &Connection,
+    repo_root: &Path,
+    config: &GitConfig,
+    mut state: CherryPickState,
+) -> crate::Result<()> {
+    let index = Index::read_from_conn(conn)?;
+    if index.has_conflicts() {
+        return Err(anyhow!(
+            "error: you must edit all merge conflicts and then mark them as resolved using \"gitqlite add\": {}",
+            index.conflicted_names().join(", ")
+        ));
+    }
+
+    if let Some(commit_id) = state.conflicted.take() {
+        let commit = Commit::read_from_conn_with_id(conn, commit_id)?;
+        let head_tree = Commit::read_from_conn_with_id(conn, head_commit(conn)?)?.tree_id;
+        let tree_id = write_tree(conn, &index_view(&index))?;
+        // A commit whose changes were all resolved away is dropped
+        if tree_id != head_tree {
+            commit_picked(conn, config, &commit, tree_id)?;
+        }
+    }
+
+    run(conn, repo_root, config, state)
+}
+
+/// Drop the commit that stopped the cherry-pick along with any changes made since, and pick
+/// the remaining commits
+pub fn skip(
+    conn: &Connection,
+    repo_root: &Path,
+    config: &GitConfig,
+    mut state: CherryPickState,
+    progress: &mut Progress,
+) -> crate::Result<()> {
+    state.conflicted = None;
+    reset_to(conn, repo_root, head_commit(conn)?, progress)?;
+    run(conn, repo_root, config, state)
+}
+
+/// Stop cherry-picking and bring the current branch, the index and the working tree back to
+/// where they were before the cherry-pick started
+pub fn abort(
+    conn: &Connection,
+    repo_root: &Path,
+    state: CherryPickState,
+    progress: &mut Progress,
+) -> crate::Result<()> {
+    reset_to(conn, repo_root, state.orig_head, progress)?;
+    Head::read_from_conn(conn)?.update(conn, state.orig_head)?;
+    CherryPickState::clear(conn)
+}
+
+/// Commit `tree_id` on top of HEAD with the message and author of the picked commit
+fn commit_picked(
+    conn: &Connection,
+    config: &GitConfig,
+    commit: &Commit<Sha1Id>,
+    tree_id: Sha1Id,
+) -> crate::Result<()> {
+    let head = Head::read_from_conn(conn)?;
+    let head_id = head_commit(conn)?;
+
+    let new_commit = commit_tree(
+        conn,
+        config,
+        tree_id,
+        vec![head_id],
+        commit.message.clone(),
+        Some((commit.author_name.clone(), commit.author_email.clone())),
+    )?;
+    head.update(conn, new_commit.commit_id)?;
+
+    let branch = match &head {
+        Head::Branch(name) => name.strip_prefix(BRANCH_PREFIX).unwrap_or(name),
+        Head::Commit(_) => "detached HEAD",
+    };
+    println!(
+        "[{} {}] {}",
+        branch,
+        short_id(new_commit.commit_id),
+        summary(&new_commit.message)
+    );
+    Ok(())
+}
+
+/// Bring the index and working tree to the snapshot of the given commit, reporting the files
+/// touched to `progress`
+fn reset_to(
+    conn: &Connection,
+    repo_root: &Path,
+    commit_id: Sha1Id,
+    progress: &mut Progress,
+) -> crate::Result<()> {
+    let view = flatten_tree(
+        conn,
+        Commit::read_from_conn_with_id(conn, commit_id)?.tree_id,
+    )?;
+    let mut index = Index::read_from_conn(conn)?;
+    reset_hard(conn, repo_root, &mut index, &view, progress)?;
+    index.persist(conn)
+}
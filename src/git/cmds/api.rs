@@ -0,0 +1,35 @@
+use crate::{
+    cli::ApiArgs,
+    git::{
+        api::{api_error_page, handle_api_request, ApiRequest},
+        web::serve,
+    },
+    repo::Repository,
+};
+
+pub fn do_api(arg: ApiArgs) -> crate::Result<()> {
+    let repo = Repository::open(std::env::current_dir()?)?;
+    let token = arg
+        .token
+        .or_else(|| std::env::var("GITQLITE_API_TOKEN").ok())
+        .filter(|token| !token.is_empty());
+
+    println!("Serving the API at http://{}/", arg.listen);
+    serve(&arg.listen, |request| {
+        let authorization = request
+            .headers()
+            .iter()
+            .find(|header| header.field.equiv("Authorization"))
+            .map(|header| header.value.as_str());
+        let request = ApiRequest {
+            method: request.method().as_str(),
+            url: request.url(),
+            authorization,
+        };
+        // Each request reads on its own connection, leaving the database free for writes
+        match repo.read_conn() {
+            Ok(conn) => handle_api_request(&conn, request, token.as_deref()),
+            Err(err) => api_error_page(500, &err.to_string()),
+        }
+    })
+}
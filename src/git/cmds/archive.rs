@@ -0,0 +1,53 @@
+use std::{
+    fs,
+    io::{self, Write},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    cli::{ArchiveArgs, ArchiveFormat},
+    git::{
+        archive::write_archive,
+        dry_run,
+        revision::resolve_tree,
+        tree::flatten_tree,
+        utils::{find_gitqlite_root, get_gitqlite_connection},
+    },
+};
+
+pub fn do_archive(arg: ArchiveArgs) -> crate::Result<()> {
+    let ArchiveArgs {
+        tree_ish,
+        format,
+        prefix,
+        output,
+    } = arg;
+
+    find_gitqlite_root(std::env::current_dir()?)?;
+    let conn = get_gitqlite_connection()?;
+
+    let view = flatten_tree(&conn, resolve_tree(&conn, &tree_ish)?)?;
+
+    let format = format.unwrap_or_else(|| {
+        match output
+            .as_ref()
+            .and_then(|path| path.extension())
+            .and_then(|ext| ext.to_str())
+        {
+            Some("zip") => ArchiveFormat::Zip,
+            _ => ArchiveFormat::Tar,
+        }
+    });
+    // Commits carry no timestamp, so entries get the current time as git does for trees
+    let mtime = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+
+    let mut archive = Vec::new();
+    write_archive(&conn, &view, format, &prefix, mtime, &mut archive)?;
+    match output {
+        Some(path) if dry_run::skip_write("write", &path) => {}
+        Some(path) => fs::write(path, archive)?,
+        None => io::stdout().write_all(&archive)?,
+    }
+
+    Ok(())
+}
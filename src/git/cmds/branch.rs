@@ -0,0 +1,369 @@
+use std::{collections::HashSet, io::IsTerminal};
+
+use anyhow::anyhow;
+use rusqlite::Connection;
+
+use crate::{
+    cli::BranchArgs,
+    git::{
+        constants::{self, BRANCH_PREFIX, REMOTE_PREFIX},
+        dry_run,
+        merge::{ancestors, is_ancestor},
+        model::{Commit, Head, Ref, Sha1Id},
+        protect::check_rewrite,
+        rebase::{short_id, summary},
+        refname::branch_ref_name,
+        remote::branch_upstream,
+        revision::resolve_revision,
+        utils::{find_gitqlite_root, get_gitqlite_connection, WriteTransaction},
+    },
+    repo::{
+        config::{ConfigSource, GitConfig},
+        db::{OldValue, RefTransaction},
+    },
+};
+
+/// Terminal escape sequences used to highlight the listing, as git does by default
+const COLOR_CURRENT: &str = "\x1b[32m";
+const COLOR_REMOTE: &str = "\x1b[31m";
+const COLOR_UPSTREAM: &str = "\x1b[34m";
+const COLOR_RESET: &str = "\x1b[m";
+
+/// [`Listed`] is one line of the branch listing
+struct Listed {
+    name: String,
+    commit_id: Sha1Id,
+    current: bool,
+    /// The escape sequence the name is highlighted with, if any
+    color: Option<&'static str>,
+    /// The remote-tracking branch the branch is compared with in verbose mode, if any
+    upstream: Option<String>,
+}
+
+pub fn do_branch(arg: BranchArgs) -> crate::Result<()> {
+    let repo_root = find_gitqlite_root(std::env::current_dir()?)?;
+    let conn = get_gitqlite_connection()?;
+    let mut config = GitConfig::load(repo_root.join(constants::GITQLITE_DIRECTORY_PREFIX))?;
+
+    let expected = arg
+        .force_protected
+        .as_deref()
+        .map(|rev| resolve_revision(&conn, rev))
+        .transpose()?;
+    if arg.delete || arg.force_delete {
+        return delete_branches(&conn, &config, &arg.names, arg.force_delete, expected);
+    }
+    if arg.rename {
+        return rename_branch(&conn, &mut config, &arg.names, arg.force, expected);
+    }
+    if let [branch, rest @ ..] = arg.names.as_slice() {
+        if rest.len() > 1 {
+            return Err(anyhow!("fatal: too many arguments"));
+        }
+        let start = rest.first().map_or("HEAD", String::as_str);
+        return create_branch(&conn, &config, branch, start, arg.force, expected);
+    }
+
+    let color = use_color(&config)?;
+    let head = Head::read_from_conn(&conn)?;
+    let refs = Ref::read_all(&conn)?;
+    let mut listed = Vec::new();
+    if !arg.remotes {
+        if let Head::Commit(id) = head {
+            listed.push(Listed {
+                name: format!("(HEAD detached at {})", short_id(id)),
+                commit_id: id,
+                current: true,
+                color: color.then_some(COLOR_CURRENT),
+                upstream: None,
+            });
+        }
+        for reference in &refs {
+            if let Some(branch) = reference.name.strip_prefix(BRANCH_PREFIX) {
+                let current = matches!(&head, Head::Branch(name) if *name == reference.name);
+                listed.push(Listed {
+                    name: branch.to_string(),
+                    commit_id: reference.commit_id,
+                    current,
+                    color: (color && current).then_some(COLOR_CURRENT),
+                    upstream: upstream(&config, branch)?,
+                });
+            }
+        }
+    }
+    if arg.remotes || arg.all {
+        for reference in &refs {
+            if let Some(tracking) = reference.name.strip_prefix(REMOTE_PREFIX) {
+                // Listed along with the local branches, the remote-tracking ones keep the
+                // `remotes/` prefix to tell them apart
+                let prefix = if arg.all { "remotes/" } else { "" };
+                listed.push(Listed {
+                    name: format!("{}{}", prefix, tracking),
+                    commit_id: reference.commit_id,
+                    current: false,
+                    color: color.then_some(COLOR_REMOTE),
+                    upstream: None,
+                });
+            }
+        }
+    }
+
+    let width = listed.iter().map(|l| l.name.chars().count()).max();
+    for branch in &listed {
+        let marker = if branch.current { '*' } else { ' ' };
+        let (start, end) = match branch.color {
+            Some(start) => (start, COLOR_RESET),
+            None => ("", ""),
+        };
+        if arg.verbose == 0 {
+            println!("{} {}{}{}", marker, start, branch.name, end);
+            continue;
+        }
+
+        let name = format!("{:<1$}", branch.name, width.unwrap_or_default());
+        let commit = Commit::read_from_conn_with_id(&conn, branch.commit_id)?;
+        let tracking = match &branch.upstream {
+            Some(upstream) => {
+                tracking_status(&conn, branch.commit_id, upstream, arg.verbose > 1, color)?
+            }
+            None => String::new(),
+        };
+        println!(
+            "{} {}{}{} {} {}{}",
+            marker,
+            start,
+            name,
+            end,
+            short_id(branch.commit_id),
+            tracking,
+            summary(&commit.message)
+        );
+    }
+    Ok(())
+}
+
+/// Point a new branch at the start commit, or an existing one if `force`
+fn create_branch(
+    conn: &Connection,
+    config: &GitConfig,
+    branch: &str,
+    start: &str,
+    force: bool,
+    expected: Option<Sha1Id>,
+) -> crate::Result<()> {
+    let name = branch_ref_name(branch)?;
+    let commit_id = resolve_revision(conn, start)?;
+
+    let old = match Ref::read_from_conn_with_name(conn, &name)? {
+        None => OldValue::Missing,
+        Some(_) if !force => {
+            return Err(anyhow!("fatal: a branch named '{}' already exists", branch))
+        }
+        Some(_) if Head::read_from_conn(conn)? == Head::Branch(name.clone()) => {
+            return Err(anyhow!("fatal: cannot force update the current branch."))
+        }
+        Some(existing) => {
+            // Moving a branch forward never loses commits, even when it is protected
+            if !is_ancestor(conn, existing.commit_id, commit_id)? {
+                check_rewrite(config, branch, Some(existing.commit_id), expected)?;
+            }
+            OldValue::Is(existing.commit_id.into())
+        }
+    };
+
+    let mut transaction = RefTransaction::new(format!("branch: Created from {}", start));
+    transaction.update(name, old, commit_id.into());
+    let txn = WriteTransaction::new(conn)?;
+    transaction.commit(&txn)?;
+    txn.commit()?;
+    Ok(())
+}
+
+/// Delete the branches, refusing the current one, and unless `force` the ones whose commits
+/// HEAD does not contain
+fn delete_branches(
+    conn: &Connection,
+    config: &GitConfig,
+    branches: &[String],
+    force: bool,
+    expected: Option<Sha1Id>,
+) -> crate::Result<()> {
+    if branches.is_empty() {
+        return Err(anyhow!("fatal: branch name required"));
+    }
+    let head = Head::read_from_conn(conn)?;
+    let merged = match head.resolve(conn)? {
+        Some(head_id) => ancestors(conn, head_id)?,
+        None => HashSet::new(),
+    };
+
+    let mut transaction = RefTransaction::new("branch: deleted");
+    let mut deleted = Vec::new();
+    for branch in branches {
+        let name = format!("{}{}", BRANCH_PREFIX, branch);
+        if head == Head::Branch(name.clone()) {
+            return Err(anyhow!(
+                "error: cannot delete branch '{}' used as HEAD",
+                branch
+            ));
+        }
+        let reference = Ref::read_from_conn_with_name(conn, &name)?
+            .ok_or_else(|| anyhow!("error: branch '{}' not found.", branch))?;
+        if !force && !merged.contains(&reference.commit_id) {
+            return Err(anyhow!(
+                "error: the branch '{}' is not fully merged.\nhint: If you are sure you want to delete it, run 'gitqlite branch -D {}'",
+                branch,
+                branch
+            ));
+        }
+        check_rewrite(config, branch, Some(reference.commit_id), expected)?;
+        transaction.delete(name, OldValue::Is(reference.commit_id.into()));
+        deleted.push((branch, reference.commit_id));
+    }
+
+    let txn = WriteTransaction::new(conn)?;
+    transaction.commit(&txn)?;
+    txn.commit()?;
+    for (branch, id) in deleted {
+        println!("Deleted branch {} (was {}).", branch, short_id(id));
+    }
+    Ok(())
+}
+
+/// Rename a branch, the current one if only the new name is given, moving HEAD along if it is
+/// on it, and its section of the local config. An existing branch with the new name is only
+/// replaced if `force`. A protected branch is only renamed with `expected` naming its tip.
+fn rename_branch(
+    conn: &Connection,
+    config: &mut GitConfig,
+    names: &[String],
+    force: bool,
+    expected: Option<Sha1Id>,
+) -> crate::Result<()> {
+    let head = Head::read_from_conn(conn)?;
+    let (old, new) = match (names, &head) {
+        ([new], Head::Branch(current)) => (
+            current.strip_prefix(BRANCH_PREFIX).unwrap_or(current),
+            new.as_str(),
+        ),
+        ([_], Head::Commit(_)) => {
+            return Err(anyhow!(
+                "fatal: cannot rename the current branch while not on any"
+            ))
+        }
+        ([old, new], _) => (old.as_str(), new.as_str()),
+        ([], _) => return Err(anyhow!("fatal: branch name required")),
+        _ => return Err(anyhow!("fatal: too many arguments")),
+    };
+    let old_name = format!("{}{}", BRANCH_PREFIX, old);
+    let new_name = branch_ref_name(new)?;
+    let reference = Ref::read_from_conn_with_name(conn, &old_name)?
+        .ok_or_else(|| anyhow!("fatal: no branch named '{}'", old))?;
+    if old_name == new_name {
+        return Ok(());
+    }
+    check_rewrite(config, old, Some(reference.commit_id), expected)?;
+
+    let replaced = match Ref::read_from_conn_with_name(conn, &new_name)? {
+        None => OldValue::Missing,
+        Some(_) if !force => return Err(anyhow!("fatal: a branch named '{}' already exists", new)),
+        Some(_) if head == Head::Branch(new_name.clone()) => {
+            return Err(anyhow!("fatal: cannot force update the current branch."))
+        }
+        Some(existing) => {
+            check_rewrite(config, new, Some(existing.commit_id), expected)?;
+            OldValue::Is(existing.commit_id.into())
+        }
+    };
+
+    let mut transaction =
+        RefTransaction::new(format!("branch: renamed {} to {}", old_name, new_name));
+    transaction.delete(old_name.clone(), OldValue::Is(reference.commit_id.into()));
+    transaction.update(new_name.clone(), replaced, reference.commit_id.into());
+    let txn = WriteTransaction::new(conn)?;
+    transaction.commit(&txn)?;
+    if head == Head::Branch(old_name) {
+        Head::Branch(new_name).persist(&txn)?;
+    }
+    txn.commit()?;
+
+    // The config of the branch, such as its upstream and protection, follows it
+    let section = format!("branch.{}", old);
+    let origin = config
+        .list(ConfigSource::Local)
+        .into_iter()
+        .find(|entry| entry.key.rsplit_once('.').map(|(prefix, _)| prefix) == Some(&section))
+        .map(|entry| entry.origin.to_path_buf());
+    match origin {
+        Some(origin) if !dry_run::skip_write("rewrite", &origin) => {
+            config.rename_section(&section, &format!("branch.{}", new), ConfigSource::Local)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Whether the listing is highlighted, from `color.branch` or else `color.ui`: `always`,
+/// `never`, or `auto` (the default) to highlight when writing to a terminal
+fn use_color(config: &GitConfig) -> crate::Result<bool> {
+    let value = match config.get("color.branch", ConfigSource::All)? {
+        Some(value) => Some(value),
+        None => config.get("color.ui", ConfigSource::All)?,
+    };
+    match value.unwrap_or("auto").to_ascii_lowercase().as_str() {
+        "always" | "true" | "yes" | "on" | "1" => Ok(true),
+        "never" | "false" | "no" | "off" | "0" => Ok(false),
+        "auto" => Ok(std::io::stdout().is_terminal()),
+        value => Err(anyhow!("fatal: bad color config value '{}'", value)),
+    }
+}
+
+/// The remote-tracking branch the branch is set to follow by `branch.<name>.remote` and
+/// `branch.<name>.merge`, e.g. `origin/main`
+fn upstream(config: &GitConfig, branch: &str) -> crate::Result<Option<String>> {
+    Ok(branch_upstream(config, branch)?.map(|(remote, merge)| format!("{}/{}", remote, merge)))
+}
+
+/// The bracketed comparison of a branch with its upstream followed by a space, e.g.
+/// `[ahead 1, behind 2] `, with the name of the upstream if `name_upstream`. Empty when they
+/// are in sync and the upstream is not named.
+fn tracking_status(
+    conn: &Connection,
+    commit_id: Sha1Id,
+    upstream: &str,
+    name_upstream: bool,
+    color: bool,
+) -> crate::Result<String> {
+    let upstream_name = if color {
+        format!("{}{}{}", COLOR_UPSTREAM, upstream, COLOR_RESET)
+    } else {
+        upstream.to_string()
+    };
+    let Some(tracking) =
+        Ref::read_from_conn_with_name(conn, format!("{}{}", REMOTE_PREFIX, upstream))?
+    else {
+        return Ok(if name_upstream {
+            format!("[{}: gone] ", upstream_name)
+        } else {
+            "[gone] ".to_string()
+        });
+    };
+
+    let ours = ancestors(conn, commit_id)?;
+    let theirs = ancestors(conn, tracking.commit_id)?;
+    let ahead = ours.difference(&theirs).count();
+    let behind = theirs.difference(&ours).count();
+    let mut counts = Vec::new();
+    if ahead > 0 {
+        counts.push(format!("ahead {}", ahead));
+    }
+    if behind > 0 {
+        counts.push(format!("behind {}", behind));
+    }
+
+    Ok(match (name_upstream, counts.is_empty()) {
+        (false, true) => String::new(),
+        (false, false) => format!("[{}] ", counts.join(", ")),
+        (true, true) => format!("[{}] ", upstream_name),
+        (true, false) => format!("[{}: {}] ", upstream_name, counts.join(", ")),
+    })
+}
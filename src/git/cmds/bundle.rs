@@ -0,0 +1,136 @@
+use std::{fs, path::Path};
+
+use anyhow::anyhow;
+use rusqlite::Connection;
+
+use crate::{
+    cli::{BundleArgs, BundleCommand},
+    git::{
+        bundle::{
+            create_bundle, lacking_prerequisites, missing_prerequisites, read_bundle_header,
+            unbundle, BundleHeader,
+        },
+        model::{Head, Ref, Sha1Id},
+        revision::{find_ref, resolve_revision},
+        utils::{find_gitqlite_root, get_gitqlite_connection},
+    },
+};
+
+pub fn do_bundle(arg: BundleArgs) -> crate::Result<()> {
+    find_gitqlite_root(std::env::current_dir()?)?;
+    let conn = get_gitqlite_connection()?;
+
+    match arg.command {
+        BundleCommand::Create {
+            file,
+            revisions,
+            all,
+        } => create(&conn, &file, &revisions, all),
+        BundleCommand::Verify { file } => {
+            let header = read_bundle_header(&file)?;
+            let missing = missing_prerequisites(&conn, &header)?;
+            if !missing.is_empty() {
+                return Err(lacking_prerequisites(&missing));
+            }
+
+            println!(
+                "The bundle contains {}:",
+                count(header.refs.len(), "this ref", "refs")
+            );
+            print_refs(&header);
+            if header.prerequisites.is_empty() {
+                println!("The bundle records a complete history.");
+            } else {
+                println!(
+                    "The bundle requires {}:",
+                    count(header.prerequisites.len(), "this ref", "refs")
+                );
+                for (id, summary) in &header.prerequisites {
+                    println!("{} {}", id, summary);
+                }
+            }
+            println!("{} is okay", file.display());
+            Ok(())
+        }
+        BundleCommand::ListHeads { file } => {
+            print_refs(&read_bundle_header(&file)?);
+            Ok(())
+        }
+        BundleCommand::Unbundle { file } => {
+            print_refs(&unbundle(&conn, &file)?);
+            Ok(())
+        }
+    }
+}
+
+fn create(conn: &Connection, file: &Path, revisions: &[String], all: bool) -> crate::Result<()> {
+    let mut refs: Vec<(String, Sha1Id)> = Vec::new();
+    let mut exclude = Vec::new();
+
+    if all {
+        if let Some(head_id) = Head::read_from_conn(conn)?.resolve(conn)? {
+            refs.push(("HEAD".to_string(), head_id));
+        }
+        refs.extend(
+            Ref::read_all(conn)?
+                .into_iter()
+                .map(|reference| (reference.name, reference.commit_id)),
+        );
+    }
+
+    for rev in revisions {
+        if let Some(rev) = rev.strip_prefix('^') {
+            exclude.push(resolve_revision(conn, rev)?);
+            continue;
+        }
+        let tip = match rev.split_once("..") {
+            Some((from, to)) => {
+                let or_head = |rev: &str| if rev.is_empty() { "HEAD" } else { rev }.to_string();
+                exclude.push(resolve_revision(conn, &or_head(from))?);
+                or_head(to)
+            }
+            None => rev.clone(),
+        };
+
+        let id = resolve_revision(conn, &tip)?;
+        match ref_name(conn, &tip)? {
+            Some(name) if !refs.iter().any(|(existing, _)| *existing == name) => {
+                refs.push((name, id))
+            }
+            Some(_) => {}
+            None => eprintln!("warning: ignoring '{}' which is not a ref", tip),
+        }
+    }
+
+    if refs.is_empty() {
+        return Err(anyhow!("fatal: Refusing to create empty bundle."));
+    }
+    if create_bundle(conn, file, &refs, &exclude)? == 0 {
+        fs::remove_file(file)?;
+        return Err(anyhow!("fatal: Refusing to create empty bundle."));
+    }
+
+    Ok(())
+}
+
+/// Full name of the ref named by the revision, None if it does not name a ref
+fn ref_name(conn: &Connection, rev: &str) -> crate::Result<Option<String>> {
+    if rev == "HEAD" {
+        return Ok(Some(rev.to_string()));
+    }
+    Ok(find_ref(conn, rev)?.map(|reference| reference.name))
+}
+
+fn print_refs(header: &BundleHeader) {
+    for (name, id) in &header.refs {
+        println!("{} {}", id, name);
+    }
+}
+
+fn count(n: usize, singular: &str, plural: &str) -> String {
+    if n == 1 {
+        singular.to_string()
+    } else {
+        format!("these {} {}", n, plural)
+    }
+}
@@ -1,29 +1,165 @@
+use std::io::{self, BufWriter, Write};
+
+use anyhow::anyhow;
 use rusqlite::Connection;
 
 use crate::{
-    cli::CatFileArgs,
+    cli::{CatFileArgs, ObjectType},
     git::{
-        model::{Blob, Commit, Sha1Id, Tree},
+        model::{AnnotatedTag, Commit, Sha1Id, Tree, TreeEntryType},
+        revision::{
+            resolve_object_id, resolve_path_object, resolve_revision, resolve_tag, resolve_tree,
+        },
+        storage::{format_size, parse_size},
         utils::get_gitqlite_connection,
     },
+    repo::db::{self, for_each_object, read_object_type, BlobReader, Object},
 };
 
 pub fn do_cat_file(arg: CatFileArgs) -> crate::Result<()> {
-    let CatFileArgs { type_, object } = arg;
+    let CatFileArgs {
+        type_,
+        object,
+        show_type,
+        exists,
+        pretty,
+        batch_all_objects,
+        size_limit,
+    } = arg;
     let conn = get_gitqlite_connection()?;
+    let size_limit = size_limit.as_deref().map(parse_size).transpose()?;
+
+    if batch_all_objects {
+        return print_all_objects(&conn);
+    }
+
+    if let Some(name) = show_type {
+        let (type_, _) = detect_object(&conn, &name)?;
+        println!("{}", type_name(&type_));
+        return Ok(());
+    }
+    if let Some(name) = exists {
+        detect_object(&conn, &name)?;
+        return Ok(());
+    }
+    let (type_, object_id) = match pretty {
+        Some(name) => detect_object(&conn, &name)?,
+        None => {
+            let type_ = type_.expect("type is required unless probing the object");
+            let object = object.expect("object is required unless probing the object");
+            let object_id = resolve_object(&conn, &type_, &object)?;
+            (type_, object_id)
+        }
+    };
 
-    let object_id = object.as_str().try_into()?;
+    match type_ {
+        ObjectType::Blob => print_blob(&conn, object_id, size_limit),
+        ObjectType::Tree => print_tree(&conn, object_id),
+        ObjectType::Commit => print_commit(&conn, object_id),
+        ObjectType::Tag => print_tag(&conn, object_id),
+    }
+}
 
+fn type_name(type_: &ObjectType) -> &'static str {
     match type_ {
-        crate::cli::ObjectType::Blob => print_blob(&conn, object_id),
-        crate::cli::ObjectType::Tree => print_tree(&conn, object_id),
-        crate::cli::ObjectType::Commit => print_commit(&conn, object_id),
+        ObjectType::Blob => "blob",
+        ObjectType::Tree => "tree",
+        ObjectType::Commit => "commit",
+        ObjectType::Tag => "tag",
     }
 }
 
-fn print_blob(conn: &Connection, blob_id: Sha1Id) -> crate::Result<()> {
-    let blob = Blob::read_from_conn_with_id(conn, blob_id)?;
-    println!("{}", String::from_utf8_lossy(&blob.data));
+/// Stream `<id> <type> <size>` for every object, one per line
+fn print_all_objects(conn: &Connection) -> crate::Result<()> {
+    let mut out = BufWriter::new(io::stdout().lock());
+    for_each_object(conn, |id, type_, size| {
+        let type_ = match type_ {
+            db::ObjectType::Commit => "commit",
+            db::ObjectType::Tree => "tree",
+            db::ObjectType::Tag => "tag",
+            _ => "blob",
+        };
+        writeln!(out, "{} {} {}", id, type_, size)?;
+        Ok(())
+    })?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Resolve the name of an object whose type is not known: a `<rev>:<path>`, an annotated
+/// tag, a revision, or the hash of an object of any type
+fn detect_object(conn: &Connection, name: &str) -> crate::Result<(ObjectType, Sha1Id)> {
+    if let Some((found, id)) = resolve_path_object(conn, name)? {
+        let type_ = match found {
+            TreeEntryType::Blob => ObjectType::Blob,
+            TreeEntryType::Tree => ObjectType::Tree,
+        };
+        return Ok((type_, id));
+    }
+    if let Some(tag) = resolve_tag(conn, name)? {
+        return Ok((ObjectType::Tag, tag.tag_id.into()));
+    }
+    if let Ok(commit_id) = resolve_revision(conn, name) {
+        return Ok((ObjectType::Commit, commit_id));
+    }
+
+    let id = resolve_object_id(conn, name)?;
+    let type_ = match read_object_type(conn, id.into())? {
+        Some(db::ObjectType::Commit) => ObjectType::Commit,
+        Some(db::ObjectType::Tree) => ObjectType::Tree,
+        Some(db::ObjectType::Tag) => ObjectType::Tag,
+        _ => ObjectType::Blob,
+    };
+    Ok((type_, id))
+}
+
+/// Resolve the name of an object of the given type: a hash, a revision (for commits and
+/// trees), the name of an annotated tag (for tags), or a `<rev>:<path>` naming a file or
+/// directory
+fn resolve_object(conn: &Connection, type_: &ObjectType, name: &str) -> crate::Result<Sha1Id> {
+    if let Some((found, id)) = resolve_path_object(conn, name)? {
+        let expected = match type_ {
+            ObjectType::Blob => Some(TreeEntryType::Blob),
+            ObjectType::Tree => Some(TreeEntryType::Tree),
+            ObjectType::Commit | ObjectType::Tag => None,
+        };
+        if expected != Some(found) {
+            return Err(anyhow!(
+                "fatal: {} is a {}, not a {}",
+                name,
+                found,
+                type_name(type_)
+            ));
+        }
+        return Ok(id);
+    }
+    match type_ {
+        ObjectType::Blob => resolve_object_id(conn, name),
+        ObjectType::Tree => resolve_tree(conn, name),
+        ObjectType::Commit => resolve_revision(conn, name),
+        ObjectType::Tag => match resolve_tag(conn, name)? {
+            Some(tag) => Ok(tag.tag_id.into()),
+            None => Err(anyhow!("fatal: {} is not an annotated tag", name)),
+        },
+    }
+}
+
+/// Copy the content of the blob to stdout as is, without reading it all at once. A blob
+/// larger than `size_limit` is only summarized on stderr.
+fn print_blob(conn: &Connection, blob_id: Sha1Id, size_limit: Option<u64>) -> crate::Result<()> {
+    let mut reader = BlobReader::open(conn, blob_id.into())?;
+    let size = reader.len() as u64;
+    if let Some(limit) = size_limit.filter(|limit| size > *limit) {
+        eprintln!(
+            "warning: blob {} is {} ({} bytes), over the size limit of {}; not printed",
+            blob_id,
+            format_size(size),
+            size,
+            format_size(limit)
+        );
+        return Ok(());
+    }
+    io::copy(&mut reader, &mut io::stdout().lock())?;
     Ok(())
 }
 
@@ -53,3 +189,22 @@ fn print_commit(conn: &Connection, commit_id: Sha1Id) -> crate::Result<()> {
     println!();
     Ok(())
 }
+
+fn print_tag(conn: &Connection, tag_id: Sha1Id) -> crate::Result<()> {
+    let Some(tag) = AnnotatedTag::read_by_id(conn, tag_id.into())? else {
+        return Err(anyhow!("fatal: not a valid tag object: {}", tag_id));
+    };
+    println!("object {}", tag.object_id);
+    println!("type commit");
+    println!("tag {}", tag.name);
+    println!(
+        "tagger {} <{}> {} +0000",
+        tag.tagger_name, tag.tagger_email, tag.tagged_at
+    );
+    println!();
+    print!("{}", tag.message);
+    if !tag.message.ends_with('\n') {
+        println!();
+    }
+    Ok(())
+}
@@ -0,0 +1,57 @@
+use anyhow::anyhow;
+
+use crate::{
+    cli::CheckAttrArgs,
+    git::{
+        attributes::{read_attributes, AttrRule, AttrValue},
+        utils::find_gitqlite_root,
+    },
+};
+
+use super::diff::repo_relative_paths;
+
+pub fn do_check_attr(arg: CheckAttrArgs) -> crate::Result<()> {
+    let CheckAttrArgs {
+        all,
+        verbose,
+        mut args,
+    } = arg;
+    let repo_root = find_gitqlite_root(std::env::current_dir()?)?;
+    let attributes = read_attributes(&repo_root)?;
+
+    // Without --all the first argument names the attribute
+    let attr = if all { None } else { Some(args.remove(0)) };
+    if args.is_empty() {
+        return Err(anyhow!("fatal: No file name specified"));
+    }
+    let names = repo_relative_paths(&repo_root, &args)?;
+
+    for (path, name) in args.iter().zip(&names) {
+        match &attr {
+            Some(attr) => {
+                let found = attributes.lookup(name, attr);
+                let value = found.map_or(&AttrValue::Unspecified, |(value, _)| value);
+                print_attr(path, attr, value, found.map(|(_, rule)| rule), verbose);
+            }
+            None => {
+                for (attr, (value, rule)) in attributes.lookup_all(name) {
+                    print_attr(path, attr, value, Some(rule), verbose);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Print `<path>: <attr>: <value>`, preceded with `<source>:<line>:<pattern>` and a tab as
+/// `check-ignore -v` does when verbose
+fn print_attr(path: &str, attr: &str, value: &AttrValue, rule: Option<&AttrRule>, verbose: bool) {
+    if verbose {
+        match rule {
+            Some(rule) => print!("{}:{}:{}\t", rule.source(), rule.line(), rule.pattern()),
+            None => print!("::\t"),
+        }
+    }
+    println!("{}: {}: {}", path, attr, value);
+}
@@ -0,0 +1,24 @@
+use crate::{
+    cli::CheckRefFormatArgs,
+    git::refname::{branch_ref_name, check_ref_format, normalize_ref_name},
+};
+
+pub fn do_check_ref_format(arg: CheckRefFormatArgs) -> crate::Result<()> {
+    if arg.branch {
+        branch_ref_name(&arg.refname)?;
+        println!("{}", arg.refname);
+        return Ok(());
+    }
+
+    let name = if arg.normalize {
+        normalize_ref_name(&arg.refname)
+    } else {
+        arg.refname
+    };
+    check_ref_format(&name, arg.allow_onelevel)?;
+    if arg.normalize {
+        println!("{}", name);
+    }
+
+    Ok(())
+}
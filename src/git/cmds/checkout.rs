@@ -0,0 +1,41 @@
+use crate::{
+    cli::CheckoutArgs,
+    git::{
+        constants::BRANCH_PREFIX,
+        model::Ref,
+        progress::Progress,
+        revision::resolve_revision,
+        utils::{find_gitqlite_root, get_gitqlite_connection},
+    },
+};
+
+use super::switch::{detach_head, switch_to_branch};
+
+pub fn do_checkout(arg: CheckoutArgs) -> crate::Result<()> {
+    let CheckoutArgs {
+        target,
+        detach,
+        force,
+        quiet,
+    } = arg;
+    let repo_root = find_gitqlite_root(std::env::current_dir()?)?;
+    let conn = get_gitqlite_connection()?;
+
+    let mut progress = Progress::new(quiet);
+    // A local branch is switched to, anything else naming a commit detaches HEAD
+    let branch = Ref::read_from_conn_with_name(&conn, format!("{}{}", BRANCH_PREFIX, target))?;
+    match branch {
+        Some(branch) if !detach => switch_to_branch(
+            &conn,
+            &repo_root,
+            &target,
+            branch.commit_id,
+            force,
+            &mut progress,
+        ),
+        _ => {
+            let commit_id = resolve_revision(&conn, &target)?;
+            detach_head(&conn, &repo_root, commit_id, force, &mut progress)
+        }
+    }
+}
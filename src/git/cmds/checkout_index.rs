@@ -0,0 +1,66 @@
+use anyhow::anyhow;
+
+use crate::{
+    cli::CheckoutIndexArgs,
+    git::{
+        model::Index,
+        revision::resolve_tree,
+        tree::{flatten_tree, index_view, TreeView},
+        utils::{find_gitqlite_root, get_gitqlite_connection},
+        worktree::export_view,
+    },
+};
+
+pub fn do_checkout_index(arg: CheckoutIndexArgs) -> crate::Result<()> {
+    let CheckoutIndexArgs {
+        paths,
+        all,
+        source,
+        prefix,
+        force,
+    } = arg;
+    let repo_root = find_gitqlite_root(std::env::current_dir()?)?;
+    let conn = get_gitqlite_connection()?;
+
+    let (view, origin) = match &source {
+        Some(tree_ish) => (
+            flatten_tree(&conn, resolve_tree(&conn, tree_ish)?)?,
+            format!("'{}'", tree_ish),
+        ),
+        None => (
+            index_view(&Index::read_from_conn(&conn)?),
+            "the index".to_string(),
+        ),
+    };
+
+    let view = if all {
+        view
+    } else {
+        let mut selected = TreeView::new();
+        for path in &paths {
+            let path = path.trim_end_matches('/');
+            let dir = format!("{}/", path);
+            let before = selected.len();
+            selected.extend(
+                view.iter()
+                    .filter(|(name, _)| *name == path || name.starts_with(&dir))
+                    .map(|(name, entry)| (name.clone(), entry.clone())),
+            );
+            if selected.len() == before {
+                return Err(anyhow!("fatal: {} is not in {}", path, origin));
+            }
+        }
+        selected
+    };
+
+    // Without a prefix the files go back to their place in the working tree
+    let base = if prefix.is_empty() {
+        repo_root
+    } else {
+        std::env::current_dir()?
+    };
+    for name in export_view(&conn, base, &prefix, &view, force)? {
+        eprintln!("{}{} already exists, no checkout", prefix, name);
+    }
+    Ok(())
+}
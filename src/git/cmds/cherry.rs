@@ -0,0 +1,34 @@
+use crate::{
+    cli::CherryArgs,
+    git::{
+        cherry::cherry,
+        model::Commit,
+        rebase::summary,
+        revision::resolve_revision,
+        utils::{find_gitqlite_root, get_gitqlite_connection},
+    },
+};
+
+pub fn do_cherry(arg: CherryArgs) -> crate::Result<()> {
+    find_gitqlite_root(std::env::current_dir()?)?;
+    let conn = get_gitqlite_connection()?;
+
+    let upstream = resolve_revision(&conn, &arg.upstream)?;
+    let head = resolve_revision(&conn, arg.head.as_deref().unwrap_or("HEAD"))?;
+    let limit = arg
+        .limit
+        .as_deref()
+        .map(|limit| resolve_revision(&conn, limit))
+        .transpose()?;
+
+    for (id, equivalent) in cherry(&conn, upstream, head, limit)? {
+        let sign = if equivalent { '-' } else { '+' };
+        if arg.verbose {
+            let commit = Commit::read_from_conn_with_id(&conn, id)?;
+            println!("{} {} {}", sign, id, summary(&commit.message));
+        } else {
+            println!("{} {}", sign, id);
+        }
+    }
+    Ok(())
+}
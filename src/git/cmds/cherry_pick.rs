@@ -0,0 +1,107 @@
+use anyhow::anyhow;
+
+use crate::{
+    cli::CherryPickArgs,
+    git::{
+        cherry_pick, constants,
+        model::{CherryPickState, Commit, Head, Index, MergeHead, RebaseState},
+        progress::Progress,
+        revision::resolve_commits,
+        tree::{flatten_tree, index_view},
+        utils::{find_gitqlite_root, get_gitqlite_connection},
+    },
+    repo::config::GitConfig,
+};
+
+pub fn do_cherry_pick(arg: CherryPickArgs) -> crate::Result<()> {
+    let CherryPickArgs {
+        commits,
+        continue_,
+        skip,
+        abort,
+        no_commit,
+        quiet,
+    } = arg;
+
+    let repo_root = find_gitqlite_root(std::env::current_dir()?)?;
+    let gitqlite_home = repo_root.join(constants::GITQLITE_DIRECTORY_PREFIX);
+    let conn = get_gitqlite_connection()?;
+    let config = GitConfig::load(&gitqlite_home)?;
+
+    let state = CherryPickState::read_from_conn(&conn)?;
+
+    if continue_ || skip || abort {
+        let state = state.ok_or_else(|| anyhow!("error: no cherry-pick in progress"))?;
+        if continue_ {
+            return cherry_pick::resume(&conn, &repo_root, &config, state);
+        }
+        let mut progress = Progress::new(quiet);
+        let result = if skip {
+            cherry_pick::skip(&conn, &repo_root, &config, state, &mut progress)
+        } else {
+            cherry_pick::abort(&conn, &repo_root, state, &mut progress)
+        };
+        progress.finish();
+        return result;
+    }
+
+    if state.is_some() {
+        return Err(anyhow!(
+            "error: a cherry-pick is already in progress\nhint: try \"gitqlite cherry-pick (--continue | --skip | --abort)\""
+        ));
+    }
+    if RebaseState::read_from_conn(&conn)?.is_some() {
+        return Err(anyhow!(
+            "error: a rebase is in progress\nhint: try \"gitqlite rebase (--continue | --skip | --abort)\""
+        ));
+    }
+    let index = Index::read_from_conn(&conn)?;
+    if index.has_conflicts() || MergeHead::read_from_conn(&conn)?.is_some() {
+        return Err(anyhow!(
+            "fatal: You have not concluded your merge (MERGE_HEAD exists)."
+        ));
+    }
+
+    let head_id = Head::read_from_conn(&conn)?
+        .resolve(&conn)?
+        .ok_or_else(|| anyhow!("fatal: You do not have any commits yet to cherry-pick onto"))?;
+    let head_view = flatten_tree(
+        &conn,
+        Commit::read_from_conn_with_id(&conn, head_id)?.tree_id,
+    )?;
+    if !no_commit && index_view(&index) != head_view {
+        return Err(anyhow!(
+            "error: your local changes would be overwritten by cherry-pick.\nhint: commit your changes or stash them to proceed."
+        ));
+    }
+
+    let mut todo = Vec::new();
+    for spec in &commits {
+        for commit_id in resolve_commits(&conn, spec)? {
+            if Commit::read_from_conn_with_id(&conn, commit_id)?
+                .parent_ids
+                .len()
+                > 1
+            {
+                return Err(anyhow!(
+                    "error: commit {} is a merge, which cherry-pick does not support",
+                    commit_id
+                ));
+            }
+            todo.push(commit_id);
+        }
+    }
+    if todo.is_empty() {
+        return Err(anyhow!("error: empty commit set passed"));
+    }
+    if no_commit {
+        return cherry_pick::stage(&conn, &repo_root, &todo);
+    }
+
+    let state = CherryPickState {
+        orig_head: head_id,
+        todo,
+        conflicted: None,
+    };
+    cherry_pick::run(&conn, &repo_root, &config, state)
+}
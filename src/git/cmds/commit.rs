@@ -1,188 +1,135 @@
-use std::{collections::HashMap, path::PathBuf};
+use std::path::Path;
 
 use anyhow::anyhow;
-use sha1::Digest;
+use rusqlite::Connection;
 
 use crate::{
     cli::CommitArgs,
     git::{
         constants,
-        model::{
-            Commit, Hashable, Head, Index, IndexEntry, Ref, Sha1Id, Tree, TreeEntry, TreeEntryType,
-        },
-        utils::{find_gitqlite_root, get_gitqlite_connection},
+        encoding::{commit_encoding, encode_message},
+        file_changes::record_file_changes,
+        hooks::{staged_files, Hooks},
+        identity::user_identity,
+        message_policy::MessagePolicy,
+        model::{Commit, Hashable, Head, Index, MergeHead, Sha1Id},
+        reachability::record_reachability,
+        trailers::{add_trailers, separators, TrailerOptions},
+        tree::{index_view, write_tree},
+        utils::{find_gitqlite_root, get_gitqlite_connection, with_transaction},
     },
-    repo::config::{ConfigSource, GitConfig},
+    repo::{config::GitConfig, db::HashAlgorithm},
 };
 
-#[derive(Debug)]
-enum BlobOrTree {
-    Blob(IndexEntry),
-    Tree {
-        tree: Tree<Sha1Id>,
-        name: String,
-        mode: String,
-    },
-}
-
-impl BlobOrTree {
-    pub fn name(&self) -> &str {
-        match self {
-            BlobOrTree::Blob(entry) => &entry.name,
-            BlobOrTree::Tree {
-                tree: _,
-                name,
-                mode: _,
-            } => name,
-        }
-    }
-}
-
 pub fn do_commit(arg: CommitArgs) -> crate::Result<()> {
-    let CommitArgs { message } = arg;
+    let CommitArgs {
+        message,
+        trailer,
+        no_verify,
+    } = arg;
     let repo_root = find_gitqlite_root(std::env::current_dir()?)?;
     let gitqlite_home = repo_root.join(constants::GITQLITE_DIRECTORY_PREFIX);
-    let conn = get_gitqlite_connection()?;
+    let mut conn = get_gitqlite_connection()?;
     let config = GitConfig::load(&gitqlite_home)?;
 
-    let user = config
-        .get("user.name", ConfigSource::All)?
-        .ok_or_else(|| anyhow!("Missing user.name in git config"))?;
-    let user_email = config
-        .get("user.email", ConfigSource::All)?
-        .ok_or_else(|| anyhow!("Missing user.email in git config"))?;
-
     let index = Index::read_from_conn(&conn)?;
-
-    // trees stores relatvie path -> a list of index entries. We will iteratively build up
-    // the git tree for the root repo
-    let mut directory_entries: HashMap<PathBuf, Vec<BlobOrTree>> = HashMap::new();
-    for entry in index.entries {
-        let path = repo_root.join(&entry.name);
-        let parent_dir = path.parent().unwrap();
-        directory_entries
-            .entry(parent_dir.to_path_buf())
-            .or_default()
-            .push(BlobOrTree::Blob(entry));
+    if index.has_conflicts() {
+        return Err(anyhow!(
+            "Committing is not possible because you have unmerged files: {}",
+            index.conflicted_names().join(", ")
+        ));
     }
 
-    let mut trees = HashMap::new();
-
-    // Sort directory by reverse length (subdirectories before their parents)
-    let mut keys: Vec<PathBuf> = directory_entries.keys().cloned().collect();
-    keys.sort_by_key(|path| -(path.to_string_lossy().len() as i32));
-
-    for key in keys {
-        // Create a tree object for this directory
-        let entries = directory_entries.get_mut(&key).unwrap();
-        // Sort tree entry by their name
-        entries.sort_by(|e1, e2| e1.name().cmp(&e2.name()));
-        let tree_entries = make_tree_entries(&entries);
-        let tree = Tree::new(tree_entries);
-        let tree_id = tree.hash(sha1::Sha1::new());
-        let tree = tree.with_id(tree_id);
-        tree.persist(&conn)?;
-        trees.insert(key.clone(), tree.tree_id);
-
-        if key == repo_root {
-            continue;
-        }
-        let parent = key.parent().unwrap();
-        directory_entries
-            .get_mut(parent)
-            .unwrap()
-            .push(BlobOrTree::Tree {
-                tree,
-                name: key
-                    .strip_prefix(&repo_root)
-                    .unwrap()
-                    .to_str()
-                    .unwrap()
-                    .to_string(),
-                mode: "040000".to_string(),
-            })
+    let mut message = add_trailers(&config, &message, &trailer, &TrailerOptions::default())?
+        .trim_end()
+        .to_string();
+    if !no_verify {
+        // The built-in policy is checked before the hooks, which may still rewrite the message
+        MessagePolicy::from_config(&config)?.check(&message, &separators(&config)?)?;
+        message = run_commit_hooks(&conn, &gitqlite_home, &message)?;
     }
+    // The pre-commit hook may have staged changes
+    let index = Index::read_from_conn(&conn)?;
 
-    let root_tree = trees.get(&repo_root).unwrap();
-
-    // Create commit
-    // Get the current root commit
-    let head = Head::read_from_conn(&conn)?;
-    let root_commit = match &head {
-        Head::Branch(branch) => Ref::read_from_conn_with_name(&conn, branch)?.map(|r| r.commit_id),
-        Head::Commit(id) => Some(*id),
-    };
+    let commit = with_transaction(&mut conn, |tx| {
+        let root_tree = write_tree(tx, &index_view(&index))?;
 
-    let parent_ids = if let Some(root_commit) = root_commit {
-        vec![root_commit]
-    } else {
-        Vec::new()
-    };
+        // Create commit on top of the current HEAD, and the commit being merged if we are
+        // concluding a conflicted merge
+        let head = Head::read_from_conn(tx)?;
+        let mut parent_ids: Vec<Sha1Id> = head.resolve(tx)?.into_iter().collect();
+        let merge_head = MergeHead::read_from_conn(tx)?;
+        if let Some(merge_head) = &merge_head {
+            parent_ids.push(merge_head.commit_id);
+        }
 
-    let commit = Commit::new(
-        *root_tree,
-        parent_ids,
-        user.to_string(),
-        user_email.to_string(),
-        user.to_string(),
-        user_email.to_string(),
-        message,
-    );
-    let commit_id = commit.hash(sha1::Sha1::new());
-    let commit = commit.with_id(commit_id);
-    commit.persist(&conn)?;
+        let commit = commit_tree(tx, &config, root_tree, parent_ids, message, None)?;
 
-    // Update ref to the root commit
-    match head {
-        Head::Branch(name) => {
-            let new_ref = Ref { name, commit_id };
-            new_ref.persist_or_update(&conn)?;
-        }
-        Head::Commit(_) => {
-            let new_head = Head::Commit(commit_id);
-            new_head.persist(&conn)?;
+        // Update ref to the root commit
+        head.update(tx, commit.commit_id)?;
+        if merge_head.is_some() {
+            MergeHead::clear(tx)?;
         }
-    }
+        Ok(commit)
+    })?;
 
     println!("Created new commit {}", commit.commit_id);
 
     Ok(())
 }
 
-/// Convert index entries to tree entries. Index entries must be sorted
-fn make_tree_entries(index_entries: &[BlobOrTree]) -> Vec<TreeEntry> {
-    index_entries
+/// Run the `pre-commit` hook on the staged files, then the `commit-msg` hook on the message.
+/// Returns the message as `commit-msg` left it.
+fn run_commit_hooks(
+    conn: &Connection,
+    gitqlite_home: &Path,
+    message: &str,
+) -> crate::Result<String> {
+    let hooks = Hooks::for_repository(gitqlite_home)?;
+    let staged: String = staged_files(conn)?
         .iter()
-        .map(|entry| {
-            let path = PathBuf::from(entry.name());
-            let filename = path.file_name().unwrap().to_str().unwrap().to_string();
-
-            match entry {
-                BlobOrTree::Blob(entry) => TreeEntry {
-                    type_: TreeEntryType::Blob,
-                    id: entry.sha,
-                    mode: entry.mode_perms.to_string(),
-                    name: filename,
-                },
-                BlobOrTree::Tree {
-                    tree,
-                    mode,
-                    name: _,
-                } => TreeEntry {
-                    type_: TreeEntryType::Tree,
-                    id: tree.tree_id,
-                    mode: mode.clone(),
-                    name: filename,
-                },
-            }
-        })
-        .collect()
+        .map(|path| format!("{}\n", path))
+        .collect();
+    if !hooks.run("pre-commit", &[], staged.as_bytes())? {
+        return Err(anyhow!("fatal: the pre-commit hook refused the commit"));
+    }
+    hooks
+        .check_commit_message(message)?
+        .ok_or_else(|| anyhow!("fatal: the commit-msg hook refused the commit message"))
 }
 
-// fn get_root_commit_id(conn: &Connection, head: Head) -> crate::Result<Option<Sha1Id>> {
-//   let root_commit_id = match head {
-//       Head::Branch(branch) => {
+/// Create and persist a commit of the given tree committed by the configured user.
+/// `author` is the (name, email) of the author, None to use the configured user as well.
+/// The commit declares the encoding of `i18n.commitEncoding`. Refs are not touched.
+pub fn commit_tree(
+    conn: &Connection,
+    config: &GitConfig,
+    tree_id: Sha1Id,
+    parent_ids: Vec<Sha1Id>,
+    message: String,
+    author: Option<(String, String)>,
+) -> crate::Result<Commit<Sha1Id>> {
+    let (user, user_email) = user_identity(config)?;
+    let (author_name, author_email) = author.unwrap_or_else(|| (user.clone(), user_email.clone()));
+
+    let mut commit = Commit::new(
+        tree_id,
+        parent_ids,
+        author_name,
+        author_email,
+        user,
+        user_email,
+        message,
+    );
+    if let Some(encoding) = commit_encoding(config)? {
+        encode_message(&commit.message, encoding)?;
+        commit.encoding = Some(encoding.name().to_string());
+    }
+    let commit_id = commit.hash(HashAlgorithm::read(conn)?.hasher());
+    let commit = commit.with_id(commit_id);
+    commit.persist(conn)?;
+    record_file_changes(conn, &[&commit])?;
+    record_reachability(conn, &[commit.commit_id])?;
 
-//       }
-//   };
-// }
+    Ok(commit)
+}
@@ -1,4 +1,4 @@
-use crate::git::{constants, utils::find_gitqlite_root};
+use crate::git::{constants, sqlite::SQLITE_CONFIG_DEFAULTS, utils::find_gitqlite_root};
 use crate::repo::config::{self, GitConfig};
 use anyhow::anyhow;
 
@@ -8,7 +8,11 @@ pub fn do_config(arg: ConfigArgs) -> crate::Result<()> {
     let ConfigArgs {
         name,
         value,
+        list,
+        unset,
+        rename_section,
         show_origin,
+        show_scope,
         system,
         global,
         local,
@@ -27,11 +31,56 @@ pub fn do_config(arg: ConfigArgs) -> crate::Result<()> {
         _ => return Err(anyhow!("error: only one config file at a time")),
     };
 
-    if let Some(value) = value {
+    // Prefix of an output line showing where the value comes from
+    let prefix = |scope: &str, origin: &str| {
+        let mut prefix = String::new();
+        if show_scope {
+            prefix.push_str(&format!("{}\t", scope));
+        }
+        if show_origin {
+            prefix.push_str(&format!("{}\t", origin));
+        }
+        prefix
+    };
+
+    if list {
+        for entry in config.list(source.clone()) {
+            println!(
+                "{}{}={}",
+                prefix(
+                    entry.source.scope(),
+                    &format!("file:{}", entry.origin.display())
+                ),
+                entry.key,
+                entry.value
+            );
+        }
+        // Document the defaults of the variables tuning the database
+        if show_scope && source == config::ConfigSource::All {
+            for (key, default) in SQLITE_CONFIG_DEFAULTS {
+                if config.get(key, config::ConfigSource::All)?.is_none() {
+                    println!("{}{}={}", prefix("default", "default:"), key, default);
+                }
+            }
+        }
+        return Ok(());
+    }
+
+    if let Some(names) = rename_section {
+        return config.rename_section(&names[0], &names[1], source);
+    }
+
+    let name = name.expect("name is required unless listing or renaming a section");
+    if unset {
+        config.unset(&name, source)
+    } else if let Some(value) = value {
         config.set(&name, value, source)
     } else {
         let value = config.get_with_source(&name, source)?;
         if let Some((value, origin)) = value {
+            if show_scope {
+                print!("{}\t", config.source_of(origin).scope());
+            }
             if show_origin {
                 println!("{}    {}", origin.display(), value);
             } else {
@@ -0,0 +1,42 @@
+use crate::{
+    cli::ContributorsArgs,
+    git::{
+        constants,
+        mailmap::Mailmap,
+        revision::{commits_between, resolve_revision},
+        stats::contributor_stats,
+        utils::{find_gitqlite_root, get_gitqlite_connection},
+    },
+    repo::config::GitConfig,
+};
+
+pub fn do_contributors(arg: ContributorsArgs) -> crate::Result<()> {
+    let repo_root = find_gitqlite_root(std::env::current_dir()?)?;
+    let conn = get_gitqlite_connection()?;
+    let mailmap = if arg.no_mailmap {
+        Mailmap::default()
+    } else {
+        let config = GitConfig::load(repo_root.join(constants::GITQLITE_DIRECTORY_PREFIX))?;
+        Mailmap::load(&repo_root, &config)?
+    };
+
+    let tip = resolve_revision(&conn, arg.revision.as_deref().unwrap_or("HEAD"))?;
+    let commits = commits_between(&conn, None, tip)?;
+    println!(
+        "{:>7} {:>8} {:>8}  {:<10}  {:<10}  author",
+        "commits", "added", "removed", "first", "last"
+    );
+    for contributor in contributor_stats(&conn, &commits, &mailmap)? {
+        println!(
+            "{:>7} {:>8} {:>8}  {:<10}  {:<10}  {} <{}>",
+            contributor.commits,
+            contributor.insertions,
+            contributor.deletions,
+            contributor.first,
+            contributor.last,
+            contributor.name,
+            contributor.email
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,191 @@
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::Mutex,
+    thread,
+    time::Duration,
+};
+
+use anyhow::anyhow;
+
+use crate::{
+    cli::DaemonArgs,
+    git::{
+        protocol::{
+            read_request, serve_connection, serve_request, write_message, DaemonOptions,
+            DEFAULT_DAEMON_PORT,
+        },
+        transport::http::PROTOCOL_CONTENT_TYPE,
+        web::Page,
+    },
+};
+
+/// Longest line accepted in the head of an HTTP request
+const MAX_HTTP_LINE: u64 = 8 << 10;
+/// Most header lines accepted in an HTTP request
+const MAX_HTTP_HEADERS: usize = 100;
+
+pub fn do_daemon(arg: DaemonArgs) -> crate::Result<()> {
+    let base_path = dunce::canonicalize(&arg.base_path).map_err(|_| {
+        anyhow!(
+            "fatal: base path '{}' does not exist",
+            arg.base_path.display()
+        )
+    })?;
+    let push_allow = arg
+        .allow_push
+        .iter()
+        .map(|pattern| {
+            glob::Pattern::new(pattern.trim_matches('/'))
+                .map_err(|err| anyhow!("fatal: invalid pattern '{}': {}", pattern, err))
+        })
+        .collect::<crate::Result<Vec<_>>>()?;
+    let options = DaemonOptions {
+        base_path,
+        push_allow,
+        max_pack_size: arg.max_pack_size,
+        upgraded: Mutex::default(),
+    };
+
+    let listen = arg
+        .listen
+        .unwrap_or_else(|| format!("127.0.0.1:{}", DEFAULT_DAEMON_PORT));
+    let listener = TcpListener::bind(&listen)
+        .map_err(|err| anyhow!("fatal: unable to listen on {}: {}", listen, err))?;
+    eprintln!(
+        "Ready to serve the repositories under {}{} on {}",
+        options.base_path.display(),
+        if arg.http { " over HTTP" } else { "" },
+        listen
+    );
+
+    // Each of --max-connections workers serves one client at a time with its own connection
+    // to the repository. The other clients wait for a worker in the backlog of the listener.
+    let timeout = Some(Duration::from_secs(arg.timeout));
+    thread::scope(|scope| {
+        for _ in 0..arg.max_connections {
+            scope.spawn(|| loop {
+                let result = listener.accept().and_then(|(stream, _)| {
+                    stream.set_read_timeout(timeout)?;
+                    stream.set_write_timeout(timeout)?;
+                    Ok(stream)
+                });
+                let stream = match result {
+                    Ok(stream) => stream,
+                    Err(err) => {
+                        eprintln!("error: failed to accept connection: {}", err);
+                        continue;
+                    }
+                };
+                let result = if arg.http {
+                    serve_http_client(stream, &options)
+                } else {
+                    serve_client(stream, &options)
+                };
+                if let Err(err) = result {
+                    eprintln!("error: {}", err);
+                }
+            });
+        }
+    });
+
+    Ok(())
+}
+
+fn serve_client(stream: TcpStream, options: &DaemonOptions) -> crate::Result<()> {
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+    serve_connection(&mut reader, &mut writer, options)
+}
+
+fn serve_http_client(stream: TcpStream, options: &DaemonOptions) -> crate::Result<()> {
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+    let page = serve_http(&mut reader, options);
+    write_page(&mut writer, &page)
+}
+
+/// Answer a request posted over HTTP, whose body holds the protocol request
+fn serve_http(reader: &mut impl BufRead, options: &DaemonOptions) -> Page {
+    let result = (|| {
+        let (method, content_length) = read_http_head(reader)?;
+        if method != "POST" {
+            return Err(anyhow!("Method not allowed"));
+        }
+        let content_length = content_length.ok_or_else(|| anyhow!("Length required"))?;
+        let (message, pack) =
+            read_request(&mut reader.take(content_length), options.max_pack_size)?;
+        let (response, pack) = serve_request(&message, pack.as_deref(), options);
+
+        let mut out = Vec::new();
+        write_message(&mut out, &response, pack.as_deref())?;
+        Ok(out)
+    })();
+
+    match result {
+        Ok(body) => Page {
+            status: 200,
+            content_type: PROTOCOL_CONTENT_TYPE,
+            body,
+        },
+        Err(err) => Page {
+            status: 400,
+            content_type: "text/plain; charset=utf-8",
+            body: format!("{}\n", err).into_bytes(),
+        },
+    }
+}
+
+/// Read the head of an HTTP request, returning its method and the length of its body
+fn read_http_head(reader: &mut impl BufRead) -> crate::Result<(String, Option<u64>)> {
+    let request_line = read_http_line(reader)?;
+    let method = request_line
+        .split(' ')
+        .next()
+        .unwrap_or_default()
+        .to_string();
+    let mut content_length = None;
+    for _ in 0..MAX_HTTP_HEADERS {
+        let line = read_http_line(reader)?;
+        if line.is_empty() {
+            return Ok((method, content_length));
+        }
+        let Some((name, value)) = line.split_once(':') else {
+            return Err(anyhow!("Invalid header line"));
+        };
+        if name.eq_ignore_ascii_case("Content-Length") {
+            let length = value.trim().parse();
+            content_length = Some(length.map_err(|_| anyhow!("Invalid Content-Length"))?);
+        } else if name.eq_ignore_ascii_case("Transfer-Encoding") {
+            return Err(anyhow!("Transfer-Encoding is not supported"));
+        }
+    }
+    Err(anyhow!("Too many headers"))
+}
+
+fn read_http_line(reader: &mut impl BufRead) -> crate::Result<String> {
+    let mut line = String::new();
+    reader.take(MAX_HTTP_LINE + 1).read_line(&mut line)?;
+    if line.len() as u64 > MAX_HTTP_LINE {
+        return Err(anyhow!("Header line too long"));
+    }
+    match line.strip_suffix('\n') {
+        Some(line) => Ok(line.strip_suffix('\r').unwrap_or(line).to_string()),
+        None => Err(anyhow!("fatal: the remote end hung up unexpectedly")),
+    }
+}
+
+/// Write `page` as the response to an HTTP request, closing the connection after it
+fn write_page(writer: &mut impl Write, page: &Page) -> crate::Result<()> {
+    write!(
+        writer,
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        page.status,
+        tiny_http::StatusCode(page.status).default_reason_phrase(),
+        page.content_type,
+        page.body.len()
+    )?;
+    writer.write_all(&page.body)?;
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,228 @@
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::anyhow;
+use rusqlite::Connection;
+
+use crate::{
+    cli::DiffArgs,
+    git::{
+        attributes::read_attributes,
+        constants,
+        diff::{
+            apply_drivers, detect_renames, diff_views, limit_to_paths, render, ContentSource,
+            DiffOutput, RenameDetection,
+        },
+        merge::merge_bases,
+        model::{Commit, Head, Index},
+        quote::PathQuoting,
+        revision::{resolve_revision, resolve_tree},
+        tree::{flatten_tree, index_view, TreeView},
+        utils::{find_gitqlite_root, get_gitqlite_connection},
+        worktree::{precompose_name, precompose_unicode, worktree_view},
+    },
+    repo::{
+        config::{ConfigSource, GitConfig},
+        db::{HashAlgorithm, HeadState},
+    },
+};
+
+pub fn do_diff(arg: DiffArgs) -> crate::Result<()> {
+    let DiffArgs {
+        revisions,
+        paths,
+        cached,
+        patch,
+        stat,
+        numstat,
+        shortstat,
+        name_only,
+        name_status,
+        find_renames,
+        find_copies,
+        no_renames,
+        word_diff,
+        no_ext_diff,
+        no_textconv,
+        null_terminated,
+    } = arg;
+
+    let repo_root = find_gitqlite_root(std::env::current_dir()?)?;
+    let gitqlite_home = repo_root.join(constants::GITQLITE_DIRECTORY_PREFIX);
+    let conn = get_gitqlite_connection()?;
+    let config = GitConfig::load(&gitqlite_home)?;
+    let index = Index::read_from_conn(&conn)?;
+
+    let detection = match (find_renames, find_copies, no_renames) {
+        (true, _, _) => RenameDetection::Renames,
+        (_, true, _) => RenameDetection::Copies,
+        (_, _, true) => RenameDetection::Off,
+        _ => rename_detection_from_config(&config)?,
+    };
+
+    let (old_view, old_source, new_view, new_source) =
+        diff_sides(&conn, &repo_root, &index, revisions, cached)?;
+
+    let paths = repo_relative_paths(&repo_root, &paths)?;
+    let old_view = limit_to_paths(old_view, &paths);
+    let new_view = limit_to_paths(new_view, &paths);
+    let changes = diff_views(&conn, &old_view, old_source, &new_view, new_source)?;
+    let changes = detect_renames(changes, &old_view, detection);
+    let attributes = read_attributes(&repo_root)?;
+    let changes = apply_drivers(changes, &config, &attributes, !no_ext_diff, !no_textconv)?;
+
+    let output = DiffOutput {
+        patch,
+        stat,
+        numstat,
+        shortstat,
+        name_only,
+        name_status,
+        word_diff,
+        quoting: PathQuoting::from_config(&config, null_terminated)?,
+    };
+    print!("{}", render(&changes, output)?);
+
+    Ok(())
+}
+
+/// The two sides compared by `diff` and `difftool`: the index and the working tree by
+/// default, HEAD and the index with `cached`, a revision and the working tree (or the index
+/// with `cached`), or two revisions. `<rev1>...<rev2>` compares the merge base of both
+/// revisions with `<rev2>`.
+pub fn diff_sides<'a>(
+    conn: &Connection,
+    repo_root: &'a Path,
+    index: &Index,
+    revisions: Vec<String>,
+    cached: bool,
+) -> crate::Result<(TreeView, ContentSource<'a>, TreeView, ContentSource<'a>)> {
+    // <rev1>..<rev2> is the same as giving both revisions, an empty side meaning HEAD
+    let revisions = match revisions.as_slice() {
+        [range] if range.contains("...") => {
+            let (from, to) = range.split_once("...").unwrap_or_default();
+            let side = |rev: &str| if rev.is_empty() { "HEAD" } else { rev }.to_string();
+            let (from, to) = (side(from), side(to));
+            let bases = merge_bases(
+                conn,
+                resolve_revision(conn, &from)?,
+                resolve_revision(conn, &to)?,
+            )?;
+            let Some(base) = bases.first() else {
+                return Err(anyhow!("fatal: {}...{}: no merge base", from, to));
+            };
+            vec![base.to_string(), to]
+        }
+        [range] if range.contains("..") => {
+            let (from, to) = range.split_once("..").unwrap_or_default();
+            [from, to]
+                .iter()
+                .map(|rev| if rev.is_empty() { "HEAD" } else { rev }.to_string())
+                .collect()
+        }
+        _ => revisions,
+    };
+    // HEAD on a branch without commits compares as an empty tree
+    let head_state = Head::read_from_conn(conn)?.state(conn)?;
+    let rev_view = |rev: &str| match (rev, &head_state) {
+        ("HEAD" | "@", HeadState::Unborn(_)) => Ok(TreeView::new()),
+        _ => flatten_tree(conn, resolve_tree(conn, rev)?),
+    };
+
+    Ok(match (revisions.as_slice(), cached) {
+        ([], true) => {
+            let head_view = match Head::read_from_conn(conn)?.resolve(conn)? {
+                Some(head_id) => {
+                    flatten_tree(conn, Commit::read_from_conn_with_id(conn, head_id)?.tree_id)?
+                }
+                None => TreeView::new(),
+            };
+            (
+                head_view,
+                ContentSource::Database,
+                index_view(index),
+                ContentSource::Database,
+            )
+        }
+        ([], false) => (
+            index_view(index),
+            ContentSource::Database,
+            worktree_view(repo_root, index, HashAlgorithm::read(conn)?)?,
+            ContentSource::Worktree(repo_root),
+        ),
+        ([rev], true) => (
+            rev_view(rev)?,
+            ContentSource::Database,
+            index_view(index),
+            ContentSource::Database,
+        ),
+        ([rev], false) => (
+            rev_view(rev)?,
+            ContentSource::Database,
+            worktree_view(repo_root, index, HashAlgorithm::read(conn)?)?,
+            ContentSource::Worktree(repo_root),
+        ),
+        (_, true) => {
+            return Err(anyhow!(
+                "fatal: --cached cannot be used when comparing two revisions"
+            ))
+        }
+        (revs, false) => (
+            rev_view(&revs[0])?,
+            ContentSource::Database,
+            rev_view(&revs[1])?,
+            ContentSource::Database,
+        ),
+    })
+}
+
+pub fn rename_detection_from_config(config: &GitConfig) -> crate::Result<RenameDetection> {
+    // `copies` is the one value of diff.renames which is not a boolean
+    let value = config.get("diff.renames", ConfigSource::All)?;
+    if value.is_some_and(|value| ["copies", "copy"].contains(&value.to_ascii_lowercase().as_str()))
+    {
+        return Ok(RenameDetection::Copies);
+    }
+    Ok(match config.get_bool("diff.renames", ConfigSource::All)? {
+        Some(true) => RenameDetection::Renames,
+        Some(false) => RenameDetection::Off,
+        None => RenameDetection::default(),
+    })
+}
+
+/// Turn pathspecs relative to the current directory into paths relative to the repository root
+pub fn repo_relative_paths(repo_root: &Path, paths: &[String]) -> crate::Result<Vec<String>> {
+    let precompose = precompose_unicode(repo_root)?;
+    let current_dir = dunce::canonicalize(std::env::current_dir()?)?;
+    let repo_root = dunce::canonicalize(repo_root)?;
+
+    paths
+        .iter()
+        .map(|path| {
+            // Resolve `.` and `..` lexically, as the path may not exist in the working tree
+            let mut full = PathBuf::new();
+            for component in current_dir.join(path).components() {
+                match component {
+                    Component::ParentDir => {
+                        full.pop();
+                    }
+                    Component::CurDir => {}
+                    component => full.push(component),
+                }
+            }
+            let relative = full.strip_prefix(&repo_root).map_err(|_| {
+                anyhow!(
+                    "fatal: {}: '{}' is outside repository at '{}'",
+                    path,
+                    path,
+                    repo_root.display()
+                )
+            })?;
+            let relative = relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            Ok(precompose_name(&relative, precompose))
+        })
+        .collect()
+}
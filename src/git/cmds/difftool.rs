@@ -0,0 +1,72 @@
+use std::path::Path;
+
+use anyhow::anyhow;
+
+use crate::{
+    cli::DifftoolArgs,
+    git::{
+        cmds::diff::{diff_sides, rename_detection_from_config, repo_relative_paths},
+        constants,
+        diff::{detect_renames, diff_views, limit_to_paths, FileVersion},
+        model::Index,
+        tools::{temp_version, ExternalTool, ToolKind},
+        utils::{find_gitqlite_root, get_gitqlite_connection},
+    },
+    repo::config::GitConfig,
+};
+
+pub fn do_difftool(arg: DifftoolArgs) -> crate::Result<()> {
+    let DifftoolArgs {
+        revisions,
+        paths,
+        cached,
+        tool,
+    } = arg;
+
+    let repo_root = find_gitqlite_root(std::env::current_dir()?)?;
+    let gitqlite_home = repo_root.join(constants::GITQLITE_DIRECTORY_PREFIX);
+    let conn = get_gitqlite_connection()?;
+    let config = GitConfig::load(&gitqlite_home)?;
+    let tool = ExternalTool::load(&config, ToolKind::Diff, tool.as_deref())?;
+    let index = Index::read_from_conn(&conn)?;
+
+    let (old_view, old_source, new_view, new_source) =
+        diff_sides(&conn, &repo_root, &index, revisions, cached)?;
+    let paths = repo_relative_paths(&repo_root, &paths)?;
+    let old_view = limit_to_paths(old_view, &paths);
+    let new_view = limit_to_paths(new_view, &paths);
+    let changes = diff_views(&conn, &old_view, old_source, &new_view, new_source)?;
+    let changes = detect_renames(changes, &old_view, rename_detection_from_config(&config)?);
+
+    // Each file is shown in turn, a missing side being an empty file as in git
+    for (i, change) in changes.iter().enumerate() {
+        println!("Viewing ({}/{}): '{}'", i + 1, changes.len(), change.name);
+        let data = |version: &Option<FileVersion>| version.as_ref().map(|v| v.data.clone());
+        let local = temp_version(
+            change.old_name(),
+            "LOCAL",
+            &data(&change.old).unwrap_or_default(),
+        )?;
+        let remote = temp_version(
+            &change.name,
+            "REMOTE",
+            &data(&change.new).unwrap_or_default(),
+        )?;
+        let merged = Path::new(&change.name);
+        let vars = [
+            ("LOCAL", local.path()),
+            ("REMOTE", remote.path()),
+            ("MERGED", merged),
+            ("BASE", merged),
+        ];
+        if !tool.run(&repo_root, &vars)? && tool.trust_exit_code {
+            return Err(anyhow!(
+                "fatal: external diff tool '{}' failed on {}",
+                tool.name,
+                change.name
+            ));
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,72 @@
+use crate::{
+    cli::DuArgs,
+    git::{
+        storage::{analyze_storage, format_size},
+        utils::{find_gitqlite_root, get_gitqlite_connection},
+    },
+};
+
+pub fn do_du(arg: DuArgs) -> crate::Result<()> {
+    find_gitqlite_root(std::env::current_dir()?)?;
+    let conn = get_gitqlite_connection()?;
+
+    let report = analyze_storage(&conn, arg.top)?;
+    let size = |bytes: u64| {
+        if arg.bytes {
+            bytes.to_string()
+        } else {
+            format_size(bytes)
+        }
+    };
+
+    println!(
+        "database: {} ({} free)",
+        size(report.database_bytes),
+        size(report.free_bytes)
+    );
+    println!("commits: {}", report.commits);
+    println!("trees: {} ({})", report.trees, size(report.tree_bytes));
+    println!("blobs: {} ({})", report.blobs, size(report.blob_bytes));
+    if report.duplicate_blobs > 0 {
+        println!(
+            "duplicate blob copies: {} ({})",
+            report.duplicate_blobs,
+            size(report.duplicate_bytes)
+        );
+    }
+    let percent = if report.history_bytes == 0 {
+        0.0
+    } else {
+        report.deduplication_savings() as f64 * 100.0 / report.history_bytes as f64
+    };
+    println!(
+        "deduplication: {} of committed files stored in {} of blobs, saving {} ({:.1}%)",
+        size(report.history_bytes),
+        size(report.blob_bytes),
+        size(report.deduplication_savings()),
+        percent
+    );
+
+    if !report.largest_blobs.is_empty() {
+        println!();
+        println!("largest blobs:");
+        for (id, bytes, path) in &report.largest_blobs {
+            println!(
+                "{:>10}  {}  {}",
+                size(*bytes),
+                id,
+                path.as_deref().unwrap_or("(not committed)")
+            );
+        }
+    }
+
+    if !report.directories.is_empty() {
+        println!();
+        println!("directories by historical size:");
+        for (directory, bytes) in &report.directories {
+            println!("{:>10}  {}", size(*bytes), directory);
+        }
+    }
+
+    Ok(())
+}
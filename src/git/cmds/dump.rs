@@ -0,0 +1,30 @@
+use std::{
+    fs,
+    io::{self, Write},
+};
+
+use crate::{
+    cli::DumpArgs,
+    git::{
+        dry_run,
+        dump::dump_database,
+        utils::{find_gitqlite_root, get_gitqlite_connection},
+    },
+};
+
+pub fn do_dump(arg: DumpArgs) -> crate::Result<()> {
+    find_gitqlite_root(std::env::current_dir()?)?;
+    let conn = get_gitqlite_connection()?;
+
+    match arg.output {
+        Some(path) if dry_run::skip_write("write", &path) => {}
+        Some(path) => {
+            let mut file = io::BufWriter::new(fs::File::create(path)?);
+            dump_database(&conn, &mut file)?;
+            file.flush()?;
+        }
+        None => dump_database(&conn, &mut io::stdout().lock())?,
+    }
+
+    Ok(())
+}
@@ -0,0 +1,306 @@
+use anyhow::anyhow;
+use rusqlite::Connection;
+
+use crate::{
+    cli::FetchArgs,
+    git::{
+        constants::{self, BRANCH_PREFIX, REMOTE_PREFIX, TAG_PREFIX},
+        merge::is_ancestor,
+        model::{FetchHead, Head, Ref, Sha1Id},
+        protocol::unpack_objects,
+        rebase::short_id,
+        refspec::{map_refs, Refspec},
+        remote::{delete_refs, prune_by_default, stale_refs, tracking_name, tracking_refspecs},
+        transfer::{forget_received, received_commits, record_received},
+        transport::{open_transport, remote_url, RemoteRefs, Transport},
+        utils::{find_gitqlite_root, get_gitqlite_connection, WriteTransaction},
+    },
+    repo::{
+        config::{ConfigSource, GitConfig},
+        db::{object_exists, OldValue, RefTransaction},
+    },
+};
+
+/// Number of commits downloaded at a time unless `fetch.chunkSize` says otherwise
+const DEFAULT_CHUNK_SIZE: usize = 1000;
+
+pub fn do_fetch(arg: FetchArgs) -> crate::Result<()> {
+    let repo_root = find_gitqlite_root(std::env::current_dir()?)?;
+    let gitqlite_home = repo_root.join(constants::GITQLITE_DIRECTORY_PREFIX);
+    let conn = get_gitqlite_connection()?;
+    let config = GitConfig::load(&gitqlite_home)?;
+
+    let url = remote_url(&config, &arg.repository)?;
+    let mut transport = open_transport(&url, &config)?;
+    let remote = transport.ls_refs()?;
+
+    // Remote-tracking branches are kept for configured remotes only
+    let tracking = tracking_refspecs(&config, &arg.repository)?;
+    let prune = match (arg.prune, arg.no_prune) {
+        (true, _) => true,
+        (_, true) => false,
+        _ => prune_by_default(&config, &arg.repository)?,
+    };
+    let refspecs: Vec<Refspec> = if arg.refs.is_empty() {
+        vec![Refspec::parse(&format!("{}*", BRANCH_PREFIX))?]
+    } else {
+        arg.refs
+            .iter()
+            .map(|refspec| Ok(Refspec::parse(refspec)?.expand()))
+            .collect::<crate::Result<_>>()?
+    };
+    let fetched = select_refs(&remote, &refspecs, arg.refs.is_empty())?;
+
+    let mut wants: Vec<Sha1Id> = Vec::new();
+    for fetched in &fetched {
+        if !object_exists(&conn, fetched.id.into())? {
+            wants.push(fetched.id);
+        }
+    }
+    if !wants.is_empty() {
+        let chunk_size = chunk_size(&config)?;
+        fetch_history(&conn, transport.as_mut(), &url, &wants, chunk_size)?;
+    }
+
+    let entries: Vec<FetchHead> = fetched
+        .iter()
+        .map(|fetched| FetchHead {
+            commit_id: fetched.id,
+            description: match fetched.name.strip_prefix(BRANCH_PREFIX) {
+                Some(branch) => format!("branch '{}' of {}", branch, url),
+                None => format!("'{}' of {}", fetched.name, url),
+            },
+        })
+        .collect();
+    FetchHead::replace_all(&conn, &entries)?;
+
+    println!("From {}", url);
+    if prune {
+        let stale = stale_refs(&conn, &tracking, &remote)?;
+        delete_refs(&conn, &arg.repository, &stale)?;
+        for stale in &stale {
+            println!(
+                " - {:<17} {:<10} -> {}",
+                "[deleted]",
+                "(none)",
+                tracking_name(&stale.name)
+            );
+        }
+    }
+    let mut updates = Vec::new();
+    for fetched in &fetched {
+        // Fetched refs also update their remote-tracking branch, as the configured refspec maps
+        let mapped = fetched
+            .dst
+            .clone()
+            .map(|dst| (dst, fetched.force))
+            .or_else(|| {
+                map_refs(&tracking, [fetched.name.as_str()])
+                    .into_iter()
+                    .find_map(|(_, dst, refspec)| Some((dst?, refspec.force)))
+            });
+        match mapped {
+            Some((dst, force)) => updates.push((fetched, dst, force)),
+            None => println!(
+                " * {:<17} {:<10} -> FETCH_HEAD",
+                kind(&fetched.name),
+                short_name(&fetched.name)
+            ),
+        }
+    }
+    update_local_refs(&conn, &arg.repository, &updates)
+}
+
+/// Number of commits downloaded at a time, from `fetch.chunkSize`
+fn chunk_size(config: &GitConfig) -> crate::Result<usize> {
+    match config.get("fetch.chunkSize", ConfigSource::All)? {
+        Some(value) => value.parse().ok().filter(|size| *size > 0).ok_or_else(|| {
+            anyhow!(
+                "fatal: bad numeric config value '{}' for 'fetch.chunkSize'",
+                value
+            )
+        }),
+        None => Ok(DEFAULT_CHUNK_SIZE),
+    }
+}
+
+/// Download the history of `wants` in chunks of commits, the oldest first. The tips of each
+/// chunk are recorded once it is stored, so that a fetch from the same remote interrupted
+/// before the last chunk resumes after the chunks it received.
+fn fetch_history(
+    conn: &Connection,
+    transport: &mut dyn Transport,
+    url: &str,
+    wants: &[Sha1Id],
+    chunk_size: usize,
+) -> crate::Result<()> {
+    let mut haves = local_tips(conn)?;
+    let received = received_commits(conn, url)?;
+    if !received.is_empty() {
+        eprintln!("Resuming the interrupted fetch from {}", url);
+        haves.extend(received);
+    }
+    loop {
+        let (pack, remaining) = transport.fetch_pack(wants, &haves, Some(chunk_size))?;
+        let header = unpack_objects(conn, &pack)?;
+        if remaining == 0 {
+            break;
+        }
+        let tips: Vec<Sha1Id> = header.refs.iter().map(|(_, id)| *id).collect();
+        record_received(conn, url, &tips)?;
+        haves.extend(tips);
+    }
+    forget_received(conn, url)
+}
+
+/// [`FetchedRef`] is a remote ref selected by the refspecs, with the local ref it is stored in
+struct FetchedRef {
+    name: String,
+    id: Sha1Id,
+    dst: Option<String>,
+    force: bool,
+}
+
+/// Move each local ref to the fetched tip of its remote ref, all at once, and report the
+/// refs that moved. Refs that would not be fast-forwarded are left alone unless forced.
+fn update_local_refs(
+    conn: &Connection,
+    remote: &str,
+    updates: &[(&FetchedRef, String, bool)],
+) -> crate::Result<()> {
+    let checked_out = match Head::read_from_conn(conn)? {
+        Head::Branch(name) => Some(name),
+        Head::Commit(_) => None,
+    };
+    let mut transaction = RefTransaction::new(format!("fetch {}", remote));
+    let mut lines = Vec::new();
+    let mut rejected = false;
+    for (fetched, dst, force) in updates {
+        if checked_out.as_ref() == Some(dst) {
+            return Err(anyhow!(
+                "fatal: refusing to fetch into branch '{}' checked out",
+                dst
+            ));
+        }
+        let new = fetched.id;
+        let src = short_name(&fetched.name);
+        let local = short_name(dst);
+        let old = Ref::read_from_conn_with_name(conn, dst)?.map(|r| r.commit_id);
+        let line = match old {
+            Some(old) if old == new => continue,
+            None => format!(
+                " * {:<17} {:<10} -> {}",
+                format!("[new {}]", kind(&fetched.name)),
+                src,
+                local
+            ),
+            Some(old) if is_ancestor(conn, old, new)? => format!(
+                "   {}..{}  {:<10} -> {}",
+                short_id(old),
+                short_id(new),
+                src,
+                local
+            ),
+            Some(old) if *force => format!(
+                " + {}...{} {:<10} -> {}  (forced update)",
+                short_id(old),
+                short_id(new),
+                src,
+                local
+            ),
+            Some(_) => {
+                rejected = true;
+                lines.push(format!(
+                    " ! [rejected]        {:<10} -> {}  (non-fast-forward)",
+                    src, local
+                ));
+                continue;
+            }
+        };
+        let old = old.map_or(OldValue::Missing, |old| OldValue::Is(old.into()));
+        transaction.update(dst.clone(), old, new.into());
+        lines.push(line);
+    }
+
+    let txn = WriteTransaction::new(conn)?;
+    transaction.commit(&txn)?;
+    txn.commit()?;
+    for line in lines {
+        println!("{}", line);
+    }
+    if rejected {
+        return Err(anyhow!("error: some local refs could not be updated"));
+    }
+    Ok(())
+}
+
+/// The remote refs the refspecs select. All of them puts the branch HEAD points to first, and
+/// a refspec without wildcard must match a remote ref.
+fn select_refs(
+    remote: &RemoteRefs,
+    refspecs: &[Refspec],
+    all: bool,
+) -> crate::Result<Vec<FetchedRef>> {
+    for refspec in refspecs.iter().filter(|refspec| !refspec.is_pattern()) {
+        if remote.get(&refspec.src).is_none() {
+            return Err(anyhow!(
+                "fatal: couldn't find remote ref {}",
+                short_name(&refspec.src)
+            ));
+        }
+    }
+
+    let names = remote.refs.iter().map(|(name, _)| name.as_str());
+    let mut fetched: Vec<FetchedRef> = map_refs(refspecs, names)
+        .into_iter()
+        .map(|(name, dst, refspec)| FetchedRef {
+            name: name.to_string(),
+            // Listed by the remote, as the name comes from its refs
+            id: remote.get(name).expect("listed ref"),
+            dst,
+            force: refspec.force,
+        })
+        .collect();
+    if all {
+        if let Some(head) = fetched
+            .iter()
+            .position(|fetched| Some(&fetched.name) == remote.head.as_ref())
+        {
+            let head = fetched.remove(head);
+            fetched.insert(0, head);
+        }
+    }
+    Ok(fetched)
+}
+
+/// What a ref is, as fetch reports it
+fn kind(name: &str) -> &'static str {
+    if name.starts_with(BRANCH_PREFIX) {
+        "branch"
+    } else if name.starts_with(TAG_PREFIX) {
+        "tag"
+    } else {
+        "ref"
+    }
+}
+
+/// Name of a ref without the prefix of branches or remote-tracking branches
+fn short_name(name: &str) -> &str {
+    name.strip_prefix(BRANCH_PREFIX)
+        .or_else(|| name.strip_prefix(REMOTE_PREFIX))
+        .unwrap_or(name)
+}
+
+/// Commits the repository already has, which bound the history to download
+fn local_tips(conn: &Connection) -> crate::Result<Vec<Sha1Id>> {
+    let mut tips: Vec<Sha1Id> = Head::read_from_conn(conn)?
+        .resolve(conn)?
+        .into_iter()
+        .collect();
+    tips.extend(
+        Ref::read_all(conn)?
+            .into_iter()
+            .map(|reference| reference.commit_id),
+    );
+    Ok(tips)
+}
@@ -0,0 +1,54 @@
+use crate::{
+    cli::GcArgs,
+    git::{
+        dry_run,
+        prune::{delete_objects, find_unreachable},
+        reachability::record_all_reachability,
+        search::index_commit_messages,
+        storage::{database_size, format_size, remove_duplicate_blobs},
+        utils::{find_gitqlite_root, get_gitqlite_connection, with_transaction},
+    },
+};
+
+pub fn do_gc(arg: GcArgs) -> crate::Result<()> {
+    find_gitqlite_root(std::env::current_dir()?)?;
+    let mut conn = get_gitqlite_connection()?;
+    let (before, _) = database_size(&conn)?;
+
+    let (indexed, pruned, duplicates) = with_transaction(&mut conn, |tx| {
+        // Fill in the messages of commits the index of commit messages missed
+        let indexed = index_commit_messages(tx)?;
+        let unreachable = find_unreachable(tx)?;
+        delete_objects(tx, &unreachable)?;
+        record_all_reachability(tx)?;
+        let duplicates = if arg.aggressive {
+            remove_duplicate_blobs(tx)?
+        } else {
+            0
+        };
+        Ok((indexed, unreachable.len(), duplicates))
+    })?;
+    if arg.aggressive {
+        conn.execute_batch("REINDEX; ANALYZE;")?;
+    }
+    // Give the pages freed above back to the filesystem, which a dry run, whose transaction
+    // is still open, cannot
+    if !dry_run::is_enabled() {
+        conn.execute_batch("VACUUM;")?;
+    }
+
+    let (after, _) = database_size(&conn)?;
+    if indexed > 0 {
+        println!("Indexed the messages of {} commits", indexed);
+    }
+    println!("Removed {} unreachable objects", pruned);
+    if arg.aggressive {
+        println!("Removed {} duplicate blobs", duplicates);
+    }
+    println!(
+        "database: {} -> {}",
+        format_size(before),
+        format_size(after)
+    );
+    Ok(())
+}
@@ -0,0 +1,48 @@
+use std::collections::HashSet;
+
+use crate::{
+    cli::GraphArgs,
+    git::{
+        graph::CommitGraph,
+        model::{Head, Ref},
+        revision::{commits_between, resolve_commits, resolve_revision},
+        utils::{find_gitqlite_root, get_gitqlite_connection},
+    },
+};
+
+pub fn do_graph(arg: GraphArgs) -> crate::Result<()> {
+    find_gitqlite_root(std::env::current_dir()?)?;
+    let conn = get_gitqlite_connection()?;
+
+    // Draw the history of every ref when no revision is given
+    let mut tips = Vec::new();
+    if arg.revisions.is_empty() {
+        tips.extend(Head::read_from_conn(&conn)?.resolve(&conn)?);
+        tips.extend(Ref::read_all(&conn)?.into_iter().map(|r| r.commit_id));
+    }
+
+    let mut seen = HashSet::new();
+    let mut commit_ids = Vec::new();
+    let mut add = |ids: Vec<_>| {
+        commit_ids.extend(ids.into_iter().filter(|id| seen.insert(*id)));
+    };
+    for tip in tips {
+        add(commits_between(&conn, None, tip)?);
+    }
+    for spec in &arg.revisions {
+        if spec.contains("..") {
+            add(resolve_commits(&conn, spec)?);
+        } else {
+            add(commits_between(
+                &conn,
+                None,
+                resolve_revision(&conn, spec)?,
+            )?);
+        }
+    }
+
+    let graph = CommitGraph::load(&conn, &commit_ids)?;
+    print!("{}", graph.render(arg.format));
+
+    Ok(())
+}
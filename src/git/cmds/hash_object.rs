@@ -1,5 +1,4 @@
 use anyhow::anyhow;
-use sha1::Digest;
 
 use std::{fs, io::Read, path::Path};
 
@@ -10,6 +9,7 @@ use crate::{
         model::{Blob, Hashable, Sha1Id},
         utils::get_gitqlite_connection,
     },
+    repo::db::HashAlgorithm,
 };
 
 pub fn do_hash_object(arg: HashObjectArgs) -> crate::Result<()> {
@@ -18,7 +18,7 @@ pub fn do_hash_object(arg: HashObjectArgs) -> crate::Result<()> {
 
     match type_ {
         ObjectType::Blob => {
-            let blob = construct_blob_from_file(&file)?;
+            let blob = construct_blob_from_file(&file, HashAlgorithm::read(&conn)?)?;
             if write {
                 blob.persist(&conn)?;
             }
@@ -30,7 +30,10 @@ pub fn do_hash_object(arg: HashObjectArgs) -> crate::Result<()> {
     Ok(())
 }
 
-pub fn construct_blob_from_file(path: impl AsRef<Path>) -> crate::Result<Blob<Sha1Id>> {
+pub fn construct_blob_from_file(
+    path: impl AsRef<Path>,
+    algorithm: HashAlgorithm,
+) -> crate::Result<Blob<Sha1Id>> {
     let path = path.as_ref();
 
     if !path.is_file() {
@@ -49,7 +52,7 @@ pub fn construct_blob_from_file(path: impl AsRef<Path>) -> crate::Result<Blob<Sh
 
     let blob = Blob::new(data);
 
-    let blob_id = blob.hash(sha1::Sha1::new());
+    let blob_id = blob.hash(algorithm.hasher());
 
     Ok(blob.with_id(blob_id))
 }
@@ -0,0 +1,41 @@
+use anyhow::anyhow;
+
+use crate::{
+    cli::{HookArgs, HookCommand},
+    git::{
+        constants,
+        hooks::{staged_files, Hooks},
+        utils::{find_gitqlite_root, get_gitqlite_connection},
+    },
+};
+
+pub fn do_hook(arg: HookArgs) -> crate::Result<()> {
+    let repo_root = find_gitqlite_root(std::env::current_dir()?)?;
+    let gitqlite_home = repo_root.join(constants::GITQLITE_DIRECTORY_PREFIX);
+    let conn = get_gitqlite_connection()?;
+    let hooks = Hooks::for_repository(&gitqlite_home)?;
+
+    match arg.command {
+        HookCommand::Run {
+            name,
+            args,
+            ignore_missing,
+        } => {
+            if !hooks.exists(&name) {
+                if ignore_missing {
+                    return Ok(());
+                }
+                return Err(anyhow!("error: cannot find a hook named {}", name));
+            }
+            let staged: String = staged_files(&conn)?
+                .iter()
+                .map(|path| format!("{}\n", path))
+                .collect();
+            let args: Vec<&str> = args.iter().map(String::as_str).collect();
+            if !hooks.run(&name, &args, staged.as_bytes())? {
+                return Err(anyhow!("error: hook {} failed", name));
+            }
+            Ok(())
+        }
+    }
+}
@@ -5,10 +5,12 @@ use rusqlite::Connection;
 
 use crate::cli::InitArgs;
 use crate::git::model::Head;
-use crate::git::{constants, model};
+use crate::git::{constants, manifest, model, schema, sqlite::SqliteSettings};
 use crate::repo::config::{self, GitConfig};
+use crate::repo::db::HashAlgorithm;
 
-pub fn do_init(_arg: InitArgs) -> crate::Result<()> {
+pub fn do_init(arg: InitArgs) -> crate::Result<()> {
+    let algorithm = HashAlgorithm::try_from(arg.object_format.as_str())?;
     let pwd = std::env::current_dir()?;
     let gitqlite_home = pwd.join(constants::GITQLITE_DIRECTORY_PREFIX);
 
@@ -26,11 +28,13 @@ pub fn do_init(_arg: InitArgs) -> crate::Result<()> {
 
     let db_path = gitqlite_home.join(constants::GITQLITE_DB_NAME);
 
+    let mut config = GitConfig::load(&gitqlite_home)?;
     let conn = Connection::open(db_path)?;
+    manifest::prepare_connection(&conn)?;
+    SqliteSettings::from_config(&config)?.apply(&conn)?;
 
-    initialize_gitqlite_tables(&conn)?;
+    initialize_gitqlite_tables(&conn, algorithm)?;
 
-    let mut config = GitConfig::load(&gitqlite_home)?;
     initialize_default_config(&mut config)?;
     initialize_head(&config, &conn)?;
 
@@ -49,19 +53,28 @@ pub fn do_init(_arg: InitArgs) -> crate::Result<()> {
     Ok(())
 }
 
-fn initialize_gitqlite_tables(conn: &Connection) -> crate::Result<()> {
+fn initialize_gitqlite_tables(conn: &Connection, algorithm: HashAlgorithm) -> crate::Result<()> {
     conn.execute(model::CREATE_INDEX_TABLE, ())
         .context("Create Index table")?;
     conn.execute(model::CREATE_HEAD_TABLE, ())
         .context("Create Head table")?;
     conn.execute(model::CREATE_REF_TABLE, ())
         .context("Create Ref table")?;
+    conn.execute(model::CREATE_IDENTITIES_TABLE, ())
+        .context("Create Identities table")?;
     conn.execute(model::CREATE_COMMIT_TABLE, ())
         .context("Create Commit table")?;
+    conn.execute(model::CREATE_COMMIT_AUTHOR_INDEX, ())
+        .context("Create Commit author index")?;
     conn.execute(model::CREATE_TREE_TABLE, ())
         .context("Create Tree table")?;
     conn.execute(model::CREATE_BLOB_TABLE, ())
         .context("Create Blob table")?;
+    algorithm
+        .persist(conn)
+        .context("Create ObjectFormat table")?;
+    schema::create_added_tables(conn).context("Create tables")?;
+    manifest::create_manifest(conn).context("Create Manifest table")?;
     Ok(())
 }
 
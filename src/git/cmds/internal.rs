@@ -0,0 +1,35 @@
+use std::io::{self, BufWriter, Write};
+
+use crate::{
+    cli::{InternalArgs, InternalCommand},
+    git::{
+        completion::candidates,
+        constants,
+        utils::{find_gitqlite_root, get_gitqlite_connection},
+    },
+};
+
+pub fn do_internal(arg: InternalArgs) -> crate::Result<()> {
+    match arg.command {
+        InternalCommand::Complete { kind } => {
+            // Completing outside of a repository has no candidates rather than an error
+            let current_dir = std::env::current_dir()?;
+            if !current_dir
+                .ancestors()
+                .any(|dir| dir.join(constants::GITQLITE_DIRECTORY_PREFIX).is_dir())
+            {
+                return Ok(());
+            }
+            let repo_root = find_gitqlite_root(current_dir)?;
+            let gitqlite_home = repo_root.join(constants::GITQLITE_DIRECTORY_PREFIX);
+            let conn = get_gitqlite_connection()?;
+
+            let mut out = BufWriter::new(io::stdout().lock());
+            for candidate in candidates(&conn, &gitqlite_home, kind)? {
+                writeln!(out, "{}", candidate)?;
+            }
+            out.flush()?;
+            Ok(())
+        }
+    }
+}
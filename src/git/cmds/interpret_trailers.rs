@@ -0,0 +1,68 @@
+use std::{
+    fs,
+    io::{self, Read},
+};
+
+use crate::{
+    cli::InterpretTrailersArgs,
+    git::{
+        constants, dry_run,
+        trailers::{new_trailer, parse_message, separators, TrailerOptions},
+        utils::find_gitqlite_root,
+    },
+    repo::config::GitConfig,
+};
+
+pub fn do_interpret_trailers(arg: InterpretTrailersArgs) -> crate::Result<()> {
+    let repo_root = find_gitqlite_root(std::env::current_dir()?)?;
+    let gitqlite_home = repo_root.join(constants::GITQLITE_DIRECTORY_PREFIX);
+    let config = GitConfig::load(&gitqlite_home)?;
+
+    let options = TrailerOptions {
+        placement: arg.placement,
+        if_exists: arg.if_exists,
+        if_missing: arg.if_missing,
+    };
+    let new_trailers = if arg.only_input || arg.parse {
+        Vec::new()
+    } else {
+        arg.trailer
+            .iter()
+            .map(|trailer| new_trailer(&config, trailer, &options))
+            .collect::<crate::Result<Vec<_>>>()?
+    };
+    let separators = separators(&config)?;
+
+    let process = |message: &str| {
+        let mut parsed = parse_message(message, &separators);
+        for new in &new_trailers {
+            parsed.add(new.clone());
+        }
+        if arg.unfold || arg.parse {
+            parsed.unfold();
+        }
+        if arg.only_trailers || arg.parse {
+            parsed.format_trailers()
+        } else {
+            parsed.format()
+        }
+    };
+
+    if arg.files.is_empty() {
+        let mut message = String::new();
+        io::stdin().read_to_string(&mut message)?;
+        print!("{}", process(&message));
+        return Ok(());
+    }
+    for file in &arg.files {
+        let output = process(&fs::read_to_string(file)?);
+        if arg.in_place {
+            if !dry_run::skip_write("write", file) {
+                fs::write(file, output)?;
+            }
+        } else {
+            print!("{}", output);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,58 @@
+use std::{
+    fs,
+    io::{self, Read},
+};
+
+use anyhow::anyhow;
+use rusqlite::Connection;
+
+use crate::{
+    cli::LoadArgs,
+    git::{constants, dump::load_database, manifest::prepare_connection, sqlite::SqliteSettings},
+    repo::config::GitConfig,
+};
+
+pub fn do_load(arg: LoadArgs) -> crate::Result<()> {
+    let LoadArgs { file, force } = arg;
+
+    let sql = match file {
+        Some(path) => fs::read_to_string(path)?,
+        None => {
+            let mut sql = String::new();
+            io::stdin().read_to_string(&mut sql)?;
+            sql
+        }
+    };
+
+    let gitqlite_home = std::env::current_dir()?.join(constants::GITQLITE_DIRECTORY_PREFIX);
+    let db_path = gitqlite_home.join(constants::GITQLITE_DB_NAME);
+    if db_path.exists() {
+        if !force {
+            return Err(anyhow!(
+                "fatal: a repository database already exists in {} (use --force to replace it)",
+                gitqlite_home.display()
+            ));
+        }
+        fs::remove_file(&db_path)?;
+        // A write-ahead log left behind would be replayed into the new database
+        for suffix in ["-wal", "-shm"] {
+            let path = gitqlite_home.join(format!("{}{}", constants::GITQLITE_DB_NAME, suffix));
+            if path.exists() {
+                fs::remove_file(path)?;
+            }
+        }
+    }
+    fs::create_dir_all(&gitqlite_home)?;
+
+    let conn = Connection::open(&db_path)?;
+    prepare_connection(&conn)?;
+    SqliteSettings::from_config(&GitConfig::load(&gitqlite_home)?)?.apply(&conn)?;
+    if let Err(err) = load_database(&conn, &sql) {
+        drop(conn);
+        fs::remove_file(&db_path)?;
+        return Err(anyhow!("fatal: could not load the dump: {}", err));
+    }
+
+    println!("Loaded Gitqlite repository in {}", gitqlite_home.display());
+    Ok(())
+}
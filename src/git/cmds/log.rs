@@ -0,0 +1,144 @@
+use std::{
+    collections::HashSet,
+    io::{self, Write},
+};
+
+use anyhow::anyhow;
+use chrono::TimeZone;
+use encoding_rs::Encoding;
+use regex::bytes::Regex;
+
+use crate::{
+    cli::LogArgs,
+    git::{
+        constants,
+        encoding::{encode_lossy, log_output_encoding},
+        file_changes::path_history,
+        model::{Commit, Head, Sha1Id},
+        pickaxe::{Pickaxe, PickaxeSearch},
+        rebase::short_id,
+        revision::resolve_revision,
+        utils::{find_gitqlite_root, open_gitqlite_read_connection},
+    },
+    repo::{
+        config::GitConfig,
+        db::{CommitWalk, HeadState},
+    },
+};
+
+pub fn do_log(arg: LogArgs) -> crate::Result<()> {
+    let LogArgs {
+        revision,
+        max_count,
+        pickaxe,
+        grep_diff,
+        follow,
+    } = arg;
+    let repo_root = find_gitqlite_root(std::env::current_dir()?)?;
+    let conn = open_gitqlite_read_connection(&repo_root)?;
+    let config = GitConfig::load(repo_root.join(constants::GITQLITE_DIRECTORY_PREFIX))?;
+    let encoding = log_output_encoding(&config)?;
+
+    let pickaxe = match (pickaxe, grep_diff) {
+        (Some(string), _) if string.is_empty() => {
+            return Err(anyhow!("fatal: -S requires a non-empty string"))
+        }
+        (Some(string), _) => Some(Pickaxe::Occurrences(string.into_bytes())),
+        (None, Some(regex)) => {
+            Some(Pickaxe::Lines(Regex::new(&regex).map_err(|err| {
+                anyhow!("fatal: invalid regex given to -G: {}", err)
+            })?))
+        }
+        (None, None) => None,
+    };
+    let mut search = pickaxe.map(|pickaxe| PickaxeSearch::new(&conn, pickaxe));
+
+    let tip = match revision {
+        Some(revision) => resolve_revision(&conn, &revision)?,
+        None => match Head::read_from_conn(&conn)?.state(&conn)? {
+            HeadState::Unborn(branch) => {
+                eprintln!(
+                    "Your current branch '{}' does not have any commits yet",
+                    branch
+                        .strip_prefix(constants::BRANCH_PREFIX)
+                        .unwrap_or(&branch)
+                );
+                return Ok(());
+            }
+            HeadState::Branch(_, commit_id) | HeadState::Detached(commit_id) => commit_id,
+        },
+    };
+    // The commits changing the followed file, looked up in the recorded file changes
+    let following = match follow {
+        Some(path) => Some(
+            path_history(&conn, tip, &path)?
+                .into_iter()
+                .map(|change| change.commit_id)
+                .collect::<HashSet<_>>(),
+        ),
+        None => None,
+    };
+    let mut shown = 0;
+    for walked in CommitWalk::new(&conn, [tip])? {
+        if max_count.is_some_and(|max_count| shown >= max_count) {
+            break;
+        }
+        let walked = walked?;
+        if following
+            .as_ref()
+            .is_some_and(|following| !following.contains(&walked.commit_id))
+        {
+            continue;
+        }
+        let commit = Commit::read_from_conn_with_id(&conn, walked.commit_id)?;
+        if let Some(search) = search.as_mut() {
+            if !search.matches(&commit)? {
+                continue;
+            }
+        }
+        // Databases created by older versions do not record when commits were written
+        let date = Some(walked.created_at).filter(|created_at| *created_at > 0);
+        print_commit_header(&commit, date, encoding)?;
+        shown += 1;
+    }
+    Ok(())
+}
+
+/// Format a Unix time as git prints dates by default, e.g. `Thu Oct 16 09:30:00 2026 +0200`
+fn format_date(timestamp: i64) -> String {
+    match chrono::Local.timestamp_opt(timestamp, 0).single() {
+        Some(time) => time.format("%a %b %-d %H:%M:%S %Y %z").to_string(),
+        None => timestamp.to_string(),
+    }
+}
+
+/// Print the id, author, date if known and indented message of a commit, followed by a blank
+/// line, in the given output encoding
+pub fn print_commit_header(
+    commit: &Commit<Sha1Id>,
+    date: Option<i64>,
+    encoding: &'static Encoding,
+) -> crate::Result<()> {
+    use std::fmt::Write as _;
+    let mut header = String::new();
+    writeln!(header, "commit {}", commit.commit_id)?;
+    if commit.parent_ids.len() > 1 {
+        let parents: Vec<String> = commit.parent_ids.iter().map(|id| short_id(*id)).collect();
+        writeln!(header, "Merge: {}", parents.join(" "))?;
+    }
+    writeln!(
+        header,
+        "Author: {} <{}>",
+        commit.author_name, commit.author_email
+    )?;
+    if let Some(date) = date {
+        writeln!(header, "Date:   {}", format_date(date))?;
+    }
+    writeln!(header)?;
+    for line in commit.message.trim_end().lines() {
+        writeln!(header, "    {}", line)?;
+    }
+    writeln!(header)?;
+    io::stdout().write_all(&encode_lossy(&header, encoding))?;
+    Ok(())
+}
@@ -3,18 +3,24 @@ use chrono::DateTime;
 use crate::{
     cli::LsFilesArgs,
     git::{
+        constants,
         model::{Index, ModeType},
-        utils::get_gitqlite_connection,
+        quote::PathQuoting,
+        utils::{find_gitqlite_root, get_gitqlite_connection},
     },
+    repo::config::GitConfig,
 };
 
 pub fn do_ls_files(arg: LsFilesArgs) -> crate::Result<()> {
+    let repo_root = find_gitqlite_root(std::env::current_dir()?)?;
     let conn = get_gitqlite_connection()?;
+    let config = GitConfig::load(repo_root.join(constants::GITQLITE_DIRECTORY_PREFIX))?;
+    let quoting = PathQuoting::from_config(&config, arg.null_terminated)?;
 
     let index = Index::read_from_conn(&conn)?;
 
     for entry in index.entries {
-        println!("{}", entry.name);
+        print!("{}{}", quoting.quote(&entry.name), quoting.terminator());
         if arg.verbose {
             let file_type = match entry.mode_type {
                 ModeType::Regular => "Regular File",
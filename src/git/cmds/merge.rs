@@ -0,0 +1,373 @@
+use std::path::Path;
+
+use anyhow::anyhow;
+use clap::ValueEnum;
+use rusqlite::Connection;
+
+use crate::{
+    cli::MergeArgs,
+    git::{
+        constants,
+        diff::{diff_views, render, ContentSource, DiffOutput},
+        merge::{
+            apply_merge, check_local_changes, is_ancestor, merge_bases, merge_contents,
+            merge_trees, MergeOutcome,
+        },
+        model::{Commit, Head, Index, MergeHead, Sha1Id},
+        progress::Progress,
+        revision::{branch_name, resolve_revision},
+        stash::{
+            apply_autostash, create_stash, keep_autostash, remove_stashed_changes, StashOptions,
+        },
+        tree::{flatten_tree, write_tree, TreeView},
+        utils::{find_gitqlite_root, get_gitqlite_connection, WriteTransaction},
+    },
+    repo::{
+        config::{ConfigSource, GitConfig},
+        db::{OldValue, RefTransaction},
+    },
+};
+
+use super::commit::commit_tree;
+
+/// How a merge that can be resolved as a fast-forward is carried out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FastForward {
+    /// Only move the branch pointer when possible, otherwise create a merge commit
+    Allow,
+    /// Always create a merge commit
+    Never,
+    /// Only move the branch pointer, refusing to merge otherwise
+    Only,
+}
+
+pub fn do_merge(arg: MergeArgs) -> crate::Result<()> {
+    let MergeArgs {
+        commit,
+        strategy,
+        strategy_option,
+        squash,
+        message,
+        ff,
+        no_ff,
+        ff_only,
+        autostash,
+        no_autostash,
+        quiet,
+    } = arg;
+
+    let repo_root = find_gitqlite_root(std::env::current_dir()?)?;
+    let gitqlite_home = repo_root.join(constants::GITQLITE_DIRECTORY_PREFIX);
+    let conn = get_gitqlite_connection()?;
+    let config = GitConfig::load(&gitqlite_home)?;
+
+    let fast_forward = match (ff, no_ff, ff_only) {
+        (true, _, _) => FastForward::Allow,
+        (_, true, _) => FastForward::Never,
+        (_, _, true) => FastForward::Only,
+        _ => fast_forward_from_config(&config)?,
+    };
+    if squash && fast_forward == FastForward::Never {
+        return Err(anyhow!("fatal: You cannot combine --squash with --no-ff."));
+    }
+
+    let mut index = Index::read_from_conn(&conn)?;
+    let mut progress = Progress::new(quiet);
+    if index.has_conflicts() || MergeHead::read_from_conn(&conn)?.is_some() {
+        return Err(anyhow!(
+            "fatal: You have not concluded your merge (MERGE_HEAD exists).\nPlease, commit your changes before you merge."
+        ));
+    }
+
+    let head = Head::read_from_conn(&conn)?;
+    let theirs_id = resolve_revision(&conn, &commit)?;
+
+    let Some(ours_id) = head.resolve(&conn)? else {
+        // Merging into a branch without commits always fast-forwards it
+        if squash {
+            return Err(anyhow!(
+                "fatal: Squash commit into empty head not supported yet"
+            ));
+        }
+        fast_forward_to(
+            &conn,
+            &repo_root,
+            &mut index,
+            &head,
+            None,
+            (theirs_id, &commit),
+            &mut progress,
+        )?;
+        return Ok(());
+    };
+
+    if is_ancestor(&conn, theirs_id, ours_id)? {
+        println!("Already up to date.");
+        return Ok(());
+    }
+
+    let can_fast_forward = is_ancestor(&conn, ours_id, theirs_id)?;
+    if !can_fast_forward && fast_forward == FastForward::Only {
+        return Err(anyhow!("fatal: Not possible to fast-forward, aborting."));
+    }
+
+    // Local changes are put aside while the merge rewrites the working tree
+    let autostash = match (autostash, no_autostash) {
+        (true, _) => true,
+        (_, true) => false,
+        _ => config
+            .get_bool("merge.autoStash", ConfigSource::All)?
+            .unwrap_or(false),
+    };
+    let autostash = if autostash {
+        let stash_id = create_stash(
+            &conn,
+            &repo_root,
+            &config,
+            "autostash",
+            StashOptions::default(),
+        )?;
+        if let Some(stash_id) = stash_id {
+            remove_stashed_changes(&conn, &repo_root, &mut index, stash_id)?;
+            println!("Created autostash: {}", &stash_id.to_string()[..7]);
+        }
+        stash_id
+    } else {
+        None
+    };
+
+    if can_fast_forward && !squash && fast_forward != FastForward::Never {
+        let result = fast_forward_to(
+            &conn,
+            &repo_root,
+            &mut index,
+            &head,
+            Some(ours_id),
+            (theirs_id, &commit),
+            &mut progress,
+        );
+        keep_autostash_on_error(&conn, autostash, result)?;
+        return apply_autostash(&conn, &repo_root, autostash, &mut progress);
+    }
+
+    let bases = merge_bases(&conn, ours_id, theirs_id)?;
+    let theirs_view = flatten_tree(
+        &conn,
+        Commit::read_from_conn_with_id(&conn, theirs_id)?.tree_id,
+    )?;
+
+    let base_view = match bases.first() {
+        Some(base_id) => {
+            let base = Commit::read_from_conn_with_id(&conn, *base_id)?;
+            flatten_tree(&conn, base.tree_id)?
+        }
+        None => TreeView::new(),
+    };
+    let ours_view = flatten_tree(
+        &conn,
+        Commit::read_from_conn_with_id(&conn, ours_id)?.tree_id,
+    )?;
+
+    let mut outcome = merge_trees(
+        &base_view,
+        &ours_view,
+        &theirs_view,
+        strategy,
+        strategy_option,
+    );
+    merge_contents(&conn, &mut outcome)?;
+
+    let result =
+        check_local_changes(&conn, &repo_root, &index, &ours_view, &outcome).and_then(|_| {
+            apply_merge(
+                &conn,
+                &repo_root,
+                &mut index,
+                &ours_view,
+                &outcome,
+                ("HEAD", &commit),
+                &mut progress,
+            )?;
+            index.persist(&conn)
+        });
+    keep_autostash_on_error(&conn, autostash, result)?;
+    progress.finish();
+
+    for conflict in &outcome.conflicts {
+        println!("{}", conflict.describe(("HEAD", &commit)));
+    }
+
+    // With conflicts, the local changes would get mixed with their resolution
+    if let (Some(stash_id), false) = (autostash, outcome.conflicts.is_empty()) {
+        keep_autostash(&conn, stash_id)?;
+    }
+
+    if squash {
+        println!("Squash commit -- not updating HEAD");
+        if !outcome.conflicts.is_empty() {
+            return Err(anyhow!(
+                "Automatic merge failed; fix conflicts and then commit the result."
+            ));
+        }
+        return apply_autostash(&conn, &repo_root, autostash, &mut progress);
+    }
+
+    let message = match message {
+        Some(message) => message,
+        None => match branch_name(&conn, &commit)? {
+            Some(branch) => format!("Merge branch '{}'", branch),
+            None => format!("Merge commit '{}'", commit),
+        },
+    };
+
+    if !outcome.conflicts.is_empty() {
+        MergeHead {
+            commit_id: theirs_id,
+            message,
+        }
+        .persist(&conn)?;
+        return Err(anyhow!(
+            "Automatic merge failed; fix conflicts and then commit the result."
+        ));
+    }
+
+    let tree_id = write_tree(&conn, &outcome.tree)?;
+    let merge_commit = commit_tree(
+        &conn,
+        &config,
+        tree_id,
+        vec![ours_id, theirs_id],
+        message,
+        None,
+    )?;
+    let strategy_name = strategy
+        .to_possible_value()
+        .map(|value| value.get_name().to_string())
+        .unwrap_or_default();
+    let summary = format!("Merge made by the '{}' strategy.", strategy_name);
+    match &head {
+        Head::Branch(branch) => {
+            let mut transaction = RefTransaction::new(format!("merge {}: {}", commit, summary));
+            transaction.update(
+                branch.clone(),
+                OldValue::Is(ours_id.into()),
+                merge_commit.commit_id.into(),
+            );
+            let txn = WriteTransaction::new(&conn)?;
+            transaction.commit(&txn)?;
+            txn.commit()?;
+        }
+        Head::Commit(_) => Head::Commit(merge_commit.commit_id).persist(&conn)?,
+    }
+
+    println!("{}", summary);
+    println!("Created new commit {}", merge_commit.commit_id);
+
+    apply_autostash(&conn, &repo_root, autostash, &mut progress)
+}
+
+/// Keep the autostash in the stash if the merge failed after it was created, as the changes
+/// it holds are no longer in the working tree
+fn keep_autostash_on_error<T>(
+    conn: &Connection,
+    autostash: Option<Sha1Id>,
+    result: crate::Result<T>,
+) -> crate::Result<T> {
+    if let (Err(_), Some(stash_id)) = (&result, autostash) {
+        keep_autostash(conn, stash_id)?;
+    }
+    result
+}
+
+/// Read the default fast-forward behavior from the merge.ff config
+fn fast_forward_from_config(config: &GitConfig) -> crate::Result<FastForward> {
+    // `only` is the one value of merge.ff which is not a boolean
+    let value = config.get("merge.ff", ConfigSource::All)?;
+    if value.is_some_and(|value| value.eq_ignore_ascii_case("only")) {
+        return Ok(FastForward::Only);
+    }
+    Ok(match config.get_bool("merge.ff", ConfigSource::All)? {
+        Some(false) => FastForward::Never,
+        _ => FastForward::Allow,
+    })
+}
+
+/// Move HEAD to `theirs_id`, named `theirs` on the command line, without creating a commit,
+/// bringing the index and working tree to its snapshot. `ours_id` is None when HEAD is on a
+/// branch without commits. The branch moves in the same transaction as the index is written,
+/// recording the fast-forward in its reflog.
+fn fast_forward_to(
+    conn: &Connection,
+    repo_root: &Path,
+    index: &mut Index,
+    head: &Head,
+    ours_id: Option<Sha1Id>,
+    (theirs_id, theirs): (Sha1Id, &str),
+    progress: &mut Progress,
+) -> crate::Result<()> {
+    let ours_view = match ours_id {
+        Some(ours_id) => {
+            flatten_tree(conn, Commit::read_from_conn_with_id(conn, ours_id)?.tree_id)?
+        }
+        None => TreeView::new(),
+    };
+    let outcome = MergeOutcome {
+        tree: flatten_tree(
+            conn,
+            Commit::read_from_conn_with_id(conn, theirs_id)?.tree_id,
+        )?,
+        conflicts: Vec::new(),
+    };
+
+    check_local_changes(conn, repo_root, index, &ours_view, &outcome)?;
+    let txn = WriteTransaction::new(conn)?;
+    apply_merge(
+        &txn,
+        repo_root,
+        index,
+        &ours_view,
+        &outcome,
+        ("HEAD", "HEAD"),
+        progress,
+    )?;
+    index.persist(&txn)?;
+    match head {
+        Head::Branch(branch) => {
+            let old = match ours_id {
+                Some(ours_id) => OldValue::Is(ours_id.into()),
+                None => OldValue::Missing,
+            };
+            let mut transaction = RefTransaction::new(format!("merge {}: Fast-forward", theirs));
+            transaction.update(branch.clone(), old, theirs_id.into());
+            transaction.commit(&txn)?;
+        }
+        Head::Commit(_) => Head::Commit(theirs_id).persist(&txn)?,
+    }
+    txn.commit()?;
+
+    if let Some(ours_id) = ours_id {
+        println!(
+            "Updating {}..{}",
+            &ours_id.to_string()[..7],
+            &theirs_id.to_string()[..7]
+        );
+    }
+    println!("Fast-forward");
+    if !progress.is_quiet() {
+        let changes = diff_views(
+            conn,
+            &ours_view,
+            ContentSource::Database,
+            &outcome.tree,
+            ContentSource::Database,
+        )?;
+        let output = DiffOutput {
+            stat: true,
+            ..DiffOutput::default()
+        };
+        print!("{}", render(&changes, output)?);
+    }
+    progress.finish();
+
+    Ok(())
+}
@@ -0,0 +1,99 @@
+use std::fs;
+
+use anyhow::anyhow;
+
+use crate::{
+    cli::MergetoolArgs,
+    git::{
+        cmds::diff::repo_relative_paths,
+        constants,
+        model::{Blob, Index},
+        tools::{temp_version, ExternalTool, ToolKind},
+        utils::{find_gitqlite_root, get_gitqlite_connection, with_transaction},
+        worktree::{precompose_unicode, stage_files},
+    },
+    repo::config::GitConfig,
+};
+
+pub fn do_mergetool(arg: MergetoolArgs) -> crate::Result<()> {
+    let MergetoolArgs { paths, tool } = arg;
+
+    let repo_root = find_gitqlite_root(std::env::current_dir()?)?;
+    let gitqlite_home = repo_root.join(constants::GITQLITE_DIRECTORY_PREFIX);
+    let mut conn = get_gitqlite_connection()?;
+    let config = GitConfig::load(&gitqlite_home)?;
+    let index = Index::read_from_conn(&conn)?;
+
+    let conflicted = index.conflicted_names();
+    let names: Vec<String> = if paths.is_empty() {
+        conflicted.iter().map(|name| name.to_string()).collect()
+    } else {
+        let names = repo_relative_paths(&repo_root, &paths)?;
+        if let Some(name) = names
+            .iter()
+            .find(|name| !conflicted.contains(&name.as_str()))
+        {
+            return Err(anyhow!("error: '{}' is not unmerged", name));
+        }
+        names
+    };
+    if names.is_empty() {
+        println!("No files need merging");
+        return Ok(());
+    }
+    let tool = ExternalTool::load(&config, ToolKind::Merge, tool.as_deref())?;
+    let precompose = precompose_unicode(&repo_root)?;
+
+    let mut failed = Vec::new();
+    for name in &names {
+        println!("Normal merge conflict for '{}'", name);
+
+        // Each stage goes to a temporary file, a missing one being empty as in git
+        let mut stages: [Vec<u8>; 3] = Default::default();
+        for entry in Index::read_entries_with_name(&conn, name)? {
+            if (1..=3).contains(&entry.flag_stage) {
+                stages[entry.flag_stage as usize - 1] =
+                    Blob::read_from_conn_with_id(&conn, entry.sha)?.data;
+            }
+        }
+        let [base, local, remote] = stages;
+        let base = temp_version(name, "BASE", &base)?;
+        let local = temp_version(name, "LOCAL", &local)?;
+        let remote = temp_version(name, "REMOTE", &remote)?;
+        let merged = repo_root.join(name);
+        let before = fs::read(&merged).ok();
+
+        let vars = [
+            ("BASE", base.path()),
+            ("LOCAL", local.path()),
+            ("REMOTE", remote.path()),
+            ("MERGED", merged.as_path()),
+        ];
+        let success = tool.run(&repo_root, &vars)?;
+        // Unless the tool is trusted to report unresolved conflicts, a merge which left the
+        // file as it was did not happen
+        let resolved = success && (tool.trust_exit_code || fs::read(&merged).ok() != before);
+        if !resolved {
+            eprintln!("merge of {} failed", name);
+            failed.push(name.as_str());
+            continue;
+        }
+
+        // Staging the result resolves the conflict, a deleted file being removed
+        with_transaction(&mut conn, |tx| {
+            if merged.exists() {
+                stage_files(tx, &repo_root, std::slice::from_ref(&merged), precompose)
+            } else {
+                Index::unstage_name(tx, name)
+            }
+        })?;
+    }
+
+    if !failed.is_empty() {
+        return Err(anyhow!(
+            "fatal: conflicts left unresolved in {}",
+            failed.join(", ")
+        ));
+    }
+    Ok(())
+}
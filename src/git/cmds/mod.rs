@@ -1,10 +1,55 @@
 pub mod add;
+pub mod api;
+pub mod archive;
+pub mod branch;
+pub mod bundle;
 pub mod cat_file;
+pub mod check_attr;
 pub mod check_ignore;
+pub mod check_ref_format;
+pub mod checkout;
+pub mod checkout_index;
+pub mod cherry;
+pub mod cherry_pick;
 pub mod commit;
 pub mod config;
+pub mod contributors;
+pub mod daemon;
+pub mod diff;
+pub mod difftool;
+pub mod du;
+pub mod dump;
+pub mod fetch;
+pub mod gc;
+pub mod graph;
 pub mod hash_object;
+pub mod hook;
 pub mod init;
+pub mod internal;
+pub mod interpret_trailers;
+pub mod load;
+pub mod log;
 pub mod ls_files;
+pub mod merge;
+pub mod mergetool;
+pub mod patch_id;
+pub mod pin;
+pub mod prune;
+pub mod push;
+pub mod range_diff;
+pub mod rebase;
+pub mod remote;
+pub mod reset;
+pub mod revert;
 pub mod rm;
+pub mod search;
+pub mod serve;
+pub mod show;
+pub mod snapshot;
+pub mod stash;
+pub mod stats;
 pub mod status;
+pub mod switch;
+pub mod tag;
+pub mod verify;
+pub mod web;
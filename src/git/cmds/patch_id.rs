@@ -0,0 +1,34 @@
+use std::io::{self, Read};
+
+use crate::{
+    cli::PatchIdArgs,
+    git::{
+        patchid::{commit_patch_id, patch_ids},
+        revision::resolve_revision,
+        utils::{find_gitqlite_root, get_gitqlite_connection},
+    },
+};
+
+/// Commit id printed for a patch not taken from a known commit, as git does
+const NULL_COMMIT: &str = "0000000000000000000000000000000000000000";
+
+pub fn do_patch_id(arg: PatchIdArgs) -> crate::Result<()> {
+    if arg.commits.is_empty() {
+        let mut text = String::new();
+        io::stdin().read_to_string(&mut text)?;
+        for (patch_id, commit) in patch_ids(&text, arg.verbatim) {
+            println!("{} {}", patch_id, commit.as_deref().unwrap_or(NULL_COMMIT));
+        }
+        return Ok(());
+    }
+
+    find_gitqlite_root(std::env::current_dir()?)?;
+    let conn = get_gitqlite_connection()?;
+    for rev in &arg.commits {
+        let commit_id = resolve_revision(&conn, rev)?;
+        if let Some(patch_id) = commit_patch_id(&conn, commit_id, arg.verbatim)? {
+            println!("{} {}", patch_id, commit_id);
+        }
+    }
+    Ok(())
+}
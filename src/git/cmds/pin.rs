@@ -0,0 +1,33 @@
+use crate::{
+    cli::PinArgs,
+    git::{
+        pin::{pin, pinned_objects, resolve_object, unpin},
+        utils::{find_gitqlite_root, get_gitqlite_connection, with_transaction},
+    },
+};
+
+pub fn do_pin(arg: PinArgs) -> crate::Result<()> {
+    find_gitqlite_root(std::env::current_dir()?)?;
+    let mut conn = get_gitqlite_connection()?;
+
+    if arg.objects.is_empty() {
+        for (kind, id) in pinned_objects(&conn)? {
+            println!("{} {}", id, kind.as_str());
+        }
+        return Ok(());
+    }
+
+    with_transaction(&mut conn, |tx| {
+        for name in &arg.objects {
+            let (kind, id) = resolve_object(tx, name)?;
+            if arg.delete {
+                if !unpin(tx, id)? {
+                    eprintln!("warning: {} {} is not pinned", kind.as_str(), id);
+                }
+            } else if !pin(tx, kind, id)? {
+                eprintln!("warning: {} {} is already pinned", kind.as_str(), id);
+            }
+        }
+        Ok(())
+    })
+}
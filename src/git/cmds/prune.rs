@@ -0,0 +1,48 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{
+    cli::PruneArgs,
+    git::{
+        prune::{delete_objects, find_unreachable, format_age},
+        reachability::record_all_reachability,
+        storage::format_size,
+        utils::{find_gitqlite_root, get_gitqlite_connection, with_transaction},
+    },
+};
+
+pub fn do_prune(arg: PruneArgs) -> crate::Result<()> {
+    find_gitqlite_root(std::env::current_dir()?)?;
+    let mut conn = get_gitqlite_connection()?;
+
+    with_transaction(&mut conn, |tx| {
+        let unreachable = find_unreachable(tx)?;
+        if arg.dry_run || arg.verbose {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+            for object in &unreachable {
+                let age = match object.created_at {
+                    Some(created_at) => format!("{} old", format_age(created_at, now)),
+                    None => "age unknown".to_string(),
+                };
+                println!(
+                    "{} {} {:>10}  {}",
+                    object.id,
+                    object.kind.as_str(),
+                    format_size(object.size),
+                    age
+                );
+            }
+        }
+        if arg.dry_run {
+            let total: u64 = unreachable.iter().map(|object| object.size).sum();
+            println!(
+                "{} unreachable objects ({}) would be removed",
+                unreachable.len(),
+                format_size(total)
+            );
+            return Ok(());
+        }
+        delete_objects(tx, &unreachable)?;
+        // Fill in the commits written before the repository recorded their ancestors
+        record_all_reachability(tx)
+    })
+}
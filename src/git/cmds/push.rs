@@ -0,0 +1,321 @@
+use anyhow::anyhow;
+use rusqlite::Connection;
+
+use crate::{
+    cli::PushArgs,
+    git::{
+        constants::{self, BRANCH_PREFIX, REMOTE_PREFIX},
+        hooks::{Hooks, NULL_ID},
+        merge::is_ancestor,
+        model::{Commit, Ref, Sha1Id},
+        protect::check_rewrite,
+        protocol::{pack_objects, RefUpdate},
+        rebase::short_id,
+        refspec::{map_refs, Refspec},
+        revision::{branch_name, resolve_revision},
+        transport::{is_configured_remote, open_transport, push_url},
+        utils::{find_gitqlite_root, get_gitqlite_connection},
+    },
+    repo::config::GitConfig,
+};
+
+/// [`PushStatus`] is how a ref update went
+enum PushStatus {
+    UpToDate,
+    Pushed,
+    /// Refused before sending it, the remote ref is not an ancestor of the new commit
+    NonFastForward,
+    /// Refused before sending it, the remote ref is not where the lease expects it
+    StaleInfo,
+    /// Refused by the remote for the given reason
+    RemoteRejected(String),
+}
+
+pub fn do_push(arg: PushArgs) -> crate::Result<()> {
+    let repo_root = find_gitqlite_root(std::env::current_dir()?)?;
+    let gitqlite_home = repo_root.join(constants::GITQLITE_DIRECTORY_PREFIX);
+    let conn = get_gitqlite_connection()?;
+    let config = GitConfig::load(&gitqlite_home)?;
+
+    let url = push_url(&config, &arg.repository)?;
+    let mut transport = open_transport(&url, &config)?;
+    let remote = transport.ls_refs()?;
+
+    let tracking_remote =
+        is_configured_remote(&config, &arg.repository)?.then_some(arg.repository.as_str());
+    let mut updates = Vec::new();
+    let mut sources = Vec::new();
+    let mut leases = Vec::new();
+    for refspec in &arg.refspecs {
+        for (source, mut update) in select_updates(&conn, refspec)? {
+            update.force |= arg.force;
+            update.old = remote.get(&update.name);
+            leases.push(lease(
+                &conn,
+                &arg.force_with_lease,
+                tracking_remote,
+                &update.name,
+            )?);
+            sources.push(source);
+            updates.push(update);
+        }
+    }
+
+    let expected = arg
+        .force_protected
+        .as_deref()
+        .map(|rev| resolve_revision(&conn, rev))
+        .transpose()?;
+    for (update, lease) in updates.iter_mut().zip(&leases) {
+        // The lease forces the update, the server refusing it if the ref moves meanwhile
+        if let Some(expected) = lease {
+            update.force = true;
+            if update.old != *expected {
+                continue;
+            }
+        }
+        let Some(branch) = update.name.strip_prefix(BRANCH_PREFIX) else {
+            continue;
+        };
+        let rewrite = match (update.old, update.new) {
+            (Some(_), None) => true,
+            (Some(old), Some(new)) => update.force && !fast_forward(&conn, old, new)?,
+            (None, _) => false,
+        };
+        if rewrite {
+            check_rewrite(&config, branch, update.old, expected.or(lease.flatten()))?;
+        }
+    }
+
+    let mut statuses: Vec<PushStatus> = updates
+        .iter()
+        .zip(&leases)
+        .map(|(update, lease)| match lease {
+            Some(expected) if update.old != *expected => Ok(PushStatus::StaleInfo),
+            _ => local_status(&conn, update),
+        })
+        .collect::<crate::Result<_>>()?;
+    let to_send: Vec<RefUpdate> = updates
+        .iter()
+        .zip(&statuses)
+        .filter(|(_, status)| matches!(status, PushStatus::Pushed))
+        .map(|(update, _)| update.clone())
+        .collect();
+
+    if !to_send.is_empty() && !arg.no_verify {
+        let input: String = updates
+            .iter()
+            .zip(&sources)
+            .zip(&statuses)
+            .filter(|(_, status)| matches!(status, PushStatus::Pushed))
+            .map(|((update, source), _)| {
+                let id = |id: Option<Sha1Id>| id.map_or(NULL_ID.to_string(), |id| id.to_string());
+                format!(
+                    "{} {} {} {}\n",
+                    source,
+                    id(update.new),
+                    update.name,
+                    id(update.old)
+                )
+            })
+            .collect();
+        let hooks = Hooks::for_repository(&gitqlite_home)?;
+        if !hooks.run("pre-push", &[&arg.repository, &url], input.as_bytes())? {
+            return Err(anyhow!(
+                "error: failed to push some refs to '{}', the pre-push hook refused the push",
+                url
+            ));
+        }
+    }
+
+    if !to_send.is_empty() {
+        let wants: Vec<Sha1Id> = to_send.iter().filter_map(|update| update.new).collect();
+        let haves: Vec<Sha1Id> = remote.refs.iter().map(|(_, id)| *id).collect();
+        let pack = if wants.is_empty() {
+            Vec::new()
+        } else {
+            pack_objects(&conn, &wants, &haves, None)?.0
+        };
+        for (name, rejection) in transport.push_pack(&to_send, &pack)? {
+            let index = updates
+                .iter()
+                .position(|update| update.name == name)
+                .ok_or_else(|| anyhow!("fatal: protocol error: unexpected ref '{}'", name))?;
+            if let Some(reason) = rejection {
+                statuses[index] = PushStatus::RemoteRejected(reason);
+            }
+        }
+    }
+
+    if statuses
+        .iter()
+        .all(|status| matches!(status, PushStatus::UpToDate))
+    {
+        println!("Everything up-to-date");
+        return Ok(());
+    }
+
+    println!("To {}", url);
+    let mut failed = false;
+    for (update, status) in updates.iter().zip(&statuses) {
+        let name = short_name(&update.name);
+        let line = match (status, update.old, update.new) {
+            (PushStatus::UpToDate, _, _) => continue,
+            (PushStatus::Pushed, None, _) => format!(" * [new branch]      {}", name),
+            (PushStatus::Pushed, _, None) => format!(" - [deleted]         {}", name),
+            (PushStatus::Pushed, Some(old), Some(new)) if fast_forward(&conn, old, new)? => {
+                format!("   {}..{}  {}", short_id(old), short_id(new), name)
+            }
+            (PushStatus::Pushed, Some(old), Some(new)) => format!(
+                " + {}...{} {} (forced update)",
+                short_id(old),
+                short_id(new),
+                name
+            ),
+            (PushStatus::NonFastForward, _, _) => {
+                failed = true;
+                format!(" ! [rejected]        {} (non-fast-forward)", name)
+            }
+            (PushStatus::StaleInfo, _, _) => {
+                failed = true;
+                format!(" ! [rejected]        {} (stale info)", name)
+            }
+            (PushStatus::RemoteRejected(reason), _, _) => {
+                failed = true;
+                format!(" ! [remote rejected] {} ({})", name, reason)
+            }
+        };
+        println!("{}", line);
+    }
+
+    if failed {
+        return Err(anyhow!("error: failed to push some refs to '{}'", url));
+    }
+    Ok(())
+}
+
+/// The updates of the remote refs a refspec asks for, each with the local ref or revision it
+/// pushes, `(delete)` for deletions. `<src>:<dst>` updates <dst> to the commit <src> names, a
+/// missing <dst> being the branch <src> names, and a refspec with a wildcard updates a remote
+/// ref for each local ref its source matches.
+fn select_updates(conn: &Connection, refspec: &str) -> crate::Result<Vec<(String, RefUpdate)>> {
+    let parsed = Refspec::parse(refspec)?;
+    if parsed.is_pattern() {
+        let parsed = parsed.expand();
+        let refs = Ref::read_all(conn)?;
+        let names = refs.iter().map(|reference| reference.name.as_str());
+        return Ok(map_refs(std::slice::from_ref(&parsed), names)
+            .into_iter()
+            .map(|(name, dst, _)| {
+                let update = RefUpdate {
+                    name: dst.unwrap_or_else(|| name.to_string()),
+                    old: None,
+                    new: refs
+                        .iter()
+                        .find(|reference| reference.name == name)
+                        .map(|reference| reference.commit_id),
+                    force: parsed.force,
+                };
+                (name.to_string(), update)
+            })
+            .collect());
+    }
+
+    let new = if parsed.is_delete() {
+        None
+    } else {
+        Some(resolve_revision(conn, &parsed.src)?)
+    };
+    let name = match parsed.expand().dst {
+        Some(dst) => dst,
+        None => match branch_name(conn, &parsed.src)? {
+            Some(branch) => format!("{}{}", BRANCH_PREFIX, branch),
+            None => {
+                return Err(anyhow!(
+                    "error: src refspec {} does not match any branch, name the destination with <src>:<dst>",
+                    parsed.src
+                ))
+            }
+        },
+    };
+
+    let source = if parsed.is_delete() {
+        "(delete)".to_string()
+    } else {
+        parsed.expand().src
+    };
+    Ok(vec![(
+        source,
+        RefUpdate {
+            name,
+            old: None,
+            new,
+            force: parsed.force,
+        },
+    )])
+}
+
+/// The value the remote ref `name` is expected to have under `--force-with-lease`, Some(None)
+/// for a missing ref, None if the update is not leased. A lease without an expected value
+/// expects our remote-tracking branch for the ref, if we push to a configured remote.
+fn lease(
+    conn: &Connection,
+    leases: &[String],
+    tracking_remote: Option<&str>,
+    name: &str,
+) -> crate::Result<Option<Option<Sha1Id>>> {
+    let short = short_name(name);
+    let mut expect = None;
+    for lease in leases {
+        let (refname, value) = match lease.split_once(':') {
+            Some((refname, value)) => (refname, Some(value)),
+            None => (lease.as_str(), None),
+        };
+        if refname.is_empty() || refname == name || refname == short {
+            expect = Some(value);
+        }
+    }
+    let Some(value) = expect else {
+        return Ok(None);
+    };
+    match (value, tracking_remote) {
+        (Some(""), _) => Ok(Some(None)),
+        (Some(value), _) => Ok(Some(Some(resolve_revision(conn, value)?))),
+        (None, Some(remote)) => {
+            let tracking = format!("{}{}/{}", REMOTE_PREFIX, remote, short);
+            Ok(Some(
+                Ref::read_from_conn_with_name(conn, &tracking)?.map(|r| r.commit_id),
+            ))
+        }
+        (None, None) => Err(anyhow!(
+            "fatal: cannot tell where '{}' is expected to be, name it with --force-with-lease={}:<expect>",
+            short,
+            short
+        )),
+    }
+}
+
+/// Decide what to do with an update before contacting the remote
+fn local_status(conn: &Connection, update: &RefUpdate) -> crate::Result<PushStatus> {
+    if update.old == update.new {
+        return Ok(PushStatus::UpToDate);
+    }
+    let (Some(old), Some(new)) = (update.old, update.new) else {
+        return Ok(PushStatus::Pushed);
+    };
+    if update.force || fast_forward(conn, old, new)? {
+        Ok(PushStatus::Pushed)
+    } else {
+        Ok(PushStatus::NonFastForward)
+    }
+}
+
+/// Whether moving a ref from `old` to `new` is a fast-forward. The remote tip must be known
+/// here to tell.
+fn fast_forward(conn: &Connection, old: Sha1Id, new: Sha1Id) -> crate::Result<bool> {
+    Ok(Commit::read_from_conn_with_id(conn, old).is_ok() && is_ancestor(conn, old, new)?)
+}
+
+fn short_name(name: &str) -> &str {
+    name.strip_prefix(BRANCH_PREFIX).unwrap_or(name)
+}
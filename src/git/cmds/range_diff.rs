@@ -0,0 +1,35 @@
+use anyhow::anyhow;
+
+use crate::{
+    cli::RangeDiffArgs,
+    git::{
+        range_diff::{range_diff, DEFAULT_CREATION_FACTOR},
+        revision::resolve_commits,
+        utils::{find_gitqlite_root, get_gitqlite_connection},
+    },
+};
+
+pub fn do_range_diff(arg: RangeDiffArgs) -> crate::Result<()> {
+    find_gitqlite_root(std::env::current_dir()?)?;
+    let conn = get_gitqlite_connection()?;
+
+    for range in [&arg.old, &arg.new] {
+        if !range.contains("..") {
+            return Err(anyhow!("fatal: '{}' is not a commit range", range));
+        }
+    }
+    let old = resolve_commits(&conn, &arg.old)?;
+    let new = resolve_commits(&conn, &arg.new)?;
+
+    print!(
+        "{}",
+        range_diff(
+            &conn,
+            &old,
+            &new,
+            arg.creation_factor.unwrap_or(DEFAULT_CREATION_FACTOR),
+            arg.no_patch
+        )?
+    );
+    Ok(())
+}
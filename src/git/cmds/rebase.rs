@@ -0,0 +1,223 @@
+use anyhow::anyhow;
+
+use crate::{
+    cli::RebaseArgs,
+    git::{
+        constants,
+        editor::edit_text,
+        model::{
+            CherryPickState, Commit, Head, Index, MergeHead, RebaseAction, RebaseState, RebaseStep,
+        },
+        progress::Progress,
+        protect::check_rewrite,
+        rebase::{
+            self, commits_to_rebase, format_todo, parse_todo, short_id, skip_applied, todo_path,
+        },
+        revision::resolve_revision,
+        stash::{create_stash, StashOptions},
+        tree::{flatten_tree, index_view},
+        utils::{find_gitqlite_root, get_gitqlite_connection},
+        worktree::{modified_files, reset_hard},
+    },
+    repo::{
+        config::{ConfigSource, GitConfig},
+        db::HashAlgorithm,
+    },
+};
+
+pub fn do_rebase(arg: RebaseArgs) -> crate::Result<()> {
+    let RebaseArgs {
+        upstream,
+        onto,
+        interactive,
+        autostash,
+        no_autostash,
+        reapply_cherry_picks,
+        continue_,
+        skip,
+        abort,
+        force_protected,
+        quiet,
+    } = arg;
+
+    let repo_root = find_gitqlite_root(std::env::current_dir()?)?;
+    let gitqlite_home = repo_root.join(constants::GITQLITE_DIRECTORY_PREFIX);
+    let conn = get_gitqlite_connection()?;
+    let config = GitConfig::load(&gitqlite_home)?;
+
+    let state = RebaseState::read_from_conn(&conn)?;
+
+    if continue_ || skip || abort {
+        let state = state.ok_or_else(|| anyhow!("fatal: No rebase in progress?"))?;
+        let mut progress = Progress::new(quiet);
+        let result = if continue_ {
+            rebase::resume(&conn, &repo_root, &config, state, &mut progress)
+        } else if skip {
+            rebase::skip(&conn, &repo_root, &config, state, &mut progress)
+        } else {
+            rebase::abort(&conn, &repo_root, state, &mut progress)
+        };
+        progress.finish();
+        return result;
+    }
+
+    if state.is_some() {
+        return Err(anyhow!(
+            "fatal: It seems that there is already a rebase in progress.\nUse \"gitqlite rebase --continue\", \"gitqlite rebase --skip\" or \"gitqlite rebase --abort\"."
+        ));
+    }
+    if CherryPickState::read_from_conn(&conn)?.is_some() {
+        return Err(anyhow!(
+            "error: a cherry-pick is in progress\nhint: try \"gitqlite cherry-pick (--continue | --skip | --abort)\""
+        ));
+    }
+    let mut index = Index::read_from_conn(&conn)?;
+    if index.has_conflicts() || MergeHead::read_from_conn(&conn)?.is_some() {
+        return Err(anyhow!(
+            "fatal: You have not concluded your merge (MERGE_HEAD exists)."
+        ));
+    }
+
+    let head = Head::read_from_conn(&conn)?;
+    let head_id = head
+        .resolve(&conn)?
+        .ok_or_else(|| anyhow!("fatal: You do not have any commits yet to rebase"))?;
+    // Checked by clap: upstream is required when not continuing a rebase
+    let upstream = upstream.unwrap_or_default();
+    let upstream_id = resolve_revision(&conn, &upstream)?;
+    let onto = match onto {
+        Some(onto) => resolve_revision(&conn, &onto)?,
+        None => upstream_id,
+    };
+
+    let head_name = match &head {
+        Head::Branch(name) => Some(name.clone()),
+        Head::Commit(_) => None,
+    };
+
+    let commits = commits_to_rebase(&conn, upstream_id, head_id)?;
+    // The commits to replay already sit on the new base
+    let base_id = match commits.first() {
+        Some(first) => Commit::read_from_conn_with_id(&conn, *first)?
+            .parent_ids
+            .first()
+            .copied(),
+        None => Some(head_id),
+    };
+    if !interactive && base_id == Some(onto) {
+        match &head_name {
+            Some(name) => println!(
+                "Current branch {} is up to date.",
+                name.strip_prefix(constants::BRANCH_PREFIX).unwrap_or(name)
+            ),
+            None => println!("HEAD is up to date."),
+        }
+        return Ok(());
+    }
+
+    if let Some(name) = &head_name {
+        let expected = force_protected
+            .map(|rev| resolve_revision(&conn, &rev))
+            .transpose()?;
+        let branch = name.strip_prefix(constants::BRANCH_PREFIX).unwrap_or(name);
+        check_rewrite(&config, branch, Some(head_id), expected)?;
+    }
+
+    // Commits whose change upstream already has would replay as empty commits
+    let commits = if reapply_cherry_picks {
+        commits
+    } else {
+        let (kept, skipped) = skip_applied(&conn, upstream_id, head_id, commits)?;
+        for id in skipped {
+            println!(
+                "warning: skipped previously applied commit {}",
+                short_id(id)
+            );
+        }
+        kept
+    };
+
+    let mut todo: Vec<RebaseStep> = commits
+        .into_iter()
+        .map(|commit_id| RebaseStep {
+            action: RebaseAction::Pick,
+            commit_id,
+        })
+        .collect();
+
+    if interactive {
+        let title = format!(
+            "Rebase {}..{} onto {}",
+            short_id(upstream_id),
+            short_id(head_id),
+            short_id(onto)
+        );
+        let text = format_todo(&conn, &todo, &title)?;
+        todo = parse_todo(&conn, &edit_text(&config, todo_path(&repo_root), &text)?)?;
+        if todo.is_empty() {
+            println!("Nothing to do");
+            return Ok(());
+        }
+    }
+
+    // Rebasing rewrites the working tree, so local changes are either put aside in the
+    // stash or refused
+    let autostash = match (autostash, no_autostash) {
+        (true, _) => true,
+        (_, true) => false,
+        _ => config
+            .get_bool("rebase.autoStash", ConfigSource::All)?
+            .unwrap_or(false),
+    };
+    let stash_id = if autostash {
+        let stash_id = create_stash(
+            &conn,
+            &repo_root,
+            &config,
+            "autostash",
+            StashOptions::default(),
+        )?;
+        if let Some(stash_id) = stash_id {
+            println!("Created autostash: {}", short_id(stash_id));
+        }
+        stash_id
+    } else {
+        let head_view = flatten_tree(
+            &conn,
+            Commit::read_from_conn_with_id(&conn, head_id)?.tree_id,
+        )?;
+        if index_view(&index) != head_view {
+            return Err(anyhow!(
+                "error: cannot rebase: Your index contains uncommitted changes.\nPlease commit or stash them."
+            ));
+        }
+        if !modified_files(&repo_root, &index, HashAlgorithm::read(&conn)?)?.is_empty() {
+            return Err(anyhow!(
+                "error: cannot rebase: You have unstaged changes.\nPlease commit or stash them."
+            ));
+        }
+        None
+    };
+
+    let state = RebaseState {
+        head_name,
+        orig_head: head_id,
+        onto,
+        todo,
+        conflicted: None,
+        autostash: stash_id,
+    };
+    state.persist(&conn)?;
+
+    // Replay the commits on a detached HEAD starting from the new base
+    let onto_view = flatten_tree(&conn, Commit::read_from_conn_with_id(&conn, onto)?.tree_id)?;
+    let mut progress = Progress::new(quiet);
+    reset_hard(&conn, &repo_root, &mut index, &onto_view, &mut progress)?;
+    progress.finish();
+    index.persist(&conn)?;
+    Head::Commit(onto).persist(&conn)?;
+
+    let result = rebase::run(&conn, &repo_root, &config, state, &mut progress);
+    progress.finish();
+    result
+}
@@ -0,0 +1,182 @@
+use anyhow::anyhow;
+use rusqlite::Connection;
+
+use crate::{
+    cli::{RemoteArgs, RemoteCommand},
+    git::{
+        constants::{self, BRANCH_PREFIX},
+        merge::is_ancestor,
+        model::{Commit, Ref, Sha1Id},
+        refspec::map_refs,
+        remote::{branch_upstream, delete_refs, stale_refs, tracking_name, tracking_refspecs},
+        transport::{is_configured_remote, open_transport, push_url, remote_url},
+        utils::{find_gitqlite_root, get_gitqlite_connection},
+    },
+    repo::config::GitConfig,
+};
+
+pub fn do_remote(arg: RemoteArgs) -> crate::Result<()> {
+    let repo_root = find_gitqlite_root(std::env::current_dir()?)?;
+    let gitqlite_home = repo_root.join(constants::GITQLITE_DIRECTORY_PREFIX);
+    let conn = get_gitqlite_connection()?;
+    let config = GitConfig::load(&gitqlite_home)?;
+
+    match arg.command {
+        RemoteCommand::Show { names } => {
+            for name in &names {
+                show_remote(&conn, &config, name)?;
+            }
+            Ok(())
+        }
+        RemoteCommand::Prune { names, dry_run } => {
+            for name in &names {
+                check_remote(&config, name)?;
+                let url = remote_url(&config, name)?;
+                let remote = open_transport(&url, &config)?.ls_refs()?;
+                let stale = stale_refs(&conn, &tracking_refspecs(&config, name)?, &remote)?;
+                if stale.is_empty() {
+                    continue;
+                }
+
+                println!("Pruning {}", name);
+                println!("URL: {}", url);
+                if !dry_run {
+                    delete_refs(&conn, name, &stale)?;
+                }
+                for stale in &stale {
+                    let status = if dry_run { "would prune" } else { "pruned" };
+                    println!(" * [{}] {}", status, tracking_name(&stale.name));
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+fn check_remote(config: &GitConfig, name: &str) -> crate::Result<()> {
+    if !is_configured_remote(config, name)? {
+        return Err(anyhow!("error: No such remote '{}'", name));
+    }
+    Ok(())
+}
+
+/// Print the URLs of the remote, the state of its branches, and the local branches set to
+/// pull from it or push to it
+fn show_remote(conn: &Connection, config: &GitConfig, name: &str) -> crate::Result<()> {
+    check_remote(config, name)?;
+    let url = remote_url(config, name)?;
+    let remote = open_transport(&url, config)?.ls_refs()?;
+
+    println!("* remote {}", name);
+    println!("  Fetch URL: {}", url);
+    println!("  Push  URL: {}", push_url(config, name)?);
+    let head = remote.head.as_deref().map_or("(unknown)", short_branch);
+    println!("  HEAD branch: {}", head);
+
+    // Remote branches, tracked or not yet, then the stale remote-tracking refs
+    let tracking = tracking_refspecs(config, name)?;
+    let names = remote.refs.iter().map(|(name, _)| name.as_str());
+    let mut branches: Vec<(String, String)> = Vec::new();
+    for (src, dst, _) in map_refs(&tracking, names) {
+        let Some(dst) = dst else {
+            continue;
+        };
+        let status = if Ref::read_from_conn_with_name(conn, &dst)?.is_some() {
+            "tracked".to_string()
+        } else {
+            format!(
+                "new (next fetch will store in {})",
+                dst.strip_prefix("refs/")
+                    .unwrap_or(&dst)
+                    .rsplit_once('/')
+                    .map_or("", |(dir, _)| dir)
+            )
+        };
+        branches.push((short_branch(src).to_string(), status));
+    }
+    for stale in stale_refs(conn, &tracking, &remote)? {
+        let src = tracking
+            .iter()
+            .find_map(|refspec| refspec.reverse_map(&stale.name))
+            .unwrap_or(stale.name);
+        branches.push((
+            short_branch(&src).to_string(),
+            "stale (use 'gitqlite remote prune' to remove)".to_string(),
+        ));
+    }
+    branches.sort();
+    print_section("Remote branch", "Remote branches", branches);
+
+    // Local branches following a branch of this remote pull from it and push to it
+    let mut pulls = Vec::new();
+    let mut pushes = Vec::new();
+    for local in Ref::read_all(conn)? {
+        let Some(branch) = local.name.strip_prefix(BRANCH_PREFIX) else {
+            continue;
+        };
+        let Some((upstream_remote, merge)) = branch_upstream(config, branch)? else {
+            continue;
+        };
+        if upstream_remote != name {
+            continue;
+        }
+        pulls.push((branch.to_string(), format!("merges with remote {}", merge)));
+        let remote_id = remote.get(&format!("{}{}", BRANCH_PREFIX, merge));
+        pushes.push((
+            branch.to_string(),
+            format!(
+                "pushes to {} ({})",
+                merge,
+                push_status(conn, local.commit_id, remote_id)?
+            ),
+        ));
+    }
+    print_section(
+        "Local branch configured for 'gitqlite pull'",
+        "Local branches configured for 'gitqlite pull'",
+        pulls,
+    );
+    print_section(
+        "Local ref configured for 'gitqlite push'",
+        "Local refs configured for 'gitqlite push'",
+        pushes,
+    );
+    Ok(())
+}
+
+/// How pushing the local commit would move the remote branch
+fn push_status(
+    conn: &Connection,
+    local: Sha1Id,
+    remote: Option<Sha1Id>,
+) -> crate::Result<&'static str> {
+    let Some(remote) = remote else {
+        return Ok("create");
+    };
+    if remote == local {
+        Ok("up to date")
+    } else if Commit::read_from_conn_with_id(conn, remote).is_ok()
+        && is_ancestor(conn, remote, local)?
+    {
+        Ok("fast-forwardable")
+    } else {
+        Ok("local out of date")
+    }
+}
+
+/// Print a titled list of names and descriptions, the names aligned, unless it is empty
+fn print_section(singular: &str, plural: &str, lines: Vec<(String, String)>) {
+    if lines.is_empty() {
+        return;
+    }
+    println!("  {}:", if lines.len() == 1 { singular } else { plural });
+    let width = lines.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+    for (name, description) in lines {
+        println!("    {:<width$} {}", name, description, width = width);
+    }
+}
+
+/// Name of a remote branch without the branch prefix
+fn short_branch(name: &str) -> &str {
+    name.strip_prefix(BRANCH_PREFIX).unwrap_or(name)
+}
@@ -0,0 +1,107 @@
+use anyhow::anyhow;
+
+use crate::{
+    cli::ResetArgs,
+    git::{
+        constants::{BRANCH_PREFIX, GITQLITE_DIRECTORY_PREFIX},
+        merge::is_ancestor,
+        model::{Commit, Head, Index, MergeHead},
+        progress::Progress,
+        protect::check_rewrite,
+        rebase::{short_id, summary},
+        revision::resolve_revision,
+        tree::flatten_tree,
+        utils::{find_gitqlite_root, get_gitqlite_connection, WriteTransaction},
+        worktree::{modified_files, reset_hard, reset_index},
+    },
+    repo::{
+        config::GitConfig,
+        db::{HashAlgorithm, OldValue, RefTransaction},
+    },
+};
+
+pub fn do_reset(arg: ResetArgs) -> crate::Result<()> {
+    let ResetArgs {
+        soft,
+        mixed: _,
+        hard,
+        commit,
+        force_protected,
+        quiet,
+    } = arg;
+    let repo_root = find_gitqlite_root(std::env::current_dir()?)?;
+    let conn = get_gitqlite_connection()?;
+    let config = GitConfig::load(repo_root.join(GITQLITE_DIRECTORY_PREFIX))?;
+
+    let name = commit.unwrap_or_else(|| "HEAD".to_string());
+    let target_id = resolve_revision(&conn, &name)?;
+    let head = Head::read_from_conn(&conn)?;
+    let head_id = head.resolve(&conn)?;
+    if soft && MergeHead::read_from_conn(&conn)?.is_some() {
+        return Err(anyhow!(
+            "fatal: Cannot do a soft reset in the middle of a merge."
+        ));
+    }
+
+    if let (Head::Branch(branch), Some(head_id)) = (&head, head_id) {
+        // Moving a branch forward never loses commits, even when it is protected
+        if !is_ancestor(&conn, head_id, target_id)? {
+            let expected = force_protected
+                .map(|rev| resolve_revision(&conn, &rev))
+                .transpose()?;
+            let branch = branch.strip_prefix(BRANCH_PREFIX).unwrap_or(branch);
+            check_rewrite(&config, branch, Some(head_id), expected)?;
+        }
+    }
+
+    let commit = Commit::read_from_conn_with_id(&conn, target_id)?;
+    let target = flatten_tree(&conn, commit.tree_id)?;
+    let mut index = Index::read_from_conn(&conn)?;
+
+    let txn = WriteTransaction::new(&conn)?;
+    match &head {
+        Head::Branch(branch) => {
+            let old = match head_id {
+                Some(head_id) => OldValue::Is(head_id.into()),
+                None => OldValue::Missing,
+            };
+            let mut transaction = RefTransaction::new(format!("reset: moving to {}", name));
+            transaction.update(branch.clone(), old, target_id.into());
+            transaction.commit(&txn)?;
+        }
+        Head::Commit(_) => Head::Commit(target_id).persist(&txn)?,
+    }
+    let mut progress = Progress::new(quiet);
+    if !soft {
+        if hard {
+            reset_hard(&txn, &repo_root, &mut index, &target, &mut progress)?;
+        } else {
+            reset_index(&mut index, &target);
+        }
+        index.persist(&txn)?;
+        // The merge being concluded is abandoned with the index it was recorded in
+        MergeHead::clear(&txn)?;
+    }
+    txn.commit()?;
+    progress.finish();
+
+    if quiet {
+        return Ok(());
+    }
+    if hard {
+        println!(
+            "HEAD is now at {} {}",
+            short_id(target_id),
+            summary(&commit.message)
+        );
+    } else if !soft {
+        let modified = modified_files(&repo_root, &index, HashAlgorithm::read(&conn)?)?;
+        if !modified.is_empty() {
+            println!("Unstaged changes after reset:");
+            for name in modified {
+                println!("M\t{}", name);
+            }
+        }
+    }
+    Ok(())
+}
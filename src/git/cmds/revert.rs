@@ -0,0 +1,109 @@
+use anyhow::anyhow;
+
+use crate::{
+    cli::RevertArgs,
+    git::{
+        cmds::commit::commit_tree,
+        constants,
+        merge::revert_commit,
+        model::{CherryPickState, Commit, Head, Index, MergeHead, RebaseState},
+        rebase::{short_id, summary},
+        revision::resolve_revision,
+        tree::{flatten_tree, index_view, write_tree},
+        utils::{find_gitqlite_root, get_gitqlite_connection, WriteTransaction},
+    },
+    repo::config::GitConfig,
+};
+
+pub fn do_revert(arg: RevertArgs) -> crate::Result<()> {
+    let repo_root = find_gitqlite_root(std::env::current_dir()?)?;
+    let gitqlite_home = repo_root.join(constants::GITQLITE_DIRECTORY_PREFIX);
+    let conn = get_gitqlite_connection()?;
+    let config = GitConfig::load(&gitqlite_home)?;
+
+    if CherryPickState::read_from_conn(&conn)?.is_some() {
+        return Err(anyhow!(
+            "error: a cherry-pick is in progress\nhint: try \"gitqlite cherry-pick (--continue | --skip | --abort)\""
+        ));
+    }
+    if RebaseState::read_from_conn(&conn)?.is_some() {
+        return Err(anyhow!(
+            "error: a rebase is in progress\nhint: try \"gitqlite rebase (--continue | --skip | --abort)\""
+        ));
+    }
+    let mut index = Index::read_from_conn(&conn)?;
+    if index.has_conflicts() || MergeHead::read_from_conn(&conn)?.is_some() {
+        return Err(anyhow!(
+            "fatal: You have not concluded your merge (MERGE_HEAD exists)."
+        ));
+    }
+
+    let head = Head::read_from_conn(&conn)?;
+    let head_id = head
+        .resolve(&conn)?
+        .ok_or_else(|| anyhow!("fatal: You do not have any commits yet to revert onto"))?;
+    let head_tree = Commit::read_from_conn_with_id(&conn, head_id)?.tree_id;
+    if index_view(&index) != flatten_tree(&conn, head_tree)? {
+        return Err(anyhow!(
+            "error: your local changes would be overwritten by revert.\nhint: commit your changes or stash them to proceed."
+        ));
+    }
+
+    let commit_id = resolve_revision(&conn, &arg.commit)?;
+    let commit = Commit::read_from_conn_with_id(&conn, commit_id)?;
+    if commit.parent_ids.len() > 1 {
+        return Err(anyhow!(
+            "error: commit {} is a merge, which revert does not support",
+            commit_id
+        ));
+    }
+    let message = format!(
+        "Revert \"{}\"\n\nThis reverts commit {}.\n",
+        summary(&commit.message),
+        commit_id
+    );
+
+    let label = format!(
+        "parent of {} ({})",
+        short_id(commit_id),
+        summary(&commit.message)
+    );
+    let outcome = revert_commit(&conn, &repo_root, &mut index, &commit, head_id, &label)?;
+    index.persist(&conn)?;
+
+    if !outcome.conflicts.is_empty() {
+        for conflict in &outcome.conflicts {
+            println!("{}", conflict.describe(("HEAD", &label)));
+        }
+        return Err(anyhow!(
+            "error: could not revert {}... {}\nhint: After resolving the conflicts, mark them with \"gitqlite add <paths>\", then commit the result with \"gitqlite commit -m <message>\".",
+            short_id(commit_id),
+            summary(&commit.message)
+        ));
+    }
+
+    let tree_id = write_tree(&conn, &outcome.tree)?;
+    if tree_id == head_tree {
+        return Err(anyhow!(
+            "error: reverting {} changes nothing on top of HEAD",
+            short_id(commit_id)
+        ));
+    }
+
+    let txn = WriteTransaction::new(&conn)?;
+    let new_commit = commit_tree(&txn, &config, tree_id, vec![head_id], message, None)?;
+    head.update(&txn, new_commit.commit_id)?;
+    txn.commit()?;
+
+    let branch = match &head {
+        Head::Branch(name) => name.strip_prefix(constants::BRANCH_PREFIX).unwrap_or(name),
+        Head::Commit(_) => "detached HEAD",
+    };
+    println!(
+        "[{} {}] {}",
+        branch,
+        short_id(new_commit.commit_id),
+        summary(&new_commit.message)
+    );
+    Ok(())
+}
@@ -1,8 +1,11 @@
+use anyhow::anyhow;
+
 use crate::{
     cli::RmArgs,
     git::{
         model::Index,
-        utils::{find_gitqlite_root, get_gitqlite_connection},
+        utils::{find_gitqlite_root, get_gitqlite_connection, with_transaction},
+        worktree::{precompose_unicode, remove_worktree_file, worktree_name},
     },
 };
 
@@ -10,14 +13,29 @@ pub fn do_rm(arg: RmArgs) -> crate::Result<()> {
     let RmArgs { path, cached } = arg;
 
     let repo_root = find_gitqlite_root(std::env::current_dir()?)?;
-    let conn = get_gitqlite_connection()?;
+    let mut conn = get_gitqlite_connection()?;
 
-    let mut index = Index::read_from_conn(&conn)?;
-    if let Some(entry) = index.remove(&path, &repo_root, !cached)? {
-        println!("rm {}", entry.name);
-    }
+    let path = dunce::canonicalize(path)?;
+    let name = worktree_name(&repo_root, &path, precompose_unicode(&repo_root)?).map_err(|_| {
+        anyhow!(
+            "Path {} is not inside the current gitqlite repository",
+            path.display()
+        )
+    })?;
 
-    index.persist(&conn)?;
+    let removed = with_transaction(&mut conn, |tx| {
+        if Index::read_entries_with_name(tx, &name)?.is_empty() {
+            return Ok(false);
+        }
+        Index::unstage_name(tx, &name)?;
+        if !cached {
+            remove_worktree_file(&repo_root, &name)?;
+        }
+        Ok(true)
+    })?;
+    if removed {
+        println!("rm {}", name);
+    }
 
     Ok(())
 }
@@ -0,0 +1,58 @@
+use std::io::IsTerminal;
+
+use crate::{
+    cli::{SearchArgs, SearchFormat},
+    git::{
+        rebase::short_id,
+        search::{parse_day, search_commit_messages, SearchQuery},
+        utils::{find_gitqlite_root, get_gitqlite_connection},
+    },
+};
+
+const COLOR_MATCH: &str = "\x1b[1;31m";
+const COLOR_RESET: &str = "\x1b[m";
+/// Marks the matches where colors are not shown
+const PLAIN_MATCH: &str = "*";
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+pub fn do_search(arg: SearchArgs) -> crate::Result<()> {
+    find_gitqlite_root(std::env::current_dir()?)?;
+    let conn = get_gitqlite_connection()?;
+
+    let query = SearchQuery {
+        text: arg.query,
+        author: arg.author,
+        since: arg.since.as_deref().map(parse_day).transpose()?,
+        // The whole day of --until is included
+        until: arg
+            .until
+            .as_deref()
+            .map(|day| Ok::<_, anyhow::Error>(parse_day(day)? + SECONDS_PER_DAY))
+            .transpose()?,
+    };
+    let markers = match arg.format {
+        SearchFormat::Oneline if std::io::stdout().is_terminal() => (COLOR_MATCH, COLOR_RESET),
+        _ => (PLAIN_MATCH, PLAIN_MATCH),
+    };
+    let hits = search_commit_messages(&conn, &query, markers)?;
+
+    match arg.format {
+        SearchFormat::Oneline => {
+            for hit in hits {
+                let date = chrono::DateTime::from_timestamp(hit.created_at, 0)
+                    .map(|time| time.format("%Y-%m-%d").to_string())
+                    .unwrap_or_else(|| hit.created_at.to_string());
+                let snippet = hit.snippet.split_whitespace().collect::<Vec<_>>().join(" ");
+                println!(
+                    "{} {} {}: {}",
+                    short_id(hit.commit_id),
+                    date,
+                    hit.author_name,
+                    snippet
+                );
+            }
+        }
+        SearchFormat::Json => println!("{}", serde_json::to_string_pretty(&hits)?),
+    }
+    Ok(())
+}
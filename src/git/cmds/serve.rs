@@ -0,0 +1,16 @@
+use std::{io, path::PathBuf};
+
+use anyhow::anyhow;
+
+use crate::{cli::ServeArgs, git::protocol::serve_repository};
+
+pub fn do_serve(arg: ServeArgs) -> crate::Result<()> {
+    let path = match arg.directory.strip_prefix("~/") {
+        Some(relative) => dirs::home_dir()
+            .ok_or_else(|| anyhow!("fatal: cannot find the home directory"))?
+            .join(relative),
+        None => PathBuf::from(&arg.directory),
+    };
+
+    serve_repository(&mut io::stdin().lock(), &mut io::stdout().lock(), &path)
+}
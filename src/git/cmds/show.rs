@@ -0,0 +1,99 @@
+use std::io::{self, Write};
+
+use chrono::DateTime;
+use encoding_rs::Encoding;
+use rusqlite::Connection;
+
+use crate::{
+    cli::ShowArgs,
+    git::{
+        constants,
+        diff::{diff_commit, render, DiffOutput},
+        encoding::log_output_encoding,
+        model::{AnnotatedTag, Blob, Commit, Sha1Id, Tree, TreeEntryType},
+        revision::{resolve_path_object, resolve_revision, resolve_tag},
+        signature::{split_signature, verify_tag},
+        utils::{find_gitqlite_root, get_gitqlite_connection},
+    },
+    repo::config::GitConfig,
+};
+
+use super::log::print_commit_header;
+
+pub fn do_show(arg: ShowArgs) -> crate::Result<()> {
+    let repo_root = find_gitqlite_root(std::env::current_dir()?)?;
+    let conn = get_gitqlite_connection()?;
+    let config = GitConfig::load(repo_root.join(constants::GITQLITE_DIRECTORY_PREFIX))?;
+    let encoding = log_output_encoding(&config)?;
+
+    let objects = if arg.objects.is_empty() {
+        vec!["HEAD".to_string()]
+    } else {
+        arg.objects
+    };
+    for name in &objects {
+        match resolve_path_object(&conn, name)? {
+            Some((TreeEntryType::Blob, id)) => {
+                io::stdout().write_all(&Blob::read_from_conn_with_id(&conn, id)?.data)?;
+            }
+            Some((TreeEntryType::Tree, id)) => show_tree(&conn, name, id)?,
+            None => match resolve_tag(&conn, name)? {
+                Some(tag) => {
+                    show_tag(&config, &tag)?;
+                    show_commit(&conn, tag.object_id.into(), encoding)?;
+                }
+                None => show_commit(&conn, resolve_revision(&conn, name)?, encoding)?,
+            },
+        }
+    }
+    Ok(())
+}
+
+/// Print the names of the entries of a tree, directories with a trailing slash
+fn show_tree(conn: &Connection, name: &str, tree_id: Sha1Id) -> crate::Result<()> {
+    println!("tree {}", name);
+    println!();
+    for entry in Tree::read_from_conn_with_id(conn, tree_id)?.entries {
+        match entry.type_ {
+            TreeEntryType::Tree => println!("{}/", entry.name),
+            TreeEntryType::Blob => println!("{}", entry.name),
+        }
+    }
+    println!();
+    Ok(())
+}
+
+/// Print the annotation of a tag, and whether its signature is good if it is signed
+fn show_tag(config: &GitConfig, tag: &AnnotatedTag) -> crate::Result<()> {
+    println!("tag {}", tag.name);
+    println!("Tagger: {} <{}>", tag.tagger_name, tag.tagger_email);
+    if let Some(date) = DateTime::from_timestamp(tag.tagged_at, 0) {
+        println!("Date:   {}", date.format("%a %b %-d %H:%M:%S %Y +0000"));
+    }
+    println!();
+    let (message, signature) = split_signature(&tag.message);
+    println!("{}", message.trim_end());
+    println!();
+    if signature.is_some() {
+        println!("{}", verify_tag(config, tag)?);
+        println!();
+    }
+    Ok(())
+}
+
+/// Print a commit along with the diff to its parent. Merges are shown without a diff.
+fn show_commit(
+    conn: &Connection,
+    commit_id: Sha1Id,
+    encoding: &'static Encoding,
+) -> crate::Result<()> {
+    let commit = Commit::read_from_conn_with_id(conn, commit_id)?;
+    print_commit_header(&commit, None, encoding)?;
+    if commit.parent_ids.len() <= 1 {
+        print!(
+            "{}",
+            render(&diff_commit(conn, &commit)?, DiffOutput::default())?
+        );
+    }
+    Ok(())
+}
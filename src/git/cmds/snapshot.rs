@@ -0,0 +1,54 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::anyhow;
+
+use crate::{
+    cli::{SnapshotArgs, SnapshotCommand},
+    git::{
+        prune::format_age,
+        snapshot::{create_snapshot, delete_snapshot, read_snapshot, restore_snapshot, snapshots},
+        utils::{find_gitqlite_root, get_gitqlite_connection, with_transaction},
+    },
+};
+
+pub fn do_snapshot(arg: SnapshotArgs) -> crate::Result<()> {
+    find_gitqlite_root(std::env::current_dir()?)?;
+    let mut conn = get_gitqlite_connection()?;
+
+    match arg.command {
+        SnapshotCommand::Create { name, force } => {
+            let id = create_snapshot(&conn, &name, force)?;
+            println!("Created snapshot {} {}", name, id);
+        }
+        SnapshotCommand::Restore { name } => {
+            let Some((id, state)) = read_snapshot(&conn, &name)? else {
+                return Err(anyhow!("fatal: no snapshot named '{}'", name));
+            };
+            with_transaction(&mut conn, |tx| restore_snapshot(tx, &name, &state))?;
+            println!("Restored snapshot {} {}", name, id);
+        }
+        SnapshotCommand::List => {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+            for snapshot in snapshots(&conn)? {
+                println!(
+                    "{} {} ({} old)",
+                    snapshot.snapshot_id,
+                    snapshot.name,
+                    format_age(snapshot.created_at, now)
+                );
+            }
+        }
+        SnapshotCommand::Delete { names } => {
+            with_transaction(&mut conn, |tx| {
+                for name in &names {
+                    if !delete_snapshot(tx, name)? {
+                        return Err(anyhow!("error: no snapshot named '{}'", name));
+                    }
+                    println!("Deleted snapshot {}", name);
+                }
+                Ok(())
+            })?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,179 @@
+use std::path::Path;
+
+use anyhow::anyhow;
+use rusqlite::Connection;
+
+use crate::{
+    cli::{StashArgs, StashCommand},
+    git::{
+        constants,
+        diff::{diff_views, render, ContentSource, DiffOutput},
+        model::{Commit, Index, StashEntry},
+        progress::Progress,
+        rebase::summary,
+        stash::{apply_stash, create_stash, remove_stashed_changes, StashOptions, STASH_LABELS},
+        tree::flatten_tree,
+        utils::{find_gitqlite_root, get_gitqlite_connection},
+    },
+    repo::config::GitConfig,
+};
+
+use super::diff::repo_relative_paths;
+
+pub fn do_stash(arg: StashArgs) -> crate::Result<()> {
+    let repo_root = find_gitqlite_root(std::env::current_dir()?)?;
+    let gitqlite_home = repo_root.join(constants::GITQLITE_DIRECTORY_PREFIX);
+    let conn = get_gitqlite_connection()?;
+    let config = GitConfig::load(&gitqlite_home)?;
+
+    let command = arg.command.unwrap_or(StashCommand::Push {
+        include_untracked: false,
+        message: "WIP".to_string(),
+        paths: Vec::new(),
+    });
+    match command {
+        StashCommand::Push {
+            include_untracked,
+            message,
+            paths,
+        } => {
+            let paths = repo_relative_paths(&repo_root, &paths)?;
+            let options = StashOptions {
+                include_untracked,
+                paths: &paths,
+            };
+            let Some(stash_id) = create_stash(&conn, &repo_root, &config, &message, options)?
+            else {
+                println!("No local changes to save");
+                return Ok(());
+            };
+
+            let message = Commit::read_from_conn_with_id(&conn, stash_id)?.message;
+            let mut index = Index::read_from_conn(&conn)?;
+            remove_stashed_changes(&conn, &repo_root, &mut index, stash_id)?;
+            index.persist(&conn)?;
+            StashEntry {
+                commit_id: stash_id,
+                message: message.clone(),
+            }
+            .push(&conn)?;
+            println!("Saved working directory and index state {}", message);
+            Ok(())
+        }
+        StashCommand::Show {
+            stash,
+            patch,
+            include_untracked,
+        } => {
+            let (_, entry) = find_stash(&conn, stash.as_deref())?;
+            let stash = Commit::read_from_conn_with_id(&conn, entry.commit_id)?;
+            let base_id = stash
+                .parent_ids
+                .first()
+                .ok_or_else(|| anyhow!("fatal: {} is not a stash commit", entry.commit_id))?;
+            let base = flatten_tree(
+                &conn,
+                Commit::read_from_conn_with_id(&conn, *base_id)?.tree_id,
+            )?;
+            let mut stashed = flatten_tree(&conn, stash.tree_id)?;
+            if let (true, Some(untracked_id)) = (include_untracked, stash.parent_ids.get(2)) {
+                let untracked = Commit::read_from_conn_with_id(&conn, *untracked_id)?;
+                stashed.extend(flatten_tree(&conn, untracked.tree_id)?);
+            }
+
+            let changes = diff_views(
+                &conn,
+                &base,
+                ContentSource::Database,
+                &stashed,
+                ContentSource::Database,
+            )?;
+            let output = DiffOutput {
+                patch,
+                stat: !patch,
+                ..DiffOutput::default()
+            };
+            print!("{}", render(&changes, output)?);
+            Ok(())
+        }
+        StashCommand::List => {
+            for (position, entry) in StashEntry::read_all(&conn)?.iter().enumerate() {
+                println!("stash@{{{}}}: {}", position, summary(&entry.message));
+            }
+            Ok(())
+        }
+        StashCommand::Apply { stash } => {
+            let (_, entry) = find_stash(&conn, stash.as_deref())?;
+            apply_entry(&conn, &repo_root, &entry, arg.quiet)
+        }
+        StashCommand::Pop { stash } => {
+            let (position, entry) = find_stash(&conn, stash.as_deref())?;
+            apply_entry(&conn, &repo_root, &entry, arg.quiet)?;
+            drop_entry(&conn, position, &entry)
+        }
+        StashCommand::Drop { stash } => {
+            let (position, entry) = find_stash(&conn, stash.as_deref())?;
+            drop_entry(&conn, position, &entry)
+        }
+    }
+}
+
+/// Apply the changes of a stash entry to the index and working tree. Conflicts are left in
+/// them as for a merge, and reported as an error so that the entry is kept.
+fn apply_entry(
+    conn: &Connection,
+    repo_root: &Path,
+    entry: &StashEntry,
+    quiet: bool,
+) -> crate::Result<()> {
+    let mut index = Index::read_from_conn(conn)?;
+    if index.has_conflicts() {
+        return Err(anyhow!(
+            "error: you need to resolve your current index first
+{}",
+            index.conflicted_names().join("\n")
+        ));
+    }
+
+    let mut progress = Progress::new(quiet);
+    let outcome = apply_stash(conn, repo_root, &mut index, entry.commit_id, &mut progress)?;
+    index.persist(conn)?;
+    progress.finish();
+
+    if !outcome.conflicts.is_empty() {
+        for conflict in &outcome.conflicts {
+            println!("{}", conflict.describe(STASH_LABELS));
+        }
+        return Err(anyhow!(
+            "error: conflicts in the stashed changes\nhint: The stash entry is kept in case you need it again."
+        ));
+    }
+    Ok(())
+}
+
+fn drop_entry(conn: &Connection, position: usize, entry: &StashEntry) -> crate::Result<()> {
+    StashEntry::drop(conn, position)?;
+    println!("Dropped stash@{{{}}} ({})", position, entry.commit_id);
+    Ok(())
+}
+
+/// The stash entry named `stash@{<n>}` or `<n>` and its position, the most recent one if None
+fn find_stash(conn: &Connection, name: Option<&str>) -> crate::Result<(usize, StashEntry)> {
+    let name = name.unwrap_or("0");
+    let position = name
+        .strip_prefix("stash@{")
+        .and_then(|rest| rest.strip_suffix('}'))
+        .unwrap_or(name);
+    let position: usize = position
+        .parse()
+        .map_err(|_| anyhow!("fatal: '{}' is not a stash reference", name))?;
+
+    let mut entries = StashEntry::read_all(conn)?;
+    if entries.is_empty() {
+        return Err(anyhow!("fatal: No stash entries found."));
+    }
+    if position >= entries.len() {
+        return Err(anyhow!("fatal: stash@{{{}}} does not exist", position));
+    }
+    Ok((position, entries.swap_remove(position)))
+}
@@ -0,0 +1,19 @@
+use crate::{
+    cli::StatsArgs,
+    git::{
+        revision::{commits_between, resolve_revision},
+        stats::{format_stats, history_stats},
+        utils::{find_gitqlite_root, get_gitqlite_connection},
+    },
+};
+
+pub fn do_stats(arg: StatsArgs) -> crate::Result<()> {
+    find_gitqlite_root(std::env::current_dir()?)?;
+    let conn = get_gitqlite_connection()?;
+
+    let tip = resolve_revision(&conn, arg.revision.as_deref().unwrap_or("HEAD"))?;
+    let commits = commits_between(&conn, None, tip)?;
+    let stats = history_stats(&conn, &commits, arg.history)?;
+    print!("{}", format_stats(&stats, arg.format)?);
+    Ok(())
+}
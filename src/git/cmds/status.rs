@@ -7,29 +7,34 @@ use std::{
 
 use anyhow::Ok;
 use rusqlite::Connection;
-use sha1::Digest;
 
 use crate::{
     cli::StatusArgs,
     git::{
         constants,
         files::GitqliteFileMetadataExt,
+        filters::load_filters,
         ignore::read_gitignore,
-        model::{
-            self, Blob, Commit, Hashable, Head, Index, IndexEntry, Sha1Id, Tree, TreeEntryType,
-        },
+        model::{hash_blob_data, Blob, Commit, Head, Index, IndexEntry, MergeHead, Sha1Id},
+        perf::{self, Category},
+        quote::PathQuoting,
+        tree::{flatten_tree, TreeView},
         utils::{find_gitqlite_root, get_gitqlite_connection},
+        worktree::{precompose_unicode, worktree_name},
     },
+    repo::{config::GitConfig, db::HashAlgorithm},
 };
 
 /// Status command does two things:
 /// 1. Compare the content of the index file with the tree pointed to by the ref of HEAD, which
-/// is shown in the files to be committed section.
+///    is shown in the files to be committed section.
 /// 2. Compare the content of the index file with the current working directory, which is shown in
-/// the files to be addeds. It also collects information about untracked files.
+///    the files to be addeds. It also collects information about untracked files.
 pub fn do_status(_arg: StatusArgs) -> crate::Result<()> {
     let repo_root = find_gitqlite_root(std::env::current_dir()?)?;
     let conn = get_gitqlite_connection()?;
+    let config = GitConfig::load(repo_root.join(constants::GITQLITE_DIRECTORY_PREFIX))?;
+    let quoting = PathQuoting::from_config(&config, false)?;
 
     let head = Head::read_from_conn(&conn)?;
 
@@ -37,25 +42,28 @@ pub fn do_status(_arg: StatusArgs) -> crate::Result<()> {
     print_status_branch(&head);
     println!();
 
-    let index = index_map(Index::read_from_conn(&conn)?);
+    let index = Index::read_from_conn(&conn)?;
+    print_merge_status(&conn, &index, quoting)?;
+
+    let index = index_map(index);
     let head_tree_view = get_head_tree_view(&conn, head)?;
 
     // Print index/head diff (things to commit)
     match head_tree_view {
         Some(head_tree_view) => {
-            print_diff_index_head(&index, &head_tree_view);
+            print_diff_index_head(&index, &head_tree_view, quoting);
         }
         None => {
             println!("No commits yet");
             let dummy_tree_view = BTreeMap::new();
-            print_diff_index_head(&index, &dummy_tree_view);
+            print_diff_index_head(&index, &dummy_tree_view, quoting);
         }
     }
 
     println!();
 
     // Print index/work-tree diff (unstaged changes)
-    print_diff_index_worktree(repo_root, index)?;
+    print_diff_index_worktree(&conn, repo_root, index, quoting)?;
     println!();
 
     Ok(())
@@ -75,17 +83,44 @@ fn print_status_branch(head: &Head) {
     }
 }
 
+fn print_merge_status(conn: &Connection, index: &Index, quoting: PathQuoting) -> crate::Result<()> {
+    let conflicts = index.conflicted_names();
+    if MergeHead::read_from_conn(conn)?.is_none() && conflicts.is_empty() {
+        return Ok(());
+    }
+
+    if conflicts.is_empty() {
+        println!("All conflicts fixed but you are still merging.");
+        println!("  (use \"git commit\" to conclude merge)");
+    } else {
+        println!("You have unmerged paths.");
+        println!("  (fix conflicts and run \"git add\" on each path, then \"git commit\")");
+        println!();
+        println!("Unmerged paths:");
+        for name in conflicts {
+            println!("      both modified: {}", quoting.quote(name));
+        }
+    }
+    println!();
+
+    Ok(())
+}
+
 fn print_diff_index_head(
     index: &BTreeMap<String, IndexEntry>,
-    head_tree_view: &BTreeMap<String, Sha1Id>,
+    head_tree_view: &TreeView,
+    quoting: PathQuoting,
 ) {
     let mut added = Vec::new();
+    let mut intended = Vec::new();
     let mut modified = Vec::new();
     let mut deleted = Vec::new();
 
     for (name, entry) in index {
-        if let Some(old_id) = head_tree_view.get(name) {
-            if *old_id != entry.sha {
+        if entry.flag_intent_to_add && !head_tree_view.contains_key(name) {
+            intended.push(&*entry.name);
+        } else if let Some(old_entry) = head_tree_view.get(name) {
+            if old_entry.id != entry.sha {
                 modified.push(&*entry.name)
             }
         } else {
@@ -93,32 +128,40 @@ fn print_diff_index_head(
         }
     }
 
-    for (file, _) in head_tree_view {
+    for file in head_tree_view.keys() {
         if !index.contains_key(file) {
             deleted.push(file.as_str());
         }
     }
 
-    if added.is_empty() && modified.is_empty() && deleted.is_empty() {
+    if added.is_empty() && intended.is_empty() && modified.is_empty() && deleted.is_empty() {
         println!("no changes added to commit (use \"git add\" and/or \"git commit -a\")")
     } else {
         println!("Changes to be committed:");
         for add in added {
-            println!("      added: {}", add);
+            println!("      added: {}", quoting.quote(add));
+        }
+        // Their content is yet to be added, which the unstaged changes show
+        for intend in intended {
+            println!("      added: {} (intent to add)", quoting.quote(intend));
         }
         for modify in modified {
-            println!("      modified: {}", modify);
+            println!("      modified: {}", quoting.quote(modify));
         }
         for delete in deleted {
-            println!("      deleted: {}", delete);
+            println!("      deleted: {}", quoting.quote(delete));
         }
     }
 }
 
 fn print_diff_index_worktree(
+    conn: &Connection,
     repo_root: impl AsRef<Path>,
     mut index: BTreeMap<String, IndexEntry>,
+    quoting: PathQuoting,
 ) -> crate::Result<()> {
+    // Files whose mtime changed since they were staged, which may have been modified
+    let mut candidates = Vec::new();
     let mut added = Vec::new();
     let mut modified = Vec::new();
     let mut deleted = Vec::new();
@@ -129,6 +172,9 @@ fn print_diff_index_worktree(
         .join(constants::GITQLITE_DIRECTORY_PREFIX);
     let gitignore = read_gitignore(repo_root.as_ref().to_path_buf())?;
 
+    let precompose = precompose_unicode(&repo_root)?;
+
+    let _span = perf::span(Category::FsWalk);
     let mut queue = VecDeque::new();
     queue.push_back(repo_root.as_ref().to_path_buf().clone());
 
@@ -143,10 +189,7 @@ fn print_diff_index_worktree(
                 continue;
             }
 
-            let rel_path = path
-                .strip_prefix(repo_root.as_ref())?
-                .to_string_lossy()
-                .to_string();
+            let rel_path = worktree_name(&repo_root, &path, precompose)?;
 
             if path.is_dir() {
                 queue.push_back(path);
@@ -158,29 +201,39 @@ fn print_diff_index_worktree(
                 continue;
             }
 
-            let entry = index.get(&rel_path).unwrap();
-            let mut f = fs::File::open(&path)?;
-            let metadata = f.metadata()?;
+            let entry = index.remove(&rel_path).unwrap();
+            if entry.mtime != fs::metadata(&path)?.g_mtime() {
+                candidates.push((rel_path, path, entry.sha));
+            }
+        }
+    }
 
-            // Compare metadata first
-            let actual_mtime = metadata.g_mtime();
-            let is_modified = if actual_mtime != entry.mtime {
-                let mut buffer = Vec::with_capacity(metadata.g_fsize() as usize);
+    // Files of another size than their staged blob are modified, which the size of the blobs
+    // tells without reading the files unless filters convert them. The others are hashed once
+    // cleaned, reusing one read buffer.
+    let ids: Vec<Sha1Id> = candidates.iter().map(|(_, _, sha)| *sha).collect();
+    let blob_sizes = Blob::read_sizes(conn, &ids)?;
+    let filters = load_filters(&repo_root)?;
+    let algorithm = HashAlgorithm::read(conn)?;
+    let mut buffer = Vec::new();
+    for (rel_path, path, sha) in candidates {
+        let mut f = fs::File::open(&path)?;
+        let size = f.metadata()?.g_fsize();
+        let is_modified = match blob_sizes.get(&sha) {
+            Some(blob_size) if *blob_size != size && !filters.converts(&rel_path) => true,
+            _ => {
+                buffer.clear();
                 f.read_to_end(&mut buffer)?;
-                let actual_hash = Blob::new(buffer).hash(sha1::Sha1::new());
-                actual_hash != entry.sha
-            } else {
-                false
-            };
-
-            index.remove(&rel_path);
-            if is_modified {
-                modified.push(rel_path);
+                let data = filters.clean(&rel_path, &buffer)?;
+                hash_blob_data(algorithm.hasher(), &data) != sha
             }
+        };
+        if is_modified {
+            modified.push(rel_path);
         }
     }
 
-    for (file, _) in &index {
+    for file in index.keys() {
         deleted.push(&**file);
     }
 
@@ -189,75 +242,36 @@ fn print_diff_index_worktree(
     } else {
         println!("Changes not staged for commit:");
         for modify in modified {
-            println!("      modified: {}", modify);
+            println!("      modified: {}", quoting.quote(&modify));
         }
         for delete in deleted {
-            println!("      deleted: {}", delete);
+            println!("      deleted: {}", quoting.quote(delete));
         }
         println!("Untracked files:");
         for add in added {
-            println!("      {}", add);
+            println!("      {}", quoting.quote(&add));
         }
     }
 
     Ok(())
 }
 
-fn get_head_tree_view(
-    conn: &Connection,
-    head: Head,
-) -> crate::Result<Option<BTreeMap<String, Sha1Id>>> {
-    let root_commit_id = match head {
-        Head::Branch(branch_name) => {
-            let Some(reference) = model::Ref::read_from_conn_with_name(conn, &branch_name)? else {
-                return Ok(None);
-            };
-
-            reference.commit_id
-        }
-        Head::Commit(commit_id) => commit_id,
+fn get_head_tree_view(conn: &Connection, head: Head) -> crate::Result<Option<TreeView>> {
+    let Some(root_commit_id) = head.resolve(conn)? else {
+        return Ok(None);
     };
 
     let root_commit = Commit::read_from_conn_with_id(conn, root_commit_id)?;
-    tree_view(root_commit.tree_id, conn).map(Option::Some)
-}
-
-/// Flatten a tree to a mapping from
-/// full path relative to repo root -> SHA1 hash of the file
-fn tree_view(tree_id: Sha1Id, conn: &Connection) -> crate::Result<BTreeMap<String, Sha1Id>> {
-    let mut view = BTreeMap::new();
-
-    // (current tree, prefix of file names in the current tree)
-    let mut stack = Vec::with_capacity(32);
-    stack.push((tree_id, "".to_string()));
-
-    while let Some((cur_tree_id, prefix)) = stack.pop() {
-        let cur_tree = Tree::read_from_conn_with_id(conn, cur_tree_id)?;
-        for entry in cur_tree.entries {
-            match entry.type_ {
-                TreeEntryType::Blob => {
-                    let blob_full_name = if prefix.is_empty() {
-                        entry.name
-                    } else {
-                        prefix.clone() + &format!("/{}", entry.name)
-                    };
-                    view.insert(blob_full_name, entry.id);
-                }
-                TreeEntryType::Tree => {
-                    let next_prefix = prefix.clone() + &format!("/{}", entry.name);
-                    stack.push((entry.id, next_prefix));
-                }
-            }
-        }
-    }
-
-    Ok(view)
+    flatten_tree(conn, root_commit.tree_id).map(Option::Some)
 }
 
+/// Map of the index entries by name. For an unmerged path our version (stage 2) stands in
+/// for the entry, as that is what HEAD has.
 fn index_map(index: Index) -> BTreeMap<String, IndexEntry> {
     index
         .entries
         .into_iter()
+        .filter(|entry| entry.flag_stage == 0 || entry.flag_stage == 2)
         .map(|entry| (entry.name.clone(), entry))
         .collect()
 }
@@ -0,0 +1,192 @@
+use std::path::Path;
+
+use anyhow::anyhow;
+use rusqlite::Connection;
+
+use crate::{
+    cli::SwitchArgs,
+    git::{
+        constants::BRANCH_PREFIX,
+        merge::{apply_merge, local_changes, MergeOutcome},
+        model::{Commit, Head, Index, Ref, Sha1Id},
+        progress::Progress,
+        rebase::{short_id, summary},
+        revision::resolve_revision,
+        tree::{flatten_tree, TreeView},
+        utils::{find_gitqlite_root, get_gitqlite_connection, WriteTransaction},
+        worktree::reset_hard,
+    },
+};
+
+pub fn do_switch(arg: SwitchArgs) -> crate::Result<()> {
+    let SwitchArgs {
+        branch,
+        orphan,
+        detach,
+        force,
+        quiet,
+    } = arg;
+    let repo_root = find_gitqlite_root(std::env::current_dir()?)?;
+    let conn = get_gitqlite_connection()?;
+    let mut progress = Progress::new(quiet);
+
+    if let Some(name) = orphan {
+        // An orphan branch starts unborn, with nothing tracked
+        let branch = format!("{}{}", BRANCH_PREFIX, name);
+        if Ref::read_from_conn_with_name(&conn, &branch)?.is_some() {
+            return Err(anyhow!("fatal: a branch named '{}' already exists", name));
+        }
+        switch_worktree(
+            &conn,
+            &repo_root,
+            Head::Branch(branch),
+            TreeView::new(),
+            force,
+            &mut progress,
+        )?;
+        if !quiet {
+            println!("Switched to a new branch '{}'", name);
+        }
+        return Ok(());
+    }
+
+    let name = branch.expect("branch is required unless creating an orphan");
+    if detach {
+        let commit_id = resolve_revision(&conn, &name)?;
+        return detach_head(&conn, &repo_root, commit_id, force, &mut progress);
+    }
+    let branch = format!("{}{}", BRANCH_PREFIX, name);
+    let Some(existing) = Ref::read_from_conn_with_name(&conn, &branch)? else {
+        return Err(anyhow!("fatal: invalid reference: {}", name));
+    };
+    switch_to_branch(
+        &conn,
+        &repo_root,
+        &name,
+        existing.commit_id,
+        force,
+        &mut progress,
+    )
+}
+
+/// Switch to the local branch `name` at `commit_id`
+pub fn switch_to_branch(
+    conn: &Connection,
+    repo_root: &Path,
+    name: &str,
+    commit_id: Sha1Id,
+    force: bool,
+    progress: &mut Progress,
+) -> crate::Result<()> {
+    let branch = format!("{}{}", BRANCH_PREFIX, name);
+    if Head::read_from_conn(conn)? == Head::Branch(branch.clone()) && !force {
+        println!("Already on '{}'", name);
+        return Ok(());
+    }
+    let target = flatten_tree(
+        conn,
+        Commit::read_from_conn_with_id(conn, commit_id)?.tree_id,
+    )?;
+    switch_worktree(
+        conn,
+        repo_root,
+        Head::Branch(branch),
+        target,
+        force,
+        progress,
+    )?;
+    if !progress.is_quiet() {
+        println!("Switched to branch '{}'", name);
+    }
+    Ok(())
+}
+
+/// Point HEAD directly at the commit, checking out its snapshot
+pub fn detach_head(
+    conn: &Connection,
+    repo_root: &Path,
+    commit_id: Sha1Id,
+    force: bool,
+    progress: &mut Progress,
+) -> crate::Result<()> {
+    let commit = Commit::read_from_conn_with_id(conn, commit_id)?;
+    let target = flatten_tree(conn, commit.tree_id)?;
+    switch_worktree(
+        conn,
+        repo_root,
+        Head::Commit(commit_id),
+        target,
+        force,
+        progress,
+    )?;
+    if !progress.is_quiet() {
+        println!(
+            "HEAD is now at {} {}",
+            short_id(commit_id),
+            summary(&commit.message)
+        );
+    }
+    Ok(())
+}
+
+/// Move HEAD to `new_head`, bringing the index and working tree from the snapshot of HEAD to
+/// `target`. Local changes to files the switch does not touch are carried over, and the
+/// switch is refused if it would overwrite other ones, unless `force` is given to throw all
+/// local changes away. The files touched are reported to `progress`, which is finished once
+/// the switch is done.
+pub fn switch_worktree(
+    conn: &Connection,
+    repo_root: &Path,
+    new_head: Head,
+    target: TreeView,
+    force: bool,
+    progress: &mut Progress,
+) -> crate::Result<()> {
+    let ours = match Head::read_from_conn(conn)?.resolve(conn)? {
+        Some(head_id) => {
+            flatten_tree(conn, Commit::read_from_conn_with_id(conn, head_id)?.tree_id)?
+        }
+        None => TreeView::new(),
+    };
+    let outcome = MergeOutcome {
+        tree: target,
+        conflicts: Vec::new(),
+    };
+
+    let mut index = Index::read_from_conn(conn)?;
+    if !force {
+        if index.has_conflicts() {
+            return Err(anyhow!(
+                "error: you need to resolve your current index first"
+            ));
+        }
+        let dirty = local_changes(conn, repo_root, &index, &ours, &outcome)?;
+        if !dirty.is_empty() {
+            let files: Vec<String> = dirty.iter().map(|name| format!("\t{}", name)).collect();
+            return Err(anyhow!(
+                "error: Your local changes to the following files would be overwritten by checkout:\n{}\nPlease commit your changes or stash them before you switch branches.\nAborting",
+                files.join("\n")
+            ));
+        }
+    }
+
+    let txn = WriteTransaction::new(conn)?;
+    new_head.persist(&txn)?;
+    if force {
+        reset_hard(&txn, repo_root, &mut index, &outcome.tree, progress)?;
+    } else {
+        apply_merge(
+            &txn,
+            repo_root,
+            &mut index,
+            &ours,
+            &outcome,
+            ("HEAD", "HEAD"),
+            progress,
+        )?;
+    }
+    index.persist(&txn)?;
+    txn.commit()?;
+    progress.finish();
+    Ok(())
+}
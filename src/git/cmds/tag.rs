@@ -0,0 +1,195 @@
+use std::{
+    cmp::Ordering,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::anyhow;
+use rusqlite::Connection;
+
+use crate::{
+    cli::TagArgs,
+    git::{
+        constants::{self, TAG_PREFIX},
+        editor::edit_text,
+        identity::user_identity,
+        merge::ancestors,
+        model::{Ref, Sha1Id},
+        rebase::short_id,
+        refname::{compare_versions, tag_ref_name},
+        revision::resolve_revision,
+        utils::{find_gitqlite_root, get_gitqlite_connection, WriteTransaction},
+    },
+    repo::{
+        config::{ConfigSource, GitConfig},
+        db::{HashAlgorithm, Object, OldValue, RefTransaction, Tag},
+    },
+};
+
+pub fn do_tag(arg: TagArgs) -> crate::Result<()> {
+    let TagArgs {
+        names,
+        list,
+        delete,
+        annotate,
+        message,
+        force,
+        sort,
+        contains,
+    } = arg;
+
+    let repo_root = find_gitqlite_root(std::env::current_dir()?)?;
+    let conn = get_gitqlite_connection()?;
+    let config = GitConfig::load(repo_root.join(constants::GITQLITE_DIRECTORY_PREFIX))?;
+
+    if delete {
+        if names.is_empty() {
+            return Err(anyhow!("fatal: no tag given to delete"));
+        }
+        let mut transaction = RefTransaction::new("tag: delete");
+        let mut deleted = Vec::new();
+        for tag in &names {
+            let name = format!("{}{}", TAG_PREFIX, tag);
+            let reference = Ref::read_from_conn_with_name(&conn, &name)?
+                .ok_or_else(|| anyhow!("error: tag '{}' not found.", tag))?;
+            transaction.delete(name, OldValue::Is(reference.commit_id.into()));
+            deleted.push((tag, reference.commit_id));
+        }
+        let txn = WriteTransaction::new(&conn)?;
+        transaction.commit(&txn)?;
+        for (tag, _) in &deleted {
+            Tag::delete(&txn, tag)?;
+        }
+        txn.commit()?;
+        for (tag, id) in deleted {
+            println!("Deleted tag '{}' (was {})", tag, short_id(id));
+        }
+        return Ok(());
+    }
+
+    if let (false, None, [tag, rest @ ..]) = (list, &contains, names.as_slice()) {
+        if rest.len() > 1 {
+            return Err(anyhow!("fatal: too many arguments"));
+        }
+        let commit_id = resolve_revision(&conn, rest.first().map_or("HEAD", String::as_str))?;
+        let annotation = match (annotate, message) {
+            (false, None) => None,
+            (_, Some(message)) => Some(message),
+            (true, None) => Some(edit_tag_message(&config, &repo_root, tag)?),
+        };
+        return create_tag(&conn, &config, tag, commit_id, annotation, force);
+    }
+
+    let patterns = names
+        .iter()
+        .map(|pattern| {
+            glob::Pattern::new(pattern)
+                .map_err(|err| anyhow!("fatal: invalid pattern '{}': {}", pattern, err))
+        })
+        .collect::<crate::Result<Vec<_>>>()?;
+    let contained = contains
+        .map(|rev| resolve_revision(&conn, &rev))
+        .transpose()?;
+
+    let mut tags = Vec::new();
+    for reference in Ref::read_all(&conn)? {
+        let Some(tag) = reference.name.strip_prefix(TAG_PREFIX) else {
+            continue;
+        };
+        if !patterns.is_empty() && !patterns.iter().any(|p| p.matches(tag)) {
+            continue;
+        }
+        if let Some(contained) = contained {
+            if !ancestors(&conn, reference.commit_id)?.contains(&contained) {
+                continue;
+            }
+        }
+        tags.push(tag.to_string());
+    }
+
+    let sort = match sort {
+        Some(sort) => sort,
+        None => config
+            .get("tag.sort", ConfigSource::All)?
+            .unwrap_or("refname")
+            .to_string(),
+    };
+    let (reverse, key) = match sort.strip_prefix('-') {
+        Some(key) => (true, key),
+        None => (false, sort.as_str()),
+    };
+    let compare: fn(&String, &String) -> Ordering = match key {
+        "refname" => |a, b| a.cmp(b),
+        "version:refname" | "v:refname" => |a, b| compare_versions(a, b).then_with(|| a.cmp(b)),
+        _ => return Err(anyhow!("fatal: unsupported sort key '{}'", key)),
+    };
+    tags.sort_by(compare);
+    if reverse {
+        tags.reverse();
+    }
+    for tag in tags {
+        println!("{}", tag);
+    }
+    Ok(())
+}
+
+/// Point the tag at the commit, annotated with `message` if given
+fn create_tag(
+    conn: &Connection,
+    config: &GitConfig,
+    tag: &str,
+    commit_id: Sha1Id,
+    message: Option<String>,
+    force: bool,
+) -> crate::Result<()> {
+    let name = tag_ref_name(tag)?;
+    let old = match Ref::read_from_conn_with_name(conn, &name)? {
+        Some(existing) if force => OldValue::Is(existing.commit_id.into()),
+        Some(_) => return Err(anyhow!("fatal: tag '{}' already exists", tag)),
+        None => OldValue::Missing,
+    };
+    let annotation = match message {
+        Some(message) if message.trim().is_empty() => {
+            return Err(anyhow!("fatal: no tag message?"))
+        }
+        Some(message) => {
+            let tagged_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+            let tagger = user_identity(config)?;
+            Some(
+                Tag::new(
+                    tag.to_string(),
+                    commit_id.into(),
+                    tagger,
+                    tagged_at,
+                    message,
+                )
+                .with_id(HashAlgorithm::read(conn)?),
+            )
+        }
+        None => None,
+    };
+
+    let mut transaction = RefTransaction::new("tag");
+    transaction.update(name, old, commit_id.into());
+    let txn = WriteTransaction::new(conn)?;
+    transaction.commit(&txn)?;
+    // A lightweight tag replacing an annotated one drops its annotation
+    match annotation {
+        Some(annotation) => annotation.persist(&txn)?,
+        None => Tag::delete(&txn, tag)?,
+    }
+    txn.commit()?;
+    Ok(())
+}
+
+/// Let the user write the message of an annotated tag
+fn edit_tag_message(config: &GitConfig, repo_root: &Path, tag: &str) -> crate::Result<String> {
+    let path = repo_root
+        .join(constants::GITQLITE_DIRECTORY_PREFIX)
+        .join(constants::TAG_MESSAGE_FILE);
+    let initial = format!(
+        "\n#\n# Write a message for tag:\n#   {}\n# Lines starting with '#' will be ignored.\n",
+        tag
+    );
+    edit_text(config, path, &initial)
+}
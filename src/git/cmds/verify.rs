@@ -0,0 +1,36 @@
+use anyhow::anyhow;
+
+use crate::{
+    cli::VerifyArgs,
+    git::{
+        connectivity::check_connectivity,
+        manifest::{read_manifest, verify_database},
+        utils::{find_gitqlite_root, get_gitqlite_connection},
+    },
+};
+
+pub fn do_verify(arg: VerifyArgs) -> crate::Result<()> {
+    find_gitqlite_root(std::env::current_dir()?)?;
+    let conn = get_gitqlite_connection()?;
+
+    let mut problems = verify_database(&conn)?;
+    if arg.connectivity {
+        problems.extend(check_connectivity(&conn)?);
+    }
+    if arg.verbose && problems.is_empty() {
+        for (table, summary) in read_manifest(&conn)? {
+            println!(
+                "{}: {} rows, checksum {:08x}",
+                table, summary.row_count, summary.checksum
+            );
+        }
+    }
+    for problem in &problems {
+        eprintln!("error: {}", problem);
+    }
+
+    if !problems.is_empty() {
+        return Err(anyhow!("fatal: the repository database is corrupt"));
+    }
+    Ok(())
+}
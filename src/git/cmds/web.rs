@@ -0,0 +1,16 @@
+use crate::{
+    cli::WebArgs,
+    git::web::{handle_request, serve, Page},
+    repo::Repository,
+};
+
+pub fn do_web(arg: WebArgs) -> crate::Result<()> {
+    let repo = Repository::open(std::env::current_dir()?)?;
+
+    println!("Serving the repository at http://{}/", arg.listen);
+    // Each request reads on its own connection, leaving the database free for writes
+    serve(&arg.listen, |request| match repo.read_conn() {
+        Ok(conn) => handle_request(&conn, request.url()),
+        Err(err) => Page::server_error(&err.to_string()),
+    })
+}
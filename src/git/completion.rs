@@ -0,0 +1,105 @@
+//! This module lists the candidates for completing the arguments of commands: branches, tags,
+//! remotes, config keys, the paths in the index and the hooks that can be run. Shell completion
+//! scripts call `gitqlite internal complete <kind>` and filter the candidates by the word being
+//! completed, so each kind is read with as little work as possible.
+
+use std::{collections::BTreeSet, path::Path};
+
+use rusqlite::Connection;
+
+use crate::{
+    cli::CompletionKind,
+    repo::config::{ConfigSource, GitConfig},
+};
+
+use super::{
+    constants::{BRANCH_PREFIX, TAG_PREFIX},
+    hooks::Hooks,
+    model::{Index, Ref},
+};
+
+/// The candidates of the given kind for the repository whose gitqlite directory is
+/// `gitqlite_home`, sorted and without duplicates
+pub fn candidates(
+    conn: &Connection,
+    gitqlite_home: &Path,
+    kind: CompletionKind,
+) -> crate::Result<Vec<String>> {
+    match kind {
+        CompletionKind::Branches => ref_names(conn, BRANCH_PREFIX),
+        CompletionKind::Tags => ref_names(conn, TAG_PREFIX),
+        CompletionKind::Remotes => {
+            let config = GitConfig::load(gitqlite_home)?;
+            let remotes: BTreeSet<String> = config
+                .list(ConfigSource::All)
+                .into_iter()
+                .filter_map(|entry| {
+                    let remote = entry.key.strip_prefix("remote.")?.strip_suffix(".url")?;
+                    Some(remote.to_string())
+                })
+                .collect();
+            Ok(remotes.into_iter().collect())
+        }
+        CompletionKind::ConfigKeys => {
+            let config = GitConfig::load(gitqlite_home)?;
+            let keys: BTreeSet<String> = config
+                .list(ConfigSource::All)
+                .into_iter()
+                .map(|entry| entry.key)
+                .collect();
+            Ok(keys.into_iter().collect())
+        }
+        CompletionKind::Paths => {
+            let index = Index::read_from_conn(conn)?;
+            let names: BTreeSet<String> =
+                index.entries.into_iter().map(|entry| entry.name).collect();
+            Ok(names.into_iter().collect())
+        }
+        CompletionKind::Hooks => Hooks::for_repository(gitqlite_home)?.names(),
+    }
+}
+
+/// Names of the refs under `prefix`, without it
+fn ref_names(conn: &Connection, prefix: &str) -> crate::Result<Vec<String>> {
+    let mut names: Vec<String> = Ref::read_all(conn)?
+        .into_iter()
+        .filter_map(|reference| Some(reference.name.strip_prefix(prefix)?.to_string()))
+        .collect();
+    names.sort();
+    Ok(names)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::model::{Sha1Id, CREATE_REF_TABLE};
+
+    #[test]
+    fn test_ref_candidates() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute(CREATE_REF_TABLE, ()).unwrap();
+        for name in [
+            "refs/head/topic",
+            "refs/head/main",
+            "refs/tags/v1",
+            "refs/remotes/origin/main",
+        ] {
+            Ref {
+                name: name.to_string(),
+                commit_id: Sha1Id::try_from(vec![1; 20]).unwrap(),
+            }
+            .persist_or_update(&conn)
+            .unwrap();
+        }
+
+        let home = Path::new("/nonexistent");
+        assert_eq!(
+            vec!["main", "topic"],
+            candidates(&conn, home, CompletionKind::Branches).unwrap()
+        );
+        assert_eq!(
+            vec!["v1"],
+            candidates(&conn, home, CompletionKind::Tags).unwrap()
+        );
+    }
+}
@@ -0,0 +1,159 @@
+//! This module checks that the objects of the database are connected, as `gitqlite verify
+//! --connectivity` reports: each ref points to a commit, each commit to its tree and parent
+//! commits, and each tree to the trees and blobs of its entries. Submodule entries name
+//! commits of other repositories, so they are not followed.
+
+use rusqlite::Connection;
+
+use crate::repo::db::{read_object_type, ObjectType};
+
+use super::model::{Commit, Ref, Sha1Id, Tree, TreeEntryType};
+
+const READ_ALL_COMMIT_IDS: &str = "SELECT commit_id FROM Commits";
+const READ_ALL_TREE_IDS: &str = "SELECT tree_id FROM Trees";
+
+/// Bits of the mode of an entry telling the type of a file, and the type of submodules
+const FILE_TYPE_MASK: u32 = 0o170000;
+const GITLINK_TYPE: u32 = 0o160000;
+
+/// Describe each link from a ref or object to an object the database lacks or holds with
+/// another type
+pub fn check_connectivity(conn: &Connection) -> crate::Result<Vec<String>> {
+    let mut problems = Vec::new();
+    let mut check = |from: &str, id: Sha1Id, expected: ObjectType| -> crate::Result<()> {
+        let name = type_name(expected);
+        match read_object_type(conn, id.into())? {
+            Some(found) if found == expected => {}
+            Some(found) => problems.push(format!(
+                "{} points to {} {} which is a {}",
+                from,
+                name,
+                id,
+                type_name(found)
+            )),
+            None => problems.push(format!("{} points to missing {} {}", from, name, id)),
+        }
+        Ok(())
+    };
+
+    for reference in Ref::read_all(conn)? {
+        check(
+            &format!("ref {}", reference.name),
+            reference.commit_id,
+            ObjectType::Commit,
+        )?;
+    }
+
+    for commit_id in read_ids(conn, READ_ALL_COMMIT_IDS)? {
+        let commit = Commit::read_from_conn_with_id(conn, commit_id)?;
+        let from = format!("commit {}", commit_id);
+        check(&from, commit.tree_id, ObjectType::Tree)?;
+        for parent_id in commit.parent_ids {
+            check(&from, parent_id, ObjectType::Commit)?;
+        }
+    }
+
+    for tree_id in read_ids(conn, READ_ALL_TREE_IDS)? {
+        let tree = Tree::read_from_conn_with_id(conn, tree_id)?;
+        let from = format!("tree {}", tree_id);
+        for entry in tree.entries {
+            let is_gitlink = entry
+                .mode
+                .parse::<u32>()
+                .is_ok_and(|mode| mode & FILE_TYPE_MASK == GITLINK_TYPE);
+            if is_gitlink {
+                continue;
+            }
+            let expected = match entry.type_ {
+                TreeEntryType::Blob => ObjectType::Blob,
+                TreeEntryType::Tree => ObjectType::Tree,
+            };
+            check(&from, entry.id, expected)?;
+        }
+    }
+
+    Ok(problems)
+}
+
+fn read_ids(conn: &Connection, query: &str) -> crate::Result<Vec<Sha1Id>> {
+    let mut stmt = conn.prepare(query)?;
+    let ids = stmt
+        .query_map((), |row| row.get(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(ids)
+}
+
+fn type_name(type_: ObjectType) -> &'static str {
+    match type_ {
+        ObjectType::Commit => "commit",
+        ObjectType::Tree => "tree",
+        ObjectType::Blob => "blob",
+        ObjectType::Tag => "tag",
+        ObjectType::Index => "index",
+        ObjectType::Head => "head",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        git::{
+            model::{Hashable, TreeEntry, CREATE_REF_TABLE},
+            test_utils::{new_commit, object_database, persist_blob, persist_commit, FILE_MODE},
+        },
+        repo::db::HashAlgorithm,
+    };
+
+    #[test]
+    fn test_check_connectivity() {
+        let conn = object_database(&[CREATE_REF_TABLE]);
+
+        let blob_id = persist_blob(&conn, b"content");
+        let missing = Sha1Id::try_from(vec![7; 20]).unwrap();
+        let tree = Tree::new(vec![
+            TreeEntry {
+                type_: TreeEntryType::Blob,
+                id: blob_id,
+                mode: FILE_MODE.to_string(),
+                name: "file".to_string(),
+            },
+            TreeEntry {
+                type_: TreeEntryType::Blob,
+                id: missing,
+                mode: "57344".to_string(),
+                name: "submodule".to_string(),
+            },
+        ]);
+        let tree_id = tree.hash(HashAlgorithm::Sha1.hasher());
+        tree.with_id(tree_id).persist(&conn).unwrap();
+        let commit_id = persist_commit(&conn, new_commit(tree_id, vec![], "root"));
+        Ref {
+            name: "refs/head/main".to_string(),
+            commit_id,
+        }
+        .persist_or_update(&conn)
+        .unwrap();
+        assert!(check_connectivity(&conn).unwrap().is_empty());
+
+        // A ref to a tree and a ref to nothing
+        for (name, id) in [("refs/head/tree", tree_id), ("refs/head/gone", missing)] {
+            Ref {
+                name: name.to_string(),
+                commit_id: id,
+            }
+            .persist_or_update(&conn)
+            .unwrap();
+        }
+        assert_eq!(
+            vec![
+                format!("ref refs/head/gone points to missing commit {}", missing),
+                format!(
+                    "ref refs/head/tree points to commit {} which is a tree",
+                    tree_id
+                ),
+            ],
+            check_connectivity(&conn).unwrap()
+        );
+    }
+}
@@ -1,3 +1,9 @@
 pub const GITQLITE_DIRECTORY_PREFIX: &str = ".gitqlite";
 pub const GITQLITE_DB_NAME: &str = "gitqlite.db";
 pub const BRANCH_PREFIX: &str = "refs/head/";
+pub const REMOTE_PREFIX: &str = "refs/remotes/";
+pub const TAG_PREFIX: &str = "refs/tags/";
+/// File under the gitqlite directory holding a commit message while it is edited or checked
+pub const COMMIT_MESSAGE_FILE: &str = "COMMIT_EDITMSG";
+/// File under the gitqlite directory holding a tag message while it is edited
+pub const TAG_MESSAGE_FILE: &str = "TAG_EDITMSG";
@@ -0,0 +1,190 @@
+//! Diff drivers: external diff programs and text conversion filters. A driver is selected per
+//! path by the `diff=<driver>` attribute and configured with `diff.<driver>.command`,
+//! `diff.<driver>.textconv` and `diff.<driver>.binary`. `diff.external` (or the
+//! `GIT_EXTERNAL_DIFF` environment variable) runs an external program for every other path,
+//! and `-diff` forces a path to be treated as binary.
+
+use std::{
+    io::Write,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use anyhow::anyhow;
+
+use crate::{
+    git::attributes::{AttrValue, Attributes},
+    repo::config::{ConfigSource, GitConfig},
+};
+
+use super::{format::format_mode, FileChange, FileVersion};
+
+/// Argument passed to external diff programs in place of a missing side's hash and mode
+const MISSING: &str = ".";
+
+/// [`DiffDriver`] tells how to compare the versions of a file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DiffDriver {
+    /// Treat the file as binary regardless of its content
+    pub binary: bool,
+    /// Command converting each version to text before it is compared
+    pub textconv: Option<String>,
+    /// External program producing the patch of the file
+    pub command: Option<String>,
+}
+
+/// Look up the driver of the file at `path`. External programs and text conversion are only
+/// used when allowed.
+pub fn load_driver(
+    config: &GitConfig,
+    attributes: &Attributes,
+    path: &str,
+    allow_external: bool,
+    allow_textconv: bool,
+) -> crate::Result<DiffDriver> {
+    let mut driver = DiffDriver::default();
+
+    match attributes.get(path, "diff") {
+        Some(AttrValue::Unset) => driver.binary = true,
+        Some(AttrValue::Value(name)) => {
+            let key = |var: &str| format!("diff.{}.{}", name, var);
+            driver.binary = config
+                .get_bool(&key("binary"), ConfigSource::All)?
+                .unwrap_or(false);
+            if allow_textconv {
+                driver.textconv = config
+                    .get(&key("textconv"), ConfigSource::All)?
+                    .map(str::to_string);
+            }
+            if allow_external {
+                driver.command = config
+                    .get(&key("command"), ConfigSource::All)?
+                    .map(str::to_string);
+            }
+        }
+        _ => {}
+    }
+
+    if allow_external && driver.command.is_none() {
+        driver.command = match std::env::var("GIT_EXTERNAL_DIFF") {
+            Ok(command) => Some(command),
+            Err(_) => config
+                .get("diff.external", ConfigSource::All)?
+                .map(str::to_string),
+        };
+    }
+
+    Ok(driver)
+}
+
+/// Attach the driver of each file to its change, converting the content of both versions
+/// to text if the driver has a textconv filter
+pub fn apply_drivers(
+    mut changes: Vec<FileChange>,
+    config: &GitConfig,
+    attributes: &Attributes,
+    allow_external: bool,
+    allow_textconv: bool,
+) -> crate::Result<Vec<FileChange>> {
+    for change in changes.iter_mut() {
+        let driver = load_driver(
+            config,
+            attributes,
+            &change.name,
+            allow_external,
+            allow_textconv,
+        )?;
+        if let Some(textconv) = &driver.textconv {
+            for version in [&mut change.old, &mut change.new].into_iter().flatten() {
+                version.data = run_textconv(textconv, &change.name, &version.data)?;
+            }
+        }
+        change.driver = driver;
+    }
+    Ok(changes)
+}
+
+/// Run the external diff program of the change as git does, with the arguments
+/// `path old-file old-hex old-mode new-file new-hex new-mode`, and return its output
+pub fn run_external(command: &str, change: &FileChange) -> crate::Result<String> {
+    let old_file = temp_file(&change.old, change.old_name())?;
+    let new_file = temp_file(&change.new, &change.name)?;
+
+    let mut args = vec![change.name.clone()];
+    for (version, file) in [(&change.old, &old_file), (&change.new, &new_file)] {
+        let path = match file {
+            Some(file) => file.path(),
+            None => Path::new("/dev/null"),
+        };
+        args.push(path.to_string_lossy().to_string());
+        match version {
+            Some(version) => {
+                args.push(version.id.to_string());
+                args.push(format_mode(&version.mode));
+            }
+            None => {
+                args.push(MISSING.to_string());
+                args.push(MISSING.to_string());
+            }
+        }
+    }
+
+    let output = shell_command(command).args(&args).output()?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "fatal: external diff died, stopping at {}",
+            change.name
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Pipe the content of a file through a textconv filter, which receives it as a temporary file
+fn run_textconv(command: &str, name: &str, data: &[u8]) -> crate::Result<Vec<u8>> {
+    let mut file = temp_named(name)?;
+    file.write_all(data)?;
+
+    let output = shell_command(command)
+        .arg(file.path())
+        .stderr(Stdio::inherit())
+        .output()?;
+    if !output.status.success() {
+        return Err(anyhow!("fatal: unable to read files to diff"));
+    }
+    Ok(output.stdout)
+}
+
+/// Write a version of a file to a temporary file, None for a missing side
+fn temp_file(
+    version: &Option<FileVersion>,
+    name: &str,
+) -> crate::Result<Option<tempfile::NamedTempFile>> {
+    version
+        .as_ref()
+        .map(|version| {
+            let mut file = temp_named(name)?;
+            file.write_all(&version.data)?;
+            Ok(file)
+        })
+        .transpose()
+}
+
+/// Create a temporary file keeping the file name of the path, so that programs can tell its
+/// format from the extension
+fn temp_named(name: &str) -> crate::Result<tempfile::NamedTempFile> {
+    let base = name.rsplit('/').next().unwrap_or(name);
+    Ok(tempfile::Builder::new()
+        .prefix("gitqlite-")
+        .suffix(&format!("-{}", base))
+        .tempfile()?)
+}
+
+/// Run a command through the shell so that commands with arguments work, passing the extra
+/// arguments on to it
+fn shell_command(command: &str) -> Command {
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c")
+        .arg(format!("{} \"$@\"", command))
+        .arg(command);
+    cmd
+}
@@ -0,0 +1,649 @@
+//! Rendering of diffs in the output formats of `git diff`: the unified patch, the
+//! `--stat`, `--numstat` and `--shortstat` summaries, and the `--name-only` and
+//! `--name-status` change lists. Paths are quoted as [`PathQuoting`] tells, and with `-z`
+//! the name lists and `--numstat` separate paths with NUL.
+
+use crate::{cli::WordDiffMode, git::quote::PathQuoting};
+
+use super::{
+    diff_lines, diff_words, driver::run_external, make_hunks, ChangeStatus, FileChange,
+    FileVersion, LineEdit, WordChunk,
+};
+
+/// Number of context lines around changes in a patch
+const CONTEXT_LINES: usize = 3;
+/// Total width of a `--stat` line
+const STAT_WIDTH: usize = 80;
+/// Maximum width of the histogram bar of a `--stat` line
+const STAT_GRAPH_WIDTH: usize = 40;
+/// Abbreviated hash shown for a missing side in the `index` line of a patch
+const NULL_ABBREV: &str = "0000000";
+/// Terminal escape sequences used by `--word-diff=color`
+const COLOR_OLD: &str = "\x1b[31m";
+const COLOR_NEW: &str = "\x1b[32m";
+const COLOR_RESET: &str = "\x1b[m";
+
+/// [`DiffOutput`] selects the parts of the diff to render. When none is selected the patch
+/// is shown.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DiffOutput {
+    pub patch: bool,
+    pub stat: bool,
+    pub numstat: bool,
+    pub shortstat: bool,
+    pub name_only: bool,
+    pub name_status: bool,
+    /// Show changed words within lines instead of whole changed lines in the patch
+    pub word_diff: Option<WordDiffMode>,
+    pub quoting: PathQuoting,
+}
+
+/// Render the changes in the selected formats, in the order git prints them. The patch of a
+/// file with an external diff program is the output of that program.
+pub fn render(changes: &[FileChange], output: DiffOutput) -> crate::Result<String> {
+    let summary = output.stat || output.numstat || output.shortstat;
+    let patch = output.patch || !(summary || output.name_only || output.name_status);
+
+    let mut out = String::new();
+    if output.name_only {
+        out.push_str(&format_name_only(changes, output.quoting));
+    } else if output.name_status {
+        out.push_str(&format_name_status(changes, output.quoting));
+    }
+    if output.numstat {
+        out.push_str(&format_numstat(changes, output.quoting));
+    }
+    if output.stat {
+        out.push_str(&format_stat(changes, output.quoting));
+    } else if output.shortstat {
+        out.push_str(&format_shortstat(changes));
+    }
+    if patch {
+        if summary && !changes.is_empty() {
+            out.push('\n');
+        }
+        for change in changes {
+            match &change.driver.command {
+                Some(command) => out.push_str(&run_external(command, change)?),
+                None => out.push_str(&format_patch(change, output.word_diff, output.quoting)),
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// Render the unified diff of one file, as a word diff if a mode is given
+pub fn format_patch(
+    change: &FileChange,
+    word_diff: Option<WordDiffMode>,
+    quoting: PathQuoting,
+) -> String {
+    // Paths in patches are never written raw, even with -z
+    let quoting = match quoting {
+        PathQuoting::Raw => PathQuoting::default(),
+        quoting => quoting,
+    };
+    let (old_name, name) = (change.old_name(), &change.name);
+    let mut out = format!(
+        "diff --git {} {}\n",
+        quoting.quote_prefixed("a/", old_name),
+        quoting.quote_prefixed("b/", name)
+    );
+
+    match &change.status {
+        ChangeStatus::Renamed { from, score } => out.push_str(&format!(
+            "similarity index {}%\nrename from {}\nrename to {}\n",
+            score,
+            quoting.quote(from),
+            quoting.quote(name)
+        )),
+        ChangeStatus::Copied { from, score } => out.push_str(&format!(
+            "similarity index {}%\ncopy from {}\ncopy to {}\n",
+            score,
+            quoting.quote(from),
+            quoting.quote(name)
+        )),
+        _ => {}
+    }
+
+    match (&change.old, &change.new) {
+        (None, Some(new)) => out.push_str(&format!("new file mode {}\n", format_mode(&new.mode))),
+        (Some(old), None) => {
+            out.push_str(&format!("deleted file mode {}\n", format_mode(&old.mode)))
+        }
+        (Some(old), Some(new)) if old.mode != new.mode => {
+            out.push_str(&format!("old mode {}\n", format_mode(&old.mode)));
+            out.push_str(&format!("new mode {}\n", format_mode(&new.mode)));
+        }
+        _ => {}
+    }
+
+    let abbrev = |version: &Option<FileVersion>| {
+        version
+            .as_ref()
+            .map(|version| version.id.to_string()[..7].to_string())
+            .unwrap_or_else(|| NULL_ABBREV.to_string())
+    };
+    if change.old.as_ref().map(|v| v.id) == change.new.as_ref().map(|v| v.id) {
+        // Only the path or the mode changed
+        return out;
+    }
+    out.push_str(&format!(
+        "index {}..{}",
+        abbrev(&change.old),
+        abbrev(&change.new)
+    ));
+    if let (Some(old), Some(new)) = (&change.old, &change.new) {
+        if old.mode == new.mode {
+            out.push_str(&format!(" {}", format_mode(&old.mode)));
+        }
+    }
+    out.push('\n');
+
+    let old_label = match change.old {
+        Some(_) => quoting.quote_prefixed("a/", old_name),
+        None => "/dev/null".to_string(),
+    };
+    let new_label = match change.new {
+        Some(_) => quoting.quote_prefixed("b/", name),
+        None => "/dev/null".to_string(),
+    };
+
+    if change.is_binary() {
+        out.push_str(&format!(
+            "Binary files {} and {} differ\n",
+            old_label, new_label
+        ));
+        return out;
+    }
+
+    let (old, new) = change.lines();
+    let edits = diff_lines(&old, &new);
+    let hunks = make_hunks(&edits, CONTEXT_LINES);
+    if hunks.is_empty() {
+        return out;
+    }
+
+    out.push_str(&format!("--- {}\n+++ {}\n", old_label, new_label));
+    for hunk in hunks {
+        out.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            hunk_range(hunk.old_start, hunk.old_len),
+            hunk_range(hunk.new_start, hunk.new_len)
+        ));
+        if let Some(mode) = word_diff {
+            out.push_str(&format_word_hunk(&hunk.edits, &old, &new, mode));
+            continue;
+        }
+        for edit in &hunk.edits {
+            let (prefix, line) = match *edit {
+                LineEdit::Equal(x, _) => (' ', old[x]),
+                LineEdit::Delete(x) => ('-', old[x]),
+                LineEdit::Insert(y) => ('+', new[y]),
+            };
+            out.push(prefix);
+            out.push_str(&String::from_utf8_lossy(line));
+            if !line.ends_with(b"\n") {
+                out.push_str("\n\\ No newline at end of file\n");
+            }
+        }
+    }
+
+    out
+}
+
+/// Render the body of a hunk as a word diff: context lines are printed as they are, and each
+/// run of changed lines is compared word by word
+fn format_word_hunk(
+    edits: &[LineEdit],
+    old: &[&[u8]],
+    new: &[&[u8]],
+    mode: WordDiffMode,
+) -> String {
+    let mut out = String::new();
+    let mut i = 0;
+    while i < edits.len() {
+        if let LineEdit::Equal(x, _) = edits[i] {
+            out.push_str(&String::from_utf8_lossy(old[x]));
+            if !old[x].ends_with(b"\n") {
+                out.push('\n');
+            }
+            i += 1;
+            continue;
+        }
+
+        let (mut old_text, mut new_text) = (Vec::new(), Vec::new());
+        while i < edits.len() {
+            match edits[i] {
+                LineEdit::Delete(x) => old_text.extend_from_slice(old[x]),
+                LineEdit::Insert(y) => new_text.extend_from_slice(new[y]),
+                LineEdit::Equal(..) => break,
+            }
+            i += 1;
+        }
+
+        let (old_text, new_text) = (
+            String::from_utf8_lossy(&old_text),
+            String::from_utf8_lossy(&new_text),
+        );
+        let mut block = String::new();
+        for chunk in diff_words(&old_text, &new_text) {
+            match (chunk, mode) {
+                (WordChunk::Equal(text), _) => block.push_str(text),
+                (WordChunk::Deleted(text), WordDiffMode::Plain) => {
+                    block.push_str(&mark_words(text, "[-", "-]"))
+                }
+                (WordChunk::Inserted(text), WordDiffMode::Plain) => {
+                    block.push_str(&mark_words(text, "{+", "+}"))
+                }
+                (WordChunk::Deleted(text), WordDiffMode::Color) => {
+                    block.push_str(&mark_words(text, COLOR_OLD, COLOR_RESET))
+                }
+                (WordChunk::Inserted(text), WordDiffMode::Color) => {
+                    block.push_str(&mark_words(text, COLOR_NEW, COLOR_RESET))
+                }
+            }
+        }
+        if !block.ends_with('\n') {
+            block.push('\n');
+        }
+        out.push_str(&block);
+    }
+    out
+}
+
+/// Wrap changed text between markers, line by line so that no marker spans a line break
+fn mark_words(text: &str, open: &str, close: &str) -> String {
+    text.split('\n')
+        .map(|line| {
+            if line.is_empty() {
+                String::new()
+            } else {
+                format!("{}{}{}", open, line, close)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Render the `--name-only` list: the path of each changed file
+pub fn format_name_only(changes: &[FileChange], quoting: PathQuoting) -> String {
+    let end = quoting.terminator();
+    changes
+        .iter()
+        .map(|change| format!("{}{}", quoting.quote(&change.name), end))
+        .collect()
+}
+
+/// Render the `--name-status` list: the status of each changed file and its path(s)
+pub fn format_name_status(changes: &[FileChange], quoting: PathQuoting) -> String {
+    // With -z the status is a field of its own
+    let (separator, end) = match quoting {
+        PathQuoting::Raw => ('\0', '\0'),
+        _ => ('\t', '\n'),
+    };
+    changes
+        .iter()
+        .map(|change| match &change.status {
+            ChangeStatus::Renamed { from, .. } | ChangeStatus::Copied { from, .. } => format!(
+                "{}{}{}{}{}{}",
+                change.status.code(),
+                separator,
+                quoting.quote(from),
+                separator,
+                quoting.quote(&change.name),
+                end
+            ),
+            _ => format!(
+                "{}{}{}{}",
+                change.status.code(),
+                separator,
+                quoting.quote(&change.name),
+                end
+            ),
+        })
+        .collect()
+}
+
+/// Render the `--numstat` lines: insertions, deletions and name separated by tabs. With
+/// `-z` both paths of a rename or copy follow as fields of their own.
+pub fn format_numstat(changes: &[FileChange], quoting: PathQuoting) -> String {
+    changes
+        .iter()
+        .map(|change| {
+            let counts = match change.line_stats() {
+                Some((insertions, deletions)) => format!("{}\t{}", insertions, deletions),
+                None => "-\t-".to_string(),
+            };
+            match (&change.status, quoting) {
+                (
+                    ChangeStatus::Renamed { from, .. } | ChangeStatus::Copied { from, .. },
+                    PathQuoting::Raw,
+                ) => format!("{}\t\0{}\0{}\0", counts, from, change.name),
+                _ => format!(
+                    "{}\t{}{}",
+                    counts,
+                    display_name(change, quoting),
+                    quoting.terminator()
+                ),
+            }
+        })
+        .collect()
+}
+
+/// Render the `--stat` histogram followed by the summary line
+pub fn format_stat(changes: &[FileChange], quoting: PathQuoting) -> String {
+    if changes.is_empty() {
+        return String::new();
+    }
+
+    let stats: Vec<Option<(usize, usize)>> = changes.iter().map(FileChange::line_stats).collect();
+    let counts: Vec<String> = stats
+        .iter()
+        .map(|stat| match stat {
+            Some((insertions, deletions)) => (insertions + deletions).to_string(),
+            None => "Bin".to_string(),
+        })
+        .collect();
+
+    let names: Vec<String> = changes
+        .iter()
+        .map(|change| display_name(change, quoting))
+        .collect();
+    let name_width = names.iter().map(|n| n.chars().count()).max().unwrap_or(0);
+    let count_width = counts.iter().map(String::len).max().unwrap_or(0);
+    let max_change = stats
+        .iter()
+        .flatten()
+        .map(|(insertions, deletions)| insertions + deletions)
+        .max()
+        .unwrap_or(0);
+    // Leave room for " name | count " on the line
+    let graph_width = STAT_WIDTH
+        .saturating_sub(name_width + count_width + 5)
+        .clamp(6, STAT_GRAPH_WIDTH);
+
+    let mut out = String::new();
+    for (((change, name), stat), count) in changes.iter().zip(&names).zip(&stats).zip(&counts) {
+        let line = format!(" {:<name_width$} | {:>count_width$}", name, count);
+        match stat {
+            Some((insertions, deletions)) => {
+                let (plus, minus) = if max_change > graph_width {
+                    (
+                        scale(*insertions, graph_width, max_change),
+                        scale(*deletions, graph_width, max_change),
+                    )
+                } else {
+                    (*insertions, *deletions)
+                };
+                let bar = format!("{}{}", "+".repeat(plus), "-".repeat(minus));
+                if bar.is_empty() {
+                    out.push_str(&format!("{}\n", line));
+                } else {
+                    out.push_str(&format!("{} {}\n", line, bar));
+                }
+            }
+            None => {
+                let size = |version: &Option<FileVersion>| {
+                    version.as_ref().map(|v| v.data.len()).unwrap_or(0)
+                };
+                out.push_str(&format!(
+                    "{} {} -> {} bytes\n",
+                    line,
+                    size(&change.old),
+                    size(&change.new)
+                ));
+            }
+        }
+    }
+
+    out.push_str(&format_shortstat(changes));
+    out
+}
+
+/// Render the summary line of the number of changed files, insertions and deletions
+pub fn format_shortstat(changes: &[FileChange]) -> String {
+    if changes.is_empty() {
+        return String::new();
+    }
+
+    let (insertions, deletions) = changes
+        .iter()
+        .filter_map(FileChange::line_stats)
+        .fold((0, 0), |(i, d), (insertions, deletions)| {
+            (i + insertions, d + deletions)
+        });
+
+    let mut out = format!(
+        " {} {} changed",
+        changes.len(),
+        plural(changes.len(), "file", "files")
+    );
+    if insertions > 0 || deletions == 0 {
+        out.push_str(&format!(
+            ", {} {}(+)",
+            insertions,
+            plural(insertions, "insertion", "insertions")
+        ));
+    }
+    if deletions > 0 || insertions == 0 {
+        out.push_str(&format!(
+            ", {} {}(-)",
+            deletions,
+            plural(deletions, "deletion", "deletions")
+        ));
+    }
+    out.push('\n');
+    out
+}
+
+/// Name of the file in summaries, showing both paths of renames and copies
+fn display_name(change: &FileChange, quoting: PathQuoting) -> String {
+    match &change.status {
+        ChangeStatus::Renamed { from, .. } | ChangeStatus::Copied { from, .. } => {
+            format!("{} => {}", quoting.quote(from), quoting.quote(&change.name))
+        }
+        _ => quoting.quote(&change.name).into_owned(),
+    }
+}
+
+/// Scale a change count to the width of the histogram, keeping non-zero counts visible
+fn scale(count: usize, width: usize, max_change: usize) -> usize {
+    if count == 0 {
+        0
+    } else {
+        1 + count * (width - 1) / max_change
+    }
+}
+
+fn plural<'a>(n: usize, singular: &'a str, plural: &'a str) -> &'a str {
+    if n == 1 {
+        singular
+    } else {
+        plural
+    }
+}
+
+/// Render a hunk range as 1-based `start,len`, omitting the length when it is 1
+fn hunk_range(start: usize, len: usize) -> String {
+    match len {
+        // An empty range names the line before it
+        0 => format!("{},0", start),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{}", start + 1, len),
+    }
+}
+
+/// Render a mode stored as a decimal `st_mode` in the octal form git prints
+pub fn format_mode(mode: &str) -> String {
+    match mode.parse::<u32>() {
+        Ok(mode) => format!("{:06o}", mode),
+        Err(_) => mode.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::{diff::DiffDriver, model::Sha1Id};
+
+    fn version(byte: u8, data: &str) -> Option<FileVersion> {
+        Some(FileVersion {
+            id: Sha1Id::try_from(vec![byte; 20]).unwrap(),
+            mode: "33188".to_string(),
+            data: data.as_bytes().to_vec(),
+        })
+    }
+
+    fn changes() -> Vec<FileChange> {
+        vec![
+            FileChange {
+                name: "a.txt".to_string(),
+                status: ChangeStatus::Modified,
+                old: version(1, "one\ntwo\nthree\n"),
+                new: version(2, "one\n2\nthree\nfour"),
+                driver: DiffDriver::default(),
+            },
+            FileChange {
+                name: "dir/new.txt".to_string(),
+                status: ChangeStatus::Added,
+                old: None,
+                new: version(3, "hello\n"),
+                driver: DiffDriver::default(),
+            },
+        ]
+    }
+
+    #[test]
+    fn test_format_patch() {
+        let patch = format_patch(&changes()[0], None, PathQuoting::default());
+        assert_eq!(
+            "diff --git a/a.txt b/a.txt
+index 0101010..0202020 100644
+--- a/a.txt
++++ b/a.txt
+@@ -1,3 +1,4 @@
+ one
+-two
++2
+ three
++four
+\\ No newline at end of file
+",
+            patch
+        );
+
+        let mut binary = changes()[0].clone();
+        binary.driver.binary = true;
+        assert!(format_patch(&binary, None, PathQuoting::default())
+            .ends_with("100644\nBinary files a/a.txt and b/a.txt differ\n"));
+
+        let patch = format_patch(&changes()[1], None, PathQuoting::default());
+        assert!(patch.contains("new file mode 100644\nindex 0000000..0303030\n"));
+        assert!(patch.contains("--- /dev/null\n+++ b/dir/new.txt\n@@ -0,0 +1 @@\n+hello\n"));
+    }
+
+    #[test]
+    fn test_format_stats() {
+        let changes = changes();
+        assert_eq!(
+            "2\t1\ta.txt\n1\t0\tdir/new.txt\n",
+            format_numstat(&changes, PathQuoting::default())
+        );
+        assert_eq!(
+            " 2 files changed, 3 insertions(+), 1 deletion(-)\n",
+            format_shortstat(&changes)
+        );
+        assert_eq!(
+            " a.txt       | 3 ++-\n dir/new.txt | 1 +\n 2 files changed, 3 insertions(+), 1 deletion(-)\n",
+            format_stat(&changes, PathQuoting::default())
+        );
+    }
+
+    #[test]
+    fn test_name_lists() {
+        let mut changes = changes();
+        changes.push(FileChange {
+            name: "moved.txt".to_string(),
+            status: ChangeStatus::Renamed {
+                from: "orig.txt".to_string(),
+                score: 100,
+            },
+            old: version(4, "same\n"),
+            new: version(4, "same\n"),
+            driver: DiffDriver::default(),
+        });
+
+        assert_eq!(
+            "a.txt\ndir/new.txt\nmoved.txt\n",
+            format_name_only(&changes, PathQuoting::default())
+        );
+        assert_eq!(
+            "M\ta.txt\nA\tdir/new.txt\nR100\torig.txt\tmoved.txt\n",
+            format_name_status(&changes, PathQuoting::default())
+        );
+        assert_eq!(
+            "diff --git a/orig.txt b/moved.txt\nsimilarity index 100%\nrename from orig.txt\nrename to moved.txt\n",
+            format_patch(&changes[2], None, PathQuoting::default())
+        );
+        assert!(format_numstat(&changes, PathQuoting::default())
+            .ends_with("0\t0\torig.txt => moved.txt\n"));
+
+        // -z separates fields with NUL and leaves paths as they are
+        assert_eq!(
+            "M\0a.txt\0A\0dir/new.txt\0R100\0orig.txt\0moved.txt\0",
+            format_name_status(&changes, PathQuoting::Raw)
+        );
+        assert!(
+            format_numstat(&changes, PathQuoting::Raw).ends_with("0\t0\t\0orig.txt\0moved.txt\0")
+        );
+
+        changes[2].name = "mov\u{e9}d\t.txt".to_string();
+        assert_eq!(
+            "\"mov\\303\\251d\\t.txt\"\n",
+            format_name_only(&changes[2..], PathQuoting::NonAscii)
+        );
+        assert_eq!(
+            "mov\u{e9}d\t.txt\0",
+            format_name_only(&changes[2..], PathQuoting::Raw)
+        );
+        assert!(format_patch(&changes[2], None, PathQuoting::Special).starts_with(
+            "diff --git a/orig.txt \"b/mov\u{e9}d\\t.txt\"\nsimilarity index 100%\nrename from orig.txt\nrename to \"mov\u{e9}d\\t.txt\"\n"
+        ));
+    }
+
+    #[test]
+    fn test_word_diff() {
+        let change = FileChange {
+            name: "a.txt".to_string(),
+            status: ChangeStatus::Modified,
+            old: version(1, "one\nthe quick fox\nfour\nfive\n"),
+            new: version(2, "one\nthe slow  brown fox\nfive\n"),
+            driver: DiffDriver::default(),
+        };
+        let patch = format_patch(&change, Some(WordDiffMode::Plain), PathQuoting::default());
+        assert!(patch
+            .ends_with("@@ -1,4 +1,3 @@\none\nthe [-quick-]{+slow  brown+} fox\n[-four-]\nfive\n"));
+
+        let patch = format_patch(&change, Some(WordDiffMode::Color), PathQuoting::default());
+        assert!(patch.contains("the \x1b[31mquick\x1b[m\x1b[32mslow  brown\x1b[m fox\n"));
+    }
+
+    #[test]
+    fn test_stat_scaling() {
+        let long = "x\n".repeat(100);
+        let changes = vec![FileChange {
+            name: "big".to_string(),
+            status: ChangeStatus::Added,
+            old: None,
+            new: version(1, &long),
+            driver: DiffDriver::default(),
+        }];
+        let stat = format_stat(&changes, PathQuoting::default());
+        let first = stat.lines().next().unwrap();
+        assert_eq!(
+            format!(" big | 100 {}", "+".repeat(STAT_GRAPH_WIDTH)),
+            first
+        );
+    }
+}
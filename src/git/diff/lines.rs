@@ -0,0 +1,370 @@
+//! Line-by-line diff of two texts using Myers' O(ND) algorithm in its linear space variant,
+//! and grouping of the edits into hunks with surrounding context.
+
+use std::ops::Range;
+
+/// One step of the edit script turning the old lines into the new lines. Indices are
+/// positions in the old and new line lists respectively.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineEdit {
+    Equal(usize, usize),
+    Delete(usize),
+    Insert(usize),
+}
+
+/// [`Hunk`] is a run of edits close enough to be shown together, with context lines around
+/// them. Starts are 0-based positions in the old and new line lists.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk {
+    pub old_start: usize,
+    pub old_len: usize,
+    pub new_start: usize,
+    pub new_len: usize,
+    pub edits: Vec<LineEdit>,
+}
+
+/// Compute a shortest edit script turning `old` into `new`
+pub fn diff_lines<T: PartialEq>(old: &[T], new: &[T]) -> Vec<LineEdit> {
+    let max_d = (old.len() + new.len()).div_ceil(2) + 1;
+    let mut forward = Diagonals::new(max_d);
+    let mut backward = Diagonals::new(max_d);
+    let mut edits = Vec::with_capacity(old.len().max(new.len()));
+    conquer(
+        old,
+        0..old.len(),
+        new,
+        0..new.len(),
+        &mut forward,
+        &mut backward,
+        &mut edits,
+    );
+    edits
+}
+
+/// Furthest x reached on each diagonal k = x - y, for k in `-max_d..=max_d`
+struct Diagonals {
+    offset: isize,
+    v: Vec<usize>,
+}
+
+impl Diagonals {
+    fn new(max_d: usize) -> Diagonals {
+        Diagonals {
+            offset: max_d as isize,
+            v: vec![0; 2 * max_d + 1],
+        }
+    }
+}
+
+impl std::ops::Index<isize> for Diagonals {
+    type Output = usize;
+
+    fn index(&self, k: isize) -> &usize {
+        &self.v[(k + self.offset) as usize]
+    }
+}
+
+impl std::ops::IndexMut<isize> for Diagonals {
+    fn index_mut(&mut self, k: isize) -> &mut usize {
+        &mut self.v[(k + self.offset) as usize]
+    }
+}
+
+/// Diff the given ranges, splitting them at the middle of a shortest edit script so that only
+/// the diagonals of the current round are kept: linear space rather than one copy of them per
+/// round
+fn conquer<T: PartialEq>(
+    old: &[T],
+    mut old_range: Range<usize>,
+    new: &[T],
+    mut new_range: Range<usize>,
+    forward: &mut Diagonals,
+    backward: &mut Diagonals,
+    edits: &mut Vec<LineEdit>,
+) {
+    while !old_range.is_empty()
+        && !new_range.is_empty()
+        && old[old_range.start] == new[new_range.start]
+    {
+        edits.push(LineEdit::Equal(old_range.start, new_range.start));
+        old_range.start += 1;
+        new_range.start += 1;
+    }
+    let mut suffix = 0;
+    while suffix < old_range.len()
+        && suffix < new_range.len()
+        && old[old_range.end - suffix - 1] == new[new_range.end - suffix - 1]
+    {
+        suffix += 1;
+    }
+    old_range.end -= suffix;
+    new_range.end -= suffix;
+
+    if old_range.is_empty() {
+        edits.extend(new_range.clone().map(LineEdit::Insert));
+    } else if new_range.is_empty() {
+        edits.extend(old_range.clone().map(LineEdit::Delete));
+    } else {
+        let (x, y) = middle_snake(old, &old_range, new, &new_range, forward, backward);
+        conquer(
+            old,
+            old_range.start..x,
+            new,
+            new_range.start..y,
+            forward,
+            backward,
+            edits,
+        );
+        conquer(
+            old,
+            x..old_range.end,
+            new,
+            y..new_range.end,
+            forward,
+            backward,
+            edits,
+        );
+    }
+
+    for i in 0..suffix {
+        edits.push(LineEdit::Equal(old_range.end + i, new_range.end + i));
+    }
+}
+
+/// Find a point on a shortest edit script of the ranges, which must both be non-empty, by
+/// searching from both ends at once until the paths overlap
+fn middle_snake<T: PartialEq>(
+    old: &[T],
+    old_range: &Range<usize>,
+    new: &[T],
+    new_range: &Range<usize>,
+    forward: &mut Diagonals,
+    backward: &mut Diagonals,
+) -> (usize, usize) {
+    let n = old_range.len();
+    let m = new_range.len();
+    let delta = n as isize - m as isize;
+    let odd = delta & 1 == 1;
+    // Backward positions count from the end of the ranges
+    let old_at = |x: usize| &old[old_range.start + x];
+    let new_at = |y: usize| &new[new_range.start + y];
+    let old_back = |x: usize| &old[old_range.end - x - 1];
+    let new_back = |y: usize| &new[new_range.end - y - 1];
+
+    forward[1] = 0;
+    backward[1] = 0;
+    for d in 0..=(n + m).div_ceil(2) as isize {
+        for k in (-d..=d).rev().step_by(2) {
+            let mut x = if k == -d || (k != d && forward[k - 1] < forward[k + 1]) {
+                forward[k + 1]
+            } else {
+                forward[k - 1] + 1
+            };
+            let (start_x, start_y) = (x, (x as isize - k) as usize);
+            let mut y = start_y;
+            while x < n && y < m && old_at(x) == new_at(y) {
+                x += 1;
+                y += 1;
+            }
+            forward[k] = x;
+            if odd && (k - delta).abs() < d && x + backward[delta - k] >= n {
+                return (old_range.start + start_x, new_range.start + start_y);
+            }
+        }
+        // Backward steps are taken as insertions first, which puts deletions before the
+        // insertions replacing them as git shows them
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && backward[k - 1] < backward[k + 1]) {
+                backward[k + 1]
+            } else {
+                backward[k - 1] + 1
+            };
+            let mut y = (x as isize - k) as usize;
+            while x < n && y < m && old_back(x) == new_back(y) {
+                x += 1;
+                y += 1;
+            }
+            backward[k] = x;
+            if !odd && (k - delta).abs() <= d && x + forward[delta - k] >= n {
+                return (old_range.end - x, new_range.end - y);
+            }
+        }
+    }
+    unreachable!("the searches from both ends meet after (n + m) / 2 rounds")
+}
+
+/// Group the edit script into hunks, keeping `context` unchanged lines around each change.
+/// Changes separated by at most twice the context are merged into one hunk.
+pub fn make_hunks(edits: &[LineEdit], context: usize) -> Vec<Hunk> {
+    let changes: Vec<usize> = edits
+        .iter()
+        .enumerate()
+        .filter(|(_, edit)| !matches!(edit, LineEdit::Equal(..)))
+        .map(|(i, _)| i)
+        .collect();
+
+    let mut hunks = Vec::new();
+    let mut i = 0;
+    while i < changes.len() {
+        let start = changes[i].saturating_sub(context);
+        let mut end = changes[i];
+        while i + 1 < changes.len() && changes[i + 1] - end <= 2 * context + 1 {
+            i += 1;
+            end = changes[i];
+        }
+        let end = (end + context + 1).min(edits.len());
+        i += 1;
+
+        let slice = &edits[start..end];
+        let (old_start, new_start) = position(edits, start);
+        let old_len = slice
+            .iter()
+            .filter(|edit| !matches!(edit, LineEdit::Insert(_)))
+            .count();
+        let new_len = slice
+            .iter()
+            .filter(|edit| !matches!(edit, LineEdit::Delete(_)))
+            .count();
+
+        hunks.push(Hunk {
+            old_start,
+            old_len,
+            new_start,
+            new_len,
+            edits: slice.to_vec(),
+        });
+    }
+
+    hunks
+}
+
+/// Number of old and new lines consumed before the edit at `index`
+fn position(edits: &[LineEdit], index: usize) -> (usize, usize) {
+    edits[..index]
+        .iter()
+        .fold((0, 0), |(old, new), edit| match edit {
+            LineEdit::Equal(..) => (old + 1, new + 1),
+            LineEdit::Delete(_) => (old + 1, new),
+            LineEdit::Insert(_) => (old, new + 1),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Apply the edit script to `old` and return the resulting lines
+    fn apply<'a>(old: &[&'a str], new: &[&'a str], edits: &[LineEdit]) -> Vec<&'a str> {
+        let mut result = Vec::new();
+        for edit in edits {
+            match edit {
+                LineEdit::Equal(x, y) => {
+                    assert_eq!(old[*x], new[*y]);
+                    result.push(old[*x]);
+                }
+                LineEdit::Insert(y) => result.push(new[*y]),
+                LineEdit::Delete(_) => {}
+            }
+        }
+        result
+    }
+
+    fn count_changes(edits: &[LineEdit]) -> usize {
+        edits
+            .iter()
+            .filter(|edit| !matches!(edit, LineEdit::Equal(..)))
+            .count()
+    }
+
+    #[test]
+    fn test_diff_lines() {
+        let old = ["a", "b", "c", "a", "b", "b", "a"];
+        let new = ["c", "b", "a", "b", "a", "c"];
+        let edits = diff_lines(&old, &new);
+        assert_eq!(new.to_vec(), apply(&old, &new, &edits));
+        // The shortest edit script for this classic example has length 5
+        assert_eq!(5, count_changes(&edits));
+
+        let empty: [&str; 0] = [];
+        assert!(diff_lines(&empty, &empty).is_empty());
+        assert_eq!(
+            vec![LineEdit::Insert(0), LineEdit::Insert(1)],
+            diff_lines(&empty, &["x", "y"])
+        );
+        assert_eq!(
+            vec![LineEdit::Delete(0), LineEdit::Delete(1)],
+            diff_lines(&["x", "y"], &empty)
+        );
+        assert_eq!(
+            vec![LineEdit::Equal(0, 0), LineEdit::Equal(1, 1)],
+            diff_lines(&["x", "y"], &["x", "y"])
+        );
+    }
+
+    /// Length of the shortest edit script, from the longest common subsequence
+    fn shortest_script(old: &[&str], new: &[&str]) -> usize {
+        let mut lcs = vec![vec![0; new.len() + 1]; old.len() + 1];
+        for x in (0..old.len()).rev() {
+            for y in (0..new.len()).rev() {
+                lcs[x][y] = if old[x] == new[y] {
+                    lcs[x + 1][y + 1] + 1
+                } else {
+                    lcs[x + 1][y].max(lcs[x][y + 1])
+                };
+            }
+        }
+        old.len() + new.len() - 2 * lcs[0][0]
+    }
+
+    #[test]
+    fn test_diff_lines_is_shortest() {
+        // Texts over a small alphabet have many common lines and many shortest scripts
+        let mut seed: u64 = 42;
+        let mut text = |len: usize| -> Vec<&str> {
+            (0..len)
+                .map(|_| {
+                    seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+                    ["a", "b", "c"][(seed >> 33) as usize % 3]
+                })
+                .collect()
+        };
+        for len in 0..40 {
+            let old = text(len);
+            let new = text(40 - len);
+            let edits = diff_lines(&old, &new);
+            assert_eq!(new, apply(&old, &new, &edits));
+            assert_eq!(shortest_script(&old, &new), count_changes(&edits));
+        }
+
+        // A rewritten file is every line deleted then every line inserted
+        let old: Vec<String> = (0..4000).map(|i| format!("old {}", i)).collect();
+        let new: Vec<String> = (0..4000).map(|i| format!("new {}", i)).collect();
+        let edits = diff_lines(&old, &new);
+        assert_eq!(8000, edits.len());
+        assert!(edits[..4000]
+            .iter()
+            .all(|edit| matches!(edit, LineEdit::Delete(_))));
+    }
+
+    #[test]
+    fn test_make_hunks() {
+        let old: Vec<String> = (0..20).map(|i| i.to_string()).collect();
+        let mut new = old.clone();
+        new[1] = "one".to_string();
+        new[3] = "three".to_string();
+        new.remove(15);
+
+        let edits = diff_lines(&old, &new);
+        let hunks = make_hunks(&edits, 3);
+        assert_eq!(2, hunks.len());
+
+        assert_eq!((0, 7, 0, 7), {
+            let h = &hunks[0];
+            (h.old_start, h.old_len, h.new_start, h.new_len)
+        });
+        assert_eq!((12, 7, 12, 6), {
+            let h = &hunks[1];
+            (h.old_start, h.old_len, h.new_start, h.new_len)
+        });
+    }
+}
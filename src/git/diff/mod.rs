@@ -0,0 +1,437 @@
+//! This module implements the diff engine: finding the files that differ between two
+//! snapshots, comparing their content line by line, and rendering the result in the
+//! formats git offers (patch, diffstat, ...).
+
+mod driver;
+mod format;
+mod lines;
+mod words;
+
+use std::path::Path;
+
+use rusqlite::Connection;
+
+use super::{
+    cache::ObjectCache,
+    model::{Blob, Commit, Sha1Id},
+    tree::{flatten_tree, flatten_tree_cached, FlatEntry, TreeView},
+    worktree::read_worktree_data,
+};
+
+pub use driver::{apply_drivers, DiffDriver};
+pub use format::{render, DiffOutput};
+pub use lines::{diff_lines, make_hunks, LineEdit};
+pub use words::{diff_words, WordChunk};
+
+/// Number of bytes inspected to decide whether a file is binary, as git does
+const BINARY_CHECK_LEN: usize = 8000;
+
+/// Where the content of the files on one side of a diff is read from
+#[derive(Debug, Clone, Copy)]
+pub enum ContentSource<'a> {
+    /// Blobs stored in the database
+    Database,
+    /// Files in the working tree rooted at the given path
+    Worktree(&'a Path),
+}
+
+/// [`FileVersion`] is the content of a file on one side of a diff
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileVersion {
+    pub id: Sha1Id,
+    pub mode: String,
+    pub data: Vec<u8>,
+}
+
+/// [`ChangeStatus`] tells how a file changed between the two sides of a diff
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChangeStatus {
+    Added,
+    Deleted,
+    Modified,
+    /// The file was moved from another path, with the given similarity percentage
+    Renamed {
+        from: String,
+        score: u8,
+    },
+    /// The file was copied from another path, with the given similarity percentage
+    Copied {
+        from: String,
+        score: u8,
+    },
+}
+
+impl ChangeStatus {
+    /// The status letter used by `--name-status` and friends, with the score if any
+    pub fn code(&self) -> String {
+        match self {
+            ChangeStatus::Added => "A".to_string(),
+            ChangeStatus::Deleted => "D".to_string(),
+            ChangeStatus::Modified => "M".to_string(),
+            ChangeStatus::Renamed { score, .. } => format!("R{:03}", score),
+            ChangeStatus::Copied { score, .. } => format!("C{:03}", score),
+        }
+    }
+}
+
+/// How hard to look for files that moved between the two sides of a diff
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RenameDetection {
+    /// Report moved files as a deletion and an addition
+    Off,
+    /// Pair deleted and added files with the same content
+    #[default]
+    Renames,
+    /// Also report added files with the same content as a file on the old side as copies
+    Copies,
+}
+
+/// [`FileChange`] describes a file that differs between the two sides of a diff.
+/// `name` is the path on the new side (on the old side for deletions), and a version is None
+/// if the file does not exist on that side.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileChange {
+    pub name: String,
+    pub status: ChangeStatus,
+    pub old: Option<FileVersion>,
+    pub new: Option<FileVersion>,
+    /// How to compare the versions, as selected by the attributes of the file
+    pub driver: DiffDriver,
+}
+
+impl FileChange {
+    /// Path of the file on the old side of the diff
+    pub fn old_name(&self) -> &str {
+        match &self.status {
+            ChangeStatus::Renamed { from, .. } | ChangeStatus::Copied { from, .. } => from,
+            _ => &self.name,
+        }
+    }
+
+    /// Whether the driver marks the file as binary or either version looks like binary data
+    /// (contains a NUL byte early on)
+    pub fn is_binary(&self) -> bool {
+        self.driver.binary
+            || [&self.old, &self.new]
+                .into_iter()
+                .flatten()
+                .any(|version| looks_binary(&version.data))
+    }
+
+    /// Lines of the old and new versions (empty for a missing side)
+    pub fn lines(&self) -> (Vec<&[u8]>, Vec<&[u8]>) {
+        fn split(version: &Option<FileVersion>) -> Vec<&[u8]> {
+            version
+                .as_ref()
+                .map(|version| split_lines(&version.data))
+                .unwrap_or_default()
+        }
+        (split(&self.old), split(&self.new))
+    }
+
+    /// Number of inserted and deleted lines, None for binary files
+    pub fn line_stats(&self) -> Option<(usize, usize)> {
+        if self.is_binary() {
+            return None;
+        }
+        let (old, new) = self.lines();
+        let edits = diff_lines(&old, &new);
+        let insertions = edits
+            .iter()
+            .filter(|edit| matches!(edit, LineEdit::Insert(_)))
+            .count();
+        let deletions = edits
+            .iter()
+            .filter(|edit| matches!(edit, LineEdit::Delete(_)))
+            .count();
+        Some((insertions, deletions))
+    }
+}
+
+/// Compare two snapshots and load both versions of each file that differs, sorted by name
+pub fn diff_views(
+    conn: &Connection,
+    old: &TreeView,
+    old_source: ContentSource,
+    new: &TreeView,
+    new_source: ContentSource,
+) -> crate::Result<Vec<FileChange>> {
+    let mut names: Vec<&String> = old.keys().chain(new.keys()).collect();
+    names.sort();
+    names.dedup();
+
+    let mut changes = Vec::new();
+    for name in names {
+        let (old_entry, new_entry) = (old.get(name), new.get(name));
+        if old_entry == new_entry {
+            continue;
+        }
+
+        let load = |entry: Option<&FlatEntry>, source| -> crate::Result<_> {
+            entry
+                .map(|entry| {
+                    Ok(FileVersion {
+                        id: entry.id,
+                        mode: entry.mode.clone(),
+                        data: read_content(conn, source, name, entry.id)?,
+                    })
+                })
+                .transpose()
+        };
+        let status = match (old_entry, new_entry) {
+            (None, _) => ChangeStatus::Added,
+            (_, None) => ChangeStatus::Deleted,
+            _ => ChangeStatus::Modified,
+        };
+        changes.push(FileChange {
+            name: name.clone(),
+            status,
+            old: load(old_entry, old_source)?,
+            new: load(new_entry, new_source)?,
+            driver: DiffDriver::default(),
+        });
+    }
+
+    Ok(changes)
+}
+
+/// The changes a commit makes to its first parent (to an empty tree for a root commit), with
+/// renamed files detected
+pub fn diff_commit(conn: &Connection, commit: &Commit<Sha1Id>) -> crate::Result<Vec<FileChange>> {
+    let old_view = match commit.parent_ids.first() {
+        Some(parent) => flatten_tree(conn, Commit::read_from_conn_with_id(conn, *parent)?.tree_id)?,
+        None => TreeView::new(),
+    };
+    let new_view = flatten_tree(conn, commit.tree_id)?;
+    diff_commit_views(conn, &old_view, &new_view)
+}
+
+/// [`diff_commit`] reading the parent commit and the trees through the cache, for commands
+/// diffing many commits of a history
+pub fn diff_commit_cached(
+    conn: &Connection,
+    cache: &mut ObjectCache,
+    commit: &Commit<Sha1Id>,
+) -> crate::Result<Vec<FileChange>> {
+    let old_view = match commit.parent_ids.first() {
+        Some(parent) => {
+            let tree_id = cache.read_commit(conn, *parent)?.tree_id;
+            flatten_tree_cached(conn, cache, tree_id)?
+        }
+        None => TreeView::new(),
+    };
+    let new_view = flatten_tree_cached(conn, cache, commit.tree_id)?;
+    diff_commit_views(conn, &old_view, &new_view)
+}
+
+fn diff_commit_views(
+    conn: &Connection,
+    old_view: &TreeView,
+    new_view: &TreeView,
+) -> crate::Result<Vec<FileChange>> {
+    let changes = diff_views(
+        conn,
+        old_view,
+        ContentSource::Database,
+        new_view,
+        ContentSource::Database,
+    )?;
+    Ok(detect_renames(
+        changes,
+        old_view,
+        RenameDetection::default(),
+    ))
+}
+
+/// Keep only the files of the snapshot that are, or are inside, one of the given paths
+/// (relative to the repository root). An empty list keeps every file.
+pub fn limit_to_paths(view: TreeView, paths: &[String]) -> TreeView {
+    if paths.is_empty() {
+        return view;
+    }
+    view.into_iter()
+        .filter(|(name, _)| matches_paths(name, paths))
+        .collect()
+}
+
+/// Whether the file is, or is inside, one of the given paths. An empty list matches every file.
+pub fn matches_paths(name: &str, paths: &[String]) -> bool {
+    paths.is_empty()
+        || paths.iter().any(|path| {
+            path.is_empty()
+                || name == path
+                || name
+                    .strip_prefix(path.as_str())
+                    .is_some_and(|rest| rest.starts_with('/'))
+        })
+}
+
+/// Find added files whose content is identical to a deleted file (renames) or, when looking
+/// for copies, to any file of the old snapshot (copies). Only exact matches are detected,
+/// so the similarity score is always 100.
+pub fn detect_renames(
+    changes: Vec<FileChange>,
+    old: &TreeView,
+    detection: RenameDetection,
+) -> Vec<FileChange> {
+    if detection == RenameDetection::Off {
+        return changes;
+    }
+
+    let (mut deleted, mut rest): (Vec<FileChange>, Vec<FileChange>) = changes
+        .into_iter()
+        .partition(|change| change.status == ChangeStatus::Deleted);
+
+    for change in rest.iter_mut() {
+        if change.status != ChangeStatus::Added {
+            continue;
+        }
+        let Some(new) = &change.new else {
+            continue;
+        };
+
+        if let Some(pos) = deleted
+            .iter()
+            .position(|d| d.old.as_ref().map(|v| v.id) == Some(new.id))
+        {
+            let source = deleted.remove(pos);
+            change.status = ChangeStatus::Renamed {
+                from: source.name,
+                score: 100,
+            };
+            change.old = source.old;
+        } else if detection == RenameDetection::Copies {
+            if let Some((from, entry)) = old.iter().find(|(_, entry)| entry.id == new.id) {
+                change.status = ChangeStatus::Copied {
+                    from: from.clone(),
+                    score: 100,
+                };
+                change.old = Some(FileVersion {
+                    id: entry.id,
+                    mode: entry.mode.clone(),
+                    data: new.data.clone(),
+                });
+            }
+        }
+    }
+
+    rest.extend(deleted);
+    rest.sort_by(|a, b| a.name.cmp(&b.name));
+    rest
+}
+
+fn read_content(
+    conn: &Connection,
+    source: ContentSource,
+    name: &str,
+    id: Sha1Id,
+) -> crate::Result<Vec<u8>> {
+    match source {
+        ContentSource::Database => Ok(Blob::read_from_conn_with_id(conn, id)?.data),
+        ContentSource::Worktree(root) => read_worktree_data(root, name),
+    }
+}
+
+/// Whether the content looks like binary data, i.e. contains a NUL byte early on
+pub fn looks_binary(data: &[u8]) -> bool {
+    data[..data.len().min(BINARY_CHECK_LEN)].contains(&0)
+}
+
+/// Split data into lines, each keeping its terminating newline (if any)
+pub fn split_lines(data: &[u8]) -> Vec<&[u8]> {
+    data.split_inclusive(|b| *b == b'\n').collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(byte: u8) -> Option<FileVersion> {
+        Some(FileVersion {
+            id: Sha1Id::try_from(vec![byte; 20]).unwrap(),
+            mode: "33188".to_string(),
+            data: vec![byte],
+        })
+    }
+
+    fn change(name: &str, status: ChangeStatus, old: u8, new: u8) -> FileChange {
+        FileChange {
+            name: name.to_string(),
+            status,
+            old: (old != 0).then(|| version(old)).flatten(),
+            new: (new != 0).then(|| version(new)).flatten(),
+            driver: DiffDriver::default(),
+        }
+    }
+
+    #[test]
+    fn test_limit_to_paths() {
+        let view: TreeView = ["a.txt", "dir/b.txt", "dir2/c.txt"]
+            .into_iter()
+            .map(|name| {
+                let entry = FlatEntry {
+                    id: Sha1Id::try_from(vec![1; 20]).unwrap(),
+                    mode: "33188".to_string(),
+                };
+                (name.to_string(), entry)
+            })
+            .collect();
+
+        let names = |paths: &[&str]| -> Vec<String> {
+            let paths: Vec<String> = paths.iter().map(|p| p.to_string()).collect();
+            limit_to_paths(view.clone(), &paths).into_keys().collect()
+        };
+        assert_eq!(3, names(&[]).len());
+        assert_eq!(vec!["dir/b.txt"], names(&["dir"]));
+        assert_eq!(vec!["a.txt", "dir2/c.txt"], names(&["a.txt", "dir2"]));
+        assert!(names(&["di"]).is_empty());
+    }
+
+    #[test]
+    fn test_detect_renames() {
+        let old: TreeView = [("gone", 1), ("kept", 2), ("modified", 3)]
+            .into_iter()
+            .map(|(name, byte)| {
+                let entry = FlatEntry {
+                    id: Sha1Id::try_from(vec![byte; 20]).unwrap(),
+                    mode: "33188".to_string(),
+                };
+                (name.to_string(), entry)
+            })
+            .collect();
+        let changes = vec![
+            change("copy", ChangeStatus::Added, 0, 2),
+            change("gone", ChangeStatus::Deleted, 1, 0),
+            change("modified", ChangeStatus::Modified, 3, 4),
+            change("moved", ChangeStatus::Added, 0, 1),
+        ];
+
+        let renamed = detect_renames(changes.clone(), &old, RenameDetection::Renames);
+        let codes: Vec<(String, &str)> = renamed
+            .iter()
+            .map(|c| (c.status.code(), c.old_name()))
+            .collect();
+        assert_eq!(
+            vec![
+                ("A".to_string(), "copy"),
+                ("M".to_string(), "modified"),
+                ("R100".to_string(), "gone"),
+            ],
+            codes
+        );
+
+        let copied = detect_renames(changes.clone(), &old, RenameDetection::Copies);
+        assert_eq!(
+            ChangeStatus::Copied {
+                from: "kept".to_string(),
+                score: 100
+            },
+            copied[0].status
+        );
+
+        assert_eq!(
+            changes,
+            detect_renames(changes.clone(), &old, RenameDetection::Off)
+        );
+    }
+}
@@ -0,0 +1,133 @@
+//! Word-level comparison of changed text, used to highlight intra-line changes. Words are
+//! runs of non-whitespace characters; whitespace only separates them and is taken from the
+//! new text where possible.
+
+use super::{diff_lines, LineEdit};
+
+/// [`WordChunk`] is a piece of a word-level diff. Concatenating the equal and inserted
+/// chunks gives back the new text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WordChunk<'a> {
+    Equal(&'a str),
+    Deleted(&'a str),
+    Inserted(&'a str),
+}
+
+/// Byte ranges of the words of the text
+pub fn tokenize(text: &str) -> Vec<(usize, usize)> {
+    let mut words = Vec::new();
+    let mut start = None;
+    for (i, c) in text.char_indices() {
+        match (c.is_whitespace(), start) {
+            (true, Some(s)) => {
+                words.push((s, i));
+                start = None;
+            }
+            (false, None) => start = Some(i),
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        words.push((s, text.len()));
+    }
+    words
+}
+
+/// Compare two texts word by word. Consecutive changed words are reported as a single
+/// deleted and/or inserted chunk spanning them.
+pub fn diff_words<'a>(old: &'a str, new: &'a str) -> Vec<WordChunk<'a>> {
+    let old_words = tokenize(old);
+    let new_words = tokenize(new);
+    let old_tokens: Vec<&str> = old_words.iter().map(|(s, e)| &old[*s..*e]).collect();
+    let new_tokens: Vec<&str> = new_words.iter().map(|(s, e)| &new[*s..*e]).collect();
+    let edits = diff_lines(&old_tokens, &new_tokens);
+
+    let mut chunks = Vec::new();
+    // End of the text already accounted for on each side
+    let (mut old_pos, mut new_pos) = (0, 0);
+    let push_equal = |chunks: &mut Vec<WordChunk<'a>>, text: &'a str| {
+        if !text.is_empty() {
+            chunks.push(WordChunk::Equal(text));
+        }
+    };
+
+    let mut i = 0;
+    while i < edits.len() {
+        if let LineEdit::Equal(x, y) = edits[i] {
+            push_equal(&mut chunks, &new[new_pos..new_words[y].1]);
+            old_pos = old_words[x].1;
+            new_pos = new_words[y].1;
+            i += 1;
+            continue;
+        }
+
+        let mut deleted = Vec::new();
+        let mut inserted = Vec::new();
+        while i < edits.len() {
+            match edits[i] {
+                LineEdit::Delete(x) => deleted.push(x),
+                LineEdit::Insert(y) => inserted.push(y),
+                LineEdit::Equal(..) => break,
+            }
+            i += 1;
+        }
+
+        // Whitespace before the change comes from the new side unless only words were removed
+        let whitespace = match (inserted.first(), deleted.first()) {
+            (Some(y), _) => &new[new_pos..new_words[*y].0],
+            (None, Some(x)) => &old[old_pos..old_words[*x].0],
+            (None, None) => "",
+        };
+        push_equal(&mut chunks, whitespace);
+
+        if let (Some(first), Some(last)) = (deleted.first(), deleted.last()) {
+            chunks.push(WordChunk::Deleted(
+                &old[old_words[*first].0..old_words[*last].1],
+            ));
+            old_pos = old_words[*last].1;
+        }
+        if let (Some(first), Some(last)) = (inserted.first(), inserted.last()) {
+            chunks.push(WordChunk::Inserted(
+                &new[new_words[*first].0..new_words[*last].1],
+            ));
+            new_pos = new_words[*last].1;
+        }
+    }
+    push_equal(&mut chunks, &new[new_pos..]);
+
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(vec![(0, 3), (5, 6), (7, 9)], tokenize("foo  b\tc;\n"));
+        assert!(tokenize(" \n").is_empty());
+    }
+
+    #[test]
+    fn test_diff_words() {
+        use WordChunk::*;
+
+        assert_eq!(
+            vec![
+                Equal("a"),
+                Equal(" "),
+                Deleted("b"),
+                Inserted("x y"),
+                Equal(" c"),
+                Equal("\n")
+            ],
+            diff_words("a b c\n", "a x y c\n")
+        );
+        assert_eq!(
+            vec![Equal("a"), Equal(" "), Deleted("b"), Equal(" c")],
+            diff_words("a b c", "a c")
+        );
+        assert_eq!(vec![Inserted("new"), Equal("\n")], diff_words("", "new\n"));
+        assert_eq!(vec![Deleted("old")], diff_words("old\n", ""));
+    }
+}
@@ -0,0 +1,257 @@
+//! This module implements `--dry-run` for the commands which opt in to it. The connections a
+//! dry run opens run the command in a transaction that is never committed, so that the
+//! command runs as usual but its writes are rolled back when the connection closes. Temporary
+//! triggers report the rows the command changes to [`record_change`] as it goes, keeping the
+//! value of each row before the command and after it. Writes outside of the database, to the
+//! working tree or other files, and the hooks, are not carried out either: they are recorded
+//! instead. Once the command is done, [`report`] tells what it would have changed.
+//!
+//! Commands which write elsewhere, such as pushes to a remote or changes to the config files,
+//! do not opt in, and refuse to run with `--dry-run`.
+
+use std::{
+    collections::BTreeMap,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
+
+use rusqlite::{functions::FunctionFlags, types::Value, Connection};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static CHANGES: Mutex<BTreeMap<(Kind, String), Change>> = Mutex::new(BTreeMap::new());
+static ACTIONS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// The triggers reporting changes to the rows of the tables a dry run reports on. Each row is
+/// reported under its kind and key, with its value before and after the change, NULL when it
+/// did not or does not exist. An update is reported as a deletion of the old row followed by an
+/// insertion of the new one, and the value of an object is whether it exists. Blobs may be
+/// stored twice, so that their insertions and deletions check for other copies.
+const CREATE_TRIGGERS: &str = "
+    CREATE TEMP TRIGGER DryRun_Refs_insert AFTER INSERT ON main.Refs BEGIN
+        SELECT gitqlite_dry_run('ref', NEW.ref_name, NULL, NEW.commit_id);
+    END;
+    CREATE TEMP TRIGGER DryRun_Refs_update AFTER UPDATE ON main.Refs BEGIN
+        SELECT gitqlite_dry_run('ref', OLD.ref_name, OLD.commit_id, NULL);
+        SELECT gitqlite_dry_run('ref', NEW.ref_name, NULL, NEW.commit_id);
+    END;
+    CREATE TEMP TRIGGER DryRun_Refs_delete AFTER DELETE ON main.Refs BEGIN
+        SELECT gitqlite_dry_run('ref', OLD.ref_name, OLD.commit_id, NULL);
+    END;
+    CREATE TEMP TRIGGER DryRun_Head_insert AFTER INSERT ON main.Head BEGIN
+        SELECT gitqlite_dry_run('head', 'HEAD', NULL, NEW.head);
+    END;
+    CREATE TEMP TRIGGER DryRun_Head_update AFTER UPDATE ON main.Head BEGIN
+        SELECT gitqlite_dry_run('head', 'HEAD', OLD.head, NEW.head);
+    END;
+    CREATE TEMP TRIGGER DryRun_Head_delete AFTER DELETE ON main.Head BEGIN
+        SELECT gitqlite_dry_run('head', 'HEAD', OLD.head, NULL);
+    END;";
+/// Legacy databases keep the index in a single row, which is left out
+const CREATE_INDEX_TRIGGERS: &str = "
+    CREATE TEMP TRIGGER DryRun_Index_insert AFTER INSERT ON main.Index_ BEGIN
+        SELECT gitqlite_dry_run('index', NEW.stage || ' ' || NEW.name, NULL,
+            hex(NEW.sha) || ' ' || NEW.mode_perms || ' ' || NEW.intent_to_add);
+    END;
+    CREATE TEMP TRIGGER DryRun_Index_update AFTER UPDATE ON main.Index_ BEGIN
+        SELECT gitqlite_dry_run('index', OLD.stage || ' ' || OLD.name,
+            hex(OLD.sha) || ' ' || OLD.mode_perms || ' ' || OLD.intent_to_add, NULL);
+        SELECT gitqlite_dry_run('index', NEW.stage || ' ' || NEW.name, NULL,
+            hex(NEW.sha) || ' ' || NEW.mode_perms || ' ' || NEW.intent_to_add);
+    END;
+    CREATE TEMP TRIGGER DryRun_Index_delete AFTER DELETE ON main.Index_ BEGIN
+        SELECT gitqlite_dry_run('index', OLD.stage || ' ' || OLD.name,
+            hex(OLD.sha) || ' ' || OLD.mode_perms || ' ' || OLD.intent_to_add, NULL);
+    END;";
+const READ_INDEX_LAYOUT: &str =
+    "SELECT count(*) > 0 FROM pragma_table_info('Index_') WHERE name = 'name'";
+const OBJECT_TABLES: [(&str, &str, &str); 3] = [
+    ("Commits", "commit_id", "commit"),
+    ("Trees", "tree_id", "tree"),
+    ("Blobs", "blob_id", "blob"),
+];
+
+/// [`Kind`] is what a changed row records, in the order [`report`] lists them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Kind {
+    Ref,
+    Head,
+    Index,
+    Commit,
+    Tree,
+    Blob,
+}
+
+impl Kind {
+    fn parse(kind: &str) -> Option<Kind> {
+        Some(match kind {
+            "ref" => Kind::Ref,
+            "head" => Kind::Head,
+            "index" => Kind::Index,
+            "commit" => Kind::Commit,
+            "tree" => Kind::Tree,
+            "blob" => Kind::Blob,
+            _ => return None,
+        })
+    }
+}
+
+/// [`Change`] is the value of a row before the command and after its last change
+#[derive(Debug, Clone, PartialEq)]
+struct Change {
+    old: Value,
+    new: Value,
+}
+
+/// Turn dry runs on for the rest of the process
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Open the transaction a dry run keeps on `conn` until it closes, so that none of the writes
+/// made through it are committed. Settings which cannot change inside a transaction, such as
+/// the journal mode, must be applied first.
+pub fn begin(conn: &Connection) -> crate::Result<()> {
+    if !is_enabled() {
+        return Ok(());
+    }
+    conn.execute_batch("BEGIN")?;
+    Ok(())
+}
+
+/// Report the changes made through `conn` from now on to [`report`]. The schema must be up to
+/// date, so that upgrading it is not reported.
+pub fn track_changes(conn: &Connection) -> crate::Result<()> {
+    if !is_enabled() {
+        return Ok(());
+    }
+    conn.create_scalar_function("gitqlite_dry_run", 4, FunctionFlags::SQLITE_UTF8, |ctx| {
+        let kind = Kind::parse(&ctx.get::<String>(0)?)
+            .ok_or_else(|| rusqlite::Error::UserFunctionError("unknown kind of change".into()))?;
+        record_change(kind, ctx.get(1)?, ctx.get(2)?, ctx.get(3)?);
+        Ok(Value::Null)
+    })?;
+    conn.execute_batch(CREATE_TRIGGERS)?;
+    if conn.query_row(READ_INDEX_LAYOUT, (), |row| row.get(0))? {
+        conn.execute_batch(CREATE_INDEX_TRIGGERS)?;
+    }
+    for (table, id, kind) in OBJECT_TABLES {
+        conn.execute_batch(&format!(
+            "CREATE TEMP TRIGGER DryRun_{table}_insert AFTER INSERT ON main.{table} BEGIN
+                SELECT gitqlite_dry_run('{kind}', hex(NEW.{id}),
+                    EXISTS (SELECT 1 FROM main.{table} WHERE {id} = NEW.{id} AND rowid != NEW.rowid), 1);
+            END;
+            CREATE TEMP TRIGGER DryRun_{table}_delete AFTER DELETE ON main.{table} BEGIN
+                SELECT gitqlite_dry_run('{kind}', hex(OLD.{id}), 1,
+                    EXISTS (SELECT 1 FROM main.{table} WHERE {id} = OLD.{id}));
+            END;"
+        ))?;
+    }
+    Ok(())
+}
+
+/// Record that the row `key` of `kind` went from `old` to `new`, keeping the value it had
+/// before its first change
+fn record_change(kind: Kind, key: String, old: Value, new: Value) {
+    CHANGES
+        .lock()
+        .expect("dry run changes lock poisoned")
+        .entry((kind, key))
+        .and_modify(|change| change.new = new.clone())
+        .or_insert(Change { old, new });
+}
+
+/// Whether a write outside of the database, described by `action` on `path` (e.g. `remove`),
+/// must be skipped, recording it in a dry run
+pub fn skip_write(action: &str, path: &Path) -> bool {
+    if !is_enabled() {
+        return false;
+    }
+    ACTIONS
+        .lock()
+        .expect("dry run actions lock poisoned")
+        .push(format!("{} {}", action, path.display()));
+    true
+}
+
+/// Print what the command would have changed
+pub fn report() -> crate::Result<()> {
+    let changes = std::mem::take(&mut *CHANGES.lock().expect("dry run changes lock poisoned"));
+    let actions = std::mem::take(&mut *ACTIONS.lock().expect("dry run actions lock poisoned"));
+
+    let short = |id: &Value| match id {
+        Value::Blob(bytes) => bytes
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>()[..7]
+            .to_string(),
+        _ => "(none)".to_string(),
+    };
+    let mut lines = Vec::new();
+    let mut staged = BTreeMap::<&str, (bool, bool)>::new();
+    let mut objects = BTreeMap::<Kind, (u64, u64)>::new();
+    for ((kind, key), Change { old, new }) in &changes {
+        if old == new {
+            continue;
+        }
+        match kind {
+            Kind::Ref => lines.push(match (old, new) {
+                (Value::Null, _) => format!("create ref {} at {}", key, short(new)),
+                (_, Value::Null) => format!("delete ref {} (was {})", key, short(old)),
+                _ => format!("update ref {}: {} -> {}", key, short(old), short(new)),
+            }),
+            Kind::Head => lines.push("move HEAD".to_string()),
+            Kind::Index => {
+                let name = key.split_once(' ').map_or(key.as_str(), |(_, name)| name);
+                let (changed, present) = staged.entry(name).or_default();
+                *changed = true;
+                *present |= *new != Value::Null;
+            }
+            _ => {
+                let (written, removed) = objects.entry(*kind).or_default();
+                match new {
+                    Value::Integer(1) => *written += 1,
+                    _ => *removed += 1,
+                }
+            }
+        }
+    }
+    for (action, present) in [("stage", true), ("unstage", false)] {
+        lines.extend(
+            staged
+                .iter()
+                .filter(|(_, (changed, staged))| *changed && *staged == present)
+                .map(|(name, _)| format!("{} {}", action, name)),
+        );
+    }
+    for (kind, (written, removed)) in objects {
+        let name = match kind {
+            Kind::Commit => "commit",
+            Kind::Tree => "tree",
+            _ => "blob",
+        };
+        let plural =
+            |count: u64| format!("{} {}{}", count, name, if count == 1 { "" } else { "s" });
+        if written > 0 {
+            lines.push(format!("write {}", plural(written)));
+        }
+        if removed > 0 {
+            lines.push(format!("remove {}", plural(removed)));
+        }
+    }
+    lines.extend(actions);
+
+    if lines.is_empty() {
+        println!("(dry run) nothing would change");
+    }
+    for line in lines {
+        println!("(dry run) would {}", line);
+    }
+    Ok(())
+}
@@ -0,0 +1,153 @@
+//! This module exports the repository database as a plain-text SQL script (schema followed by
+//! data, in the style of sqlite3's `.dump`) and rebuilds a database from such a script.
+
+use std::io::Write;
+
+use rusqlite::{types::ValueRef, Connection};
+
+/// Schema of the tables, in creation order. SQLite's internal tables are left out, as are the
+/// shadow tables holding the content of virtual tables, which creating the virtual table
+/// recreates.
+const READ_TABLES: &str = "SELECT m.name, m.sql FROM sqlite_master m JOIN pragma_table_list l ON l.schema = 'main' AND l.name = m.name WHERE m.type = 'table' AND l.type IN ('table', 'virtual') AND m.name NOT LIKE 'sqlite_%' ORDER BY m.rowid";
+/// Indexes, triggers and views, created after the data is inserted
+const READ_OTHER_SCHEMA: &str = "SELECT sql FROM sqlite_master WHERE type IN ('index', 'trigger', 'view') AND sql IS NOT NULL ORDER BY rowid";
+
+/// Write the schema and content of the database as SQL statements
+pub fn dump_database(conn: &Connection, out: &mut impl Write) -> crate::Result<()> {
+    writeln!(out, "PRAGMA foreign_keys=OFF;")?;
+    let version: i64 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    if version != 0 {
+        writeln!(out, "PRAGMA user_version={};", version)?;
+    }
+    writeln!(out, "BEGIN TRANSACTION;")?;
+
+    let tables = conn
+        .prepare(READ_TABLES)?
+        .query_map((), |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    for (name, sql) in &tables {
+        writeln!(out, "{};", sql)?;
+        dump_rows(conn, name, out)?;
+    }
+
+    // Keep the counters of AUTOINCREMENT tables so that ids are not reused after a load
+    let has_sequence: bool = conn.query_row(
+        "SELECT count(*) > 0 FROM sqlite_master WHERE name = 'sqlite_sequence'",
+        (),
+        |row| row.get(0),
+    )?;
+    if has_sequence {
+        writeln!(out, "DELETE FROM sqlite_sequence;")?;
+        dump_rows(conn, "sqlite_sequence", out)?;
+    }
+
+    let others = conn
+        .prepare(READ_OTHER_SCHEMA)?
+        .query_map((), |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    for sql in others {
+        writeln!(out, "{};", sql)?;
+    }
+
+    writeln!(out, "COMMIT;")?;
+    Ok(())
+}
+
+/// Run the statements of a dump against the (empty) database
+pub fn load_database(conn: &Connection, sql: &str) -> crate::Result<()> {
+    conn.execute_batch(sql)?;
+    Ok(())
+}
+
+fn dump_rows(conn: &Connection, table: &str, out: &mut impl Write) -> crate::Result<()> {
+    let table = quote_identifier(table);
+    let mut stmt = conn.prepare(&format!("SELECT * FROM {}", table))?;
+    let columns = stmt.column_count();
+
+    let mut rows = stmt.query(())?;
+    while let Some(row) = rows.next()? {
+        let values = (0..columns)
+            .map(|i| Ok(format_value(row.get_ref(i)?)))
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        writeln!(out, "INSERT INTO {} VALUES({});", table, values.join(","))?;
+    }
+    Ok(())
+}
+
+/// Render a value as an SQL literal
+fn format_value(value: ValueRef) -> String {
+    match value {
+        ValueRef::Null => "NULL".to_string(),
+        ValueRef::Integer(i) => i.to_string(),
+        ValueRef::Real(f) => format!("{:?}", f),
+        ValueRef::Text(text) => quote_string(&String::from_utf8_lossy(text)),
+        ValueRef::Blob(blob) => {
+            let hex: String = blob.iter().map(|b| format!("{:02X}", b)).collect();
+            format!("X'{}'", hex)
+        }
+    }
+}
+
+fn quote_string(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "''"))
+}
+
+fn quote_identifier(s: &str) -> String {
+    if s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        s.to_string()
+    } else {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::model::{CREATE_BLOB_TABLE, CREATE_STASH_TABLE, INSERT_STASH};
+
+    fn dump(conn: &Connection) -> String {
+        let mut out = Vec::new();
+        dump_database(conn, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_dump_and_load() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute(CREATE_BLOB_TABLE, ()).unwrap();
+        conn.execute(CREATE_STASH_TABLE, ()).unwrap();
+        conn.execute(
+            "INSERT INTO Blobs (blob_id, data, created_at) VALUES (?1, ?2, 0)",
+            (vec![0xabu8, 0x01], b"it's\nbinary\0".to_vec()),
+        )
+        .unwrap();
+        conn.execute(INSERT_STASH, (vec![1u8; 2], "WIP on 'main'\nbody"))
+            .unwrap();
+        conn.execute("DELETE FROM Stash", ()).unwrap();
+        conn.execute(INSERT_STASH, (vec![2u8; 2], "second"))
+            .unwrap();
+
+        let text = dump(&conn);
+        assert!(
+            text.starts_with("PRAGMA foreign_keys=OFF;\nBEGIN TRANSACTION;\nCREATE TABLE Blobs")
+        );
+        assert!(text.contains("INSERT INTO Blobs VALUES(X'AB01',X'697427730A62696E61727900',0);"));
+        assert!(text.contains("INSERT INTO Stash VALUES(2,X'0202','second');"));
+        assert!(text.ends_with("COMMIT;\n"));
+
+        let loaded = Connection::open_in_memory().unwrap();
+        load_database(&loaded, &text).unwrap();
+        assert_eq!(text, dump(&loaded));
+
+        // The stash counter survives, so a new entry does not reuse an old id
+        loaded
+            .execute(INSERT_STASH, (vec![3u8; 2], "third"))
+            .unwrap();
+        let id: i64 = loaded
+            .query_row("SELECT max(stash_id) FROM Stash", (), |row| row.get(0))
+            .unwrap();
+        assert_eq!(3, id);
+    }
+}
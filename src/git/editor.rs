@@ -0,0 +1,82 @@
+//! This module launches the user's editor to edit text such as commit messages and rebase
+//! todo lists, following git's lookup order: `GIT_EDITOR`, `core.editor`, `VISUAL`, `EDITOR`.
+
+use std::{fs, path::Path, process::Command};
+
+use anyhow::anyhow;
+
+use crate::repo::config::{ConfigSource, GitConfig};
+
+/// Editor used when nothing is configured
+const DEFAULT_EDITOR: &str = "vi";
+
+/// Find the editor command configured by the user
+pub fn editor_command(config: &GitConfig) -> crate::Result<String> {
+    if let Ok(editor) = std::env::var("GIT_EDITOR") {
+        return Ok(editor);
+    }
+    if let Some(editor) = config.get("core.editor", ConfigSource::All)? {
+        return Ok(editor.to_string());
+    }
+    for var in ["VISUAL", "EDITOR"] {
+        if let Ok(editor) = std::env::var(var) {
+            return Ok(editor);
+        }
+    }
+    Ok(DEFAULT_EDITOR.to_string())
+}
+
+/// Write `initial` to `path`, let the user edit it and return the edited text with
+/// comment lines (starting with '#') removed.
+pub fn edit_text(
+    config: &GitConfig,
+    path: impl AsRef<Path>,
+    initial: &str,
+) -> crate::Result<String> {
+    let path = path.as_ref();
+    fs::write(path, initial)?;
+
+    let editor = editor_command(config)?;
+    // Run through the shell so that editor commands with arguments (e.g., "code --wait") work
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$@\"", editor))
+        .arg(&editor)
+        .arg(path)
+        .status()?;
+    if !status.success() {
+        return Err(anyhow!(
+            "error: There was a problem with the editor '{}'.",
+            editor
+        ));
+    }
+
+    let text = fs::read_to_string(path)?;
+    fs::remove_file(path)?;
+    Ok(strip_comments(&text))
+}
+
+/// Remove comment lines and surrounding blank lines from the text, and collapse consecutive
+/// blank lines into one
+pub fn strip_comments(text: &str) -> String {
+    let mut lines: Vec<&str> = Vec::new();
+    for line in text.lines().filter(|line| !line.starts_with('#')) {
+        let line = line.trim_end();
+        if line.is_empty() && lines.last().is_some_and(|last| last.is_empty()) {
+            continue;
+        }
+        lines.push(line);
+    }
+    lines.join("\n").trim().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_comments() {
+        let text = "\nsubject\n\n# comment\n\nbody   \n# comment\n# another\n\n";
+        assert_eq!("subject\n\nbody", strip_comments(text));
+    }
+}
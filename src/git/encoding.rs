@@ -0,0 +1,78 @@
+//! This module handles the character encodings of commit messages, for repositories whose
+//! history was written in something else than UTF-8.
+//!
+//! Messages are always kept as UTF-8 in the database, along with the encoding they were
+//! written in. `i18n.commitEncoding` is the encoding new commits declare, and
+//! `i18n.logOutputEncoding`, which defaults to the former, the one commits are displayed in.
+
+use std::borrow::Cow;
+
+use anyhow::anyhow;
+use encoding_rs::{Encoding, UTF_8};
+
+use crate::repo::config::{ConfigSource, GitConfig};
+
+/// The encoding new commits are written in, None for UTF-8
+pub fn commit_encoding(config: &GitConfig) -> crate::Result<Option<&'static Encoding>> {
+    match config.get("i18n.commitEncoding", ConfigSource::All)? {
+        Some(label) => Ok(Some(lookup(label)?).filter(|encoding| *encoding != UTF_8)),
+        None => Ok(None),
+    }
+}
+
+/// The encoding commits are displayed in
+pub fn log_output_encoding(config: &GitConfig) -> crate::Result<&'static Encoding> {
+    match config.get("i18n.logOutputEncoding", ConfigSource::All)? {
+        Some(label) => lookup(label),
+        None => Ok(commit_encoding(config)?.unwrap_or(UTF_8)),
+    }
+}
+
+/// Look up an encoding by any of its names, e.g. `latin1` or `ISO-8859-1`
+pub fn lookup(label: &str) -> crate::Result<&'static Encoding> {
+    Encoding::for_label(label.trim().as_bytes())
+        .ok_or_else(|| anyhow!("fatal: unknown encoding '{}'", label))
+}
+
+/// Convert a UTF-8 message to `encoding`, failing if some character has no representation
+pub fn encode_message<'a>(
+    message: &'a str,
+    encoding: &'static Encoding,
+) -> crate::Result<Cow<'a, [u8]>> {
+    let (bytes, _, unmappable) = encoding.encode(message);
+    if unmappable {
+        return Err(anyhow!(
+            "fatal: the commit message cannot be written in {}",
+            encoding.name()
+        ));
+    }
+    Ok(bytes)
+}
+
+/// Convert text to `encoding` for display, characters without a representation being written
+/// as numeric character references
+pub fn encode_lossy<'a>(text: &'a str, encoding: &'static Encoding) -> Cow<'a, [u8]> {
+    encoding.encode(text).0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        let latin1 = lookup("latin1").unwrap();
+        assert_eq!("windows-1252", latin1.name());
+        assert_eq!(b"caf\xe9", encode_message("café", latin1).unwrap().as_ref());
+        assert!(encode_message("日本", latin1).is_err());
+        assert_eq!(b"&#26085;", encode_lossy("日", latin1).as_ref());
+
+        let sjis = lookup("Shift_JIS").unwrap();
+        assert_eq!(b"\x93\xfa", encode_message("日", sjis).unwrap().as_ref());
+        assert_eq!(
+            "café".as_bytes(),
+            encode_message("café", UTF_8).unwrap().as_ref()
+        );
+        assert!(lookup("klingon").is_err());
+    }
+}
@@ -0,0 +1,208 @@
+//! This module maintains the `FileChanges` table, an index of the files each commit changes
+//! in its first parent, as `git whatchanged` lists them. The table is derived from the trees
+//! of the commits and filled in as commits are written or received, so that the history of a
+//! path is an indexed lookup rather than a diff of the trees of every commit.
+
+use std::collections::HashMap;
+
+use rusqlite::Connection;
+
+use super::{
+    cache::ObjectCache,
+    diff::{diff_commit_cached, ChangeStatus},
+    model::{Commit, Sha1Id},
+    revision::commits_between,
+};
+
+/// Files changed by each commit. `change_type` is the status code of the change (`A`, `M`,
+/// `D`, or `R`/`C` followed by the similarity score), `old_path` the source of a rename or
+/// copy, and a blob is NULL when the file does not exist on that side.
+pub const CREATE_FILE_CHANGES_TABLE: &str = "CREATE TABLE FileChanges (commit_id BLOB NOT NULL, path TEXT NOT NULL, change_type TEXT NOT NULL, old_path TEXT, old_blob BLOB, new_blob BLOB, PRIMARY KEY (commit_id, path));";
+pub const CREATE_FILE_CHANGES_PATH_INDEX: &str =
+    "CREATE INDEX FileChanges_path ON FileChanges (path);";
+/// The renames among the changes, with the similarity of the two versions of the file as a
+/// percentage, for queries looking up where a file came from
+pub const CREATE_RENAMES_VIEW: &str = "CREATE VIEW Renames AS SELECT commit_id, old_path, path AS new_path, CAST(substr(change_type, 2) AS INTEGER) AS similarity FROM FileChanges WHERE change_type LIKE 'R%';";
+const INSERT_FILE_CHANGE: &str = "INSERT OR REPLACE INTO FileChanges (commit_id, path, change_type, old_path, old_blob, new_blob) VALUES (?1, ?2, ?3, ?4, ?5, ?6);";
+const DELETE_FILE_CHANGES: &str = "DELETE FROM FileChanges WHERE commit_id = ?1";
+const READ_CHANGES_OF_PATH: &str = "SELECT commit_id, path, change_type, old_path, old_blob, new_blob FROM FileChanges WHERE path = ?1";
+const READ_CHANGES_OF_COMMIT: &str = "SELECT commit_id, path, change_type, old_path, old_blob, new_blob FROM FileChanges WHERE commit_id = ?1 ORDER BY path";
+const READ_ALL_COMMIT_IDS: &str = "SELECT commit_id FROM Commits";
+
+/// [`RecordedChange`] is a row of the `FileChanges` table
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordedChange {
+    pub commit_id: Sha1Id,
+    pub path: String,
+    pub change_type: String,
+    pub old_path: Option<String>,
+    pub old_blob: Option<Sha1Id>,
+    pub new_blob: Option<Sha1Id>,
+}
+
+impl RecordedChange {
+    fn from_row(row: &rusqlite::Row) -> rusqlite::Result<RecordedChange> {
+        Ok(RecordedChange {
+            commit_id: row.get(0)?,
+            path: row.get(1)?,
+            change_type: row.get(2)?,
+            old_path: row.get(3)?,
+            old_blob: row.get(4)?,
+            new_blob: row.get(5)?,
+        })
+    }
+}
+
+/// Record the files changed by the given commits
+pub fn record_file_changes(conn: &Connection, commits: &[&Commit<Sha1Id>]) -> crate::Result<()> {
+    if commits.is_empty() {
+        return Ok(());
+    }
+    let mut insert = conn.prepare_cached(INSERT_FILE_CHANGE)?;
+    // Successive commits mostly share their trees, and each is diffed to its parent
+    let mut cache = ObjectCache::default();
+    for commit in commits {
+        for change in diff_commit_cached(conn, &mut cache, commit)? {
+            let old_path = match &change.status {
+                ChangeStatus::Renamed { from, .. } | ChangeStatus::Copied { from, .. } => {
+                    Some(from.as_str())
+                }
+                _ => None,
+            };
+            insert.execute((
+                commit.commit_id,
+                &change.name,
+                change.status.code(),
+                old_path,
+                change.old.as_ref().map(|old| old.id),
+                change.new.as_ref().map(|new| new.id),
+            ))?;
+        }
+    }
+    Ok(())
+}
+
+/// Record the files changed by every commit, for a table created after the history was written
+pub fn record_all_file_changes(conn: &Connection) -> crate::Result<()> {
+    let ids = conn
+        .prepare(READ_ALL_COMMIT_IDS)?
+        .query_map((), |row| row.get(0))?
+        .collect::<Result<Vec<Sha1Id>, _>>()?;
+    let commits = ids
+        .into_iter()
+        .map(|id| Commit::read_from_conn_with_id(conn, id))
+        .collect::<crate::Result<Vec<_>>>()?;
+    record_file_changes(conn, &commits.iter().collect::<Vec<_>>())
+}
+
+/// Forget the files changed by a commit that is being deleted
+pub fn forget_file_changes(conn: &Connection, commit_id: Sha1Id) -> crate::Result<()> {
+    conn.prepare_cached(DELETE_FILE_CHANGES)?
+        .execute([commit_id])?;
+    Ok(())
+}
+
+/// The files changed by a commit, ordered by path
+pub fn read_file_changes(
+    conn: &Connection,
+    commit_id: Sha1Id,
+) -> crate::Result<Vec<RecordedChange>> {
+    let mut stmt = conn.prepare_cached(READ_CHANGES_OF_COMMIT)?;
+    let changes = stmt
+        .query_map([commit_id], RecordedChange::from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(changes)
+}
+
+/// The changes to `path` made by the commits reachable from `tip`, the most recent first.
+/// The path is followed through renames: changes older than the commit renaming a file to
+/// `path` are looked up under the name the file had before.
+pub fn path_history(
+    conn: &Connection,
+    tip: Sha1Id,
+    path: &str,
+) -> crate::Result<Vec<RecordedChange>> {
+    let read_changes = |path: &str| -> crate::Result<HashMap<Sha1Id, RecordedChange>> {
+        let mut stmt = conn.prepare_cached(READ_CHANGES_OF_PATH)?;
+        let rows = stmt.query_map([path], RecordedChange::from_row)?;
+        let mut changes = HashMap::new();
+        for row in rows {
+            let change = row?;
+            changes.insert(change.commit_id, change);
+        }
+        Ok(changes)
+    };
+
+    let mut changes = read_changes(path)?;
+    let mut history = Vec::new();
+    for id in commits_between(conn, None, tip)?.into_iter().rev() {
+        let Some(change) = changes.remove(&id) else {
+            continue;
+        };
+        let renamed_from = change
+            .old_path
+            .clone()
+            .filter(|_| change.change_type.starts_with('R'));
+        history.push(change);
+        if let Some(from) = renamed_from {
+            changes = read_changes(&from)?;
+        }
+    }
+    Ok(history)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::test_utils::{commit_files, object_database};
+
+    fn commit(conn: &Connection, parent: Option<Sha1Id>, files: &[(&str, &str)]) -> Sha1Id {
+        let id = commit_files(conn, parent.into_iter().collect(), files, "change");
+        let commit = Commit::read_from_conn_with_id(conn, id).unwrap();
+        record_file_changes(conn, &[&commit]).unwrap();
+        id
+    }
+
+    #[test]
+    fn test_path_history() {
+        let conn = object_database(&[
+            CREATE_FILE_CHANGES_TABLE,
+            CREATE_FILE_CHANGES_PATH_INDEX,
+            CREATE_RENAMES_VIEW,
+        ]);
+        let added = commit(&conn, None, &[("old", "1\n2\n3\n"), ("other", "x\n")]);
+        let modified = commit(
+            &conn,
+            Some(added),
+            &[("old", "1\n2\n4\n"), ("other", "x\n")],
+        );
+        let unrelated = commit(
+            &conn,
+            Some(modified),
+            &[("old", "1\n2\n4\n"), ("other", "y\n")],
+        );
+        let renamed = commit(
+            &conn,
+            Some(unrelated),
+            &[("new", "1\n2\n4\n"), ("other", "y\n")],
+        );
+
+        let history = path_history(&conn, renamed, "new").unwrap();
+        let commits: Vec<Sha1Id> = history.iter().map(|change| change.commit_id).collect();
+        assert_eq!(vec![renamed, modified, added], commits);
+        assert_eq!("R100", history[0].change_type);
+        assert_eq!(Some("old".to_string()), history[0].old_path);
+        assert_eq!(history[1].new_blob, history[0].new_blob);
+        assert_eq!("A", history[2].change_type);
+        assert_eq!(None, history[2].old_blob);
+        let rename: (Sha1Id, String, String, i64) = conn
+            .query_row("SELECT * FROM Renames", (), |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+            })
+            .unwrap();
+        assert_eq!((renamed, "old".to_string(), "new".to_string(), 100), rename);
+
+        forget_file_changes(&conn, renamed).unwrap();
+        assert!(path_history(&conn, renamed, "new").unwrap().is_empty());
+    }
+}
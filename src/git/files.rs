@@ -2,14 +2,13 @@
 
 use std::fs;
 
-#[cfg(target_os = "linux")]
-use std::os::linux::fs::MetadataExt;
-#[cfg(target_os = "macos")]
+#[cfg(unix)]
 use std::os::unix::fs::MetadataExt;
-#[cfg(target_os = "windows")]
+#[cfg(windows)]
 use std::os::windows::fs::MetadataExt;
 
-/// Provides a gitqlite specific trait to fetch necessary metadata from the file cross-platform
+/// Provides a gitqlite specific trait to fetch necessary metadata from the file cross-platform.
+/// Timestamps are in nanoseconds since the Unix epoch.
 pub trait GitqliteFileMetadataExt {
     fn g_ctime(&self) -> i64;
     fn g_mtime(&self) -> i64;
@@ -21,49 +20,28 @@ pub trait GitqliteFileMetadataExt {
     fn g_fsize(&self) -> u64;
 }
 
-#[cfg(target_os = "windows")]
-impl GitqliteFileMetadataExt for fs::Metadata {
-    fn g_ctime(&self) -> i64 {
-        self.creation_time() as i64 * 10
-    }
-
-    fn g_mtime(&self) -> i64 {
-        self.last_write_time() as i64 * 10
-    }
-
-    fn g_dev(&self) -> u64 {
-        0
-    }
-
-    fn g_ino(&self) -> u64 {
-        0
-    }
-
-    fn g_mode_perms(&self) -> u32 {
-        0
-    }
-
-    fn g_uid(&self) -> u32 {
-        0
-    }
-
-    fn g_gid(&self) -> u32 {
-        0
-    }
+/// Nanoseconds since the Unix epoch of a timestamp given as seconds and nanoseconds
+#[cfg(unix)]
+fn unix_nanos(secs: i64, nsecs: i64) -> i64 {
+    secs * 1_000_000_000 + nsecs
+}
 
-    fn g_fsize(&self) -> u64 {
-        self.file_size()
-    }
+/// Nanoseconds since the Unix epoch of a Windows file time, which counts 100-nanosecond
+/// intervals since January 1, 1601
+#[cfg(windows)]
+fn windows_nanos(file_time: u64) -> i64 {
+    const UNIX_EPOCH_FILE_TIME: i64 = 116_444_736_000_000_000;
+    (file_time as i64 - UNIX_EPOCH_FILE_TIME) * 100
 }
 
-#[cfg(target_os = "macos")]
+#[cfg(unix)]
 impl GitqliteFileMetadataExt for fs::Metadata {
     fn g_ctime(&self) -> i64 {
-        self.ctime_nsec()
+        unix_nanos(self.ctime(), self.ctime_nsec())
     }
 
     fn g_mtime(&self) -> i64 {
-        self.mtime_nsec()
+        unix_nanos(self.mtime(), self.mtime_nsec())
     }
 
     fn g_dev(&self) -> u64 {
@@ -91,37 +69,76 @@ impl GitqliteFileMetadataExt for fs::Metadata {
     }
 }
 
-#[cfg(target_os = "linux")]
+#[cfg(windows)]
 impl GitqliteFileMetadataExt for fs::Metadata {
     fn g_ctime(&self) -> i64 {
-        self.st_ctime_nsec()
+        windows_nanos(self.creation_time())
     }
 
     fn g_mtime(&self) -> i64 {
-        self.st_mtime_nsec()
+        windows_nanos(self.last_write_time())
     }
 
     fn g_dev(&self) -> u64 {
-        self.st_dev()
+        0
     }
 
     fn g_ino(&self) -> u64 {
-        self.st_ino()
+        0
     }
 
     fn g_mode_perms(&self) -> u32 {
-        self.st_mode()
+        0
     }
 
     fn g_uid(&self) -> u32 {
-        self.st_uid()
+        0
     }
 
     fn g_gid(&self) -> u32 {
-        self.st_gid()
+        0
     }
 
     fn g_fsize(&self) -> u64 {
-        self.st_size()
+        self.file_size()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::*;
+
+    #[test]
+    fn test_metadata() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file.txt");
+        fs::write(&path, "hello").unwrap();
+        let metadata = fs::metadata(&path).unwrap();
+
+        let modified = metadata
+            .modified()
+            .unwrap()
+            .duration_since(UNIX_EPOCH)
+            .unwrap();
+        assert_eq!(modified.as_nanos() as i64, metadata.g_mtime());
+        // Timestamps hold the seconds, not only their fractional part
+        let ctime = Duration::from_nanos(metadata.g_ctime() as u64);
+        assert!(modified.abs_diff(ctime) < Duration::from_secs(60));
+
+        assert_eq!(5, metadata.g_fsize());
+        #[cfg(unix)]
+        {
+            assert_ne!(0, metadata.g_ino());
+            assert_eq!(0o100000, metadata.g_mode_perms() & 0o170000);
+        }
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_windows_nanos() {
+        assert_eq!(0, windows_nanos(116_444_736_000_000_000));
+        assert_eq!(1_000_000_000, windows_nanos(116_444_736_010_000_000));
     }
 }
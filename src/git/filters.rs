@@ -0,0 +1,383 @@
+//! This module converts file content between the working tree and the database, as git's
+//! filters do. Content is cleaned as it is read from the working tree to be hashed or stored,
+//! and smudged as it is written back, so that a file checked out and read again hashes to the
+//! blob it came from.
+//!
+//! Two conversions are supported, selected per path by attributes:
+//! - line endings: files with the `text` attribute (`text=auto` for those that do not look
+//!   binary) are stored with LF line endings and checked out with the line endings of `eol`,
+//!   `core.autocrlf` or `core.eol`. Without a `text` attribute, `core.autocrlf` set to `true`
+//!   or `input` treats every file as `text=auto`.
+//! - drivers: `filter=<driver>` pipes the content through the `filter.<driver>.clean` and
+//!   `filter.<driver>.smudge` commands, in which `%f` stands for the path of the file. A failing
+//!   command leaves the content as it is, unless `filter.<driver>.required` is set.
+//!
+//! Cleaning runs the driver before converting line endings, and smudging the other way round.
+
+use std::{
+    borrow::Cow,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::anyhow;
+
+use super::{
+    attributes::{read_attributes, AttrValue, Attributes},
+    constants::GITQLITE_DIRECTORY_PREFIX,
+    diff::looks_binary,
+};
+use crate::repo::config::{ConfigSource, GitConfig};
+
+/// Filters of the repository the command runs in, loaded on first use
+static FILTERS: Mutex<Option<(PathBuf, Arc<Filters>)>> = Mutex::new(None);
+
+/// `core.autocrlf`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AutoCrlf {
+    False,
+    /// Convert to LF when cleaning and to CRLF when smudging
+    True,
+    /// Convert to LF when cleaning only
+    Input,
+}
+
+/// How the line endings of a file are converted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TextMode {
+    /// Left alone
+    Binary,
+    /// Converted if the content does not look binary
+    Auto,
+    Text,
+}
+
+/// [`Filters`] converts the content of the files of a working tree
+#[derive(Debug)]
+pub struct Filters {
+    attributes: Attributes,
+    config: GitConfig,
+    autocrlf: AutoCrlf,
+    /// Whether text files are checked out with CRLF line endings when `eol` does not say
+    crlf: bool,
+}
+
+/// The filters of the working tree at `repo_root`, read once per command. Writing a
+/// .gitattributes file with [`forget_filters`] makes the next call read them again.
+pub fn load_filters(repo_root: impl AsRef<Path>) -> crate::Result<Arc<Filters>> {
+    let repo_root = repo_root.as_ref();
+    let mut cached = FILTERS.lock().unwrap();
+    if let Some((root, filters)) = cached.as_ref() {
+        if root == repo_root {
+            return Ok(filters.clone());
+        }
+    }
+    let config = GitConfig::load(repo_root.join(GITQLITE_DIRECTORY_PREFIX))?;
+    let filters = Arc::new(Filters::new(read_attributes(repo_root)?, config)?);
+    *cached = Some((repo_root.to_path_buf(), filters.clone()));
+    Ok(filters)
+}
+
+/// Drop the filters read by [`load_filters`], once the attributes changed
+pub fn forget_filters() {
+    *FILTERS.lock().unwrap() = None;
+}
+
+impl Filters {
+    pub fn new(attributes: Attributes, config: GitConfig) -> crate::Result<Filters> {
+        let autocrlf = match config.get("core.autocrlf", ConfigSource::All)? {
+            Some(value) if value.eq_ignore_ascii_case("input") => AutoCrlf::Input,
+            Some(_) => match config.get_bool("core.autocrlf", ConfigSource::All)? {
+                Some(true) => AutoCrlf::True,
+                _ => AutoCrlf::False,
+            },
+            None => AutoCrlf::False,
+        };
+        let crlf = match autocrlf {
+            AutoCrlf::True => true,
+            AutoCrlf::Input => false,
+            AutoCrlf::False => match config.get("core.eol", ConfigSource::All)? {
+                Some(value) if value.eq_ignore_ascii_case("crlf") => true,
+                Some(value) if value.eq_ignore_ascii_case("lf") => false,
+                _ => cfg!(windows),
+            },
+        };
+        Ok(Filters {
+            attributes,
+            config,
+            autocrlf,
+            crlf,
+        })
+    }
+
+    /// Convert the content of the file `name` (relative to the repository root) read from
+    /// the working tree to the content stored in the database
+    pub fn clean<'a>(&self, name: &str, data: &'a [u8]) -> crate::Result<Cow<'a, [u8]>> {
+        let mut data = Cow::Borrowed(data);
+        if let Some(command) = self.driver_command(name, "clean")? {
+            data = Cow::Owned(self.run_driver(name, "clean", &command, &data)?);
+        }
+        if self.converts_line_endings(name, &data) && data.contains(&b'\r') {
+            data = Cow::Owned(crlf_to_lf(&data));
+        }
+        Ok(data)
+    }
+
+    /// Convert the content of the file `name` (relative to the repository root) stored in the
+    /// database to the content written to the working tree
+    pub fn smudge<'a>(&self, name: &str, data: &'a [u8]) -> crate::Result<Cow<'a, [u8]>> {
+        let mut data = Cow::Borrowed(data);
+        let to_crlf = match self.attributes.get(name, "eol") {
+            Some(AttrValue::Value(eol)) => eol == "crlf",
+            _ => self.crlf,
+        };
+        // Files stored with CRLF line endings were not normalized and are left so
+        if to_crlf && self.converts_line_endings(name, &data) && !data.contains(&b'\r') {
+            data = Cow::Owned(lf_to_crlf(&data));
+        }
+        if let Some(command) = self.driver_command(name, "smudge")? {
+            data = Cow::Owned(self.run_driver(name, "smudge", &command, &data)?);
+        }
+        Ok(data)
+    }
+
+    /// Whether the content of the file may be converted, in which case the size of the file
+    /// tells nothing of the size of its blob
+    pub fn converts(&self, name: &str) -> bool {
+        self.text_mode(name) != TextMode::Binary
+            || matches!(
+                self.attributes.get(name, "filter"),
+                Some(AttrValue::Value(_))
+            )
+    }
+
+    fn text_mode(&self, name: &str) -> TextMode {
+        if self.attributes.get(name, "binary") == Some(&AttrValue::Set) {
+            return TextMode::Binary;
+        }
+        match self.attributes.get(name, "text") {
+            Some(AttrValue::Set) => TextMode::Text,
+            Some(AttrValue::Unset) => TextMode::Binary,
+            Some(AttrValue::Value(value)) if value == "auto" => TextMode::Auto,
+            // A file given an end of line is text
+            _ if matches!(self.attributes.get(name, "eol"), Some(AttrValue::Value(_))) => {
+                TextMode::Text
+            }
+            _ if self.autocrlf != AutoCrlf::False => TextMode::Auto,
+            _ => TextMode::Binary,
+        }
+    }
+
+    fn converts_line_endings(&self, name: &str, data: &[u8]) -> bool {
+        match self.text_mode(name) {
+            TextMode::Binary => false,
+            TextMode::Auto => !looks_binary(data),
+            TextMode::Text => true,
+        }
+    }
+
+    /// The `clean` or `smudge` command of the driver of the file, if any
+    fn driver_command(&self, name: &str, kind: &str) -> crate::Result<Option<String>> {
+        let Some(AttrValue::Value(driver)) = self.attributes.get(name, "filter") else {
+            return Ok(None);
+        };
+        Ok(self
+            .config
+            .get(&format!("filter.{}.{}", driver, kind), ConfigSource::All)?
+            .map(|command| command.replace("%f", &shell_quote(name))))
+    }
+
+    /// Pipe the content through the command of the driver of the file, keeping it as it is if
+    /// the command fails and the driver is not required
+    fn run_driver(
+        &self,
+        name: &str,
+        kind: &str,
+        command: &str,
+        data: &[u8],
+    ) -> crate::Result<Vec<u8>> {
+        let Some(AttrValue::Value(driver)) = self.attributes.get(name, "filter") else {
+            unreachable!("the file has a driver");
+        };
+        match run_command(command, data) {
+            Ok(output) => Ok(output),
+            Err(err) => {
+                let required = self
+                    .config
+                    .get_bool(&format!("filter.{}.required", driver), ConfigSource::All)?
+                    .unwrap_or(false);
+                if required {
+                    return Err(anyhow!(
+                        "fatal: {}: {} filter '{}' failed: {}",
+                        name,
+                        kind,
+                        driver,
+                        err
+                    ));
+                }
+                eprintln!(
+                    "error: {} filter '{}' failed on {}: {}",
+                    kind, driver, name, err
+                );
+                Ok(data.to_vec())
+            }
+        }
+    }
+}
+
+/// Run a command through the shell, feeding it `input` and returning what it prints
+fn run_command(command: &str, input: &[u8]) -> crate::Result<Vec<u8>> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()?;
+    // Feed the input from another thread, so that a command writing as it reads does not
+    // block on a full pipe
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let output = std::thread::scope(|scope| {
+        let writer = scope.spawn(move || stdin.write_all(input));
+        let output = child.wait_with_output();
+        // A command exiting without reading its input is judged by its status
+        let _ = writer.join();
+        output
+    })?;
+    if !output.status.success() {
+        return Err(anyhow!("'{}' exited with {}", command, output.status));
+    }
+    Ok(output.stdout)
+}
+
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+fn crlf_to_lf(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    for (i, &byte) in data.iter().enumerate() {
+        if byte == b'\r' && data.get(i + 1) == Some(&b'\n') {
+            continue;
+        }
+        out.push(byte);
+    }
+    out
+}
+
+fn lf_to_crlf(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() + data.len() / 16);
+    for &byte in data {
+        if byte == b'\n' {
+            out.push(b'\r');
+        }
+        out.push(byte);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::attributes::parse_attributes;
+
+    fn filters(attributes: &str, config: &str) -> Filters {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("config"), config).unwrap();
+        let config = GitConfig::load(dir.path()).unwrap();
+        let attributes = Attributes::from_rules(parse_attributes("", attributes));
+        Filters::new(attributes, config).unwrap()
+    }
+
+    /// Check out `blob` as `name`, returning what is written and what reading it back stores
+    fn round_trip(filters: &Filters, name: &str, blob: &[u8]) -> (Vec<u8>, Vec<u8>) {
+        let worktree = filters.smudge(name, blob).unwrap().into_owned();
+        let cleaned = filters.clean(name, &worktree).unwrap().into_owned();
+        (worktree, cleaned)
+    }
+
+    #[test]
+    fn test_line_endings() {
+        let text = b"one\ntwo\n".as_slice();
+        let binary = b"\0one\ntwo\n".as_slice();
+
+        // Nothing is converted by default
+        let none = filters("", "");
+        assert_eq!(
+            b"a\r\nb".as_slice(),
+            &*none.clean("a.txt", b"a\r\nb").unwrap()
+        );
+
+        // core.autocrlf converts the files that look like text, both ways
+        let autocrlf = filters("*.bin -text\n", "[core]\n\tautocrlf = true\n");
+        let (worktree, cleaned) = round_trip(&autocrlf, "a.txt", text);
+        assert_eq!(b"one\r\ntwo\r\n".as_slice(), worktree);
+        assert_eq!(text, cleaned);
+        assert_eq!(
+            (binary.to_vec(), binary.to_vec()),
+            round_trip(&autocrlf, "a", binary)
+        );
+        assert_eq!(
+            (text.to_vec(), text.to_vec()),
+            round_trip(&autocrlf, "a.bin", text)
+        );
+        // A blob stored with CRLF is checked out as it is
+        assert_eq!(
+            b"a\r\nb\n".as_slice(),
+            &*autocrlf.smudge("a.txt", b"a\r\nb\n").unwrap()
+        );
+
+        // input only normalizes what is stored
+        let input = filters("", "[core]\n\tautocrlf = input\n");
+        assert_eq!(
+            (text.to_vec(), text.to_vec()),
+            round_trip(&input, "a.txt", text)
+        );
+        assert_eq!(text, &*input.clean("a.txt", b"one\r\ntwo\r\n").unwrap());
+
+        // Attributes choose per path
+        let attributes = filters("*.txt text\n*.bat eol=crlf\n", "[core]\n\teol = lf\n");
+        assert_eq!(
+            text,
+            &*attributes.clean("a.txt", b"one\r\ntwo\r\n").unwrap()
+        );
+        assert_eq!(text, &*attributes.smudge("a.txt", text).unwrap());
+        let (worktree, cleaned) = round_trip(&attributes, "run.bat", text);
+        assert_eq!(b"one\r\ntwo\r\n".as_slice(), worktree);
+        assert_eq!(text, cleaned);
+        assert_eq!(
+            b"a\r\n".as_slice(),
+            &*attributes.clean("other", b"a\r\n").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_drivers() {
+        let config = "[filter \"upper\"]\n\tclean = tr a-z A-Z\n\tsmudge = tr A-Z a-z\n[filter \"broken\"]\n\tclean = false\n[filter \"strict\"]\n\tclean = false\n\trequired = true\n[filter \"name\"]\n\tsmudge = echo %f\n";
+        let filters = filters(
+            "*.up filter=upper text\n*.broken filter=broken\n*.strict filter=strict\n*.name filter=name\n",
+            config,
+        );
+
+        let (worktree, cleaned) = round_trip(&filters, "a.up", b"HELLO\n");
+        assert_eq!(b"hello\n".as_slice(), worktree);
+        assert_eq!(b"HELLO\n".as_slice(), cleaned);
+        // The driver runs before the line endings are converted
+        assert_eq!(
+            b"HELLO\n".as_slice(),
+            &*filters.clean("a.up", b"hello\r\n").unwrap()
+        );
+
+        assert_eq!(
+            b"data".as_slice(),
+            &*filters.clean("a.broken", b"data").unwrap()
+        );
+        assert!(filters.clean("a.strict", b"data").is_err());
+        assert_eq!(
+            b"it's.name\n".as_slice(),
+            &*filters.smudge("it's.name", b"").unwrap()
+        );
+    }
+}
@@ -0,0 +1,185 @@
+//! This module renders the commit graph in the input languages of graph visualization tools:
+//! Graphviz's DOT and Mermaid flowcharts. Each commit is a node labelled with its abbreviated
+//! hash, summary and the refs pointing at it, with an edge from each commit to its parents.
+
+use std::collections::HashMap;
+
+use rusqlite::Connection;
+
+use crate::cli::GraphFormat;
+
+use super::{
+    model::{Commit, Head, Ref, Sha1Id},
+    rebase::{short_id, summary},
+};
+
+/// Prefixes removed from ref names in labels
+const LABEL_PREFIXES: [&str; 2] = ["refs/head/", "refs/tags/"];
+
+/// [`CommitGraph`] is the part of the history to render, newest commits first
+#[derive(Debug)]
+pub struct CommitGraph {
+    commits: Vec<Commit<Sha1Id>>,
+    /// Names of the refs pointing at each commit
+    labels: HashMap<Sha1Id, Vec<String>>,
+}
+
+impl CommitGraph {
+    /// Load the given commits, listed parents first, and the refs pointing at them
+    pub fn load(conn: &Connection, commit_ids: &[Sha1Id]) -> crate::Result<CommitGraph> {
+        let mut commits = commit_ids
+            .iter()
+            .map(|id| Commit::read_from_conn_with_id(conn, *id))
+            .collect::<crate::Result<Vec<_>>>()?;
+        commits.reverse();
+
+        let mut labels: HashMap<Sha1Id, Vec<String>> = HashMap::new();
+        if let Some(head_id) = Head::read_from_conn(conn)?.resolve(conn)? {
+            labels.entry(head_id).or_default().push("HEAD".to_string());
+        }
+        for reference in Ref::read_all(conn)? {
+            let name = LABEL_PREFIXES
+                .iter()
+                .find_map(|prefix| reference.name.strip_prefix(prefix))
+                .unwrap_or(&reference.name);
+            labels
+                .entry(reference.commit_id)
+                .or_default()
+                .push(name.to_string());
+        }
+
+        Ok(CommitGraph { commits, labels })
+    }
+
+    /// Render the graph in the given format
+    pub fn render(&self, format: GraphFormat) -> String {
+        match format {
+            GraphFormat::Dot => self.format_dot(),
+            GraphFormat::Mermaid => self.format_mermaid(),
+        }
+    }
+
+    fn format_dot(&self) -> String {
+        let mut out = String::from("digraph commits {\n  node [shape=box];\n");
+        for commit in &self.commits {
+            out.push_str(&format!(
+                "  \"{}\" [label=\"{}\"];\n",
+                short_id(commit.commit_id),
+                escape_dot(&self.label(commit))
+            ));
+        }
+        for (child, parent) in self.edges() {
+            out.push_str(&format!(
+                "  \"{}\" -> \"{}\";\n",
+                short_id(child),
+                short_id(parent)
+            ));
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    fn format_mermaid(&self) -> String {
+        let mut out = String::from("graph TD\n");
+        for commit in &self.commits {
+            out.push_str(&format!(
+                "  c{}[\"{}\"]\n",
+                short_id(commit.commit_id),
+                escape_mermaid(&self.label(commit))
+            ));
+        }
+        for (child, parent) in self.edges() {
+            out.push_str(&format!(
+                "  c{} --> c{}\n",
+                short_id(child),
+                short_id(parent)
+            ));
+        }
+        out
+    }
+
+    /// Label of a commit: `<short hash> <summary>`, followed by the refs pointing at it
+    fn label(&self, commit: &Commit<Sha1Id>) -> String {
+        let mut label = format!(
+            "{} {}",
+            short_id(commit.commit_id),
+            summary(&commit.message)
+        );
+        if let Some(names) = self.labels.get(&commit.commit_id) {
+            label.push_str(&format!("\n({})", names.join(", ")));
+        }
+        label
+    }
+
+    /// Edges from each commit to its parents, leaving out parents outside the graph
+    fn edges(&self) -> Vec<(Sha1Id, Sha1Id)> {
+        let included: Vec<Sha1Id> = self.commits.iter().map(|c| c.commit_id).collect();
+        self.commits
+            .iter()
+            .flat_map(|commit| {
+                commit
+                    .parent_ids
+                    .iter()
+                    .filter(|parent| included.contains(parent))
+                    .map(|parent| (commit.commit_id, *parent))
+            })
+            .collect()
+    }
+}
+
+fn escape_dot(label: &str) -> String {
+    label
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn escape_mermaid(label: &str) -> String {
+    label.replace('"', "#quot;").replace('\n', "<br/>")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::{
+        constants::BRANCH_PREFIX,
+        model::{CREATE_HEAD_TABLE, CREATE_REF_TABLE},
+        test_utils::{make_commit, object_database},
+    };
+
+    #[test]
+    fn test_render_graph() {
+        let conn = object_database(&[CREATE_HEAD_TABLE, CREATE_REF_TABLE]);
+
+        let root = make_commit(&conn, vec![], "root");
+        let tip = make_commit(&conn, vec![root], "say \"hi\"");
+        Ref {
+            name: format!("{}main", BRANCH_PREFIX),
+            commit_id: tip,
+        }
+        .persist_or_update(&conn)
+        .unwrap();
+        Head::Branch(format!("{}main", BRANCH_PREFIX))
+            .persist(&conn)
+            .unwrap();
+
+        let (r, t) = (short_id(root), short_id(tip));
+        let graph = CommitGraph::load(&conn, &[root, tip]).unwrap();
+        assert_eq!(
+            format!(
+                "digraph commits {{\n  node [shape=box];\n  \"{t}\" [label=\"{t} say \\\"hi\\\"\\n(HEAD, main)\"];\n  \"{r}\" [label=\"{r} root\"];\n  \"{t}\" -> \"{r}\";\n}}\n"
+            ),
+            graph.render(GraphFormat::Dot)
+        );
+        assert_eq!(
+            format!(
+                "graph TD\n  c{t}[\"{t} say #quot;hi#quot;<br/>(HEAD, main)\"]\n  c{r}[\"{r} root\"]\n  c{t} --> c{r}\n"
+            ),
+            graph.render(GraphFormat::Mermaid)
+        );
+
+        // Parents outside the selected commits get no edge
+        let graph = CommitGraph::load(&conn, &[tip]).unwrap();
+        assert!(!graph.render(GraphFormat::Dot).contains("->"));
+    }
+}
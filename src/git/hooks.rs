@@ -0,0 +1,235 @@
+//! This module runs the hooks of a repository: programs in `.gitqlite/hooks`, or in the
+//! directory named by `core.hooksPath`, which gitqlite runs at given points of an operation
+//! and whose exit status can stop it. Hooks that do not exist or are not executable are
+//! skipped.
+//!
+//! The receiving side of a push runs `pre-receive` once with every ref update on its standard
+//! input, as `<old-id> <new-id> <ref-name>` lines, and `update` for each ref with the name and
+//! the ids as arguments, a missing ref being written as the null id. This is where servers
+//! enforce their policies, e.g. refusing to rewind protected branches.
+//!
+//! On the client, `commit` runs `pre-commit` with the staged paths on its standard input, one
+//! per line, then `commit-msg` with the path of a file holding the message, which the hook may
+//! rewrite. `push` runs `pre-push` with the remote name and URL as arguments and a
+//! `<local-ref> <local-id> <remote-ref> <remote-id>` line per ref to update. `--no-verify`
+//! skips the hooks of both, and `gitqlite hook run` runs any hook by hand.
+//!
+//! Hooks are run from the root of the work tree, with `GITQLITE_DIR` naming the gitqlite
+//! directory of the repository.
+
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use rusqlite::Connection;
+
+use super::{
+    constants::{COMMIT_MESSAGE_FILE, GITQLITE_DIRECTORY_PREFIX},
+    dry_run,
+    model::{Commit, Head, Index},
+    tree::{flatten_tree, index_view},
+};
+use crate::repo::config::{ConfigSource, GitConfig};
+
+/// Id standing for a missing ref in the input of hooks
+pub const NULL_ID: &str = "0000000000000000000000000000000000000000";
+
+/// [`Hooks`] finds and runs the hooks of a repository
+#[derive(Debug, Clone)]
+pub struct Hooks {
+    /// Directory holding the hooks
+    dir: PathBuf,
+    /// Directory the hooks are run in, the root of the repository
+    work_dir: PathBuf,
+    /// Gitqlite directory of the repository, passed to the hooks as `GITQLITE_DIR`
+    gitqlite_dir: PathBuf,
+}
+
+impl Hooks {
+    pub fn new(dir: impl Into<PathBuf>, work_dir: impl Into<PathBuf>) -> Hooks {
+        let work_dir = work_dir.into();
+        Hooks {
+            dir: dir.into(),
+            gitqlite_dir: work_dir.join(GITQLITE_DIRECTORY_PREFIX),
+            work_dir,
+        }
+    }
+
+    /// The hooks of the repository whose gitqlite directory is `gitqlite_home`
+    pub fn for_repository(gitqlite_home: &Path) -> crate::Result<Hooks> {
+        let work_dir = gitqlite_home.parent().unwrap_or(gitqlite_home);
+        let config = GitConfig::load(gitqlite_home)?;
+        let dir = match config.get("core.hooksPath", ConfigSource::All)? {
+            Some(path) => work_dir.join(path),
+            None => gitqlite_home.join("hooks"),
+        };
+        Ok(Hooks {
+            gitqlite_dir: gitqlite_home.to_path_buf(),
+            ..Hooks::new(dir, work_dir)
+        })
+    }
+
+    /// Whether the hook `name` exists and can be run
+    pub fn exists(&self, name: &str) -> bool {
+        self.find(name).is_some()
+    }
+
+    /// Names of the hooks that exist and can be run, sorted
+    pub fn names(&self) -> crate::Result<Vec<String>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut names = Vec::new();
+        for entry in entries {
+            if let Some(name) = entry?.file_name().to_str() {
+                if self.exists(name) {
+                    names.push(name.to_string());
+                }
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    /// Path of the hook `name` if it exists and can be run
+    fn find(&self, name: &str) -> Option<PathBuf> {
+        let path = self.dir.join(name);
+        let metadata = path.metadata().ok().filter(|metadata| metadata.is_file())?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            if metadata.permissions().mode() & 0o111 == 0 {
+                return None;
+            }
+        }
+        #[cfg(not(unix))]
+        let _ = metadata;
+        Some(path)
+    }
+
+    /// Run the hook `name` with the arguments and standard input. Returns whether it exited
+    /// successfully, which a missing hook always does, as does a hook a dry run skips. The
+    /// output of the hook goes to the standard error, the standard output possibly carrying a
+    /// protocol.
+    pub fn run(&self, name: &str, args: &[&str], input: &[u8]) -> crate::Result<bool> {
+        let Some(path) = self.find(name) else {
+            return Ok(true);
+        };
+        if dry_run::skip_write("run", &path) {
+            return Ok(true);
+        }
+        let mut child = Command::new(&path)
+            .args(args)
+            .current_dir(&self.work_dir)
+            .env("GITQLITE_DIR", &self.gitqlite_dir)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::from(io::stderr()))
+            .spawn()?;
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        // Hooks are free not to read their input
+        match stdin.write_all(input) {
+            Err(err) if err.kind() != io::ErrorKind::BrokenPipe => return Err(err.into()),
+            _ => {}
+        }
+        drop(stdin);
+        Ok(child.wait()?.success())
+    }
+
+    /// Run `commit-msg` on the message. Returns the message as the hook left it, or None if
+    /// the hook refused it.
+    pub fn check_commit_message(&self, message: &str) -> crate::Result<Option<String>> {
+        let Some(hook) = self.find("commit-msg") else {
+            return Ok(Some(message.to_string()));
+        };
+        if dry_run::skip_write("run", &hook) {
+            return Ok(Some(message.to_string()));
+        }
+        let path = self.gitqlite_dir.join(COMMIT_MESSAGE_FILE);
+        fs::write(&path, format!("{}\n", message))?;
+        if !self.run("commit-msg", &[&path.to_string_lossy()], b"")? {
+            return Ok(None);
+        }
+        Ok(Some(fs::read_to_string(&path)?.trim_end().to_string()))
+    }
+}
+
+/// Paths of the files whose staged version differs from HEAD, sorted, as `pre-commit` reads
+/// them
+pub fn staged_files(conn: &Connection) -> crate::Result<Vec<String>> {
+    let index = index_view(&Index::read_from_conn(conn)?);
+    let head = match Head::read_from_conn(conn)?.resolve(conn)? {
+        Some(id) => flatten_tree(conn, Commit::read_from_conn_with_id(conn, id)?.tree_id)?,
+        None => Default::default(),
+    };
+    let mut paths: Vec<String> = index
+        .iter()
+        .filter(|(path, entry)| head.get(*path) != Some(entry))
+        .map(|(path, _)| path.clone())
+        .chain(
+            head.keys()
+                .filter(|path| !index.contains_key(*path))
+                .cloned(),
+        )
+        .collect();
+    paths.sort();
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_hook() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let hooks = Hooks::new(dir.path().join("hooks"), dir.path());
+        assert!(hooks.run("update", &[], b"").unwrap());
+
+        fs::create_dir(dir.path().join("hooks")).unwrap();
+        let script = dir.path().join("hooks/update");
+        // Refuse updates of main, recording the input of the hook in the work directory
+        fs::write(
+            &script,
+            "#!/bin/sh\ncat > input\ntest \"$1\" != refs/head/main\n",
+        )
+        .unwrap();
+        // Hooks which cannot be run are skipped
+        assert!(hooks.run("update", &["refs/head/main"], b"").unwrap());
+        assert!(hooks.names().unwrap().is_empty());
+
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+        assert_eq!(vec!["update"], hooks.names().unwrap());
+        assert!(!hooks.run("update", &["refs/head/main"], b"x").unwrap());
+        assert!(hooks.run("update", &["refs/head/topic"], b"y").unwrap());
+        assert_eq!("y", fs::read_to_string(dir.path().join("input")).unwrap());
+
+        // commit-msg may rewrite the message, or refuse it
+        fs::create_dir(dir.path().join(GITQLITE_DIRECTORY_PREFIX)).unwrap();
+        let script = dir.path().join("hooks/commit-msg");
+        fs::write(
+            &script,
+            "#!/bin/sh\ngrep -q WIP \"$1\" && exit 1\necho 'Signed-off-by: A' >> \"$1\"\ntest -d \"$GITQLITE_DIR\"\n",
+        )
+        .unwrap();
+        assert_eq!(
+            Some("fix".to_string()),
+            hooks.check_commit_message("fix").unwrap()
+        );
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+        assert_eq!(
+            Some("fix\nSigned-off-by: A".to_string()),
+            hooks.check_commit_message("fix").unwrap()
+        );
+        assert_eq!(None, hooks.check_commit_message("WIP").unwrap());
+    }
+}
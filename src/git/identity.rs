@@ -0,0 +1,113 @@
+//! This module works out who the author and committer of new commits are. The identity comes
+//! from `user.name` and `user.email`. When they are not configured, it is made up like git
+//! does, from the name of the logged in user and the host name (`user@host`), unless
+//! `user.useConfigOnly` asks to only ever use the configured identity.
+
+use std::fs;
+
+use anyhow::anyhow;
+
+use crate::repo::config::{ConfigSource, GitConfig};
+
+/// Read the identity used as author and committer, as (name, email)
+pub fn user_identity(config: &GitConfig) -> crate::Result<(String, String)> {
+    let name = config
+        .get("user.name", ConfigSource::All)?
+        .map(str::to_string);
+    let email = config
+        .get("user.email", ConfigSource::All)?
+        .map(str::to_string);
+    if let (Some(name), Some(email)) = (&name, &email) {
+        return Ok((name.clone(), email.clone()));
+    }
+    if config
+        .get_bool("user.useConfigOnly", ConfigSource::All)?
+        .unwrap_or(false)
+    {
+        return Err(missing_identity(
+            "user.useConfigOnly is set, so no identity is guessed",
+        ));
+    }
+
+    let email = email.or_else(|| {
+        std::env::var("EMAIL")
+            .ok()
+            .filter(|email| !email.is_empty())
+    });
+    guess_identity(name, email, os_user_name(), os_host_name())
+        .ok_or_else(|| missing_identity("unable to guess one"))
+}
+
+/// Fill in the parts of the identity that are not configured: the name is the user name, the
+/// email `user@host`
+fn guess_identity(
+    name: Option<String>,
+    email: Option<String>,
+    user: Option<String>,
+    host: Option<String>,
+) -> Option<(String, String)> {
+    let email = match (email, &user, host) {
+        (Some(email), _, _) => email,
+        (None, Some(user), Some(host)) => format!("{}@{}", user, host),
+        _ => return None,
+    };
+    Some((name.or(user)?, email))
+}
+
+/// The error telling the user how to configure an identity
+fn missing_identity(reason: &str) -> anyhow::Error {
+    anyhow!(
+        "*** Please tell me who you are.\n\n\
+         Run\n\n  \
+         gitqlite config --global user.email \"you@example.com\"\n  \
+         gitqlite config --global user.name \"Your Name\"\n\n\
+         to set your account's default identity.\n\
+         Omit --global to set the identity only in this repository.\n\n\
+         fatal: no name or email configured ({})",
+        reason
+    )
+}
+
+fn os_user_name() -> Option<String> {
+    ["USER", "LOGNAME", "USERNAME"]
+        .into_iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|name| !name.trim().is_empty())
+}
+
+fn os_host_name() -> Option<String> {
+    std::env::var("HOSTNAME")
+        .ok()
+        .or_else(|| fs::read_to_string("/etc/hostname").ok())
+        .or_else(|| std::env::var("COMPUTERNAME").ok())
+        .map(|host| host.trim().to_string())
+        .filter(|host| !host.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_guess_identity() {
+        let some = |s: &str| Some(s.to_string());
+        let pair = |name: &str, email: &str| Some((name.to_string(), email.to_string()));
+        assert_eq!(
+            pair("ann", "ann@box"),
+            guess_identity(None, None, some("ann"), some("box"))
+        );
+        assert_eq!(
+            pair("Ann", "ann@box"),
+            guess_identity(some("Ann"), None, some("ann"), some("box"))
+        );
+        assert_eq!(
+            pair("ann", "a@example.com"),
+            guess_identity(None, some("a@example.com"), some("ann"), None)
+        );
+        assert_eq!(None, guess_identity(None, None, some("ann"), None));
+        assert_eq!(
+            None,
+            guess_identity(None, some("a@example.com"), None, None)
+        );
+    }
+}
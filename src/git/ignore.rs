@@ -7,6 +7,8 @@ use std::{
     path::{Path, PathBuf},
 };
 
+use super::perf::{self, Category};
+
 /// [`GitIgnore`] describes the whole git ignore structure of the current repository.
 #[derive(Debug)]
 pub struct GitIgnore {
@@ -60,37 +62,49 @@ pub fn gitignore_parse(r: &mut impl Read) -> crate::Result<Vec<IgnoreRule>> {
 
 /// Read and build the whole gitignore structure of the current repository
 pub fn read_gitignore(repo_root: PathBuf) -> crate::Result<GitIgnore> {
-    let mut scoped = HashMap::new();
-
-    // TODO: Implement absolute rules by looking at system configuration directories
-    let absolute = Vec::new();
+    let mut ignore = GitIgnore::empty();
 
-    // Run a dfs over the directory tree
+    // Run a dfs over the directory tree, without following symbolic links which may lead
+    // back up the tree
+    let _span = perf::span(Category::FsWalk);
     let mut stack = Vec::new();
     stack.push(repo_root);
 
     while let Some(current_dir) = stack.pop() {
-        let gitignore_file = current_dir.join(".gitignore");
-
-        // If there is a .gitignore file in the current directory
-        if let Ok(mut file) = fs::File::open(gitignore_file) {
-            let rules = gitignore_parse(&mut file)?;
-            scoped.insert(current_dir.clone(), rules);
-        }
+        ignore.read_directory(&current_dir)?;
 
         for entry in fs::read_dir(current_dir)? {
             let entry = entry?;
-            let path = entry.path();
-            if path.is_dir() {
-                stack.push(path)
+            if entry.file_type()?.is_dir() {
+                stack.push(entry.path())
             }
         }
     }
 
-    Ok(GitIgnore { scoped, absolute })
+    Ok(ignore)
 }
 
 impl GitIgnore {
+    /// A structure without any rule, to be filled with [`GitIgnore::read_directory`]
+    pub fn empty() -> GitIgnore {
+        // TODO: Implement absolute rules by looking at system configuration directories
+        GitIgnore {
+            scoped: HashMap::new(),
+            absolute: Vec::new(),
+        }
+    }
+
+    /// Add the rules of the .gitignore file in `dir`, if any. Directories are expected to be
+    /// read before the ones below them.
+    pub fn read_directory(&mut self, dir: &Path) -> crate::Result<()> {
+        // If there is a .gitignore file in the directory
+        if let Ok(mut file) = fs::File::open(dir.join(".gitignore")) {
+            let rules = gitignore_parse(&mut file)?;
+            self.scoped.insert(dir.to_path_buf(), rules);
+        }
+        Ok(())
+    }
+
     pub fn should_ignore(&self, target: impl AsRef<Path>) -> bool {
         let target = target.as_ref();
 
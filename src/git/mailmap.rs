@@ -0,0 +1,160 @@
+//! This module reads mailmaps, which map the names and emails authors committed with to their
+//! canonical ones, so that one person committing under several identities is counted once.
+//! The mailmap is `.mailmap` at the root of the work tree, followed by the file named by
+//! `mailmap.file`. Each line takes one of the forms git accepts:
+//!
+//! ```text
+//! Proper Name <commit@email>
+//! <proper@email> <commit@email>
+//! Proper Name <proper@email> <commit@email>
+//! Proper Name <proper@email> Commit Name <commit@email>
+//! ```
+//!
+//! Emails are compared regardless of case, and `#` starts a comment.
+
+use std::{fs, io, path::Path};
+
+use crate::repo::config::{ConfigSource, GitConfig};
+
+/// Name of the mailmap at the root of the work tree
+pub const MAILMAP_FILE: &str = ".mailmap";
+
+/// [`MailmapEntry`] maps an identity, or any name with an email, to a proper name and email
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct MailmapEntry {
+    proper_name: Option<String>,
+    proper_email: Option<String>,
+    commit_name: Option<String>,
+    commit_email: String,
+}
+
+/// [`Mailmap`] is the list of mappings of a repository
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Mailmap {
+    entries: Vec<MailmapEntry>,
+}
+
+impl Mailmap {
+    /// Parse the text of a mailmap, skipping the lines which are not mappings
+    pub fn parse(text: &str) -> Mailmap {
+        let entries = text
+            .lines()
+            .filter_map(|line| parse_line(line.split('#').next().unwrap_or_default()))
+            .collect();
+        Mailmap { entries }
+    }
+
+    /// Load the mailmap of the repository whose work tree is at `work_dir`. Missing files map
+    /// nothing.
+    pub fn load(work_dir: &Path, config: &GitConfig) -> crate::Result<Mailmap> {
+        let mut text = String::new();
+        let mut files = vec![work_dir.join(MAILMAP_FILE)];
+        if let Some(file) = config.get("mailmap.file", ConfigSource::All)? {
+            files.push(work_dir.join(file));
+        }
+        for file in files {
+            match fs::read_to_string(&file) {
+                Ok(file_text) => {
+                    text.push_str(&file_text);
+                    text.push('\n');
+                }
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+        Ok(Mailmap::parse(&text))
+    }
+
+    /// The proper name and email of an identity. Entries naming the commit name win over those
+    /// naming the email only, and later entries win over earlier ones.
+    pub fn map(&self, name: &str, email: &str) -> (String, String) {
+        let matches = |entry: &&MailmapEntry| entry.commit_email.eq_ignore_ascii_case(email);
+        let entry = self
+            .entries
+            .iter()
+            .rev()
+            .filter(matches)
+            .find(|entry| entry.commit_name.as_deref() == Some(name))
+            .or_else(|| {
+                self.entries
+                    .iter()
+                    .rev()
+                    .filter(matches)
+                    .find(|entry| entry.commit_name.is_none())
+            });
+        match entry {
+            Some(entry) => (
+                entry
+                    .proper_name
+                    .clone()
+                    .unwrap_or_else(|| name.to_string()),
+                entry
+                    .proper_email
+                    .clone()
+                    .unwrap_or_else(|| email.to_string()),
+            ),
+            None => (name.to_string(), email.to_string()),
+        }
+    }
+}
+
+/// Parse `[name] <email> [[name] <email>]`
+fn parse_line(line: &str) -> Option<MailmapEntry> {
+    let mut identities = Vec::new();
+    let mut rest = line;
+    while let Some(open) = rest.find('<') {
+        let close = open + rest[open..].find('>')?;
+        let name = rest[..open].trim();
+        let name = (!name.is_empty()).then(|| name.to_string());
+        identities.push((name, rest[open + 1..close].trim().to_string()));
+        rest = &rest[close + 1..];
+    }
+    if !rest.trim().is_empty() {
+        return None;
+    }
+    match identities.as_slice() {
+        [(Some(name), email)] => Some(MailmapEntry {
+            proper_name: Some(name.clone()),
+            proper_email: None,
+            commit_name: None,
+            commit_email: email.clone(),
+        }),
+        [(proper_name, proper_email), (commit_name, commit_email)] => Some(MailmapEntry {
+            proper_name: proper_name.clone(),
+            proper_email: Some(proper_email.clone()),
+            commit_name: commit_name.clone(),
+            commit_email: commit_email.clone(),
+        }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_map() {
+        let mailmap = Mailmap::parse(
+            "# Comments and malformed lines are skipped\n\
+             Jane Doe <jane@example.com>\n\
+             <jane@example.com> <jane@old.example.com>\n\
+             Jane Doe <jane@example.com> <JD@laptop>\n\
+             Joe <joe@example.com> joe <shared@example.com>\n\
+             <missing-close\n\
+             trailing <a@b> text\n",
+        );
+        let map = |name: &str, email: &str| {
+            let (name, email) = mailmap.map(name, email);
+            format!("{} <{}>", name, email)
+        };
+        assert_eq!(
+            "Jane Doe <jane@example.com>",
+            map("jane", "jane@example.com")
+        );
+        assert_eq!("jd <jane@example.com>", map("jd", "jane@old.example.com"));
+        assert_eq!("Jane Doe <jane@example.com>", map("jd", "jd@LAPTOP"));
+        assert_eq!("Joe <joe@example.com>", map("joe", "shared@example.com"));
+        assert_eq!("Ann <shared@example.com>", map("Ann", "shared@example.com"));
+    }
+}
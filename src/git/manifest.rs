@@ -0,0 +1,303 @@
+//! This module keeps a manifest of the tables of the repository database: the number of rows
+//! of each table and a checksum of their content, so that `gitqlite verify` can tell when the
+//! database was torn by an interrupted write or modified behind gitqlite's back.
+//!
+//! The manifest lives in the `Manifest` table and is maintained by triggers on every other
+//! table, so it is updated in the same transaction as the rows it describes. The checksum of a
+//! table is the sum of the hashes of its rows, which the triggers adjust as rows are inserted,
+//! updated and deleted. The rows are hashed by the `gitqlite_row_hash` SQL function, which
+//! [`prepare_connection`] registers on each connection writing to the database.
+
+use anyhow::anyhow;
+use rusqlite::{functions::FunctionFlags, types::ValueRef, Connection};
+use sha1::{Digest, Sha1};
+
+use super::schema::SCHEMA_VERSION;
+use crate::repo::db::table_exists;
+
+pub const CREATE_MANIFEST_TABLE: &str = "CREATE TABLE Manifest (table_name TEXT PRIMARY KEY, row_count INTEGER NOT NULL, checksum INTEGER NOT NULL);";
+const READ_MANIFEST: &str =
+    "SELECT table_name, row_count, checksum FROM Manifest ORDER BY table_name";
+const INSERT_MANIFEST: &str =
+    "INSERT INTO Manifest (table_name, row_count, checksum) VALUES (?1, ?2, ?3);";
+/// Tables covered by the manifest: every table but the manifest itself and SQLite's own.
+/// Virtual tables, which cannot have triggers, and their shadow tables are derived indexes
+/// and left out too.
+const READ_TABLE_NAMES: &str = "SELECT name FROM pragma_table_list WHERE schema = 'main' AND type = 'table' AND name NOT LIKE 'sqlite_%' AND name != 'Manifest' ORDER BY name";
+const READ_TRIGGER_NAMES: &str = "SELECT name FROM sqlite_master WHERE type = 'trigger'";
+
+/// [`TableSummary`] is the number of rows of a table and the checksum of their content
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TableSummary {
+    pub row_count: i64,
+    pub checksum: i64,
+}
+
+/// Register the functions used by the manifest triggers. Rows deleted to resolve a REPLACE
+/// conflict are reported to the delete triggers too.
+pub fn prepare_connection(conn: &Connection) -> crate::Result<()> {
+    conn.create_scalar_function(
+        "gitqlite_row_hash",
+        -1,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        |ctx| Ok(row_hash((0..ctx.len()).map(|i| ctx.get_raw(i)))),
+    )?;
+    conn.pragma_update(None, "recursive_triggers", true)?;
+    Ok(())
+}
+
+/// Create the manifest of the existing tables along with the triggers maintaining it, and
+/// record the schema version
+pub fn create_manifest(conn: &Connection) -> crate::Result<()> {
+    conn.execute(CREATE_MANIFEST_TABLE, ())?;
+    for table in table_names(conn)? {
+        let summary = summarize_table(conn, &table)?;
+        conn.execute(
+            INSERT_MANIFEST,
+            (&table, summary.row_count, summary.checksum),
+        )?;
+        create_triggers(conn, &table)?;
+    }
+    conn.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+    Ok(())
+}
+
+/// Record the current content of a table created or rebuilt after the manifest, along with the
+/// triggers maintaining it. Repositories without a manifest are left alone.
+pub fn track_table(conn: &Connection, table: &str) -> crate::Result<()> {
+    if !table_exists(conn, "Manifest")? {
+        return Ok(());
+    }
+    for operation in ["insert", "update", "delete"] {
+        conn.execute(
+            &format!(
+                "DROP TRIGGER IF EXISTS \"Manifest_{}_{}\"",
+                table, operation
+            ),
+            (),
+        )?;
+    }
+    let summary = summarize_table(conn, table)?;
+    conn.execute(
+        "INSERT OR REPLACE INTO Manifest (table_name, row_count, checksum) VALUES (?1, ?2, ?3)",
+        (table, summary.row_count, summary.checksum),
+    )?;
+    create_triggers(conn, table)
+}
+
+fn create_triggers(conn: &Connection, table: &str) -> crate::Result<()> {
+    let columns = conn
+        .prepare(&format!("PRAGMA table_info(\"{}\")", table))?
+        .query_map((), |row| row.get::<_, String>(1))?
+        .collect::<Result<Vec<_>, _>>()?;
+    let hash = |row: &str| {
+        let values: Vec<String> = columns
+            .iter()
+            .map(|column| format!("{}.\"{}\"", row, column))
+            .collect();
+        format!("gitqlite_row_hash({})", values.join(", "))
+    };
+
+    conn.execute_batch(&format!(
+        "CREATE TRIGGER \"Manifest_{table}_insert\" AFTER INSERT ON \"{table}\" BEGIN
+            UPDATE Manifest SET row_count = row_count + 1, checksum = checksum + {new}
+                WHERE table_name = '{table}';
+        END;
+        CREATE TRIGGER \"Manifest_{table}_update\" AFTER UPDATE ON \"{table}\" BEGIN
+            UPDATE Manifest SET checksum = checksum - {old} + {new}
+                WHERE table_name = '{table}';
+        END;
+        CREATE TRIGGER \"Manifest_{table}_delete\" AFTER DELETE ON \"{table}\" BEGIN
+            UPDATE Manifest SET row_count = row_count - 1, checksum = checksum - {old}
+                WHERE table_name = '{table}';
+        END;",
+        table = table,
+        new = hash("NEW"),
+        old = hash("OLD"),
+    ))?;
+    Ok(())
+}
+
+/// Check the database, returning the problems found
+pub fn verify_database(conn: &Connection) -> crate::Result<Vec<String>> {
+    let mut problems: Vec<String> = conn
+        .prepare("PRAGMA integrity_check")?
+        .query_map((), |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .filter(|message| message != "ok")
+        .collect();
+
+    let version: i64 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    if version != SCHEMA_VERSION {
+        problems.push(format!(
+            "schema version is {}, expected {}",
+            version, SCHEMA_VERSION
+        ));
+    }
+
+    if !table_exists(conn, "Manifest")? {
+        problems.push("the database has no manifest".to_string());
+        return Ok(problems);
+    }
+
+    let mut recorded = read_manifest(conn)?;
+    let triggers = conn
+        .prepare(READ_TRIGGER_NAMES)?
+        .query_map((), |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    for table in table_names(conn)? {
+        let actual = summarize_table(conn, &table)?;
+        match recorded.iter().position(|(name, _)| *name == table) {
+            None => problems.push(format!("table {} is missing from the manifest", table)),
+            Some(index) => {
+                let (_, expected) = recorded.remove(index);
+                if actual.row_count != expected.row_count {
+                    problems.push(format!(
+                        "table {} has {} rows, the manifest records {}",
+                        table, actual.row_count, expected.row_count
+                    ));
+                } else if actual.checksum != expected.checksum {
+                    problems.push(format!(
+                        "table {} does not match the checksum of the manifest",
+                        table
+                    ));
+                }
+            }
+        }
+        for operation in ["insert", "update", "delete"] {
+            let trigger = format!("Manifest_{}_{}", table, operation);
+            if !triggers.contains(&trigger) {
+                problems.push(format!("trigger {} is missing", trigger));
+            }
+        }
+    }
+    for (table, _) in recorded {
+        problems.push(format!("table {} of the manifest does not exist", table));
+    }
+
+    Ok(problems)
+}
+
+/// Read the summaries recorded by the manifest
+pub fn read_manifest(conn: &Connection) -> crate::Result<Vec<(String, TableSummary)>> {
+    let mut stmt = conn.prepare(READ_MANIFEST)?;
+    let rows = stmt
+        .query_map((), |row| {
+            Ok((
+                row.get(0)?,
+                TableSummary {
+                    row_count: row.get(1)?,
+                    checksum: row.get(2)?,
+                },
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(rows)
+}
+
+/// Count and hash the rows of a table
+pub fn summarize_table(conn: &Connection, table: &str) -> crate::Result<TableSummary> {
+    let mut stmt = conn.prepare(&format!("SELECT * FROM \"{}\"", table))?;
+    let columns = stmt.column_count();
+    let mut rows = stmt.query(())?;
+    let mut summary = TableSummary {
+        row_count: 0,
+        checksum: 0,
+    };
+    while let Some(row) = rows.next()? {
+        let values = (0..columns)
+            .map(|i| row.get_ref(i))
+            .collect::<Result<Vec<_>, _>>()?;
+        summary.row_count += 1;
+        summary.checksum += row_hash(values.into_iter());
+    }
+    Ok(summary)
+}
+
+fn table_names(conn: &Connection) -> crate::Result<Vec<String>> {
+    let names = conn
+        .prepare(READ_TABLE_NAMES)?
+        .query_map((), |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    if names.iter().any(|name| name.contains(['"', '\''])) {
+        return Err(anyhow!("fatal: unexpected table name in the database"));
+    }
+    Ok(names)
+}
+
+/// Hash of a row, taken from the first 32 bits of the SHA-1 of its values so that the sum of
+/// the hashes of a table cannot overflow
+fn row_hash<'a>(values: impl Iterator<Item = ValueRef<'a>>) -> i64 {
+    let mut hasher = Sha1::new();
+    for value in values {
+        match value {
+            ValueRef::Null => hasher.update([0]),
+            ValueRef::Integer(i) => {
+                hasher.update([1]);
+                hasher.update(i.to_be_bytes());
+            }
+            ValueRef::Real(f) => {
+                hasher.update([2]);
+                hasher.update(f.to_bits().to_be_bytes());
+            }
+            ValueRef::Text(bytes) | ValueRef::Blob(bytes) => {
+                hasher.update([if matches!(value, ValueRef::Text(_)) {
+                    3
+                } else {
+                    4
+                }]);
+                hasher.update((bytes.len() as u64).to_be_bytes());
+                hasher.update(bytes);
+            }
+        }
+    }
+    let digest = hasher.finalize();
+    u32::from_be_bytes([digest[0], digest[1], digest[2], digest[3]]) as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manifest() {
+        let conn = Connection::open_in_memory().unwrap();
+        prepare_connection(&conn).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE Refs (ref_name TEXT PRIMARY KEY, commit_id BLOB NOT NULL);
+            INSERT INTO Refs VALUES ('refs/head/main', x'01');",
+        )
+        .unwrap();
+        create_manifest(&conn).unwrap();
+        assert!(verify_database(&conn).unwrap().is_empty());
+
+        // Every kind of write keeps the manifest up to date, including REPLACE conflicts
+        conn.execute_batch(
+            "INSERT INTO Refs VALUES ('refs/head/topic', x'02');
+            INSERT OR REPLACE INTO Refs VALUES ('refs/head/main', x'03');
+            UPDATE Refs SET commit_id = x'04' WHERE ref_name = 'refs/head/topic';
+            DELETE FROM Refs WHERE ref_name = 'refs/head/main';",
+        )
+        .unwrap();
+        assert!(verify_database(&conn).unwrap().is_empty());
+        assert_eq!(
+            summarize_table(&conn, "Refs").unwrap(),
+            read_manifest(&conn).unwrap()[0].1
+        );
+
+        // Writes bypassing the triggers are detected
+        conn.execute_batch(
+            "DROP TRIGGER Manifest_Refs_update;
+            UPDATE Refs SET commit_id = x'05';",
+        )
+        .unwrap();
+        assert_eq!(
+            vec![
+                "table Refs does not match the checksum of the manifest".to_string(),
+                "trigger Manifest_Refs_update is missing".to_string(),
+            ],
+            verify_database(&conn).unwrap()
+        );
+    }
+}
@@ -0,0 +1,597 @@
+//! This module implements merging the history of another commit into HEAD.
+//!
+//! Merges are resolved one file at a time against the merge base of the two commits: a file
+//! changed on only one side takes that side's version, while a file changed differently on
+//! both sides is a conflict unless a [`StrategyOption`] says which side wins. The content of
+//! such a text file is then merged line by line (see [`merge_file`]), which resolves the
+//! conflict when the two sides changed different parts of it.
+
+use std::{
+    collections::{BTreeSet, HashSet},
+    path::Path,
+};
+
+use anyhow::anyhow;
+use rusqlite::Connection;
+
+use crate::{
+    cli::{MergeStrategy, StrategyOption},
+    repo::db::{CommitWalk, HashAlgorithm},
+};
+
+use super::{
+    diff::looks_binary,
+    merge_file::merge_file,
+    model::{hash_blob_data, Blob, Commit, Index, Sha1Id, INSERT_BLOB},
+    progress::{FileEvent, Progress},
+    tree::{flatten_tree, index_view, FlatEntry, TreeView},
+    worktree::{
+        blob_index_entry, checkout_blob, hash_worktree_file, remove_worktree_file,
+        write_worktree_file,
+    },
+};
+
+/// [`Conflict`] describes a file changed differently by both sides of a merge.
+/// A version is None if the file does not exist on that side.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    pub name: String,
+    pub base: Option<FlatEntry>,
+    pub ours: Option<FlatEntry>,
+    pub theirs: Option<FlatEntry>,
+}
+
+impl Conflict {
+    /// The line reporting the conflict, naming the sides by `labels` (ours, theirs) like the
+    /// conflict markers do
+    pub fn describe(&self, (ours, theirs): (&str, &str)) -> String {
+        match (&self.ours, &self.theirs) {
+            (None, _) => format!(
+                "CONFLICT (modify/delete): {} deleted in {} and modified in {}",
+                self.name, ours, theirs
+            ),
+            (_, None) => format!(
+                "CONFLICT (modify/delete): {} deleted in {} and modified in {}",
+                self.name, theirs, ours
+            ),
+            _ => format!("CONFLICT (content): Merge conflict in {}", self.name),
+        }
+    }
+}
+
+/// [`MergeOutcome`] is the result of merging two trees.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergeOutcome {
+    /// The merged snapshot. Conflicted files keep our version (if any).
+    pub tree: TreeView,
+    /// Files that could not be merged automatically
+    pub conflicts: Vec<Conflict>,
+}
+
+/// Collect the commit and all of its ancestors
+pub fn ancestors(conn: &Connection, id: Sha1Id) -> crate::Result<HashSet<Sha1Id>> {
+    CommitWalk::new(conn, [id])?
+        .map(|commit| Ok(commit?.commit_id))
+        .collect()
+}
+
+/// Whether `ancestor` is reachable from `descendant` (a commit is its own ancestor)
+pub fn is_ancestor(conn: &Connection, ancestor: Sha1Id, descendant: Sha1Id) -> crate::Result<bool> {
+    Ok(ancestors(conn, descendant)?.contains(&ancestor))
+}
+
+/// Find the best common ancestors of two commits, i.e., common ancestors that are not
+/// ancestors of another common ancestor. Returns an empty list for unrelated histories.
+pub fn merge_bases(conn: &Connection, a: Sha1Id, b: Sha1Id) -> crate::Result<Vec<Sha1Id>> {
+    let ancestors_a = ancestors(conn, a)?;
+    let ancestors_b = ancestors(conn, b)?;
+    let common: HashSet<Sha1Id> = ancestors_a.intersection(&ancestors_b).copied().collect();
+
+    // A common ancestor reachable from the parents of another one is not a best candidate
+    let mut redundant = HashSet::new();
+    for id in &common {
+        let commit = Commit::read_from_conn_with_id(conn, *id)?;
+        for parent in commit.parent_ids {
+            if common.contains(&parent) && !redundant.contains(&parent) {
+                redundant.extend(ancestors(conn, parent)?);
+            }
+        }
+    }
+
+    let mut bases: Vec<Sha1Id> = common.difference(&redundant).copied().collect();
+    bases.sort();
+    Ok(bases)
+}
+
+/// Merge `ours` and `theirs` against their common `base`
+pub fn merge_trees(
+    base: &TreeView,
+    ours: &TreeView,
+    theirs: &TreeView,
+    strategy: MergeStrategy,
+    option: Option<StrategyOption>,
+) -> MergeOutcome {
+    if strategy == MergeStrategy::Ours {
+        // Record the merge but ignore everything the other side did
+        return MergeOutcome {
+            tree: ours.clone(),
+            conflicts: Vec::new(),
+        };
+    }
+
+    let names: BTreeSet<&String> = base
+        .keys()
+        .chain(ours.keys())
+        .chain(theirs.keys())
+        .collect();
+
+    let mut tree = TreeView::new();
+    let mut conflicts = Vec::new();
+
+    for name in names {
+        let b = base.get(name);
+        let o = ours.get(name);
+        let t = theirs.get(name);
+
+        let merged = if o == t || b == t {
+            o
+        } else if b == o {
+            t
+        } else {
+            match option {
+                Some(StrategyOption::Ours) => o,
+                Some(StrategyOption::Theirs) => t,
+                None => {
+                    conflicts.push(Conflict {
+                        name: name.clone(),
+                        base: b.cloned(),
+                        ours: o.cloned(),
+                        theirs: t.cloned(),
+                    });
+                    o
+                }
+            }
+        };
+
+        if let Some(merged) = merged {
+            tree.insert(name.clone(), merged.clone());
+        }
+    }
+
+    MergeOutcome { tree, conflicts }
+}
+
+/// Merge the content of the conflicted text files changed on both sides line by line.
+/// Files whose changes combine without conflicts get their merged content stored as a blob
+/// and are moved from the conflicts into the merged tree.
+pub fn merge_contents(conn: &Connection, outcome: &mut MergeOutcome) -> crate::Result<()> {
+    let algorithm = HashAlgorithm::read(conn)?;
+    let mut conflicts = Vec::with_capacity(outcome.conflicts.len());
+
+    for conflict in std::mem::take(&mut outcome.conflicts) {
+        let (Some(ours), Some(theirs)) = (&conflict.ours, &conflict.theirs) else {
+            conflicts.push(conflict);
+            continue;
+        };
+        let base_data = match &conflict.base {
+            Some(base) => Blob::read_from_conn_with_id(conn, base.id)?.data,
+            None => Vec::new(),
+        };
+        let ours_data = Blob::read_from_conn_with_id(conn, ours.id)?.data;
+        let theirs_data = Blob::read_from_conn_with_id(conn, theirs.id)?.data;
+        if [&base_data, &ours_data, &theirs_data]
+            .into_iter()
+            .any(|data| looks_binary(data))
+        {
+            conflicts.push(conflict);
+            continue;
+        }
+
+        let merged = merge_file(&base_data, &ours_data, &theirs_data, ("", ""));
+        if merged.conflicts > 0 {
+            conflicts.push(conflict);
+            continue;
+        }
+
+        let id = hash_blob_data(algorithm.hasher(), &merged.content);
+        conn.prepare_cached(INSERT_BLOB)?
+            .execute((&id, &merged.content))?;
+        // A mode change on one side is kept like any other change
+        let mode = match &conflict.base {
+            Some(base) if base.mode == ours.mode => theirs.mode.clone(),
+            _ => ours.mode.clone(),
+        };
+        outcome.tree.insert(conflict.name, FlatEntry { id, mode });
+    }
+
+    outcome.conflicts = conflicts;
+    Ok(())
+}
+
+/// Ensure that applying the merge outcome will not lose local changes: the index must match
+/// HEAD, and files touched by the merge must be unmodified in the working tree.
+pub fn check_local_changes(
+    conn: &Connection,
+    repo_root: impl AsRef<Path>,
+    index: &Index,
+    ours: &TreeView,
+    outcome: &MergeOutcome,
+) -> crate::Result<()> {
+    let dirty = local_changes(conn, repo_root, index, ours, outcome)?;
+    if dirty.is_empty() {
+        return Ok(());
+    }
+
+    let files: Vec<String> = dirty.iter().map(|name| format!("\t{}", name)).collect();
+    Err(anyhow!(
+        "error: Your local changes to the following files would be overwritten by merge:\n{}\nPlease commit your changes before you merge.\nAborting",
+        files.join("\n")
+    ))
+}
+
+/// The files whose local changes applying the outcome would lose: the staged ones, and those
+/// the outcome touches which are modified in the working tree
+pub fn local_changes(
+    conn: &Connection,
+    repo_root: impl AsRef<Path>,
+    index: &Index,
+    ours: &TreeView,
+    outcome: &MergeOutcome,
+) -> crate::Result<BTreeSet<String>> {
+    let staged = index_view(index);
+    let mut dirty: BTreeSet<String> = BTreeSet::new();
+
+    for name in staged.keys().chain(ours.keys()) {
+        if staged.get(name) != ours.get(name) {
+            dirty.insert(name.clone());
+        }
+    }
+
+    let touched = outcome
+        .tree
+        .keys()
+        .chain(ours.keys())
+        .filter(|name| outcome.tree.get(*name) != ours.get(*name))
+        .chain(outcome.conflicts.iter().map(|conflict| &conflict.name));
+
+    let algorithm = HashAlgorithm::read(conn)?;
+    for name in touched {
+        let expected = ours.get(name).map(|entry| entry.id);
+        if hash_worktree_file(&repo_root, name, algorithm)? != expected {
+            dirty.insert(name.clone());
+        }
+    }
+
+    Ok(dirty)
+}
+
+/// Bring the index and working tree from our snapshot to the merge outcome.
+/// Conflicted files are recorded in the index with one entry per side, and written to the
+/// working tree with conflict markers labelled with `labels` (ours, theirs) around the
+/// regions changed differently on both sides, or around the whole content of binary files. Each file written
+/// or removed is reported to `progress`.
+pub fn apply_merge(
+    conn: &Connection,
+    repo_root: impl AsRef<Path>,
+    index: &mut Index,
+    ours: &TreeView,
+    outcome: &MergeOutcome,
+    labels: (&str, &str),
+    progress: &mut Progress,
+) -> crate::Result<()> {
+    let repo_root = repo_root.as_ref();
+
+    let updated: Vec<_> = outcome
+        .tree
+        .iter()
+        .filter(|(name, entry)| ours.get(*name) != Some(*entry))
+        .collect();
+    let conflicted: HashSet<&str> = outcome
+        .conflicts
+        .iter()
+        .map(|conflict| conflict.name.as_str())
+        .collect();
+    let removed: Vec<&String> = ours
+        .keys()
+        .filter(|name| !outcome.tree.contains_key(*name) && !conflicted.contains(name.as_str()))
+        .collect();
+    progress.add_total(updated.len() + removed.len() + outcome.conflicts.len());
+
+    for (name, entry) in updated {
+        index.insert(checkout_blob(conn, repo_root, name, entry)?);
+        progress.report(FileEvent::Updated);
+    }
+    for name in removed {
+        remove_worktree_file(repo_root, name)?;
+        index.remove_name(name);
+        progress.report(FileEvent::Removed);
+    }
+
+    for conflict in &outcome.conflicts {
+        let stages = [
+            (1, &conflict.base),
+            (2, &conflict.ours),
+            (3, &conflict.theirs),
+        ]
+        .into_iter()
+        .filter_map(|(stage, entry)| {
+            entry
+                .as_ref()
+                .map(|entry| blob_index_entry(&conflict.name, entry, stage))
+        })
+        .collect();
+        index.insert_conflict(&conflict.name, stages);
+
+        match (&conflict.ours, &conflict.theirs) {
+            (Some(ours), Some(theirs)) => {
+                let base_data = match &conflict.base {
+                    Some(base) => Blob::read_from_conn_with_id(conn, base.id)?.data,
+                    None => Vec::new(),
+                };
+                let ours_data = Blob::read_from_conn_with_id(conn, ours.id)?.data;
+                let theirs_data = Blob::read_from_conn_with_id(conn, theirs.id)?.data;
+                let data = if [&base_data, &ours_data, &theirs_data]
+                    .into_iter()
+                    .any(|data| looks_binary(data))
+                {
+                    conflict_file_content(&ours_data, &theirs_data, labels)
+                } else {
+                    merge_file(&base_data, &ours_data, &theirs_data, labels).content
+                };
+                write_worktree_file(repo_root, &conflict.name, &data, &ours.mode)?;
+            }
+            // Modified by them but deleted by us: leave their version for the user to decide
+            (None, Some(theirs)) => {
+                checkout_blob(conn, repo_root, &conflict.name, theirs)?;
+            }
+            // Deleted by them but modified by us: our version is already in the working tree
+            (Some(_), None) | (None, None) => {}
+        }
+        progress.report(FileEvent::Updated);
+    }
+
+    Ok(())
+}
+
+/// Apply the changes `commit` introduced relative to its first parent on top of `head_id`,
+/// bringing the index and working tree to the result. `label` names the commit in conflict
+/// markers.
+pub fn pick_commit(
+    conn: &Connection,
+    repo_root: impl AsRef<Path>,
+    index: &mut Index,
+    commit: &Commit<Sha1Id>,
+    head_id: Sha1Id,
+    label: &str,
+) -> crate::Result<MergeOutcome> {
+    let ours = flatten_tree(conn, Commit::read_from_conn_with_id(conn, head_id)?.tree_id)?;
+    let parent = parent_view(conn, commit)?;
+    let theirs = flatten_tree(conn, commit.tree_id)?;
+    apply_change(conn, repo_root, index, (&parent, &ours, &theirs), label)
+}
+
+/// Apply the changes `commit` introduced relative to its first parent on top of the index
+/// rather than HEAD, so that the changes of several commits can be staged one after another.
+pub fn pick_commit_into_index(
+    conn: &Connection,
+    repo_root: impl AsRef<Path>,
+    index: &mut Index,
+    commit: &Commit<Sha1Id>,
+    label: &str,
+) -> crate::Result<MergeOutcome> {
+    let ours = index_view(index);
+    let parent = parent_view(conn, commit)?;
+    let theirs = flatten_tree(conn, commit.tree_id)?;
+    apply_change(conn, repo_root, index, (&parent, &ours, &theirs), label)
+}
+
+/// Undo the changes `commit` introduced relative to its first parent on top of `head_id`,
+/// bringing the index and working tree to the result. `label` names the parent of the commit
+/// in conflict markers.
+pub fn revert_commit(
+    conn: &Connection,
+    repo_root: impl AsRef<Path>,
+    index: &mut Index,
+    commit: &Commit<Sha1Id>,
+    head_id: Sha1Id,
+    label: &str,
+) -> crate::Result<MergeOutcome> {
+    let ours = flatten_tree(conn, Commit::read_from_conn_with_id(conn, head_id)?.tree_id)?;
+    let parent = parent_view(conn, commit)?;
+    let changed = flatten_tree(conn, commit.tree_id)?;
+    apply_change(conn, repo_root, index, (&changed, &ours, &parent), label)
+}
+
+/// Snapshot of the first parent of a commit, empty for a root commit
+fn parent_view(conn: &Connection, commit: &Commit<Sha1Id>) -> crate::Result<TreeView> {
+    match commit.parent_ids.first() {
+        Some(parent_id) => flatten_tree(
+            conn,
+            Commit::read_from_conn_with_id(conn, *parent_id)?.tree_id,
+        ),
+        None => Ok(TreeView::new()),
+    }
+}
+
+/// Apply the change from `base` to `theirs` on top of `ours` as a three-way merge
+fn apply_change(
+    conn: &Connection,
+    repo_root: impl AsRef<Path>,
+    index: &mut Index,
+    (base, ours, theirs): (&TreeView, &TreeView, &TreeView),
+    label: &str,
+) -> crate::Result<MergeOutcome> {
+    let mut outcome = merge_trees(base, ours, theirs, MergeStrategy::Ort, None);
+    merge_contents(conn, &mut outcome)?;
+    check_local_changes(conn, &repo_root, index, ours, &outcome)?;
+    apply_merge(
+        conn,
+        repo_root,
+        index,
+        ours,
+        &outcome,
+        ("HEAD", label),
+        &mut Progress::silent(),
+    )?;
+
+    Ok(outcome)
+}
+
+/// Render a file that both sides changed, with the whole content of each side between
+/// conflict markers
+fn conflict_file_content(ours: &[u8], theirs: &[u8], labels: (&str, &str)) -> Vec<u8> {
+    let mut content = Vec::with_capacity(ours.len() + theirs.len() + 64);
+
+    content.extend(format!("<<<<<<< {}\n", labels.0).as_bytes());
+    content.extend(ours);
+    if !ours.is_empty() && !ours.ends_with(b"\n") {
+        content.push(b'\n');
+    }
+    content.extend(b"=======\n");
+    content.extend(theirs);
+    if !theirs.is_empty() && !theirs.ends_with(b"\n") {
+        content.push(b'\n');
+    }
+    content.extend(format!(">>>>>>> {}\n", labels.1).as_bytes());
+
+    content
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::test_utils::{make_commit, object_database};
+
+    fn entry(byte: u8) -> FlatEntry {
+        FlatEntry {
+            id: Sha1Id::try_from(vec![byte; 20]).unwrap(),
+            mode: "33188".to_string(),
+        }
+    }
+
+    fn view(files: &[(&str, u8)]) -> TreeView {
+        files
+            .iter()
+            .map(|(name, byte)| (name.to_string(), entry(*byte)))
+            .collect()
+    }
+
+    #[test]
+    fn test_merge_bases() {
+        let conn = object_database(&[]);
+
+        // root - a1 - a2
+        //    \       /
+        //     b1 -- b2 - b3
+        let root = make_commit(&conn, vec![], "root");
+        let a1 = make_commit(&conn, vec![root], "a1");
+        let b1 = make_commit(&conn, vec![root], "b1");
+        let b2 = make_commit(&conn, vec![b1], "b2");
+        let a2 = make_commit(&conn, vec![a1, b2], "a2");
+        let b3 = make_commit(&conn, vec![b2], "b3");
+        let unrelated = make_commit(&conn, vec![], "unrelated");
+
+        assert_eq!(vec![b2], merge_bases(&conn, a2, b3).unwrap());
+        assert_eq!(vec![root], merge_bases(&conn, a1, b3).unwrap());
+        assert_eq!(vec![a1], merge_bases(&conn, a1, a2).unwrap());
+        assert!(merge_bases(&conn, a2, unrelated).unwrap().is_empty());
+
+        assert!(is_ancestor(&conn, root, a2).unwrap());
+        assert!(is_ancestor(&conn, b2, a2).unwrap());
+        assert!(is_ancestor(&conn, a2, a2).unwrap());
+        assert!(!is_ancestor(&conn, b3, a2).unwrap());
+    }
+
+    #[test]
+    fn test_merge_trees() {
+        let base = view(&[
+            ("same", 1),
+            ("ours", 1),
+            ("theirs", 1),
+            ("both", 1),
+            ("gone", 1),
+        ]);
+        let ours = view(&[
+            ("same", 1),
+            ("ours", 2),
+            ("theirs", 1),
+            ("both", 2),
+            ("new", 4),
+        ]);
+        let theirs = view(&[("same", 1), ("ours", 1), ("theirs", 3), ("both", 3)]);
+
+        let outcome = merge_trees(&base, &ours, &theirs, MergeStrategy::Ort, None);
+        assert_eq!(
+            view(&[
+                ("same", 1),
+                ("ours", 2),
+                ("theirs", 3),
+                ("both", 2),
+                ("new", 4)
+            ]),
+            outcome.tree
+        );
+        assert_eq!(
+            vec![Conflict {
+                name: "both".to_string(),
+                base: Some(entry(1)),
+                ours: Some(entry(2)),
+                theirs: Some(entry(3)),
+            }],
+            outcome.conflicts
+        );
+
+        let outcome = merge_trees(
+            &base,
+            &ours,
+            &theirs,
+            MergeStrategy::Ort,
+            Some(StrategyOption::Theirs),
+        );
+        assert_eq!(Some(&entry(3)), outcome.tree.get("both"));
+        assert!(outcome.conflicts.is_empty());
+
+        let outcome = merge_trees(&base, &ours, &theirs, MergeStrategy::Ours, None);
+        assert_eq!(ours, outcome.tree);
+        assert!(outcome.conflicts.is_empty());
+    }
+
+    #[test]
+    fn test_describe_conflict() {
+        let conflict = Conflict {
+            name: "file".to_string(),
+            base: Some(entry(1)),
+            ours: Some(entry(2)),
+            theirs: Some(entry(3)),
+        };
+        assert_eq!(
+            "CONFLICT (content): Merge conflict in file",
+            conflict.describe(("HEAD", "topic"))
+        );
+        let deleted_by_us = Conflict {
+            ours: None,
+            ..conflict.clone()
+        };
+        assert_eq!(
+            "CONFLICT (modify/delete): file deleted in HEAD and modified in topic",
+            deleted_by_us.describe(("HEAD", "topic"))
+        );
+        let deleted_by_them = Conflict {
+            theirs: None,
+            ..conflict
+        };
+        assert_eq!(
+            "CONFLICT (modify/delete): file deleted in topic and modified in HEAD",
+            deleted_by_them.describe(("HEAD", "topic"))
+        );
+    }
+
+    #[test]
+    fn test_conflict_file_content() {
+        let content = conflict_file_content(b"a\nb", b"c\n", ("HEAD", "feature"));
+        assert_eq!(
+            "<<<<<<< HEAD\na\nb\n=======\nc\n>>>>>>> feature\n",
+            String::from_utf8(content).unwrap()
+        );
+    }
+}
@@ -0,0 +1,211 @@
+//! This module merges the content of a file changed on both sides of a merge, line by line,
+//! as `git merge-file` does. Both versions are diffed against the base, and the runs of base
+//! lines each side replaced are combined: a run changed by one side only takes that side's
+//! lines, while runs changed by both sides which overlap or touch are a conflict unless both
+//! made the same change. Conflicts are written between markers, with our lines first.
+
+use super::diff::{diff_lines, split_lines, LineEdit};
+
+/// [`FileMerge`] is the merged content of a file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileMerge {
+    pub content: Vec<u8>,
+    /// Number of regions left between conflict markers
+    pub conflicts: usize,
+}
+
+/// A run of base lines `base_start..base_end` one side replaced with its lines
+/// `side_start..side_end`
+#[derive(Debug, Clone, Copy)]
+struct Change {
+    base_start: usize,
+    base_end: usize,
+    side_start: usize,
+    side_end: usize,
+}
+
+/// Group the edit script turning the base into a side into the runs of lines it replaced
+fn changes(edits: &[LineEdit]) -> Vec<Change> {
+    let mut changes: Vec<Change> = Vec::new();
+    let (mut base, mut side) = (0, 0);
+    let mut in_change = false;
+    for edit in edits {
+        match edit {
+            LineEdit::Equal(old, new) => {
+                (base, side) = (old + 1, new + 1);
+                in_change = false;
+                continue;
+            }
+            LineEdit::Delete(_) => base += 1,
+            LineEdit::Insert(_) => side += 1,
+        }
+        match changes.last_mut().filter(|_| in_change) {
+            Some(change) => (change.base_end, change.side_end) = (base, side),
+            None => {
+                let (base_start, side_start) = match edit {
+                    LineEdit::Delete(_) => (base - 1, side),
+                    _ => (base, side - 1),
+                };
+                changes.push(Change {
+                    base_start,
+                    base_end: base,
+                    side_start,
+                    side_end: side,
+                });
+                in_change = true;
+            }
+        }
+    }
+    changes
+}
+
+/// The lines of a side standing for the base lines `start..end`, given the changes of the
+/// side within them and the difference in length its earlier changes made
+fn side_range(changes: &[Change], delta: isize, start: usize, end: usize) -> (usize, usize) {
+    let grown: isize = changes
+        .iter()
+        .map(|c| (c.side_end - c.side_start) as isize - (c.base_end - c.base_start) as isize)
+        .sum();
+    (
+        (start as isize + delta) as usize,
+        (end as isize + delta + grown) as usize,
+    )
+}
+
+/// Merge `ours` and `theirs` against their common `base`, labelling conflicts with `labels`
+/// (ours, theirs)
+pub fn merge_file(base: &[u8], ours: &[u8], theirs: &[u8], labels: (&str, &str)) -> FileMerge {
+    let (base_lines, our_lines, their_lines) =
+        (split_lines(base), split_lines(ours), split_lines(theirs));
+    let our_changes = changes(&diff_lines(&base_lines, &our_lines));
+    let their_changes = changes(&diff_lines(&base_lines, &their_lines));
+
+    let mut content = Vec::with_capacity(ours.len().max(theirs.len()));
+    let mut conflicts = 0;
+    let (mut i, mut j) = (0, 0);
+    let (mut our_delta, mut their_delta) = (0isize, 0isize);
+    let mut copied = 0;
+    let push_lines = |content: &mut Vec<u8>, lines: &[&[u8]]| {
+        for line in lines {
+            content.extend_from_slice(line);
+        }
+    };
+
+    while i < our_changes.len() || j < their_changes.len() {
+        // Start a region at the first change, and grow it while changes of either side
+        // overlap or touch it
+        let first = match (our_changes.get(i), their_changes.get(j)) {
+            (Some(o), Some(t)) => o.base_start.min(t.base_start),
+            (Some(o), None) => o.base_start,
+            (None, Some(t)) => t.base_start,
+            (None, None) => unreachable!(),
+        };
+        let (start, mut end) = (first, first);
+        let (ours_from, theirs_from) = (i, j);
+        loop {
+            if let Some(o) = our_changes.get(i).filter(|c| c.base_start <= end) {
+                end = end.max(o.base_end);
+                i += 1;
+            } else if let Some(t) = their_changes.get(j).filter(|c| c.base_start <= end) {
+                end = end.max(t.base_end);
+                j += 1;
+            } else {
+                break;
+            }
+        }
+
+        push_lines(&mut content, &base_lines[copied..start]);
+        copied = end;
+        let (ours_in, theirs_in) = (&our_changes[ours_from..i], &their_changes[theirs_from..j]);
+        let (os, oe) = side_range(ours_in, our_delta, start, end);
+        let (ts, te) = side_range(theirs_in, their_delta, start, end);
+        our_delta += (oe - os) as isize - (end - start) as isize;
+        their_delta += (te - ts) as isize - (end - start) as isize;
+
+        let (our_region, their_region) = (&our_lines[os..oe], &their_lines[ts..te]);
+        if theirs_in.is_empty() || our_region == their_region {
+            push_lines(&mut content, our_region);
+        } else if ours_in.is_empty() {
+            push_lines(&mut content, their_region);
+        } else {
+            conflicts += 1;
+            for (marker, region) in [
+                (format!("<<<<<<< {}\n", labels.0), our_region),
+                ("=======\n".to_string(), their_region),
+            ] {
+                content.extend_from_slice(marker.as_bytes());
+                push_lines(&mut content, region);
+                if !content.ends_with(b"\n") {
+                    content.push(b'\n');
+                }
+            }
+            content.extend_from_slice(format!(">>>>>>> {}\n", labels.1).as_bytes());
+        }
+    }
+    push_lines(&mut content, &base_lines[copied..]);
+
+    FileMerge { content, conflicts }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn merge(base: &str, ours: &str, theirs: &str) -> (String, usize) {
+        let merged = merge_file(
+            base.as_bytes(),
+            ours.as_bytes(),
+            theirs.as_bytes(),
+            ("HEAD", "topic"),
+        );
+        (String::from_utf8(merged.content).unwrap(), merged.conflicts)
+    }
+
+    #[test]
+    fn test_merge_file() {
+        let base = "1\n2\n3\n4\n5\n6\n";
+
+        // Changes to different lines combine
+        assert_eq!(
+            ("one\n2\n3\n4\n5\nsix\n".to_string(), 0),
+            merge(base, "one\n2\n3\n4\n5\n6\n", "1\n2\n3\n4\n5\nsix\n")
+        );
+        assert_eq!(
+            ("1\n2\n3\n3.5\n4\n6\n".to_string(), 0),
+            merge(base, "1\n2\n3\n3.5\n4\n5\n6\n", "1\n2\n3\n4\n6\n")
+        );
+        // The same change on both sides is taken once
+        assert_eq!(
+            ("1\nII\n3\n4\n5\n6\nseven\n".to_string(), 0),
+            merge(base, "1\nII\n3\n4\n5\n6\n", "1\nII\n3\n4\n5\n6\nseven\n")
+        );
+
+        // Only the lines changed differently are between markers
+        assert_eq!(
+            (
+                "1\n<<<<<<< HEAD\nours\n=======\ntheirs\n>>>>>>> topic\n3\n4\n5\nsix\n".to_string(),
+                1
+            ),
+            merge(base, "1\nours\n3\n4\n5\nsix\n", "1\ntheirs\n3\n4\n5\n6\n")
+        );
+        // Adjacent changes conflict, as does a deletion against a modification
+        assert_eq!(1, merge(base, "1\nB\n3\n4\n5\n6\n", "1\n2\nC\n4\n5\n6\n").1);
+        assert_eq!(
+            (
+                "1\n<<<<<<< HEAD\n=======\nchanged\n>>>>>>> topic\n3\n4\n5\n6\n".to_string(),
+                1
+            ),
+            merge(base, "1\n3\n4\n5\n6\n", "1\nchanged\n3\n4\n5\n6\n")
+        );
+
+        // Files added on both sides merge against an empty base
+        assert_eq!(
+            (
+                "<<<<<<< HEAD\na\n=======\nb\n>>>>>>> topic\n".to_string(),
+                1
+            ),
+            merge("", "a", "b")
+        );
+        assert_eq!(("same\n".to_string(), 0), merge("", "same\n", "same\n"));
+    }
+}
@@ -0,0 +1,172 @@
+//! This module checks commit messages against the policy configured for the repository, a
+//! built-in `commit-msg` hook which needs no script:
+//!
+//! - `commit.maxSubjectLength`: the most characters the subject line may have
+//! - `commit.conventional`: the subject must start with a conventional-commit prefix, i.e.
+//!   `<type>[(<scope>)][!]: `, like `fix(fetch): ` or `feat!: `
+//! - `commit.messageRegex`: a regular expression the subject line must match
+//! - `commit.requireTrailer`: comma-separated keys of trailers the message must have, like
+//!   `Signed-off-by`, compared regardless of case
+//!
+//! A message is checked against every rule so that all the problems are reported at once.
+
+use anyhow::anyhow;
+use regex::Regex;
+
+use super::trailers::parse_message;
+use crate::repo::config::{ConfigSource, GitConfig};
+
+const CONVENTIONAL_PREFIX: &str = r"^[a-z]+(\([^()\s]+\))?!?: \S";
+
+/// [`MessagePolicy`] holds the rules commit messages must follow
+#[derive(Debug, Clone, Default)]
+pub struct MessagePolicy {
+    max_subject_length: Option<usize>,
+    conventional: bool,
+    subject_regex: Option<Regex>,
+    required_trailers: Vec<String>,
+}
+
+impl MessagePolicy {
+    /// Read the policy from the configuration. Nothing is enforced by default.
+    pub fn from_config(config: &GitConfig) -> crate::Result<MessagePolicy> {
+        let max_subject_length = config
+            .get("commit.maxSubjectLength", ConfigSource::All)?
+            .map(|value| {
+                value.parse::<usize>().map_err(|_| {
+                    anyhow!(
+                        "fatal: bad numeric config value '{}' for 'commit.maxSubjectLength'",
+                        value
+                    )
+                })
+            })
+            .transpose()?;
+        let conventional = config
+            .get_bool("commit.conventional", ConfigSource::All)?
+            .unwrap_or(false);
+        let subject_regex = config
+            .get("commit.messageRegex", ConfigSource::All)?
+            .map(|regex| {
+                Regex::new(regex)
+                    .map_err(|err| anyhow!("fatal: invalid regex in commit.messageRegex: {}", err))
+            })
+            .transpose()?;
+        let required_trailers = config
+            .get("commit.requireTrailer", ConfigSource::All)?
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|key| !key.is_empty())
+            .map(str::to_string)
+            .collect();
+        Ok(MessagePolicy {
+            max_subject_length,
+            conventional,
+            subject_regex,
+            required_trailers,
+        })
+    }
+
+    /// The rules the message breaks, empty if it follows the policy. `separators` are those
+    /// of `trailer.separators`.
+    pub fn violations(&self, message: &str, separators: &str) -> Vec<String> {
+        let mut violations = Vec::new();
+        let subject = message.lines().next().unwrap_or_default().trim_end();
+        if subject.trim().is_empty() {
+            violations.push("the message has no subject line".to_string());
+        }
+
+        let length = subject.chars().count();
+        if let Some(max) = self.max_subject_length.filter(|max| length > *max) {
+            violations.push(format!(
+                "the subject is {} characters long, more than commit.maxSubjectLength ({})",
+                length, max
+            ));
+        }
+        if self.conventional && !Regex::new(CONVENTIONAL_PREFIX).unwrap().is_match(subject) {
+            violations.push(
+                "the subject does not start with a conventional-commit prefix like 'fix(scope): ' (commit.conventional)"
+                    .to_string(),
+            );
+        }
+        if let Some(regex) = self.subject_regex.as_ref().filter(|r| !r.is_match(subject)) {
+            violations.push(format!(
+                "the subject does not match commit.messageRegex '{}'",
+                regex.as_str()
+            ));
+        }
+
+        let trailers = parse_message(message, separators).trailers;
+        for key in &self.required_trailers {
+            if !trailers.iter().any(|t| t.key.eq_ignore_ascii_case(key)) {
+                violations.push(format!(
+                    "the message lacks a '{}' trailer (commit.requireTrailer)",
+                    key
+                ));
+            }
+        }
+        violations
+    }
+
+    /// Fail with every rule the message breaks
+    pub fn check(&self, message: &str, separators: &str) -> crate::Result<()> {
+        let violations = self.violations(message, separators);
+        if violations.is_empty() {
+            return Ok(());
+        }
+        let list: String = violations
+            .iter()
+            .map(|violation| format!("\n  - {}", violation))
+            .collect();
+        Err(anyhow!(
+            "fatal: the commit message does not follow the repository's policy:{}",
+            list
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_violations() {
+        let policy = MessagePolicy::default();
+        assert!(policy.violations("anything goes", ":").is_empty());
+        assert_eq!(1, policy.violations("\n\nbody only", ":").len());
+
+        let policy = MessagePolicy {
+            max_subject_length: Some(22),
+            conventional: true,
+            subject_regex: Some(Regex::new(r"#\d+").unwrap()),
+            required_trailers: vec!["Signed-off-by".to_string()],
+        };
+        assert!(policy
+            .violations(
+                "fix(fetch): resume #12\n\nBody.\n\nsigned-off-by: A <a@example.com>",
+                ":"
+            )
+            .is_empty());
+        assert!(policy
+            .violations("feat!: drop v1 #3\n\nSigned-off-by: A", ":")
+            .is_empty());
+
+        let violations = policy.violations("Fix the fetch of chunks\n\nSigned-off-by: A", ":");
+        assert_eq!(3, violations.len());
+        assert!(violations[0].contains("23 characters"));
+        assert!(violations[1].contains("conventional"));
+        assert!(violations[2].contains("commit.messageRegex"));
+
+        // The subject paragraph is never a trailer block
+        let violations = policy.violations("Signed-off-by: A", ":");
+        assert!(violations
+            .last()
+            .unwrap()
+            .contains("'Signed-off-by' trailer"));
+
+        let err = policy.check("fix: #1", ":").unwrap_err().to_string();
+        assert!(err.ends_with(
+            "\n  - the message lacks a 'Signed-off-by' trailer (commit.requireTrailer)"
+        ));
+    }
+}
@@ -1,8 +1,66 @@
 //! This module provides actual implementations of the git operations.
 
+mod api;
+mod archive;
+mod attributes;
+mod bundle;
+mod cache;
+mod cherry;
+mod cherry_pick;
 pub mod cmds;
+mod completion;
+mod connectivity;
 mod constants;
+mod diff;
+pub mod dry_run;
+mod dump;
+mod editor;
+mod encoding;
+mod file_changes;
 mod files;
+mod filters;
+mod graph;
+mod hooks;
+mod identity;
 pub mod ignore;
+mod mailmap;
+mod manifest;
+mod merge;
+mod merge_file;
+mod message_policy;
 mod model;
+mod patchid;
+mod paths;
+pub mod perf;
+mod pickaxe;
+mod pin;
+mod progress;
+mod protect;
+mod protocol;
+mod prune;
+mod quote;
+mod range_diff;
+mod reachability;
+mod rebase;
+mod refname;
+mod refspec;
+mod remote;
+mod revision;
+mod schema;
+mod search;
+mod signature;
+mod snapshot;
+mod sqlite;
+mod stash;
+mod stats;
+mod storage;
+#[cfg(test)]
+mod test_utils;
+mod tools;
+mod trailers;
+mod transfer;
+mod transport;
+mod tree;
 pub mod utils;
+mod web;
+mod worktree;
@@ -6,86 +6,165 @@
 
 use anyhow::{anyhow, Context};
 use serde::{de::Visitor, Deserialize, Serialize};
-use sha1::{self, Digest};
-use std::{fmt, path::Path};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
 
 use rusqlite::{
     params,
-    types::{FromSql, FromSqlResult, ToSqlOutput, ValueRef},
-    Connection, OptionalExtension, ToSql,
+    types::{FromSql, FromSqlError, FromSqlResult, ToSqlOutput, Type, ValueRef},
+    Connection, OptionalExtension, Row, ToSql,
+};
+
+use super::{
+    constants::BRANCH_PREFIX,
+    manifest::track_table,
+    perf::{self, Category},
+    refname::check_ref_format,
 };
+use crate::repo::db::{Hasher, HeadState};
 
-/// Index table stores a single copy of a [`Index`] data structure in JSONB format
-pub const CREATE_INDEX_TABLE: &str = "CREATE TABLE Index_ (index_ JSON);";
+/// Index table stores one row per [`IndexEntry`], keyed by name and merge stage. Repositories
+/// created by older versions store the whole [`Index`] as a single JSON row instead, or lack
+/// the intent_to_add column.
+pub const CREATE_INDEX_TABLE: &str = "CREATE TABLE Index_ (name TEXT NOT NULL, stage INTEGER NOT NULL, ctime INTEGER NOT NULL, mtime INTEGER NOT NULL, dev INTEGER NOT NULL, ino INTEGER NOT NULL, mode_type TEXT NOT NULL, mode_perms INTEGER NOT NULL, uid INTEGER NOT NULL, gid INTEGER NOT NULL, fsize INTEGER NOT NULL, sha BLOB NOT NULL, assume_valid INTEGER NOT NULL, intent_to_add INTEGER NOT NULL DEFAULT 0, PRIMARY KEY (name, stage));";
 /// Head tables stores a single copy of a [`Head`] data structure in JSON format
 pub const CREATE_HEAD_TABLE: &str = "CREATE TABLE Head (head JSON);";
 /// Ref points to a commit
 pub const CREATE_REF_TABLE: &str =
     "CREATE TABLE Refs (ref_name TEXT PRIMARY KEY, commit_id BLOB NOT NULL);";
+// The object tables record in created_at the Unix time each row was written, telling how long
+// an unreachable object has been around. Databases created by older versions lack the column.
+/// Identities stores each distinct (name, email) pair commits are authored or committed with once
+pub const CREATE_IDENTITIES_TABLE: &str = "CREATE TABLE Identities (identity_id INTEGER PRIMARY KEY, name TEXT NOT NULL, email TEXT NOT NULL, UNIQUE (name, email));";
 /// Commit points to a tree and contains a set of metadata
 /// Note: parent_id is empty for the root commit, and for other commits,
 /// parent_id is a list of sha1 hash blobs stored side by side, and we leverage the fact that sha1 hashes are always 8-bytes long to delimit them.
-pub const CREATE_COMMIT_TABLE: &str = "CREATE TABLE Commits (commit_id BLOB PRIMARY KEY, tree_id TEXT NOT NULL, parent_ids BLOB NOT NULL, author_name TEXT NOT NULL, author_email TEXT NOT NULL, committer_name TEXT NOT NULL, committer_email TEXT NOT NULL, message TEXT NOT NULL);";
+/// The author and committer reference the Identities table. Repositories created by older
+/// versions store their names and emails inline instead (see [`CommitLayout`]).
+pub const CREATE_COMMIT_TABLE: &str = "CREATE TABLE Commits (commit_id BLOB PRIMARY KEY, tree_id TEXT NOT NULL, parent_ids BLOB NOT NULL, author_id INTEGER NOT NULL REFERENCES Identities (identity_id), committer_id INTEGER NOT NULL REFERENCES Identities (identity_id), message TEXT NOT NULL, encoding TEXT, created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now')));";
+pub const CREATE_COMMIT_AUTHOR_INDEX: &str = "CREATE INDEX Commits_author ON Commits (author_id);";
 /// Tree points to a list of other trees (subdirectories) and blobs (file contents) and maintains their symbolic names
 /// This data is encoded as a newline-separated text following the original git file format, where each line is of format
 /// <file_mode> <file_type[blob|tree]> <object_id[tree_id|blob_id]> <file_name>
-pub const CREATE_TREE_TABLE: &str =
-    "CREATE TABLE Trees (tree_id TEXT PRIMARY KEY, data TEXT NOT NULL);";
+pub const CREATE_TREE_TABLE: &str = "CREATE TABLE Trees (tree_id TEXT PRIMARY KEY, data TEXT NOT NULL, created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now')));";
 /// Blob stores actual file content
-pub const CREATE_BLOB_TABLE: &str = "CREATE TABLE Blobs (blob_id TEXT, data BLOB NOT NULL);";
+pub const CREATE_BLOB_TABLE: &str = "CREATE TABLE Blobs (blob_id TEXT, data BLOB NOT NULL, created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now')));";
+/// MergeHead stores the commit being merged into HEAD while a conflicted merge waits to be committed.
+/// Like the Head table, it holds at most a single row.
+pub const CREATE_MERGE_HEAD_TABLE: &str =
+    "CREATE TABLE MergeHead (commit_id BLOB NOT NULL, message TEXT NOT NULL);";
+/// RebaseState stores a single copy of a [`RebaseState`] in JSON format while a rebase is in progress
+pub const CREATE_REBASE_STATE_TABLE: &str = "CREATE TABLE RebaseState (state JSON);";
+/// CherryPickState stores a single copy of a [`CherryPickState`] in JSON format while a cherry-pick is in progress
+pub const CREATE_CHERRY_PICK_STATE_TABLE: &str = "CREATE TABLE CherryPickState (state JSON);";
+/// Stash stores the stack of stashed changes, the most recent entry having the largest stash_id
+pub const CREATE_STASH_TABLE: &str = "CREATE TABLE Stash (stash_id INTEGER PRIMARY KEY AUTOINCREMENT, commit_id BLOB NOT NULL, message TEXT NOT NULL);";
+/// FetchHead records the tips fetched by the last fetch, in order, the first one being FETCH_HEAD
+pub const CREATE_FETCH_HEAD_TABLE: &str =
+    "CREATE TABLE FetchHead (commit_id BLOB NOT NULL, description TEXT NOT NULL);";
 
 // Read queries
-pub const READ_INDEX: &str = "SELECT index_ FROM Index_";
+pub const READ_INDEX: &str = "SELECT name, stage, ctime, mtime, dev, ino, mode_type, mode_perms, uid, gid, fsize, sha, assume_valid, intent_to_add FROM Index_ ORDER BY name, stage";
+pub const READ_INDEX_FOR_NAME: &str = "SELECT name, stage, ctime, mtime, dev, ino, mode_type, mode_perms, uid, gid, fsize, sha, assume_valid, intent_to_add FROM Index_ WHERE name = ?1 ORDER BY stage";
+pub const READ_INDEX_WITHOUT_INTENT_TO_ADD: &str = "SELECT name, stage, ctime, mtime, dev, ino, mode_type, mode_perms, uid, gid, fsize, sha, assume_valid, 0 FROM Index_ ORDER BY name, stage";
+pub const READ_INDEX_FOR_NAME_WITHOUT_INTENT_TO_ADD: &str = "SELECT name, stage, ctime, mtime, dev, ino, mode_type, mode_perms, uid, gid, fsize, sha, assume_valid, 0 FROM Index_ WHERE name = ?1 ORDER BY stage";
+/// Whether the Index_ table has the intent_to_add column, which repositories created by older
+/// versions lack
+pub const HAS_INTENT_TO_ADD: &str =
+    "SELECT count(*) > 0 FROM pragma_table_info('Index_') WHERE name = 'intent_to_add'";
+pub const READ_LEGACY_INDEX: &str = "SELECT index_ FROM Index_";
+pub const IS_LEGACY_INDEX: &str =
+    "SELECT count(*) > 0 FROM pragma_table_info('Index_') WHERE name = 'index_'";
 pub const READ_HEAD: &str = "SELECT head FROM Head";
 pub const READ_BLOB_FOR_ID: &str = "SELECT blob_id, data FROM Blobs WHERE blob_id = ?1";
+/// Followed by the list of blob ids to look up
+pub const READ_BLOB_SIZES: &str = "SELECT blob_id, length(data) FROM Blobs WHERE blob_id IN";
 pub const READ_TREE_FOR_ID: &str = "SELECT tree_id, data FROM Trees WHERE tree_id = ?1";
-pub const READ_COMMIT_FOR_ID: &str = "SELECT commit_id, tree_id, parent_ids, author_name, author_email, committer_name, committer_email, message FROM Commits WHERE commit_id = ?1";
+/// Whether the Commits table of the given schema references identities, and whether it records
+/// the encoding of the messages
+pub const READ_COMMIT_LAYOUT: &str = "SELECT (SELECT count(*) > 0 FROM pragma_table_info('Commits', ?1) WHERE name = 'author_id'), (SELECT count(*) > 0 FROM pragma_table_info('Commits', ?1) WHERE name = 'encoding')";
 pub const READ_REF_FOR_NAME: &str = "SELECT ref_name, commit_id FROM Refs WHERE ref_name = ?1";
+pub const READ_ALL_REFS: &str = "SELECT ref_name, commit_id FROM Refs ORDER BY ref_name";
+/// A ref whose name is a directory of the given name, or that has the given name as a directory
+pub const READ_CONFLICTING_REF: &str = "SELECT ref_name FROM Refs WHERE substr(?1, 1, length(ref_name) + 1) = ref_name || '/' OR substr(ref_name, 1, length(?1) + 1) = ?1 || '/' LIMIT 1";
+pub const READ_MERGE_HEAD: &str = "SELECT commit_id, message FROM MergeHead";
+pub const READ_REBASE_STATE: &str = "SELECT state FROM RebaseState";
+pub const READ_CHERRY_PICK_STATE: &str = "SELECT state FROM CherryPickState";
+pub const READ_STASH: &str = "SELECT commit_id, message FROM Stash ORDER BY stash_id DESC";
+pub const READ_FETCH_HEAD: &str = "SELECT commit_id, description FROM FetchHead ORDER BY rowid";
 
 // Write queries
-pub const INSERT_INDEX: &str = "INSERT INTO Index_ VALUES (?1);";
+pub const INSERT_INDEX_ENTRY: &str = "INSERT INTO Index_ (name, stage, ctime, mtime, dev, ino, mode_type, mode_perms, uid, gid, fsize, sha, assume_valid, intent_to_add) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14);";
+pub const INSERT_INDEX_ENTRY_WITHOUT_INTENT_TO_ADD: &str = "INSERT INTO Index_ (name, stage, ctime, mtime, dev, ino, mode_type, mode_perms, uid, gid, fsize, sha, assume_valid) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13);";
+pub const INSERT_LEGACY_INDEX: &str = "INSERT INTO Index_ VALUES (?1);";
 pub const INSERT_HEAD: &str = "INSERT INTO Head (head) VALUES (?1);";
 pub const INSERT_BLOB: &str = "INSERT OR IGNORE INTO Blobs (blob_id, data) VALUES (?1, ?2);";
 pub const INSERT_TREE: &str = "INSERT OR IGNORE INTO Trees (tree_id, data) VALUES (?1, ?2);";
-pub const INSERT_COMMIT: &str = "INSERT OR IGNORE INTO Commits (commit_id, tree_id, parent_ids, author_name, author_email, committer_name, committer_email, message) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8);";
+pub const INSERT_IDENTITY: &str = "INSERT OR IGNORE INTO Identities (name, email) VALUES (?1, ?2);";
+pub const INSERT_COMMIT: &str = "INSERT OR IGNORE INTO Commits (commit_id, tree_id, parent_ids, author_id, committer_id, message, encoding) VALUES (?1, ?2, ?3, (SELECT identity_id FROM Identities WHERE name = ?4 AND email = ?5), (SELECT identity_id FROM Identities WHERE name = ?6 AND email = ?7), ?8, ?9);";
+pub const INSERT_INLINE_COMMIT: &str = "INSERT OR IGNORE INTO Commits (commit_id, tree_id, parent_ids, author_name, author_email, committer_name, committer_email, message, encoding) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9);";
+pub const INSERT_LEGACY_COMMIT: &str = "INSERT OR IGNORE INTO Commits (commit_id, tree_id, parent_ids, author_name, author_email, committer_name, committer_email, message) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8);";
 pub const INSERT_OR_REPLACE_REF: &str =
     "INSERT OR REPLACE INTO Refs (ref_name, commit_id) VALUES (?1, ?2);";
+pub const INSERT_MERGE_HEAD: &str = "INSERT INTO MergeHead (commit_id, message) VALUES (?1, ?2);";
+pub const INSERT_REBASE_STATE: &str = "INSERT INTO RebaseState (state) VALUES (?1);";
+pub const INSERT_CHERRY_PICK_STATE: &str = "INSERT INTO CherryPickState (state) VALUES (?1);";
+pub const INSERT_STASH: &str = "INSERT INTO Stash (commit_id, message) VALUES (?1, ?2);";
+pub const INSERT_FETCH_HEAD: &str =
+    "INSERT INTO FetchHead (commit_id, description) VALUES (?1, ?2);";
+pub const DELETE_INDEX: &str = "DELETE FROM Index_;";
+pub const DELETE_INDEX_FOR_NAME: &str = "DELETE FROM Index_ WHERE name = ?1;";
+pub const DELETE_STASH_AT: &str = "DELETE FROM Stash WHERE stash_id = (SELECT stash_id FROM Stash ORDER BY stash_id DESC LIMIT 1 OFFSET ?1);";
+
+/// Number of blob ids bound to a single query of [`Blob::read_sizes`], staying well below the
+/// limit of SQLite on the number of parameters
+const BLOB_SIZES_BATCH_SIZE: usize = 500;
 
 /// Generic trait describing any git object that could be hashed and get an ID for.
 pub trait Hashable {
-    fn hash(&self, sha: sha1::Sha1) -> Sha1Id;
+    fn hash(&self, hasher: impl Hasher) -> Sha1Id;
 }
 
 impl<T> Hashable for Blob<T> {
-    fn hash(&self, mut sha: sha1::Sha1) -> Sha1Id {
-        // The hash of the glob is just the hash of the content
-        sha.update(&self.data);
-        let result = sha.finalize();
-        Sha1Id(result.into())
+    fn hash(&self, hasher: impl Hasher) -> Sha1Id {
+        hash_blob_data(hasher, &self.data)
     }
 }
 
+/// Hash the content of a blob without building the [`Blob`], so that the buffer holding it can
+/// be reused
+pub fn hash_blob_data(mut hasher: impl Hasher, data: &[u8]) -> Sha1Id {
+    let _span = perf::span(Category::Hashing);
+    // The hash of the glob is just the hash of the content
+    hasher.update(data);
+    Sha1Id(hasher.finish())
+}
+
 impl<T> Hashable for Tree<T> {
-    fn hash(&self, mut sha: sha1::Sha1) -> Sha1Id {
+    fn hash(&self, mut sha: impl Hasher) -> Sha1Id {
+        let _span = perf::span(Category::Hashing);
         // The hash of the tree is the hash of all the tree entries in the format
         // <mode> <type> <id> <name>
         // concatenated with "\n"
         let text = self.encode_entries();
         sha.update(&text);
 
-        let result = sha.finalize();
-        Sha1Id(result.into())
+        Sha1Id(sha.finish())
     }
 }
 
 impl<T> Hashable for Commit<T> {
-    fn hash(&self, mut sha: sha1::Sha1) -> Sha1Id {
+    fn hash(&self, mut sha: impl Hasher) -> Sha1Id {
+        let _span = perf::span(Category::Hashing);
         // the hash of the commit is the hash of all the fields concatednated in the form
         // <tree_id>
         // <parent_id>
         // ...
         // <author_name> <author_email>
         // <committer_name> <committer_email>
+        // [encoding <encoding>, unless the message is UTF-8]
         // [empty line]
         // <message>
         // [empty line]
@@ -107,19 +186,26 @@ impl<T> Hashable for Commit<T> {
         sha.update(" ");
 
         sha.update(&self.committer_email);
-        sha.update("\n\n");
+        sha.update("\n");
+
+        if let Some(encoding) = &self.encoding {
+            sha.update("encoding ");
+            sha.update(encoding);
+            sha.update("\n");
+        }
+        sha.update("\n");
 
         sha.update(&self.message);
         sha.update("\n");
 
-        let result = sha.finalize();
-        Sha1Id(result.into())
+        Sha1Id(sha.finish())
     }
 }
 
 /// The models provded in this module like [`Commit`] and [`Blob`] have two possible states:
 /// 1. Freshly constructed from the staging area -> No Id yet
 /// 2. Stored in the gitqlite database -> Has a valid hash as Id
+///
 /// The [`IdType`] trait generalizes over the states.
 pub trait IdType<T>: Copy + fmt::Display {
     type Id: PartialEq + Eq;
@@ -148,7 +234,7 @@ impl<T> IdType<T> for NoId {
 }
 
 /// The canonical ID type used for all git objects, which is a SHA1 hash byte array
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct Sha1Id([u8; 20]);
 
 impl fmt::Display for Sha1Id {
@@ -235,6 +321,19 @@ impl TryFrom<Vec<u8>> for Sha1Id {
     }
 }
 
+/// The ids of the git layer name the same objects as the ids of [`crate::repo::db`]
+impl From<Sha1Id> for crate::repo::db::Sha1Id {
+    fn from(value: Sha1Id) -> Self {
+        crate::repo::db::Sha1Id::try_from(value.0.to_vec()).expect("a sha1 is 20 bytes")
+    }
+}
+
+impl From<crate::repo::db::Sha1Id> for Sha1Id {
+    fn from(value: crate::repo::db::Sha1Id) -> Self {
+        Sha1Id::try_from(value.to_string().as_str()).expect("a sha1 is 20 bytes")
+    }
+}
+
 impl IdType<Sha1Id> for Sha1Id {
     type Id = Sha1Id;
 
@@ -256,7 +355,7 @@ impl ToSql for Sha1Id {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub enum ModeType {
     Regular,
     Symlink,
@@ -265,7 +364,7 @@ pub enum ModeType {
 
 /// [`IndexEntry`] represents one entry in the staging area, which is the snapshot of a file
 /// in a point in time
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct IndexEntry {
     /// The last time the file's metadata has changed, in nanosecond
     pub ctime: i64,
@@ -289,8 +388,13 @@ pub struct IndexEntry {
     pub sha: Sha1Id,
     /// TODO: fill doc
     pub flag_assume_valid: bool,
-    /// TODO: fill doc
+    /// Merge stage of the entry: 0 for a normal entry, and 1 (common ancestor), 2 (ours)
+    /// or 3 (theirs) for the versions of a file left conflicted by a merge
     pub flag_stage: u8,
+    /// The path was added with `add --intent-to-add`: it is tracked, but its content is not
+    /// staged yet and `sha` is the empty blob. Such entries are left out of commits.
+    #[serde(default)]
+    pub flag_intent_to_add: bool,
     /// Full path of the object relative to repo root
     pub name: String,
 }
@@ -301,46 +405,371 @@ pub struct Index {
     pub entries: Vec<IndexEntry>,
 }
 
+impl ModeType {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ModeType::Regular => "Regular",
+            ModeType::Symlink => "Symlink",
+            ModeType::Gitlink => "Gitlink",
+        }
+    }
+}
+
+impl FromSql for ModeType {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value.as_str()? {
+            "Regular" => Ok(ModeType::Regular),
+            "Symlink" => Ok(ModeType::Symlink),
+            "Gitlink" => Ok(ModeType::Gitlink),
+            _ => Err(FromSqlError::InvalidType),
+        }
+    }
+}
+
+impl IndexEntry {
+    fn from_row(row: &rusqlite::Row) -> rusqlite::Result<IndexEntry> {
+        Ok(IndexEntry {
+            name: row.get(0)?,
+            flag_stage: row.get(1)?,
+            ctime: row.get(2)?,
+            mtime: row.get(3)?,
+            // Unsigned values are stored as the signed integers of the same bits
+            dev: row.get::<_, i64>(4)? as u64,
+            ino: row.get::<_, i64>(5)? as u64,
+            mode_type: row.get(6)?,
+            mode_perms: row.get(7)?,
+            uid: row.get(8)?,
+            gid: row.get(9)?,
+            fsize: row.get::<_, i64>(10)? as u64,
+            sha: row.get(11)?,
+            flag_assume_valid: row.get(12)?,
+            flag_intent_to_add: row.get(13)?,
+        })
+    }
+
+    fn persist(&self, conn: &Connection) -> crate::Result<()> {
+        if !has_intent_to_add(conn)? {
+            if self.flag_intent_to_add {
+                return Err(anyhow!(
+                    "fatal: this repository was created by an older version of gitqlite and cannot record intent-to-add entries"
+                ));
+            }
+            conn.prepare_cached(INSERT_INDEX_ENTRY_WITHOUT_INTENT_TO_ADD)?
+                .execute(params![
+                    self.name,
+                    self.flag_stage,
+                    self.ctime,
+                    self.mtime,
+                    self.dev as i64,
+                    self.ino as i64,
+                    self.mode_type.as_str(),
+                    self.mode_perms,
+                    self.uid,
+                    self.gid,
+                    self.fsize as i64,
+                    self.sha,
+                    self.flag_assume_valid,
+                ])?;
+            return Ok(());
+        }
+        conn.prepare_cached(INSERT_INDEX_ENTRY)?.execute(params![
+            self.name,
+            self.flag_stage,
+            self.ctime,
+            self.mtime,
+            self.dev as i64,
+            self.ino as i64,
+            self.mode_type.as_str(),
+            self.mode_perms,
+            self.uid,
+            self.gid,
+            self.fsize as i64,
+            self.sha,
+            self.flag_assume_valid,
+            self.flag_intent_to_add,
+        ])?;
+        Ok(())
+    }
+}
+
 impl Index {
     pub fn read_from_conn(conn: &Connection) -> crate::Result<Index> {
-        Ok(conn
-            .query_row(READ_INDEX, (), |row| row.get::<_, String>(0))
-            .optional()?
-            .map(|s| serde_json::from_str(&s))
-            .transpose()?
-            .unwrap_or_default())
+        if is_legacy_index(conn)? {
+            return Ok(conn
+                .query_row(READ_LEGACY_INDEX, (), |row| row.get::<_, String>(0))
+                .optional()?
+                .map(|s| serde_json::from_str(&s))
+                .transpose()?
+                .unwrap_or_default());
+        }
+
+        let query = if has_intent_to_add(conn)? {
+            READ_INDEX
+        } else {
+            READ_INDEX_WITHOUT_INTENT_TO_ADD
+        };
+        let entries = conn
+            .prepare_cached(query)?
+            .query_map((), IndexEntry::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Index { entries })
     }
 
     pub fn persist(&self, conn: &Connection) -> crate::Result<()> {
-        conn.execute("DELETE FROM Index_;", ())?;
-        let s = serde_json::to_string(self)?;
-        conn.execute(INSERT_INDEX, params![s])?;
+        conn.execute(DELETE_INDEX, ())?;
+        if is_legacy_index(conn)? {
+            let s = serde_json::to_string(self)?;
+            conn.execute(INSERT_LEGACY_INDEX, params![s])?;
+            return Ok(());
+        }
+
+        for entry in &self.entries {
+            entry.persist(conn)?;
+        }
+        Ok(())
+    }
+
+    /// Read the entries (one per merge stage) of the given name, without reading the whole
+    /// index
+    pub fn read_entries_with_name(conn: &Connection, name: &str) -> crate::Result<Vec<IndexEntry>> {
+        if is_legacy_index(conn)? {
+            let mut index = Index::read_from_conn(conn)?;
+            index.entries.retain(|e| e.name == name);
+            return Ok(index.entries);
+        }
+
+        let query = if has_intent_to_add(conn)? {
+            READ_INDEX_FOR_NAME
+        } else {
+            READ_INDEX_FOR_NAME_WITHOUT_INTENT_TO_ADD
+        };
+        let entries = conn
+            .prepare_cached(query)?
+            .query_map([name], IndexEntry::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(entries)
+    }
+
+    /// Stage the entries in the database, replacing the existing entries with the same names,
+    /// without reading the whole index
+    pub fn stage_entries(conn: &Connection, entries: Vec<IndexEntry>) -> crate::Result<()> {
+        if is_legacy_index(conn)? {
+            let mut index = Index::read_from_conn(conn)?;
+            index.insert_all(entries);
+            return index.persist(conn);
+        }
+
+        let mut delete = conn.prepare_cached(DELETE_INDEX_FOR_NAME)?;
+        for entry in &entries {
+            delete.execute([&entry.name])?;
+        }
+        for entry in &entries {
+            entry.persist(conn)?;
+        }
+        Ok(())
+    }
+
+    /// Remove every entry of the given name from the database, without reading the whole index
+    pub fn unstage_name(conn: &Connection, name: &str) -> crate::Result<()> {
+        if is_legacy_index(conn)? {
+            let mut index = Index::read_from_conn(conn)?;
+            index.remove_name(name);
+            return index.persist(conn);
+        }
+
+        conn.prepare_cached(DELETE_INDEX_FOR_NAME)?
+            .execute([name])?;
         Ok(())
     }
 
-    pub fn remove(
-        &mut self,
-        path: impl AsRef<Path>,
-        repo_root: impl AsRef<Path>,
-        remove_worktree: bool,
-    ) -> crate::Result<Option<IndexEntry>> {
-        let path = dunce::canonicalize(path)?;
-        let rel_path = path.strip_prefix(repo_root)?.to_string_lossy();
+    /// Insert an entry, replacing every existing entry (of any merge stage) with the same name.
+    /// Entries are kept sorted by name and stage.
+    pub fn insert(&mut self, entry: IndexEntry) {
+        self.entries.retain(|e| e.name != entry.name);
+        self.entries.push(entry);
+        self.entries
+            .sort_by(|e1, e2| (&e1.name, e1.flag_stage).cmp(&(&e2.name, e2.flag_stage)));
+    }
+
+    /// Insert many entries at once, replacing the existing entries with the same names. This is
+    /// the same as inserting them one by one, but sorts the entries only once.
+    pub fn insert_all(&mut self, entries: Vec<IndexEntry>) {
+        let names: HashSet<String> = entries.iter().map(|e| e.name.clone()).collect();
+        self.entries.retain(|e| !names.contains(&e.name));
+        self.entries.extend(entries);
+        self.entries
+            .sort_by(|e1, e2| (&e1.name, e1.flag_stage).cmp(&(&e2.name, e2.flag_stage)));
+    }
+
+    /// Insert the entries of a conflicted path, one per merge stage, replacing any existing entry.
+    pub fn insert_conflict(&mut self, name: &str, stages: Vec<IndexEntry>) {
+        self.entries.retain(|e| e.name != name);
+        self.entries.extend(stages);
+        self.entries
+            .sort_by(|e1, e2| (&e1.name, e1.flag_stage).cmp(&(&e2.name, e2.flag_stage)));
+    }
+
+    /// Remove every entry of the given name (relative to repo root) from the index.
+    pub fn remove_name(&mut self, name: &str) {
+        self.entries.retain(|e| e.name != name);
+    }
+
+    /// Whether the index contains unmerged entries left by a conflicted merge.
+    pub fn has_conflicts(&self) -> bool {
+        self.entries.iter().any(|e| e.flag_stage != 0)
+    }
 
-        let Some(idx) = self
+    /// Names of the paths left unmerged, deduplicated and sorted
+    pub fn conflicted_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self
             .entries
             .iter()
-            .position(|entry| &entry.name == &*rel_path)
-        else {
-            return Ok(None);
-        };
+            .filter(|e| e.flag_stage != 0)
+            .map(|e| e.name.as_str())
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+}
+
+/// [`CommitLayout`] is how the Commits table of a repository stores commits, which changed
+/// over the versions of gitqlite
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommitLayout {
+    /// Authors and committers reference the Identities table
+    Identities,
+    /// Authors and committers are stored inline as names and emails
+    Inline,
+    /// Inline identities, and no encoding column
+    Legacy,
+}
+
+impl CommitLayout {
+    /// Query selecting every commit of the Commits table of `schema` as (commit_id, tree_id,
+    /// parent_ids, author_name, author_email, committer_name, committer_email, message,
+    /// encoding), whatever the layout
+    pub fn select_commits(self, schema: &str) -> String {
+        match self {
+            CommitLayout::Identities => format!(
+                "SELECT c.commit_id AS commit_id, c.tree_id AS tree_id, c.parent_ids AS parent_ids, \
+                a.name AS author_name, a.email AS author_email, \
+                m.name AS committer_name, m.email AS committer_email, \
+                c.message AS message, c.encoding AS encoding \
+                FROM \"{schema}\".Commits AS c \
+                JOIN \"{schema}\".Identities AS a ON a.identity_id = c.author_id \
+                JOIN \"{schema}\".Identities AS m ON m.identity_id = c.committer_id"
+            ),
+            CommitLayout::Inline | CommitLayout::Legacy => format!(
+                "SELECT c.commit_id AS commit_id, c.tree_id AS tree_id, c.parent_ids AS parent_ids, \
+                c.author_name AS author_name, c.author_email AS author_email, \
+                c.committer_name AS committer_name, c.committer_email AS committer_email, \
+                c.message AS message, {} AS encoding \
+                FROM \"{schema}\".Commits AS c",
+                if self == CommitLayout::Inline {
+                    "c.encoding"
+                } else {
+                    "NULL"
+                }
+            ),
+        }
+    }
 
-        if remove_worktree {
-            std::fs::remove_file(&path)?;
+    /// Statements copying the commits selected by `source`, with the columns of
+    /// [`CommitLayout::select_commits`], into `table` laid out like this. The encoding is
+    /// dropped by the legacy layout.
+    pub fn copy_commits(self, source: &str, table: &str) -> String {
+        match self {
+            CommitLayout::Identities => format!(
+                "INSERT OR IGNORE INTO main.Identities (name, email) \
+                    SELECT author_name, author_email FROM ({source}) \
+                    UNION SELECT committer_name, committer_email FROM ({source});
+                INSERT OR IGNORE INTO main.\"{table}\" (commit_id, tree_id, parent_ids, author_id, committer_id, message, encoding) \
+                    SELECT s.commit_id, s.tree_id, s.parent_ids, a.identity_id, m.identity_id, s.message, s.encoding \
+                    FROM ({source}) AS s \
+                    JOIN main.Identities AS a ON a.name = s.author_name AND a.email = s.author_email \
+                    JOIN main.Identities AS m ON m.name = s.committer_name AND m.email = s.committer_email;"
+            ),
+            CommitLayout::Inline => format!(
+                "INSERT OR IGNORE INTO main.\"{table}\" (commit_id, tree_id, parent_ids, author_name, author_email, committer_name, committer_email, message, encoding) \
+                    SELECT commit_id, tree_id, parent_ids, author_name, author_email, committer_name, committer_email, message, encoding FROM ({source});"
+            ),
+            CommitLayout::Legacy => format!(
+                "INSERT OR IGNORE INTO main.\"{table}\" (commit_id, tree_id, parent_ids, author_name, author_email, committer_name, committer_email, message) \
+                    SELECT commit_id, tree_id, parent_ids, author_name, author_email, committer_name, committer_email, message FROM ({source});"
+            ),
         }
+    }
+}
 
-        Ok(Some(self.entries.remove(idx)))
+/// The layout of the Commits table of the schema (`main`, or the name of an attached database)
+pub fn commit_layout(conn: &Connection, schema: &str) -> crate::Result<CommitLayout> {
+    let (identities, encoding): (bool, bool) = conn
+        .prepare_cached(READ_COMMIT_LAYOUT)?
+        .query_row([schema], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    Ok(match (identities, encoding) {
+        (true, _) => CommitLayout::Identities,
+        (false, true) => CommitLayout::Inline,
+        (false, false) => CommitLayout::Legacy,
+    })
+}
+
+/// Move the authors and committers stored inline in the Commits table of a repository created
+/// by an older version into the Identities table. Returns the number of identities, or None if
+/// the commits already reference identities.
+pub fn migrate_commit_identities(conn: &Connection) -> crate::Result<Option<usize>> {
+    let layout = commit_layout(conn, "main")?;
+    if layout == CommitLayout::Identities {
+        return Ok(None);
+    }
+
+    // Build the new table next to the old one, then swap them
+    let has_created_at: bool = conn.query_row(
+        "SELECT count(*) > 0 FROM pragma_table_info('Commits') WHERE name = 'created_at'",
+        (),
+        |row| row.get(0),
+    )?;
+    conn.execute(CREATE_IDENTITIES_TABLE, ())?;
+    conn.execute(
+        &CREATE_COMMIT_TABLE.replacen("Commits", "IdentityCommits", 1),
+        (),
+    )?;
+    conn.execute_batch(
+        &CommitLayout::Identities.copy_commits(&layout.select_commits("main"), "IdentityCommits"),
+    )?;
+    if has_created_at {
+        conn.execute(
+            "UPDATE IdentityCommits SET created_at = (SELECT created_at FROM Commits WHERE Commits.commit_id = IdentityCommits.commit_id)",
+            (),
+        )?;
     }
+    conn.execute_batch(
+        "DROP TABLE Commits;
+        ALTER TABLE IdentityCommits RENAME TO Commits;",
+    )?;
+    conn.execute(CREATE_COMMIT_AUTHOR_INDEX, ())?;
+    track_table(conn, "Identities")?;
+    track_table(conn, "Commits")?;
+
+    Ok(Some(
+        conn.query_row("SELECT count(*) FROM Identities", (), |row| {
+            row.get::<_, i64>(0)
+        })? as usize,
+    ))
+}
+
+/// Whether the index can record intent-to-add entries
+fn has_intent_to_add(conn: &Connection) -> crate::Result<bool> {
+    Ok(conn
+        .prepare_cached(HAS_INTENT_TO_ADD)?
+        .query_row((), |row| row.get(0))?)
+}
+
+/// Whether the index is stored in the format of older versions, as a single JSON row
+fn is_legacy_index(conn: &Connection) -> crate::Result<bool> {
+    Ok(conn
+        .prepare_cached(IS_LEGACY_INDEX)?
+        .query_row((), |row| row.get(0))?)
 }
 
 #[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
@@ -355,13 +784,57 @@ impl Head {
         serde_json::from_str(&head).map_err(anyhow::Error::from)
     }
 
+    /// Persist HEAD, which must be detached or on a branch that exists or could be created
     pub fn persist(&self, conn: &Connection) -> crate::Result<()> {
+        if let Head::Branch(name) = self {
+            if !name.starts_with(BRANCH_PREFIX) {
+                return Err(anyhow!(
+                    "fatal: HEAD cannot point to '{}', not a branch",
+                    name
+                ));
+            }
+            Ref::check_name_available(conn, name)?;
+        }
         // Wipe out existing head
         conn.execute("DELETE FROM Head;", ())?;
         let s = serde_json::to_string(self)?;
         conn.execute(INSERT_HEAD, params![s])?;
         Ok(())
     }
+
+    /// Resolve the commit HEAD points to. Returns None if HEAD is on a branch that
+    /// has no commit yet.
+    pub fn resolve(&self, conn: &Connection) -> crate::Result<Option<Sha1Id>> {
+        match self {
+            Head::Branch(branch) => {
+                Ok(Ref::read_from_conn_with_name(conn, branch)?.map(|r| r.commit_id))
+            }
+            Head::Commit(id) => Ok(Some(*id)),
+        }
+    }
+
+    /// Resolve HEAD, telling a branch without commits from one with commits
+    pub fn state(self, conn: &Connection) -> crate::Result<HeadState<Sha1Id>> {
+        let commit_id = self.resolve(conn)?;
+        Ok(match (self, commit_id) {
+            (Head::Branch(name), Some(commit_id)) => HeadState::Branch(name, commit_id),
+            (Head::Branch(name), None) => HeadState::Unborn(name),
+            (Head::Commit(commit_id), _) => HeadState::Detached(commit_id),
+        })
+    }
+
+    /// Move HEAD to the given commit: if HEAD is on a branch the branch ref is updated,
+    /// otherwise the detached HEAD itself is replaced.
+    pub fn update(&self, conn: &Connection, commit_id: Sha1Id) -> crate::Result<()> {
+        match self {
+            Head::Branch(name) => Ref {
+                name: name.clone(),
+                commit_id,
+            }
+            .persist_or_update(conn),
+            Head::Commit(_) => Head::Commit(commit_id).persist(conn),
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -391,12 +864,260 @@ impl Ref {
         .map_err(anyhow::Error::from)
     }
 
+    /// Read all references, sorted by name
+    pub fn read_all(conn: &Connection) -> crate::Result<Vec<Ref>> {
+        let mut stmt = conn.prepare(READ_ALL_REFS)?;
+        let refs = stmt
+            .query_map((), |row| {
+                Ok(Ref {
+                    name: row.get(0)?,
+                    commit_id: row.get(1)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(refs)
+    }
+
     pub fn persist_or_update(&self, conn: &Connection) -> crate::Result<()> {
+        Ref::check_name_available(conn, &self.name)?;
         conn.execute(INSERT_OR_REPLACE_REF, params![self.name, self.commit_id])?;
         Ok(())
     }
+
+    /// Check that a reference could be written with the given name: the name must be valid, and
+    /// no other reference may be one of its directories or have it as a directory, as they could
+    /// not both be stored as loose refs
+    pub fn check_name_available(conn: &Connection, name: &str) -> crate::Result<()> {
+        check_ref_format(name, false)?;
+        let conflicting = conn
+            .query_row(READ_CONFLICTING_REF, [name], |row| row.get::<_, String>(0))
+            .optional()?;
+        match conflicting {
+            Some(existing) => Err(anyhow!(
+                "fatal: cannot update ref '{}': '{}' exists; cannot create '{}'",
+                name,
+                existing,
+                name
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+/// [`MergeHead`] records the commit being merged while a conflicted merge is in progress,
+/// so that the next commit concludes the merge with it as the second parent.
+#[derive(Debug, PartialEq, Eq)]
+pub struct MergeHead {
+    pub commit_id: Sha1Id,
+    /// Message prepared for the merge commit
+    pub message: String,
+}
+
+impl MergeHead {
+    pub fn read_from_conn(conn: &Connection) -> crate::Result<Option<MergeHead>> {
+        conn.query_row_and_then(READ_MERGE_HEAD, (), |row| {
+            Ok(MergeHead {
+                commit_id: row.get(0)?,
+                message: row.get(1)?,
+            })
+        })
+        .optional()
+        .map_err(anyhow::Error::from)
+    }
+
+    pub fn persist(&self, conn: &Connection) -> crate::Result<()> {
+        MergeHead::clear(conn)?;
+        conn.execute(INSERT_MERGE_HEAD, params![self.commit_id, self.message])?;
+        Ok(())
+    }
+
+    /// Remove the merge state, if any
+    pub fn clear(conn: &Connection) -> crate::Result<()> {
+        conn.execute("DELETE FROM MergeHead;", ())?;
+        Ok(())
+    }
+}
+
+/// [`FetchHead`] is a tip fetched by the last fetch
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FetchHead {
+    pub commit_id: Sha1Id,
+    /// Where the tip comes from, e.g. `branch 'main' of <url>`
+    pub description: String,
+}
+
+impl FetchHead {
+    /// Read the tips of the last fetch, in the order they were fetched
+    pub fn read_all(conn: &Connection) -> crate::Result<Vec<FetchHead>> {
+        let mut stmt = conn.prepare(READ_FETCH_HEAD)?;
+        let entries = stmt
+            .query_map((), |row| {
+                Ok(FetchHead {
+                    commit_id: row.get(0)?,
+                    description: row.get(1)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(entries)
+    }
+
+    /// Replace the tips of the previous fetch
+    pub fn replace_all(conn: &Connection, entries: &[FetchHead]) -> crate::Result<()> {
+        conn.execute("DELETE FROM FetchHead;", ())?;
+        for entry in entries {
+            conn.execute(
+                INSERT_FETCH_HEAD,
+                params![entry.commit_id, entry.description],
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// [`RebaseAction`] is the command of one line in a rebase todo list
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RebaseAction {
+    /// Use the commit
+    Pick,
+    /// Use the commit, but edit its message
+    Reword,
+    /// Use the commit, but stop for amending
+    Edit,
+    /// Meld the commit into the previous one, combining their messages
+    Squash,
+    /// Meld the commit into the previous one, keeping only the previous message
+    Fixup,
+    /// Remove the commit
+    Drop,
+}
+
+/// [`RebaseStep`] is one line of a rebase todo list
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RebaseStep {
+    pub action: RebaseAction,
+    pub commit_id: Sha1Id,
+}
+
+/// [`RebaseState`] records a rebase in progress so that it can be resumed after it stops for
+/// a conflict or an `edit` step, or aborted.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RebaseState {
+    /// Full name of the branch being rebased, None if HEAD was detached when the rebase started
+    pub head_name: Option<String>,
+    /// The commit HEAD pointed to when the rebase started, restored on abort
+    pub orig_head: Sha1Id,
+    /// The commit the steps are replayed onto
+    pub onto: Sha1Id,
+    /// Steps not carried out yet
+    pub todo: Vec<RebaseStep>,
+    /// The step that stopped the rebase because it could not be applied cleanly
+    pub conflicted: Option<RebaseStep>,
+    /// Stash commit holding the local changes put aside by `--autostash`, applied back when
+    /// the rebase finishes or is aborted
+    #[serde(default)]
+    pub autostash: Option<Sha1Id>,
+}
+
+impl RebaseState {
+    pub fn read_from_conn(conn: &Connection) -> crate::Result<Option<RebaseState>> {
+        conn.query_row(READ_REBASE_STATE, (), |row| row.get::<_, String>(0))
+            .optional()?
+            .map(|s| serde_json::from_str(&s))
+            .transpose()
+            .map_err(anyhow::Error::from)
+    }
+
+    pub fn persist(&self, conn: &Connection) -> crate::Result<()> {
+        RebaseState::clear(conn)?;
+        let s = serde_json::to_string(self)?;
+        conn.execute(INSERT_REBASE_STATE, params![s])?;
+        Ok(())
+    }
+
+    /// Remove the rebase state, if any
+    pub fn clear(conn: &Connection) -> crate::Result<()> {
+        conn.execute("DELETE FROM RebaseState;", ())?;
+        Ok(())
+    }
+}
+
+/// [`CherryPickState`] records a sequence of commits being cherry-picked so that it can be
+/// resumed after a commit does not apply cleanly, or aborted.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CherryPickState {
+    /// The commit HEAD pointed to when the cherry-pick started, restored on abort
+    pub orig_head: Sha1Id,
+    /// Commits not picked yet
+    pub todo: Vec<Sha1Id>,
+    /// The commit that stopped the cherry-pick because it could not be applied cleanly
+    pub conflicted: Option<Sha1Id>,
+}
+
+impl CherryPickState {
+    pub fn read_from_conn(conn: &Connection) -> crate::Result<Option<CherryPickState>> {
+        conn.query_row(READ_CHERRY_PICK_STATE, (), |row| row.get::<_, String>(0))
+            .optional()?
+            .map(|s| serde_json::from_str(&s))
+            .transpose()
+            .map_err(anyhow::Error::from)
+    }
+
+    pub fn persist(&self, conn: &Connection) -> crate::Result<()> {
+        CherryPickState::clear(conn)?;
+        let s = serde_json::to_string(self)?;
+        conn.execute(INSERT_CHERRY_PICK_STATE, params![s])?;
+        Ok(())
+    }
+
+    /// Remove the cherry-pick state, if any
+    pub fn clear(conn: &Connection) -> crate::Result<()> {
+        conn.execute("DELETE FROM CherryPickState;", ())?;
+        Ok(())
+    }
+}
+
+/// [`StashEntry`] is one entry of the stash stack
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StashEntry {
+    /// The stash commit, whose tree records the working tree and whose parents are the
+    /// commit HEAD pointed to and a commit recording the index
+    pub commit_id: Sha1Id,
+    pub message: String,
+}
+
+impl StashEntry {
+    /// Push the entry on top of the stash stack
+    pub fn push(&self, conn: &Connection) -> crate::Result<()> {
+        conn.execute(INSERT_STASH, params![self.commit_id, self.message])?;
+        Ok(())
+    }
+
+    /// Read the stash stack, the most recent entry (`stash@{0}`) first
+    pub fn read_all(conn: &Connection) -> crate::Result<Vec<StashEntry>> {
+        let mut stmt = conn.prepare(READ_STASH)?;
+        let entries = stmt
+            .query_map((), |row| {
+                Ok(StashEntry {
+                    commit_id: row.get(0)?,
+                    message: row.get(1)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(entries)
+    }
+
+    /// Remove the entry at `position` in the stack (`stash@{<position>}`), moving the older
+    /// entries up. Returns whether there was such an entry.
+    pub fn drop(conn: &Connection, position: usize) -> crate::Result<bool> {
+        let removed = conn.execute(DELETE_STASH_AT, params![position])?;
+        Ok(removed > 0)
+    }
 }
 
+/// [`AnnotatedTag`] is the annotation of a tag: who tagged the commit, when, and why. It is
+/// the [`Tag`](crate::repo::db::Tag) of the database layer, which owns its table.
+pub type AnnotatedTag = crate::repo::db::Tag<crate::repo::db::Sha1Id>;
+
 #[derive(Debug, PartialEq, Eq)]
 pub struct Commit<ID> {
     pub commit_id: ID,
@@ -407,6 +1128,9 @@ pub struct Commit<ID> {
     pub committer_name: String,
     pub committer_email: String,
     pub message: String,
+    /// Encoding of the message when it was written, None for UTF-8. The message itself is
+    /// always kept as UTF-8 and converted back to this encoding when needed.
+    pub encoding: Option<String>,
 }
 
 impl Commit<NoId> {
@@ -428,6 +1152,7 @@ impl Commit<NoId> {
             committer_name,
             committer_email,
             message,
+            encoding: None,
         }
     }
 
@@ -441,41 +1166,54 @@ impl Commit<NoId> {
             committer_name: self.committer_name,
             committer_email: self.committer_email,
             message: self.message,
+            encoding: self.encoding,
         }
     }
 }
 
 impl Commit<Sha1Id> {
     pub fn read_from_conn_with_id(conn: &Connection, id: Sha1Id) -> crate::Result<Commit<Sha1Id>> {
-        conn.query_row_and_then(READ_COMMIT_FOR_ID, [id], |row| {
-            let commit_id = row.get(0)?;
-            let tree_id = row.get(1)?;
-
-            let parent_ids: Vec<Sha1Id> = row
-                .get::<_, Vec<u8>>(2)?
-                .chunks(20)
-                .skip_while(|s| s.is_empty())
-                .map(|s| {
-                    let inner: [u8; 20] = s.try_into().unwrap();
-                    Sha1Id(inner)
-                })
-                .collect();
-
-            let author_name = row.get(3)?;
-            let author_email = row.get(4)?;
-            let committer_name = row.get(5)?;
-            let committer_email = row.get(6)?;
-            let message = row.get(7)?;
-            Ok(Commit {
-                commit_id,
-                tree_id,
-                parent_ids,
-                author_name,
-                author_email,
-                committer_name,
-                committer_email,
-                message,
+        let query = format!(
+            "{} WHERE c.commit_id = ?1",
+            commit_layout(conn, "main")?.select_commits("main")
+        );
+        let commit = conn
+            .prepare_cached(&query)?
+            .query_row([id], Commit::from_row)?;
+        Ok(commit)
+    }
+
+    /// Read a commit from a row of the query [`CommitLayout::select_commits`] builds
+    pub fn from_row(row: &Row) -> rusqlite::Result<Commit<Sha1Id>> {
+        let commit_id = row.get(0)?;
+        let tree_id = row.get(1)?;
+
+        let parent_ids = row.get::<_, Vec<u8>>(2)?;
+        if parent_ids.len() % 20 != 0 {
+            return Err(rusqlite::Error::FromSqlConversionFailure(
+                2,
+                Type::Blob,
+                format!("{} bytes of parent ids", parent_ids.len()).into(),
+            ));
+        }
+        let parent_ids: Vec<Sha1Id> = parent_ids
+            .chunks(20)
+            .map(|s| {
+                let inner: [u8; 20] = s.try_into().unwrap();
+                Sha1Id(inner)
             })
+            .collect();
+
+        Ok(Commit {
+            commit_id,
+            tree_id,
+            parent_ids,
+            author_name: row.get(3)?,
+            author_email: row.get(4)?,
+            committer_name: row.get(5)?,
+            committer_email: row.get(6)?,
+            message: row.get(7)?,
+            encoding: row.get(8)?,
         })
     }
 
@@ -485,9 +1223,18 @@ impl Commit<Sha1Id> {
             parent_ids.extend(parent_id.0.iter());
         }
 
-        conn.execute(
-            INSERT_COMMIT,
-            params![
+        let layout = commit_layout(conn, "main")?;
+        if layout == CommitLayout::Identities {
+            let mut insert_identity = conn.prepare_cached(INSERT_IDENTITY)?;
+            insert_identity.execute((&self.author_name, &self.author_email))?;
+            insert_identity.execute((&self.committer_name, &self.committer_email))?;
+        }
+        if layout != CommitLayout::Legacy {
+            let insert = match layout {
+                CommitLayout::Identities => INSERT_COMMIT,
+                _ => INSERT_INLINE_COMMIT,
+            };
+            conn.prepare_cached(insert)?.execute(params![
                 self.commit_id,
                 self.tree_id,
                 parent_ids,
@@ -495,9 +1242,30 @@ impl Commit<Sha1Id> {
                 self.author_email,
                 self.committer_name,
                 self.committer_email,
-                self.message
-            ],
-        )?;
+                self.message,
+                self.encoding
+            ])?;
+        } else if let Some(encoding) = &self.encoding {
+            return Err(anyhow!(
+                "fatal: cannot record the {} encoding of commit {} in a repository created by an older version",
+                encoding,
+                self.commit_id
+            ));
+        } else {
+            conn.execute(
+                INSERT_LEGACY_COMMIT,
+                params![
+                    self.commit_id,
+                    self.tree_id,
+                    parent_ids,
+                    self.author_name,
+                    self.author_email,
+                    self.committer_name,
+                    self.committer_email,
+                    self.message
+                ],
+            )?;
+        }
 
         Ok(())
     }
@@ -557,9 +1325,11 @@ impl Tree<Sha1Id> {
 
             let mut entries = vec![];
 
-            for line in tree_data.split('\n') {
+            // An empty tree (e.g., the result of merging away every file) has no lines at all
+            for line in tree_data.split('\n').filter(|line| !line.is_empty()) {
                 // line format: <file_mode> <file_type[blob|tree]> <object_id[tree_id|blob_id]> <file_name>
-                let mut split = line.split(' ');
+                // file names may contain spaces, so only split off the first three fields
+                let mut split = line.splitn(4, ' ');
                 let mode = split.next().unwrap().to_string();
                 let type_ = match split.next().unwrap() {
                     "blob" => TreeEntryType::Blob,
@@ -589,7 +1359,7 @@ impl Tree<Sha1Id> {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum TreeEntryType {
     Blob,
     Tree,
@@ -610,7 +1380,7 @@ impl TreeEntryType {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct TreeEntry {
     pub type_: TreeEntryType,
     pub id: Sha1Id,
@@ -638,6 +1408,24 @@ impl Blob<Sha1Id> {
         conn.execute(INSERT_BLOB, params![&self.blob_id, &self.data])?;
         Ok(())
     }
+
+    /// Read the sizes of the given blobs without reading their content. Blobs missing from the
+    /// database are left out of the result.
+    pub fn read_sizes(conn: &Connection, ids: &[Sha1Id]) -> crate::Result<HashMap<Sha1Id, u64>> {
+        let mut sizes = HashMap::with_capacity(ids.len());
+        for batch in ids.chunks(BLOB_SIZES_BATCH_SIZE) {
+            let placeholders = vec!["?"; batch.len()].join(", ");
+            let mut stmt = conn.prepare(&format!("{} ({})", READ_BLOB_SIZES, placeholders))?;
+            let rows = stmt.query_map(rusqlite::params_from_iter(batch), |row| {
+                Ok((row.get(0)?, row.get::<_, i64>(1)? as u64))
+            })?;
+            for row in rows {
+                let (id, size) = row?;
+                sizes.insert(id, size);
+            }
+        }
+        Ok(sizes)
+    }
 }
 
 impl Blob<NoId> {
@@ -660,8 +1448,13 @@ impl Blob<NoId> {
 #[cfg(test)]
 mod tests {
     use rusqlite::params;
+    use sha1::Digest;
 
     use super::*;
+    use crate::git::manifest;
+
+    /// Commits table of the repositories created before identities were deduplicated
+    const INLINE_COMMIT_TABLE: &str = "CREATE TABLE Commits (commit_id BLOB PRIMARY KEY, tree_id TEXT NOT NULL, parent_ids BLOB NOT NULL, author_name TEXT NOT NULL, author_email TEXT NOT NULL, committer_name TEXT NOT NULL, committer_email TEXT NOT NULL, message TEXT NOT NULL, encoding TEXT, created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now')));";
 
     #[test]
     fn test_sqlite_version() {
@@ -669,6 +1462,25 @@ mod tests {
         assert!(rusqlite::version_number() >= 304500)
     }
 
+    fn index_entry(name: &str, stage: u8) -> IndexEntry {
+        IndexEntry {
+            ctime: 1,
+            mtime: 2,
+            dev: u64::MAX,
+            ino: 3,
+            mode_type: ModeType::Regular,
+            mode_perms: 100644,
+            uid: 4,
+            gid: 5,
+            fsize: 6,
+            sha: Sha1Id([stage; 20]),
+            flag_assume_valid: false,
+            flag_stage: stage,
+            flag_intent_to_add: false,
+            name: name.to_string(),
+        }
+    }
+
     #[test]
     fn test_index() {
         let conn = Connection::open_in_memory().unwrap();
@@ -677,9 +1489,83 @@ mod tests {
         let dummy_index = Index::read_from_conn(&conn).unwrap();
         assert_eq!(Index::default(), dummy_index);
 
-        dummy_index.persist(&conn).unwrap();
-        dummy_index.persist(&conn).unwrap();
+        let mut index = Index::default();
+        index.insert_conflict("b", vec![index_entry("b", 2), index_entry("b", 3)]);
+        index.insert(index_entry("a", 0));
+        index.persist(&conn).unwrap();
+        index.persist(&conn).unwrap();
+
+        let num_index = conn
+            .query_row("SELECT COUNT(*) FROM Index_;", (), |row| {
+                row.get::<_, i32>(0)
+            })
+            .unwrap();
+        assert_eq!(3, num_index);
+        assert_eq!(index, Index::read_from_conn(&conn).unwrap());
+
+        // Entries are staged and unstaged by name without going through the whole index
+        let intent_to_add = IndexEntry {
+            flag_intent_to_add: true,
+            ..index_entry("c", 0)
+        };
+        Index::stage_entries(&conn, vec![intent_to_add.clone()]).unwrap();
+        assert_eq!(
+            vec![intent_to_add],
+            Index::read_entries_with_name(&conn, "c").unwrap()
+        );
+        Index::unstage_name(&conn, "c").unwrap();
+        Index::stage_entries(&conn, vec![index_entry("b", 0)]).unwrap();
+        assert_eq!(
+            vec![index_entry("b", 0)],
+            Index::read_entries_with_name(&conn, "b").unwrap()
+        );
+        Index::unstage_name(&conn, "a").unwrap();
+        assert_eq!(
+            vec![index_entry("b", 0)],
+            Index::read_from_conn(&conn).unwrap().entries
+        );
+    }
+
+    #[test]
+    fn test_index_without_intent_to_add() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute(
+            &CREATE_INDEX_TABLE.replace(", intent_to_add INTEGER NOT NULL DEFAULT 0", ""),
+            (),
+        )
+        .unwrap();
+        Index::stage_entries(&conn, vec![index_entry("a", 0)]).unwrap();
+        assert_eq!(
+            vec![index_entry("a", 0)],
+            Index::read_from_conn(&conn).unwrap().entries
+        );
+        let intent_to_add = IndexEntry {
+            flag_intent_to_add: true,
+            ..index_entry("b", 0)
+        };
+        assert!(Index::stage_entries(&conn, vec![intent_to_add]).is_err());
+    }
+
+    #[test]
+    fn test_legacy_index() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute("CREATE TABLE Index_ (index_ JSON);", ())
+            .unwrap();
+        let mut index = Index::default();
+        index.insert(index_entry("a", 0));
+        conn.execute(
+            INSERT_LEGACY_INDEX,
+            [serde_json::to_string(&index).unwrap()],
+        )
+        .unwrap();
 
+        assert_eq!(index, Index::read_from_conn(&conn).unwrap());
+        Index::stage_entries(&conn, vec![index_entry("b", 0)]).unwrap();
+        Index::unstage_name(&conn, "a").unwrap();
+        assert_eq!(
+            vec![index_entry("b", 0)],
+            Index::read_from_conn(&conn).unwrap().entries
+        );
         let num_index = conn
             .query_row("SELECT COUNT(*) FROM Index_;", (), |row| {
                 row.get::<_, i32>(0)
@@ -692,8 +1578,9 @@ mod tests {
     fn test_head() {
         let conn = Connection::open_in_memory().unwrap();
         conn.execute(CREATE_HEAD_TABLE, ()).unwrap();
+        conn.execute(CREATE_REF_TABLE, ()).unwrap();
 
-        let head = Head::Branch("ref/head/main".to_string());
+        let head = Head::Branch("refs/head/main".to_string());
         head.persist(&conn).unwrap();
 
         let another_head =
@@ -710,6 +1597,33 @@ mod tests {
         assert_eq!(another_head, current_head);
     }
 
+    #[test]
+    fn test_stash() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute(CREATE_STASH_TABLE, ()).unwrap();
+
+        let entries: Vec<StashEntry> = (1..=3u8)
+            .map(|byte| StashEntry {
+                commit_id: Sha1Id::try_from(vec![byte; 20]).unwrap(),
+                message: format!("WIP {}", byte),
+            })
+            .collect();
+        for entry in &entries {
+            entry.push(&conn).unwrap();
+        }
+        assert_eq!(
+            vec![entries[2].clone(), entries[1].clone(), entries[0].clone()],
+            StashEntry::read_all(&conn).unwrap()
+        );
+
+        assert!(StashEntry::drop(&conn, 1).unwrap());
+        assert!(!StashEntry::drop(&conn, 2).unwrap());
+        assert_eq!(
+            vec![entries[2].clone(), entries[0].clone()],
+            StashEntry::read_all(&conn).unwrap()
+        );
+    }
+
     #[test]
     fn test_read_ref_none() {
         let conn = Connection::open_in_memory().unwrap();
@@ -718,6 +1632,39 @@ mod tests {
         assert_eq!(None, r)
     }
 
+    #[test]
+    fn test_ref_invariants() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute(CREATE_HEAD_TABLE, ()).unwrap();
+        conn.execute(CREATE_REF_TABLE, ()).unwrap();
+        let commit_id = Sha1Id::try_from("a1b2c3d4e5f6a7b8c9d0e1f2a3b4c5d6e7f8a9b0").unwrap();
+        let write = |name: &str| {
+            Ref {
+                name: name.to_string(),
+                commit_id,
+            }
+            .persist_or_update(&conn)
+        };
+
+        write("refs/head/topic").unwrap();
+        write("refs/head/topic").unwrap();
+        write("refs/head/topical").unwrap();
+        assert!(write("refs/head/topic/x").is_err());
+        assert!(write("refs/head").is_err());
+        assert!(write("refs/head/a..b").is_err());
+
+        // HEAD may only be on a branch that exists or could be created
+        assert!(Head::Branch("refs/head/new".to_string())
+            .persist(&conn)
+            .is_ok());
+        assert!(Head::Branch("refs/head/topic/x".to_string())
+            .persist(&conn)
+            .is_err());
+        assert!(Head::Branch("refs/tags/v1".to_string())
+            .persist(&conn)
+            .is_err());
+    }
+
     #[test]
     fn test_read_blob() {
         let conn = Connection::open_in_memory().unwrap();
@@ -737,6 +1684,10 @@ mod tests {
         let blob = Blob::read_from_conn_with_id(&conn, blob_id).unwrap();
 
         assert_eq!(&data[..], &blob.data[..]);
+
+        let missing_id = Sha1Id([0; 20]);
+        let sizes = Blob::read_sizes(&conn, &[blob_id, missing_id]).unwrap();
+        assert_eq!(HashMap::from([(blob_id, 5)]), sizes);
     }
 
     #[test]
@@ -788,7 +1739,7 @@ mod tests {
     #[test]
     fn test_read_commit() {
         let conn = Connection::open_in_memory().unwrap();
-        conn.execute(CREATE_COMMIT_TABLE, ()).unwrap();
+        conn.execute(INLINE_COMMIT_TABLE, ()).unwrap();
 
         let commit_id = "a94a8fe5ccb19ba61c4c0873d391e987982fbbd3"
             .try_into()
@@ -817,19 +1768,115 @@ mod tests {
         let commit = Commit::read_from_conn_with_id(&conn, commit_id).unwrap();
 
         let expected_commit = Commit {
-            commit_id: commit_id,
-            tree_id: tree_id,
+            commit_id,
+            tree_id,
             parent_ids,
             author_name: author_name.to_string(),
             author_email: author_email.to_string(),
             committer_name: committer_name.to_string(),
             committer_email: committer_email.to_string(),
             message: message.to_string(),
+            encoding: None,
         };
 
         assert_eq!(expected_commit, commit);
     }
 
+    #[test]
+    fn test_commit_encoding() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute(CREATE_IDENTITIES_TABLE, ()).unwrap();
+        conn.execute(CREATE_COMMIT_TABLE, ()).unwrap();
+
+        let tree_id = Sha1Id([1; 20]);
+        let commit = Commit::new(
+            tree_id,
+            vec![],
+            "Jérôme".to_string(),
+            "j@example.com".to_string(),
+            "Jérôme".to_string(),
+            "j@example.com".to_string(),
+            "Ça marche".to_string(),
+        );
+        let utf8_id = commit.hash(sha1::Sha1::new());
+        let commit = Commit {
+            encoding: Some("ISO-8859-1".to_string()),
+            ..commit
+        };
+        let id = commit.hash(sha1::Sha1::new());
+        // The encoding is part of the commit
+        assert_ne!(utf8_id, id);
+
+        let commit = commit.with_id(id);
+        commit.persist(&conn).unwrap();
+        assert_eq!(commit, Commit::read_from_conn_with_id(&conn, id).unwrap());
+
+        // Repositories of older versions have no encoding column
+        conn.execute_batch("DROP TABLE Commits; DROP TABLE Identities;")
+            .unwrap();
+        conn.execute(INLINE_COMMIT_TABLE, ()).unwrap();
+        commit.persist(&conn).unwrap();
+        conn.execute("ALTER TABLE Commits DROP COLUMN encoding", ())
+            .unwrap();
+        assert_eq!(CommitLayout::Legacy, commit_layout(&conn, "main").unwrap());
+        assert_eq!(
+            None,
+            Commit::read_from_conn_with_id(&conn, id).unwrap().encoding
+        );
+        assert!(commit.persist(&conn).is_err());
+    }
+
+    #[test]
+    fn test_migrate_commit_identities() {
+        let conn = Connection::open_in_memory().unwrap();
+        manifest::prepare_connection(&conn).unwrap();
+        conn.execute(INLINE_COMMIT_TABLE, ()).unwrap();
+        manifest::create_manifest(&conn).unwrap();
+        assert_eq!(CommitLayout::Inline, commit_layout(&conn, "main").unwrap());
+
+        let make_commit = |author: &str, committer: &str, message: &str| {
+            let commit = Commit::new(
+                Sha1Id([1; 20]),
+                vec![],
+                author.to_string(),
+                format!("{}@example.com", author),
+                committer.to_string(),
+                format!("{}@example.com", committer),
+                message.to_string(),
+            );
+            let id = commit.hash(sha1::Sha1::new());
+            let commit = commit.with_id(id);
+            commit.persist(&conn).unwrap();
+            commit
+        };
+        let commits = [
+            make_commit("alice", "alice", "first"),
+            make_commit("bob", "alice", "second"),
+            make_commit("alice", "carol", "third"),
+        ];
+
+        assert_eq!(Some(3), migrate_commit_identities(&conn).unwrap());
+        assert_eq!(None, migrate_commit_identities(&conn).unwrap());
+        assert_eq!(
+            CommitLayout::Identities,
+            commit_layout(&conn, "main").unwrap()
+        );
+        for commit in &commits {
+            assert_eq!(
+                commit,
+                &Commit::read_from_conn_with_id(&conn, commit.commit_id).unwrap()
+            );
+        }
+        // The manifest follows the rebuilt table, and further writes
+        assert!(manifest::verify_database(&conn).unwrap().is_empty());
+        make_commit("dave", "alice", "fourth");
+        assert!(manifest::verify_database(&conn).unwrap().is_empty());
+        let identities: i64 = conn
+            .query_row("SELECT count(*) FROM Identities", (), |row| row.get(0))
+            .unwrap();
+        assert_eq!(4, identities);
+    }
+
     #[test]
     fn test_hash_blob() {
         let data = b"daslkdjaslkdjaslkjdaslkALJKDSlkjsadclje";
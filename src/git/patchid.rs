@@ -0,0 +1,146 @@
+//! This module computes patch ids, as `git patch-id` does: the hash of a diff with the parts
+//! that change when the same patch is applied elsewhere left out. Commits making the same
+//! change on different bases (e.g. a commit and its cherry-pick) share a patch id, which is
+//! how `cherry`, `range-diff` and `rebase` tell that a change is already applied.
+//!
+//! Only the lines from the first `diff` header of a patch are hashed, without the `index`
+//! lines (which hold blob hashes) and the hunk headers (which hold line numbers). Unless the
+//! id is computed verbatim, whitespace is removed from the hashed lines too, so that
+//! reindenting a line does not change the patch id.
+
+use std::collections::HashSet;
+
+use rusqlite::Connection;
+use sha1::{Digest, Sha1};
+
+use super::{
+    diff::{diff_commit, render, DiffOutput},
+    model::{hash_blob_data, Commit, Sha1Id},
+    revision::commits_between,
+};
+
+/// Patch id of a single patch, None if the text holds no diff
+pub fn patch_id(patch: &str, verbatim: bool) -> Option<Sha1Id> {
+    let mut text = Vec::new();
+    let mut in_diff = false;
+    for line in patch.lines() {
+        if line.starts_with("diff ") {
+            in_diff = true;
+        }
+        if !in_diff || line.starts_with("index ") || line.starts_with("@@") {
+            continue;
+        }
+        if verbatim {
+            text.extend_from_slice(line.as_bytes());
+            text.push(b'\n');
+        } else {
+            text.extend(line.bytes().filter(|b| !b.is_ascii_whitespace()));
+        }
+    }
+    in_diff.then(|| hash_blob_data(Sha1::new(), &text))
+}
+
+/// Patch ids of the patches of a text such as the output of `git log -p` or `git
+/// format-patch`, which start with a `commit <id>` or `From <id>` line. Each id comes with
+/// the commit the patch was taken from, if known.
+pub fn patch_ids(text: &str, verbatim: bool) -> Vec<(Sha1Id, Option<String>)> {
+    let commit_of = |line: &str| {
+        let rest = line
+            .strip_prefix("commit ")
+            .or_else(|| line.strip_prefix("From "))?;
+        let id = rest.split_whitespace().next()?;
+        (id.len() == 40 && id.bytes().all(|b| b.is_ascii_hexdigit())).then(|| id.to_string())
+    };
+
+    // (commit the patch comes from, its text)
+    let mut patches: Vec<(Option<String>, String)> = vec![(None, String::new())];
+    for line in text.split_inclusive('\n') {
+        if let Some(commit) = commit_of(line) {
+            patches.push((Some(commit), String::new()));
+            continue;
+        }
+        if let Some((_, patch)) = patches.last_mut() {
+            patch.push_str(line);
+        }
+    }
+    patches
+        .into_iter()
+        .filter_map(|(commit, patch)| Some((patch_id(&patch, verbatim)?, commit)))
+        .collect()
+}
+
+/// Patch id of the change a commit makes to its first parent, None for a merge or for a
+/// commit that changes nothing
+pub fn commit_patch_id(
+    conn: &Connection,
+    commit_id: Sha1Id,
+    verbatim: bool,
+) -> crate::Result<Option<Sha1Id>> {
+    let commit = Commit::read_from_conn_with_id(conn, commit_id)?;
+    if commit.parent_ids.len() > 1 {
+        return Ok(None);
+    }
+    let changes = diff_commit(conn, &commit)?;
+    if changes.is_empty() {
+        return Ok(None);
+    }
+    Ok(patch_id(
+        &render(&changes, DiffOutput::default())?,
+        verbatim,
+    ))
+}
+
+/// Patch ids of the commits reachable from `include` but not from `exclude`
+pub fn patch_ids_between(
+    conn: &Connection,
+    exclude: Sha1Id,
+    include: Sha1Id,
+) -> crate::Result<HashSet<Sha1Id>> {
+    let mut ids = HashSet::new();
+    for commit_id in commits_between(conn, Some(exclude), include)? {
+        ids.extend(commit_patch_id(conn, commit_id, false)?);
+    }
+    Ok(ids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PATCH: &str = "diff --git a/file b/file
+index 1111111..2222222 100644
+--- a/file
++++ b/file
+@@ -1,2 +1,2 @@
+ context
+-old
++new
+";
+
+    #[test]
+    fn test_patch_id() {
+        let id = patch_id(PATCH, false).unwrap();
+        // Blob hashes, line numbers and whitespace do not matter
+        let moved = PATCH
+            .replace("1111111..2222222", "3333333..4444444")
+            .replace("-1,2 +1,2", "-10,2 +10,2")
+            .replace("+new", "+  new");
+        assert_eq!(Some(id), patch_id(&moved, false));
+        assert_ne!(patch_id(PATCH, true), patch_id(&moved, true));
+        assert_ne!(Some(id), patch_id(&PATCH.replace("+new", "+newer"), false));
+        assert_eq!(None, patch_id("just a message\n", false));
+
+        let log = format!(
+            "commit {}\nAuthor: A\n\n    message\n\n{}commit {}\n\n    empty\n\nFrom {} Mon Sep 17 00:00:00 2001\n{}",
+            "a".repeat(40),
+            PATCH,
+            "b".repeat(40),
+            "c".repeat(40),
+            moved
+        );
+        assert_eq!(
+            vec![(id, Some("a".repeat(40))), (id, Some("c".repeat(40)))],
+            patch_ids(&log, false)
+        );
+    }
+}
@@ -0,0 +1,155 @@
+//! This module checks the names of files before they are written to the working tree or
+//! recorded in the index, so that a tree cannot make gitqlite write outside the repository or
+//! into its database, and so that names Windows cannot represent are refused with a clear error
+//! rather than materialized as something else (e.g. `aux.txt` opening a device, or `file.`
+//! silently becoming `file`).
+
+use std::path::{Path, PathBuf};
+
+use anyhow::anyhow;
+
+use super::constants::GITQLITE_DIRECTORY_PREFIX;
+
+/// Longest path the Windows APIs accept without the extended-length prefix
+pub const MAX_PATH: usize = 260;
+
+/// Names Windows reserves for devices, whatever their extension
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Characters Windows does not allow in file names
+const INVALID_WINDOWS_CHARS: [char; 7] = ['<', '>', ':', '"', '|', '?', '*'];
+
+/// Check that a name (relative to repo root, separated by `/`) is safe to write in the working
+/// tree of this platform
+pub fn verify_path(name: &str) -> crate::Result<()> {
+    verify_path_for(name, cfg!(windows))
+}
+
+fn verify_path_for(name: &str, windows: bool) -> crate::Result<()> {
+    let invalid = |reason: &str| Err(anyhow!("fatal: invalid path '{}': {}", name, reason));
+
+    if name.is_empty() || name.starts_with('/') {
+        return invalid("not relative to the repository root");
+    }
+    for component in name.split('/') {
+        if component.is_empty() || component == "." || component == ".." {
+            return invalid("empty, '.' or '..' path component");
+        }
+        if component.contains('\0') {
+            return invalid("contains a NUL byte");
+        }
+        // The repository directories must not be written through the working tree, even by
+        // names Windows would resolve to them
+        let trimmed = component.trim_end_matches(['.', ' ']);
+        if trimmed.eq_ignore_ascii_case(GITQLITE_DIRECTORY_PREFIX)
+            || trimmed.eq_ignore_ascii_case(".git")
+        {
+            return invalid("names a repository directory");
+        }
+
+        if !windows {
+            continue;
+        }
+        if component.contains('\\') {
+            return invalid("contains a backslash");
+        }
+        if let Some(c) = component
+            .chars()
+            .find(|c| INVALID_WINDOWS_CHARS.contains(c) || c.is_control())
+        {
+            return invalid(&format!("contains '{}'", c.escape_default()));
+        }
+        if component.ends_with(['.', ' ']) {
+            return invalid("path component ending with a dot or a space");
+        }
+        let stem = component.split('.').next().unwrap_or_default().trim_end();
+        if RESERVED_NAMES
+            .iter()
+            .any(|reserved| stem.eq_ignore_ascii_case(reserved))
+        {
+            return invalid(&format!("'{}' is a reserved device name", stem));
+        }
+    }
+    Ok(())
+}
+
+/// Path of a file in the working tree usable with the file system APIs. Paths longer than
+/// [`MAX_PATH`] get the extended-length prefix on Windows.
+pub fn worktree_path(repo_root: impl AsRef<Path>, name: &str) -> PathBuf {
+    let path = repo_root.as_ref().join(name);
+    if cfg!(windows) {
+        extended_length_path(path)
+    } else {
+        path
+    }
+}
+
+fn extended_length_path(path: PathBuf) -> PathBuf {
+    let text = path.to_string_lossy();
+    if text.len() < MAX_PATH || text.starts_with(r"\\?\") || !path.is_absolute() {
+        return path;
+    }
+    // Extended-length paths are passed to the file system as is, so they must use backslashes
+    let text = text.replace('/', "\\");
+    match text.strip_prefix(r"\\") {
+        Some(unc) => PathBuf::from(format!(r"\\?\UNC\{}", unc)),
+        None => PathBuf::from(format!(r"\\?\{}", text)),
+    }
+}
+
+/// Path for display, without the extended-length prefix
+pub fn display_path(path: &Path) -> &Path {
+    dunce::simplified(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify_path() {
+        for name in ["src/main.rs", "a b/c.d", "aux/x", "con.txt", "file."] {
+            assert!(verify_path_for(name, false).is_ok(), "{}", name);
+        }
+        for name in [
+            "",
+            "/etc/passwd",
+            "a//b",
+            "../x",
+            "a/./b",
+            ".git/config",
+            ".gitqlite/db",
+        ] {
+            assert!(verify_path_for(name, false).is_err(), "{}", name);
+        }
+
+        assert!(verify_path_for("src/main.rs", true).is_ok());
+        for name in [
+            "aux/x",
+            "con.txt",
+            "dir/Com1",
+            "file.",
+            "dir /file",
+            "a:b",
+            "a\\b",
+            ".GIT./config",
+        ] {
+            assert!(verify_path_for(name, true).is_err(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_extended_length_path() {
+        let short = PathBuf::from("C:/repo/file");
+        assert_eq!(short, extended_length_path(short.clone()));
+        let long = PathBuf::from(format!("C:/repo/{}", "d/".repeat(150)));
+        if long.is_absolute() {
+            assert!(extended_length_path(long)
+                .to_string_lossy()
+                .starts_with(r"\\?\C:\repo\d\d"));
+        }
+    }
+}
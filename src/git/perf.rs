@@ -0,0 +1,177 @@
+//! This module measures where the time of a command goes, so that a slow command can be
+//! reported along with numbers. It is turned on by `--profile` or by setting
+//! `GITQLITE_TRACE_PERF`, and then reports on stderr once the command is done:
+//!
+//! - the time SQLite spent running statements on the repository database, which SQLite
+//!   measures to the millisecond, so that many quick statements may add up to less than they
+//!   took
+//! - the time spent hashing objects
+//! - the time spent walking directories of the working tree
+//!
+//! Hashing may run on several threads at once, so its time is the sum over all threads and can
+//! exceed the wall time of the command. The time of a walk includes the work done for each
+//! file found, such as hashing it.
+//!
+//! When profiling is off, measuring is a single atomic load.
+
+use std::{
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use rusqlite::Connection;
+
+/// Environment variable turning profiling on, like `GIT_TRACE_PERFORMANCE`
+pub const TRACE_PERF_ENV: &str = "GITQLITE_TRACE_PERF";
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// [`Category`] is a kind of work whose time is measured
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Category {
+    DbQuery,
+    Hashing,
+    FsWalk,
+}
+
+impl Category {
+    const ALL: [Category; 3] = [Category::DbQuery, Category::Hashing, Category::FsWalk];
+
+    fn label(self) -> &'static str {
+        match self {
+            Category::DbQuery => "db queries",
+            Category::Hashing => "hashing",
+            Category::FsWalk => "fs walks",
+        }
+    }
+
+    fn unit(self) -> &'static str {
+        match self {
+            Category::DbQuery => "statements",
+            Category::Hashing => "objects",
+            Category::FsWalk => "walks",
+        }
+    }
+}
+
+/// Total time and number of measurements of a category
+struct Counter {
+    nanos: AtomicU64,
+    count: AtomicU64,
+}
+
+static COUNTERS: [Counter; 3] = [const {
+    Counter {
+        nanos: AtomicU64::new(0),
+        count: AtomicU64::new(0),
+    }
+}; 3];
+
+/// Turn profiling on for the rest of the process
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Whether `GITQLITE_TRACE_PERF` asks for profiling: set to anything but empty, 0 or false
+pub fn requested_by_env() -> bool {
+    std::env::var(TRACE_PERF_ENV)
+        .is_ok_and(|value| !matches!(value.to_ascii_lowercase().as_str(), "" | "0" | "false"))
+}
+
+/// Add a measurement to a category
+pub fn record(category: Category, elapsed: Duration) {
+    let counter = &COUNTERS[category as usize];
+    counter
+        .nanos
+        .fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
+    counter.count.fetch_add(1, Ordering::Relaxed);
+}
+
+/// [`Span`] measures the time until it is dropped, when profiling is on
+pub struct Span {
+    category: Category,
+    start: Option<Instant>,
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        if let Some(start) = self.start {
+            record(self.category, start.elapsed());
+        }
+    }
+}
+
+/// Start measuring a piece of work, which ends when the returned [`Span`] is dropped
+pub fn span(category: Category) -> Span {
+    Span {
+        category,
+        start: is_enabled().then(Instant::now),
+    }
+}
+
+/// Have SQLite report the time of every statement run on the connection, when profiling is on
+pub fn profile_connection(conn: &mut Connection) {
+    if is_enabled() {
+        conn.profile(Some(record_statement));
+    }
+}
+
+fn record_statement(_sql: &str, elapsed: Duration) {
+    record(Category::DbQuery, elapsed);
+}
+
+/// The report of a command which took `total`, from the measurements made so far
+pub fn report(command: &str, total: Duration) -> String {
+    let totals: Vec<(Category, Duration, u64)> = Category::ALL
+        .iter()
+        .map(|&category| {
+            let counter = &COUNTERS[category as usize];
+            (
+                category,
+                Duration::from_nanos(counter.nanos.load(Ordering::Relaxed)),
+                counter.count.load(Ordering::Relaxed),
+            )
+        })
+        .collect();
+    format_report(command, total, &totals)
+}
+
+fn format_report(command: &str, total: Duration, totals: &[(Category, Duration, u64)]) -> String {
+    let millis = |duration: Duration| duration.as_secs_f64() * 1000.0;
+    let mut report = format!("perf: {} took {:.3} ms\n", command, millis(total));
+    for (category, elapsed, count) in totals {
+        report.push_str(&format!(
+            "perf:   {:<10} {:>10.3} ms ({} {})\n",
+            category.label(),
+            millis(*elapsed),
+            count,
+            category.unit()
+        ));
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_report() {
+        let totals = [
+            (Category::DbQuery, Duration::from_micros(2500), 42),
+            (Category::Hashing, Duration::from_millis(12), 7),
+            (Category::FsWalk, Duration::ZERO, 0),
+        ];
+        assert_eq!(
+            "perf: status took 20.000 ms\n\
+             perf:   db queries      2.500 ms (42 statements)\n\
+             perf:   hashing        12.000 ms (7 objects)\n\
+             perf:   fs walks        0.000 ms (0 walks)\n",
+            format_report("status", Duration::from_millis(20), &totals)
+        );
+    }
+}
@@ -0,0 +1,162 @@
+//! This module implements the pickaxe of `git log -S` and `git log -G`: finding the commits
+//! that introduced or removed a string, e.g. to tell which commit added a function.
+//!
+//! The files a commit changes are looked up in the `FileChanges` table, so only the blobs of
+//! the changed files are read, never the trees of the commits.
+
+use std::collections::HashMap;
+
+use regex::bytes::Regex;
+use rusqlite::Connection;
+
+use super::{
+    diff::{diff_lines, looks_binary, split_lines, LineEdit},
+    file_changes::read_file_changes,
+    model::{Blob, Commit, Sha1Id},
+};
+
+/// [`Pickaxe`] tells which changes of a file a commit must make to be found
+#[derive(Debug, Clone)]
+pub enum Pickaxe {
+    /// The number of occurrences of the string in the file changes (`-S`)
+    Occurrences(Vec<u8>),
+    /// An added or removed line matches the regex (`-G`)
+    Lines(Regex),
+}
+
+/// [`PickaxeSearch`] looks for the commits whose changes the pickaxe catches, remembering the
+/// blobs already read
+pub struct PickaxeSearch<'a> {
+    conn: &'a Connection,
+    pickaxe: Pickaxe,
+    blobs: HashMap<Sha1Id, Vec<u8>>,
+}
+
+impl<'a> PickaxeSearch<'a> {
+    pub fn new(conn: &'a Connection, pickaxe: Pickaxe) -> PickaxeSearch<'a> {
+        PickaxeSearch {
+            conn,
+            pickaxe,
+            blobs: HashMap::new(),
+        }
+    }
+
+    /// Whether a change of the commit is caught. Merges are never caught, as `git log` does
+    /// not diff them by default.
+    pub fn matches(&mut self, commit: &Commit<Sha1Id>) -> crate::Result<bool> {
+        if commit.parent_ids.len() > 1 {
+            return Ok(false);
+        }
+        for change in read_file_changes(self.conn, commit.commit_id)? {
+            self.load(change.old_blob)?;
+            self.load(change.new_blob)?;
+            let content = |id: Option<Sha1Id>| match id {
+                Some(id) => self.blobs[&id].as_slice(),
+                None => &[],
+            };
+            let (old, new) = (content(change.old_blob), content(change.new_blob));
+            let caught = match &self.pickaxe {
+                Pickaxe::Occurrences(needle) => {
+                    count_occurrences(old, needle) != count_occurrences(new, needle)
+                }
+                Pickaxe::Lines(regex) => {
+                    if looks_binary(old) || looks_binary(new) {
+                        continue;
+                    }
+                    let (old, new) = (split_lines(old), split_lines(new));
+                    diff_lines(&old, &new).iter().any(|edit| match *edit {
+                        LineEdit::Equal(..) => false,
+                        LineEdit::Delete(x) => regex.is_match(old[x]),
+                        LineEdit::Insert(y) => regex.is_match(new[y]),
+                    })
+                }
+            };
+            if caught {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Read a blob unless it was read before. A missing side of a change has nothing to read.
+    fn load(&mut self, id: Option<Sha1Id>) -> crate::Result<()> {
+        if let Some(id) = id.filter(|id| !self.blobs.contains_key(id)) {
+            let data = Blob::read_from_conn_with_id(self.conn, id)?.data;
+            self.blobs.insert(id, data);
+        }
+        Ok(())
+    }
+}
+
+/// Number of non-overlapping occurrences of `needle` in `haystack`
+fn count_occurrences(haystack: &[u8], needle: &[u8]) -> usize {
+    if needle.is_empty() {
+        return 0;
+    }
+    let mut count = 0;
+    let mut rest = haystack;
+    while let Some(at) = rest
+        .windows(needle.len())
+        .position(|window| window == needle)
+    {
+        count += 1;
+        rest = &rest[at + needle.len()..];
+    }
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::{
+        file_changes::{
+            record_file_changes, CREATE_FILE_CHANGES_PATH_INDEX, CREATE_FILE_CHANGES_TABLE,
+        },
+        revision::commits_between,
+        test_utils::{commit_files, object_database},
+    };
+
+    fn commit(conn: &Connection, parent: Option<Sha1Id>, content: &str) -> Sha1Id {
+        let parent_ids = parent.into_iter().collect();
+        let id = commit_files(conn, parent_ids, &[("lib.rs", content)], content);
+        let commit = Commit::read_from_conn_with_id(conn, id).unwrap();
+        record_file_changes(conn, &[&commit]).unwrap();
+        id
+    }
+
+    #[test]
+    fn test_pickaxe() {
+        let conn = object_database(&[CREATE_FILE_CHANGES_TABLE, CREATE_FILE_CHANGES_PATH_INDEX]);
+        let first = commit(&conn, None, "fn main() {}\n");
+        let added = commit(&conn, Some(first), "fn main() {}\nfn parse() {}\n");
+        let indented = commit(&conn, Some(added), "fn main() {}\n    fn parse() {}\n");
+        let removed = commit(&conn, Some(indented), "fn main() {}\n");
+
+        let search = |pickaxe: Pickaxe| -> crate::Result<Vec<Sha1Id>> {
+            let mut search = PickaxeSearch::new(&conn, pickaxe);
+            let mut found = Vec::new();
+            for id in commits_between(&conn, None, removed)?.into_iter().rev() {
+                if search.matches(&Commit::read_from_conn_with_id(&conn, id)?)? {
+                    found.push(id);
+                }
+            }
+            Ok(found)
+        };
+        // Indenting the line does not change the number of occurrences
+        assert_eq!(
+            vec![removed, added],
+            search(Pickaxe::Occurrences(b"fn parse".to_vec())).unwrap()
+        );
+        assert_eq!(
+            vec![removed, indented, added],
+            search(Pickaxe::Lines(Regex::new(r"parse\(").unwrap())).unwrap()
+        );
+        assert_eq!(
+            vec![first],
+            search(Pickaxe::Occurrences(b"main".to_vec())).unwrap()
+        );
+
+        assert_eq!(2, count_occurrences(b"aaaa", b"aa"));
+        assert_eq!(0, count_occurrences(b"a", b"aa"));
+    }
+}
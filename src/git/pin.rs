@@ -0,0 +1,92 @@
+//! This module maintains the `PinnedObjects` table: objects which `gitqlite prune` and
+//! `gitqlite gc` keep even when no ref leads to them, e.g. the commit of a release whose id is
+//! published elsewhere, or a blob an external system refers to by hash. A pinned commit or tree
+//! keeps everything it leads to.
+
+use anyhow::anyhow;
+use rusqlite::Connection;
+
+use super::{
+    model::{Sha1Id, TreeEntryType},
+    prune::{object_kind, ObjectKind},
+    revision::{resolve_path_object, resolve_revision},
+};
+
+pub const CREATE_PINNED_OBJECTS_TABLE: &str = "CREATE TABLE PinnedObjects (object_id BLOB PRIMARY KEY, kind TEXT NOT NULL, created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now')));";
+const READ_PINNED_OBJECTS: &str = "SELECT kind, object_id FROM PinnedObjects ORDER BY rowid";
+const INSERT_PINNED_OBJECT: &str =
+    "INSERT OR IGNORE INTO PinnedObjects (object_id, kind) VALUES (?1, ?2);";
+const DELETE_PINNED_OBJECT: &str = "DELETE FROM PinnedObjects WHERE object_id = ?1";
+
+/// Resolve the name of an object to pin: a `<rev>:<path>`, a revision, or the hash of a tree
+/// or blob
+pub fn resolve_object(conn: &Connection, name: &str) -> crate::Result<(ObjectKind, Sha1Id)> {
+    if let Some((type_, id)) = resolve_path_object(conn, name)? {
+        let kind = match type_ {
+            TreeEntryType::Tree => ObjectKind::Tree,
+            TreeEntryType::Blob => ObjectKind::Blob,
+        };
+        return Ok((kind, id));
+    }
+    if let Ok(id) = resolve_revision(conn, name) {
+        return Ok((ObjectKind::Commit, id));
+    }
+    let not_found = || anyhow!("fatal: not a valid object name {}", name);
+    let id = Sha1Id::try_from(name).map_err(|_| not_found())?;
+    let kind = object_kind(conn, id)?.ok_or_else(not_found)?;
+    Ok((kind, id))
+}
+
+/// The pinned objects, the first pinned first
+pub fn pinned_objects(conn: &Connection) -> crate::Result<Vec<(ObjectKind, Sha1Id)>> {
+    let mut stmt = conn.prepare(READ_PINNED_OBJECTS)?;
+    let rows = stmt.query_map((), |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, Sha1Id>(1)?))
+    })?;
+    let mut pinned = Vec::new();
+    for row in rows {
+        let (kind, id) = row?;
+        let kind = ObjectKind::parse(&kind)
+            .ok_or_else(|| anyhow!("fatal: pinned object {} has unknown kind {}", id, kind))?;
+        pinned.push((kind, id));
+    }
+    Ok(pinned)
+}
+
+/// Pin an object. Returns whether it was not pinned yet.
+pub fn pin(conn: &Connection, kind: ObjectKind, id: Sha1Id) -> crate::Result<bool> {
+    Ok(conn.execute(INSERT_PINNED_OBJECT, (id, kind.as_str()))? > 0)
+}
+
+/// Unpin an object. Returns whether it was pinned.
+pub fn unpin(conn: &Connection, id: Sha1Id) -> crate::Result<bool> {
+    Ok(conn.execute(DELETE_PINNED_OBJECT, [id])? > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pin() {
+        let conn = Connection::open_in_memory().unwrap();
+        let (a, b) = (
+            Sha1Id::try_from(vec![1; 20]).unwrap(),
+            Sha1Id::try_from(vec![2; 20]).unwrap(),
+        );
+        conn.execute(CREATE_PINNED_OBJECTS_TABLE, ()).unwrap();
+        assert!(pinned_objects(&conn).unwrap().is_empty());
+        assert!(!unpin(&conn, a).unwrap());
+
+        assert!(pin(&conn, ObjectKind::Commit, a).unwrap());
+        assert!(!pin(&conn, ObjectKind::Commit, a).unwrap());
+        assert!(pin(&conn, ObjectKind::Blob, b).unwrap());
+        assert_eq!(
+            vec![(ObjectKind::Commit, a), (ObjectKind::Blob, b)],
+            pinned_objects(&conn).unwrap()
+        );
+
+        assert!(unpin(&conn, a).unwrap());
+        assert_eq!(vec![(ObjectKind::Blob, b)], pinned_objects(&conn).unwrap());
+    }
+}
@@ -0,0 +1,132 @@
+//! This module reports the progress of commands which write many files to the working tree,
+//! like merge and rebase. The code materializing the files announces how many it is about to
+//! touch, then reports an event for each one. As long as stderr is a terminal, [`Progress`]
+//! redraws `Updating files: 42% (21/50)` as the events come, and once the command is done it
+//! prints a summary of the files updated and removed. Quiet progress only counts.
+
+use std::io::{IsTerminal, Write};
+
+/// [`FileEvent`] is what happened to a file of the working tree
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileEvent {
+    /// The file was written, new or with new content
+    Updated,
+    Removed,
+}
+
+/// [`Progress`] counts the files written to the working tree
+#[derive(Debug, Clone, Default)]
+pub struct Progress {
+    quiet: bool,
+    /// Whether the progress line is drawn on stderr
+    draw: bool,
+    total: usize,
+    done: usize,
+    updated: usize,
+    removed: usize,
+    percent: Option<usize>,
+}
+
+impl Progress {
+    /// Progress which is drawn when stderr is a terminal, and summarized unless `quiet`
+    pub fn new(quiet: bool) -> Progress {
+        Progress {
+            quiet,
+            draw: !quiet && std::io::stderr().is_terminal(),
+            ..Progress::default()
+        }
+    }
+
+    /// Progress which is neither drawn nor summarized
+    pub fn silent() -> Progress {
+        Progress::new(true)
+    }
+
+    /// Whether the command was asked to be quiet
+    pub fn is_quiet(&self) -> bool {
+        self.quiet
+    }
+
+    /// Announce that `count` more files are about to be touched
+    pub fn add_total(&mut self, count: usize) {
+        self.total += count;
+    }
+
+    pub fn report(&mut self, event: FileEvent) {
+        match event {
+            FileEvent::Updated => self.updated += 1,
+            FileEvent::Removed => self.removed += 1,
+        }
+        self.done += 1;
+        self.total = self.total.max(self.done);
+        let percent = self.done * 100 / self.total;
+        if self.draw && self.percent != Some(percent) {
+            self.percent = Some(percent);
+            eprint!("\r{}", self.line());
+            let _ = std::io::stderr().flush();
+        }
+    }
+
+    /// Terminate the progress line and print the summary. The count starts over, so that each
+    /// step of a command finishing the progress summarizes the files it touched.
+    pub fn finish(&mut self) {
+        if self.percent.is_some() {
+            eprintln!("\r{}, done.", self.line());
+        }
+        if !self.quiet && self.done > 0 {
+            println!("{}", self.summary());
+        }
+        *self = Progress {
+            quiet: self.quiet,
+            draw: self.draw,
+            ..Progress::default()
+        };
+    }
+
+    /// e.g. `Updated 3 files, removed 1 file`
+    pub fn summary(&self) -> String {
+        let files = |count: usize| format!("{} file{}", count, if count == 1 { "" } else { "s" });
+        let mut summary = format!("Updated {}", files(self.updated));
+        if self.removed > 0 {
+            summary.push_str(&format!(", removed {}", files(self.removed)));
+        }
+        summary
+    }
+
+    fn line(&self) -> String {
+        format!(
+            "Updating files: {:>3}% ({}/{})",
+            self.done * 100 / self.total.max(1),
+            self.done,
+            self.total
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_progress() {
+        let mut progress = Progress::silent();
+        progress.add_total(3);
+        progress.report(FileEvent::Updated);
+        assert_eq!("Updating files:  33% (1/3)", progress.line());
+        progress.report(FileEvent::Removed);
+        progress.report(FileEvent::Updated);
+        assert_eq!("Updating files: 100% (3/3)", progress.line());
+        assert_eq!("Updated 2 files, removed 1 file", progress.summary());
+
+        // Files touched beyond the announced ones grow the total
+        progress.report(FileEvent::Updated);
+        assert_eq!("Updating files: 100% (4/4)", progress.line());
+        progress.finish();
+        assert_eq!("Updating files:   0% (0/0)", progress.line());
+        assert_eq!("Updated 1 file", {
+            let mut one = Progress::silent();
+            one.report(FileEvent::Updated);
+            one.summary()
+        });
+    }
+}
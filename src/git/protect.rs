@@ -0,0 +1,74 @@
+//! This module enforces branch protection: a branch with `branch.<name>.protect` set may only
+//! move forward. Commands that would rewind, rewrite or delete it refuse to unless given
+//! `--force-protected=<commit>`, naming the commit the branch is expected to be at, so that
+//! the override cannot clobber work the user has not seen, as `--force-with-lease` does.
+
+use anyhow::anyhow;
+
+use super::model::Sha1Id;
+use crate::repo::config::{ConfigSource, GitConfig};
+
+/// Whether the branch, given by its short name, is protected
+pub fn is_protected(config: &GitConfig, branch: &str) -> crate::Result<bool> {
+    Ok(config
+        .get_bool(&format!("branch.{}.protect", branch), ConfigSource::All)?
+        .unwrap_or(false))
+}
+
+/// Check that the branch may be rewound, rewritten or deleted. `current` is the commit the
+/// branch is at, and `expected` the one given to `--force-protected`, if any.
+pub fn check_rewrite(
+    config: &GitConfig,
+    branch: &str,
+    current: Option<Sha1Id>,
+    expected: Option<Sha1Id>,
+) -> crate::Result<()> {
+    if !is_protected(config, branch)? {
+        return Ok(());
+    }
+    match (current, expected) {
+        (_, None) => Err(anyhow!(
+            "fatal: branch '{}' is protected (branch.{}.protect)\nhint: use --force-protected=<commit> naming its current tip to override",
+            branch,
+            branch
+        )),
+        (current, Some(expected)) if current != Some(expected) => Err(anyhow!(
+            "fatal: protected branch '{}' is at {}, not at {} as expected",
+            branch,
+            current.map_or("nothing".to_string(), |id| id.to_string()),
+            expected
+        )),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn test_check_rewrite() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("config"),
+            "[branch \"main\"]\n\tprotect = true\n[branch \"topic\"]\n\tprotect = false\n",
+        )
+        .unwrap();
+        let config = GitConfig::load(dir.path()).unwrap();
+        let (a, b) = (
+            Sha1Id::try_from(vec![1; 20]).unwrap(),
+            Sha1Id::try_from(vec![2; 20]).unwrap(),
+        );
+
+        assert!(check_rewrite(&config, "topic", Some(a), None).is_ok());
+        assert!(check_rewrite(&config, "other", Some(a), None).is_ok());
+        let err = check_rewrite(&config, "main", Some(a), None).unwrap_err();
+        assert!(err.to_string().contains("is protected"));
+        // The override names the commit the branch is at
+        assert!(check_rewrite(&config, "main", Some(a), Some(b)).is_err());
+        assert!(check_rewrite(&config, "main", None, Some(b)).is_err());
+        assert!(check_rewrite(&config, "main", Some(a), Some(a)).is_ok());
+    }
+}
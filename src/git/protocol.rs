@@ -0,0 +1,909 @@
+//! This module implements the protocol used to move history between gitqlite repositories,
+//! and the server side of it run by `gitqlite daemon`.
+//!
+//! A client sends a single request and the server answers with a single response. Each
+//! message is a line of JSON; a message carrying a pack (the objects being transferred, in the
+//! bundle format) records its size in `pack_size`, and the pack follows the line as raw bytes.
+//!
+//! - `ls-refs` asks for the refs of the repository and the branch HEAD points to
+//! - `fetch` asks for a pack of the history reachable from `wants`, leaving out the history
+//!   reachable from the `haves` the server knows about. With `max_commits`, the pack holds the
+//!   oldest commits of that history only, and the response tells how many are `remaining`, so
+//!   that a client can download a long history in chunks and resume after an interruption.
+//! - `push` sends a pack and asks the server to update its refs, each update asserting the
+//!   value the ref had when the client listed it
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    io::{BufRead, Read, Write},
+    path::{Component, Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::anyhow;
+use rusqlite::{Connection, OpenFlags};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use super::{
+    bundle::{bundled_commits, create_bundle, unbundle, BundleHeader},
+    constants::{GITQLITE_DB_NAME, GITQLITE_DIRECTORY_PREFIX},
+    hooks::{Hooks, NULL_ID},
+    manifest::prepare_connection,
+    merge::is_ancestor,
+    model::{Commit, Head, Ref, Sha1Id},
+    utils::{upgrade_database, WriteTransaction},
+};
+use crate::repo::db::{read_object_type, ObjectType, OldValue, RefTransaction};
+
+/// Port `gitqlite daemon` listens on by default, the same as git's daemon
+pub const DEFAULT_DAEMON_PORT: u16 = 9418;
+/// Longest message accepted, so that a peer cannot make the other end buffer a line forever.
+/// It leaves room for the refs of large repositories.
+const MAX_MESSAGE_SIZE: u64 = 16 << 20;
+/// Largest pack a push may carry unless the daemon is told otherwise
+pub const DEFAULT_MAX_PACK_SIZE: u64 = 1 << 30;
+
+/// [`ProtocolRequest`] is the message sent by a client
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "kebab-case")]
+pub enum ProtocolRequest {
+    LsRefs {
+        repo: String,
+    },
+    Fetch {
+        repo: String,
+        wants: Vec<Sha1Id>,
+        haves: Vec<Sha1Id>,
+        /// Largest number of commits the pack may hold
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max_commits: Option<usize>,
+    },
+    Push {
+        repo: String,
+        updates: Vec<RefUpdate>,
+        pack_size: u64,
+    },
+}
+
+impl ProtocolRequest {
+    /// Path of the repository the request is for
+    pub fn repo(&self) -> &str {
+        match self {
+            ProtocolRequest::LsRefs { repo }
+            | ProtocolRequest::Fetch { repo, .. }
+            | ProtocolRequest::Push { repo, .. } => repo,
+        }
+    }
+}
+
+/// [`RefUpdate`] asks the server to move a ref from `old` to `new`. None stands for a missing
+/// ref, so a None `old` creates the ref and a None `new` deletes it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RefUpdate {
+    pub name: String,
+    pub old: Option<Sha1Id>,
+    pub new: Option<Sha1Id>,
+    /// Allow updates which are not fast-forwards
+    pub force: bool,
+}
+
+/// [`ProtocolResponse`] is the message sent back by the server
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "kebab-case")]
+pub enum ProtocolResponse {
+    Refs {
+        /// Full name of the branch HEAD points to, None if HEAD is detached
+        head: Option<String>,
+        refs: Vec<(String, Sha1Id)>,
+    },
+    Pack {
+        pack_size: u64,
+        /// Commits left out of the pack to keep it within `max_commits`
+        #[serde(default)]
+        remaining: usize,
+    },
+    /// Outcome of each ref update of a push: None if it was applied, or the reason it was not
+    Pushed {
+        results: Vec<(String, Option<String>)>,
+    },
+    Error {
+        message: String,
+    },
+}
+
+/// [`DaemonOptions`] configures the repositories served by the daemon
+#[derive(Debug)]
+pub struct DaemonOptions {
+    /// Directory holding the repositories, request paths are relative to it
+    pub base_path: PathBuf,
+    /// Repositories accepting pushes, matched against the request path
+    pub push_allow: Vec<glob::Pattern>,
+    /// Largest pack accepted from a push, in bytes
+    pub max_pack_size: u64,
+    /// Databases brought up to the current schema since the daemon started, which are not
+    /// checked again
+    pub upgraded: Mutex<HashSet<PathBuf>>,
+}
+
+impl DaemonOptions {
+    /// Bring the database at `db_path` up to the current schema the first time it is served
+    fn upgrade_once(&self, db_path: &Path) -> crate::Result<()> {
+        let mut upgraded = self
+            .upgraded
+            .lock()
+            .expect("upgraded databases lock poisoned");
+        if !upgraded.contains(db_path) {
+            upgrade_database(db_path)?;
+            upgraded.insert(db_path.to_path_buf());
+        }
+        Ok(())
+    }
+}
+
+/// Write a message, followed by the pack if any
+pub fn write_message(
+    writer: &mut impl Write,
+    message: &impl Serialize,
+    pack: Option<&[u8]>,
+) -> crate::Result<()> {
+    writeln!(writer, "{}", serde_json::to_string(message)?)?;
+    if let Some(pack) = pack {
+        writer.write_all(pack)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Read a message. Its pack, if any, is left to be read with [`read_pack`].
+pub fn read_message<T: DeserializeOwned>(reader: &mut impl BufRead) -> crate::Result<T> {
+    let mut line = String::new();
+    if reader.take(MAX_MESSAGE_SIZE + 1).read_line(&mut line)? == 0 {
+        return Err(anyhow!("fatal: the remote end hung up unexpectedly"));
+    }
+    if line.len() as u64 > MAX_MESSAGE_SIZE {
+        return Err(anyhow!(
+            "fatal: protocol error: message longer than {} bytes",
+            MAX_MESSAGE_SIZE
+        ));
+    }
+    serde_json::from_str(&line).map_err(|err| anyhow!("fatal: protocol error: {}", err))
+}
+
+/// Read a pack of the given size following a message
+pub fn read_pack(reader: &mut impl Read, size: u64) -> crate::Result<Vec<u8>> {
+    let mut pack = Vec::new();
+    reader.take(size).read_to_end(&mut pack)?;
+    if pack.len() as u64 != size {
+        return Err(anyhow!("fatal: early EOF"));
+    }
+    Ok(pack)
+}
+
+/// Write a pack holding the history reachable from `wants` but not from `haves`. With
+/// `max_commits`, the pack holds the oldest commits of that history only, parents before their
+/// children. Returns the pack and the number of commits left out of it.
+pub fn pack_objects(
+    conn: &Connection,
+    wants: &[Sha1Id],
+    haves: &[Sha1Id],
+    max_commits: Option<usize>,
+) -> crate::Result<(Vec<u8>, usize)> {
+    // Commits the repository does not have cannot bound the history
+    let mut exclude = Vec::new();
+    for have in haves {
+        if read_object_type(conn, (*have).into())? == Some(ObjectType::Commit) {
+            exclude.push(*have);
+        }
+    }
+    let mut tips: Vec<Sha1Id> = Vec::new();
+    for want in wants {
+        if !tips.contains(want) {
+            tips.push(*want);
+        }
+    }
+
+    let mut remaining = 0;
+    if let Some(max_commits) = max_commits {
+        let commits = bundled_commits(conn, &tips, &exclude)?;
+        let max_commits = max_commits.max(1);
+        if commits.len() > max_commits {
+            // The oldest commits are all the ancestors they have in the history, so that the
+            // chunk is the history of its own tips
+            let chunk = &parents_first(&commits)[..max_commits];
+            let parents: HashSet<Sha1Id> = chunk
+                .iter()
+                .flat_map(|commit| commit.parent_ids.iter().copied())
+                .collect();
+            tips = chunk
+                .iter()
+                .map(|commit| commit.commit_id)
+                .filter(|id| !parents.contains(id))
+                .collect();
+            remaining = commits.len() - max_commits;
+        }
+    }
+
+    let refs: Vec<(String, Sha1Id)> = tips.iter().map(|id| (id.to_string(), *id)).collect();
+    let file = tempfile::NamedTempFile::new()?;
+    create_bundle(conn, file.path(), &refs, &exclude)?;
+    Ok((fs::read(file.path())?, remaining))
+}
+
+/// Order the commits so that the parents among them come before their children
+fn parents_first(commits: &[Commit<Sha1Id>]) -> Vec<&Commit<Sha1Id>> {
+    let ids: HashSet<Sha1Id> = commits.iter().map(|commit| commit.commit_id).collect();
+    let mut children: HashMap<Sha1Id, Vec<usize>> = HashMap::new();
+    let mut pending = vec![0; commits.len()];
+    for (index, commit) in commits.iter().enumerate() {
+        for parent in commit.parent_ids.iter().filter(|id| ids.contains(id)) {
+            pending[index] += 1;
+            children.entry(*parent).or_default().push(index);
+        }
+    }
+
+    let mut ready: Vec<usize> = (0..commits.len())
+        .filter(|index| pending[*index] == 0)
+        .collect();
+    let mut ordered = Vec::with_capacity(commits.len());
+    while let Some(index) = ready.pop() {
+        ordered.push(&commits[index]);
+        for child in children
+            .get(&commits[index].commit_id)
+            .into_iter()
+            .flatten()
+        {
+            pending[*child] -= 1;
+            if pending[*child] == 0 {
+                ready.push(*child);
+            }
+        }
+    }
+    ordered
+}
+
+/// Store the objects of a pack in the repository. Returns the header of the pack, whose refs
+/// are the tips of the history it carries.
+pub fn unpack_objects(conn: &Connection, pack: &[u8]) -> crate::Result<BundleHeader> {
+    let mut file = tempfile::NamedTempFile::new()?;
+    file.write_all(pack)?;
+    file.flush()?;
+    unbundle(conn, file.path())
+}
+
+/// Read a request along with the pack it carries, refusing packs larger than `max_pack_size`
+/// before reading them
+pub fn read_request(
+    reader: &mut impl BufRead,
+    max_pack_size: u64,
+) -> crate::Result<(ProtocolRequest, Option<Vec<u8>>)> {
+    let request: ProtocolRequest = read_message(reader)?;
+    let pack = match &request {
+        ProtocolRequest::Push { pack_size, .. } if *pack_size > max_pack_size => {
+            return Err(anyhow!(
+                "fatal: protocol error: pack larger than {} bytes",
+                max_pack_size
+            ));
+        }
+        ProtocolRequest::Push { pack_size, .. } => Some(read_pack(reader, *pack_size)?),
+        _ => None,
+    };
+    Ok((request, pack))
+}
+
+/// Read a response along with the pack it carries. Error responses become errors.
+pub fn read_response(
+    reader: &mut impl BufRead,
+) -> crate::Result<(ProtocolResponse, Option<Vec<u8>>)> {
+    let response: ProtocolResponse = read_message(reader)?;
+    match response {
+        ProtocolResponse::Error { message } => Err(anyhow!("{}", message)),
+        ProtocolResponse::Pack { pack_size, .. } => {
+            let pack = read_pack(reader, pack_size)?;
+            Ok((response, Some(pack)))
+        }
+        response => Ok((response, None)),
+    }
+}
+
+/// Send a request over a stream and read back the response
+pub fn exchange(
+    reader: &mut impl BufRead,
+    writer: &mut impl Write,
+    request: &ProtocolRequest,
+    pack: Option<&[u8]>,
+) -> crate::Result<(ProtocolResponse, Option<Vec<u8>>)> {
+    write_message(writer, request, pack)?;
+    read_response(reader)
+}
+
+/// Answer the request of one client
+pub fn serve_connection(
+    reader: &mut impl BufRead,
+    writer: &mut impl Write,
+    options: &DaemonOptions,
+) -> crate::Result<()> {
+    let (request, pack) = read_request(reader, options.max_pack_size)?;
+    let (response, pack) = serve_request(&request, pack.as_deref(), options);
+    write_message(writer, &response, pack.as_deref())
+}
+
+/// Answer the request of a client for the repository at `path`, as run on the remote host by
+/// the SSH transport. Pushes are allowed, the client having logged in to the host.
+pub fn serve_repository(
+    reader: &mut impl BufRead,
+    writer: &mut impl Write,
+    path: &Path,
+) -> crate::Result<()> {
+    let (request, pack) = read_request(reader, DEFAULT_MAX_PACK_SIZE)?;
+    let (response, pack) = local_db_path(path)
+        .and_then(|db_path| {
+            upgrade_database(&db_path)?;
+            answer_request(&db_path, &request, pack.as_deref(), true)
+        })
+        .unwrap_or_else(error_response);
+    write_message(writer, &response, pack.as_deref())
+}
+
+/// Answer a request for one of the repositories under the base path. Failures are reported
+/// to the client as error responses.
+pub fn serve_request(
+    request: &ProtocolRequest,
+    pack: Option<&[u8]>,
+    options: &DaemonOptions,
+) -> (ProtocolResponse, Option<Vec<u8>>) {
+    let repo = request.repo().trim_matches('/');
+    let allow_push = options
+        .push_allow
+        .iter()
+        .any(|pattern| pattern.matches(repo));
+    repository_db_path(options, repo)
+        .and_then(|db_path| {
+            options.upgrade_once(&db_path)?;
+            answer_request(&db_path, request, pack, allow_push)
+        })
+        .unwrap_or_else(error_response)
+}
+
+fn error_response(err: anyhow::Error) -> (ProtocolResponse, Option<Vec<u8>>) {
+    let message = err.to_string();
+    (ProtocolResponse::Error { message }, None)
+}
+
+/// Answer a request for the repository whose database is at `db_path`, which must be up to
+/// date with the current schema (see [`upgrade_database`])
+pub fn answer_request(
+    db_path: &Path,
+    request: &ProtocolRequest,
+    pack: Option<&[u8]>,
+    allow_push: bool,
+) -> crate::Result<(ProtocolResponse, Option<Vec<u8>>)> {
+    let read_only = || Connection::open_with_flags(db_path, OpenFlags::SQLITE_OPEN_READ_ONLY);
+    match request {
+        ProtocolRequest::LsRefs { .. } => Ok((list_refs(&read_only()?)?, None)),
+        ProtocolRequest::Fetch {
+            wants,
+            haves,
+            max_commits,
+            ..
+        } => {
+            let (pack, remaining) = pack_objects(&read_only()?, wants, haves, *max_commits)?;
+            let response = ProtocolResponse::Pack {
+                pack_size: pack.len() as u64,
+                remaining,
+            };
+            Ok((response, Some(pack)))
+        }
+        ProtocolRequest::Push { repo, updates, .. } => {
+            if !allow_push {
+                return Err(anyhow!("fatal: push to '{}' is not allowed", repo));
+            }
+            let conn = Connection::open_with_flags(db_path, OpenFlags::SQLITE_OPEN_READ_WRITE)?;
+            prepare_connection(&conn)?;
+            let hooks = Hooks::for_repository(db_path.parent().unwrap_or(db_path))?;
+            let results = receive_pack(&conn, &hooks, updates, pack.unwrap_or_default())?;
+            Ok((ProtocolResponse::Pushed { results }, None))
+        }
+    }
+}
+
+/// The refs of the repository along with the branch HEAD points to
+pub fn list_refs(conn: &Connection) -> crate::Result<ProtocolResponse> {
+    let head = match Head::read_from_conn(conn)? {
+        Head::Branch(name) => Some(name),
+        Head::Commit(_) => None,
+    };
+    let refs = Ref::read_all(conn)?
+        .into_iter()
+        .map(|reference| (reference.name, reference.commit_id))
+        .collect();
+    Ok(ProtocolResponse::Refs { head, refs })
+}
+
+/// Store the objects of a pushed pack and apply the ref updates which are valid and which the
+/// hooks accept. A failing `pre-receive` hook refuses every update. The objects are only kept
+/// if some update is applied.
+pub fn receive_pack(
+    conn: &Connection,
+    hooks: &Hooks,
+    updates: &[RefUpdate],
+    pack: &[u8],
+) -> crate::Result<Vec<(String, Option<String>)>> {
+    // The objects are stored in the same transaction as the refs, so that they are rolled back
+    // if the hooks refuse every update. The hooks do not see them in the repository.
+    let txn = WriteTransaction::new(conn)?;
+    if !pack.is_empty() {
+        unpack_objects(&txn, pack)?;
+    }
+
+    let hook_id = |id: Option<Sha1Id>| id.map_or(NULL_ID.to_string(), |id| id.to_string());
+    let input: String = updates
+        .iter()
+        .map(|update| {
+            format!(
+                "{} {} {}\n",
+                hook_id(update.old),
+                hook_id(update.new),
+                update.name
+            )
+        })
+        .collect();
+    if !hooks.run("pre-receive", &[], input.as_bytes())? {
+        let declined = Some("pre-receive hook declined".to_string());
+        return Ok(updates
+            .iter()
+            .map(|update| (update.name.clone(), declined.clone()))
+            .collect());
+    }
+
+    let checked_out = match Head::read_from_conn(&txn)? {
+        Head::Branch(name) => Some(name),
+        Head::Commit(_) => None,
+    };
+    let mut results = Vec::new();
+    let mut accepted = RefTransaction::new("push");
+    for update in updates {
+        let mut result = check_update(&txn, update, checked_out.as_deref())?;
+        if result.is_none() {
+            let args = [
+                update.name.as_str(),
+                &hook_id(update.old),
+                &hook_id(update.new),
+            ];
+            if !hooks.run("update", &args, &[])? {
+                result = Some("hook declined".to_string());
+            }
+        }
+        if result.is_none() {
+            let old = update
+                .old
+                .map_or(OldValue::Missing, |old| OldValue::Is(old.into()));
+            match update.new {
+                Some(new) => accepted.update(&update.name, old, new.into()),
+                None => accepted.delete(&update.name, old),
+            };
+        }
+        results.push((update.name.clone(), result));
+    }
+
+    if results.iter().all(|(_, result)| result.is_some()) {
+        return Ok(results);
+    }
+    accepted.commit(&txn)?;
+    txn.commit()?;
+    Ok(results)
+}
+
+/// Tell why a ref update cannot be applied, None if it can
+fn check_update(
+    conn: &Connection,
+    update: &RefUpdate,
+    checked_out: Option<&str>,
+) -> crate::Result<Option<String>> {
+    let current = Ref::read_from_conn_with_name(conn, &update.name)?.map(|r| r.commit_id);
+    if current != update.old {
+        return Ok(Some("stale info".to_string()));
+    }
+    if checked_out == Some(update.name.as_str()) {
+        return Ok(Some("branch is currently checked out".to_string()));
+    }
+    let Some(new) = update.new else {
+        return Ok(None);
+    };
+    if let Err(err) = Ref::check_name_available(conn, &update.name) {
+        let reason = err.to_string();
+        return Ok(Some(reason.trim_start_matches("fatal: ").to_string()));
+    }
+    if read_object_type(conn, new.into())? != Some(ObjectType::Commit) {
+        return Ok(Some("missing necessary objects".to_string()));
+    }
+    if let Some(current) = current {
+        if !update.force && !is_ancestor(conn, current, new)? {
+            return Ok(Some("non-fast-forward".to_string()));
+        }
+    }
+    Ok(None)
+}
+
+/// Path of the database of the repository at `repo` under the base path
+fn repository_db_path(options: &DaemonOptions, repo: &str) -> crate::Result<PathBuf> {
+    let relative = Path::new(repo.trim_start_matches('/'));
+    let no_such_repository = || {
+        anyhow!(
+            "fatal: '{}' does not appear to be a gitqlite repository",
+            repo
+        )
+    };
+    if !relative
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+    {
+        return Err(no_such_repository());
+    }
+
+    let db_path = options
+        .base_path
+        .join(relative)
+        .join(GITQLITE_DIRECTORY_PREFIX)
+        .join(GITQLITE_DB_NAME);
+    if !db_path.is_file() {
+        return Err(no_such_repository());
+    }
+    Ok(db_path)
+}
+
+/// Path of the database of the repository at `path` on the local filesystem
+pub fn local_db_path(path: &Path) -> crate::Result<PathBuf> {
+    let db_path = path.join(GITQLITE_DIRECTORY_PREFIX).join(GITQLITE_DB_NAME);
+    if !db_path.is_file() {
+        return Err(anyhow!(
+            "fatal: '{}' does not appear to be a gitqlite repository",
+            path.display()
+        ));
+    }
+    Ok(db_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::git::{
+        constants::BRANCH_PREFIX,
+        model::{
+            CREATE_BLOB_TABLE, CREATE_COMMIT_TABLE, CREATE_HEAD_TABLE, CREATE_IDENTITIES_TABLE,
+            CREATE_REF_TABLE, CREATE_TREE_TABLE,
+        },
+        schema::upgrade_schema,
+        test_utils::{commit_content, create_tables},
+    };
+
+    /// Create a repository database at `<base>/<name>` with HEAD on main
+    fn create_repository(base: &Path, name: &str) -> Connection {
+        let dir = base.join(name).join(GITQLITE_DIRECTORY_PREFIX);
+        fs::create_dir_all(&dir).unwrap();
+        let mut conn = Connection::open(dir.join(GITQLITE_DB_NAME)).unwrap();
+        // Upgrading the schema adds triggers using this function
+        prepare_connection(&conn).unwrap();
+        create_tables(
+            &conn,
+            &[
+                CREATE_BLOB_TABLE,
+                CREATE_TREE_TABLE,
+                CREATE_IDENTITIES_TABLE,
+                CREATE_COMMIT_TABLE,
+                CREATE_HEAD_TABLE,
+                CREATE_REF_TABLE,
+            ],
+        );
+        Head::Branch(format!("{}main", BRANCH_PREFIX))
+            .persist(&conn)
+            .unwrap();
+        // The tables added since the first release, as opening the repository creates them
+        upgrade_schema(&mut conn).unwrap();
+        conn
+    }
+
+    /// Send a request to the server and read back the response and its pack
+    fn exchange(
+        options: &DaemonOptions,
+        request: &ProtocolRequest,
+        pack: Option<&[u8]>,
+    ) -> (ProtocolResponse, Vec<u8>) {
+        let mut input = Vec::new();
+        write_message(&mut input, request, pack).unwrap();
+        let mut output = Vec::new();
+        serve_connection(&mut Cursor::new(input), &mut output, options).unwrap();
+
+        let mut reader = Cursor::new(output);
+        let response: ProtocolResponse = read_message(&mut reader).unwrap();
+        let pack = match response {
+            ProtocolResponse::Pack { pack_size, .. } => read_pack(&mut reader, pack_size).unwrap(),
+            _ => Vec::new(),
+        };
+        (response, pack)
+    }
+
+    #[test]
+    fn test_fetch() {
+        let base = tempfile::tempdir().unwrap();
+        let server = create_repository(base.path(), "project");
+        let root = commit_content(&server, vec![], "root");
+        let tip = commit_content(&server, vec![root], "tip");
+        let main = format!("{}main", BRANCH_PREFIX);
+        Ref {
+            name: main.clone(),
+            commit_id: tip,
+        }
+        .persist_or_update(&server)
+        .unwrap();
+        let options = DaemonOptions {
+            base_path: base.path().to_path_buf(),
+            push_allow: vec![],
+            max_pack_size: DEFAULT_MAX_PACK_SIZE,
+            upgraded: Mutex::default(),
+        };
+
+        let ls_refs = ProtocolRequest::LsRefs {
+            repo: "/project".to_string(),
+        };
+        assert_eq!(
+            ProtocolResponse::Refs {
+                head: Some(main.clone()),
+                refs: vec![(main, tip)]
+            },
+            exchange(&options, &ls_refs, None).0
+        );
+
+        // A client which already has the root only receives the tip
+        let client = create_repository(base.path(), "client");
+        commit_content(&client, vec![], "root");
+        let fetch = ProtocolRequest::Fetch {
+            repo: "project".to_string(),
+            wants: vec![tip],
+            haves: vec![root],
+            max_commits: None,
+        };
+        let (_, pack) = exchange(&options, &fetch, None);
+        unpack_objects(&client, &pack).unwrap();
+        assert_eq!(
+            "tip",
+            Commit::read_from_conn_with_id(&client, tip)
+                .unwrap()
+                .message
+        );
+
+        for repo in ["missing", "../project", "project/.gitqlite"] {
+            let request = ProtocolRequest::LsRefs {
+                repo: repo.to_string(),
+            };
+            assert!(matches!(
+                exchange(&options, &request, None).0,
+                ProtocolResponse::Error { .. }
+            ));
+        }
+    }
+
+    #[test]
+    fn test_fetch_in_chunks() {
+        let base = tempfile::tempdir().unwrap();
+        let server = create_repository(base.path(), "project");
+        // root - a - merge - c
+        //     \- b -/
+        let root = commit_content(&server, vec![], "root");
+        let a = commit_content(&server, vec![root], "a");
+        let b = commit_content(&server, vec![root], "b");
+        let merge = commit_content(&server, vec![a, b], "merge");
+        let c = commit_content(&server, vec![merge], "c");
+
+        // Each chunk only needs what the client received before
+        let client = create_repository(base.path(), "client");
+        let mut haves = Vec::new();
+        let mut chunks = Vec::new();
+        loop {
+            let (pack, remaining) = pack_objects(&server, &[c], &haves, Some(2)).unwrap();
+            let header = unpack_objects(&client, &pack).unwrap();
+            haves.extend(header.refs.iter().map(|(_, id)| *id));
+            chunks.push(remaining);
+            if remaining == 0 {
+                break;
+            }
+        }
+        assert_eq!(vec![3, 1, 0], chunks);
+        for id in [root, a, b, merge, c] {
+            assert!(Commit::read_from_conn_with_id(&client, id).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_forged_pack() {
+        let base = tempfile::tempdir().unwrap();
+        let server = create_repository(base.path(), "project");
+        let client = create_repository(base.path(), "client");
+        let tip = commit_content(&client, vec![], "tip");
+        let (pack, _) = pack_objects(&client, &[tip], &[], None).unwrap();
+
+        // Swap the content of the blob, keeping its id
+        let file = tempfile::NamedTempFile::new().unwrap();
+        fs::write(file.path(), &pack).unwrap();
+        Connection::open(file.path())
+            .unwrap()
+            .execute("UPDATE Blobs SET data = CAST('forged' AS BLOB)", ())
+            .unwrap();
+        let forged = fs::read(file.path()).unwrap();
+
+        // Neither a push nor a fetch stores any of its objects
+        let main = format!("{}main", BRANCH_PREFIX);
+        let update = RefUpdate {
+            name: main.clone(),
+            old: None,
+            new: Some(tip),
+            force: false,
+        };
+        let no_hooks = Hooks::new(base.path().join("no-hooks"), base.path());
+        assert!(receive_pack(&server, &no_hooks, &[update], &forged).is_err());
+        assert!(unpack_objects(&server, &forged).is_err());
+        assert!(Ref::read_from_conn_with_name(&server, &main)
+            .unwrap()
+            .is_none());
+        for table in ["Commits", "Trees", "Blobs"] {
+            let count: i64 = server
+                .query_row(&format!("SELECT count(*) FROM {}", table), (), |row| {
+                    row.get(0)
+                })
+                .unwrap();
+            assert_eq!(0, count);
+        }
+
+        // The real objects are taken afterwards
+        unpack_objects(&server, &pack).unwrap();
+        assert!(Commit::read_from_conn_with_id(&server, tip).is_ok());
+    }
+
+    #[test]
+    fn test_message_size_limit() {
+        let line = format!("\"{}\"\n", "a".repeat(MAX_MESSAGE_SIZE as usize));
+        let result: crate::Result<String> = read_message(&mut Cursor::new(line));
+        assert!(result.is_err());
+        let result: crate::Result<String> = read_message(&mut Cursor::new("\"a\"\n"));
+        assert_eq!("a", result.unwrap());
+    }
+
+    #[test]
+    fn test_pack_size_limit() {
+        let push = ProtocolRequest::Push {
+            repo: "project".to_string(),
+            updates: vec![],
+            pack_size: 4,
+        };
+        let mut input = Vec::new();
+        write_message(&mut input, &push, Some(b"pack")).unwrap();
+        assert!(read_request(&mut Cursor::new(&input), 3).is_err());
+        let (_, pack) = read_request(&mut Cursor::new(&input), 4).unwrap();
+        assert_eq!(Some(b"pack".to_vec()), pack);
+    }
+
+    #[test]
+    fn test_push() {
+        let base = tempfile::tempdir().unwrap();
+        let server = create_repository(base.path(), "project");
+        let root = commit_content(&server, vec![], "root");
+        let topic = format!("{}topic", BRANCH_PREFIX);
+        for name in ["main", "topic"] {
+            Ref {
+                name: format!("{}{}", BRANCH_PREFIX, name),
+                commit_id: root,
+            }
+            .persist_or_update(&server)
+            .unwrap();
+        }
+
+        let client = create_repository(base.path(), "client");
+        commit_content(&client, vec![], "root");
+        let next = commit_content(&client, vec![root], "next");
+        let other = commit_content(&client, vec![], "other");
+        let (pack, _) = pack_objects(&client, &[next, other], &[root], None).unwrap();
+
+        let update = |name: &str, old, new, force| RefUpdate {
+            name: name.to_string(),
+            old,
+            new,
+            force,
+        };
+        let push = ProtocolRequest::Push {
+            repo: "project".to_string(),
+            updates: vec![
+                update(&topic, Some(root), Some(next), false),
+                update(
+                    &format!("{}main", BRANCH_PREFIX),
+                    Some(root),
+                    Some(next),
+                    false,
+                ),
+                update(
+                    &format!("{}new", BRANCH_PREFIX),
+                    Some(root),
+                    Some(next),
+                    false,
+                ),
+                update(&format!("{}side", BRANCH_PREFIX), None, Some(other), false),
+            ],
+            pack_size: pack.len() as u64,
+        };
+
+        // Pushes are refused unless the repository is allowed
+        let mut options = DaemonOptions {
+            base_path: base.path().to_path_buf(),
+            push_allow: vec![],
+            max_pack_size: DEFAULT_MAX_PACK_SIZE,
+            upgraded: Mutex::default(),
+        };
+        assert!(matches!(
+            exchange(&options, &push, Some(&pack)).0,
+            ProtocolResponse::Error { .. }
+        ));
+
+        options.push_allow = vec![glob::Pattern::new("proj*").unwrap()];
+        let (response, _) = exchange(&options, &push, Some(&pack));
+        let ProtocolResponse::Pushed { results } = response else {
+            panic!("unexpected response {:?}", response);
+        };
+        let reasons: Vec<Option<&str>> = results.iter().map(|(_, r)| r.as_deref()).collect();
+        assert_eq!(
+            vec![
+                None,
+                Some("branch is currently checked out"),
+                Some("stale info"),
+                None
+            ],
+            reasons
+        );
+        let read = |name: &str| Ref::read_from_conn_with_name(&server, name).unwrap();
+        assert_eq!(next, read(&topic).unwrap().commit_id);
+
+        // Rewinding needs force, deleting does not
+        let no_hooks = Hooks::new(base.path().join("no-hooks"), base.path());
+        let rewind = update(&topic, Some(next), Some(other), false);
+        assert_eq!(
+            Some("non-fast-forward".to_string()),
+            receive_pack(&server, &no_hooks, &[rewind], &[]).unwrap()[0].1
+        );
+        let delete = update(&topic, Some(next), None, false);
+        assert_eq!(
+            None,
+            receive_pack(&server, &no_hooks, &[delete], &[]).unwrap()[0].1
+        );
+        assert!(read(&topic).is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_push_declined() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let base = tempfile::tempdir().unwrap();
+        let server = create_repository(base.path(), "project");
+        let client = create_repository(base.path(), "client");
+        let tip = commit_content(&client, vec![], "tip");
+        let (pack, _) = pack_objects(&client, &[tip], &[], None).unwrap();
+
+        let hooks_dir = base.path().join("hooks");
+        fs::create_dir(&hooks_dir).unwrap();
+        let script = hooks_dir.join("pre-receive");
+        fs::write(&script, "#!/bin/sh\nexit 1\n").unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+        let hooks = Hooks::new(hooks_dir, base.path());
+
+        // The objects of a refused push are not kept
+        let update = RefUpdate {
+            name: format!("{}topic", BRANCH_PREFIX),
+            old: None,
+            new: Some(tip),
+            force: false,
+        };
+        let results = receive_pack(&server, &hooks, &[update], &pack).unwrap();
+        assert_eq!(Some("pre-receive hook declined"), results[0].1.as_deref());
+        assert!(Commit::read_from_conn_with_id(&server, tip).is_err());
+    }
+}
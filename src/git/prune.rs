@@ -0,0 +1,335 @@
+//! This module finds the objects of the database that nothing refers to anymore, e.g. the
+//! commits replaced by an amend or a rebase, or the blobs of files staged and then changed
+//! again, so that `gitqlite prune` can report and delete them.
+//!
+//! Objects are reachable from the refs, HEAD, the stash, the last fetch, the merge, rebase and
+//! cherry-pick in progress if any, the fetches which did not complete, the snapshots, and the
+//! index, which refers to blobs directly. Pinned objects are kept along with everything they lead to.
+
+use std::collections::HashSet;
+
+use rusqlite::Connection;
+
+use crate::repo::db::{read_object_type, ObjectType};
+
+use super::{
+    file_changes::forget_file_changes,
+    model::{
+        commit_layout, CherryPickState, Commit, CommitLayout, FetchHead, Head, Index, MergeHead,
+        RebaseState, Ref, Sha1Id, Tree, TreeEntryType,
+    },
+    pin::pinned_objects,
+    reachability::forget_reachability,
+    snapshot::snapshots,
+    transfer::all_received_commits,
+};
+
+const READ_STASH_COMMITS: &str = "SELECT commit_id FROM Stash";
+const DELETE_UNUSED_IDENTITIES: &str = "DELETE FROM Identities WHERE identity_id NOT IN (SELECT author_id FROM Commits UNION SELECT committer_id FROM Commits)";
+const HAS_CREATED_AT: &str =
+    "SELECT count(*) > 0 FROM pragma_table_info(?1) WHERE name = 'created_at'";
+
+/// [`ObjectKind`] tells which table an object is stored in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ObjectKind {
+    Commit,
+    Tree,
+    Blob,
+}
+
+impl ObjectKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ObjectKind::Commit => "commit",
+            ObjectKind::Tree => "tree",
+            ObjectKind::Blob => "blob",
+        }
+    }
+
+    pub fn parse(kind: &str) -> Option<ObjectKind> {
+        [ObjectKind::Commit, ObjectKind::Tree, ObjectKind::Blob]
+            .into_iter()
+            .find(|k| k.as_str() == kind)
+    }
+
+    /// Table and id column of the objects of this kind
+    fn table(&self) -> (&'static str, &'static str) {
+        match self {
+            ObjectKind::Commit => ("Commits", "commit_id"),
+            ObjectKind::Tree => ("Trees", "tree_id"),
+            ObjectKind::Blob => ("Blobs", "blob_id"),
+        }
+    }
+
+    /// Row size expression of the objects of this kind. Identities shared by commits are not
+    /// counted in their size.
+    fn size(&self, layout: CommitLayout) -> &'static str {
+        match (self, layout) {
+            (ObjectKind::Commit, CommitLayout::Identities) => {
+                "length(tree_id) + length(parent_ids) + length(message)"
+            }
+            (ObjectKind::Commit, CommitLayout::Inline | CommitLayout::Legacy) => "length(tree_id) + length(parent_ids) + length(author_name) + length(author_email) + length(committer_name) + length(committer_email) + length(message)",
+            (ObjectKind::Tree | ObjectKind::Blob, _) => "length(data)",
+        }
+    }
+}
+
+/// The kind of the object with the given id, None if the database does not have it
+pub fn object_kind(conn: &Connection, id: Sha1Id) -> crate::Result<Option<ObjectKind>> {
+    Ok(match read_object_type(conn, id.into())? {
+        Some(ObjectType::Commit) => Some(ObjectKind::Commit),
+        Some(ObjectType::Tree) => Some(ObjectKind::Tree),
+        Some(ObjectType::Blob) => Some(ObjectKind::Blob),
+        // Annotations go with the name of their tag, which keeps them
+        Some(ObjectType::Index | ObjectType::Head | ObjectType::Tag) | None => None,
+    })
+}
+
+/// [`UnreachableObject`] is an object no ref, state or index entry leads to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnreachableObject {
+    pub kind: ObjectKind,
+    pub id: Sha1Id,
+    pub size: u64,
+    /// Unix time the object was written, None if the database does not record it
+    pub created_at: Option<i64>,
+}
+
+/// Find the unreachable objects, commits first, then trees and blobs, the oldest first
+pub fn find_unreachable(conn: &Connection) -> crate::Result<Vec<UnreachableObject>> {
+    let reachable = reachable_objects(conn)?;
+
+    let layout = commit_layout(conn, "main")?;
+    let mut unreachable = Vec::new();
+    for kind in [ObjectKind::Commit, ObjectKind::Tree, ObjectKind::Blob] {
+        let (table, id) = kind.table();
+        let size = kind.size(layout);
+        let has_created_at: bool = conn.query_row(HAS_CREATED_AT, [table], |row| row.get(0))?;
+        let created_at = if has_created_at {
+            "min(created_at)"
+        } else {
+            "NULL"
+        };
+        // Blobs may be stored more than once, the first copy telling the age of the object
+        let query = format!(
+            "SELECT {id}, max({size}), {created_at} FROM {table} GROUP BY {id} ORDER BY 3, 1"
+        );
+        let mut stmt = conn.prepare(&query)?;
+        let rows = stmt.query_map((), |row| {
+            Ok(UnreachableObject {
+                kind,
+                id: row.get(0)?,
+                size: row.get::<_, i64>(1)? as u64,
+                created_at: row.get(2)?,
+            })
+        })?;
+        for row in rows {
+            let object = row?;
+            if !reachable.contains(&object.id) {
+                unreachable.push(object);
+            }
+        }
+    }
+    Ok(unreachable)
+}
+
+/// Delete the given objects, and the identities only the deleted commits referenced
+pub fn delete_objects(conn: &Connection, objects: &[UnreachableObject]) -> crate::Result<()> {
+    for object in objects {
+        let (table, id) = object.kind.table();
+        conn.prepare_cached(&format!("DELETE FROM {} WHERE {} = ?1", table, id))?
+            .execute([object.id])?;
+        if object.kind == ObjectKind::Commit {
+            forget_file_changes(conn, object.id)?;
+            forget_reachability(conn, object.id)?;
+        }
+    }
+    if objects
+        .iter()
+        .any(|object| object.kind == ObjectKind::Commit)
+        && commit_layout(conn, "main")? == CommitLayout::Identities
+    {
+        conn.execute(DELETE_UNUSED_IDENTITIES, ())?;
+    }
+    Ok(())
+}
+
+/// Ids of every object reachable from the refs, states and index
+fn reachable_objects(conn: &Connection) -> crate::Result<HashSet<Sha1Id>> {
+    let mut reachable = HashSet::new();
+    for entry in Index::read_from_conn(conn)?.entries {
+        reachable.insert(entry.sha);
+    }
+
+    let mut commits: Vec<Sha1Id> = Ref::read_all(conn)?
+        .into_iter()
+        .map(|r| r.commit_id)
+        .collect();
+    if let Head::Commit(id) = Head::read_from_conn(conn)? {
+        commits.push(id);
+    }
+    commits.extend(FetchHead::read_all(conn)?.iter().map(|f| f.commit_id));
+    commits.extend(MergeHead::read_from_conn(conn)?.map(|m| m.commit_id));
+    if let Some(state) = RebaseState::read_from_conn(conn)? {
+        commits.extend([state.orig_head, state.onto]);
+        commits.extend(state.todo.iter().map(|step| step.commit_id));
+        commits.extend(state.conflicted.map(|step| step.commit_id));
+        commits.extend(state.autostash);
+    }
+    if let Some(state) = CherryPickState::read_from_conn(conn)? {
+        commits.push(state.orig_head);
+        commits.extend(state.todo);
+        commits.extend(state.conflicted);
+    }
+    let mut stmt = conn.prepare(READ_STASH_COMMITS)?;
+    for id in stmt.query_map((), |row| row.get::<_, Sha1Id>(0))? {
+        commits.push(id?);
+    }
+    commits.extend(all_received_commits(conn)?);
+    for snapshot in snapshots(conn)? {
+        let (snapshot_commits, snapshot_blobs) = snapshot.state.objects();
+        commits.extend(snapshot_commits);
+        reachable.extend(snapshot_blobs);
+    }
+
+    let mut trees = Vec::new();
+    for (kind, id) in pinned_objects(conn)? {
+        match kind {
+            ObjectKind::Commit => commits.push(id),
+            ObjectKind::Tree => trees.push(id),
+            ObjectKind::Blob => {
+                reachable.insert(id);
+            }
+        }
+    }
+    while let Some(id) = commits.pop() {
+        if !reachable.insert(id) {
+            continue;
+        }
+        let commit = Commit::read_from_conn_with_id(conn, id)?;
+        trees.push(commit.tree_id);
+        commits.extend(commit.parent_ids);
+    }
+    while let Some(id) = trees.pop() {
+        if !reachable.insert(id) {
+            continue;
+        }
+        for entry in Tree::read_from_conn_with_id(conn, id)?.entries {
+            match entry.type_ {
+                TreeEntryType::Tree => trees.push(entry.id),
+                TreeEntryType::Blob => {
+                    reachable.insert(entry.id);
+                }
+            }
+        }
+    }
+    Ok(reachable)
+}
+
+/// Format the age of an object written at `created_at` for humans, e.g. `3 days`
+pub fn format_age(created_at: i64, now: i64) -> String {
+    const UNITS: [(i64, &str); 5] = [
+        (365 * 24 * 3600, "year"),
+        (30 * 24 * 3600, "month"),
+        (24 * 3600, "day"),
+        (3600, "hour"),
+        (60, "minute"),
+    ];
+    let seconds = (now - created_at).max(0);
+    let (count, unit) = UNITS
+        .iter()
+        .find(|(length, _)| seconds >= *length)
+        .map(|(length, unit)| (seconds / length, *unit))
+        .unwrap_or((seconds, "second"));
+    if count == 1 {
+        format!("1 {}", unit)
+    } else {
+        format!("{} {}s", count, unit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::{
+        constants::BRANCH_PREFIX,
+        file_changes::CREATE_FILE_CHANGES_TABLE,
+        model::{
+            CREATE_CHERRY_PICK_STATE_TABLE, CREATE_FETCH_HEAD_TABLE, CREATE_HEAD_TABLE,
+            CREATE_INDEX_TABLE, CREATE_MERGE_HEAD_TABLE, CREATE_REBASE_STATE_TABLE,
+            CREATE_REF_TABLE, CREATE_STASH_TABLE,
+        },
+        pin::{pin, unpin, CREATE_PINNED_OBJECTS_TABLE},
+        reachability::CREATE_COMMIT_GENERATIONS_TABLE,
+        snapshot::CREATE_SNAPSHOTS_TABLE,
+        test_utils::{commit_files, object_database},
+        transfer::CREATE_TRANSFER_STATE_TABLE,
+    };
+
+    fn commit(conn: &Connection, content: &str, parent_ids: Vec<Sha1Id>) -> Sha1Id {
+        commit_files(conn, parent_ids, &[("dir/file", content)], content)
+    }
+
+    #[test]
+    fn test_find_unreachable() {
+        let conn = object_database(&[
+            CREATE_INDEX_TABLE,
+            CREATE_HEAD_TABLE,
+            CREATE_REF_TABLE,
+            CREATE_MERGE_HEAD_TABLE,
+            CREATE_REBASE_STATE_TABLE,
+            CREATE_CHERRY_PICK_STATE_TABLE,
+            CREATE_STASH_TABLE,
+            CREATE_FETCH_HEAD_TABLE,
+            CREATE_COMMIT_GENERATIONS_TABLE,
+            CREATE_FILE_CHANGES_TABLE,
+            CREATE_PINNED_OBJECTS_TABLE,
+            CREATE_SNAPSHOTS_TABLE,
+            CREATE_TRANSFER_STATE_TABLE,
+        ]);
+        let main = format!("{}main", BRANCH_PREFIX);
+        Head::Branch(main.clone()).persist(&conn).unwrap();
+
+        let first = commit(&conn, "first", vec![]);
+        let amended = commit(&conn, "amended", vec![]);
+        let second = commit(&conn, "second", vec![first]);
+        Ref {
+            name: main,
+            commit_id: second,
+        }
+        .persist_or_update(&conn)
+        .unwrap();
+
+        let unreachable = find_unreachable(&conn).unwrap();
+        let kinds: Vec<_> = unreachable.iter().map(|o| o.kind).collect();
+        // The amended commit, its tree, the tree of its directory and its blob
+        assert_eq!(
+            vec![
+                ObjectKind::Commit,
+                ObjectKind::Tree,
+                ObjectKind::Tree,
+                ObjectKind::Blob
+            ],
+            kinds
+        );
+        assert_eq!(amended, unreachable[0].id);
+        assert_eq!(7, unreachable[3].size);
+        assert!(unreachable[0].created_at.is_some());
+
+        // Pinning the amended commit keeps its tree and blob too
+        pin(&conn, ObjectKind::Commit, amended).unwrap();
+        assert!(find_unreachable(&conn).unwrap().is_empty());
+        unpin(&conn, amended).unwrap();
+
+        delete_objects(&conn, &unreachable).unwrap();
+        assert!(find_unreachable(&conn).unwrap().is_empty());
+        assert!(Commit::read_from_conn_with_id(&conn, second).is_ok());
+    }
+
+    #[test]
+    fn test_format_age() {
+        assert_eq!("5 seconds", format_age(95, 100));
+        assert_eq!("1 minute", format_age(0, 90));
+        assert_eq!("2 days", format_age(0, 2 * 24 * 3600 + 5));
+    }
+}
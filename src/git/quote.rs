@@ -0,0 +1,139 @@
+//! This module writes paths in command output the way git does. A path containing control
+//! characters, double quotes or backslashes is C-quoted: it is enclosed in double quotes,
+//! with `\t`, `\n`, `\"`, `\\` and the like escaped, and other unusual bytes written as octal
+//! `\ooo`. Unless `core.quotePath` is false, bytes outside ASCII are quoted as well, so that
+//! `été` prints as `"\303\251t\303\251"`. With `-z`, commands separate paths with NUL
+//! instead and write them as they are.
+
+use std::borrow::Cow;
+
+use crate::repo::config::{ConfigSource, GitConfig};
+
+/// [`PathQuoting`] tells how paths are written in the output of a command
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PathQuoting {
+    /// Paths with special characters or bytes outside ASCII are quoted
+    #[default]
+    NonAscii,
+    /// Only paths with special characters are quoted, as with `core.quotePath=false`
+    Special,
+    /// Paths are written as they are and terminated with NUL, as with `-z`
+    Raw,
+}
+
+impl PathQuoting {
+    /// The quoting of paths from `core.quotePath`, unless `null_terminated` output is asked for
+    pub fn from_config(config: &GitConfig, null_terminated: bool) -> crate::Result<PathQuoting> {
+        if null_terminated {
+            return Ok(PathQuoting::Raw);
+        }
+        let quote_path = config
+            .get_bool("core.quotePath", ConfigSource::All)?
+            .unwrap_or(true);
+        Ok(if quote_path {
+            PathQuoting::NonAscii
+        } else {
+            PathQuoting::Special
+        })
+    }
+
+    /// The path as it is written in the output
+    pub fn quote(self, path: &str) -> Cow<'_, str> {
+        let needs_quoting = |byte: &u8| match self {
+            PathQuoting::Raw => false,
+            PathQuoting::Special => escape(*byte, false).is_some(),
+            PathQuoting::NonAscii => escape(*byte, true).is_some(),
+        };
+        if !path.as_bytes().iter().any(needs_quoting) {
+            return Cow::Borrowed(path);
+        }
+
+        let quote_non_ascii = self == PathQuoting::NonAscii;
+        let mut quoted = String::from("\"");
+        let mut rest = path.as_bytes();
+        while let Some((&byte, tail)) = rest.split_first() {
+            match escape(byte, quote_non_ascii) {
+                Some(escaped) => {
+                    quoted.push_str(&escaped);
+                    rest = tail;
+                }
+                None => {
+                    // Copy the run of bytes which need no escaping, whole characters at a time
+                    let run = rest
+                        .iter()
+                        .position(|byte| escape(*byte, quote_non_ascii).is_some())
+                        .unwrap_or(rest.len());
+                    quoted.push_str(&String::from_utf8_lossy(&rest[..run]));
+                    rest = &rest[run..];
+                }
+            }
+        }
+        quoted.push('"');
+        Cow::Owned(quoted)
+    }
+
+    /// The path following a prefix like the `a/` of diff headers, quoted as a whole
+    pub fn quote_prefixed(self, prefix: &str, path: &str) -> String {
+        self.quote(&format!("{}{}", prefix, path)).into_owned()
+    }
+
+    /// What ends a path in a list of paths
+    pub fn terminator(self) -> char {
+        match self {
+            PathQuoting::Raw => '\0',
+            _ => '\n',
+        }
+    }
+}
+
+/// How a byte of a quoted path is written, None if as it is
+fn escape(byte: u8, quote_non_ascii: bool) -> Option<String> {
+    let escaped = match byte {
+        b'\x07' => "\\a",
+        b'\x08' => "\\b",
+        b'\t' => "\\t",
+        b'\n' => "\\n",
+        b'\x0b' => "\\v",
+        b'\x0c' => "\\f",
+        b'\r' => "\\r",
+        b'"' => "\\\"",
+        b'\\' => "\\\\",
+        0..=0x1f | 0x7f => return Some(format!("\\{:03o}", byte)),
+        0x80.. if quote_non_ascii => return Some(format!("\\{:03o}", byte)),
+        _ => return None,
+    };
+    Some(escaped.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quote_path() {
+        let quote = |quoting: PathQuoting, path| quoting.quote(path).into_owned();
+
+        assert_eq!(
+            "dir/plain file.txt",
+            quote(PathQuoting::NonAscii, "dir/plain file.txt")
+        );
+        assert_eq!(
+            "\"tab\\there\\nand \\\"quotes\\\" \\\\ \\001\"",
+            quote(PathQuoting::NonAscii, "tab\there\nand \"quotes\" \\ \x01")
+        );
+        assert_eq!(
+            "\"\\303\\251t\\303\\251\"",
+            quote(PathQuoting::NonAscii, "été")
+        );
+        assert_eq!("été", quote(PathQuoting::Special, "été"));
+        assert_eq!("\"été\\t\"", quote(PathQuoting::Special, "été\t"));
+        assert_eq!("été\t\"", quote(PathQuoting::Raw, "été\t\""));
+
+        assert_eq!(
+            "\"a/\\303\\251\"",
+            PathQuoting::NonAscii.quote_prefixed("a/", "é")
+        );
+        assert_eq!("a/x", PathQuoting::NonAscii.quote_prefixed("a/", "x"));
+        assert_eq!('\0', PathQuoting::Raw.terminator());
+    }
+}
@@ -0,0 +1,313 @@
+//! This module compares two versions of a series of commits, e.g. a branch before and after a
+//! rebase, as `git range-diff` does.
+//!
+//! Each commit is turned into the text of its patch: the commit message followed by the hunks
+//! of the files it changes, leaving out the blob hashes and line numbers that a rebase changes
+//! without changing the patch. Commits of the two ranges are then paired by how few lines
+//! differ between their patches, and each pair is shown with the diff of the two patches.
+
+use rusqlite::Connection;
+
+use super::{
+    cache::ObjectCache,
+    diff::{diff_commit_cached, diff_lines, make_hunks, ChangeStatus, LineEdit},
+    model::{Commit, Sha1Id},
+    patchid::commit_patch_id,
+    rebase::{short_id, summary},
+};
+
+/// Percentage of the combined length of two patches that the diff of the patches must stay
+/// under for the commits to be paired
+pub const DEFAULT_CREATION_FACTOR: usize = 60;
+/// Number of context lines around changes in the diff of two patches
+const CONTEXT_LINES: usize = 3;
+/// Indentation of the diff of two patches under the line of their pair
+const INDENT: &str = "    ";
+
+/// [`RangeCommit`] is a commit of a range along with the text of its patch
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct RangeCommit {
+    id: Sha1Id,
+    subject: String,
+    patch: Vec<String>,
+    patch_id: Option<Sha1Id>,
+}
+
+impl RangeCommit {
+    fn read(
+        conn: &Connection,
+        cache: &mut ObjectCache,
+        commit: &Commit<Sha1Id>,
+    ) -> crate::Result<RangeCommit> {
+        let changes = diff_commit_cached(conn, cache, commit)?;
+
+        let mut patch = vec![" ## Commit message ##".to_string()];
+        patch.extend(
+            commit
+                .message
+                .trim_end()
+                .lines()
+                .map(|line| format!("{}{}", INDENT, line)),
+        );
+        for change in &changes {
+            let header = match &change.status {
+                ChangeStatus::Added => format!(" ## {} (new) ##", change.name),
+                ChangeStatus::Deleted => format!(" ## {} (deleted) ##", change.name),
+                ChangeStatus::Modified => format!(" ## {} ##", change.name),
+                ChangeStatus::Renamed { from, .. } => {
+                    format!(" ## {} (renamed from {}) ##", change.name, from)
+                }
+                ChangeStatus::Copied { from, .. } => {
+                    format!(" ## {} (copied from {}) ##", change.name, from)
+                }
+            };
+            patch.push(String::new());
+            patch.push(header);
+            if change.is_binary() {
+                patch.push(" Binary files differ".to_string());
+                continue;
+            }
+            let (old, new) = change.lines();
+            let edits = diff_lines(&old, &new);
+            for hunk in make_hunks(&edits, CONTEXT_LINES) {
+                patch.push(" @@".to_string());
+                for edit in &hunk.edits {
+                    let (prefix, line) = match *edit {
+                        LineEdit::Equal(x, _) => (' ', old[x]),
+                        LineEdit::Delete(x) => ('-', old[x]),
+                        LineEdit::Insert(y) => ('+', new[y]),
+                    };
+                    let line = String::from_utf8_lossy(line);
+                    patch.push(format!("{}{}", prefix, line.trim_end_matches('\n')));
+                }
+            }
+        }
+
+        Ok(RangeCommit {
+            id: commit.commit_id,
+            subject: summary(&commit.message).to_string(),
+            patch,
+            patch_id: commit_patch_id(conn, commit.commit_id, false)?,
+        })
+    }
+
+    /// Number of lines of the patch
+    fn size(&self) -> usize {
+        self.patch.len()
+    }
+}
+
+/// Number of lines that differ between two patches
+fn patch_distance(old: &[String], new: &[String]) -> usize {
+    diff_lines(old, new)
+        .iter()
+        .filter(|edit| !matches!(edit, LineEdit::Equal(..)))
+        .count()
+}
+
+/// Pair the commits of the old range with those of the new range, returning for each old
+/// commit the index of its counterpart, if any.
+///
+/// Pairs are picked greedily, the closest patches first, starting with the commits making the
+/// same change as told by their patch ids. Other commits are only paired if the diff of
+/// their patches is smaller than `creation_factor` percent of their combined size, and are
+/// reported as removed and added otherwise.
+fn pair_commits(
+    old: &[RangeCommit],
+    new: &[RangeCommit],
+    creation_factor: usize,
+) -> Vec<Option<usize>> {
+    let mut candidates = Vec::with_capacity(old.len() * new.len());
+    for (i, a) in old.iter().enumerate() {
+        for (j, b) in new.iter().enumerate() {
+            let distance = if a.patch_id.is_some() && a.patch_id == b.patch_id {
+                0
+            } else {
+                patch_distance(&a.patch, &b.patch)
+            };
+            if distance == 0 || distance * 100 < creation_factor * (a.size() + b.size()) {
+                candidates.push((distance, i, j));
+            }
+        }
+    }
+    candidates.sort();
+
+    let mut pairs = vec![None; old.len()];
+    let mut paired = vec![false; new.len()];
+    for (_, i, j) in candidates {
+        if pairs[i].is_none() && !paired[j] {
+            pairs[i] = Some(j);
+            paired[j] = true;
+        }
+    }
+    pairs
+}
+
+/// Compare the commits of two ranges, each given parents first. Merge commits are left out.
+/// Unless `no_patch` is set, the diff of the patches of each pair that differs is shown.
+pub fn range_diff(
+    conn: &Connection,
+    old: &[Sha1Id],
+    new: &[Sha1Id],
+    creation_factor: usize,
+    no_patch: bool,
+) -> crate::Result<String> {
+    // The two ranges usually share most of their trees
+    let mut cache = ObjectCache::default();
+    let mut read = |ids: &[Sha1Id]| -> crate::Result<Vec<RangeCommit>> {
+        let mut commits = Vec::with_capacity(ids.len());
+        for id in ids {
+            let commit = cache.read_commit(conn, *id)?;
+            if commit.parent_ids.len() <= 1 {
+                commits.push(RangeCommit::read(conn, &mut cache, &commit)?);
+            }
+        }
+        Ok(commits)
+    };
+    let (old, new) = (read(old)?, read(new)?);
+    let pairs = pair_commits(&old, &new, creation_factor);
+    let mut counterpart = vec![None; new.len()];
+    for (i, j) in pairs.iter().enumerate() {
+        if let Some(j) = j {
+            counterpart[*j] = Some(i);
+        }
+    }
+
+    let width = old.len().max(new.len()).to_string().len();
+    let side = |index: Option<usize>, commits: &[RangeCommit]| match index {
+        Some(index) => format!(
+            "{:>width$}:  {}",
+            index + 1,
+            short_id(commits[index].id),
+            width = width
+        ),
+        None => format!("{:>width$}:  -------", "-", width = width),
+    };
+    let mut out = String::new();
+    let mut show = |i: Option<usize>, j: Option<usize>| {
+        let (a, b) = (i.map(|i| &old[i]), j.map(|j| &new[j]));
+        let marker = match (a, b) {
+            (Some(a), Some(b)) if a.patch == b.patch => '=',
+            (Some(_), Some(_)) => '!',
+            (Some(_), None) => '<',
+            _ => '>',
+        };
+        let subject = b.or(a).map(|c| c.subject.as_str()).unwrap_or_default();
+        out.push_str(&format!(
+            "{} {} {} {}\n",
+            side(i, &old),
+            marker,
+            side(j, &new),
+            subject
+        ));
+        if let (Some(a), Some(b), '!', false) = (a, b, marker, no_patch) {
+            out.push_str(&format_interdiff(&a.patch, &b.patch));
+        }
+    };
+
+    // Walk the new range in order, showing each removed commit of the old range as soon as
+    // the commits before it have been shown
+    let mut shown = vec![false; old.len()];
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        while i < old.len() && shown[i] {
+            i += 1;
+        }
+        if i < old.len() && pairs[i].is_none() {
+            show(Some(i), None);
+            shown[i] = true;
+            i += 1;
+            continue;
+        }
+        while j < new.len() && counterpart[j].is_none() {
+            show(None, Some(j));
+            j += 1;
+        }
+        if j < new.len() {
+            let paired = counterpart[j];
+            show(paired, Some(j));
+            if let Some(paired) = paired {
+                shown[paired] = true;
+            }
+            j += 1;
+        }
+    }
+    Ok(out)
+}
+
+/// Render the diff of two patches, indented under the line of their pair
+fn format_interdiff(old: &[String], new: &[String]) -> String {
+    let edits = diff_lines(old, new);
+    let mut out = String::new();
+    for hunk in make_hunks(&edits, CONTEXT_LINES) {
+        out.push_str(&format!("{}@@\n", INDENT));
+        for edit in &hunk.edits {
+            let (prefix, line) = match *edit {
+                LineEdit::Equal(x, _) => (' ', &old[x]),
+                LineEdit::Delete(x) => ('-', &old[x]),
+                LineEdit::Insert(y) => ('+', &new[y]),
+            };
+            out.push_str(&format!("{}{}{}\n", INDENT, prefix, line));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::test_utils::{commit_change, object_database};
+
+    fn commit(conn: &Connection, parent: Option<Sha1Id>, file: &str, message: &str) -> Sha1Id {
+        let content: String = (1..=5)
+            .map(|n| {
+                format!(
+                    "{} {}
+",
+                    file, n
+                )
+            })
+            .collect();
+        commit_change(conn, parent, file, &content, message)
+    }
+
+    #[test]
+    fn test_range_diff() {
+        let conn = object_database(&[]);
+        let base = commit(&conn, None, "base", "base");
+        let upstream = commit(&conn, Some(base), "upstream", "upstream");
+
+        // The old series adds three files, the new one is rebased onto upstream, drops the
+        // second commit, rewords the third and adds a fourth
+        let a1 = commit(&conn, Some(base), "a", "add a");
+        let a2 = commit(&conn, Some(a1), "b", "add b");
+        let a3 = commit(&conn, Some(a2), "c", "add c");
+        let b1 = commit(&conn, Some(upstream), "a", "add a");
+        let b2 = commit(&conn, Some(b1), "c", "add c");
+        let b3 = commit(&conn, Some(b2), "d", "add d");
+
+        let old = [a1, a2, a3];
+        let new = [b1, b2, b3];
+        let out = range_diff(&conn, &old, &new, DEFAULT_CREATION_FACTOR, true).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(
+            vec![
+                format!("1:  {} = 1:  {} add a", short_id(a1), short_id(b1)),
+                format!("2:  {} < -:  ------- add b", short_id(a2)),
+                format!("3:  {} = 2:  {} add c", short_id(a3), short_id(b2)),
+                format!("-:  ------- > 3:  {} add d", short_id(b3)),
+            ],
+            lines
+        );
+
+        // A reworded commit is still paired, with the diff of the messages
+        let c1 = commit(&conn, Some(upstream), "a", "add a\n\nWith a body");
+        let out = range_diff(&conn, &[a1], &[c1], DEFAULT_CREATION_FACTOR, false).unwrap();
+        assert!(out.starts_with(&format!(
+            "1:  {} ! 1:  {} add a\n",
+            short_id(a1),
+            short_id(c1)
+        )));
+        assert!(out.contains("    +    With a body\n"));
+    }
+}
@@ -0,0 +1,219 @@
+//! This module maintains the generation numbers of the commits in the `CommitGenerations`
+//! table: a root commit has generation 1, and any other commit one more than the highest
+//! generation of its parents. Commits can only be reached from commits of a higher generation,
+//! so the history one side of a fetch or push has and the other lacks is found by walking the
+//! graph down from the highest generation, and the walk stops as soon as every commit left to
+//! visit is known to the other side instead of going all the way to the roots.
+//!
+//! Generations are recorded as commits are written or received, one row per commit. The
+//! callers walk the whole graph instead when a commit is not recorded yet.
+
+use std::collections::{hash_map::Entry, BinaryHeap, HashMap};
+
+use rusqlite::{Connection, OptionalExtension};
+
+use super::model::{Commit, Sha1Id};
+
+pub const CREATE_COMMIT_GENERATIONS_TABLE: &str = "CREATE TABLE CommitGenerations (commit_id BLOB PRIMARY KEY, generation INTEGER NOT NULL) WITHOUT ROWID;";
+const READ_GENERATION: &str = "SELECT generation FROM CommitGenerations WHERE commit_id = ?1";
+const INSERT_GENERATION: &str =
+    "INSERT OR IGNORE INTO CommitGenerations (commit_id, generation) VALUES (?1, ?2);";
+const DELETE_GENERATION: &str = "DELETE FROM CommitGenerations WHERE commit_id = ?1";
+const READ_ALL_COMMIT_IDS: &str = "SELECT commit_id FROM Commits";
+
+/// Flags of the commits visited by [`missing_commits`]
+const WANTED: u8 = 1;
+const HAD: u8 = 2;
+
+fn generation(conn: &Connection, id: Sha1Id) -> crate::Result<Option<i64>> {
+    Ok(conn
+        .prepare_cached(READ_GENERATION)?
+        .query_row([id], |row| row.get(0))
+        .optional()?)
+}
+
+/// Record the generation of the given commits. Ancestors that are not recorded yet are
+/// recorded first.
+pub fn record_reachability(conn: &Connection, ids: &[Sha1Id]) -> crate::Result<()> {
+    // Depth-first walk recording a commit once all of its parents are recorded
+    let mut stack: Vec<(Sha1Id, Option<Vec<Sha1Id>>)> = ids.iter().map(|id| (*id, None)).collect();
+    while let Some((id, parents)) = stack.pop() {
+        if let Some(parents) = parents {
+            let mut highest = 0;
+            for parent in parents {
+                highest = highest.max(generation(conn, parent)?.unwrap_or(0));
+            }
+            conn.prepare_cached(INSERT_GENERATION)?
+                .execute((id, highest + 1))?;
+            continue;
+        }
+        if generation(conn, id)?.is_some() {
+            continue;
+        }
+        let parents = Commit::read_from_conn_with_id(conn, id)?.parent_ids;
+        let mut missing = Vec::new();
+        for parent in &parents {
+            if generation(conn, *parent)?.is_none() {
+                missing.push((*parent, None));
+            }
+        }
+        stack.push((id, Some(parents)));
+        stack.extend(missing);
+    }
+    Ok(())
+}
+
+/// Record the generation of every commit of the repository missing from the table
+pub fn record_all_reachability(conn: &Connection) -> crate::Result<()> {
+    let ids = conn
+        .prepare(READ_ALL_COMMIT_IDS)?
+        .query_map((), |row| row.get(0))?
+        .collect::<Result<Vec<Sha1Id>, _>>()?;
+    record_reachability(conn, &ids)
+}
+
+/// Forget the generation of a commit that is being deleted
+pub fn forget_reachability(conn: &Connection, commit_id: Sha1Id) -> crate::Result<()> {
+    conn.prepare_cached(DELETE_GENERATION)?
+        .execute([commit_id])?;
+    Ok(())
+}
+
+/// The commits reachable from `wants` but not from `haves`, in no particular order. None if
+/// one of the commits visited is not recorded, in which case the history must be walked.
+pub fn missing_commits(
+    conn: &Connection,
+    wants: &[Sha1Id],
+    haves: &[Sha1Id],
+) -> crate::Result<Option<Vec<Sha1Id>>> {
+    let mut flags: HashMap<Sha1Id, u8> = HashMap::new();
+    let mut queue = BinaryHeap::new();
+    // Number of commits in the queue not reachable from the haves so far
+    let mut pending = 0;
+    for (ids, flag) in [(wants, WANTED), (haves, HAD)] {
+        for id in ids {
+            let Some(generation) = generation(conn, *id)? else {
+                return Ok(None);
+            };
+            let flags = flags.entry(*id).or_insert_with(|| {
+                queue.push((generation, *id));
+                pending += 1;
+                0
+            });
+            if *flags & HAD == 0 && flag == HAD {
+                pending -= 1;
+            }
+            *flags |= flag;
+        }
+    }
+
+    // A commit leaves the queue after all of its children, and so with its final flags
+    let mut missing = Vec::new();
+    while pending > 0 {
+        let Some((_, id)) = queue.pop() else {
+            break;
+        };
+        let flag = flags[&id];
+        if flag & HAD == 0 {
+            missing.push(id);
+            pending -= 1;
+        }
+        for parent in Commit::read_from_conn_with_id(conn, id)?.parent_ids {
+            match flags.entry(parent) {
+                Entry::Vacant(entry) => {
+                    let Some(generation) = generation(conn, parent)? else {
+                        return Ok(None);
+                    };
+                    queue.push((generation, parent));
+                    if flag & HAD == 0 {
+                        pending += 1;
+                    }
+                    entry.insert(flag);
+                }
+                Entry::Occupied(mut entry) => {
+                    if *entry.get() & HAD == 0 && flag & HAD != 0 {
+                        pending -= 1;
+                    }
+                    *entry.get_mut() |= flag;
+                }
+            }
+        }
+    }
+    Ok(Some(missing))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::git::test_utils::{make_commit, object_database};
+
+    #[test]
+    fn test_missing_commits() {
+        let conn = object_database(&[CREATE_COMMIT_GENERATIONS_TABLE]);
+
+        // root - a - merge
+        //     \- b -/
+        let root = make_commit(&conn, vec![], "root");
+        let a = make_commit(&conn, vec![root], "a");
+        let b = make_commit(&conn, vec![root], "b");
+        let merge = make_commit(&conn, vec![a, b], "merge");
+        assert_eq!(None, missing_commits(&conn, &[merge], &[]).unwrap());
+
+        // Recording the merge records its ancestors too
+        record_reachability(&conn, &[merge]).unwrap();
+        let missing = |wants: &[Sha1Id], haves: &[Sha1Id]| -> HashSet<Sha1Id> {
+            missing_commits(&conn, wants, haves)
+                .unwrap()
+                .unwrap()
+                .into_iter()
+                .collect()
+        };
+        assert_eq!(HashSet::from([root, a, b, merge]), missing(&[merge], &[]));
+        assert_eq!(HashSet::from([b, merge]), missing(&[merge], &[a]));
+        assert_eq!(HashSet::new(), missing(&[a, b], &[merge]));
+
+        let c = make_commit(&conn, vec![merge], "c");
+        forget_reachability(&conn, merge).unwrap();
+        record_all_reachability(&conn).unwrap();
+        assert_eq!(HashSet::from([c]), missing(&[c], &[merge]));
+        assert_eq!(HashSet::from([a, merge, c]), missing(&[c], &[b]));
+
+        // One row per commit
+        let rows: i64 = conn
+            .query_row("SELECT count(*) FROM CommitGenerations", (), |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(5, rows);
+    }
+
+    #[test]
+    fn test_missing_commits_of_long_history() {
+        let conn = object_database(&[CREATE_COMMIT_GENERATIONS_TABLE]);
+
+        // A long history and a short branch forked off its middle
+        let mut main = vec![make_commit(&conn, vec![], "0")];
+        for i in 1..200 {
+            main.push(make_commit(&conn, vec![main[i - 1]], &i.to_string()));
+        }
+        let topic = make_commit(&conn, vec![main[100]], "topic");
+        record_all_reachability(&conn).unwrap();
+
+        let missing = |wants: &[Sha1Id], haves: &[Sha1Id]| -> HashSet<Sha1Id> {
+            missing_commits(&conn, wants, haves)
+                .unwrap()
+                .unwrap()
+                .into_iter()
+                .collect()
+        };
+        assert_eq!(HashSet::from([topic]), missing(&[topic], &[main[199]]));
+        assert_eq!(
+            main[101..].iter().copied().collect::<HashSet<_>>(),
+            missing(&[main[199]], &[topic])
+        );
+        assert_eq!(200, missing(&[main[199]], &[]).len());
+        assert_eq!(HashSet::new(), missing(&[main[50]], &[topic]));
+    }
+}
@@ -0,0 +1,601 @@
+//! This module implements the rebase sequencer: it replays a todo list of commits on top of
+//! a new base one step at a time. The progress is recorded in the [`RebaseState`] table so
+//! that a rebase stopped by a conflict or an `edit` step can be resumed with `--continue`.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::anyhow;
+use rusqlite::Connection;
+
+use crate::repo::config::GitConfig;
+
+use super::{
+    cmds::commit::commit_tree,
+    constants::{COMMIT_MESSAGE_FILE, GITQLITE_DIRECTORY_PREFIX},
+    editor::{edit_text, strip_comments},
+    merge::{ancestors, apply_merge, pick_commit, MergeOutcome},
+    model::{Commit, Head, Index, RebaseAction, RebaseState, RebaseStep, Ref, Sha1Id},
+    patchid::{commit_patch_id, patch_ids_between},
+    progress::Progress,
+    revision::resolve_revision,
+    stash::apply_autostash,
+    tree::{flatten_tree, index_view, write_tree},
+    worktree::reset_hard,
+};
+
+/// File under the gitqlite directory holding the todo list while the user edits it
+const TODO_FILE: &str = "git-rebase-todo";
+
+const TODO_HELP: &str = "
+# Commands:
+# p, pick <commit> = use commit
+# r, reword <commit> = use commit, but edit the commit message
+# e, edit <commit> = use commit, but stop for amending
+# s, squash <commit> = use commit, but meld into previous commit
+# f, fixup <commit> = like \"squash\", but discard this commit's log message
+# d, drop <commit> = remove commit
+#
+# These lines can be re-ordered; they are executed from top to bottom.
+#
+# If you remove a line here THAT COMMIT WILL BE LOST.
+#
+# However, if you remove everything, the rebase will be aborted.
+";
+
+/// Result of carrying out one step of the todo list
+enum StepResult {
+    /// The step was carried out, move on to the next one
+    Applied,
+    /// The step could not be applied cleanly and needs the user to resolve it
+    Conflicted,
+    /// The step asks the rebase to stop (e.g., `edit`)
+    Stopped,
+}
+
+/// Commits reachable from `head` but not from `upstream` following first parents, oldest first.
+/// Merge commits are left out, as the changes they bring in are not replayed.
+pub fn commits_to_rebase(
+    conn: &Connection,
+    upstream: Sha1Id,
+    head: Sha1Id,
+) -> crate::Result<Vec<Sha1Id>> {
+    let excluded = ancestors(conn, upstream)?;
+
+    let mut commits = Vec::new();
+    let mut current = Some(head);
+    while let Some(id) = current {
+        if excluded.contains(&id) {
+            break;
+        }
+        let commit = Commit::read_from_conn_with_id(conn, id)?;
+        if commit.parent_ids.len() <= 1 {
+            commits.push(id);
+        }
+        current = commit.parent_ids.first().copied();
+    }
+
+    commits.reverse();
+    Ok(commits)
+}
+
+/// Split the commits to rebase into those to replay and those whose change `upstream`
+/// already has, e.g. because they were cherry-picked there
+pub fn skip_applied(
+    conn: &Connection,
+    upstream: Sha1Id,
+    head: Sha1Id,
+    commits: Vec<Sha1Id>,
+) -> crate::Result<(Vec<Sha1Id>, Vec<Sha1Id>)> {
+    let applied = patch_ids_between(conn, head, upstream)?;
+    if applied.is_empty() {
+        return Ok((commits, Vec::new()));
+    }
+
+    let (mut kept, mut skipped) = (Vec::new(), Vec::new());
+    for id in commits {
+        if commit_patch_id(conn, id, false)?.is_some_and(|patch_id| applied.contains(&patch_id)) {
+            skipped.push(id);
+        } else {
+            kept.push(id);
+        }
+    }
+    Ok((kept, skipped))
+}
+
+/// Render the todo list presented to the user by `rebase -i`
+pub fn format_todo(conn: &Connection, steps: &[RebaseStep], title: &str) -> crate::Result<String> {
+    let mut text = String::new();
+    for step in steps {
+        let commit = Commit::read_from_conn_with_id(conn, step.commit_id)?;
+        text.push_str(&format!(
+            "{} {} {}\n",
+            action_name(step.action),
+            short_id(step.commit_id),
+            summary(&commit.message)
+        ));
+    }
+    text.push_str(&format!("\n# {} ({} commands)\n", title, steps.len()));
+    text.push_str(TODO_HELP);
+    Ok(text)
+}
+
+/// Parse a todo list edited by the user. Comments and blank lines are ignored.
+pub fn parse_todo(conn: &Connection, text: &str) -> crate::Result<Vec<RebaseStep>> {
+    let mut steps = Vec::new();
+
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut parts = line.split_whitespace();
+        let command = parts.next().unwrap_or_default();
+        let action = match command {
+            "p" | "pick" => RebaseAction::Pick,
+            "r" | "reword" => RebaseAction::Reword,
+            "e" | "edit" => RebaseAction::Edit,
+            "s" | "squash" => RebaseAction::Squash,
+            "f" | "fixup" => RebaseAction::Fixup,
+            "d" | "drop" => RebaseAction::Drop,
+            _ => {
+                return Err(anyhow!(
+                    "error: invalid command '{}' in line: {}",
+                    command,
+                    line
+                ))
+            }
+        };
+        let commit = parts
+            .next()
+            .ok_or_else(|| anyhow!("error: missing commit in line: {}", line))?;
+        let commit_id = resolve_revision(conn, commit)?;
+
+        steps.push(RebaseStep { action, commit_id });
+    }
+
+    let first = steps.iter().find(|step| step.action != RebaseAction::Drop);
+    if let Some(step) = first {
+        if matches!(step.action, RebaseAction::Squash | RebaseAction::Fixup) {
+            return Err(anyhow!(
+                "error: cannot '{}' without a previous commit",
+                action_name(step.action)
+            ));
+        }
+    }
+
+    Ok(steps)
+}
+
+/// Carry out the remaining steps of the rebase until it is done or needs to stop
+pub fn run(
+    conn: &Connection,
+    repo_root: &Path,
+    config: &GitConfig,
+    mut state: RebaseState,
+    progress: &mut Progress,
+) -> crate::Result<()> {
+    while !state.todo.is_empty() {
+        let step = state.todo.remove(0);
+        state.persist(conn)?;
+
+        match apply_step(conn, repo_root, config, &step, progress)? {
+            StepResult::Applied => {}
+            StepResult::Conflicted => {
+                let commit = Commit::read_from_conn_with_id(conn, step.commit_id)?;
+                state.conflicted = Some(step.clone());
+                state.persist(conn)?;
+                return Err(anyhow!(
+                    "error: could not apply {}... {}\nResolve all conflicts manually, mark them as resolved with \"gitqlite add\", then run \"gitqlite rebase --continue\".\nYou can instead skip this commit with \"gitqlite rebase --skip\".\nTo abort and get back to the state before \"gitqlite rebase\", run \"gitqlite rebase --abort\".",
+                    short_id(step.commit_id),
+                    summary(&commit.message)
+                ));
+            }
+            StepResult::Stopped => {
+                let commit = Commit::read_from_conn_with_id(conn, step.commit_id)?;
+                println!(
+                    "Stopped at {}... {}",
+                    short_id(step.commit_id),
+                    summary(&commit.message)
+                );
+                println!("You can amend the commit now by staging changes with \"gitqlite add\".");
+                println!();
+                println!("Once you are satisfied with your changes, run");
+                println!();
+                println!("  gitqlite rebase --continue");
+                return Ok(());
+            }
+        }
+    }
+
+    finish(conn, repo_root, &state, progress)
+}
+
+/// Resume a stopped rebase: commit the resolution of a conflicted step, or fold staged changes
+/// into HEAD after an `edit` step, then carry out the remaining steps.
+pub fn resume(
+    conn: &Connection,
+    repo_root: &Path,
+    config: &GitConfig,
+    mut state: RebaseState,
+    progress: &mut Progress,
+) -> crate::Result<()> {
+    let index = Index::read_from_conn(conn)?;
+    if index.has_conflicts() {
+        return Err(anyhow!(
+            "error: you must edit all merge conflicts and then mark them as resolved using \"gitqlite add\": {}",
+            index.conflicted_names().join(", ")
+        ));
+    }
+
+    let head_id = head_commit(conn)?;
+    let head_tree = Commit::read_from_conn_with_id(conn, head_id)?.tree_id;
+    let tree_id = write_tree(conn, &index_view(&index))?;
+
+    match state.conflicted.take() {
+        Some(step) => {
+            let commit = Commit::read_from_conn_with_id(conn, step.commit_id)?;
+            let squashing = matches!(step.action, RebaseAction::Squash | RebaseAction::Fixup);
+            // A commit whose changes were all resolved away is dropped
+            if squashing || tree_id != head_tree {
+                commit_step(conn, repo_root, config, step.action, &commit, tree_id)?;
+            }
+        }
+        None if tree_id != head_tree => {
+            // Amend HEAD with the changes staged while stopped at an `edit` step
+            let head = Commit::read_from_conn_with_id(conn, head_id)?;
+            let amended = commit_tree(
+                conn,
+                config,
+                tree_id,
+                head.parent_ids,
+                head.message,
+                Some((head.author_name, head.author_email)),
+            )?;
+            Head::Commit(amended.commit_id).persist(conn)?;
+        }
+        None => {}
+    }
+
+    run(conn, repo_root, config, state, progress)
+}
+
+/// Drop the step that stopped the rebase along with any changes made since, and carry on
+pub fn skip(
+    conn: &Connection,
+    repo_root: &Path,
+    config: &GitConfig,
+    mut state: RebaseState,
+    progress: &mut Progress,
+) -> crate::Result<()> {
+    state.conflicted = None;
+
+    let head_id = head_commit(conn)?;
+    let head_view = flatten_tree(conn, Commit::read_from_conn_with_id(conn, head_id)?.tree_id)?;
+    let mut index = Index::read_from_conn(conn)?;
+    reset_hard(conn, repo_root, &mut index, &head_view, progress)?;
+    index.persist(conn)?;
+
+    run(conn, repo_root, config, state, progress)
+}
+
+/// Stop rebasing and bring HEAD, the index and the working tree back to where they were
+/// before the rebase started
+pub fn abort(
+    conn: &Connection,
+    repo_root: &Path,
+    state: RebaseState,
+    progress: &mut Progress,
+) -> crate::Result<()> {
+    let orig_view = flatten_tree(
+        conn,
+        Commit::read_from_conn_with_id(conn, state.orig_head)?.tree_id,
+    )?;
+    let mut index = Index::read_from_conn(conn)?;
+    reset_hard(conn, repo_root, &mut index, &orig_view, progress)?;
+    index.persist(conn)?;
+
+    // The branch itself is only moved when the rebase finishes, so it still points to orig_head
+    match &state.head_name {
+        Some(name) => Head::Branch(name.clone()).persist(conn)?,
+        None => Head::Commit(state.orig_head).persist(conn)?,
+    }
+    RebaseState::clear(conn)?;
+
+    apply_autostash(conn, repo_root, state.autostash, progress)
+}
+
+fn apply_step(
+    conn: &Connection,
+    repo_root: &Path,
+    config: &GitConfig,
+    step: &RebaseStep,
+    progress: &mut Progress,
+) -> crate::Result<StepResult> {
+    if step.action == RebaseAction::Drop {
+        return Ok(StepResult::Applied);
+    }
+
+    let commit = Commit::read_from_conn_with_id(conn, step.commit_id)?;
+    let head_id = head_commit(conn)?;
+    let mut index = Index::read_from_conn(conn)?;
+
+    let squashing = matches!(step.action, RebaseAction::Squash | RebaseAction::Fixup);
+    if !squashing && commit.parent_ids.first() == Some(&head_id) {
+        // The commit already sits on top of HEAD: reuse it as is
+        let head_view = flatten_tree(conn, Commit::read_from_conn_with_id(conn, head_id)?.tree_id)?;
+        let outcome = MergeOutcome {
+            tree: flatten_tree(conn, commit.tree_id)?,
+            conflicts: Vec::new(),
+        };
+        apply_merge(
+            conn,
+            repo_root,
+            &mut index,
+            &head_view,
+            &outcome,
+            ("HEAD", "HEAD"),
+            progress,
+        )?;
+        index.persist(conn)?;
+        Head::Commit(commit.commit_id).persist(conn)?;
+
+        if step.action == RebaseAction::Reword {
+            commit_step(
+                conn,
+                repo_root,
+                config,
+                step.action,
+                &commit,
+                commit.tree_id,
+            )?;
+        }
+    } else {
+        let label = format!(
+            "{} ({})",
+            short_id(commit.commit_id),
+            summary(&commit.message)
+        );
+        let outcome = pick_commit(conn, repo_root, &mut index, &commit, head_id, &label)?;
+        index.persist(conn)?;
+
+        for conflict in &outcome.conflicts {
+            println!("{}", conflict.describe(("HEAD", &label)));
+        }
+        if !outcome.conflicts.is_empty() {
+            return Ok(StepResult::Conflicted);
+        }
+
+        let tree_id = write_tree(conn, &outcome.tree)?;
+        commit_step(conn, repo_root, config, step.action, &commit, tree_id)?;
+    }
+
+    if step.action == RebaseAction::Edit {
+        return Ok(StepResult::Stopped);
+    }
+    Ok(StepResult::Applied)
+}
+
+/// Record the tree resulting from a step on top of HEAD, according to the step's action
+fn commit_step(
+    conn: &Connection,
+    repo_root: &Path,
+    config: &GitConfig,
+    action: RebaseAction,
+    commit: &Commit<Sha1Id>,
+    tree_id: Sha1Id,
+) -> crate::Result<()> {
+    let head_id = head_commit(conn)?;
+    let author = Some((commit.author_name.clone(), commit.author_email.clone()));
+
+    let new_commit = match action {
+        RebaseAction::Pick | RebaseAction::Edit | RebaseAction::Drop => commit_tree(
+            conn,
+            config,
+            tree_id,
+            vec![head_id],
+            commit.message.clone(),
+            author,
+        )?,
+        RebaseAction::Reword => {
+            // A reworded commit reused as is has HEAD as itself, so take its own parents
+            let parent_ids = if head_id == commit.commit_id {
+                commit.parent_ids.clone()
+            } else {
+                vec![head_id]
+            };
+            let message = edit_message(repo_root, config, &commit.message)?;
+            commit_tree(conn, config, tree_id, parent_ids, message, author)?
+        }
+        RebaseAction::Squash | RebaseAction::Fixup => {
+            // Meld into HEAD: replace it by a commit with its parents and the combined changes
+            let previous = Commit::read_from_conn_with_id(conn, head_id)?;
+            let message = if action == RebaseAction::Squash {
+                let combined = format!(
+                    "# This is a combination of 2 commits.\n# This is the 1st commit message:\n\n{}\n\n# This is the commit message #2:\n\n{}\n",
+                    previous.message, commit.message
+                );
+                edit_message(repo_root, config, &combined)?
+            } else {
+                previous.message.clone()
+            };
+            commit_tree(
+                conn,
+                config,
+                tree_id,
+                previous.parent_ids,
+                message,
+                Some((previous.author_name, previous.author_email)),
+            )?
+        }
+    };
+
+    Head::Commit(new_commit.commit_id).persist(conn)
+}
+
+/// Let the user edit a commit message. An empty message keeps the original one.
+fn edit_message(repo_root: &Path, config: &GitConfig, message: &str) -> crate::Result<String> {
+    let path = repo_root
+        .join(GITQLITE_DIRECTORY_PREFIX)
+        .join(COMMIT_MESSAGE_FILE);
+    let edited = edit_text(
+        config,
+        path,
+        &format!(
+            "{}\n\n# Please enter the commit message for your changes. Lines starting\n# with '#' will be ignored.\n",
+            message
+        ),
+    )?;
+    if edited.is_empty() {
+        println!("Empty commit message, keeping the original one");
+        return Ok(strip_comments(message));
+    }
+    Ok(edited)
+}
+
+/// Path of the file holding the todo list while the user edits it
+pub fn todo_path(repo_root: &Path) -> PathBuf {
+    repo_root.join(GITQLITE_DIRECTORY_PREFIX).join(TODO_FILE)
+}
+
+/// Move the rebased branch to the rewritten history and attach HEAD to it again
+fn finish(
+    conn: &Connection,
+    repo_root: &Path,
+    state: &RebaseState,
+    progress: &mut Progress,
+) -> crate::Result<()> {
+    let head_id = head_commit(conn)?;
+    progress.finish();
+
+    match &state.head_name {
+        Some(name) => {
+            Ref {
+                name: name.clone(),
+                commit_id: head_id,
+            }
+            .persist_or_update(conn)?;
+            Head::Branch(name.clone()).persist(conn)?;
+            println!("Successfully rebased and updated {}.", name);
+        }
+        None => println!("Successfully rebased and updated detached HEAD."),
+    }
+
+    RebaseState::clear(conn)?;
+    apply_autostash(conn, repo_root, state.autostash, progress)
+}
+
+/// The commit of the detached HEAD the rebase works on
+/// The commit HEAD points to, failing on a branch without commits
+pub fn head_commit(conn: &Connection) -> crate::Result<Sha1Id> {
+    Head::read_from_conn(conn)?
+        .resolve(conn)?
+        .ok_or_else(|| anyhow!("fatal: HEAD does not point to a commit"))
+}
+
+fn action_name(action: RebaseAction) -> &'static str {
+    match action {
+        RebaseAction::Pick => "pick",
+        RebaseAction::Reword => "reword",
+        RebaseAction::Edit => "edit",
+        RebaseAction::Squash => "squash",
+        RebaseAction::Fixup => "fixup",
+        RebaseAction::Drop => "drop",
+    }
+}
+
+/// Abbreviated commit hash shown to the user
+pub fn short_id(id: Sha1Id) -> String {
+    id.to_string()[..7].to_string()
+}
+
+/// First line of a commit message
+pub fn summary(message: &str) -> &str {
+    message.lines().next().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::{
+        model::{CREATE_HEAD_TABLE, CREATE_REF_TABLE},
+        test_utils::{make_commit, object_database},
+    };
+
+    fn setup() -> Connection {
+        object_database(&[CREATE_HEAD_TABLE, CREATE_REF_TABLE])
+    }
+
+    #[test]
+    fn test_commits_to_rebase() {
+        let conn = setup();
+
+        // root - a1 - a2
+        //    \     \
+        //     b1 -- m - b2
+        let root = make_commit(&conn, vec![], "root");
+        let a1 = make_commit(&conn, vec![root], "a1");
+        let a2 = make_commit(&conn, vec![a1], "a2");
+        let b1 = make_commit(&conn, vec![root], "b1");
+        let m = make_commit(&conn, vec![b1, a1], "m");
+        let b2 = make_commit(&conn, vec![m], "b2");
+
+        assert_eq!(vec![b1, b2], commits_to_rebase(&conn, a2, b2).unwrap());
+        assert!(commits_to_rebase(&conn, a2, a1).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_todo_round_trip() {
+        let conn = setup();
+        let root = make_commit(&conn, vec![], "root");
+        let first = make_commit(&conn, vec![root], "first\n\nbody");
+        let second = make_commit(&conn, vec![first], "second");
+
+        let steps = vec![
+            RebaseStep {
+                action: RebaseAction::Pick,
+                commit_id: first,
+            },
+            RebaseStep {
+                action: RebaseAction::Pick,
+                commit_id: second,
+            },
+        ];
+        let text = format_todo(&conn, &steps, "Rebase").unwrap();
+        assert!(text.starts_with(&format!(
+            "pick {} first\npick {} second\n",
+            short_id(first),
+            short_id(second)
+        )));
+        assert_eq!(steps, parse_todo(&conn, &text).unwrap());
+
+        let edited = format!(
+            "r {}\n\n# comment\nf {} whatever\n",
+            short_id(first),
+            second
+        );
+        assert_eq!(
+            vec![
+                RebaseStep {
+                    action: RebaseAction::Reword,
+                    commit_id: first,
+                },
+                RebaseStep {
+                    action: RebaseAction::Fixup,
+                    commit_id: second,
+                },
+            ],
+            parse_todo(&conn, &edited).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_todo_errors() {
+        let conn = setup();
+        let root = make_commit(&conn, vec![], "root");
+        let first = make_commit(&conn, vec![root], "first");
+
+        assert!(parse_todo(&conn, &format!("bogus {}", first)).is_err());
+        assert!(parse_todo(&conn, "pick").is_err());
+        assert!(parse_todo(&conn, &format!("drop {}\nsquash {}", root, first)).is_err());
+    }
+}
@@ -0,0 +1,198 @@
+//! This module validates reference names following the rules of `git check-ref-format`, so that
+//! every name stored in the Refs table can also be written as a loose ref by git, and can be
+//! told apart from revision expressions such as `main~2` or `HEAD@{1}`.
+
+use std::cmp::Ordering;
+
+use anyhow::anyhow;
+
+use super::constants::{BRANCH_PREFIX, TAG_PREFIX};
+
+/// Characters never allowed in a reference name, besides ASCII control characters
+const INVALID_CHARS: [char; 8] = [' ', '~', '^', ':', '?', '*', '[', '\\'];
+
+/// Check that `name` is a valid reference name. Unless `allow_onelevel` is set, the name must
+/// have at least two components, e.g. `refs/head/main` or `heads/main`.
+pub fn check_ref_format(name: &str, allow_onelevel: bool) -> crate::Result<()> {
+    let invalid = |reason: &str| Err(anyhow!("fatal: invalid ref name '{}': {}", name, reason));
+
+    if name.is_empty() {
+        return invalid("empty name");
+    }
+    if name == "@" {
+        return invalid("'@' alone is reserved");
+    }
+    if name.starts_with('/') || name.ends_with('/') {
+        return invalid("begins or ends with '/'");
+    }
+    if name.ends_with('.') {
+        return invalid("ends with '.'");
+    }
+    if name.contains("..") {
+        return invalid("contains '..'");
+    }
+    if name.contains("@{") {
+        return invalid("contains '@{'");
+    }
+    if let Some(c) = name
+        .chars()
+        .find(|c| c.is_ascii_control() || INVALID_CHARS.contains(c))
+    {
+        return invalid(&format!("contains '{}'", c.escape_default()));
+    }
+    for component in name.split('/') {
+        if component.is_empty() {
+            return invalid("contains '//'");
+        }
+        if component.starts_with('.') {
+            return invalid("path component beginning with '.'");
+        }
+        if component.ends_with(".lock") {
+            return invalid("path component ending with '.lock'");
+        }
+    }
+    if !allow_onelevel && !name.contains('/') {
+        return invalid("must contain at least one '/'");
+    }
+    Ok(())
+}
+
+/// Remove leading slashes and collapse runs of slashes, as `git check-ref-format --normalize`
+pub fn normalize_ref_name(name: &str) -> String {
+    let mut normalized = String::with_capacity(name.len());
+    for c in name.trim_start_matches('/').chars() {
+        if c == '/' && normalized.ends_with('/') {
+            continue;
+        }
+        normalized.push(c);
+    }
+    normalized
+}
+
+/// Full reference name of a branch, e.g. `refs/head/main` for `main`, if the branch name is
+/// valid
+pub fn branch_ref_name(branch: &str) -> crate::Result<String> {
+    if branch.starts_with('-') || branch == "HEAD" {
+        return Err(anyhow!("fatal: '{}' is not a valid branch name", branch));
+    }
+    let name = format!("{}{}", BRANCH_PREFIX, branch);
+    check_ref_format(&name, false)
+        .map_err(|_| anyhow!("fatal: '{}' is not a valid branch name", branch))?;
+    Ok(name)
+}
+
+/// Full reference name of a tag, e.g. `refs/tags/v1.0` for `v1.0`, if the tag name is valid
+pub fn tag_ref_name(tag: &str) -> crate::Result<String> {
+    if tag.starts_with('-') || tag == "HEAD" {
+        return Err(anyhow!("fatal: '{}' is not a valid tag name", tag));
+    }
+    let name = format!("{}{}", TAG_PREFIX, tag);
+    check_ref_format(&name, false)
+        .map_err(|_| anyhow!("fatal: '{}' is not a valid tag name", tag))?;
+    Ok(name)
+}
+
+/// Compare names as versions, as `--sort=version:refname` does: runs of digits compare as
+/// numbers and the rest as text, so that `v1.10` comes after `v1.9`. A name continuing with
+/// `-` where the other ends is a pre-release and comes first, e.g. `v1.0-rc1` before `v1.0`.
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a, b);
+    loop {
+        match (a.is_empty(), b.is_empty()) {
+            (true, true) => return Ordering::Equal,
+            (true, false) if b.starts_with('-') => return Ordering::Greater,
+            (false, true) if a.starts_with('-') => return Ordering::Less,
+            (true, false) => return Ordering::Less,
+            (false, true) => return Ordering::Greater,
+            (false, false) => {}
+        }
+        let (chunk_a, rest_a) = split_version_chunk(a);
+        let (chunk_b, rest_b) = split_version_chunk(b);
+        let numbers = (chunk_a.parse::<u64>(), chunk_b.parse::<u64>());
+        let ordering = match numbers {
+            (Ok(x), Ok(y)) => x.cmp(&y),
+            _ => chunk_a.cmp(chunk_b),
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+        (a, b) = (rest_a, rest_b);
+    }
+}
+
+/// Split off the leading run of digits, or of other characters
+fn split_version_chunk(name: &str) -> (&str, &str) {
+    let digits = name.starts_with(|c: char| c.is_ascii_digit());
+    let end = name
+        .find(|c: char| c.is_ascii_digit() != digits)
+        .unwrap_or(name.len());
+    name.split_at(end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_ref_format() {
+        for name in [
+            "refs/head/main",
+            "refs/tags/v1.0",
+            "heads/feature/x-y",
+            "a/@b",
+        ] {
+            assert!(check_ref_format(name, false).is_ok(), "{}", name);
+        }
+        for name in [
+            "",
+            "@",
+            "main",
+            "/refs/head/main",
+            "refs/head/main/",
+            "refs/head/main.",
+            "refs/head/a..b",
+            "refs/head/a@{1}",
+            "refs/head/a b",
+            "refs/head/a~1",
+            "refs/head/a^",
+            "refs/head/a:b",
+            "refs/head/a?",
+            "refs/head/a*",
+            "refs/head/a[b",
+            "refs/head/a\\b",
+            "refs/head/a\x7f",
+            "refs//head",
+            "refs/.hidden",
+            "refs/head/main.lock",
+        ] {
+            assert!(check_ref_format(name, false).is_err(), "{}", name);
+        }
+        assert!(check_ref_format("main", true).is_ok());
+    }
+
+    #[test]
+    fn test_normalize_and_branch() {
+        assert_eq!("refs/head/main", normalize_ref_name("//refs///head/main"));
+        assert_eq!("a/b/", normalize_ref_name("a//b//"));
+
+        assert_eq!("refs/head/topic/x", branch_ref_name("topic/x").unwrap());
+        for branch in ["-x", "HEAD", "a..b", "a.lock", ""] {
+            assert!(branch_ref_name(branch).is_err(), "{}", branch);
+        }
+    }
+
+    #[test]
+    fn test_tag_and_versions() {
+        assert_eq!("refs/tags/v1.0", tag_ref_name("v1.0").unwrap());
+        assert!(tag_ref_name("-d").is_err());
+        assert!(tag_ref_name("v1..0").is_err());
+
+        let mut names = vec!["v1.10", "v1.0", "v1.9", "v1.0-rc1", "v2", "v1.0.1", "alpha"];
+        names.sort_by(|a, b| compare_versions(a, b));
+        assert_eq!(
+            vec!["alpha", "v1.0-rc1", "v1.0", "v1.0.1", "v1.9", "v1.10", "v2"],
+            names
+        );
+        assert_eq!(Ordering::Equal, compare_versions("v01", "v1"));
+    }
+}
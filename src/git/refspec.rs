@@ -0,0 +1,282 @@
+//! This module parses refspecs, the `[+]<src>[:<dst>]` patterns telling fetch which remote
+//! refs to store in which local refs, and push which local refs to upload to which remote
+//! refs. `+` allows updates which are not fast-forwards.
+//!
+//! Either side may hold a single `*`, in which case both must, and the refspec maps every ref
+//! matching the source to the destination with the `*` replaced by the same text, e.g.
+//! `+refs/head/*:refs/remotes/origin/*` stores each remote branch in a remote-tracking branch.
+//! A missing destination means the ref of the same name for push, and nothing but FETCH_HEAD
+//! for fetch; an empty source pushes the deletion of the destination.
+
+use std::fmt;
+
+use anyhow::anyhow;
+
+use super::constants::{BRANCH_PREFIX, REMOTE_PREFIX};
+
+/// [`Refspec`] maps source refs to destination refs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Refspec {
+    pub force: bool,
+    pub src: String,
+    pub dst: Option<String>,
+}
+
+impl Refspec {
+    pub fn parse(refspec: &str) -> crate::Result<Refspec> {
+        let invalid = || anyhow!("fatal: invalid refspec '{}'", refspec);
+        let (force, spec) = match refspec.strip_prefix('+') {
+            Some(spec) => (true, spec),
+            None => (false, refspec),
+        };
+        let (src, dst) = match spec.split_once(':') {
+            Some((src, dst)) => (src, Some(dst)),
+            None => (spec, None),
+        };
+
+        let wildcards = |side: &str| side.matches('*').count();
+        if wildcards(src) > 1 || dst.is_some_and(|dst| wildcards(dst) > 1) {
+            return Err(invalid());
+        }
+        if let Some(dst) = dst {
+            if dst.is_empty() || (wildcards(dst) != wildcards(src) && !src.is_empty()) {
+                return Err(invalid());
+            }
+            if src.is_empty() && wildcards(dst) > 0 {
+                return Err(invalid());
+            }
+        } else if src.is_empty() {
+            return Err(invalid());
+        }
+
+        Ok(Refspec {
+            force,
+            src: src.to_string(),
+            dst: dst.map(str::to_string),
+        })
+    }
+
+    /// The default refspec of fetch, storing the branches of `remote` in its remote-tracking
+    /// branches
+    pub fn default_fetch(remote: &str) -> Refspec {
+        Refspec {
+            force: true,
+            src: format!("{}*", BRANCH_PREFIX),
+            dst: Some(format!("{}{}/*", REMOTE_PREFIX, remote)),
+        }
+    }
+
+    pub fn is_pattern(&self) -> bool {
+        self.src.contains('*')
+    }
+
+    /// Whether the refspec deletes its destination, which only push does
+    pub fn is_delete(&self) -> bool {
+        self.src.is_empty()
+    }
+
+    /// The text `*` stands for in `name`, if the source matches it. A source without `*`
+    /// must be the name itself.
+    pub fn match_src<'a>(&self, name: &'a str) -> Option<&'a str> {
+        match self.src.split_once('*') {
+            Some((prefix, suffix)) => name
+                .strip_prefix(prefix)?
+                .strip_suffix(suffix)
+                .filter(|matched| !matched.is_empty()),
+            None => (self.src == name).then_some(""),
+        }
+    }
+
+    /// The destination of the ref `name`, if the source matches it and the refspec has a
+    /// destination
+    pub fn map(&self, name: &str) -> Option<String> {
+        let matched = self.match_src(name)?;
+        Some(self.dst.as_ref()?.replacen('*', matched, 1))
+    }
+
+    /// The source ref mapped to the destination `name`, if the destination matches it
+    pub fn reverse_map(&self, name: &str) -> Option<String> {
+        let dst = self.dst.as_ref()?;
+        match dst.split_once('*') {
+            Some((prefix, suffix)) => {
+                let matched = name
+                    .strip_prefix(prefix)?
+                    .strip_suffix(suffix)
+                    .filter(|matched| !matched.is_empty())?;
+                Some(self.src.replacen('*', matched, 1))
+            }
+            None => (dst == name).then(|| self.src.clone()),
+        }
+    }
+
+    /// The refspec with both sides written in full, a name outside of `refs/` being a branch
+    pub fn expand(&self) -> Refspec {
+        let expand = |side: &str| {
+            if side.is_empty() || side.starts_with("refs/") || side == "HEAD" {
+                side.to_string()
+            } else {
+                format!("{}{}", BRANCH_PREFIX, side)
+            }
+        };
+        Refspec {
+            force: self.force,
+            src: expand(&self.src),
+            dst: self.dst.as_deref().map(expand),
+        }
+    }
+}
+
+impl fmt::Display for Refspec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.force {
+            write!(f, "+")?;
+        }
+        write!(f, "{}", self.src)?;
+        if let Some(dst) = &self.dst {
+            write!(f, ":{}", dst)?;
+        }
+        Ok(())
+    }
+}
+
+/// The refs of `names` the refspecs select, each with its destination, in the order of the
+/// refspecs then of the names. A ref matched by several refspecs is taken from the first one.
+pub fn map_refs<'a>(
+    refspecs: &'a [Refspec],
+    names: impl IntoIterator<Item = &'a str> + Clone,
+) -> Vec<(&'a str, Option<String>, &'a Refspec)> {
+    let mut mapped: Vec<(&str, Option<String>, &Refspec)> = Vec::new();
+    for refspec in refspecs {
+        for name in names.clone() {
+            if refspec.match_src(name).is_some() && !mapped.iter().any(|(n, ..)| *n == name) {
+                mapped.push((name, refspec.map(name), refspec));
+            }
+        }
+    }
+    mapped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(force: bool, src: &str, dst: Option<&str>) -> Refspec {
+        Refspec {
+            force,
+            src: src.to_string(),
+            dst: dst.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(spec(false, "main", None), Refspec::parse("main").unwrap());
+        assert_eq!(
+            spec(true, "main", Some("topic")),
+            Refspec::parse("+main:topic").unwrap()
+        );
+        assert_eq!(
+            spec(true, "refs/head/*", Some("refs/remotes/origin/*")),
+            Refspec::parse("+refs/head/*:refs/remotes/origin/*").unwrap()
+        );
+        assert_eq!(
+            spec(false, "", Some("topic")),
+            Refspec::parse(":topic").unwrap()
+        );
+        assert_eq!(
+            spec(false, "refs/head/feat-*-wip", Some("refs/wip/*")),
+            Refspec::parse("refs/head/feat-*-wip:refs/wip/*").unwrap()
+        );
+
+        for invalid in [
+            "",
+            "+",
+            ":",
+            "main:",
+            "refs/*/*",
+            "refs/head/*:refs/remotes/origin/main",
+            "refs/head/main:refs/remotes/*",
+            ":refs/head/*",
+            "refs/head/*:refs/*/*",
+        ] {
+            assert!(Refspec::parse(invalid).is_err(), "{:?}", invalid);
+        }
+
+        for text in ["+refs/head/*:refs/remotes/origin/*", "main", ":gone"] {
+            assert_eq!(text, Refspec::parse(text).unwrap().to_string());
+        }
+    }
+
+    #[test]
+    fn test_map() {
+        let fetch = Refspec::default_fetch("origin");
+        assert!(fetch.is_pattern());
+        assert_eq!(
+            Some("refs/remotes/origin/main".to_string()),
+            fetch.map("refs/head/main")
+        );
+        assert_eq!(
+            Some("refs/remotes/origin/feature/x".to_string()),
+            fetch.map("refs/head/feature/x")
+        );
+        assert_eq!(None, fetch.map("refs/tags/v1"));
+        // `*` matches at least one character
+        assert_eq!(None, fetch.map("refs/head/"));
+
+        let wip = Refspec::parse("refs/head/*-wip:refs/wip/*").unwrap();
+        assert_eq!(Some("refs/wip/a".to_string()), wip.map("refs/head/a-wip"));
+        assert_eq!(None, wip.map("refs/head/a"));
+
+        // Back from the destination to the source
+        assert_eq!(
+            Some("refs/head/feature/x".to_string()),
+            fetch.reverse_map("refs/remotes/origin/feature/x")
+        );
+        assert_eq!(None, fetch.reverse_map("refs/remotes/upstream/main"));
+        assert_eq!(
+            Some("refs/head/a-wip".to_string()),
+            wip.reverse_map("refs/wip/a")
+        );
+
+        let exact = Refspec::parse("main:topic").unwrap().expand();
+        assert_eq!(
+            spec(false, "refs/head/main", Some("refs/head/topic")),
+            exact
+        );
+        assert_eq!(
+            Some("refs/head/topic".to_string()),
+            exact.map("refs/head/main")
+        );
+        assert_eq!(None, exact.map("refs/head/mainline"));
+        assert_eq!(
+            Some("refs/head/main".to_string()),
+            exact.reverse_map("refs/head/topic")
+        );
+        assert_eq!(None, Refspec::parse("main").unwrap().map("main"));
+        assert!(Refspec::parse(":topic").unwrap().is_delete());
+        assert_eq!("HEAD", Refspec::parse("HEAD").unwrap().expand().src);
+    }
+
+    #[test]
+    fn test_map_refs() {
+        let refspecs = vec![
+            Refspec::parse("refs/head/main:refs/head/stable").unwrap(),
+            Refspec::parse("refs/head/*:refs/remotes/origin/*").unwrap(),
+        ];
+        let names = ["refs/head/main", "refs/head/topic", "refs/tags/v1"];
+        let mapped: Vec<(&str, Option<String>)> = map_refs(&refspecs, names)
+            .into_iter()
+            .map(|(name, dst, _)| (name, dst))
+            .collect();
+        assert_eq!(
+            vec![
+                ("refs/head/main", Some("refs/head/stable".to_string())),
+                (
+                    "refs/head/topic",
+                    Some("refs/remotes/origin/topic".to_string())
+                ),
+            ],
+            mapped
+        );
+    }
+}
@@ -0,0 +1,146 @@
+//! This module maintains the remote-tracking refs of configured remotes: the refspec of
+//! `remote.<name>.fetch` maps the refs of the remote to local refs, and pruning deletes the
+//! local refs whose remote ref no longer exists.
+//!
+//! Fetch prunes when asked with `--prune`, or when `remote.<name>.prune` says so, falling back
+//! to `fetch.prune` for every remote.
+
+use rusqlite::Connection;
+
+use crate::repo::{
+    config::{ConfigSource, GitConfig},
+    db::{OldValue, RefTransaction},
+};
+
+use super::{
+    constants::{BRANCH_PREFIX, REMOTE_PREFIX},
+    model::Ref,
+    refspec::Refspec,
+    transport::{is_configured_remote, RemoteRefs},
+    utils::WriteTransaction,
+};
+
+/// The refspecs storing the refs of `remote` in remote-tracking refs, none if it is not a
+/// configured remote
+pub fn tracking_refspecs(config: &GitConfig, remote: &str) -> crate::Result<Vec<Refspec>> {
+    if !is_configured_remote(config, remote)? {
+        return Ok(Vec::new());
+    }
+    let key = format!("remote.{}.fetch", remote);
+    Ok(match config.get(&key, ConfigSource::All)? {
+        Some(refspec) => vec![Refspec::parse(refspec)?],
+        None => vec![Refspec::default_fetch(remote)],
+    })
+}
+
+/// The remote a branch pulls from and the name of the branch it merges there, from
+/// `branch.<name>.remote` and `branch.<name>.merge`
+pub fn branch_upstream(
+    config: &GitConfig,
+    branch: &str,
+) -> crate::Result<Option<(String, String)>> {
+    let remote = config.get(&format!("branch.{}.remote", branch), ConfigSource::All)?;
+    let merge = config.get(&format!("branch.{}.merge", branch), ConfigSource::All)?;
+    let (Some(remote), Some(merge)) = (remote, merge) else {
+        return Ok(None);
+    };
+    // The merged branch is named as on the remote, in git's `refs/heads/` form or ours
+    let merge = ["refs/heads/", BRANCH_PREFIX]
+        .iter()
+        .find_map(|prefix| merge.strip_prefix(prefix))
+        .unwrap_or(merge);
+    Ok(Some((remote.to_string(), merge.to_string())))
+}
+
+/// Whether fetching from `remote` prunes its stale remote-tracking refs by default
+pub fn prune_by_default(config: &GitConfig, remote: &str) -> crate::Result<bool> {
+    let key = format!("remote.{}.prune", remote);
+    Ok(match config.get_bool(&key, ConfigSource::All)? {
+        Some(prune) => prune,
+        None => config
+            .get_bool("fetch.prune", ConfigSource::All)?
+            .unwrap_or(false),
+    })
+}
+
+/// The local refs the refspecs map from a ref the remote no longer has
+pub fn stale_refs(
+    conn: &Connection,
+    tracking: &[Refspec],
+    remote: &RemoteRefs,
+) -> crate::Result<Vec<Ref>> {
+    Ok(Ref::read_all(conn)?
+        .into_iter()
+        .filter(|local| {
+            tracking.iter().any(|refspec| {
+                refspec
+                    .reverse_map(&local.name)
+                    .is_some_and(|src| remote.get(&src).is_none())
+            })
+        })
+        .collect())
+}
+
+/// Delete the stale refs at once, unless they moved since they were read
+pub fn delete_refs(conn: &Connection, remote: &str, stale: &[Ref]) -> crate::Result<()> {
+    let mut transaction = RefTransaction::new(format!("prune {}", remote));
+    for stale in stale {
+        transaction.delete(stale.name.clone(), OldValue::Is(stale.commit_id.into()));
+    }
+    let txn = WriteTransaction::new(conn)?;
+    transaction.commit(&txn)?;
+    txn.commit()?;
+    Ok(())
+}
+
+/// Name of a remote-tracking ref as it is reported, e.g. `origin/main`
+pub fn tracking_name(name: &str) -> &str {
+    name.strip_prefix(REMOTE_PREFIX).unwrap_or(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        git::model::{Sha1Id, CREATE_REF_TABLE},
+        repo::db::CREATE_REFLOG_TABLE,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_stale_refs() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute(CREATE_REF_TABLE, ()).unwrap();
+        conn.execute(CREATE_REFLOG_TABLE, ()).unwrap();
+        let id = Sha1Id::try_from("0123456789abcdef0123456789abcdef01234567").unwrap();
+        for name in [
+            "refs/head/main",
+            "refs/remotes/origin/main",
+            "refs/remotes/origin/gone",
+            "refs/remotes/upstream/gone",
+        ] {
+            Ref {
+                name: name.to_string(),
+                commit_id: id,
+            }
+            .persist_or_update(&conn)
+            .unwrap();
+        }
+
+        let remote = RemoteRefs {
+            head: Some("refs/head/main".to_string()),
+            refs: vec![("refs/head/main".to_string(), id)],
+        };
+        let stale = stale_refs(&conn, &[Refspec::default_fetch("origin")], &remote).unwrap();
+        let names: Vec<&str> = stale.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(vec!["refs/remotes/origin/gone"], names);
+
+        delete_refs(&conn, "origin", &stale).unwrap();
+        assert!(
+            Ref::read_from_conn_with_name(&conn, "refs/remotes/origin/gone")
+                .unwrap()
+                .is_none()
+        );
+        assert_eq!("origin/main", tracking_name("refs/remotes/origin/main"));
+    }
+}
@@ -0,0 +1,580 @@
+//! This module resolves revision names given on the command line to commits.
+//!
+//! A revision is a base name optionally followed by any number of ancestry suffixes:
+//! - `HEAD` (or `@`), `FETCH_HEAD`, a branch name, a full ref name, a tag name, a
+//!   remote-tracking branch (`origin/main` for `refs/remotes/origin/main`), or a (possibly
+//!   abbreviated) commit hash
+//! - `<rev>~<n>` selects the n-th generation ancestor following first parents (`~` means `~1`)
+//! - `<rev>^<n>` selects the n-th parent (`^` means `^1`, `^0` is the commit itself)
+//!
+//! The hash of an annotated tag names the commit it tags.
+//!
+//! Where a list of commits is expected, a range `<a>..<b>` selects the commits reachable from
+//! `b` but not from `a`. An omitted side of the range means `HEAD`.
+//!
+//! Where a tree is expected, a revision names the tree of its commit, and a (possibly
+//! abbreviated) tree hash names that tree.
+//!
+//! Where any object is expected, `<tree-ish>:<path>` names the file or directory at the path
+//! in that tree (`<tree-ish>:` the tree itself), and `:<path>` the file staged in the index.
+
+use std::collections::HashSet;
+
+use anyhow::anyhow;
+use rusqlite::Connection;
+
+use crate::repo::db::{object_exists, HeadState, Object};
+
+use super::{
+    constants::{BRANCH_PREFIX, REMOTE_PREFIX, TAG_PREFIX},
+    merge::ancestors,
+    model::{
+        AnnotatedTag, Commit, FetchHead, Head, Index, Ref, Sha1Id, Tree, TreeEntry, TreeEntryType,
+    },
+};
+
+/// Minimum number of hex digits accepted as an abbreviated object hash
+const MIN_ABBREV_LEN: usize = 4;
+
+// hex() renders blobs in upper case
+const READ_COMMIT_IDS_WITH_PREFIX: &str =
+    "SELECT commit_id FROM Commits WHERE hex(commit_id) LIKE ?1 || '%' LIMIT 2";
+const READ_TAG_IDS_WITH_PREFIX: &str =
+    "SELECT tag_id FROM Tags WHERE hex(tag_id) LIKE ?1 || '%' LIMIT 2";
+const READ_TREE_IDS_WITH_PREFIX: &str =
+    "SELECT tree_id FROM Trees WHERE hex(tree_id) LIKE ?1 || '%' LIMIT 2";
+const READ_OBJECT_IDS_WITH_PREFIX: &str =
+    "SELECT commit_id FROM Commits WHERE hex(commit_id) LIKE ?1 || '%' \
+     UNION SELECT tree_id FROM Trees WHERE hex(tree_id) LIKE ?1 || '%' \
+     UNION SELECT blob_id FROM Blobs WHERE hex(blob_id) LIKE ?1 || '%' LIMIT 2";
+
+/// Resolve a revision to the id of the commit it names
+pub fn resolve_revision(conn: &Connection, rev: &str) -> crate::Result<Sha1Id> {
+    let (base, suffixes) = match rev.find(['~', '^']) {
+        Some(pos) if pos > 0 => rev.split_at(pos),
+        _ => (rev, ""),
+    };
+
+    let mut commit_id = resolve_base(conn, base)?
+        .ok_or_else(|| anyhow!("fatal: ambiguous argument '{}': unknown revision", rev))?;
+
+    let mut chars = suffixes.chars().peekable();
+    while let Some(op) = chars.next() {
+        let mut digits = String::new();
+        while let Some(c) = chars.next_if(char::is_ascii_digit) {
+            digits.push(c);
+        }
+        let n: usize = if digits.is_empty() {
+            1
+        } else {
+            digits.parse()?
+        };
+
+        commit_id = match op {
+            '~' => nth_ancestor(conn, commit_id, n, rev)?,
+            '^' if n == 0 => commit_id,
+            '^' => {
+                let commit = Commit::read_from_conn_with_id(conn, commit_id)?;
+                *commit
+                    .parent_ids
+                    .get(n - 1)
+                    .ok_or_else(|| anyhow!("fatal: revision '{}' has no parent {}", rev, n))?
+            }
+            _ => return Err(anyhow!("fatal: invalid revision '{}'", rev)),
+        };
+    }
+
+    Ok(commit_id)
+}
+
+/// Resolve a revision or a range to the commits it selects, parents listed before children
+pub fn resolve_commits(conn: &Connection, spec: &str) -> crate::Result<Vec<Sha1Id>> {
+    let Some((from, to)) = spec.split_once("..") else {
+        return Ok(vec![resolve_revision(conn, spec)?]);
+    };
+
+    let side = |rev: &str| resolve_revision(conn, if rev.is_empty() { "HEAD" } else { rev });
+    commits_between(conn, Some(side(from)?), side(to)?)
+}
+
+/// Resolve a revision, a tree hash or a `<tree-ish>:<dir>` to the id of a tree. A `^{tree}`
+/// suffix is accepted.
+pub fn resolve_tree(conn: &Connection, rev: &str) -> crate::Result<Sha1Id> {
+    if rev.contains(':') {
+        return match resolve_path_object(conn, rev)? {
+            Some((TreeEntryType::Tree, id)) => Ok(id),
+            _ => Err(anyhow!("fatal: '{}' is not a tree", rev)),
+        };
+    }
+    let rev = rev.strip_suffix("^{tree}").unwrap_or(rev);
+    match resolve_revision(conn, rev) {
+        Ok(commit_id) => Ok(Commit::read_from_conn_with_id(conn, commit_id)?.tree_id),
+        Err(err) => resolve_prefix(conn, READ_TREE_IDS_WITH_PREFIX, rev)?.ok_or(err),
+    }
+}
+
+/// Resolve a full or abbreviated hash to the id of the object it names, whatever its type
+pub fn resolve_object_id(conn: &Connection, name: &str) -> crate::Result<Sha1Id> {
+    if let Ok(id) = Sha1Id::try_from(name) {
+        if object_exists(conn, id.into())? {
+            return Ok(id);
+        }
+    } else if let Some(id) = resolve_prefix(conn, READ_OBJECT_IDS_WITH_PREFIX, name)? {
+        return Ok(id);
+    }
+    Err(anyhow!("fatal: Not a valid object name {}", name))
+}
+
+/// Commits reachable from `include` but not from `exclude`, parents listed before children
+pub fn commits_between(
+    conn: &Connection,
+    exclude: Option<Sha1Id>,
+    include: Sha1Id,
+) -> crate::Result<Vec<Sha1Id>> {
+    let mut seen = match exclude {
+        Some(exclude) => ancestors(conn, exclude)?,
+        None => HashSet::new(),
+    };
+
+    // Depth-first walk emitting a commit once all of its parents have been emitted
+    let mut commits = Vec::new();
+    let mut stack = vec![(include, false)];
+    while let Some((id, parents_done)) = stack.pop() {
+        if parents_done {
+            commits.push(id);
+            continue;
+        }
+        if !seen.insert(id) {
+            continue;
+        }
+        stack.push((id, true));
+        let commit = Commit::read_from_conn_with_id(conn, id)?;
+        for parent in commit.parent_ids.into_iter().rev() {
+            if !seen.contains(&parent) {
+                stack.push((parent, false));
+            }
+        }
+    }
+
+    Ok(commits)
+}
+
+/// Resolve `<tree-ish>:<path>` or `:<path>` to the type and id of the object it names, None
+/// if the name has no colon
+pub fn resolve_path_object(
+    conn: &Connection,
+    name: &str,
+) -> crate::Result<Option<(TreeEntryType, Sha1Id)>> {
+    let Some((rev, path)) = name.split_once(':') else {
+        return Ok(None);
+    };
+    let path = path.trim_matches('/');
+
+    if rev.is_empty() {
+        let entry = Index::read_from_conn(conn)?
+            .entries
+            .into_iter()
+            .find(|entry| entry.name == path && entry.flag_stage == 0)
+            .ok_or_else(|| anyhow!("fatal: path '{}' is not in the index", path))?;
+        return Ok(Some((TreeEntryType::Blob, entry.sha)));
+    }
+
+    let tree_id = resolve_tree(conn, rev)?;
+    if path.is_empty() {
+        return Ok(Some((TreeEntryType::Tree, tree_id)));
+    }
+    let entry = lookup_path(conn, tree_id, path)?
+        .ok_or_else(|| anyhow!("fatal: path '{}' does not exist in '{}'", path, rev))?;
+    Ok(Some((entry.type_, entry.id)))
+}
+
+/// Find the entry at `path` (relative to the root of the tree) in a tree
+pub fn lookup_path(
+    conn: &Connection,
+    tree_id: Sha1Id,
+    path: &str,
+) -> crate::Result<Option<TreeEntry>> {
+    let mut tree_id = tree_id;
+    let mut components = path.split('/').peekable();
+    while let Some(name) = components.next() {
+        let tree = Tree::read_from_conn_with_id(conn, tree_id)?;
+        let Some(entry) = tree.entries.into_iter().find(|entry| entry.name == name) else {
+            return Ok(None);
+        };
+        if components.peek().is_none() {
+            return Ok(Some(entry));
+        }
+        if entry.type_ != TreeEntryType::Tree {
+            return Ok(None);
+        }
+        tree_id = entry.id;
+    }
+    Ok(None)
+}
+
+/// Resolve a base revision name without ancestry suffixes. Refs take precedence over hashes.
+fn resolve_base(conn: &Connection, name: &str) -> crate::Result<Option<Sha1Id>> {
+    if name == "HEAD" || name == "@" {
+        return match Head::read_from_conn(conn)?.state(conn)? {
+            HeadState::Unborn(branch) => Err(anyhow!(
+                "fatal: your current branch '{}' does not have any commits yet",
+                branch.strip_prefix(BRANCH_PREFIX).unwrap_or(&branch)
+            )),
+            state => Ok(state.commit_id()),
+        };
+    }
+    if name == "FETCH_HEAD" {
+        let fetched = FetchHead::read_all(conn)?;
+        return Ok(fetched.first().map(|entry| entry.commit_id));
+    }
+
+    if let Some(reference) = find_ref(conn, name)? {
+        return Ok(Some(reference.commit_id));
+    }
+
+    if let Some(id) = resolve_prefix(conn, READ_COMMIT_IDS_WITH_PREFIX, name)? {
+        return Ok(Some(id));
+    }
+    Ok(resolve_tag_id(conn, name)?.map(|tag| tag.object_id.into()))
+}
+
+/// The ref a short or full name refers to, looked up like git does: as a branch, as a full ref
+/// name, as a tag, then as a remote-tracking branch
+pub fn find_ref(conn: &Connection, name: &str) -> crate::Result<Option<Ref>> {
+    for ref_name in [
+        format!("{}{}", BRANCH_PREFIX, name),
+        name.to_string(),
+        format!("{}{}", TAG_PREFIX, name),
+        format!("{}{}", REMOTE_PREFIX, name),
+    ] {
+        if let Some(reference) = Ref::read_from_conn_with_name(conn, &ref_name)? {
+            return Ok(Some(reference));
+        }
+    }
+    Ok(None)
+}
+
+/// The annotated tag a name refers to, if any: the name of a tag (unless a branch has the
+/// same name), its full ref name, or its (possibly abbreviated) hash
+pub fn resolve_tag(conn: &Connection, name: &str) -> crate::Result<Option<AnnotatedTag>> {
+    let tag_name = match name.strip_prefix(TAG_PREFIX) {
+        Some(tag_name) => Some(tag_name),
+        None if Ref::read_from_conn_with_name(conn, format!("{}{}", BRANCH_PREFIX, name))?
+            .is_none() =>
+        {
+            Some(name)
+        }
+        None => None,
+    };
+    if let Some(tag_name) = tag_name {
+        let reference = Ref::read_from_conn_with_name(conn, format!("{}{}", TAG_PREFIX, tag_name))?;
+        if let Some(reference) = reference {
+            // The annotation is stale if the ref was moved behind the tag command
+            let tag = AnnotatedTag::read_by_name(conn, tag_name)?;
+            return Ok(tag.filter(|tag| tag.object_id == reference.commit_id.into()));
+        }
+    }
+    resolve_tag_id(conn, name)
+}
+
+fn resolve_tag_id(conn: &Connection, prefix: &str) -> crate::Result<Option<AnnotatedTag>> {
+    match resolve_prefix(conn, READ_TAG_IDS_WITH_PREFIX, prefix)? {
+        Some(tag_id) => AnnotatedTag::read_by_id(conn, tag_id.into()),
+        None => Ok(None),
+    }
+}
+
+/// Find the object whose hash starts with the given hex prefix, using a query selecting ids
+/// by the upper case hex prefix
+fn resolve_prefix(conn: &Connection, query: &str, prefix: &str) -> crate::Result<Option<Sha1Id>> {
+    if prefix.len() < MIN_ABBREV_LEN || !prefix.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Ok(None);
+    }
+
+    let mut stmt = conn.prepare(query)?;
+    let matches = stmt
+        .query_map([prefix.to_ascii_uppercase()], |row| row.get::<_, Sha1Id>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    match matches.as_slice() {
+        [] => Ok(None),
+        [id] => Ok(Some(*id)),
+        _ => Err(anyhow!("fatal: short object ID {} is ambiguous", prefix)),
+    }
+}
+
+fn nth_ancestor(conn: &Connection, mut id: Sha1Id, n: usize, rev: &str) -> crate::Result<Sha1Id> {
+    for _ in 0..n {
+        let commit = Commit::read_from_conn_with_id(conn, id)?;
+        id = *commit
+            .parent_ids
+            .first()
+            .ok_or_else(|| anyhow!("fatal: revision '{}' goes past the root commit", rev))?;
+    }
+    Ok(id)
+}
+
+/// If the revision names a branch, return the short branch name
+pub fn branch_name(conn: &Connection, rev: &str) -> crate::Result<Option<String>> {
+    let short = rev.strip_prefix(BRANCH_PREFIX).unwrap_or(rev);
+    let full = format!("{}{}", BRANCH_PREFIX, short);
+    Ok(Ref::read_from_conn_with_name(conn, &full)?.map(|_| short.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        git::{
+            model::{
+                CREATE_FETCH_HEAD_TABLE, CREATE_HEAD_TABLE, CREATE_INDEX_TABLE, CREATE_REF_TABLE,
+            },
+            test_utils::{
+                create_tables, file_entry, make_commit, new_commit, object_database, persist_blob,
+                persist_commit,
+            },
+            tree::{write_tree, TreeView},
+            worktree::blob_index_entry,
+        },
+        repo::db::{HashAlgorithm, Tag},
+    };
+
+    fn setup() -> Connection {
+        object_database(&[CREATE_HEAD_TABLE, CREATE_REF_TABLE, CREATE_FETCH_HEAD_TABLE])
+    }
+
+    #[test]
+    fn test_resolve_revision() {
+        let conn = setup();
+        let root = make_commit(&conn, vec![], "root");
+        let side = make_commit(&conn, vec![root], "side");
+        let second = make_commit(&conn, vec![root], "second");
+        let merge = make_commit(&conn, vec![second, side], "merge");
+
+        // HEAD on a branch without commits
+        Head::Branch(format!("{}main", BRANCH_PREFIX))
+            .persist(&conn)
+            .unwrap();
+        assert_eq!(
+            "fatal: your current branch 'main' does not have any commits yet",
+            resolve_revision(&conn, "HEAD").unwrap_err().to_string()
+        );
+
+        Ref {
+            name: format!("{}main", BRANCH_PREFIX),
+            commit_id: merge,
+        }
+        .persist_or_update(&conn)
+        .unwrap();
+
+        assert_eq!(merge, resolve_revision(&conn, "HEAD").unwrap());
+        assert_eq!(merge, resolve_revision(&conn, "main").unwrap());
+        assert_eq!(merge, resolve_revision(&conn, "refs/head/main").unwrap());
+        assert_eq!(second, resolve_revision(&conn, "main~").unwrap());
+        assert_eq!(root, resolve_revision(&conn, "HEAD~2").unwrap());
+        assert_eq!(side, resolve_revision(&conn, "main^2").unwrap());
+        assert_eq!(root, resolve_revision(&conn, "main^2~1").unwrap());
+        assert_eq!(merge, resolve_revision(&conn, "main^0").unwrap());
+
+        let abbrev = side.to_string()[..8].to_string();
+        assert_eq!(side, resolve_revision(&conn, &abbrev).unwrap());
+        assert_eq!(side, resolve_revision(&conn, &side.to_string()).unwrap());
+
+        assert!(resolve_revision(&conn, "HEAD~3").is_err());
+        assert!(resolve_revision(&conn, "nonexistent").is_err());
+
+        // FETCH_HEAD names the first tip of the last fetch
+        assert!(resolve_revision(&conn, "FETCH_HEAD").is_err());
+        let fetched = [side, root].map(|commit_id| FetchHead {
+            commit_id,
+            description: "branch 'main' of ../other".to_string(),
+        });
+        FetchHead::replace_all(&conn, &fetched).unwrap();
+        assert_eq!(root, resolve_revision(&conn, "FETCH_HEAD^").unwrap());
+
+        // Remote-tracking branches are named after their remote
+        Ref {
+            name: format!("{}origin/main", REMOTE_PREFIX),
+            commit_id: second,
+        }
+        .persist_or_update(&conn)
+        .unwrap();
+        assert_eq!(second, resolve_revision(&conn, "origin/main").unwrap());
+        assert_eq!(root, resolve_revision(&conn, "origin/main~").unwrap());
+
+        Ref {
+            name: format!("{}v1.0", TAG_PREFIX),
+            commit_id: root,
+        }
+        .persist_or_update(&conn)
+        .unwrap();
+        assert_eq!(root, resolve_revision(&conn, "v1.0").unwrap());
+    }
+
+    #[test]
+    fn test_resolve_tag() {
+        let conn = setup();
+        let root = make_commit(&conn, vec![], "root");
+        let tag = Tag::new(
+            "v1.0".to_string(),
+            root.into(),
+            ("A U Thor".to_string(), "author@example.com".to_string()),
+            1_700_000_000,
+            "Release 1.0".to_string(),
+        )
+        .with_id(HashAlgorithm::Sha1);
+        tag.persist(&conn).unwrap();
+        for (name, commit_id) in [("refs/tags/v1.0", root), ("refs/tags/v2.0", root)] {
+            Ref {
+                name: name.to_string(),
+                commit_id,
+            }
+            .persist_or_update(&conn)
+            .unwrap();
+        }
+
+        assert_eq!(Some(&tag), resolve_tag(&conn, "v1.0").unwrap().as_ref());
+        assert_eq!(
+            Some(&tag),
+            resolve_tag(&conn, "refs/tags/v1.0").unwrap().as_ref()
+        );
+        let abbrev = &tag.tag_id.to_string()[..8];
+        assert_eq!(Some(&tag), resolve_tag(&conn, abbrev).unwrap().as_ref());
+        // The hash of the tag names the tagged commit
+        assert_eq!(root, resolve_revision(&conn, abbrev).unwrap());
+        // Lightweight tags have no annotation
+        assert_eq!(None, resolve_tag(&conn, "v2.0").unwrap());
+        let full_name = |name: &str| find_ref(&conn, name).unwrap().map(|r| r.name);
+        assert_eq!(Some("refs/tags/v2.0".to_string()), full_name("v2.0"));
+        assert_eq!(
+            Some("refs/tags/v2.0".to_string()),
+            full_name("refs/tags/v2.0")
+        );
+        assert_eq!(None, full_name("v3.0"));
+
+        // A branch of the same name takes precedence
+        Ref {
+            name: format!("{}v1.0", BRANCH_PREFIX),
+            commit_id: root,
+        }
+        .persist_or_update(&conn)
+        .unwrap();
+        assert_eq!(None, resolve_tag(&conn, "v1.0").unwrap());
+        assert_eq!(Some(format!("{}v1.0", BRANCH_PREFIX)), full_name("v1.0"));
+    }
+
+    #[test]
+    fn test_resolve_commits() {
+        let conn = setup();
+        let root = make_commit(&conn, vec![], "root");
+        let side = make_commit(&conn, vec![root], "side");
+        let second = make_commit(&conn, vec![root], "second");
+        let merge = make_commit(&conn, vec![second, side], "merge");
+        let tip = make_commit(&conn, vec![merge], "tip");
+
+        Ref {
+            name: format!("{}main", BRANCH_PREFIX),
+            commit_id: tip,
+        }
+        .persist_or_update(&conn)
+        .unwrap();
+        Head::Branch(format!("{}main", BRANCH_PREFIX))
+            .persist(&conn)
+            .unwrap();
+
+        assert_eq!(vec![side], resolve_commits(&conn, "main^^2").unwrap());
+        assert_eq!(
+            vec![second, side, merge, tip],
+            resolve_commits(&conn, "main~3..main").unwrap()
+        );
+        assert_eq!(
+            vec![side, merge, tip],
+            resolve_commits(&conn, &format!("{}..", second)).unwrap()
+        );
+        assert!(resolve_commits(&conn, "main..main").unwrap().is_empty());
+        assert!(resolve_commits(&conn, "main..nonexistent").is_err());
+    }
+
+    #[test]
+    fn test_resolve_tree() {
+        let conn = setup();
+        let commit = make_commit(&conn, vec![], "root");
+        Ref {
+            name: format!("{}main", BRANCH_PREFIX),
+            commit_id: commit,
+        }
+        .persist_or_update(&conn)
+        .unwrap();
+        let tree_id = Sha1Id::try_from(vec![0xab; 20]).unwrap();
+        conn.execute(
+            "INSERT INTO Trees (tree_id, data) VALUES (?1, '')",
+            [tree_id],
+        )
+        .unwrap();
+
+        let empty = Sha1Id::try_from(vec![0; 20]).unwrap();
+        assert_eq!(empty, resolve_tree(&conn, "main").unwrap());
+        assert_eq!(empty, resolve_tree(&conn, "main^{tree}").unwrap());
+        assert_eq!(tree_id, resolve_tree(&conn, "abababab").unwrap());
+        assert!(resolve_tree(&conn, "cdcdcdcd").is_err());
+    }
+
+    #[test]
+    fn test_resolve_object_id() {
+        let conn = setup();
+        let commit = make_commit(&conn, vec![], "root");
+        let blob_id = persist_blob(&conn, b"content");
+
+        let hex = blob_id.to_string();
+        assert_eq!(blob_id, resolve_object_id(&conn, &hex).unwrap());
+        assert_eq!(blob_id, resolve_object_id(&conn, &hex[..8]).unwrap());
+        assert_eq!(
+            commit,
+            resolve_object_id(&conn, &commit.to_string()[..6]).unwrap()
+        );
+        assert!(resolve_object_id(&conn, &"0".repeat(40)).is_err());
+        assert!(resolve_object_id(&conn, "main").is_err());
+    }
+
+    #[test]
+    fn test_resolve_path_object() {
+        let conn = setup();
+        create_tables(&conn, &[CREATE_INDEX_TABLE]);
+        let file = file_entry(&conn, "fn main() {}\n");
+        let blob_id = file.id;
+        let mut view = TreeView::new();
+        view.insert("src/main.rs".to_string(), file.clone());
+        let tree_id = write_tree(&conn, &view).unwrap();
+        let commit_id = persist_commit(&conn, new_commit(tree_id, vec![], "root"));
+        Ref {
+            name: format!("{}main", BRANCH_PREFIX),
+            commit_id,
+        }
+        .persist_or_update(&conn)
+        .unwrap();
+        Index {
+            entries: vec![blob_index_entry("staged.rs", &file, 0)],
+        }
+        .persist(&conn)
+        .unwrap();
+
+        let resolve = |name: &str| resolve_path_object(&conn, name).unwrap();
+        assert_eq!(None, resolve("main"));
+        assert_eq!(
+            Some((TreeEntryType::Blob, blob_id)),
+            resolve("main:src/main.rs")
+        );
+        assert_eq!(Some((TreeEntryType::Tree, tree_id)), resolve("main:"));
+        assert_eq!(
+            TreeEntryType::Tree,
+            resolve(&format!("{}:src/", tree_id)).unwrap().0
+        );
+        assert_eq!(Some((TreeEntryType::Blob, blob_id)), resolve(":staged.rs"));
+        assert_eq!(tree_id, resolve_tree(&conn, "main:").unwrap());
+        assert!(resolve_tree(&conn, "main:src/main.rs").is_err());
+        assert!(resolve_tree(&conn, ":staged.rs").is_err());
+        assert!(resolve_path_object(&conn, "main:src/lib.rs").is_err());
+        assert!(resolve_path_object(&conn, "main:src/main.rs/x").is_err());
+        assert!(resolve_path_object(&conn, ":src/main.rs").is_err());
+    }
+}
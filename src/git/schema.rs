@@ -0,0 +1,249 @@
+//! This module brings the database of a repository created by an older version of gitqlite up
+//! to the current schema. The version of the schema is recorded in `PRAGMA user_version`, and
+//! every writable connection whose database is behind creates the tables added since, so that
+//! commands can rely on every table existing. Authors and committers stored inline in the
+//! commits move to the Identities table. Derived tables are filled in for the history
+//! written before they existed.
+
+use rusqlite::{Connection, TransactionBehavior};
+
+use crate::repo::db::{table_exists, HashAlgorithm, CREATE_REFLOG_TABLE, CREATE_TAGS_TABLE};
+
+use super::{
+    file_changes::{
+        record_all_file_changes, CREATE_FILE_CHANGES_PATH_INDEX, CREATE_FILE_CHANGES_TABLE,
+        CREATE_RENAMES_VIEW,
+    },
+    manifest, model,
+    pin::CREATE_PINNED_OBJECTS_TABLE,
+    reachability::{record_all_reachability, CREATE_COMMIT_GENERATIONS_TABLE},
+    search::{index_commit_messages, CREATE_COMMIT_MESSAGES_TABLE},
+    snapshot::CREATE_SNAPSHOTS_TABLE,
+    transfer::CREATE_TRANSFER_STATE_TABLE,
+    utils::WriteTransaction,
+};
+
+/// Version of the schema created by `gitqlite init`, stored in `PRAGMA user_version`
+pub const SCHEMA_VERSION: i64 = 3;
+
+/// Tables added to the schema since the first release, with the statements creating them
+const ADDED_TABLES: &[(&str, &[&str])] = &[
+    ("MergeHead", &[model::CREATE_MERGE_HEAD_TABLE]),
+    ("RebaseState", &[model::CREATE_REBASE_STATE_TABLE]),
+    ("CherryPickState", &[model::CREATE_CHERRY_PICK_STATE_TABLE]),
+    ("Stash", &[model::CREATE_STASH_TABLE]),
+    ("FetchHead", &[model::CREATE_FETCH_HEAD_TABLE]),
+    ("Tags", &[CREATE_TAGS_TABLE]),
+    ("Reflog", &[CREATE_REFLOG_TABLE]),
+    (
+        "FileChanges",
+        &[CREATE_FILE_CHANGES_TABLE, CREATE_FILE_CHANGES_PATH_INDEX],
+    ),
+    ("CommitGenerations", &[CREATE_COMMIT_GENERATIONS_TABLE]),
+    ("TransferState", &[CREATE_TRANSFER_STATE_TABLE]),
+    ("PinnedObjects", &[CREATE_PINNED_OBJECTS_TABLE]),
+    ("Snapshots", &[CREATE_SNAPSHOTS_TABLE]),
+];
+
+/// The version of the schema of the database
+pub fn schema_version(conn: &Connection) -> crate::Result<i64> {
+    Ok(conn.pragma_query_value(None, "user_version", |row| row.get(0))?)
+}
+
+/// Bring the database up to the current schema if it is behind. The connection must be
+/// prepared by [`manifest::prepare_connection`], whose triggers the new tables get. A database
+/// `gitqlite init` has not set up yet is left alone.
+pub fn upgrade_schema(conn: &mut Connection) -> crate::Result<()> {
+    if schema_version(conn)? >= SCHEMA_VERSION || !table_exists(conn, "Commits")? {
+        return Ok(());
+    }
+
+    let txn = WriteTransaction::with_behavior(conn, TransactionBehavior::Immediate)?;
+    // Another process may have upgraded the database while this one waited for the lock
+    if schema_version(&txn)? < SCHEMA_VERSION {
+        if !table_exists(&txn, "ObjectFormat")? {
+            HashAlgorithm::Sha1.persist(&txn)?;
+            manifest::track_table(&txn, "ObjectFormat")?;
+        }
+        // Version 2 stored the transitive closure of the commit graph, which generations replace
+        if table_exists(&txn, "Reachability")? {
+            txn.execute("DROP TABLE Reachability", ())?;
+            if table_exists(&txn, "Manifest")? {
+                txn.execute("DELETE FROM Manifest WHERE table_name = 'Reachability'", ())?;
+            }
+        }
+        // Rebuilding the commits drops the triggers indexing their messages, which
+        // `create_added_tables` puts back
+        model::migrate_commit_identities(&txn)?;
+        create_added_tables(&txn)?;
+        if table_exists(&txn, "Manifest")? {
+            txn.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+        } else {
+            manifest::create_manifest(&txn)?;
+        }
+    }
+    txn.commit()?;
+    Ok(())
+}
+
+/// Create the tables added since the first release that the database lacks, and fill in the
+/// derived ones
+pub fn create_added_tables(conn: &Connection) -> crate::Result<()> {
+    let mut created = Vec::new();
+    for (name, statements) in ADDED_TABLES {
+        if table_exists(conn, name)? {
+            continue;
+        }
+        for statement in *statements {
+            conn.execute(statement, ())?;
+        }
+        manifest::track_table(conn, name)?;
+        created.push(*name);
+    }
+    if !table_exists(conn, "Renames")? {
+        conn.execute(CREATE_RENAMES_VIEW, ())?;
+    }
+    if !table_exists(conn, "CommitMessages")? {
+        conn.execute(CREATE_COMMIT_MESSAGES_TABLE, ())?;
+    }
+
+    // The derived tables are filled in for the history written before they existed
+    if created.contains(&"FileChanges") {
+        record_all_file_changes(conn)?;
+    }
+    record_all_reachability(conn)?;
+    index_commit_messages(conn)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use rusqlite::params;
+    use sha1::Digest;
+
+    use super::*;
+    use crate::git::{
+        manifest::verify_database,
+        model::{commit_layout, Commit, CommitLayout, Hashable, Tree},
+        search::{search_commit_messages, SearchQuery},
+        test_utils::{authored_by, new_commit, persist_commit},
+    };
+
+    /// The schema `gitqlite init` created in the first release
+    const BASELINE_SCHEMA: &str = "CREATE TABLE Index_ (index_ JSON);
+        CREATE TABLE Head (head JSON);
+        CREATE TABLE Refs (ref_name TEXT PRIMARY KEY, commit_id BLOB NOT NULL);
+        CREATE TABLE Commits (commit_id BLOB PRIMARY KEY, tree_id TEXT NOT NULL, parent_ids BLOB NOT NULL, author_name TEXT NOT NULL, author_email TEXT NOT NULL, committer_name TEXT NOT NULL, committer_email TEXT NOT NULL, message TEXT NOT NULL);
+        CREATE TABLE Trees (tree_id TEXT PRIMARY KEY, data TEXT NOT NULL);
+        CREATE TABLE Blobs (blob_id TEXT, data BLOB NOT NULL);";
+
+    #[test]
+    fn test_upgrade_schema() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(BASELINE_SCHEMA).unwrap();
+        manifest::prepare_connection(&conn).unwrap();
+
+        upgrade_schema(&mut conn).unwrap();
+        assert_eq!(SCHEMA_VERSION, schema_version(&conn).unwrap());
+        for (name, _) in ADDED_TABLES {
+            assert!(table_exists(&conn, name).unwrap(), "{} is missing", name);
+        }
+        for name in ["Renames", "CommitMessages", "ObjectFormat", "Manifest"] {
+            assert!(table_exists(&conn, name).unwrap(), "{} is missing", name);
+        }
+        assert!(verify_database(&conn).unwrap().is_empty());
+
+        // An up to date database is left alone
+        upgrade_schema(&mut conn).unwrap();
+        assert!(verify_database(&conn).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_upgrade_baseline_commits() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(BASELINE_SCHEMA).unwrap();
+        manifest::prepare_connection(&conn).unwrap();
+
+        let tree = Tree::new(vec![]);
+        let tree_id = tree.hash(sha1::Sha1::new());
+        tree.with_id(tree_id).persist(&conn).unwrap();
+
+        // Commits as the first release wrote them, with the identities inline
+        let mut commits = Vec::new();
+        let mut parent_ids = vec![];
+        for (author, message) in [("alice", "first"), ("bob", "second"), ("alice", "third")] {
+            let commit = Commit::new(
+                tree_id,
+                parent_ids.clone(),
+                author.to_string(),
+                format!("{}@example.com", author),
+                "carol".to_string(),
+                "carol@example.com".to_string(),
+                message.to_string(),
+            );
+            let id = commit.hash(sha1::Sha1::new());
+            let commit = commit.with_id(id);
+            conn.execute(
+                "INSERT INTO Commits (commit_id, tree_id, parent_ids, author_name, author_email, committer_name, committer_email, message) VALUES (?1, ?2, coalesce(?3, X''), ?4, ?5, ?6, ?7, ?8);",
+                params![
+                    commit.commit_id,
+                    commit.tree_id,
+                    parent_ids.first(),
+                    commit.author_name,
+                    commit.author_email,
+                    commit.committer_name,
+                    commit.committer_email,
+                    commit.message
+                ],
+            )
+            .unwrap();
+            parent_ids = vec![commit.commit_id];
+            commits.push(commit);
+        }
+        conn.execute(
+            "INSERT INTO Refs (ref_name, commit_id) VALUES ('refs/head/main', ?1);",
+            [parent_ids[0]],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO Head (head) VALUES ('{\"Branch\":\"refs/head/main\"}');",
+            (),
+        )
+        .unwrap();
+        assert_eq!(CommitLayout::Legacy, commit_layout(&conn, "main").unwrap());
+
+        upgrade_schema(&mut conn).unwrap();
+        assert_eq!(
+            CommitLayout::Identities,
+            commit_layout(&conn, "main").unwrap()
+        );
+        let identities: i64 = conn
+            .query_row("SELECT count(*) FROM Identities", (), |row| row.get(0))
+            .unwrap();
+        assert_eq!(3, identities);
+        for commit in &commits {
+            assert_eq!(
+                commit,
+                &Commit::read_from_conn_with_id(&conn, commit.commit_id).unwrap()
+            );
+        }
+
+        // The messages of the rebuilt commits are indexed, and so are the ones written later
+        let search = |text: &str| {
+            let query = SearchQuery {
+                text: text.to_string(),
+                ..Default::default()
+            };
+            search_commit_messages(&conn, &query, ("", ""))
+                .unwrap()
+                .len()
+        };
+        assert_eq!(1, search("second"));
+        persist_commit(
+            &conn,
+            authored_by(new_commit(tree_id, parent_ids, "fourth"), "bob"),
+        );
+        assert_eq!(1, search("fourth"));
+        assert!(verify_database(&conn).unwrap().is_empty());
+    }
+}
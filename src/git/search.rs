@@ -0,0 +1,211 @@
+//! This module maintains the `CommitMessages` table, an SQLite FTS5 full-text index of the
+//! messages of the commits, and searches it. The index is kept in sync with the `Commits`
+//! table by triggers, so commits are indexed as they are written or received and forgotten as
+//! they are pruned. Messages are tokenized with the Porter stemmer, so that a search for
+//! `fix` also finds `fixes` and `fixed`.
+
+use anyhow::anyhow;
+use chrono::NaiveDate;
+use rusqlite::Connection;
+use serde::Serialize;
+
+use super::model::Sha1Id;
+
+pub const CREATE_COMMIT_MESSAGES_TABLE: &str = "CREATE VIRTUAL TABLE CommitMessages USING fts5(commit_id UNINDEXED, message, tokenize = 'porter unicode61');";
+/// Triggers keeping the index in sync with the commits. They are dropped along with the
+/// `Commits` table when the schema upgrade rebuilds it, and recreated by [`index_commit_messages`].
+pub const CREATE_COMMIT_MESSAGES_TRIGGERS: &str =
+    "CREATE TRIGGER IF NOT EXISTS CommitMessages_insert AFTER INSERT ON Commits BEGIN
+    INSERT INTO CommitMessages (commit_id, message) VALUES (NEW.commit_id, NEW.message);
+END;
+CREATE TRIGGER IF NOT EXISTS CommitMessages_delete AFTER DELETE ON Commits BEGIN
+    DELETE FROM CommitMessages WHERE commit_id = OLD.commit_id;
+END;";
+const INDEX_MISSING_MESSAGES: &str = "INSERT INTO CommitMessages (commit_id, message) SELECT commit_id, message FROM Commits WHERE commit_id NOT IN (SELECT commit_id FROM CommitMessages);";
+/// The best matches first, the newest first among equally good ones. The author pattern is
+/// matched against `Name <email>`, and the dates bound `created_at` as `[since, until)`.
+const SEARCH_COMMIT_MESSAGES: &str = "SELECT c.commit_id, a.name, a.email, c.created_at, snippet(CommitMessages, 1, ?1, ?2, '...', ?3)
+    FROM CommitMessages m
+    JOIN Commits c ON c.commit_id = m.commit_id
+    JOIN Identities a ON a.identity_id = c.author_id
+    WHERE CommitMessages MATCH ?4
+        AND (?5 IS NULL OR instr(a.name || ' <' || a.email || '>', ?5) > 0)
+        AND (?6 IS NULL OR c.created_at >= ?6)
+        AND (?7 IS NULL OR c.created_at < ?7)
+    ORDER BY m.rank, c.created_at DESC";
+
+/// Number of tokens of the message shown around the matches
+const SNIPPET_TOKENS: i64 = 16;
+
+/// [`SearchQuery`] is a full-text query of the commit messages along with the filters of the
+/// commits it applies to
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SearchQuery {
+    /// FTS5 query: words, `"phrases"`, `prefix*`, combined with `AND`, `OR` and `NOT`
+    pub text: String,
+    /// Only commits whose author, as `Name <email>`, contains this
+    pub author: Option<String>,
+    /// Only commits written at or after this time, in seconds since the epoch
+    pub since: Option<i64>,
+    /// Only commits written before this time, in seconds since the epoch
+    pub until: Option<i64>,
+}
+
+/// [`SearchHit`] is a commit whose message matches a [`SearchQuery`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SearchHit {
+    pub commit_id: Sha1Id,
+    pub author_name: String,
+    pub author_email: String,
+    /// Seconds since the epoch
+    pub created_at: i64,
+    /// The part of the message around the matches, with the matched terms between the
+    /// markers given to [`search_commit_messages`]
+    pub snippet: String,
+}
+
+/// Create the triggers of the index if they are missing, and index the commits it lacks,
+/// returning their number
+pub fn index_commit_messages(conn: &Connection) -> crate::Result<usize> {
+    conn.execute_batch(CREATE_COMMIT_MESSAGES_TRIGGERS)?;
+    Ok(conn.execute(INDEX_MISSING_MESSAGES, ())?)
+}
+
+/// Search the commit messages, highlighting the matched terms of the snippets between
+/// `markers` (opening, closing)
+pub fn search_commit_messages(
+    conn: &Connection,
+    query: &SearchQuery,
+    markers: (&str, &str),
+) -> crate::Result<Vec<SearchHit>> {
+    let mut stmt = conn.prepare(SEARCH_COMMIT_MESSAGES)?;
+    let hits = stmt
+        .query_map(
+            (
+                markers.0,
+                markers.1,
+                SNIPPET_TOKENS,
+                &query.text,
+                &query.author,
+                query.since,
+                query.until,
+            ),
+            |row| {
+                Ok(SearchHit {
+                    commit_id: row.get(0)?,
+                    author_name: row.get(1)?,
+                    author_email: row.get(2)?,
+                    created_at: row.get(3)?,
+                    snippet: row.get(4)?,
+                })
+            },
+        )?
+        .collect::<Result<Vec<_>, _>>();
+    // The query is the only part of the statement the user writes, so a plain SQL error is
+    // a syntax error of the query
+    hits.map_err(|e| match e {
+        rusqlite::Error::SqliteFailure(err, Some(message))
+            if err.code == rusqlite::ErrorCode::Unknown =>
+        {
+            anyhow!("fatal: invalid search query '{}': {}", query.text, message)
+        }
+        e => e.into(),
+    })
+}
+
+/// The start of a day given as `YYYY-MM-DD`, in seconds since the epoch, UTC
+pub fn parse_day(day: &str) -> crate::Result<i64> {
+    NaiveDate::parse_from_str(day, "%Y-%m-%d")
+        .ok()
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .map(|time| time.and_utc().timestamp())
+        .ok_or_else(|| anyhow!("fatal: invalid date '{}', expected YYYY-MM-DD", day))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::test_utils::{authored_by, new_commit, object_database, persist_commit};
+
+    fn commit(conn: &Connection, author: &str, message: &str, created_at: i64) -> Sha1Id {
+        let tree_id = Sha1Id::try_from(vec![0; 20]).unwrap();
+        let commit_id = persist_commit(
+            conn,
+            authored_by(new_commit(tree_id, vec![], message), author),
+        );
+        conn.execute(
+            "UPDATE Commits SET created_at = ?2 WHERE commit_id = ?1",
+            (commit_id, created_at),
+        )
+        .unwrap();
+        commit_id
+    }
+
+    fn search(conn: &Connection, query: SearchQuery) -> Vec<(Sha1Id, String)> {
+        search_commit_messages(conn, &query, ("[", "]"))
+            .unwrap()
+            .into_iter()
+            .map(|hit| (hit.commit_id, hit.snippet))
+            .collect()
+    }
+
+    fn query(text: &str) -> SearchQuery {
+        SearchQuery {
+            text: text.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_search_commit_messages() {
+        let conn = object_database(&[CREATE_COMMIT_MESSAGES_TABLE]);
+        let old = commit(&conn, "alice", "Fix the parser", 1_000);
+        assert!(search(&conn, query("parser")).is_empty());
+
+        // Building the index indexes the existing commits, later ones are indexed as written
+        assert_eq!(1, index_commit_messages(&conn).unwrap());
+        assert_eq!(0, index_commit_messages(&conn).unwrap());
+        let new = commit(&conn, "bob", "Parser fixes\n\nThe parser fixed", 2_000);
+        assert_eq!(
+            vec![
+                (new, "[Parser] [fixes]\n\nThe [parser] [fixed]".to_string()),
+                (old, "[Fix] the [parser]".to_string()),
+            ],
+            search(&conn, query("fix parser"))
+        );
+
+        // Filters on the author and the date
+        let by_alice = SearchQuery {
+            author: Some("alice@".to_string()),
+            ..query("parser")
+        };
+        assert_eq!(vec![old], ids(search(&conn, by_alice)));
+        let recent = SearchQuery {
+            since: Some(1_500),
+            ..query("parser")
+        };
+        assert_eq!(vec![new], ids(search(&conn, recent)));
+        let before = SearchQuery {
+            until: Some(2_000),
+            ..query("parser")
+        };
+        assert_eq!(vec![old], ids(search(&conn, before)));
+
+        // Pruned commits are forgotten
+        conn.execute("DELETE FROM Commits WHERE commit_id = ?1", [old])
+            .unwrap();
+        assert_eq!(vec![new], ids(search(&conn, query("parser"))));
+
+        assert!(search_commit_messages(&conn, &query("\"unterminated"), ("[", "]")).is_err());
+    }
+
+    fn ids(hits: Vec<(Sha1Id, String)>) -> Vec<Sha1Id> {
+        hits.into_iter().map(|(id, _)| id).collect()
+    }
+
+    #[test]
+    fn test_parse_day() {
+        assert_eq!(86_400, parse_day("1970-01-02").unwrap());
+        assert!(parse_day("02/01/1970").is_err());
+    }
+}
@@ -0,0 +1,142 @@
+//! This module checks the signatures of annotated tags. A tag message may end with an
+//! ASCII-armored PGP signature of the tag's payload: the tag in git's format (`object`,
+//! `type`, `tag` and `tagger` headers, then the message without the signature), so that the
+//! signature made by `git tag -s` over the same tag verifies. The signature is checked by
+//! running `gpg.program` (`gpg` by default).
+
+use std::{fmt, io::Write, process::Command};
+
+use super::model::AnnotatedTag;
+use crate::repo::config::{ConfigSource, GitConfig};
+
+const SIGNATURE_BEGIN: &str = "-----BEGIN PGP SIGNATURE-----";
+
+/// [`SignatureStatus`] is the outcome of checking the signature of a tag
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SignatureStatus {
+    Unsigned,
+    Good,
+    Bad,
+    /// The signature could not be checked, for the given reason
+    CannotVerify(String),
+}
+
+impl fmt::Display for SignatureStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SignatureStatus::Unsigned => write!(f, "No signature"),
+            SignatureStatus::Good => write!(f, "Good signature"),
+            SignatureStatus::Bad => write!(f, "BAD signature"),
+            SignatureStatus::CannotVerify(reason) => {
+                write!(f, "Can't check signature: {}", reason)
+            }
+        }
+    }
+}
+
+/// The message of the tag without its signature, and the signature if there is one
+pub fn split_signature(message: &str) -> (&str, Option<&str>) {
+    match message.find(SIGNATURE_BEGIN) {
+        Some(start) => (&message[..start], Some(&message[start..])),
+        None => (message, None),
+    }
+}
+
+/// The text the signature of the tag is made over
+pub fn tag_payload(tag: &AnnotatedTag) -> String {
+    format!(
+        "object {}\ntype commit\ntag {}\ntagger {} <{}> {} +0000\n\n{}",
+        tag.object_id,
+        tag.name,
+        tag.tagger_name,
+        tag.tagger_email,
+        tag.tagged_at,
+        split_signature(&tag.message).0
+    )
+}
+
+/// Check the signature of the tag, if it is signed
+pub fn verify_tag(config: &GitConfig, tag: &AnnotatedTag) -> crate::Result<SignatureStatus> {
+    let Some(signature) = split_signature(&tag.message).1 else {
+        return Ok(SignatureStatus::Unsigned);
+    };
+    let program = config
+        .get("gpg.program", ConfigSource::All)?
+        .unwrap_or("gpg")
+        .to_string();
+
+    let mut signature_file = tempfile::NamedTempFile::new()?;
+    signature_file.write_all(signature.as_bytes())?;
+    let mut payload_file = tempfile::NamedTempFile::new()?;
+    payload_file.write_all(tag_payload(tag).as_bytes())?;
+    let output = Command::new(&program)
+        .arg("--verify")
+        .arg(signature_file.path())
+        .arg(payload_file.path())
+        .output();
+    Ok(match output {
+        Ok(output) if output.status.success() => SignatureStatus::Good,
+        Ok(_) => SignatureStatus::Bad,
+        Err(err) => SignatureStatus::CannotVerify(format!("cannot run {}: {}", program, err)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::repo::db::{HashAlgorithm, Sha1Id, Tag};
+
+    #[test]
+    fn test_verify_tag() {
+        let signature = format!("{}\n\nabc\n-----END PGP SIGNATURE-----\n", SIGNATURE_BEGIN);
+        let tag = Tag::new(
+            "v1.0".to_string(),
+            Sha1Id::try_from("0123456789abcdef0123456789abcdef01234567").unwrap(),
+            ("A U Thor".to_string(), "author@example.com".to_string()),
+            1_700_000_000,
+            format!("Release 1.0\n{}", signature),
+        )
+        .with_id(HashAlgorithm::Sha1);
+        assert_eq!(
+            ("Release 1.0\n", Some(signature.as_str())),
+            split_signature(&tag.message)
+        );
+        assert_eq!(
+            "object 0123456789abcdef0123456789abcdef01234567\ntype commit\ntag v1.0\ntagger A U Thor <author@example.com> 1700000000 +0000\n\nRelease 1.0\n",
+            tag_payload(&tag)
+        );
+
+        // The verifying program is given the signature and the payload
+        let dir = tempfile::tempdir().unwrap();
+        let config = |program: &str| {
+            fs::write(
+                dir.path().join("config"),
+                format!("[gpg]\n\tprogram = {}\n", program),
+            )
+            .unwrap();
+            GitConfig::load(dir.path()).unwrap()
+        };
+        assert_eq!(
+            SignatureStatus::Good,
+            verify_tag(&config("true"), &tag).unwrap()
+        );
+        assert_eq!(
+            SignatureStatus::Bad,
+            verify_tag(&config("false"), &tag).unwrap()
+        );
+        assert!(matches!(
+            verify_tag(&config("/nonexistent/gpg"), &tag).unwrap(),
+            SignatureStatus::CannotVerify(_)
+        ));
+        let unsigned = AnnotatedTag {
+            message: "Release 1.0\n".to_string(),
+            ..tag
+        };
+        assert_eq!(
+            SignatureStatus::Unsigned,
+            verify_tag(&config("false"), &unsigned).unwrap()
+        );
+    }
+}
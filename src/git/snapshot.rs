@@ -0,0 +1,320 @@
+//! This module maintains the `Snapshots` table: named copies of the state of the repository,
+//! which are HEAD, the refs and the index, so that a CI job can save the exact state it
+//! worked on and restore it later without archiving the whole directory. The objects are not
+//! copied, as the repository keeps them: `gitqlite prune` treats snapshots like refs.
+//!
+//! A snapshot is content addressed: its id is the hash of its state, leaving out the stat data
+//! of the index, which depends on the machine. Two jobs capturing the same state get the same
+//! id, which makes it a cache key, and restoring checks the state still matches its id. The
+//! restored index has no stat data either, so the next `status` hashes the tracked files.
+
+use std::collections::BTreeMap;
+
+use anyhow::anyhow;
+use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+use super::model::{Head, Index, IndexEntry, ModeType, Ref, Sha1Id};
+use crate::repo::db::{HashAlgorithm, Hasher, OldValue, RefTransaction};
+
+pub const CREATE_SNAPSHOTS_TABLE: &str = "CREATE TABLE Snapshots (name TEXT PRIMARY KEY, snapshot_id BLOB NOT NULL, state JSON NOT NULL, created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now')));";
+const READ_SNAPSHOT: &str = "SELECT snapshot_id, state FROM Snapshots WHERE name = ?1";
+const READ_SNAPSHOTS: &str =
+    "SELECT name, snapshot_id, created_at, state FROM Snapshots ORDER BY name";
+const INSERT_OR_REPLACE_SNAPSHOT: &str =
+    "INSERT OR REPLACE INTO Snapshots (name, snapshot_id, state) VALUES (?1, ?2, ?3);";
+const DELETE_SNAPSHOT: &str = "DELETE FROM Snapshots WHERE name = ?1";
+
+/// [`SnapshotEntry`] is an index entry as a snapshot records it, without its stat data
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotEntry {
+    pub name: String,
+    pub stage: u8,
+    pub mode_type: ModeType,
+    pub mode_perms: u32,
+    pub sha: Sha1Id,
+    pub intent_to_add: bool,
+}
+
+/// [`RepoState`] is what a snapshot records
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RepoState {
+    pub head: Head,
+    pub refs: BTreeMap<String, Sha1Id>,
+    /// Sorted by name and stage
+    pub index: Vec<SnapshotEntry>,
+}
+
+/// [`Snapshot`] is a row of the `Snapshots` table
+#[derive(Debug)]
+pub struct Snapshot {
+    pub name: String,
+    pub snapshot_id: Sha1Id,
+    pub created_at: i64,
+    pub state: RepoState,
+}
+
+impl RepoState {
+    /// The current state of the repository
+    pub fn capture(conn: &Connection) -> crate::Result<RepoState> {
+        let refs = Ref::read_all(conn)?
+            .into_iter()
+            .map(|r| (r.name, r.commit_id))
+            .collect();
+        let mut index: Vec<SnapshotEntry> = Index::read_from_conn(conn)?
+            .entries
+            .into_iter()
+            .map(|entry| SnapshotEntry {
+                name: entry.name,
+                stage: entry.flag_stage,
+                mode_type: entry.mode_type,
+                mode_perms: entry.mode_perms,
+                sha: entry.sha,
+                intent_to_add: entry.flag_intent_to_add,
+            })
+            .collect();
+        index.sort_by(|a, b| (&a.name, a.stage).cmp(&(&b.name, b.stage)));
+        Ok(RepoState {
+            head: Head::read_from_conn(conn)?,
+            refs,
+            index,
+        })
+    }
+
+    /// The id of the state, the hash of its canonical JSON form
+    pub fn id(&self, algorithm: HashAlgorithm) -> crate::Result<Sha1Id> {
+        let mut hasher = algorithm.hasher();
+        hasher.update(serde_json::to_string(self)?);
+        Sha1Id::try_from(hasher.finish().to_vec())
+    }
+
+    /// The commits and blobs the state refers to, which must be kept in the repository
+    pub fn objects(&self) -> (Vec<Sha1Id>, Vec<Sha1Id>) {
+        let mut commits: Vec<Sha1Id> = self.refs.values().copied().collect();
+        if let Head::Commit(id) = self.head {
+            commits.push(id);
+        }
+        let blobs = self.index.iter().map(|entry| entry.sha).collect();
+        (commits, blobs)
+    }
+
+    /// The index of the state, without stat data
+    fn to_index(&self) -> Index {
+        let entries = self
+            .index
+            .iter()
+            .map(|entry| IndexEntry {
+                ctime: 0,
+                mtime: 0,
+                dev: 0,
+                ino: 0,
+                mode_type: entry.mode_type.clone(),
+                mode_perms: entry.mode_perms,
+                uid: 0,
+                gid: 0,
+                fsize: 0,
+                sha: entry.sha,
+                flag_assume_valid: false,
+                flag_stage: entry.stage,
+                flag_intent_to_add: entry.intent_to_add,
+                name: entry.name.clone(),
+            })
+            .collect();
+        Index { entries }
+    }
+}
+
+/// Record the current state of the repository as the snapshot `name`, replacing an existing
+/// one only if `force`. Returns the id of the snapshot.
+pub fn create_snapshot(conn: &Connection, name: &str, force: bool) -> crate::Result<Sha1Id> {
+    if name.is_empty() {
+        return Err(anyhow!("fatal: snapshot name cannot be empty"));
+    }
+    if !force && read_snapshot(conn, name)?.is_some() {
+        return Err(anyhow!("fatal: snapshot '{}' already exists", name));
+    }
+
+    let state = RepoState::capture(conn)?;
+    let id = state.id(HashAlgorithm::read(conn)?)?;
+    conn.execute(
+        INSERT_OR_REPLACE_SNAPSHOT,
+        (name, id, serde_json::to_string(&state)?),
+    )?;
+    Ok(id)
+}
+
+/// The snapshot `name`, if any, checking that its state matches its id
+pub fn read_snapshot(conn: &Connection, name: &str) -> crate::Result<Option<(Sha1Id, RepoState)>> {
+    let Some((id, state)) = conn
+        .query_row(READ_SNAPSHOT, [name], |row| {
+            Ok((row.get::<_, Sha1Id>(0)?, row.get::<_, String>(1)?))
+        })
+        .optional()?
+    else {
+        return Ok(None);
+    };
+    let state: RepoState = serde_json::from_str(&state)?;
+    if state.id(HashAlgorithm::read(conn)?)? != id {
+        return Err(anyhow!(
+            "fatal: snapshot '{}' does not match its id {}",
+            name,
+            id
+        ));
+    }
+    Ok(Some((id, state)))
+}
+
+/// Every snapshot, by name
+pub fn snapshots(conn: &Connection) -> crate::Result<Vec<Snapshot>> {
+    let mut stmt = conn.prepare(READ_SNAPSHOTS)?;
+    let rows = stmt.query_map((), |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, Sha1Id>(1)?,
+            row.get::<_, i64>(2)?,
+            row.get::<_, String>(3)?,
+        ))
+    })?;
+    let mut snapshots = Vec::new();
+    for row in rows {
+        let (name, snapshot_id, created_at, state) = row?;
+        snapshots.push(Snapshot {
+            name,
+            snapshot_id,
+            created_at,
+            state: serde_json::from_str(&state)?,
+        });
+    }
+    Ok(snapshots)
+}
+
+/// Bring HEAD, the refs and the index back to the state. The working tree is left alone.
+pub fn restore_snapshot(txn: &Connection, name: &str, state: &RepoState) -> crate::Result<()> {
+    let mut transaction = RefTransaction::new(format!("snapshot: restore {}", name));
+    for existing in Ref::read_all(txn)? {
+        if !state.refs.contains_key(&existing.name) {
+            transaction.delete(existing.name, OldValue::Is(existing.commit_id.into()));
+        }
+    }
+    for (ref_name, commit_id) in &state.refs {
+        transaction.update(ref_name.clone(), OldValue::Any, (*commit_id).into());
+    }
+    transaction.commit(txn)?;
+    state.head.persist(txn)?;
+    state.to_index().persist(txn)
+}
+
+/// Delete the snapshot `name`. Returns whether it existed.
+pub fn delete_snapshot(conn: &Connection, name: &str) -> crate::Result<bool> {
+    Ok(conn.execute(DELETE_SNAPSHOT, [name])? > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::model::{CREATE_HEAD_TABLE, CREATE_INDEX_TABLE, CREATE_REF_TABLE};
+    use crate::repo::db::CREATE_REFLOG_TABLE;
+
+    fn id(byte: u8) -> Sha1Id {
+        Sha1Id::try_from(vec![byte; 20]).unwrap()
+    }
+
+    fn entry(name: &str, sha: Sha1Id, mtime: i64) -> IndexEntry {
+        IndexEntry {
+            ctime: mtime,
+            mtime,
+            dev: 1,
+            ino: 2,
+            mode_type: ModeType::Regular,
+            mode_perms: 0o644,
+            uid: 3,
+            gid: 4,
+            fsize: 5,
+            sha,
+            flag_assume_valid: false,
+            flag_stage: 0,
+            flag_intent_to_add: false,
+            name: name.to_string(),
+        }
+    }
+
+    fn set_ref(conn: &mut Connection, name: &str, commit_id: Sha1Id) {
+        let txn = conn.transaction().unwrap();
+        let mut transaction = RefTransaction::new("test");
+        transaction.update(name, OldValue::Any, commit_id.into());
+        transaction.commit(&txn).unwrap();
+        txn.commit().unwrap();
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute(CREATE_INDEX_TABLE, ()).unwrap();
+        conn.execute(CREATE_HEAD_TABLE, ()).unwrap();
+        conn.execute(CREATE_REF_TABLE, ()).unwrap();
+        conn.execute(CREATE_SNAPSHOTS_TABLE, ()).unwrap();
+        conn.execute(CREATE_REFLOG_TABLE, ()).unwrap();
+        HashAlgorithm::Sha1.persist(&conn).unwrap();
+        Head::Branch("refs/head/main".to_string())
+            .persist(&conn)
+            .unwrap();
+        set_ref(&mut conn, "refs/head/main", id(1));
+        Index {
+            entries: vec![entry("b", id(3), 10), entry("a", id(2), 10)],
+        }
+        .persist(&conn)
+        .unwrap();
+
+        assert!(read_snapshot(&conn, "ci").unwrap().is_none());
+        let created = create_snapshot(&conn, "ci", false).unwrap();
+        assert!(create_snapshot(&conn, "ci", false).is_err());
+
+        // The id only depends on the content, not on the stat data or the order of the index
+        Index {
+            entries: vec![entry("a", id(2), 20), entry("b", id(3), 20)],
+        }
+        .persist(&conn)
+        .unwrap();
+        assert_eq!(created, create_snapshot(&conn, "ci", true).unwrap());
+
+        // Move everything away, then restore
+        set_ref(&mut conn, "refs/head/main", id(4));
+        set_ref(&mut conn, "refs/head/topic", id(5));
+        Head::Commit(id(5)).persist(&conn).unwrap();
+        Index {
+            entries: vec![entry("c", id(6), 30)],
+        }
+        .persist(&conn)
+        .unwrap();
+        assert_ne!(
+            created,
+            RepoState::capture(&conn)
+                .unwrap()
+                .id(HashAlgorithm::Sha1)
+                .unwrap()
+        );
+
+        let (read_id, state) = read_snapshot(&conn, "ci").unwrap().unwrap();
+        assert_eq!(created, read_id);
+        assert_eq!((vec![id(1)], vec![id(2), id(3)]), state.objects());
+        let txn = conn.transaction().unwrap();
+        restore_snapshot(&txn, "ci", &state).unwrap();
+        txn.commit().unwrap();
+        assert_eq!(state, RepoState::capture(&conn).unwrap());
+        assert_eq!(0, Index::read_from_conn(&conn).unwrap().entries[0].mtime);
+
+        assert_eq!(
+            vec!["ci"],
+            snapshots(&conn)
+                .unwrap()
+                .iter()
+                .map(|s| s.name.as_str())
+                .collect::<Vec<_>>()
+        );
+        conn.execute("UPDATE Snapshots SET snapshot_id = ?1", [id(9)])
+            .unwrap();
+        assert!(read_snapshot(&conn, "ci").is_err());
+        assert!(delete_snapshot(&conn, "ci").unwrap());
+        assert!(!delete_snapshot(&conn, "ci").unwrap());
+    }
+}
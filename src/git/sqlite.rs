@@ -0,0 +1,150 @@
+//! This module tunes the SQLite connections to the repository database from the `core.sqlite.*`
+//! config keys, which let users trade durability for speed, e.g. on large imports.
+//!
+//! The database is in WAL mode by default, so that read-only connections, such as the ones of
+//! `gitqlite web` or a long `gitqlite log`, see the last committed state without blocking the
+//! command writing to the database, nor being blocked by it.
+
+use anyhow::anyhow;
+use rusqlite::Connection;
+
+use crate::repo::config::{ConfigSource, GitConfig};
+
+/// The `core.sqlite.*` keys with their defaults, which are the defaults of SQLite itself but
+/// for the journal mode
+pub const SQLITE_CONFIG_DEFAULTS: [(&str, &str); 5] = [
+    ("core.sqlite.pageSize", "4096"),
+    ("core.sqlite.cacheSize", "-2000"),
+    ("core.sqlite.mmapSize", "0"),
+    ("core.sqlite.synchronous", "full"),
+    ("core.sqlite.journalMode", "wal"),
+];
+
+const SYNCHRONOUS_MODES: [&str; 4] = ["off", "normal", "full", "extra"];
+const JOURNAL_MODES: [&str; 6] = ["delete", "truncate", "persist", "memory", "wal", "off"];
+
+/// [`SqliteSettings`] are the pragmas applied to every connection opened on the database
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SqliteSettings {
+    /// Size of a database page in bytes, only taking effect when the database is created
+    pub page_size: i64,
+    /// Pages kept in memory if positive, or KiB of memory if negative
+    pub cache_size: i64,
+    /// Bytes of the database file accessed through memory-mapped I/O
+    pub mmap_size: i64,
+    pub synchronous: String,
+    pub journal_mode: String,
+}
+
+impl SqliteSettings {
+    pub fn from_config(config: &GitConfig) -> crate::Result<SqliteSettings> {
+        let get = |key: &str| -> crate::Result<String> {
+            let default = SQLITE_CONFIG_DEFAULTS
+                .iter()
+                .find(|(name, _)| *name == key)
+                .map(|(_, default)| *default)
+                .expect("every sqlite setting has a default");
+            Ok(config
+                .get(key, ConfigSource::All)?
+                .unwrap_or(default)
+                .to_ascii_lowercase())
+        };
+        let get_int = |key: &str| -> crate::Result<i64> {
+            let value = get(key)?;
+            value
+                .parse()
+                .map_err(|_| anyhow!("fatal: bad numeric config value '{}' for '{}'", value, key))
+        };
+        let get_mode = |key: &str, modes: &[&str]| -> crate::Result<String> {
+            let value = get(key)?;
+            if !modes.contains(&value.as_str()) {
+                return Err(anyhow!(
+                    "fatal: bad config value '{}' for '{}' (expected one of {})",
+                    value,
+                    key,
+                    modes.join(", ")
+                ));
+            }
+            Ok(value)
+        };
+
+        Ok(SqliteSettings {
+            page_size: get_int("core.sqlite.pageSize")?,
+            cache_size: get_int("core.sqlite.cacheSize")?,
+            mmap_size: get_int("core.sqlite.mmapSize")?,
+            synchronous: get_mode("core.sqlite.synchronous", &SYNCHRONOUS_MODES)?,
+            journal_mode: get_mode("core.sqlite.journalMode", &JOURNAL_MODES)?,
+        })
+    }
+
+    /// Set the pragmas on a connection
+    pub fn apply(&self, conn: &Connection) -> crate::Result<()> {
+        conn.pragma_update(None, "page_size", self.page_size)?;
+        conn.pragma_update(None, "cache_size", self.cache_size)?;
+        conn.pragma_update(None, "mmap_size", self.mmap_size)?;
+        conn.pragma_update(None, "synchronous", &self.synchronous)?;
+        conn.pragma_update_and_check(None, "journal_mode", &self.journal_mode, |_| Ok(()))?;
+        Ok(())
+    }
+
+    /// Set the pragmas on a read-only connection, which cannot change how the database is
+    /// written
+    pub fn apply_read_only(&self, conn: &Connection) -> crate::Result<()> {
+        conn.pragma_update(None, "cache_size", self.cache_size)?;
+        conn.pragma_update(None, "mmap_size", self.mmap_size)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+
+    #[test]
+    fn test_sqlite_settings() {
+        let dir = tempdir().unwrap();
+        let mut config = GitConfig::load(dir.path()).unwrap();
+        let defaults = SqliteSettings::from_config(&config).unwrap();
+        assert_eq!(4096, defaults.page_size);
+        assert_eq!("wal", defaults.journal_mode);
+
+        config
+            .set(
+                "core.sqlite.synchronous",
+                "OFF".to_string(),
+                ConfigSource::Local,
+            )
+            .unwrap();
+        config
+            .set(
+                "core.sqlite.journalMode",
+                "TRUNCATE".to_string(),
+                ConfigSource::Local,
+            )
+            .unwrap();
+        let settings = SqliteSettings::from_config(&config).unwrap();
+        assert_eq!("off", settings.synchronous);
+
+        let conn = Connection::open(dir.path().join("db.sqlite")).unwrap();
+        settings.apply(&conn).unwrap();
+        let journal_mode: String = conn
+            .pragma_query_value(None, "journal_mode", |row| row.get(0))
+            .unwrap();
+        assert_eq!("truncate", journal_mode);
+        let synchronous: i64 = conn
+            .pragma_query_value(None, "synchronous", |row| row.get(0))
+            .unwrap();
+        assert_eq!(0, synchronous);
+
+        config
+            .set(
+                "core.sqlite.cacheSize",
+                "lots".to_string(),
+                ConfigSource::Local,
+            )
+            .unwrap();
+        assert!(SqliteSettings::from_config(&config).is_err());
+    }
+}
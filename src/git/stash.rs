@@ -0,0 +1,354 @@
+//! This module implements the stash: local changes to tracked files are recorded as commits
+//! so that the working tree can be cleaned, and applied back later on top of another HEAD.
+//!
+//! A stash commit records the working tree; its first parent is the commit HEAD pointed to
+//! when stashing, and its second parent is a commit recording the index. When untracked files
+//! are stashed too, a third parent without parents of its own records them.
+//!
+//! A stash limited to some paths records the changes of those paths only: the other files are
+//! recorded as HEAD has them.
+
+use std::{collections::BTreeSet, fs, path::Path};
+
+use anyhow::anyhow;
+use rusqlite::Connection;
+
+use crate::{
+    cli::MergeStrategy,
+    repo::{config::GitConfig, db::HashAlgorithm},
+};
+
+use super::{
+    cmds::commit::commit_tree,
+    constants::BRANCH_PREFIX,
+    diff::{limit_to_paths, matches_paths},
+    files::GitqliteFileMetadataExt,
+    ignore::read_gitignore,
+    merge::{apply_merge, check_local_changes, merge_contents, merge_trees, MergeOutcome},
+    model::{Commit, Head, Index, Sha1Id, StashEntry},
+    progress::Progress,
+    tree::{flatten_tree, index_view, write_tree, FlatEntry, TreeView},
+    worktree::{
+        blob_index_entry, checkout_blob, hash_worktree_file, precompose_unicode,
+        read_worktree_blob, remove_worktree_file, reset_hard, worktree_files, worktree_name,
+    },
+};
+
+/// Names of HEAD and of the stash (ours, theirs) in the conflicts applying a stash
+pub const STASH_LABELS: (&str, &str) = ("Updated upstream", "Stashed changes");
+
+/// [`StashOptions`] tells which local changes a stash records
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StashOptions<'a> {
+    /// Record the untracked files too, which are not ignored
+    pub include_untracked: bool,
+    /// Only record the changes of these paths, relative to the repository root, if any
+    pub paths: &'a [String],
+}
+
+/// Record the local changes in a stash commit described by `message`. Returns None if there is
+/// nothing to stash. HEAD, the index and the working tree are left untouched.
+pub fn create_stash(
+    conn: &Connection,
+    repo_root: impl AsRef<Path>,
+    config: &GitConfig,
+    message: &str,
+    options: StashOptions,
+) -> crate::Result<Option<Sha1Id>> {
+    let repo_root = repo_root.as_ref();
+
+    let head = Head::read_from_conn(conn)?;
+    let head_id = head
+        .resolve(conn)?
+        .ok_or_else(|| anyhow!("fatal: You do not have the initial commit yet"))?;
+    let head_commit = Commit::read_from_conn_with_id(conn, head_id)?;
+    let head_view = flatten_tree(conn, head_commit.tree_id)?;
+
+    let index = Index::read_from_conn(conn)?;
+    if index.has_conflicts() {
+        return Err(anyhow!(
+            "error: could not save the local changes, you have unmerged paths: {}",
+            index.conflicted_names().join(", ")
+        ));
+    }
+
+    // Files outside of the paths are recorded as HEAD has them
+    let in_paths = |name: &str| matches_paths(name, options.paths);
+    let mut staged: TreeView = head_view
+        .iter()
+        .filter(|(name, _)| !in_paths(name))
+        .map(|(name, entry)| (name.clone(), entry.clone()))
+        .collect();
+    staged.extend(limit_to_paths(index_view(&index), options.paths));
+    let algorithm = HashAlgorithm::read(conn)?;
+    let mut worktree = staged.clone();
+    for (name, entry) in staged.iter().filter(|(name, _)| in_paths(name)) {
+        match hash_worktree_file(repo_root, name, algorithm)? {
+            None => {
+                worktree.remove(name);
+            }
+            Some(id) if id != entry.id => {
+                read_worktree_blob(repo_root, name, algorithm)?.persist(conn)?;
+                let mode = entry.mode.clone();
+                worktree.insert(name.clone(), FlatEntry { id, mode });
+            }
+            Some(_) => {}
+        }
+    }
+    let untracked = if options.include_untracked {
+        untracked_files(conn, repo_root, &index, options.paths)?
+    } else {
+        TreeView::new()
+    };
+
+    if staged == head_view && worktree == staged && untracked.is_empty() {
+        return Ok(None);
+    }
+
+    let branch = match &head {
+        Head::Branch(name) => name.strip_prefix(BRANCH_PREFIX).unwrap_or(name),
+        Head::Commit(_) => "(no branch)",
+    };
+    let summary = format!(
+        "{}: {} {}",
+        branch,
+        &head_id.to_string()[..7],
+        head_commit.message.lines().next().unwrap_or_default()
+    );
+
+    let index_commit = commit_tree(
+        conn,
+        config,
+        write_tree(conn, &staged)?,
+        vec![head_id],
+        format!("index on {}", summary),
+        None,
+    )?;
+    let mut parent_ids = vec![head_id, index_commit.commit_id];
+    if !untracked.is_empty() {
+        let untracked_commit = commit_tree(
+            conn,
+            config,
+            write_tree(conn, &untracked)?,
+            Vec::new(),
+            format!("untracked files on {}", summary),
+            None,
+        )?;
+        parent_ids.push(untracked_commit.commit_id);
+    }
+    let stash_commit = commit_tree(
+        conn,
+        config,
+        write_tree(conn, &worktree)?,
+        parent_ids,
+        format!("{} on {}", message, summary),
+        None,
+    )?;
+
+    Ok(Some(stash_commit.commit_id))
+}
+
+/// Snapshot of the untracked files which are not ignored, storing their content as blobs
+fn untracked_files(
+    conn: &Connection,
+    repo_root: &Path,
+    index: &Index,
+    paths: &[String],
+) -> crate::Result<TreeView> {
+    let precompose = precompose_unicode(repo_root)?;
+    let tracked: BTreeSet<&str> = index.entries.iter().map(|e| e.name.as_str()).collect();
+    let ignore = read_gitignore(repo_root.to_path_buf())?;
+
+    let algorithm = HashAlgorithm::read(conn)?;
+    let mut view = TreeView::new();
+    for path in worktree_files(repo_root, repo_root, &ignore)? {
+        let name = worktree_name(repo_root, &path, precompose)?;
+        if tracked.contains(name.as_str()) || !matches_paths(&name, paths) {
+            continue;
+        }
+        let blob = read_worktree_blob(repo_root, &name, algorithm)?;
+        blob.persist(conn)?;
+        let mode = fs::metadata(&path)?.g_mode_perms().to_string();
+        view.insert(
+            name,
+            FlatEntry {
+                id: blob.blob_id,
+                mode,
+            },
+        );
+    }
+    Ok(view)
+}
+
+/// Once a stash is recorded, bring the files it records back to HEAD: the changed files are
+/// checked out as HEAD has them, the files HEAD lacks are removed from the index and working
+/// tree, and so are the stashed untracked files.
+pub fn remove_stashed_changes(
+    conn: &Connection,
+    repo_root: impl AsRef<Path>,
+    index: &mut Index,
+    stash_id: Sha1Id,
+) -> crate::Result<()> {
+    let repo_root = repo_root.as_ref();
+    let stash = Commit::read_from_conn_with_id(conn, stash_id)?;
+    let [base_id, index_id, untracked_id @ ..] = stash.parent_ids.as_slice() else {
+        return Err(anyhow!("fatal: {} is not a stash commit", stash_id));
+    };
+    let base = flatten_tree(
+        conn,
+        Commit::read_from_conn_with_id(conn, *base_id)?.tree_id,
+    )?;
+    let staged = flatten_tree(
+        conn,
+        Commit::read_from_conn_with_id(conn, *index_id)?.tree_id,
+    )?;
+    let worktree = flatten_tree(conn, stash.tree_id)?;
+
+    let names: BTreeSet<&String> = base
+        .keys()
+        .chain(staged.keys())
+        .chain(worktree.keys())
+        .collect();
+    for name in names {
+        let entry = base.get(name);
+        if staged.get(name) == entry && worktree.get(name) == entry {
+            continue;
+        }
+        match entry {
+            Some(entry) => index.insert(checkout_blob(conn, repo_root, name, entry)?),
+            None => {
+                remove_worktree_file(repo_root, name)?;
+                index.remove_name(name);
+            }
+        }
+    }
+
+    if let Some(untracked_id) = untracked_id.first() {
+        let untracked = Commit::read_from_conn_with_id(conn, *untracked_id)?;
+        for name in flatten_tree(conn, untracked.tree_id)?.keys() {
+            remove_worktree_file(repo_root, name)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Apply the changes recorded in a stash commit on top of HEAD. Like `git stash apply`, the
+/// changes are left unstaged, except for files the stash adds. Conflicts are recorded in the
+/// index and working tree as for a merge. Each file written is reported to `progress`.
+pub fn apply_stash(
+    conn: &Connection,
+    repo_root: impl AsRef<Path>,
+    index: &mut Index,
+    stash_id: Sha1Id,
+    progress: &mut Progress,
+) -> crate::Result<MergeOutcome> {
+    let stash = Commit::read_from_conn_with_id(conn, stash_id)?;
+    let base_id = stash
+        .parent_ids
+        .first()
+        .ok_or_else(|| anyhow!("fatal: {} is not a stash commit", stash_id))?;
+    let base = flatten_tree(
+        conn,
+        Commit::read_from_conn_with_id(conn, *base_id)?.tree_id,
+    )?;
+
+    let head_id = Head::read_from_conn(conn)?
+        .resolve(conn)?
+        .ok_or_else(|| anyhow!("fatal: You do not have the initial commit yet"))?;
+    let ours = flatten_tree(conn, Commit::read_from_conn_with_id(conn, head_id)?.tree_id)?;
+    let theirs = flatten_tree(conn, stash.tree_id)?;
+
+    // Untracked files are restored as they were, but never over an existing file
+    let untracked = match stash.parent_ids.get(2) {
+        Some(untracked_id) => flatten_tree(
+            conn,
+            Commit::read_from_conn_with_id(conn, *untracked_id)?.tree_id,
+        )?,
+        None => TreeView::new(),
+    };
+    for name in untracked.keys() {
+        if repo_root.as_ref().join(name).exists() {
+            return Err(anyhow!(
+                "error: {} already exists, no checkout\nerror: could not restore untracked files from stash",
+                name
+            ));
+        }
+    }
+
+    let mut outcome = merge_trees(&base, &ours, &theirs, MergeStrategy::Ort, None);
+    merge_contents(conn, &mut outcome)?;
+    check_local_changes(conn, &repo_root, index, &ours, &outcome)?;
+    apply_merge(
+        conn,
+        &repo_root,
+        index,
+        &ours,
+        &outcome,
+        STASH_LABELS,
+        progress,
+    )?;
+
+    for (name, entry) in &untracked {
+        checkout_blob(conn, &repo_root, name, entry)?;
+    }
+
+    if outcome.conflicts.is_empty() {
+        for (name, entry) in &ours {
+            if outcome.tree.get(name) != Some(entry) {
+                index.insert(blob_index_entry(name, entry, 0));
+            }
+        }
+    }
+
+    Ok(outcome)
+}
+
+/// Bring back the local changes put aside by `--autostash` before a command rewrote the
+/// working tree. If they do not apply cleanly, the working tree is left clean and the changes
+/// are kept in the stash. The files touched are reported to `progress`, which is finished
+/// before telling how it went.
+pub fn apply_autostash(
+    conn: &Connection,
+    repo_root: &Path,
+    autostash: Option<Sha1Id>,
+    progress: &mut Progress,
+) -> crate::Result<()> {
+    let Some(stash_id) = autostash else {
+        return Ok(());
+    };
+
+    let mut index = Index::read_from_conn(conn)?;
+    let applied = match apply_stash(conn, repo_root, &mut index, stash_id, progress) {
+        Ok(outcome) => outcome.conflicts.is_empty(),
+        Err(_) => false,
+    };
+    if applied {
+        index.persist(conn)?;
+        progress.finish();
+        println!("Applied autostash.");
+        return Ok(());
+    }
+
+    let mut index = Index::read_from_conn(conn)?;
+    let head_id = Head::read_from_conn(conn)?
+        .resolve(conn)?
+        .ok_or_else(|| anyhow!("fatal: HEAD does not point to a commit"))?;
+    let head_view = flatten_tree(conn, Commit::read_from_conn_with_id(conn, head_id)?.tree_id)?;
+    reset_hard(conn, repo_root, &mut index, &head_view, progress)?;
+    index.persist(conn)?;
+    progress.finish();
+    println!("Applying autostash resulted in conflicts.");
+    keep_autostash(conn, stash_id)
+}
+
+/// Keep the autostash in the stash when it cannot be applied yet
+pub fn keep_autostash(conn: &Connection, stash_id: Sha1Id) -> crate::Result<()> {
+    StashEntry {
+        commit_id: stash_id,
+        message: "autostash".to_string(),
+    }
+    .push(conn)?;
+    println!("Your changes are safe in the stash as {}.", stash_id);
+    Ok(())
+}
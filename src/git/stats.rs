@@ -0,0 +1,305 @@
+//! This module computes statistics of the history of a branch for dashboards: the number of
+//! commits, of distinct authors, and of lines inserted and deleted, over the whole history, for
+//! each month, or for each author as the mailmap identifies them. Lines are counted from the `FileChanges` table, comparing the blobs each
+//! commit changed in its first parent, so merges count as commits but not as changed lines.
+//!
+//! Commits do not record when they were authored, so the month of a commit is the month it was
+//! written to the database, which for fetched history is the month it was received.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use rusqlite::Connection;
+use serde::Serialize;
+
+use crate::cli::StatsFormat;
+
+use super::{
+    diff::{diff_lines, looks_binary, split_lines, LineEdit},
+    file_changes::read_file_changes,
+    mailmap::Mailmap,
+    model::{Blob, Commit, Sha1Id},
+};
+
+const READ_COMMIT_MONTH: &str =
+    "SELECT strftime('%Y-%m', created_at, 'unixepoch') FROM Commits WHERE commit_id = ?1";
+const READ_COMMIT_DAY: &str =
+    "SELECT strftime('%Y-%m-%d', created_at, 'unixepoch') FROM Commits WHERE commit_id = ?1";
+
+/// Name of the period covering the whole history
+pub const TOTAL_PERIOD: &str = "total";
+
+/// [`PeriodStats`] sums up the commits of a period
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct PeriodStats {
+    /// The month as `YYYY-MM`, or [`TOTAL_PERIOD`]
+    pub period: String,
+    pub commits: u64,
+    pub contributors: u64,
+    pub insertions: u64,
+    pub deletions: u64,
+}
+
+/// Compute the statistics of the given commits, for each month from the oldest if `by_month`,
+/// or in a single [`TOTAL_PERIOD`] otherwise
+pub fn history_stats(
+    conn: &Connection,
+    commit_ids: &[Sha1Id],
+    by_month: bool,
+) -> crate::Result<Vec<PeriodStats>> {
+    let mut periods: BTreeMap<String, (PeriodStats, HashSet<String>)> = BTreeMap::new();
+    for id in commit_ids {
+        let commit = Commit::read_from_conn_with_id(conn, *id)?;
+        let period = if by_month {
+            conn.prepare_cached(READ_COMMIT_MONTH)?
+                .query_row([id], |row| row.get(0))?
+        } else {
+            TOTAL_PERIOD.to_string()
+        };
+        let (stats, authors) = periods.entry(period.clone()).or_insert_with(|| {
+            let stats = PeriodStats {
+                period,
+                ..PeriodStats::default()
+            };
+            (stats, HashSet::new())
+        });
+        stats.commits += 1;
+        authors.insert(commit.author_email.clone());
+        if commit.parent_ids.len() < 2 {
+            let (insertions, deletions) = changed_lines(conn, *id)?;
+            stats.insertions += insertions;
+            stats.deletions += deletions;
+        }
+    }
+    Ok(periods
+        .into_values()
+        .map(|(mut stats, authors)| {
+            stats.contributors = authors.len() as u64;
+            stats
+        })
+        .collect())
+}
+
+/// [`ContributorStats`] sums up the commits of an author
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContributorStats {
+    /// Name of the most recent commit of the author
+    pub name: String,
+    pub email: String,
+    pub commits: u64,
+    pub insertions: u64,
+    pub deletions: u64,
+    /// Days of the first and last commits, as `YYYY-MM-DD`
+    pub first: String,
+    pub last: String,
+}
+
+/// Compute the statistics of the authors of the given commits, identified by the email the
+/// mailmap maps them to. The most active authors come first: by commits, then by lines changed.
+pub fn contributor_stats(
+    conn: &Connection,
+    commit_ids: &[Sha1Id],
+    mailmap: &Mailmap,
+) -> crate::Result<Vec<ContributorStats>> {
+    let mut contributors: HashMap<String, ContributorStats> = HashMap::new();
+    for id in commit_ids {
+        let commit = Commit::read_from_conn_with_id(conn, *id)?;
+        let (name, email) = mailmap.map(&commit.author_name, &commit.author_email);
+        let day: String = conn
+            .prepare_cached(READ_COMMIT_DAY)?
+            .query_row([id], |row| row.get(0))?;
+        let stats = contributors
+            .entry(email.to_lowercase())
+            .or_insert_with(|| ContributorStats {
+                name: name.clone(),
+                email,
+                commits: 0,
+                insertions: 0,
+                deletions: 0,
+                first: day.clone(),
+                last: day.clone(),
+            });
+        stats.commits += 1;
+        if commit.parent_ids.len() < 2 {
+            let (insertions, deletions) = changed_lines(conn, *id)?;
+            stats.insertions += insertions;
+            stats.deletions += deletions;
+        }
+        if day >= stats.last {
+            stats.name = name;
+            stats.last = day;
+        } else if day < stats.first {
+            stats.first = day;
+        }
+    }
+
+    let mut contributors: Vec<ContributorStats> = contributors.into_values().collect();
+    contributors.sort_by(|a, b| {
+        (b.commits, b.insertions + b.deletions)
+            .cmp(&(a.commits, a.insertions + a.deletions))
+            .then_with(|| a.name.cmp(&b.name))
+            .then_with(|| a.email.cmp(&b.email))
+    });
+    Ok(contributors)
+}
+
+/// Lines inserted and deleted by a commit, binary files left out
+fn changed_lines(conn: &Connection, commit_id: Sha1Id) -> crate::Result<(u64, u64)> {
+    let read = |id: Option<Sha1Id>| -> crate::Result<Vec<u8>> {
+        match id {
+            Some(id) => Ok(Blob::read_from_conn_with_id(conn, id)?.data),
+            None => Ok(Vec::new()),
+        }
+    };
+    let (mut insertions, mut deletions) = (0, 0);
+    for change in read_file_changes(conn, commit_id)? {
+        let (old, new) = (read(change.old_blob)?, read(change.new_blob)?);
+        if looks_binary(&old) || looks_binary(&new) {
+            continue;
+        }
+        for edit in diff_lines(&split_lines(&old), &split_lines(&new)) {
+            match edit {
+                LineEdit::Insert(_) => insertions += 1,
+                LineEdit::Delete(_) => deletions += 1,
+                _ => {}
+            }
+        }
+    }
+    Ok((insertions, deletions))
+}
+
+/// Render the statistics in the given format
+pub fn format_stats(stats: &[PeriodStats], format: StatsFormat) -> crate::Result<String> {
+    let row = |s: &PeriodStats| {
+        [
+            s.period.clone(),
+            s.commits.to_string(),
+            s.contributors.to_string(),
+            s.insertions.to_string(),
+            s.deletions.to_string(),
+        ]
+    };
+    const HEADER: [&str; 5] = [
+        "period",
+        "commits",
+        "contributors",
+        "insertions",
+        "deletions",
+    ];
+    let mut out = String::new();
+    match format {
+        StatsFormat::Table => {
+            out.push_str(&format!(
+                "{:<8} {:>8} {:>13} {:>11} {:>10}\n",
+                HEADER[0], HEADER[1], HEADER[2], HEADER[3], HEADER[4]
+            ));
+            for stats in stats {
+                let [period, commits, contributors, insertions, deletions] = row(stats);
+                out.push_str(&format!(
+                    "{:<8} {:>8} {:>13} {:>11} {:>10}\n",
+                    period, commits, contributors, insertions, deletions
+                ));
+            }
+        }
+        StatsFormat::Csv => {
+            out.push_str(&HEADER.join(","));
+            out.push('\n');
+            for stats in stats {
+                out.push_str(&row(stats).join(","));
+                out.push('\n');
+            }
+        }
+        StatsFormat::Json => {
+            out.push_str(&serde_json::to_string_pretty(stats)?);
+            out.push('\n');
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::{
+        file_changes::{record_file_changes, CREATE_FILE_CHANGES_TABLE},
+        test_utils::{authored_by, files_view, new_commit, object_database, persist_commit},
+        tree::write_tree,
+    };
+
+    fn commit(
+        conn: &Connection,
+        parent_ids: Vec<Sha1Id>,
+        author: &str,
+        content: &str,
+        month: &str,
+    ) -> Sha1Id {
+        let tree_id = write_tree(conn, &files_view(conn, &[("file", content)])).unwrap();
+        let id = persist_commit(
+            conn,
+            authored_by(new_commit(tree_id, parent_ids, content), author),
+        );
+        record_file_changes(conn, &[&Commit::read_from_conn_with_id(conn, id).unwrap()]).unwrap();
+        conn.execute(
+            "UPDATE Commits SET created_at = strftime('%s', ?1) WHERE commit_id = ?2",
+            (format!("{}-15", month), id),
+        )
+        .unwrap();
+        id
+    }
+
+    #[test]
+    fn test_history_stats() {
+        let conn = object_database(&[CREATE_FILE_CHANGES_TABLE]);
+        let root = commit(&conn, vec![], "a", "1\n2\n", "2026-01");
+        let b = commit(&conn, vec![root], "b", "1\n3\n", "2026-01");
+        let c = commit(&conn, vec![b], "a", "1\n3\n4\n5\n", "2026-03");
+        let ids = [root, b, c];
+
+        let monthly = history_stats(&conn, &ids, true).unwrap();
+        let stats = |period: &str, commits, contributors, insertions, deletions| PeriodStats {
+            period: period.to_string(),
+            commits,
+            contributors,
+            insertions,
+            deletions,
+        };
+        assert_eq!(
+            vec![stats("2026-01", 2, 2, 3, 1), stats("2026-03", 1, 1, 2, 0)],
+            monthly
+        );
+        assert_eq!(
+            vec![stats(TOTAL_PERIOD, 3, 2, 5, 1)],
+            history_stats(&conn, &ids, false).unwrap()
+        );
+
+        assert_eq!(
+            "period,commits,contributors,insertions,deletions\n2026-01,2,2,3,1\n2026-03,1,1,2,0\n",
+            format_stats(&monthly, StatsFormat::Csv).unwrap()
+        );
+        assert_eq!(
+            "period    commits  contributors  insertions  deletions\n2026-01         2             2           3          1\n2026-03         1             1           2          0\n",
+            format_stats(&monthly, StatsFormat::Table).unwrap()
+        );
+        let mailmap = Mailmap::parse("A <a@example.com> <b@example.com>\n");
+        let contributors = contributor_stats(&conn, &ids, &mailmap).unwrap();
+        assert_eq!(1, contributors.len());
+        assert_eq!(
+            ContributorStats {
+                name: "a".to_string(),
+                email: "a@example.com".to_string(),
+                commits: 3,
+                insertions: 5,
+                deletions: 1,
+                first: "2026-01-15".to_string(),
+                last: "2026-03-15".to_string(),
+            },
+            contributors[0]
+        );
+        let contributors = contributor_stats(&conn, &ids, &Mailmap::default()).unwrap();
+        let names: Vec<&str> = contributors.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(vec!["a", "b"], names);
+
+        let json: serde_json::Value =
+            serde_json::from_str(&format_stats(&monthly, StatsFormat::Json).unwrap()).unwrap();
+        assert_eq!(2, json[1]["insertions"]);
+    }
+}
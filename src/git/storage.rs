@@ -0,0 +1,320 @@
+//! This module analyzes how the repository database uses its space: the largest blobs, the
+//! size of the history of each top-level directory, and how much storing each distinct blob
+//! once saves over storing every file of every commit.
+//!
+//! Object counts and sizes are aggregated by SQL over the object tables. Attributing blobs to
+//! paths needs the trees, which are walked once per distinct (tree, top-level directory) pair.
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::anyhow;
+use rusqlite::Connection;
+
+use super::model::{Blob, Sha1Id, Tree, TreeEntryType};
+
+const COUNT_OBJECTS: &str = "SELECT (SELECT count(*) FROM Commits), (SELECT count(*) FROM Trees), (SELECT coalesce(sum(length(data)), 0) FROM Trees), (SELECT count(*) FROM Blobs), (SELECT coalesce(sum(length(data)), 0) FROM Blobs)";
+/// Rows of the blobs stored more than once, beyond their first copy
+const COUNT_DUPLICATE_BLOBS: &str = "SELECT coalesce(sum(copies - 1), 0), coalesce(sum((copies - 1) * size), 0) FROM (SELECT count(*) AS copies, max(length(data)) AS size FROM Blobs GROUP BY blob_id)";
+const READ_LARGEST_BLOBS: &str =
+    "SELECT blob_id, max(length(data)) AS size FROM Blobs GROUP BY blob_id ORDER BY size DESC, blob_id LIMIT ?1";
+const READ_ROOT_TREES: &str = "SELECT DISTINCT tree_id FROM Commits";
+/// Every copy of a blob but the first one stored
+const DELETE_DUPLICATE_BLOBS: &str =
+    "DELETE FROM Blobs WHERE rowid NOT IN (SELECT min(rowid) FROM Blobs GROUP BY blob_id)";
+
+/// Name of the directory holding the files at the root of the repository
+pub const ROOT_DIRECTORY: &str = ".";
+
+/// [`StorageReport`] summarizes the space used by the repository database
+#[derive(Debug, PartialEq, Eq)]
+pub struct StorageReport {
+    pub commits: u64,
+    pub trees: u64,
+    pub tree_bytes: u64,
+    /// Rows of the blob table and their size, counting blobs stored more than once
+    pub blobs: u64,
+    pub blob_bytes: u64,
+    /// Extra copies of blobs stored more than once, and their size
+    pub duplicate_blobs: u64,
+    pub duplicate_bytes: u64,
+    /// Size of the database file, and how much of it is free pages
+    pub database_bytes: u64,
+    pub free_bytes: u64,
+    /// Total size of the files of every commit, as if each were stored separately
+    pub history_bytes: u64,
+    /// The largest distinct blobs, with a path they were committed at if any
+    pub largest_blobs: Vec<(Sha1Id, u64, Option<String>)>,
+    /// Size of the distinct blobs ever committed under each top-level directory, largest first
+    pub directories: Vec<(String, u64)>,
+}
+
+impl StorageReport {
+    /// Space saved by storing each distinct blob once
+    pub fn deduplication_savings(&self) -> u64 {
+        self.history_bytes.saturating_sub(self.blob_bytes)
+    }
+}
+
+/// Analyze the database, reporting the `top` largest blobs
+pub fn analyze_storage(conn: &Connection, top: usize) -> crate::Result<StorageReport> {
+    let (commits, trees, tree_bytes, blobs, blob_bytes) =
+        conn.query_row(COUNT_OBJECTS, (), |row| {
+            Ok((
+                row.get::<_, i64>(0)? as u64,
+                row.get::<_, i64>(1)? as u64,
+                row.get::<_, i64>(2)? as u64,
+                row.get::<_, i64>(3)? as u64,
+                row.get::<_, i64>(4)? as u64,
+            ))
+        })?;
+    let (duplicate_blobs, duplicate_bytes) = conn.query_row(COUNT_DUPLICATE_BLOBS, (), |row| {
+        Ok((row.get::<_, i64>(0)? as u64, row.get::<_, i64>(1)? as u64))
+    })?;
+    let (database_bytes, free_bytes) = database_size(conn)?;
+
+    let roots = conn
+        .prepare(READ_ROOT_TREES)?
+        .query_map((), |row| row.get::<_, Sha1Id>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut walk = HistoryWalk::default();
+    for root in &roots {
+        walk.visit_root(conn, *root)?;
+    }
+
+    let all_blobs: Vec<Sha1Id> = walk.paths.keys().copied().collect();
+    let sizes = Blob::read_sizes(conn, &all_blobs)?;
+    let size_of = |id: &Sha1Id| sizes.get(id).copied().unwrap_or_default();
+
+    let mut history_bytes = 0;
+    let mut tree_sizes = HashMap::new();
+    for root in &roots {
+        history_bytes += walk.tree_size(*root, &size_of, &mut tree_sizes);
+    }
+
+    let largest_blobs = conn
+        .prepare(READ_LARGEST_BLOBS)?
+        .query_map([top as i64], |row| {
+            Ok((row.get::<_, Sha1Id>(0)?, row.get::<_, i64>(1)? as u64))
+        })?
+        .map(|row| row.map(|(id, size)| (id, size, walk.paths.get(&id).cloned())))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut directories: Vec<(String, u64)> = walk
+        .directories
+        .into_iter()
+        .map(|(directory, ids)| (directory, ids.iter().map(size_of).sum()))
+        .collect();
+    directories.sort_by(|(d1, s1), (d2, s2)| s2.cmp(s1).then(d1.cmp(d2)));
+
+    Ok(StorageReport {
+        commits,
+        trees,
+        tree_bytes,
+        blobs,
+        blob_bytes,
+        duplicate_blobs,
+        duplicate_bytes,
+        database_bytes,
+        free_bytes,
+        history_bytes,
+        largest_blobs,
+        directories,
+    })
+}
+
+/// [`HistoryWalk`] collects the blobs reachable from the committed trees
+#[derive(Debug, Default)]
+struct HistoryWalk {
+    /// Entries of each tree read so far
+    trees: HashMap<Sha1Id, Vec<(TreeEntryType, Sha1Id, String)>>,
+    /// Top-level directory -> blobs ever committed under it
+    directories: HashMap<String, HashSet<Sha1Id>>,
+    /// Blob -> the first path it was found at
+    paths: HashMap<Sha1Id, String>,
+    /// (tree, top-level directory it is in) pairs walked so far
+    visited: HashSet<(Sha1Id, Option<String>)>,
+}
+
+impl HistoryWalk {
+    fn visit_root(&mut self, conn: &Connection, root: Sha1Id) -> crate::Result<()> {
+        // (tree, its path, the top-level directory it is in)
+        let mut stack = vec![(root, String::new(), None::<String>)];
+        while let Some((tree_id, prefix, top)) = stack.pop() {
+            if !self.visited.insert((tree_id, top.clone())) {
+                continue;
+            }
+            for (type_, id, name) in self.entries(conn, tree_id)? {
+                let path = if prefix.is_empty() {
+                    name.clone()
+                } else {
+                    format!("{}/{}", prefix, name)
+                };
+                match type_ {
+                    TreeEntryType::Tree => {
+                        let top = top.clone().unwrap_or(name);
+                        stack.push((id, path, Some(top)));
+                    }
+                    TreeEntryType::Blob => {
+                        let directory = top.clone().unwrap_or(ROOT_DIRECTORY.to_string());
+                        self.directories.entry(directory).or_default().insert(id);
+                        self.paths.entry(id).or_insert(path);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn entries(
+        &mut self,
+        conn: &Connection,
+        tree_id: Sha1Id,
+    ) -> crate::Result<Vec<(TreeEntryType, Sha1Id, String)>> {
+        if let Some(entries) = self.trees.get(&tree_id) {
+            return Ok(entries.clone());
+        }
+        let entries: Vec<_> = Tree::read_from_conn_with_id(conn, tree_id)?
+            .entries
+            .into_iter()
+            .map(|entry| (entry.type_, entry.id, entry.name))
+            .collect();
+        self.trees.insert(tree_id, entries.clone());
+        Ok(entries)
+    }
+
+    /// Total size of the files of a tree, counting every file even if its blob is shared
+    fn tree_size(
+        &self,
+        tree_id: Sha1Id,
+        size_of: &impl Fn(&Sha1Id) -> u64,
+        memo: &mut HashMap<Sha1Id, u64>,
+    ) -> u64 {
+        if let Some(size) = memo.get(&tree_id) {
+            return *size;
+        }
+        let size = self.trees[&tree_id]
+            .iter()
+            .map(|(type_, id, _)| match type_ {
+                TreeEntryType::Blob => size_of(id),
+                TreeEntryType::Tree => self.tree_size(*id, size_of, memo),
+            })
+            .sum();
+        memo.insert(tree_id, size);
+        size
+    }
+}
+
+/// Size of the database file, and how much of it is free pages
+pub fn database_size(conn: &Connection) -> crate::Result<(u64, u64)> {
+    let pragma = |name: &str| -> crate::Result<u64> {
+        Ok(conn.pragma_query_value(None, name, |row| row.get::<_, i64>(0))? as u64)
+    };
+    let page_size = pragma("page_size")?;
+    Ok((
+        pragma("page_count")? * page_size,
+        pragma("freelist_count")? * page_size,
+    ))
+}
+
+/// Delete the extra copies of the blobs stored more than once, keeping the first one. Returns
+/// the number of copies deleted.
+pub fn remove_duplicate_blobs(conn: &Connection) -> crate::Result<usize> {
+    Ok(conn.execute(DELETE_DUPLICATE_BLOBS, ())?)
+}
+
+/// Format a size in bytes for humans, e.g. `1.5 MiB`
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// Parse a size in bytes given on the command line, with an optional `k`, `m` or `g` suffix
+/// counting in KiB, MiB or GiB as git does, e.g. `512k`
+pub fn parse_size(size: &str) -> crate::Result<u64> {
+    let invalid = || anyhow!("fatal: invalid size '{}'", size);
+    let lower = size.to_ascii_lowercase();
+    let (digits, shift) = match lower.as_bytes().last() {
+        Some(b'k') => (&lower[..lower.len() - 1], 10),
+        Some(b'm') => (&lower[..lower.len() - 1], 20),
+        Some(b'g') => (&lower[..lower.len() - 1], 30),
+        _ => (lower.as_str(), 0),
+    };
+    let value: u64 = digits.parse().map_err(|_| invalid())?;
+    value.checked_mul(1 << shift).ok_or_else(invalid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::test_utils::{commit_files, object_database};
+
+    fn commit(conn: &Connection, files: &[(&str, &str)]) {
+        commit_files(conn, vec![], files, &files.len().to_string());
+    }
+
+    #[test]
+    fn test_analyze_storage() {
+        let conn = object_database(&[]);
+        commit(
+            &conn,
+            &[("README", "hello"), ("src/main.rs", "fn main() {}")],
+        );
+        commit(
+            &conn,
+            &[
+                ("README", "hello"),
+                ("src/main.rs", "fn main() { println!() }"),
+                ("src/copy.rs", "fn main() {}"),
+            ],
+        );
+
+        let report = analyze_storage(&conn, 2).unwrap();
+        assert_eq!(2, report.commits);
+        // Committing the same content again stores another copy of its blob
+        assert_eq!(5, report.blobs);
+        assert_eq!(
+            (2, 5 + 12),
+            (report.duplicate_blobs, report.duplicate_bytes)
+        );
+        assert_eq!(5 + 12 + 5 + 24 + 12, report.history_bytes);
+        assert_eq!(0, report.deduplication_savings());
+        let largest: Vec<_> = report
+            .largest_blobs
+            .iter()
+            .map(|(_, size, path)| (*size, path.as_deref().unwrap()))
+            .collect();
+        assert_eq!(2, largest.len());
+        assert_eq!((24, "src/main.rs"), largest[0]);
+        // The blob shared by two paths is reported at either of them
+        assert_eq!(12, largest[1].0);
+        assert_eq!(
+            vec![("src".to_string(), 36), (".".to_string(), 5)],
+            report.directories
+        );
+
+        assert_eq!(2, remove_duplicate_blobs(&conn).unwrap());
+        let report = analyze_storage(&conn, 2).unwrap();
+        assert_eq!((3, 0), (report.blobs, report.duplicate_blobs));
+        assert_eq!(5 + 12 + 24, report.blob_bytes);
+
+        assert_eq!("512 B", format_size(512));
+        assert_eq!("1.5 MiB", format_size(3 << 19));
+
+        assert_eq!(512, parse_size("512").unwrap());
+        assert_eq!(512 << 10, parse_size("512k").unwrap());
+        assert_eq!(2 << 20, parse_size("2M").unwrap());
+        assert_eq!(1 << 30, parse_size("1g").unwrap());
+        assert!(parse_size("m").is_err());
+        assert!(parse_size("1.5m").is_err());
+        assert!(parse_size("99999999999g").is_err());
+    }
+}
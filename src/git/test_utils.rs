@@ -0,0 +1,151 @@
+//! This module gathers the factories the unit tests use to set up repository databases and
+//! write history into them.
+
+use rusqlite::Connection;
+
+use super::{
+    model::{
+        Blob, Commit, Hashable, NoId, Sha1Id, CREATE_BLOB_TABLE, CREATE_COMMIT_TABLE,
+        CREATE_IDENTITIES_TABLE, CREATE_TREE_TABLE,
+    },
+    tree::{flatten_tree, write_tree, FlatEntry, TreeView},
+};
+use crate::repo::db::{HashAlgorithm, CREATE_TAGS_TABLE};
+
+/// Name and email of the author and committer of the commits written by [`new_commit`]
+pub const AUTHOR: (&str, &str) = ("eikasia30", "eikasia30@gmail.com");
+
+/// Mode of the files written by [`file_entry`], as the index records the mode of a regular file
+pub const FILE_MODE: &str = "33188";
+
+/// Run the given `CREATE` statements
+pub fn create_tables(conn: &Connection, creates: &[&str]) {
+    for create in creates {
+        conn.execute(create, ()).unwrap();
+    }
+}
+
+/// An in-memory SHA-1 database holding the tables of the objects, plus the given ones
+pub fn object_database(creates: &[&str]) -> Connection {
+    let conn = Connection::open_in_memory().unwrap();
+    create_tables(
+        &conn,
+        &[
+            CREATE_BLOB_TABLE,
+            CREATE_TREE_TABLE,
+            CREATE_IDENTITIES_TABLE,
+            CREATE_COMMIT_TABLE,
+            CREATE_TAGS_TABLE,
+        ],
+    );
+    HashAlgorithm::Sha1.persist(&conn).unwrap();
+    create_tables(&conn, creates);
+    conn
+}
+
+/// Persist a blob and return its id
+pub fn persist_blob(conn: &Connection, data: &[u8]) -> Sha1Id {
+    let blob = Blob::new(data.to_vec());
+    let id = blob.hash(HashAlgorithm::Sha1.hasher());
+    blob.with_id(id).persist(conn).unwrap();
+    id
+}
+
+/// Persist a blob and return the entry of a regular file holding it
+pub fn file_entry(conn: &Connection, content: &str) -> FlatEntry {
+    FlatEntry {
+        id: persist_blob(conn, content.as_bytes()),
+        mode: FILE_MODE.to_string(),
+    }
+}
+
+/// The snapshot of the given (path, content) files, their blobs persisted
+pub fn files_view(conn: &Connection, files: &[(&str, &str)]) -> TreeView {
+    files
+        .iter()
+        .map(|(name, content)| (name.to_string(), file_entry(conn, content)))
+        .collect()
+}
+
+/// A commit by [`AUTHOR`]
+pub fn new_commit(tree_id: Sha1Id, parent_ids: Vec<Sha1Id>, message: &str) -> Commit<NoId> {
+    Commit::new(
+        tree_id,
+        parent_ids,
+        AUTHOR.0.to_string(),
+        AUTHOR.1.to_string(),
+        AUTHOR.0.to_string(),
+        AUTHOR.1.to_string(),
+        message.to_string(),
+    )
+}
+
+/// The commit, authored by `author` as `author@example.com` instead
+pub fn authored_by(mut commit: Commit<NoId>, author: &str) -> Commit<NoId> {
+    commit.author_name = author.to_string();
+    commit.author_email = format!("{}@example.com", author);
+    commit
+}
+
+/// Persist a commit and return its id
+pub fn persist_commit(conn: &Connection, commit: Commit<NoId>) -> Sha1Id {
+    let id = commit.hash(HashAlgorithm::Sha1.hasher());
+    commit.with_id(id).persist(conn).unwrap();
+    id
+}
+
+/// Persist a commit of a tree that is not stored, for tests only walking the history
+pub fn make_commit(conn: &Connection, parent_ids: Vec<Sha1Id>, message: &str) -> Sha1Id {
+    let tree_id = Sha1Id::try_from(vec![0; 20]).unwrap();
+    persist_commit(conn, new_commit(tree_id, parent_ids, message))
+}
+
+/// Persist the trees of the snapshot and a commit of it
+pub fn commit_view(
+    conn: &Connection,
+    parent_ids: Vec<Sha1Id>,
+    view: &TreeView,
+    message: &str,
+) -> Sha1Id {
+    let tree_id = write_tree(conn, view).unwrap();
+    persist_commit(conn, new_commit(tree_id, parent_ids, message))
+}
+
+/// Persist a commit of the given (path, content) files
+pub fn commit_files(
+    conn: &Connection,
+    parent_ids: Vec<Sha1Id>,
+    files: &[(&str, &str)],
+    message: &str,
+) -> Sha1Id {
+    let view = files_view(conn, files);
+    commit_view(conn, parent_ids, &view, message)
+}
+
+/// Persist a commit of the snapshot of `parent` (an empty one if None) with `file` set to
+/// `content`
+pub fn commit_change(
+    conn: &Connection,
+    parent: Option<Sha1Id>,
+    file: &str,
+    content: &str,
+    message: &str,
+) -> Sha1Id {
+    let mut view = match parent {
+        Some(parent) => {
+            let tree_id = Commit::read_from_conn_with_id(conn, parent)
+                .unwrap()
+                .tree_id;
+            flatten_tree(conn, tree_id).unwrap()
+        }
+        None => TreeView::new(),
+    };
+    view.insert(file.to_string(), file_entry(conn, content));
+    commit_view(conn, parent.into_iter().collect(), &view, message)
+}
+
+/// Persist a commit of a single file named `file` holding the content, which is also the
+/// message of the commit
+pub fn commit_content(conn: &Connection, parent_ids: Vec<Sha1Id>, content: &str) -> Sha1Id {
+    commit_files(conn, parent_ids, &[("file", content)], content)
+}
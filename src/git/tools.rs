@@ -0,0 +1,151 @@
+//! This module launches the external tools of `gitqlite difftool` and `gitqlite mergetool`.
+//! The tool is the one given with `--tool`, or else `diff.tool` (difftool only) or
+//! `merge.tool`, and its command is `difftool.<tool>.cmd` or `mergetool.<tool>.cmd`.
+//!
+//! As in git, the command is run by the shell with the files to compare in the variables
+//! `LOCAL` and `REMOTE`, the path of the file in the work tree in `MERGED`, and the version
+//! of the common ancestor in `BASE`, e.g. `meld "$LOCAL" "$BASE" "$REMOTE" -o "$MERGED"`.
+//! The versions are written to temporary files, which keep the file name of the path so that
+//! tools can tell the format from the extension.
+
+use std::{io::Write, path::Path, process::Command};
+
+use anyhow::anyhow;
+
+use crate::repo::config::{ConfigSource, GitConfig};
+
+/// [`ToolKind`] tells which command the tool is launched by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToolKind {
+    Diff,
+    Merge,
+}
+
+impl ToolKind {
+    fn section(&self) -> &'static str {
+        match self {
+            ToolKind::Diff => "difftool",
+            ToolKind::Merge => "mergetool",
+        }
+    }
+}
+
+/// [`ExternalTool`] is a configured diff or merge tool
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExternalTool {
+    pub name: String,
+    pub cmd: String,
+    /// Whether the exit code of the command tells if it succeeded, from
+    /// `<kind>tool.<tool>.trustExitCode`
+    pub trust_exit_code: bool,
+}
+
+impl ExternalTool {
+    /// Look up the tool named `tool`, or the configured one
+    pub fn load(
+        config: &GitConfig,
+        kind: ToolKind,
+        tool: Option<&str>,
+    ) -> crate::Result<ExternalTool> {
+        let configured = match kind {
+            ToolKind::Diff => match config.get("diff.tool", ConfigSource::All)? {
+                Some(tool) => Some(tool),
+                None => config.get("merge.tool", ConfigSource::All)?,
+            },
+            ToolKind::Merge => config.get("merge.tool", ConfigSource::All)?,
+        };
+        let name = tool.or(configured).ok_or_else(|| {
+            anyhow!(
+                "fatal: no {} configured, set {} or use --tool",
+                kind.section(),
+                if kind == ToolKind::Diff {
+                    "diff.tool"
+                } else {
+                    "merge.tool"
+                }
+            )
+        })?;
+
+        let key = |var: &str| format!("{}.{}.{}", kind.section(), name, var);
+        let cmd = config
+            .get(&key("cmd"), ConfigSource::All)?
+            .ok_or_else(|| anyhow!("fatal: unknown tool '{}', set {}", name, key("cmd")))?
+            .to_string();
+        let trust_exit_code = config
+            .get_bool(&key("trustExitCode"), ConfigSource::All)?
+            .unwrap_or(false);
+        Ok(ExternalTool {
+            name: name.to_string(),
+            cmd,
+            trust_exit_code,
+        })
+    }
+
+    /// Run the command with the given variables set in its environment, from the root of the
+    /// work tree. Returns whether it exited successfully.
+    pub fn run(&self, work_dir: &Path, vars: &[(&str, &Path)]) -> crate::Result<bool> {
+        let status = Command::new("sh")
+            .arg("-c")
+            .arg(&self.cmd)
+            .current_dir(work_dir)
+            .envs(vars.iter().copied())
+            .status()
+            .map_err(|err| anyhow!("fatal: cannot run {}: {}", self.name, err))?;
+        Ok(status.success())
+    }
+}
+
+/// Write a version of the file at `path` to a temporary file, labelled with the side it is on
+pub fn temp_version(
+    path: &str,
+    label: &str,
+    data: &[u8],
+) -> crate::Result<tempfile::NamedTempFile> {
+    let base = path.rsplit('/').next().unwrap_or(path);
+    let mut file = tempfile::Builder::new()
+        .prefix(&format!("gitqlite-{}-", label))
+        .suffix(&format!("-{}", base))
+        .tempfile()?;
+    file.write_all(data)?;
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn test_external_tool() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("config"),
+            "[merge]\n\ttool = cat\n[difftool \"cat\"]\n\tcmd = cat \"$LOCAL\" \"$REMOTE\" > \"$MERGED\"\n[mergetool \"cat\"]\n\tcmd = false\n\ttrustExitCode = true\n",
+        )
+        .unwrap();
+        let config = GitConfig::load(dir.path()).unwrap();
+
+        // difftool falls back to merge.tool
+        let tool = ExternalTool::load(&config, ToolKind::Diff, None).unwrap();
+        assert_eq!("cat", tool.name);
+        assert!(!tool.trust_exit_code);
+        let merge = ExternalTool::load(&config, ToolKind::Merge, None).unwrap();
+        assert_eq!("false", merge.cmd);
+        assert!(merge.trust_exit_code);
+        assert!(ExternalTool::load(&config, ToolKind::Diff, Some("meld")).is_err());
+
+        let local = temp_version("src/a.txt", "LOCAL", b"old\n").unwrap();
+        let remote = temp_version("src/a.txt", "REMOTE", b"new\n").unwrap();
+        assert!(local.path().to_string_lossy().ends_with("-a.txt"));
+        let merged = dir.path().join("merged");
+        let vars = [
+            ("LOCAL", local.path()),
+            ("REMOTE", remote.path()),
+            ("MERGED", merged.as_path()),
+        ];
+        assert!(tool.run(dir.path(), &vars).unwrap());
+        assert_eq!("old\nnew\n", fs::read_to_string(&merged).unwrap());
+        assert!(!merge.run(dir.path(), &vars).unwrap());
+    }
+}
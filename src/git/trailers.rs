@@ -0,0 +1,401 @@
+//! This module parses and edits the trailers of commit messages: the `Key: value` lines of the
+//! last paragraph, like `Signed-off-by: A U Thor <author@example.com>`.
+//!
+//! A paragraph is a trailer block when every line of it is a trailer or the continuation of
+//! one (a line starting with whitespace). The first paragraph is the subject and is never a
+//! trailer block. Which characters separate the key from the value is set by
+//! `trailer.separators` (`:` by default).
+//!
+//! Adding a trailer follows rules which can be set for all trailers (`trailer.where`,
+//! `trailer.ifExists` and `trailer.ifMissing`), for the trailers of one key
+//! (`trailer.<token>.where`, ...) or on the command line. `trailer.<token>.key` makes `<token>`
+//! an alias of a longer key, e.g. `sign` for `Signed-off-by`.
+
+use anyhow::anyhow;
+use clap::ValueEnum;
+
+use crate::{
+    cli::{TrailerIfExists, TrailerIfMissing, TrailerWhere},
+    repo::config::{ConfigSource, GitConfig},
+};
+
+const DEFAULT_SEPARATORS: &str = ":";
+
+/// [`Trailer`] is a trailer of a message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trailer {
+    pub key: String,
+    pub value: String,
+    /// The lines of the trailer as they are written in the message
+    text: String,
+}
+
+impl Trailer {
+    pub fn new(key: &str, separator: char, value: &str) -> Trailer {
+        let text = if separator == ':' {
+            format!("{}: {}", key, value)
+        } else {
+            format!("{} {}{}", key, separator, value)
+        };
+        Trailer {
+            key: key.to_string(),
+            value: value.to_string(),
+            text: text.trim_end().to_string(),
+        }
+    }
+
+    /// Whether both trailers have the same key, ignoring case, and the same value
+    fn same_as(&self, other: &Trailer) -> bool {
+        self.key.eq_ignore_ascii_case(&other.key) && self.value == other.value
+    }
+}
+
+/// [`TrailerRule`] tells how to add a trailer to a message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TrailerRule {
+    pub placement: TrailerWhere,
+    pub if_exists: TrailerIfExists,
+    pub if_missing: TrailerIfMissing,
+}
+
+impl Default for TrailerRule {
+    fn default() -> Self {
+        TrailerRule {
+            placement: TrailerWhere::End,
+            if_exists: TrailerIfExists::AddIfDifferentNeighbor,
+            if_missing: TrailerIfMissing::Add,
+        }
+    }
+}
+
+/// [`TrailerOptions`] are the rules given on the command line, taking precedence over the
+/// configuration
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TrailerOptions {
+    pub placement: Option<TrailerWhere>,
+    pub if_exists: Option<TrailerIfExists>,
+    pub if_missing: Option<TrailerIfMissing>,
+}
+
+/// [`NewTrailer`] is a trailer to add along with the rule to follow
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NewTrailer {
+    pub trailer: Trailer,
+    pub rule: TrailerRule,
+}
+
+/// [`TrailerMessage`] is a message split into its body and its trailers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrailerMessage {
+    /// The message without the trailer block, nor the blank lines ending it
+    pub body: String,
+    pub trailers: Vec<Trailer>,
+}
+
+/// The characters separating keys from values
+pub fn separators(config: &GitConfig) -> crate::Result<String> {
+    Ok(config
+        .get("trailer.separators", ConfigSource::All)?
+        .filter(|separators| !separators.is_empty())
+        .unwrap_or(DEFAULT_SEPARATORS)
+        .to_string())
+}
+
+/// Split a message into its body and trailers
+pub fn parse_message(message: &str, separators: &str) -> TrailerMessage {
+    let lines: Vec<&str> = message.trim_end().lines().collect();
+    let block_start = lines
+        .iter()
+        .rposition(|line| line.trim().is_empty())
+        .map(|blank| blank + 1);
+
+    if let Some(start) = block_start.filter(|start| *start < lines.len()) {
+        if let Some(trailers) = parse_block(&lines[start..], separators) {
+            return TrailerMessage {
+                body: lines[..start].join("\n").trim_end().to_string(),
+                trailers,
+            };
+        }
+    }
+    TrailerMessage {
+        body: lines.join("\n"),
+        trailers: Vec::new(),
+    }
+}
+
+/// Parse the lines of a paragraph as trailers, None if it is not a trailer block
+fn parse_block(lines: &[&str], separators: &str) -> Option<Vec<Trailer>> {
+    let mut trailers: Vec<Trailer> = Vec::new();
+    for line in lines {
+        if line.starts_with(char::is_whitespace) {
+            let last = trailers.last_mut()?;
+            last.value = format!("{}\n{}", last.value, line);
+            last.text = format!("{}\n{}", last.text, line);
+            continue;
+        }
+        let (key, value) = split_trailer(line, separators)?;
+        trailers.push(Trailer {
+            key: key.to_string(),
+            value: value.to_string(),
+            text: line.to_string(),
+        });
+    }
+    Some(trailers)
+}
+
+/// Split `<key><separator><value>` into key and value, None if the line is not a trailer
+fn split_trailer<'a>(line: &'a str, separators: &str) -> Option<(&'a str, &'a str)> {
+    let index = line.find(|c| separators.contains(c))?;
+    let key = line[..index].trim_end();
+    let valid = !key.is_empty() && key.chars().all(|c| c.is_alphanumeric() || c == '-');
+    valid.then(|| (key, line[index + 1..].trim()))
+}
+
+/// Parse a trailer given on the command line as `<token>[(=|<separator>)<value>]`, along with
+/// the rule to add it with
+pub fn new_trailer(
+    config: &GitConfig,
+    arg: &str,
+    options: &TrailerOptions,
+) -> crate::Result<NewTrailer> {
+    let separators = separators(config)?;
+    let (token, value) = match arg.find(|c| c == '=' || separators.contains(c)) {
+        Some(index) => (arg[..index].trim(), arg[index + 1..].trim()),
+        None => (arg.trim(), ""),
+    };
+    if token.is_empty() {
+        return Err(anyhow!("fatal: empty trailer token in trailer '{}'", arg));
+    }
+
+    let key = match config.get(&format!("trailer.{}.key", token), ConfigSource::All)? {
+        Some(key) => key.trim_end_matches(|c: char| c.is_whitespace() || separators.contains(c)),
+        None => token,
+    };
+    let separator = separators.chars().next().unwrap_or(':');
+
+    let defaults = TrailerRule::default();
+    let rule = TrailerRule {
+        placement: match options.placement {
+            Some(placement) => placement,
+            None => rule_value(config, token, "where")?.unwrap_or(defaults.placement),
+        },
+        if_exists: match options.if_exists {
+            Some(if_exists) => if_exists,
+            None => rule_value(config, token, "ifExists")?.unwrap_or(defaults.if_exists),
+        },
+        if_missing: match options.if_missing {
+            Some(if_missing) => if_missing,
+            None => rule_value(config, token, "ifMissing")?.unwrap_or(defaults.if_missing),
+        },
+    };
+
+    Ok(NewTrailer {
+        trailer: Trailer::new(key, separator, value),
+        rule,
+    })
+}
+
+/// Read `trailer.<token>.<name>`, falling back to `trailer.<name>`
+fn rule_value<T: ValueEnum>(
+    config: &GitConfig,
+    token: &str,
+    name: &str,
+) -> crate::Result<Option<T>> {
+    for key in [
+        format!("trailer.{}.{}", token, name),
+        format!("trailer.{}", name),
+    ] {
+        if let Some(value) = config.get(&key, ConfigSource::All)? {
+            return T::from_str(value, true)
+                .map(Some)
+                .map_err(|_| anyhow!("fatal: unknown value '{}' for key '{}'", value, key));
+        }
+    }
+    Ok(None)
+}
+
+/// Add trailers given on the command line to a message
+pub fn add_trailers(
+    config: &GitConfig,
+    message: &str,
+    args: &[String],
+    options: &TrailerOptions,
+) -> crate::Result<String> {
+    if args.is_empty() {
+        return Ok(message.to_string());
+    }
+    let mut parsed = parse_message(message, &separators(config)?);
+    for arg in args {
+        parsed.add(new_trailer(config, arg, options)?);
+    }
+    Ok(parsed.format())
+}
+
+impl TrailerMessage {
+    /// Add a trailer following its rule
+    pub fn add(&mut self, new: NewTrailer) {
+        let NewTrailer { trailer, rule } = new;
+        let same_key: Vec<usize> = (0..self.trailers.len())
+            .filter(|i| self.trailers[*i].key.eq_ignore_ascii_case(&trailer.key))
+            .collect();
+        let (Some(&first), Some(&last)) = (same_key.first(), same_key.last()) else {
+            if rule.if_missing == TrailerIfMissing::Add {
+                let index = match rule.placement {
+                    TrailerWhere::End | TrailerWhere::After => self.trailers.len(),
+                    TrailerWhere::Start | TrailerWhere::Before => 0,
+                };
+                self.trailers.insert(index, trailer);
+            }
+            return;
+        };
+
+        // Where the trailer goes, and the trailer it would be next to
+        let (mut index, neighbor) = match rule.placement {
+            TrailerWhere::End => (self.trailers.len(), self.trailers.len().checked_sub(1)),
+            TrailerWhere::Start => (0, Some(0)),
+            TrailerWhere::After => (last + 1, Some(last)),
+            TrailerWhere::Before => (first, Some(first)),
+        };
+        match rule.if_exists {
+            TrailerIfExists::DoNothing => {}
+            TrailerIfExists::Add => self.trailers.insert(index, trailer),
+            TrailerIfExists::AddIfDifferent => {
+                if !self.trailers.iter().any(|t| t.same_as(&trailer)) {
+                    self.trailers.insert(index, trailer);
+                }
+            }
+            TrailerIfExists::AddIfDifferentNeighbor => {
+                if !neighbor.is_some_and(|i| self.trailers[i].same_as(&trailer)) {
+                    self.trailers.insert(index, trailer);
+                }
+            }
+            TrailerIfExists::Replace => {
+                let replaced = match rule.placement {
+                    TrailerWhere::End | TrailerWhere::After => last,
+                    TrailerWhere::Start | TrailerWhere::Before => first,
+                };
+                self.trailers.remove(replaced);
+                if replaced < index {
+                    index -= 1;
+                }
+                self.trailers.insert(index, trailer);
+            }
+        }
+    }
+
+    /// Join the continuation lines of the trailers to their first line
+    pub fn unfold(&mut self) {
+        for trailer in &mut self.trailers {
+            let unfold = |text: &str| {
+                text.split('\n')
+                    .map(str::trim)
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            };
+            trailer.value = unfold(&trailer.value);
+            trailer.text = unfold(&trailer.text);
+        }
+    }
+
+    /// The trailer block, each line ending with a newline
+    pub fn format_trailers(&self) -> String {
+        self.trailers
+            .iter()
+            .map(|trailer| format!("{}\n", trailer.text))
+            .collect()
+    }
+
+    /// The message with its trailers
+    pub fn format(&self) -> String {
+        match (self.body.is_empty(), self.trailers.is_empty()) {
+            (true, true) => String::new(),
+            (true, false) => self.format_trailers(),
+            (false, true) => format!("{}\n", self.body),
+            (false, false) => format!("{}\n\n{}", self.body, self.format_trailers()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    fn load_config(text: &str) -> (tempfile::TempDir, GitConfig) {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("config"), text).unwrap();
+        let config = GitConfig::load(dir.path()).unwrap();
+        (dir, config)
+    }
+
+    #[test]
+    fn test_parse_message() {
+        let parsed = parse_message(
+            "fix: subject\n\nbody text\n\nAcked-by: A\nBug #42\n  continued\n\n",
+            ":#",
+        );
+        assert_eq!("fix: subject\n\nbody text", parsed.body);
+        let keys: Vec<&str> = parsed.trailers.iter().map(|t| t.key.as_str()).collect();
+        assert_eq!(vec!["Acked-by", "Bug"], keys);
+        assert_eq!("42\n  continued", parsed.trailers[1].value);
+
+        // The subject is never a trailer block, nor is a paragraph with other lines
+        assert!(parse_message("fix: typo", ":").trailers.is_empty());
+        assert!(parse_message("s\n\nKey: v\nnot a trailer", ":")
+            .trailers
+            .is_empty());
+
+        let mut parsed = parsed;
+        parsed.unfold();
+        assert_eq!("Acked-by: A\nBug #42 continued\n", parsed.format_trailers());
+    }
+
+    #[test]
+    fn test_add_trailers() {
+        let (_dir, config) = load_config(
+            "[trailer \"sign\"]\nkey = Signed-off-by\nifExists = replace\n[trailer]\nwhere = end\n",
+        );
+        let options = TrailerOptions::default();
+        let add = |message: &str, args: &[&str], options: &TrailerOptions| {
+            let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+            add_trailers(&config, message, &args, options).unwrap()
+        };
+
+        assert_eq!(
+            "subject\n\nSigned-off-by: A\n",
+            add("subject\n", &["sign=A"], &options)
+        );
+        // The alias replaces the existing trailer
+        assert_eq!(
+            "subject\n\nAcked-by: C\nSigned-off-by: B\n",
+            add(
+                "subject\n\nSigned-off-by: A\nAcked-by: C\n",
+                &["sign: B"],
+                &options
+            )
+        );
+        // A trailer identical to its neighbor is not added again
+        assert_eq!(
+            "subject\n\nAcked-by: C\n",
+            add("subject\n\nAcked-by: C\n", &["Acked-by=C"], &options)
+        );
+
+        let options = TrailerOptions {
+            placement: Some(TrailerWhere::After),
+            if_exists: Some(TrailerIfExists::Add),
+            if_missing: Some(TrailerIfMissing::DoNothing),
+        };
+        assert_eq!(
+            "subject\n\nAcked-by: A\nAcked-by: B\nBug: 1\n",
+            add(
+                "subject\n\nAcked-by: A\nBug: 1\n",
+                &["Acked-by=B", "Missing=x"],
+                &options
+            )
+        );
+
+        let (_dir, config) = load_config("[trailer]\nifExists = sometimes\n");
+        assert!(new_trailer(&config, "a=b", &TrailerOptions::default()).is_err());
+    }
+}
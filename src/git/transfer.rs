@@ -0,0 +1,77 @@
+//! This module maintains the `TransferState` table, which lets an interrupted fetch resume
+//! where it left off. Fetch downloads the history it lacks in chunks of commits, the parents
+//! before their children, and records the tips of each chunk once it is stored. The next fetch
+//! from the same remote sends them along with its own tips as commits it has, so that only the
+//! chunks that never arrived are downloaded again.
+//!
+//! The rows of a remote are dropped once a fetch from it completes. Until then, the commits
+//! they name keep the history received so far from being pruned.
+
+use rusqlite::Connection;
+
+use super::model::Sha1Id;
+
+pub const CREATE_TRANSFER_STATE_TABLE: &str = "CREATE TABLE TransferState (remote TEXT NOT NULL, commit_id BLOB NOT NULL, PRIMARY KEY (remote, commit_id)) WITHOUT ROWID;";
+const READ_RECEIVED: &str = "SELECT commit_id FROM TransferState WHERE remote = ?1";
+const READ_ALL_RECEIVED: &str = "SELECT DISTINCT commit_id FROM TransferState";
+const INSERT_RECEIVED: &str =
+    "INSERT OR IGNORE INTO TransferState (remote, commit_id) VALUES (?1, ?2);";
+const DELETE_RECEIVED: &str = "DELETE FROM TransferState WHERE remote = ?1";
+
+/// Tips of the chunks received from the remote at `url` by a fetch that did not complete
+pub fn received_commits(conn: &Connection, url: &str) -> crate::Result<Vec<Sha1Id>> {
+    let ids = conn
+        .prepare(READ_RECEIVED)?
+        .query_map([url], |row| row.get(0))?
+        .collect::<Result<Vec<Sha1Id>, _>>()?;
+    Ok(ids)
+}
+
+/// Tips of the chunks received from any remote by fetches that did not complete
+pub fn all_received_commits(conn: &Connection) -> crate::Result<Vec<Sha1Id>> {
+    let ids = conn
+        .prepare(READ_ALL_RECEIVED)?
+        .query_map((), |row| row.get(0))?
+        .collect::<Result<Vec<Sha1Id>, _>>()?;
+    Ok(ids)
+}
+
+/// Record the tips of a chunk received from the remote at `url`
+pub fn record_received(conn: &Connection, url: &str, ids: &[Sha1Id]) -> crate::Result<()> {
+    let mut insert = conn.prepare_cached(INSERT_RECEIVED)?;
+    for id in ids {
+        insert.execute((url, id))?;
+    }
+    Ok(())
+}
+
+/// Forget what was received from the remote at `url`, once a fetch from it completes
+pub fn forget_received(conn: &Connection, url: &str) -> crate::Result<()> {
+    conn.execute(DELETE_RECEIVED, [url])?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transfer_state() {
+        let conn = Connection::open_in_memory().unwrap();
+        let (a, b) = (
+            Sha1Id::try_from(vec![1; 20]).unwrap(),
+            Sha1Id::try_from(vec![2; 20]).unwrap(),
+        );
+        conn.execute(CREATE_TRANSFER_STATE_TABLE, ()).unwrap();
+        assert!(received_commits(&conn, "/origin").unwrap().is_empty());
+        record_received(&conn, "/origin", &[a]).unwrap();
+        record_received(&conn, "/origin", &[a]).unwrap();
+        record_received(&conn, "/upstream", &[a, b]).unwrap();
+        assert_eq!(vec![a], received_commits(&conn, "/origin").unwrap());
+        assert_eq!(2, all_received_commits(&conn).unwrap().len());
+
+        forget_received(&conn, "/upstream").unwrap();
+        assert!(received_commits(&conn, "/upstream").unwrap().is_empty());
+        assert_eq!(vec![a], all_received_commits(&conn).unwrap());
+    }
+}
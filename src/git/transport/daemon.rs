@@ -0,0 +1,51 @@
+use std::{io::BufReader, net::TcpStream};
+
+use anyhow::anyhow;
+
+use crate::git::protocol::{exchange, ProtocolRequest, ProtocolResponse, DEFAULT_DAEMON_PORT};
+
+use super::Transport;
+
+/// [`DaemonTransport`] sends each request over its own TCP connection to `gitqlite daemon`
+#[derive(Debug)]
+pub struct DaemonTransport {
+    /// `host:port` of the daemon
+    address: String,
+    repo: String,
+}
+
+impl DaemonTransport {
+    /// Connect to the repository named by the part of a `gitqlite://` URL after the scheme
+    pub fn new(address: &str) -> crate::Result<DaemonTransport> {
+        let (host, repo) = address.split_once('/').unwrap_or((address, ""));
+        if host.is_empty() {
+            return Err(anyhow!("fatal: no host in 'gitqlite://{}'", address));
+        }
+        let address = if host.contains(':') {
+            host.to_string()
+        } else {
+            format!("{}:{}", host, DEFAULT_DAEMON_PORT)
+        };
+        Ok(DaemonTransport {
+            address,
+            repo: repo.to_string(),
+        })
+    }
+}
+
+impl Transport for DaemonTransport {
+    fn repo(&self) -> &str {
+        &self.repo
+    }
+
+    fn request(
+        &mut self,
+        request: &ProtocolRequest,
+        pack: Option<&[u8]>,
+    ) -> crate::Result<(ProtocolResponse, Option<Vec<u8>>)> {
+        let stream = TcpStream::connect(&self.address)
+            .map_err(|err| anyhow!("fatal: unable to connect to {}: {}", self.address, err))?;
+        let mut writer = stream.try_clone()?;
+        exchange(&mut BufReader::new(stream), &mut writer, request, pack)
+    }
+}
@@ -0,0 +1,317 @@
+//! The HTTP transport posts each request to the URL of the remote repository, as served by
+//! `gitqlite daemon --http`, and reads the response from the body of the reply.
+//!
+//! Connections are configured like git's:
+//! - `http.proxy` is the proxy to go through (`http_proxy`, `https_proxy` and `all_proxy`
+//!   from the environment are used if it is not set)
+//! - `http.sslVerify` (or `GIT_SSL_NO_VERIFY`) turns off the verification of certificates
+//! - `http.sslCAInfo` (or `GIT_SSL_CAINFO`) is a PEM file holding the certificate authorities
+//!   to trust instead of the built-in ones
+
+use std::{
+    fs,
+    io::{BufReader, Read},
+    path::PathBuf,
+    sync::Arc,
+};
+
+use anyhow::anyhow;
+use rustls::{
+    client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider},
+    pki_types::{CertificateDer, ServerName, UnixTime},
+    ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme,
+};
+
+use crate::{
+    git::protocol::{read_response, write_message, ProtocolRequest, ProtocolResponse},
+    repo::config::{ConfigSource, GitConfig},
+};
+
+use super::Transport;
+
+/// Content type of the bodies of protocol requests and responses
+pub const PROTOCOL_CONTENT_TYPE: &str = "application/x-gitqlite-protocol";
+
+/// [`HttpOptions`] configures the connections of the HTTP transport
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpOptions {
+    pub proxy: Option<String>,
+    pub ssl_verify: bool,
+    pub ca_info: Option<PathBuf>,
+}
+
+impl HttpOptions {
+    /// Read the options from the configuration, letting the environment override it
+    pub fn from_config(config: &GitConfig) -> crate::Result<HttpOptions> {
+        HttpOptions::from_config_and_env(config, |name| std::env::var(name).ok())
+    }
+
+    fn from_config_and_env(
+        config: &GitConfig,
+        env: impl Fn(&str) -> Option<String>,
+    ) -> crate::Result<HttpOptions> {
+        let proxy = config
+            .get("http.proxy", ConfigSource::All)?
+            .filter(|proxy| !proxy.is_empty())
+            .map(str::to_string);
+        let ssl_verify = match env("GIT_SSL_NO_VERIFY") {
+            Some(_) => false,
+            None => config
+                .get_bool("http.sslVerify", ConfigSource::All)?
+                .unwrap_or(true),
+        };
+        let ca_info = match env("GIT_SSL_CAINFO") {
+            Some(path) => Some(PathBuf::from(path)),
+            None => config
+                .get("http.sslCAInfo", ConfigSource::All)?
+                .map(PathBuf::from),
+        };
+        Ok(HttpOptions {
+            proxy,
+            ssl_verify,
+            ca_info,
+        })
+    }
+
+    /// Build an HTTP client following the options
+    pub fn agent(&self) -> crate::Result<ureq::Agent> {
+        let mut builder = ureq::AgentBuilder::new();
+        builder = match &self.proxy {
+            Some(proxy) => builder.proxy(
+                ureq::Proxy::new(proxy)
+                    .map_err(|err| anyhow!("fatal: invalid proxy '{}': {}", proxy, err))?,
+            ),
+            None => builder.try_proxy_from_env(true),
+        };
+        if let Some(tls_config) = self.tls_config()? {
+            builder = builder.tls_config(Arc::new(tls_config));
+        }
+        Ok(builder.build())
+    }
+
+    /// The TLS configuration, None to use the default one
+    fn tls_config(&self) -> crate::Result<Option<ClientConfig>> {
+        if self.ssl_verify && self.ca_info.is_none() {
+            return Ok(None);
+        }
+
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let builder = ClientConfig::builder_with_provider(Arc::clone(&provider))
+            .with_safe_default_protocol_versions()?;
+        if !self.ssl_verify {
+            let verifier = Arc::new(NoVerification(provider));
+            return Ok(Some(
+                builder
+                    .dangerous()
+                    .with_custom_certificate_verifier(verifier)
+                    .with_no_client_auth(),
+            ));
+        }
+
+        let mut roots = RootCertStore::empty();
+        if let Some(path) = &self.ca_info {
+            let pem = fs::read(path).map_err(|err| {
+                anyhow!(
+                    "fatal: unable to read CA file '{}': {}",
+                    path.display(),
+                    err
+                )
+            })?;
+            for cert in rustls_pemfile::certs(&mut pem.as_slice()) {
+                roots.add(cert?)?;
+            }
+            if roots.is_empty() {
+                return Err(anyhow!(
+                    "fatal: no certificates found in CA file '{}'",
+                    path.display()
+                ));
+            }
+        }
+        Ok(Some(
+            builder.with_root_certificates(roots).with_no_client_auth(),
+        ))
+    }
+}
+
+/// [`HttpTransport`] posts requests to a repository served over HTTP
+pub struct HttpTransport {
+    agent: ureq::Agent,
+    url: String,
+    repo: String,
+}
+
+impl HttpTransport {
+    pub fn new(url: &str, config: &GitConfig) -> crate::Result<HttpTransport> {
+        let agent = HttpOptions::from_config(config)?.agent()?;
+        let without_scheme = url.split_once("://").map_or(url, |(_, rest)| rest);
+        let repo = without_scheme
+            .split_once('/')
+            .map_or("", |(_, path)| path)
+            .to_string();
+        Ok(HttpTransport {
+            agent,
+            url: url.to_string(),
+            repo,
+        })
+    }
+}
+
+impl Transport for HttpTransport {
+    fn repo(&self) -> &str {
+        &self.repo
+    }
+
+    fn request(
+        &mut self,
+        request: &ProtocolRequest,
+        pack: Option<&[u8]>,
+    ) -> crate::Result<(ProtocolResponse, Option<Vec<u8>>)> {
+        let mut body = Vec::new();
+        write_message(&mut body, request, pack)?;
+
+        let response = self
+            .agent
+            .post(&self.url)
+            .set("Content-Type", PROTOCOL_CONTENT_TYPE)
+            .send_bytes(&body)
+            .map_err(|err| anyhow!("fatal: unable to access '{}': {}", self.url, err))?;
+        let mut reader = BufReader::new(response.into_reader());
+        let result = read_response(&mut reader);
+        // Drain the body so that the connection can be reused
+        let _ = std::io::copy(&mut reader.take(u64::MAX), &mut std::io::sink());
+        result
+    }
+}
+
+/// [`NoVerification`] accepts any certificate, for `http.sslVerify=false`. Signatures are
+/// still checked so that the handshake itself is sound.
+#[derive(Debug)]
+struct NoVerification(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for NoVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Write, thread};
+
+    use super::*;
+    use crate::git::{
+        model::Sha1Id,
+        protocol::{read_request, ProtocolResponse},
+    };
+
+    fn load_config(text: &str) -> (tempfile::TempDir, GitConfig) {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("config"), text).unwrap();
+        let config = GitConfig::load(dir.path()).unwrap();
+        (dir, config)
+    }
+
+    #[test]
+    fn test_options() {
+        let (_dir, config) = load_config(
+            "[http]\nproxy = http://proxy.example.com:3128\nsslVerify = false\nsslCAInfo = /etc/ca.pem\n",
+        );
+        let options = HttpOptions::from_config_and_env(&config, |_| None).unwrap();
+        assert_eq!(
+            Some("http://proxy.example.com:3128".to_string()),
+            options.proxy
+        );
+        assert!(!options.ssl_verify);
+        assert_eq!(Some(PathBuf::from("/etc/ca.pem")), options.ca_info);
+        assert!(options.tls_config().unwrap().is_some());
+
+        let (dir, config) = load_config("");
+        let mut options = HttpOptions::from_config_and_env(&config, |_| None).unwrap();
+        assert!(options.ssl_verify);
+        assert!(options.tls_config().unwrap().is_none());
+
+        // The environment overrides the configuration
+        let env = |name: &str| (name == "GIT_SSL_NO_VERIFY").then(|| "1".to_string());
+        assert!(
+            !HttpOptions::from_config_and_env(&config, env)
+                .unwrap()
+                .ssl_verify
+        );
+
+        // A CA file without certificates is refused
+        let ca = dir.path().join("ca.pem");
+        fs::write(&ca, "not a certificate").unwrap();
+        options.ca_info = Some(ca);
+        assert!(options.tls_config().is_err());
+    }
+
+    #[test]
+    fn test_request() {
+        let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let port = server.server_addr().to_ip().unwrap().port();
+        let id = Sha1Id::try_from(vec![7; 20]).unwrap();
+        let handle = thread::spawn(move || {
+            let mut request = server.recv().unwrap();
+            let (message, _) = read_request(&mut BufReader::new(request.as_reader()), 0).unwrap();
+            assert_eq!("group/project", message.repo());
+
+            let mut body = Vec::new();
+            let response = ProtocolResponse::Refs {
+                head: None,
+                refs: vec![("refs/head/main".to_string(), id)],
+            };
+            write_message(&mut body, &response, None).unwrap();
+            body.flush().unwrap();
+            request
+                .respond(tiny_http::Response::from_data(body))
+                .unwrap();
+        });
+
+        let (_dir, config) = load_config("");
+        let url = format!("http://127.0.0.1:{}/group/project", port);
+        let mut transport = HttpTransport::new(&url, &config).unwrap();
+        let refs = transport.ls_refs().unwrap();
+        assert_eq!(Some(id), refs.get("refs/head/main"));
+        handle.join().unwrap();
+    }
+}
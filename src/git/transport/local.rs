@@ -0,0 +1,41 @@
+use std::path::{Path, PathBuf};
+
+use crate::git::{
+    protocol::{answer_request, local_db_path, ProtocolRequest, ProtocolResponse},
+    utils::upgrade_database,
+};
+
+use super::Transport;
+
+/// [`LocalTransport`] answers requests by opening the database of a repository on the local
+/// filesystem directly
+#[derive(Debug)]
+pub struct LocalTransport {
+    path: String,
+    db_path: PathBuf,
+}
+
+impl LocalTransport {
+    pub fn new(path: &str) -> crate::Result<LocalTransport> {
+        let db_path = local_db_path(Path::new(path))?;
+        upgrade_database(&db_path)?;
+        Ok(LocalTransport {
+            path: path.to_string(),
+            db_path,
+        })
+    }
+}
+
+impl Transport for LocalTransport {
+    fn repo(&self) -> &str {
+        &self.path
+    }
+
+    fn request(
+        &mut self,
+        request: &ProtocolRequest,
+        pack: Option<&[u8]>,
+    ) -> crate::Result<(ProtocolResponse, Option<Vec<u8>>)> {
+        answer_request(&self.db_path, request, pack, true)
+    }
+}
@@ -0,0 +1,148 @@
+//! This module connects to remote repositories to fetch from and push to them. Every
+//! transport carries the same requests and responses (see [`crate::git::protocol`]), so the
+//! negotiation is shared and a backend only has to deliver the messages:
+//!
+//! - `http://` and `https://` URLs are reached over HTTP, see [`http`]
+//! - `gitqlite://host[:port]/path` URLs are reached over TCP, served by `gitqlite daemon`
+//! - `ssh://[user@]host[:port]/path` and `[user@]host:path` URLs are reached over SSH, see
+//!   [`ssh`]
+//! - anything else is a path to a repository on the local filesystem
+
+mod daemon;
+pub mod http;
+mod local;
+mod ssh;
+
+use anyhow::anyhow;
+
+use crate::repo::config::{ConfigSource, GitConfig};
+
+use super::{
+    model::Sha1Id,
+    protocol::{ProtocolRequest, ProtocolResponse, RefUpdate},
+};
+
+/// [`RemoteRefs`] is what a remote repository advertises
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteRefs {
+    /// Full name of the branch HEAD points to, None if HEAD is detached
+    pub head: Option<String>,
+    pub refs: Vec<(String, Sha1Id)>,
+}
+
+impl RemoteRefs {
+    /// The commit the ref with the given full name points to
+    pub fn get(&self, name: &str) -> Option<Sha1Id> {
+        self.refs
+            .iter()
+            .find(|(ref_name, _)| ref_name == name)
+            .map(|(_, id)| *id)
+    }
+}
+
+/// [`Transport`] delivers requests to a remote repository
+pub trait Transport {
+    /// Path of the repository on the remote side, sent along with each request
+    fn repo(&self) -> &str;
+
+    /// Send a request and return the response, along with the pack it carries if any
+    fn request(
+        &mut self,
+        request: &ProtocolRequest,
+        pack: Option<&[u8]>,
+    ) -> crate::Result<(ProtocolResponse, Option<Vec<u8>>)>;
+
+    /// List the refs of the remote repository
+    fn ls_refs(&mut self) -> crate::Result<RemoteRefs> {
+        let request = ProtocolRequest::LsRefs {
+            repo: self.repo().to_string(),
+        };
+        match self.request(&request, None)? {
+            (ProtocolResponse::Refs { head, refs }, _) => Ok(RemoteRefs { head, refs }),
+            (response, _) => Err(unexpected(&response)),
+        }
+    }
+
+    /// Download the history reachable from `wants` but not from `haves`, or its oldest
+    /// `max_commits` commits. Returns the pack and the number of commits left to download,
+    /// which servers not sending history in chunks never leave.
+    fn fetch_pack(
+        &mut self,
+        wants: &[Sha1Id],
+        haves: &[Sha1Id],
+        max_commits: Option<usize>,
+    ) -> crate::Result<(Vec<u8>, usize)> {
+        let request = ProtocolRequest::Fetch {
+            repo: self.repo().to_string(),
+            wants: wants.to_vec(),
+            haves: haves.to_vec(),
+            max_commits,
+        };
+        match self.request(&request, None)? {
+            (ProtocolResponse::Pack { remaining, .. }, Some(pack)) => Ok((pack, remaining)),
+            (response, _) => Err(unexpected(&response)),
+        }
+    }
+
+    /// Upload a pack and ask the remote to update its refs. Returns the outcome of each
+    /// update: None if it was applied, or the reason it was rejected.
+    fn push_pack(
+        &mut self,
+        updates: &[RefUpdate],
+        pack: &[u8],
+    ) -> crate::Result<Vec<(String, Option<String>)>> {
+        let request = ProtocolRequest::Push {
+            repo: self.repo().to_string(),
+            updates: updates.to_vec(),
+            pack_size: pack.len() as u64,
+        };
+        match self.request(&request, Some(pack))? {
+            (ProtocolResponse::Pushed { results }, _) => Ok(results),
+            (response, _) => Err(unexpected(&response)),
+        }
+    }
+}
+
+/// Open a transport to the repository at `url`
+pub fn open_transport(url: &str, config: &GitConfig) -> crate::Result<Box<dyn Transport>> {
+    if url.starts_with("http://") || url.starts_with("https://") {
+        Ok(Box::new(http::HttpTransport::new(url, config)?))
+    } else if let Some(address) = url.strip_prefix("gitqlite://") {
+        Ok(Box::new(daemon::DaemonTransport::new(address)?))
+    } else if url.starts_with("ssh://") || ssh::is_scp_like(url) {
+        Ok(Box::new(ssh::SshTransport::new(url, config)?))
+    } else {
+        let path = url.strip_prefix("file://").unwrap_or(url);
+        Ok(Box::new(local::LocalTransport::new(path)?))
+    }
+}
+
+/// The URL of the remote with the given name, or the argument itself if it is not the name of
+/// a configured remote
+pub fn remote_url(config: &GitConfig, remote: &str) -> crate::Result<String> {
+    let key = format!("remote.{}.url", remote);
+    Ok(config
+        .get(&key, ConfigSource::All)?
+        .unwrap_or(remote)
+        .to_string())
+}
+
+/// The URL pushes to the remote with the given name go to: `remote.<name>.pushurl` if set,
+/// else the one fetches use
+pub fn push_url(config: &GitConfig, remote: &str) -> crate::Result<String> {
+    let key = format!("remote.{}.pushurl", remote);
+    match config.get(&key, ConfigSource::All)? {
+        Some(url) => Ok(url.to_string()),
+        None => remote_url(config, remote),
+    }
+}
+
+/// Whether the name is a remote configured with a URL, rather than a URL or path itself
+pub fn is_configured_remote(config: &GitConfig, remote: &str) -> crate::Result<bool> {
+    let key = format!("remote.{}.url", remote);
+    Ok(config.get(&key, ConfigSource::All)?.is_some())
+}
+
+fn unexpected(response: &ProtocolResponse) -> anyhow::Error {
+    anyhow!("fatal: protocol error: unexpected response {:?}", response)
+}
@@ -0,0 +1,194 @@
+//! The SSH transport runs `gitqlite serve <path>` on the remote host through `ssh` for each
+//! request, writing the request to its standard input and reading the response from its
+//! standard output.
+//!
+//! Both `ssh://[user@]host[:port]/path` and scp-like `[user@]host:path` URLs are accepted.
+//! The `ssh` program can be replaced by setting `GIT_SSH_COMMAND` or `core.sshCommand`.
+
+use std::{
+    io::BufReader,
+    process::{Command, Stdio},
+};
+
+use anyhow::anyhow;
+
+use crate::{
+    git::protocol::{exchange, ProtocolRequest, ProtocolResponse},
+    repo::config::{ConfigSource, GitConfig},
+};
+
+use super::Transport;
+
+/// [`SshTransport`] sends each request to a `gitqlite serve` run over its own SSH session
+#[derive(Debug)]
+pub struct SshTransport {
+    /// The ssh program followed by its own arguments
+    ssh_command: Vec<String>,
+    /// `[user@]host`
+    host: String,
+    port: Option<String>,
+    repo: String,
+}
+
+impl SshTransport {
+    pub fn new(url: &str, config: &GitConfig) -> crate::Result<SshTransport> {
+        let (host, port, repo) = parse_ssh_url(url)?;
+        let ssh_command = match std::env::var("GIT_SSH_COMMAND") {
+            Ok(command) => command,
+            Err(_) => config
+                .get("core.sshCommand", ConfigSource::All)?
+                .unwrap_or("ssh")
+                .to_string(),
+        };
+        let ssh_command: Vec<String> = ssh_command.split_whitespace().map(String::from).collect();
+        if ssh_command.is_empty() {
+            return Err(anyhow!("fatal: empty ssh command"));
+        }
+        Ok(SshTransport {
+            ssh_command,
+            host,
+            port,
+            repo,
+        })
+    }
+}
+
+impl Transport for SshTransport {
+    fn repo(&self) -> &str {
+        &self.repo
+    }
+
+    fn request(
+        &mut self,
+        request: &ProtocolRequest,
+        pack: Option<&[u8]>,
+    ) -> crate::Result<(ProtocolResponse, Option<Vec<u8>>)> {
+        let mut command = Command::new(&self.ssh_command[0]);
+        command.args(&self.ssh_command[1..]);
+        if let Some(port) = &self.port {
+            command.args(["-p", port]);
+        }
+        // The host cannot be taken for an option of ssh, even if it starts with a dash
+        let mut child = command
+            .arg("--")
+            .arg(&self.host)
+            .arg(format!("gitqlite serve {}", shell_quote(&self.repo)))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|err| anyhow!("fatal: cannot run {}: {}", self.ssh_command[0], err))?;
+
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let result = exchange(&mut BufReader::new(stdout), &mut stdin, request, pack);
+        drop(stdin);
+        let status = child.wait()?;
+        if result.is_err() && !status.success() {
+            return Err(anyhow!("fatal: Could not read from remote repository."));
+        }
+        result
+    }
+}
+
+/// Split an SSH URL into `[user@]host`, port and the path of the repository
+fn parse_ssh_url(url: &str) -> crate::Result<(String, Option<String>, String)> {
+    let invalid = || anyhow!("fatal: invalid ssh URL '{}'", url);
+    if let Some(rest) = url.strip_prefix("ssh://") {
+        let (authority, path) = rest.split_once('/').ok_or_else(invalid)?;
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (host, Some(port.to_string())),
+            None => (authority, None),
+        };
+        check_host(host).map_err(|_| invalid())?;
+        if port
+            .as_ref()
+            .is_some_and(|port| port.is_empty() || !port.bytes().all(|b| b.is_ascii_digit()))
+        {
+            return Err(invalid());
+        }
+        // `ssh://host/~/repo` is relative to the home directory, like `host:~/repo`
+        let path = if path.starts_with('~') {
+            path.to_string()
+        } else {
+            format!("/{}", path)
+        };
+        return Ok((host.to_string(), port, path));
+    }
+
+    let (host, path) = url.split_once(':').ok_or_else(invalid)?;
+    check_host(host).map_err(|_| invalid())?;
+    if path.is_empty() {
+        return Err(invalid());
+    }
+    Ok((host.to_string(), None, path.to_string()))
+}
+
+/// Refuse an empty `[user@]host`, or one whose user or host starts with a dash and would be
+/// taken for an option by ssh (e.g. `-oProxyCommand=...`)
+fn check_host(host: &str) -> crate::Result<()> {
+    let name = host.rsplit_once('@').map_or(host, |(_, name)| name);
+    if name.is_empty() || host.starts_with('-') || name.starts_with('-') {
+        return Err(anyhow!("fatal: invalid ssh host '{}'", host));
+    }
+    Ok(())
+}
+
+/// Whether the URL is an scp-like `[user@]host:path` rather than a local path
+pub fn is_scp_like(url: &str) -> bool {
+    match url.split_once(':') {
+        Some((host, _)) => !host.is_empty() && !host.contains('/'),
+        None => false,
+    }
+}
+
+/// Quote an argument for the remote shell
+fn shell_quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ssh_url() {
+        let parsed = |url| parse_ssh_url(url).unwrap();
+        assert_eq!(
+            (
+                "git@example.com".to_string(),
+                None,
+                "group/repo".to_string()
+            ),
+            parsed("git@example.com:group/repo")
+        );
+        assert_eq!(
+            (
+                "git@example.com".to_string(),
+                Some("2222".to_string()),
+                "/srv/repo".to_string()
+            ),
+            parsed("ssh://git@example.com:2222/srv/repo")
+        );
+        assert_eq!(
+            ("example.com".to_string(), None, "~/repo".to_string()),
+            parsed("ssh://example.com/~/repo")
+        );
+        assert!(parse_ssh_url("ssh://example.com").is_err());
+        for url in [
+            "ssh://-oProxyCommand=touch%20x/repo",
+            "-oProxyCommand=touch x:repo",
+            "git@-oProxyCommand=x:repo",
+            "ssh://-l@example.com/repo",
+            "ssh://example.com:-1/repo",
+            "ssh://@/repo",
+        ] {
+            assert!(parse_ssh_url(url).is_err(), "{}", url);
+        }
+
+        assert!(is_scp_like("example.com:repo"));
+        assert!(!is_scp_like("./dir:with-colon"));
+        assert!(!is_scp_like("/tmp/repo"));
+
+        assert_eq!("'it'\\''s'", shell_quote("it's"));
+    }
+}
@@ -0,0 +1,204 @@
+//! This module converts between the nested tree objects stored in the database and a flat
+//! view of a snapshot, mapping every file's path (relative to repo root) to its blob.
+
+use std::{collections::BTreeMap, rc::Rc};
+
+use rusqlite::Connection;
+
+use super::{
+    cache::ObjectCache,
+    model::{Hashable, Index, Sha1Id, Tree, TreeEntry, TreeEntryType},
+};
+use crate::repo::db::HashAlgorithm;
+
+/// Mode recorded in the tree entry of a subdirectory
+pub const TREE_MODE: &str = "040000";
+
+/// [`FlatEntry`] is one file of a flattened tree: the blob it points to and its mode
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlatEntry {
+    pub id: Sha1Id,
+    pub mode: String,
+}
+
+/// Flat view of a snapshot: full path relative to repo root -> file
+pub type TreeView = BTreeMap<String, FlatEntry>;
+
+/// Flatten a tree stored in the database into a [`TreeView`]
+pub fn flatten_tree(conn: &Connection, tree_id: Sha1Id) -> crate::Result<TreeView> {
+    flatten(tree_id, |id| {
+        Tree::read_from_conn_with_id(conn, id).map(Rc::new)
+    })
+}
+
+/// Flatten a tree stored in the database into a [`TreeView`], reading its subtrees through
+/// the cache, for commands flattening the trees of many commits
+pub fn flatten_tree_cached(
+    conn: &Connection,
+    cache: &mut ObjectCache,
+    tree_id: Sha1Id,
+) -> crate::Result<TreeView> {
+    flatten(tree_id, |id| cache.read_tree(conn, id))
+}
+
+fn flatten(
+    tree_id: Sha1Id,
+    mut read_tree: impl FnMut(Sha1Id) -> crate::Result<Rc<Tree<Sha1Id>>>,
+) -> crate::Result<TreeView> {
+    let mut view = BTreeMap::new();
+
+    // (current tree, prefix of file names in the current tree)
+    let mut stack = Vec::with_capacity(32);
+    stack.push((tree_id, "".to_string()));
+
+    while let Some((cur_tree_id, prefix)) = stack.pop() {
+        let cur_tree = read_tree(cur_tree_id)?;
+        for entry in &cur_tree.entries {
+            let full_name = if prefix.is_empty() {
+                entry.name.clone()
+            } else {
+                format!("{}/{}", prefix, entry.name)
+            };
+
+            match entry.type_ {
+                TreeEntryType::Blob => {
+                    view.insert(
+                        full_name,
+                        FlatEntry {
+                            id: entry.id,
+                            mode: entry.mode.clone(),
+                        },
+                    );
+                }
+                TreeEntryType::Tree => stack.push((entry.id, full_name)),
+            }
+        }
+    }
+
+    Ok(view)
+}
+
+/// Build the [`TreeView`] of the snapshot staged in the index. Unmerged entries are skipped,
+/// and so are intent-to-add entries, whose content is not staged.
+pub fn index_view(index: &Index) -> TreeView {
+    index
+        .entries
+        .iter()
+        .filter(|entry| entry.flag_stage == 0 && !entry.flag_intent_to_add)
+        .map(|entry| {
+            (
+                entry.name.clone(),
+                FlatEntry {
+                    id: entry.sha,
+                    mode: entry.mode_perms.to_string(),
+                },
+            )
+        })
+        .collect()
+}
+
+/// Persist the trees needed to represent the snapshot and return the id of the root tree
+pub fn write_tree(conn: &Connection, view: &TreeView) -> crate::Result<Sha1Id> {
+    let files = view
+        .iter()
+        .map(|(name, entry)| (name.as_str(), entry))
+        .collect();
+    write_subtree(conn, HashAlgorithm::read(conn)?, files)
+}
+
+/// Persist the tree of one directory, given its files with paths relative to the directory.
+fn write_subtree(
+    conn: &Connection,
+    algorithm: HashAlgorithm,
+    files: Vec<(&str, &FlatEntry)>,
+) -> crate::Result<Sha1Id> {
+    let mut entries = Vec::new();
+    let mut subdirectories: BTreeMap<&str, Vec<(&str, &FlatEntry)>> = BTreeMap::new();
+
+    for (name, file) in files {
+        match name.split_once('/') {
+            Some((dir, rest)) => subdirectories.entry(dir).or_default().push((rest, file)),
+            None => entries.push(TreeEntry {
+                type_: TreeEntryType::Blob,
+                id: file.id,
+                mode: file.mode.clone(),
+                name: name.to_string(),
+            }),
+        }
+    }
+
+    for (dir, files) in subdirectories {
+        let id = write_subtree(conn, algorithm, files)?;
+        entries.push(TreeEntry {
+            type_: TreeEntryType::Tree,
+            id,
+            mode: TREE_MODE.to_string(),
+            name: dir.to_string(),
+        });
+    }
+
+    // Sort tree entry by their name
+    entries.sort_by(|e1, e2| e1.name.cmp(&e2.name));
+
+    let tree = Tree::new(entries);
+    let tree_id = tree.hash(algorithm.hasher());
+    let tree = tree.with_id(tree_id);
+    tree.persist(conn)?;
+
+    Ok(tree_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::model::CREATE_TREE_TABLE;
+
+    fn entry(byte: u8) -> FlatEntry {
+        FlatEntry {
+            id: Sha1Id::try_from(vec![byte; 20]).unwrap(),
+            mode: "33188".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_write_and_flatten_tree() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute(CREATE_TREE_TABLE, ()).unwrap();
+        HashAlgorithm::Sha1.persist(&conn).unwrap();
+
+        // a/b has no sibling files, so `a` only contains a subdirectory
+        let mut view = TreeView::new();
+        view.insert("README.md".to_string(), entry(1));
+        view.insert("a/b/c.txt".to_string(), entry(2));
+        view.insert("a/b/file with space.txt".to_string(), entry(3));
+        view.insert("src/main.rs".to_string(), entry(4));
+
+        let tree_id = write_tree(&conn, &view).unwrap();
+        assert_eq!(view, flatten_tree(&conn, tree_id).unwrap());
+    }
+
+    #[test]
+    fn test_write_empty_tree() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute(CREATE_TREE_TABLE, ()).unwrap();
+        HashAlgorithm::Sha1.persist(&conn).unwrap();
+
+        let tree_id = write_tree(&conn, &TreeView::new()).unwrap();
+        assert!(flatten_tree(&conn, tree_id).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_write_tree_with_repository_algorithm() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute(CREATE_TREE_TABLE, ()).unwrap();
+        HashAlgorithm::Blake3.persist(&conn).unwrap();
+
+        let mut view = TreeView::new();
+        view.insert("README.md".to_string(), entry(1));
+        let tree_id = write_tree(&conn, &view).unwrap();
+        let tree = Tree::read_from_conn_with_id(&conn, tree_id).unwrap();
+        assert_eq!(tree_id, tree.hash(HashAlgorithm::Blake3.hasher()));
+        assert_ne!(tree_id, tree.hash(HashAlgorithm::Sha1.hasher()));
+        assert_eq!(view, flatten_tree(&conn, tree_id).unwrap());
+    }
+}
@@ -1,27 +1,162 @@
 use std::{
     fs,
+    ops::Deref,
     path::{Path, PathBuf},
 };
 
 use anyhow::Context;
-use rusqlite::Connection;
+use rusqlite::{Connection, OpenFlags, TransactionBehavior};
 
-use super::constants::{GITQLITE_DB_NAME, GITQLITE_DIRECTORY_PREFIX};
+use super::{
+    constants::{GITQLITE_DB_NAME, GITQLITE_DIRECTORY_PREFIX},
+    dry_run,
+    manifest::prepare_connection,
+    perf,
+    schema::{schema_version, upgrade_schema, SCHEMA_VERSION},
+    sqlite::SqliteSettings,
+};
+use crate::repo::{config::GitConfig, db::HashAlgorithm};
 
 /**
  * Return a SQLITE connection to the local instance for the repository.
  */
 pub fn get_gitqlite_connection() -> crate::Result<Connection> {
     let pwd = std::env::current_dir()?;
-    let repo_root = find_gitqlite_root(pwd)?;
-    let db_path = repo_root
-        .join(GITQLITE_DIRECTORY_PREFIX)
-        .join(GITQLITE_DB_NAME);
+    open_gitqlite_connection(find_gitqlite_root(pwd)?)
+}
+
+/// Return a SQLite connection to the database of the repository at `repo_root`
+pub fn open_gitqlite_connection(repo_root: impl AsRef<Path>) -> crate::Result<Connection> {
+    let gitqlite_home = repo_root.as_ref().join(GITQLITE_DIRECTORY_PREFIX);
+    let db_path = gitqlite_home.join(GITQLITE_DB_NAME);
 
-    let conn = Connection::open(db_path)?;
+    let mut conn = Connection::open(db_path)?;
+    perf::profile_connection(&mut conn);
+    prepare_connection(&conn)?;
+    SqliteSettings::from_config(&GitConfig::load(&gitqlite_home)?)?.apply(&conn)?;
+    // A dry run upgrades the schema in the transaction it never commits too
+    dry_run::begin(&conn)?;
+    upgrade_schema(&mut conn)?;
+    dry_run::track_changes(&conn)?;
+    // The database of a repository `gitqlite init` is about to set up has no schema yet
+    if schema_version(&conn)? > 0 {
+        HashAlgorithm::read(&conn)?;
+    }
+    Ok(conn)
+}
+
+/// Return a read-only SQLite connection to the database of the repository at `repo_root`, for
+/// commands which only read. It does not keep the writing commands waiting.
+pub fn open_gitqlite_read_connection(repo_root: impl AsRef<Path>) -> crate::Result<Connection> {
+    let gitqlite_home = repo_root.as_ref().join(GITQLITE_DIRECTORY_PREFIX);
+    let db_path = gitqlite_home.join(GITQLITE_DB_NAME);
+
+    // A dry run leaves the database as it is, reading the schema it finds
+    if !dry_run::is_enabled() {
+        upgrade_database(&db_path)?;
+    }
+
+    let flags = OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX;
+    let mut conn = Connection::open_with_flags(db_path, flags)?;
+    perf::profile_connection(&mut conn);
+    if schema_version(&conn)? > 0 {
+        HashAlgorithm::read(&conn)?;
+    }
+    SqliteSettings::from_config(&GitConfig::load(&gitqlite_home)?)?.apply_read_only(&conn)?;
     Ok(conn)
 }
 
+/// Bring the database at `db_path` up to the current schema, which needs to write to it only
+/// if it is behind
+pub fn upgrade_database(db_path: &Path) -> crate::Result<()> {
+    let flags = OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX;
+    if schema_version(&Connection::open_with_flags(db_path, flags)?)? >= SCHEMA_VERSION {
+        return Ok(());
+    }
+    let mut conn = Connection::open(db_path)?;
+    prepare_connection(&conn)?;
+    upgrade_schema(&mut conn)
+}
+
+/// [`WriteTransaction`] applies the writes made through it all together or not at all: they are
+/// committed by [`WriteTransaction::commit`] and rolled back if it is dropped first, including
+/// when the process dies before it is done. Inside a transaction already open, such as the one a
+/// dry run keeps, it is a savepoint, releasing into the enclosing transaction.
+pub struct WriteTransaction<'conn> {
+    conn: &'conn Connection,
+    nested: bool,
+    done: bool,
+}
+
+impl<'conn> WriteTransaction<'conn> {
+    pub fn new(conn: &'conn Connection) -> crate::Result<WriteTransaction<'conn>> {
+        WriteTransaction::with_behavior(conn, TransactionBehavior::Deferred)
+    }
+
+    /// Begin a transaction taking the locks `behavior` says up front, unless it is nested
+    pub fn with_behavior(
+        conn: &'conn Connection,
+        behavior: TransactionBehavior,
+    ) -> crate::Result<WriteTransaction<'conn>> {
+        let nested = !conn.is_autocommit();
+        conn.execute_batch(match (nested, behavior) {
+            (true, _) => "SAVEPOINT gitqlite_write",
+            (false, TransactionBehavior::Immediate) => "BEGIN IMMEDIATE",
+            (false, TransactionBehavior::Exclusive) => "BEGIN EXCLUSIVE",
+            (false, _) => "BEGIN DEFERRED",
+        })?;
+        Ok(WriteTransaction {
+            conn,
+            nested,
+            done: false,
+        })
+    }
+
+    pub fn commit(mut self) -> crate::Result<()> {
+        self.done = true;
+        self.conn.execute_batch(if self.nested {
+            "RELEASE gitqlite_write"
+        } else {
+            "COMMIT"
+        })?;
+        Ok(())
+    }
+}
+
+impl Deref for WriteTransaction<'_> {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        self.conn
+    }
+}
+
+impl Drop for WriteTransaction<'_> {
+    fn drop(&mut self) {
+        if self.done {
+            return;
+        }
+        let _ = self.conn.execute_batch(if self.nested {
+            "ROLLBACK TO gitqlite_write; RELEASE gitqlite_write"
+        } else {
+            "ROLLBACK"
+        });
+    }
+}
+
+/// Run `f` in a transaction, so that the writes of a command are applied all together or not at
+/// all. The transaction is committed if `f` succeeds and rolled back otherwise, including when
+/// the process dies before it is done.
+pub fn with_transaction<T>(
+    conn: &mut Connection,
+    f: impl FnOnce(&Connection) -> crate::Result<T>,
+) -> crate::Result<T> {
+    let tx = WriteTransaction::new(conn)?;
+    let result = f(&tx)?;
+    tx.commit()?;
+    Ok(result)
+}
+
 /**
  * Recursively climb the directory to find the root of a gitqlite repository starting from the start directory path
  * by looking for a .gitqlite subdirectory.
@@ -54,3 +189,54 @@ fn is_gitqlite_root(path: impl AsRef<Path>) -> crate::Result<bool> {
 
     Ok(false)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::{
+        model::{Index, CREATE_BLOB_TABLE, CREATE_INDEX_TABLE},
+        test_utils::file_entry,
+        worktree::blob_index_entry,
+    };
+    use anyhow::anyhow;
+
+    /// Store a blob and stage it, as `add` does
+    fn add(tx: &Connection, name: &str) -> crate::Result<()> {
+        let mut index = Index::read_from_conn(tx)?;
+        index.insert(blob_index_entry(name, &file_entry(tx, name), 0));
+        index.persist(tx)
+    }
+
+    fn count_blobs(conn: &Connection) -> i64 {
+        conn.query_row("SELECT count(*) FROM Blobs", (), |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_with_transaction() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute(CREATE_BLOB_TABLE, ()).unwrap();
+        conn.execute(CREATE_INDEX_TABLE, ()).unwrap();
+        with_transaction(&mut conn, |tx| add(tx, "a")).unwrap();
+        let index = Index::read_from_conn(&conn).unwrap();
+        assert_eq!(1, index.entries.len());
+
+        // A command failing halfway through leaves no partial state behind
+        let result: crate::Result<()> = with_transaction(&mut conn, |tx| {
+            add(tx, "b")?;
+            Err(anyhow!("interrupted"))
+        });
+        assert!(result.is_err());
+        assert_eq!(1, count_blobs(&conn));
+        assert_eq!(index, Index::read_from_conn(&conn).unwrap());
+
+        // Nor does a process dying between the writes, which never commits the transaction
+        {
+            let tx = conn.transaction().unwrap();
+            add(&tx, "c").unwrap();
+            tx.execute("DELETE FROM Index_;", ()).unwrap();
+        }
+        assert_eq!(1, count_blobs(&conn));
+        assert_eq!(index, Index::read_from_conn(&conn).unwrap());
+    }
+}
@@ -0,0 +1,408 @@
+//! This module renders the pages of the repository browser served by `gitqlite web`. Every
+//! page is built straight from the database, so the working tree is never read:
+//!
+//! - `/` lists the branches
+//! - `/log/<rev>` lists the history of a revision
+//! - `/commit/<rev>` shows a commit and its changes against its first parent
+//! - `/tree/<rev>/<path>` lists a directory of the snapshot of a revision
+//! - `/blob/<rev>/<path>` shows a file of the snapshot of a revision
+
+use anyhow::anyhow;
+use rusqlite::Connection;
+use tiny_http::{Header, Request, Response, Server};
+
+use super::{
+    constants::BRANCH_PREFIX,
+    diff::{diff_commit, render, DiffOutput},
+    model::{Blob, Commit, Head, Ref, Sha1Id, Tree, TreeEntryType},
+    rebase::{short_id, summary},
+    revision::{commits_between, lookup_path, resolve_revision},
+};
+
+const STYLE: &str = "body { font-family: sans-serif; margin: 2em; }
+pre { background: #f6f8fa; padding: 1em; overflow: auto; }
+table { border-collapse: collapse; }
+td { padding: 0.2em 1em 0.2em 0; }
+.add { color: #22863a; }
+.del { color: #b31d28; }
+.hunk { color: #6f42c1; }
+.id { font-family: monospace; }";
+
+/// [`Page`] is the response to a request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Page {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: Vec<u8>,
+}
+
+impl Page {
+    fn html(title: &str, content: &str) -> Page {
+        let body = format!(
+            "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{}</title><style>{}</style></head>\n<body><p><a href=\"/\">branches</a></p>\n<h1>{}</h1>\n{}</body></html>\n",
+            escape_html(title),
+            STYLE,
+            escape_html(title),
+            content
+        );
+        Page {
+            status: 200,
+            content_type: "text/html; charset=utf-8",
+            body: body.into_bytes(),
+        }
+    }
+
+    fn not_found(message: &str) -> Page {
+        Page {
+            status: 404,
+            ..Page::html("Not Found", &format!("<p>{}</p>\n", escape_html(message)))
+        }
+    }
+
+    pub fn server_error(message: &str) -> Page {
+        Page {
+            status: 500,
+            ..Page::html(
+                "Internal Server Error",
+                &format!("<p>{}</p>\n", escape_html(message)),
+            )
+        }
+    }
+}
+
+/// Serve HTTP requests on `listen` forever, answering each with the page built by `handler`
+pub fn serve(listen: &str, mut handler: impl FnMut(&mut Request) -> Page) -> crate::Result<()> {
+    let server = Server::http(listen)
+        .map_err(|err| anyhow!("fatal: unable to listen on {}: {}", listen, err))?;
+
+    for mut request in server.incoming_requests() {
+        let page = handler(&mut request);
+        let content_type = Header::from_bytes("Content-Type", page.content_type)
+            .map_err(|_| anyhow!("fatal: invalid content type {}", page.content_type))?;
+        let response = Response::from_data(page.body)
+            .with_status_code(page.status)
+            .with_header(content_type);
+        if let Err(err) = request.respond(response) {
+            eprintln!("error: failed to send response: {}", err);
+        }
+    }
+
+    Ok(())
+}
+
+/// Build the page for the given request URL. Errors, such as unknown revisions or paths,
+/// result in a 404 page.
+pub fn handle_request(conn: &Connection, url: &str) -> Page {
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    let path = percent_decode(path);
+    let path = path.trim_matches('/');
+    let (route, rest) = path.split_once('/').unwrap_or((path, ""));
+
+    let page = match route {
+        "" => branches_page(conn),
+        "log" => log_page(conn, rest),
+        "commit" => commit_page(conn, rest),
+        "tree" | "blob" => {
+            let (rev, file) = rest.split_once('/').unwrap_or((rest, ""));
+            if route == "tree" {
+                tree_page(conn, rev, file)
+            } else {
+                blob_page(conn, rev, file)
+            }
+        }
+        _ => Err(anyhow!("No such page: /{}", path)),
+    };
+    page.unwrap_or_else(|err| Page::not_found(&err.to_string()))
+}
+
+fn branches_page(conn: &Connection) -> crate::Result<Page> {
+    let head = Head::read_from_conn(conn)?;
+    let mut rows = String::new();
+    for reference in Ref::read_all(conn)? {
+        let name = reference
+            .name
+            .strip_prefix(BRANCH_PREFIX)
+            .unwrap_or(&reference.name);
+        let current = matches!(&head, Head::Branch(branch) if *branch == reference.name);
+        let commit = Commit::read_from_conn_with_id(conn, reference.commit_id)?;
+        rows.push_str(&format!(
+            "<tr><td>{}{}</td><td>{}</td><td>{}</td><td><a href=\"/log/{}\">log</a> <a href=\"/tree/{}/\">tree</a></td></tr>\n",
+            if current { "* " } else { "" },
+            escape_html(name),
+            commit_link(commit.commit_id),
+            escape_html(summary(&commit.message)),
+            escape_html(name),
+            commit.commit_id,
+        ));
+    }
+
+    let content = if rows.is_empty() {
+        "<p>There are no commits yet.</p>\n".to_string()
+    } else {
+        format!("<table>\n{}</table>\n", rows)
+    };
+    Ok(Page::html("Branches", &content))
+}
+
+fn log_page(conn: &Connection, rev: &str) -> crate::Result<Page> {
+    let rev = if rev.is_empty() { "HEAD" } else { rev };
+    let tip = resolve_revision(conn, rev)?;
+
+    let mut rows = String::new();
+    for id in commits_between(conn, None, tip)?.into_iter().rev() {
+        let commit = Commit::read_from_conn_with_id(conn, id)?;
+        rows.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+            commit_link(id),
+            escape_html(summary(&commit.message)),
+            escape_html(&commit.author_name),
+        ));
+    }
+    Ok(Page::html(
+        &format!("History of {}", rev),
+        &format!("<table>\n{}</table>\n", rows),
+    ))
+}
+
+fn commit_page(conn: &Connection, rev: &str) -> crate::Result<Page> {
+    let id = resolve_revision(conn, rev)?;
+    let commit = Commit::read_from_conn_with_id(conn, id)?;
+
+    let mut content = format!(
+        "<table>\n<tr><td>commit</td><td class=\"id\">{}</td></tr>\n<tr><td>author</td><td>{} &lt;{}&gt;</td></tr>\n<tr><td>committer</td><td>{} &lt;{}&gt;</td></tr>\n<tr><td>tree</td><td><a class=\"id\" href=\"/tree/{}/\">{}</a></td></tr>\n",
+        id,
+        escape_html(&commit.author_name),
+        escape_html(&commit.author_email),
+        escape_html(&commit.committer_name),
+        escape_html(&commit.committer_email),
+        id,
+        commit.tree_id,
+    );
+    for parent in &commit.parent_ids {
+        content.push_str(&format!(
+            "<tr><td>parent</td><td>{}</td></tr>\n",
+            commit_link(*parent)
+        ));
+    }
+    content.push_str(&format!(
+        "</table>\n<pre>{}</pre>\n",
+        escape_html(&commit.message)
+    ));
+
+    let changes = diff_commit(conn, &commit)?;
+    let output = DiffOutput {
+        stat: true,
+        patch: true,
+        ..DiffOutput::default()
+    };
+    content.push_str(&format!(
+        "<pre>{}</pre>\n",
+        highlight_diff(&render(&changes, output)?)
+    ));
+
+    Ok(Page::html(summary(&commit.message), &content))
+}
+
+fn tree_page(conn: &Connection, rev: &str, path: &str) -> crate::Result<Page> {
+    let path = path.trim_end_matches('/');
+    let root = Commit::read_from_conn_with_id(conn, resolve_revision(conn, rev)?)?.tree_id;
+    let tree_id = if path.is_empty() {
+        root
+    } else {
+        match lookup_path(conn, root, path)? {
+            Some(entry) if entry.type_ == TreeEntryType::Tree => entry.id,
+            _ => return Err(anyhow!("No such directory: {}", path)),
+        }
+    };
+
+    let prefix = if path.is_empty() {
+        String::new()
+    } else {
+        format!("{}/", path)
+    };
+    let mut rows = String::new();
+    for entry in Tree::read_from_conn_with_id(conn, tree_id)?.entries {
+        let (kind, suffix) = match entry.type_ {
+            TreeEntryType::Tree => ("tree", "/"),
+            TreeEntryType::Blob => ("blob", ""),
+        };
+        rows.push_str(&format!(
+            "<tr><td class=\"id\">{:06o}</td><td><a href=\"/{}/{}/{}{}\">{}{}</a></td></tr>\n",
+            entry.mode.parse::<u32>().unwrap_or_default(),
+            kind,
+            escape_html(rev),
+            escape_html(&prefix),
+            escape_html(&entry.name),
+            escape_html(&entry.name),
+            suffix,
+        ));
+    }
+
+    Ok(Page::html(
+        &format!("{}:{}", rev, path),
+        &format!("<table>\n{}</table>\n", rows),
+    ))
+}
+
+fn blob_page(conn: &Connection, rev: &str, path: &str) -> crate::Result<Page> {
+    let root = Commit::read_from_conn_with_id(conn, resolve_revision(conn, rev)?)?.tree_id;
+    let blob_id = match lookup_path(conn, root, path)? {
+        Some(entry) if entry.type_ == TreeEntryType::Blob => entry.id,
+        _ => return Err(anyhow!("No such file: {}", path)),
+    };
+
+    let data = Blob::read_from_conn_with_id(conn, blob_id)?.data;
+    let content = if data.contains(&0) {
+        format!("<p>Binary file ({} bytes)</p>\n", data.len())
+    } else {
+        format!(
+            "<pre>{}</pre>\n",
+            escape_html(&String::from_utf8_lossy(&data))
+        )
+    };
+    Ok(Page::html(&format!("{}:{}", rev, path), &content))
+}
+
+fn commit_link(id: Sha1Id) -> String {
+    format!(
+        "<a class=\"id\" href=\"/commit/{}\">{}</a>",
+        id,
+        short_id(id)
+    )
+}
+
+/// Escape a patch and color its added, deleted and hunk header lines
+fn highlight_diff(patch: &str) -> String {
+    let mut out = String::new();
+    for line in patch.lines() {
+        let class = if line.starts_with("+++") || line.starts_with("---") {
+            None
+        } else if line.starts_with('+') {
+            Some("add")
+        } else if line.starts_with('-') {
+            Some("del")
+        } else if line.starts_with("@@") {
+            Some("hunk")
+        } else {
+            None
+        };
+        match class {
+            Some(class) => out.push_str(&format!(
+                "<span class=\"{}\">{}</span>\n",
+                class,
+                escape_html(line)
+            )),
+            None => out.push_str(&format!("{}\n", escape_html(line))),
+        }
+    }
+    out
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Decode `%XX` escapes in a URL path, leaving malformed escapes as they are
+pub fn percent_decode(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let decoded = (bytes[i] == b'%')
+            .then(|| bytes.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match decoded {
+            Some(byte) => {
+                out.push(byte);
+                i += 3;
+            }
+            None => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::{
+        model::{CREATE_HEAD_TABLE, CREATE_REF_TABLE},
+        test_utils::{commit_files, object_database},
+    };
+
+    /// A repository with two commits on main, the second changing `src/lib.rs`
+    fn setup() -> (Connection, Sha1Id) {
+        let conn = object_database(&[CREATE_HEAD_TABLE, CREATE_REF_TABLE]);
+
+        let mut parent_ids = vec![];
+        for content in ["fn a() {}\n", "fn a() {}\nfn b<T>() {}\n"] {
+            let message = format!("write {} bytes", content.len());
+            let id = commit_files(&conn, parent_ids, &[("src/lib.rs", content)], &message);
+            parent_ids = vec![id];
+        }
+
+        let tip = parent_ids[0];
+        Ref {
+            name: format!("{}main", BRANCH_PREFIX),
+            commit_id: tip,
+        }
+        .persist_or_update(&conn)
+        .unwrap();
+        Head::Branch(format!("{}main", BRANCH_PREFIX))
+            .persist(&conn)
+            .unwrap();
+        (conn, tip)
+    }
+
+    fn body(page: &Page) -> &str {
+        std::str::from_utf8(&page.body).unwrap()
+    }
+
+    #[test]
+    fn test_pages() {
+        let (conn, tip) = setup();
+
+        let page = handle_request(&conn, "/");
+        assert_eq!(200, page.status);
+        assert!(body(&page).contains("* main"));
+        assert!(body(&page).contains(&format!("/commit/{}", tip)));
+
+        let page = handle_request(&conn, "/log/main");
+        assert!(body(&page).contains("write 10 bytes"));
+        assert!(body(&page).contains("write 23 bytes"));
+
+        let page = handle_request(&conn, &format!("/commit/{}", tip));
+        assert!(body(&page).contains("<span class=\"add\">+fn b&lt;T&gt;() {}</span>"));
+        assert!(body(&page).contains("1 file changed, 1 insertion(+)"));
+
+        let page = handle_request(&conn, "/tree/main/src/");
+        assert!(body(&page).contains("<a href=\"/blob/main/src/lib.rs\">lib.rs</a>"));
+
+        let page = handle_request(&conn, "/blob/main%5E/src/lib.rs?plain");
+        assert_eq!(200, page.status);
+        assert!(body(&page).contains("<pre>fn a() {}\n</pre>"));
+    }
+
+    #[test]
+    fn test_not_found() {
+        let (conn, _) = setup();
+        assert_eq!(404, handle_request(&conn, "/nowhere").status);
+        assert_eq!(404, handle_request(&conn, "/log/unknown").status);
+        assert_eq!(404, handle_request(&conn, "/blob/main/src").status);
+        assert_eq!(404, handle_request(&conn, "/tree/main/src/lib.rs").status);
+    }
+
+    #[test]
+    fn test_percent_decode() {
+        assert_eq!("a b/c^", percent_decode("a%20b/c%5e"));
+        assert_eq!("100%", percent_decode("100%"));
+        assert_eq!("%zz", percent_decode("%zz"));
+    }
+}
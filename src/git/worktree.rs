@@ -0,0 +1,696 @@
+//! This module provides helpers to bring files in the working tree in sync with blobs in the
+//! database, and to record their state in the index.
+
+use std::{
+    borrow::Cow,
+    collections::{BTreeSet, HashMap, HashSet, VecDeque},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context};
+use rayon::prelude::*;
+use rusqlite::Connection;
+use unicode_normalization::UnicodeNormalization;
+
+use super::{
+    attributes::ATTRIBUTES_FILE,
+    constants::GITQLITE_DIRECTORY_PREFIX,
+    dry_run,
+    files::GitqliteFileMetadataExt,
+    filters::{forget_filters, load_filters},
+    ignore::GitIgnore,
+    model::{hash_blob_data, Blob, Hashable, Index, IndexEntry, ModeType, Sha1Id, INSERT_BLOB},
+    paths::{display_path, verify_path, worktree_path},
+    perf::{self, Category},
+    progress::{FileEvent, Progress},
+    tree::{FlatEntry, TreeView},
+};
+use crate::repo::{
+    config::{ConfigSource, GitConfig},
+    db::HashAlgorithm,
+};
+
+/// Number of files read and hashed together by [`stage_files`], bounding the memory holding
+/// their content
+const STAGE_BATCH_SIZE: usize = 256;
+
+/// Whether the names of files read from the working tree are precomposed (converted to Unicode
+/// NFC) before being recorded, as set by `core.precomposeUnicode`. macOS file systems may hand
+/// out decomposed (NFD) names, so this is on by default there.
+pub fn precompose_unicode(repo_root: impl AsRef<Path>) -> crate::Result<bool> {
+    let config = GitConfig::load(repo_root.as_ref().join(GITQLITE_DIRECTORY_PREFIX))?;
+    Ok(config
+        .get_bool("core.precomposeUnicode", ConfigSource::All)?
+        .unwrap_or(cfg!(target_os = "macos")))
+}
+
+/// Name of a file of the working tree as recorded in the index and trees: its path relative to
+/// repo root, precomposed if `precompose` is set
+pub fn worktree_name(
+    repo_root: impl AsRef<Path>,
+    path: impl AsRef<Path>,
+    precompose: bool,
+) -> crate::Result<String> {
+    // Names are separated by `/` whatever the platform
+    let name = path
+        .as_ref()
+        .strip_prefix(repo_root)?
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
+    Ok(precompose_name(&name, precompose))
+}
+
+/// Precompose a name if `precompose` is set
+pub fn precompose_name(name: &str, precompose: bool) -> String {
+    if precompose {
+        name.nfc().collect()
+    } else {
+        name.to_string()
+    }
+}
+
+/// Build the index entry of a file in the working tree whose content hashes to `sha`.
+/// `name` is the path of the file relative to repo root.
+pub fn index_entry_from_file(
+    path: impl AsRef<Path>,
+    name: String,
+    sha: Sha1Id,
+) -> crate::Result<IndexEntry> {
+    let metadata = fs::metadata(path)?;
+
+    Ok(IndexEntry {
+        ctime: metadata.g_ctime(),
+        mtime: metadata.g_mtime(),
+        dev: metadata.g_dev(),
+        ino: metadata.g_ino(),
+        mode_type: ModeType::Regular,
+        mode_perms: metadata.g_mode_perms(),
+        uid: metadata.g_uid(),
+        gid: metadata.g_gid(),
+        fsize: metadata.g_fsize(),
+        sha,
+        flag_assume_valid: false,
+        flag_stage: 0,
+        flag_intent_to_add: false,
+        name,
+    })
+}
+
+/// Build an index entry for a blob that is not checked out in the working tree, such as one
+/// version of a conflicted file. Only the blob, mode and stage are filled in.
+pub fn blob_index_entry(name: &str, entry: &FlatEntry, stage: u8) -> IndexEntry {
+    IndexEntry {
+        ctime: 0,
+        mtime: 0,
+        dev: 0,
+        ino: 0,
+        mode_type: ModeType::Regular,
+        mode_perms: entry.mode.parse().unwrap_or_default(),
+        uid: 0,
+        gid: 0,
+        fsize: 0,
+        sha: entry.id,
+        flag_assume_valid: false,
+        flag_stage: stage,
+        flag_intent_to_add: false,
+        name: name.to_string(),
+    }
+}
+
+/// Hash the file at `name` in the working tree, returning None if it does not exist
+pub fn hash_worktree_file(
+    repo_root: impl AsRef<Path>,
+    name: &str,
+    algorithm: HashAlgorithm,
+) -> crate::Result<Option<Sha1Id>> {
+    if !worktree_path(&repo_root, name).is_file() {
+        return Ok(None);
+    }
+    Ok(Some(
+        read_worktree_blob(repo_root, name, algorithm)?.blob_id,
+    ))
+}
+
+/// Read the file at `name` in the working tree as the blob storing it, cleaned by the filters
+/// of its path
+pub fn read_worktree_blob(
+    repo_root: impl AsRef<Path>,
+    name: &str,
+    algorithm: HashAlgorithm,
+) -> crate::Result<Blob<Sha1Id>> {
+    let blob = Blob::new(read_worktree_data(repo_root, name)?);
+    let blob_id = blob.hash(algorithm.hasher());
+    Ok(blob.with_id(blob_id))
+}
+
+/// Read the content of the file at `name` in the working tree, cleaned by the filters of its
+/// path
+pub fn read_worktree_data(repo_root: impl AsRef<Path>, name: &str) -> crate::Result<Vec<u8>> {
+    let repo_root = repo_root.as_ref();
+    let data = fs::read(worktree_path(repo_root, name))?;
+    let cleaned = match load_filters(repo_root)?.clean(name, &data)? {
+        Cow::Owned(cleaned) => Some(cleaned),
+        Cow::Borrowed(_) => None,
+    };
+    Ok(cleaned.unwrap_or(data))
+}
+
+/// Write the content of a blob to `name` in the working tree, creating parent directories
+/// as needed, and return the index entry describing the written file.
+pub fn checkout_blob(
+    conn: &Connection,
+    repo_root: impl AsRef<Path>,
+    name: &str,
+    entry: &FlatEntry,
+) -> crate::Result<IndexEntry> {
+    let blob = Blob::read_from_conn_with_id(conn, entry.id)?;
+    write_worktree_file(&repo_root, name, &blob.data, &entry.mode)?;
+    if dry_run::is_enabled() {
+        // Nothing was written to describe
+        return Ok(blob_index_entry(name, entry, 0));
+    }
+    index_entry_from_file(worktree_path(repo_root, name), name.to_string(), entry.id)
+}
+
+/// Write arbitrary data to `name` in the working tree with the permissions encoded in `mode`,
+/// smudged by the filters of its path
+pub fn write_worktree_file(
+    repo_root: impl AsRef<Path>,
+    name: &str,
+    data: &[u8],
+    mode: &str,
+) -> crate::Result<()> {
+    verify_path(name)?;
+    let repo_root = repo_root.as_ref();
+    let data = load_filters(repo_root)?.smudge(name, data)?;
+    write_file(&worktree_path(repo_root, name), name, &data, mode)?;
+    if name.rsplit('/').next() == Some(ATTRIBUTES_FILE) {
+        forget_filters();
+    }
+    Ok(())
+}
+
+/// Write the files of a snapshot outside of the working tree, each file to `<prefix><name>`
+/// under `base`, e.g. with the prefix `out/` to export into the `out` directory. Existing
+/// files are only overwritten with `force`; the names of the files left alone are returned.
+pub fn export_view(
+    conn: &Connection,
+    base: impl AsRef<Path>,
+    prefix: &str,
+    view: &TreeView,
+    force: bool,
+) -> crate::Result<Vec<String>> {
+    let mut skipped = Vec::new();
+    for (name, entry) in view {
+        verify_path(name)?;
+        let path = worktree_path(&base, &format!("{}{}", prefix, name));
+        if !force && fs::symlink_metadata(&path).is_ok() {
+            skipped.push(name.clone());
+            continue;
+        }
+        let blob = Blob::read_from_conn_with_id(conn, entry.id)?;
+        write_file(&path, name, &blob.data, &entry.mode)?;
+    }
+    Ok(skipped)
+}
+
+/// Write data to the file `name` at `path`, creating parent directories as needed, with the
+/// permissions encoded in `mode`. The file is not written through a symbolic link standing for
+/// one of the directories of `name`, which could lead out of the working tree.
+fn write_file(path: &Path, name: &str, data: &[u8], mode: &str) -> crate::Result<()> {
+    let cannot_write = || format!("fatal: cannot write '{}'", display_path(path).display());
+    if beyond_symlink(path, name) {
+        return Err(anyhow!("{}: beyond a symbolic link", cannot_write()));
+    }
+    if dry_run::skip_write("write", path) {
+        return Ok(());
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).with_context(cannot_write)?;
+    }
+    fs::write(path, data).with_context(cannot_write)?;
+
+    #[cfg(unix)]
+    if let Ok(mode) = mode.parse::<u32>() {
+        use std::os::unix::fs::PermissionsExt;
+        if mode & 0o777 != 0 {
+            fs::set_permissions(path, fs::Permissions::from_mode(mode & 0o777))?;
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = mode;
+
+    Ok(())
+}
+
+/// Whether one of the directories of `name`, the file at `path`, is a symbolic link
+fn beyond_symlink(path: &Path, name: &str) -> bool {
+    let depth = name.split('/').count() - 1;
+    path.ancestors().skip(1).take(depth).any(|dir| {
+        fs::symlink_metadata(dir).is_ok_and(|metadata| metadata.file_type().is_symlink())
+    })
+}
+
+/// Remove `name` from the working tree, along with any parent directory left empty. A file
+/// beyond a symbolic link is not in the working tree, and is left alone.
+pub fn remove_worktree_file(repo_root: impl AsRef<Path>, name: &str) -> crate::Result<()> {
+    verify_path(name)?;
+    let path = worktree_path(repo_root, name);
+    if beyond_symlink(&path, name) {
+        return Ok(());
+    }
+    if path.is_file() && dry_run::skip_write("remove", &path) {
+        return Ok(());
+    }
+    if path.is_file() {
+        fs::remove_file(&path)?;
+    }
+
+    // The directories of `name`, which stop at the root of the working tree
+    for dir in path.ancestors().skip(1).take(name.split('/').count() - 1) {
+        if fs::remove_dir(dir).is_err() {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// Bring the index and working tree to the given snapshot, discarding any change to tracked
+/// files (including unmerged ones). Untracked files are left alone. Each file written or
+/// removed is reported to `progress`.
+pub fn reset_hard(
+    conn: &Connection,
+    repo_root: impl AsRef<Path>,
+    index: &mut Index,
+    target: &TreeView,
+    progress: &mut Progress,
+) -> crate::Result<()> {
+    let repo_root = repo_root.as_ref();
+
+    let tracked: BTreeSet<String> = index.entries.iter().map(|e| e.name.clone()).collect();
+    let removed: Vec<String> = tracked
+        .into_iter()
+        .filter(|name| !target.contains_key(name))
+        .collect();
+    let algorithm = HashAlgorithm::read(conn)?;
+    let staged: HashMap<&str, &IndexEntry> = index
+        .entries
+        .iter()
+        .filter(|e| e.flag_stage == 0)
+        .map(|e| (e.name.as_str(), e))
+        .collect();
+    let mut updated = Vec::new();
+    for (name, entry) in target {
+        let unchanged = match staged.get(name.as_str()) {
+            Some(staged) if staged.sha == entry.id => {
+                match fs::metadata(worktree_path(repo_root, name)) {
+                    Ok(metadata) if metadata.is_file() => {
+                        stat_unchanged(staged, &metadata)
+                            || hash_worktree_file(repo_root, name, algorithm)? == Some(entry.id)
+                    }
+                    _ => false,
+                }
+            }
+            _ => false,
+        };
+        if !unchanged {
+            updated.push((name, entry));
+        }
+    }
+    progress.add_total(removed.len() + updated.len());
+
+    for name in removed {
+        remove_worktree_file(repo_root, &name)?;
+        index.remove_name(&name);
+        progress.report(FileEvent::Removed);
+    }
+    for (name, entry) in updated {
+        index.insert(checkout_blob(conn, repo_root, name, entry)?);
+        progress.report(FileEvent::Updated);
+    }
+
+    Ok(())
+}
+
+/// Bring the index to the given snapshot, leaving the working tree alone. Entries already
+/// staging the blob and mode of the snapshot keep the state of the file they record, so that
+/// unchanged files are not read again to tell they are unchanged; the other files of the
+/// snapshot are staged without any, and conflicts are dropped.
+pub fn reset_index(index: &mut Index, target: &TreeView) {
+    index.entries.retain(|e| {
+        e.flag_stage == 0
+            && !e.flag_intent_to_add
+            && target.get(&e.name).is_some_and(|entry| {
+                entry.id == e.sha && entry.mode.parse().ok() == Some(e.mode_perms)
+            })
+    });
+    let kept: HashSet<&str> = index.entries.iter().map(|e| e.name.as_str()).collect();
+    let missing: Vec<IndexEntry> = target
+        .iter()
+        .filter(|(name, _)| !kept.contains(name.as_str()))
+        .map(|(name, entry)| blob_index_entry(name, entry, 0))
+        .collect();
+    index.insert_all(missing);
+}
+
+/// Snapshot of the tracked files as they currently are in the working tree. Tracked files
+/// missing from the working tree are left out. As in `status`, a file still modified at the
+/// time and of the size the index records is taken to hold the staged blob, without being read.
+pub fn worktree_view(
+    repo_root: impl AsRef<Path>,
+    index: &Index,
+    algorithm: HashAlgorithm,
+) -> crate::Result<TreeView> {
+    let repo_root = repo_root.as_ref();
+    let mut view = TreeView::new();
+    for entry in index.entries.iter().filter(|e| e.flag_stage == 0) {
+        let path = worktree_path(repo_root, &entry.name);
+        let metadata = match fs::metadata(&path) {
+            Ok(metadata) if metadata.is_file() => metadata,
+            _ => continue,
+        };
+        let id = if stat_unchanged(entry, &metadata) {
+            entry.sha
+        } else {
+            read_worktree_blob(repo_root, &entry.name, algorithm)?.blob_id
+        };
+        view.insert(
+            entry.name.clone(),
+            FlatEntry {
+                id,
+                mode: metadata.g_mode_perms().to_string(),
+            },
+        );
+    }
+    Ok(view)
+}
+
+/// Whether the file of the working tree `metadata` describes still holds the blob `entry`
+/// stages, as far as its modification time and size tell, so that it need not be read
+fn stat_unchanged(entry: &IndexEntry, metadata: &fs::Metadata) -> bool {
+    // The blob of an intent-to-add entry is a placeholder, not the content of the file
+    entry.mtime == metadata.g_mtime()
+        && entry.fsize == metadata.g_fsize()
+        && !entry.flag_intent_to_add
+}
+
+/// Names of tracked files whose content in the working tree differs from the index
+pub fn modified_files(
+    repo_root: impl AsRef<Path>,
+    index: &Index,
+    algorithm: HashAlgorithm,
+) -> crate::Result<Vec<String>> {
+    let mut modified = Vec::new();
+    for entry in index.entries.iter().filter(|e| e.flag_stage == 0) {
+        if hash_worktree_file(&repo_root, &entry.name, algorithm)? != Some(entry.sha) {
+            modified.push(entry.name.clone());
+        }
+    }
+    Ok(modified)
+}
+
+/// List the files under `dir` in the working tree, leaving out ignored files and the
+/// repository directories
+pub fn worktree_files(
+    repo_root: impl AsRef<Path>,
+    dir: impl AsRef<Path>,
+    ignore: &GitIgnore,
+) -> crate::Result<Vec<PathBuf>> {
+    let gitqlite_home = repo_root.as_ref().join(GITQLITE_DIRECTORY_PREFIX);
+    let git_home = repo_root.as_ref().join(".git");
+
+    let _span = perf::span(Category::FsWalk);
+    let mut files = Vec::new();
+    let mut queue = VecDeque::new();
+    queue.push_back(dir.as_ref().to_path_buf());
+    while let Some(cur_directory) = queue.pop_front() {
+        if cur_directory.starts_with(&gitqlite_home) || cur_directory.starts_with(&git_home) {
+            continue;
+        }
+        for entry in fs::read_dir(&cur_directory)?.filter_map(Result::ok) {
+            let path = entry.path();
+            if ignore.should_ignore(&path) {
+                continue;
+            }
+            if path.is_dir() {
+                queue.push_back(path);
+            } else {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Store the content of the files as blobs and stage them in the index. Files are hashed in
+/// parallel, and the blobs inserted with a single prepared statement, so this should run in a
+/// transaction to write them all at once.
+pub fn stage_files(
+    conn: &Connection,
+    repo_root: impl AsRef<Path>,
+    paths: &[PathBuf],
+    precompose: bool,
+) -> crate::Result<()> {
+    let repo_root = repo_root.as_ref();
+    let algorithm = HashAlgorithm::read(conn)?;
+    let mut insert_blob = conn.prepare_cached(INSERT_BLOB)?;
+    let mut entries = Vec::with_capacity(paths.len());
+
+    for batch in paths.chunks(STAGE_BATCH_SIZE) {
+        let names = batch
+            .iter()
+            .map(|path| {
+                let name = worktree_name(repo_root, path, precompose)?;
+                verify_path(&name)?;
+                Ok(name)
+            })
+            .collect::<crate::Result<Vec<_>>>()?;
+        let blobs = names
+            .par_iter()
+            .map(|name| read_worktree_blob(repo_root, name, algorithm))
+            .collect::<crate::Result<Vec<_>>>()?;
+        for ((path, name), blob) in batch.iter().zip(names).zip(blobs) {
+            insert_blob.execute((&blob.blob_id, &blob.data))?;
+            entries.push(index_entry_from_file(path, name, blob.blob_id)?);
+        }
+    }
+
+    Index::stage_entries(conn, entries)
+}
+
+/// Record the untracked files among `paths` in the index as intent-to-add entries, pointing
+/// at the empty blob: they become tracked without their content being staged. Tracked files
+/// are left alone.
+pub fn intend_to_add(
+    conn: &Connection,
+    repo_root: impl AsRef<Path>,
+    paths: &[PathBuf],
+    precompose: bool,
+) -> crate::Result<()> {
+    let repo_root = repo_root.as_ref();
+    let empty_id = hash_blob_data(HashAlgorithm::read(conn)?.hasher(), &[]);
+    conn.prepare_cached(INSERT_BLOB)?
+        .execute((&empty_id, &[] as &[u8]))?;
+
+    let mut entries = Vec::new();
+    for path in paths {
+        let name = worktree_name(repo_root, path, precompose)?;
+        verify_path(&name)?;
+        if !Index::read_entries_with_name(conn, &name)?.is_empty() {
+            continue;
+        }
+        // Without the stat data of the file, its content always gets compared with the index
+        entries.push(IndexEntry {
+            ctime: 0,
+            mtime: 0,
+            fsize: 0,
+            flag_intent_to_add: true,
+            ..index_entry_from_file(path, name, empty_id)?
+        });
+    }
+    Index::stage_entries(conn, entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sha1::Digest;
+
+    #[test]
+    fn test_worktree_name() {
+        let repo_root = Path::new("/repo");
+        // "é" decomposed into "e" and a combining acute accent, as macOS may store it
+        let path = repo_root.join("dir/cafe\u{301}.txt");
+        assert_eq!(
+            "dir/caf\u{e9}.txt",
+            worktree_name(repo_root, &path, true).unwrap()
+        );
+        assert_eq!(
+            "dir/cafe\u{301}.txt",
+            worktree_name(repo_root, &path, false).unwrap()
+        );
+        assert!(worktree_name(repo_root, "/elsewhere/file", true).is_err());
+    }
+
+    #[test]
+    fn test_reset_index() {
+        let blob = |byte: u8| FlatEntry {
+            id: Sha1Id::try_from(vec![byte; 20]).unwrap(),
+            mode: "100644".to_string(),
+        };
+        let kept = IndexEntry {
+            mtime: 42,
+            ..blob_index_entry("kept", &blob(1), 0)
+        };
+        let mut index = Index {
+            entries: vec![
+                blob_index_entry("changed", &blob(2), 0),
+                blob_index_entry("conflicted", &blob(3), 2),
+                kept.clone(),
+                blob_index_entry("removed", &blob(4), 0),
+            ],
+        };
+        let target = TreeView::from([
+            ("added".to_string(), blob(5)),
+            ("changed".to_string(), blob(6)),
+            ("conflicted".to_string(), blob(3)),
+            ("kept".to_string(), blob(1)),
+        ]);
+        reset_index(&mut index, &target);
+        assert_eq!(
+            vec![
+                blob_index_entry("added", &blob(5), 0),
+                blob_index_entry("changed", &blob(6), 0),
+                blob_index_entry("conflicted", &blob(3), 0),
+                kept,
+            ],
+            index.entries
+        );
+    }
+
+    #[test]
+    fn test_worktree_view() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("file"), "content\n").unwrap();
+        let id = hash_worktree_file(dir.path(), "file", HashAlgorithm::Sha1)
+            .unwrap()
+            .unwrap();
+        let stale = Sha1Id::try_from(vec![7; 20]).unwrap();
+        let entry =
+            index_entry_from_file(dir.path().join("file"), "file".to_string(), stale).unwrap();
+        let view_id = |entry: &IndexEntry| {
+            let index = Index {
+                entries: vec![entry.clone()],
+            };
+            worktree_view(dir.path(), &index, HashAlgorithm::Sha1).unwrap()["file"].id
+        };
+
+        // The file is only read when its modification time changed since it was staged
+        assert_eq!(stale, view_id(&entry));
+        assert_eq!(
+            id,
+            view_id(&IndexEntry {
+                mtime: 0,
+                ..entry.clone()
+            })
+        );
+        assert_eq!(
+            id,
+            view_id(&IndexEntry {
+                flag_intent_to_add: true,
+                ..entry.clone()
+            })
+        );
+
+        fs::remove_file(dir.path().join("file")).unwrap();
+        let index = Index {
+            entries: vec![entry],
+        };
+        assert!(worktree_view(dir.path(), &index, HashAlgorithm::Sha1)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_filtered_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join(GITQLITE_DIRECTORY_PREFIX)).unwrap();
+        write_worktree_file(dir.path(), ".gitattributes", b"*.bat eol=crlf\n", "100644").unwrap();
+
+        // Files are checked out with the line endings of their attributes, and hash to the
+        // blob they were checked out from
+        let blob = b"echo one\necho two\n";
+        write_worktree_file(dir.path(), "run.bat", blob, "100644").unwrap();
+        assert_eq!(
+            b"echo one\r\necho two\r\n".to_vec(),
+            fs::read(dir.path().join("run.bat")).unwrap()
+        );
+        assert_eq!(
+            Some(hash_blob_data(sha1::Sha1::new(), blob)),
+            hash_worktree_file(dir.path(), "run.bat", HashAlgorithm::Sha1).unwrap()
+        );
+        assert_eq!(
+            blob.to_vec(),
+            read_worktree_data(dir.path(), "run.bat").unwrap()
+        );
+
+        write_worktree_file(dir.path(), "notes.txt", blob, "100644").unwrap();
+        assert_eq!(
+            blob.to_vec(),
+            fs::read(dir.path().join("notes.txt")).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_export_view() {
+        use crate::git::test_utils::{files_view, object_database};
+
+        let conn = object_database(&[]);
+        let view = files_view(
+            &conn,
+            &[("README", "readme\n"), ("src/main.rs", "fn main() {}\n")],
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("out")).unwrap();
+        fs::write(dir.path().join("out/README"), "local\n").unwrap();
+        let skipped = export_view(&conn, dir.path(), "out/", &view, false).unwrap();
+        assert_eq!(vec!["README".to_string()], skipped);
+        let read = |name: &str| fs::read_to_string(dir.path().join(name)).unwrap();
+        assert_eq!("local\n", read("out/README"));
+        assert_eq!("fn main() {}\n", read("out/src/main.rs"));
+
+        assert!(export_view(&conn, dir.path(), "out/", &view, true)
+            .unwrap()
+            .is_empty());
+        assert_eq!("readme\n", read("out/README"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_beyond_symlink() {
+        let dir = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        fs::write(outside.path().join("file"), "outside\n").unwrap();
+        std::os::unix::fs::symlink(outside.path(), dir.path().join("link")).unwrap();
+
+        // Neither written nor removed through the link
+        assert!(write_worktree_file(dir.path(), "link/file", b"inside\n", "100644").is_err());
+        remove_worktree_file(dir.path(), "link/file").unwrap();
+        assert_eq!(
+            "outside\n",
+            fs::read_to_string(outside.path().join("file")).unwrap()
+        );
+        assert!(remove_worktree_file(dir.path(), "../file").is_err());
+
+        write_worktree_file(dir.path(), "dir/file", b"inside\n", "100644").unwrap();
+        remove_worktree_file(dir.path(), "dir/file").unwrap();
+        assert!(!dir.path().join("dir").exists());
+        assert!(dir.path().exists());
+    }
+}
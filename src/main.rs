@@ -1,31 +1,146 @@
-use clap::Parser;
-use gitqlite::git;
+use std::time::Instant;
+
+use anyhow::anyhow;
+use clap::{CommandFactory, FromArgMatches};
 use gitqlite::cli;
+use gitqlite::git;
 
 use git::cmds::add::do_add;
+use git::cmds::api::do_api;
+use git::cmds::archive::do_archive;
+use git::cmds::branch::do_branch;
+use git::cmds::bundle::do_bundle;
 use git::cmds::cat_file::do_cat_file;
+use git::cmds::check_attr::do_check_attr;
 use git::cmds::check_ignore::do_check_ignore;
+use git::cmds::check_ref_format::do_check_ref_format;
+use git::cmds::checkout::do_checkout;
+use git::cmds::checkout_index::do_checkout_index;
+use git::cmds::cherry::do_cherry;
+use git::cmds::cherry_pick::do_cherry_pick;
 use git::cmds::commit::do_commit;
 use git::cmds::config::do_config;
+use git::cmds::contributors::do_contributors;
+use git::cmds::daemon::do_daemon;
+use git::cmds::diff::do_diff;
+use git::cmds::difftool::do_difftool;
+use git::cmds::du::do_du;
+use git::cmds::dump::do_dump;
+use git::cmds::fetch::do_fetch;
+use git::cmds::gc::do_gc;
+use git::cmds::graph::do_graph;
 use git::cmds::hash_object::do_hash_object;
+use git::cmds::hook::do_hook;
 use git::cmds::init::do_init;
+use git::cmds::internal::do_internal;
+use git::cmds::interpret_trailers::do_interpret_trailers;
+use git::cmds::load::do_load;
+use git::cmds::log::do_log;
 use git::cmds::ls_files::do_ls_files;
+use git::cmds::merge::do_merge;
+use git::cmds::mergetool::do_mergetool;
+use git::cmds::patch_id::do_patch_id;
+use git::cmds::pin::do_pin;
+use git::cmds::prune::do_prune;
+use git::cmds::push::do_push;
+use git::cmds::range_diff::do_range_diff;
+use git::cmds::rebase::do_rebase;
+use git::cmds::remote::do_remote;
+use git::cmds::reset::do_reset;
+use git::cmds::revert::do_revert;
 use git::cmds::rm::do_rm;
+use git::cmds::search::do_search;
+use git::cmds::serve::do_serve;
+use git::cmds::show::do_show;
+use git::cmds::snapshot::do_snapshot;
+use git::cmds::stash::do_stash;
+use git::cmds::stats::do_stats;
 use git::cmds::status::do_status;
+use git::cmds::switch::do_switch;
+use git::cmds::tag::do_tag;
+use git::cmds::verify::do_verify;
+use git::cmds::web::do_web;
+use git::dry_run;
+use git::perf;
 
 fn main() -> gitqlite::Result<()> {
-    let cli = cli::GitCli::parse();
+    let matches = cli::GitCli::command().get_matches();
+    let cli = cli::GitCli::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+    let command = matches.subcommand_name().unwrap_or_default().to_string();
+    if cli.profile || perf::requested_by_env() {
+        perf::enable();
+    }
+    if cli.dry_run {
+        if !cli.command.supports_dry_run() {
+            return Err(anyhow!("fatal: {} does not support --dry-run", command));
+        }
+        dry_run::enable();
+    }
+    let start = Instant::now();
 
-    match cli.command {
+    let result = match cli.command {
         cli::GitCommand::Init(arg) => do_init(arg),
         cli::GitCommand::CatFile(arg) => do_cat_file(arg),
         cli::GitCommand::HashObject(arg) => do_hash_object(arg),
         cli::GitCommand::LsFiles(arg) => do_ls_files(arg),
         cli::GitCommand::CheckIgnore(arg) => do_check_ignore(arg),
+        cli::GitCommand::CheckAttr(arg) => do_check_attr(arg),
+        cli::GitCommand::CheckRefFormat(arg) => do_check_ref_format(arg),
         cli::GitCommand::Config(arg) => do_config(arg),
         cli::GitCommand::Status(arg) => do_status(arg),
         cli::GitCommand::Rm(arg) => do_rm(arg),
         cli::GitCommand::Add(arg) => do_add(arg),
         cli::GitCommand::Commit(arg) => do_commit(arg),
+        cli::GitCommand::Diff(arg) => do_diff(arg),
+        cli::GitCommand::Difftool(arg) => do_difftool(arg),
+        cli::GitCommand::Mergetool(arg) => do_mergetool(arg),
+        cli::GitCommand::Merge(arg) => do_merge(arg),
+        cli::GitCommand::Rebase(arg) => do_rebase(arg),
+        cli::GitCommand::CherryPick(arg) => do_cherry_pick(arg),
+        cli::GitCommand::Stash(arg) => do_stash(arg),
+        cli::GitCommand::Archive(arg) => do_archive(arg),
+        cli::GitCommand::Bundle(arg) => do_bundle(arg),
+        cli::GitCommand::Dump(arg) => do_dump(arg),
+        cli::GitCommand::Load(arg) => do_load(arg),
+        cli::GitCommand::Graph(arg) => do_graph(arg),
+        cli::GitCommand::Web(arg) => do_web(arg),
+        cli::GitCommand::Api(arg) => do_api(arg),
+        cli::GitCommand::Daemon(arg) => do_daemon(arg),
+        cli::GitCommand::Fetch(arg) => do_fetch(arg),
+        cli::GitCommand::Push(arg) => do_push(arg),
+        cli::GitCommand::Remote(arg) => do_remote(arg),
+        cli::GitCommand::Serve(arg) => do_serve(arg),
+        cli::GitCommand::InterpretTrailers(arg) => do_interpret_trailers(arg),
+        cli::GitCommand::Verify(arg) => do_verify(arg),
+        cli::GitCommand::Du(arg) => do_du(arg),
+        cli::GitCommand::Stats(arg) => do_stats(arg),
+        cli::GitCommand::Search(arg) => do_search(arg),
+        cli::GitCommand::Contributors(arg) => do_contributors(arg),
+        cli::GitCommand::Prune(arg) => do_prune(arg),
+        cli::GitCommand::Gc(arg) => do_gc(arg),
+        cli::GitCommand::Pin(arg) => do_pin(arg),
+        cli::GitCommand::Snapshot(arg) => do_snapshot(arg),
+        cli::GitCommand::Hook(arg) => do_hook(arg),
+        cli::GitCommand::RangeDiff(arg) => do_range_diff(arg),
+        cli::GitCommand::Cherry(arg) => do_cherry(arg),
+        cli::GitCommand::PatchId(arg) => do_patch_id(arg),
+        cli::GitCommand::Log(arg) => do_log(arg),
+        cli::GitCommand::Show(arg) => do_show(arg),
+        cli::GitCommand::Branch(arg) => do_branch(arg),
+        cli::GitCommand::Switch(arg) => do_switch(arg),
+        cli::GitCommand::Checkout(arg) => do_checkout(arg),
+        cli::GitCommand::Tag(arg) => do_tag(arg),
+        cli::GitCommand::Reset(arg) => do_reset(arg),
+        cli::GitCommand::Revert(arg) => do_revert(arg),
+        cli::GitCommand::CheckoutIndex(arg) => do_checkout_index(arg),
+        cli::GitCommand::Internal(arg) => do_internal(arg),
+    };
+
+    if perf::is_enabled() {
+        eprint!("{}", perf::report(&command, start.elapsed()));
+    }
+    if cli.dry_run && result.is_ok() {
+        dry_run::report()?;
     }
+    result
 }
@@ -51,6 +51,28 @@ pub enum ConfigSource {
     All,
 }
 
+impl ConfigSource {
+    /// Name of the scope, as shown by `gitqlite config --show-scope`
+    pub fn scope(&self) -> &'static str {
+        match self {
+            ConfigSource::System => "system",
+            ConfigSource::Global => "global",
+            ConfigSource::Local => "local",
+            ConfigSource::All => "all",
+        }
+    }
+}
+
+/// [`ConfigEntry`] is one variable set in a config file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigEntry<'a> {
+    pub source: ConfigSource,
+    pub origin: &'a Path,
+    /// Full name of the variable, e.g. `diff.jupyter.textconv`
+    pub key: String,
+    pub value: &'a str,
+}
+
 type ConfigInner = HashMap<String, HashMap<String, String>>;
 
 /// [`GitConfig`] stores the in-memory snapshot of the git configuration, constructed from:
@@ -105,50 +127,156 @@ impl GitConfig {
     }
 
     pub fn get(&self, key: &str, source: ConfigSource) -> crate::Result<Option<&str>> {
-        let (section, key) = key
-            .split_once(".")
-            .ok_or_else(|| anyhow!("Config key must be of form SECTION.KEY"))?;
+        let (section, key) = split_key(key)?;
 
         Ok(match source {
-            ConfigSource::System => self.get_system_inner(section, key),
-            ConfigSource::Global => self.get_global_inner(section, key),
-            ConfigSource::Local => self.get_local_inner(section, key),
-            ConfigSource::All => self.get_all_inner(section, key).map(|(val, _)| val),
+            ConfigSource::System => self.get_system_inner(&section, key),
+            ConfigSource::Global => self.get_global_inner(&section, key),
+            ConfigSource::Local => self.get_local_inner(&section, key),
+            ConfigSource::All => self.get_all_inner(&section, key).map(|(val, _)| val),
         })
     }
 
+    /// Read a boolean value, accepting the same spellings as git (true/yes/on/1 and
+    /// false/no/off/0). A key without value is true.
+    pub fn get_bool(&self, key: &str, source: ConfigSource) -> crate::Result<Option<bool>> {
+        let Some(value) = self.get(key, source)? else {
+            return Ok(None);
+        };
+
+        match value.to_ascii_lowercase().as_str() {
+            "" | "true" | "yes" | "on" | "1" => Ok(Some(true)),
+            "false" | "no" | "off" | "0" => Ok(Some(false)),
+            _ => Err(anyhow!(
+                "fatal: bad boolean config value '{}' for '{}'",
+                value,
+                key
+            )),
+        }
+    }
+
     pub fn get_with_source(
         &self,
         key: &str,
         source: ConfigSource,
     ) -> crate::Result<Option<(&str, &Path)>> {
-        let (section, key) = key
-            .split_once(".")
-            .ok_or_else(|| anyhow!("Config key must be of form SECTION.KEY"))?;
+        let (section, key) = split_key(key)?;
         Ok(match source {
             ConfigSource::System => self
-                .get_system_inner(section, key)
+                .get_system_inner(&section, key)
                 .map(|val| (val, self.system_path.as_path())),
             ConfigSource::Global => self
-                .get_global_inner(section, key)
+                .get_global_inner(&section, key)
                 .map(|val| (val, self.global_path.as_path())),
             ConfigSource::Local => self
-                .get_local_inner(section, key)
+                .get_local_inner(&section, key)
                 .map(|val| (val, self.local_path.as_path())),
-            ConfigSource::All => self.get_all_inner(section, key),
+            ConfigSource::All => self.get_all_inner(&section, key),
         })
     }
 
+    /// The config file at the given path
+    pub fn source_of(&self, origin: &Path) -> ConfigSource {
+        if origin == self.system_path {
+            ConfigSource::System
+        } else if origin == self.global_path {
+            ConfigSource::Global
+        } else {
+            ConfigSource::Local
+        }
+    }
+
+    /// List the variables set in the given config file, or in all of them from the lowest
+    /// precedence to the highest
+    pub fn list(&self, source: ConfigSource) -> Vec<ConfigEntry<'_>> {
+        let files = [
+            (ConfigSource::System, &self.system_path, &self.system_config),
+            (ConfigSource::Global, &self.global_path, &self.global_config),
+            (ConfigSource::Local, &self.local_path, &self.local_config),
+        ];
+
+        let mut entries = Vec::new();
+        for (file_source, path, config) in files {
+            if source != ConfigSource::All && source != file_source {
+                continue;
+            }
+            let mut file_entries: Vec<ConfigEntry> = config
+                .iter()
+                .filter(|(section, _)| !section.is_empty())
+                .flat_map(|(section, properties)| {
+                    properties.iter().map(|(key, value)| ConfigEntry {
+                        source: file_source.clone(),
+                        origin: path.as_path(),
+                        key: format!("{}.{}", join_section(section), key),
+                        value,
+                    })
+                })
+                .collect();
+            file_entries.sort_by(|a, b| a.key.cmp(&b.key));
+            entries.extend(file_entries);
+        }
+        entries
+    }
+
+    /// Set a variable in the given config file, the local one for All. The rest of the file is
+    /// left as it is, comments included.
     pub fn set(&mut self, key: &str, value: String, source: ConfigSource) -> crate::Result<()> {
-        let (section, key) = key
-            .split_once(".")
-            .ok_or_else(|| anyhow!("Config key must be of form SECTION.KEY"))?;
+        let (section, key) = split_key(key)?;
+        let (path, config) = self.file_mut(source);
 
-        match source {
-            ConfigSource::System => self.set_system_inner(section, key, value),
-            ConfigSource::Global => self.set_global_inner(section, key, value),
-            _ => self.set_local_inner(section, key, value),
+        let mut file = ConfigFile::read(path)?;
+        file.set(&section, key, &value);
+        file.write(path)?;
+
+        config
+            .entry(section)
+            .or_default()
+            .insert(key.to_string(), value);
+        Ok(())
+    }
+
+    /// Remove a variable from the given config file, the local one for All
+    pub fn unset(&mut self, key: &str, source: ConfigSource) -> crate::Result<()> {
+        let (section, name) = split_key(key)?;
+        let (path, config) = self.file_mut(source);
+
+        let mut file = ConfigFile::read(path)?;
+        if !file.unset(&section, name) {
+            return Err(anyhow!("error: key '{}' is not set", key));
         }
+        file.write(path)?;
+
+        if let Some(section_map) = config.get_mut(&section) {
+            section_map.remove(name);
+            if section_map.is_empty() {
+                config.remove(&section);
+            }
+        }
+        Ok(())
+    }
+
+    /// Rename a section of the given config file, the local one for All. Sections are named
+    /// like the prefix of their keys, e.g. `remote.origin`.
+    pub fn rename_section(
+        &mut self,
+        old: &str,
+        new: &str,
+        source: ConfigSource,
+    ) -> crate::Result<()> {
+        let new_section = section_name(new)?;
+        let old_section = section_name(old)?;
+        let (path, config) = self.file_mut(source);
+
+        let mut file = ConfigFile::read(path)?;
+        if !file.rename_section(&old_section, &new_section) {
+            return Err(anyhow!("fatal: no such section: {}", old));
+        }
+        file.write(path)?;
+
+        if let Some(properties) = config.remove(&old_section) {
+            config.entry(new_section).or_default().extend(properties);
+        }
+        Ok(())
     }
 
     fn load_config(config_path: impl AsRef<Path>) -> crate::Result<ConfigInner> {
@@ -182,9 +310,7 @@ impl GitConfig {
                 continue;
             }
 
-            let section_map = config
-                .entry(section_name.clone())
-                .or_insert_with(HashMap::new);
+            let section_map = config.entry(section_name.clone()).or_default();
 
             for (key, value) in properties.iter() {
                 section_map.insert(key.to_string(), value.to_string());
@@ -213,7 +339,7 @@ impl GitConfig {
     fn get_system_inner(&self, section: &str, key: &str) -> Option<&str> {
         if let Some(section_map) = self.system_config.get(section) {
             if let Some(val) = section_map.get(key) {
-                return Some(&*val);
+                return Some(val);
             }
         }
         None
@@ -222,7 +348,7 @@ impl GitConfig {
     fn get_global_inner(&self, section: &str, key: &str) -> Option<&str> {
         if let Some(section_map) = self.global_config.get(section) {
             if let Some(val) = section_map.get(key) {
-                return Some(&*val);
+                return Some(val);
             }
         }
         None
@@ -231,47 +357,243 @@ impl GitConfig {
     fn get_local_inner(&self, section: &str, key: &str) -> Option<&str> {
         if let Some(section_map) = self.local_config.get(section) {
             if let Some(val) = section_map.get(key) {
-                return Some(&*val);
+                return Some(val);
             }
         }
         None
     }
 
-    fn set_system_inner(&mut self, section: &str, key: &str, value: String) -> crate::Result<()> {
-        let section_map = self
-            .system_config
-            .entry(section.to_string())
-            .or_insert_with(HashMap::new);
-        section_map.insert(key.to_string(), value.clone());
-        let mut ini = Ini::load_from_file(&self.system_path)?;
-        ini.set_to(Some(section), key.to_string(), value);
-        ini.write_to_file(&self.system_path)?;
-        Ok(())
+    /// The path and in-memory snapshot of the file written for `source`, the local one for All
+    fn file_mut(&mut self, source: ConfigSource) -> (&Path, &mut ConfigInner) {
+        match source {
+            ConfigSource::System => (&self.system_path, &mut self.system_config),
+            ConfigSource::Global => (&self.global_path, &mut self.global_config),
+            _ => (&self.local_path, &mut self.local_config),
+        }
     }
+}
 
-    fn set_global_inner(&mut self, section: &str, key: &str, value: String) -> crate::Result<()> {
-        let section_map = self
-            .global_config
-            .entry(section.to_string())
-            .or_insert_with(HashMap::new);
-        section_map.insert(key.to_string(), value.clone());
-        let mut ini = Ini::load_from_file(&self.global_path)?;
-        ini.set_to(Some(section), key.to_string(), value);
-        ini.write_to_file(&self.global_path)?;
-        Ok(())
+/// [`ConfigFile`] holds the lines of a config file, so that setting a variable rewrites only
+/// the line defining it: comments, blank lines and indentation are preserved, unlike a round
+/// trip through [`Ini`].
+#[derive(Debug, Clone, Default)]
+struct ConfigFile {
+    lines: Vec<String>,
+}
+
+impl ConfigFile {
+    /// Read the config file at `path`, which is empty if it does not exist
+    fn read(path: &Path) -> crate::Result<ConfigFile> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(ConfigFile {
+            lines: text.lines().map(str::to_string).collect(),
+        })
     }
 
-    fn set_local_inner(&mut self, section: &str, key: &str, value: String) -> crate::Result<()> {
-        let section_map = self
-            .local_config
-            .entry(section.to_string())
-            .or_insert_with(HashMap::new);
-        section_map.insert(key.to_string(), value.clone());
-        let mut ini = Ini::load_from_file(&self.local_path).unwrap_or_default();
-        ini.set_to(Some(section), key.to_string(), value);
-        ini.write_to_file(&self.local_path)?;
+    fn write(&self, path: &Path) -> crate::Result<()> {
+        let mut text = self.lines.join("\n");
+        if !text.is_empty() {
+            text.push('\n');
+        }
+        std::fs::write(path, text)?;
         Ok(())
     }
+
+    /// The lines of each section in the file, as the range from its header to the next one
+    fn sections(&self) -> Vec<(&str, std::ops::Range<usize>)> {
+        let headers: Vec<(usize, &str)> = self
+            .lines
+            .iter()
+            .enumerate()
+            .filter_map(|(i, line)| section_header(line).map(|name| (i, name)))
+            .collect();
+        headers
+            .iter()
+            .enumerate()
+            .map(|(n, (start, name))| {
+                let end = headers.get(n + 1).map_or(self.lines.len(), |(end, _)| *end);
+                (*name, *start..end)
+            })
+            .collect()
+    }
+
+    /// Lines defining `key` in the sections named `section`
+    fn key_lines(&self, section: &str, key: &str) -> Vec<usize> {
+        self.sections()
+            .into_iter()
+            .filter(|(name, _)| *name == section)
+            .flat_map(|(_, range)| range)
+            .filter(|i| split_assignment(&self.lines[*i]).is_some_and(|(_, k)| k == key))
+            .collect()
+    }
+
+    fn set(&mut self, section: &str, key: &str, value: &str) {
+        let value = escape_value(value);
+
+        // The last definition wins, so that is the one to rewrite
+        if let Some(&i) = self.key_lines(section, key).last() {
+            let (prefix, _) = split_assignment(&self.lines[i]).expect("line defines the key");
+            self.lines[i] = format!("{}{}", prefix, value);
+            return;
+        }
+
+        let Some((_, range)) = self
+            .sections()
+            .into_iter()
+            .rev()
+            .find(|(name, _)| *name == section)
+        else {
+            self.lines.push(format!("[{}]", section));
+            self.lines.push(format!("{}={}", key, value));
+            return;
+        };
+
+        // Append after the last variable of the section, formatted like it
+        let last = range
+            .clone()
+            .rev()
+            .find(|i| split_assignment(&self.lines[*i]).is_some());
+        let line = match last {
+            Some(i) => {
+                let line = &self.lines[i];
+                let (prefix, name) = split_assignment(line).expect("line defines a variable");
+                let indent = &line[..line.len() - line.trim_start().len()];
+                let separator = &prefix[indent.len() + name.len()..];
+                format!("{}{}{}{}", indent, key, separator, value)
+            }
+            None => format!("{}={}", key, value),
+        };
+        self.lines.insert(last.unwrap_or(range.start) + 1, line);
+    }
+
+    /// Remove the definitions of `key`, and the sections left without content. Returns whether
+    /// the key was set.
+    fn unset(&mut self, section: &str, key: &str) -> bool {
+        let lines = self.key_lines(section, key);
+        for i in lines.iter().rev() {
+            self.lines.remove(*i);
+        }
+
+        let empty: Vec<std::ops::Range<usize>> = self
+            .sections()
+            .into_iter()
+            .filter(|(name, range)| {
+                *name == section
+                    && self.lines[range.start + 1..range.end]
+                        .iter()
+                        .all(|line| line.trim().is_empty())
+            })
+            .map(|(_, range)| range)
+            .collect();
+        for range in empty.into_iter().rev() {
+            self.lines.drain(range);
+        }
+
+        !lines.is_empty()
+    }
+
+    /// Rename the headers of the sections named `old`. Returns whether there was any.
+    fn rename_section(&mut self, old: &str, new: &str) -> bool {
+        let headers: Vec<usize> = self
+            .sections()
+            .into_iter()
+            .filter(|(name, _)| *name == old)
+            .map(|(_, range)| range.start)
+            .collect();
+        for i in &headers {
+            let line = &self.lines[*i];
+            let start = line.find('[').expect("line is a section header");
+            let end = line.find(']').expect("line is a section header");
+            self.lines[*i] = format!("{}[{}]{}", &line[..start], new, &line[end + 1..]);
+        }
+        !headers.is_empty()
+    }
+}
+
+/// Name of the section a line opens, if it is a section header
+fn section_header(line: &str) -> Option<&str> {
+    let line = line.trim_start().strip_prefix('[')?;
+    let (name, _) = line.split_once(']')?;
+    Some(name.trim())
+}
+
+/// Split a line defining a variable into everything up to its value and the variable name
+fn split_assignment(line: &str) -> Option<(&str, &str)> {
+    let trimmed = line.trim_start();
+    if trimmed.starts_with(['#', ';', '[']) {
+        return None;
+    }
+    let separator = line.find(['=', ':'])?;
+    let name = line[..separator].trim();
+    if name.is_empty() {
+        return None;
+    }
+    let value = &line[separator + 1..];
+    let prefix_len = separator + 1 + value.len() - value.trim_start().len();
+    Some((&line[..prefix_len], name))
+}
+
+/// Escape a value the way the config files are parsed: backslashes and control characters
+fn escape_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\0' => escaped.push_str("\\0"),
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            '\r' => escaped.push_str("\\r"),
+            c if c.is_ascii_control() => escaped.push_str(&format!("\\x{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Split a key of form SECTION.KEY or SECTION.SUBSECTION.KEY into the name of its section in
+/// the config file (`section "subsection"` for subsections) and the variable name
+fn split_key(key: &str) -> crate::Result<(String, &str)> {
+    let (section, name) = key
+        .rsplit_once(".")
+        .ok_or_else(|| anyhow!("Config key must be of form SECTION.KEY"))?;
+
+    Ok(match section.split_once(".") {
+        Some((section, subsection)) => (format!("{} \"{}\"", section, subsection), name),
+        None => (section.to_string(), name),
+    })
+}
+
+/// Name of the section in the config file whose keys are prefixed with `prefix`, i.e.
+/// `section "subsection"` for `section.subsection`
+fn section_name(prefix: &str) -> crate::Result<String> {
+    let (section, subsection) = match prefix.split_once('.') {
+        Some((section, subsection)) => (section, Some(subsection)),
+        None => (prefix, None),
+    };
+    if section.is_empty()
+        || !section
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-')
+    {
+        return Err(anyhow!("fatal: invalid section name: {}", prefix));
+    }
+    Ok(match subsection {
+        Some(subsection) => format!("{} \"{}\"", section, subsection),
+        None => section.to_string(),
+    })
+}
+
+/// Turn the name of a section in the config file back into the prefix of its keys, i.e.
+/// `section "subsection"` into `section.subsection`
+fn join_section(section: &str) -> String {
+    match section.split_once(' ') {
+        Some((section, subsection)) => format!("{}.{}", section, subsection.trim_matches('"')),
+        None => section.to_string(),
+    }
 }
 
 #[cfg(test)]
@@ -321,4 +643,207 @@ mod tests {
                 .unwrap()
         );
     }
+
+    #[test]
+    fn test_get_bool() {
+        let dir = tempdir().unwrap();
+        let mut config = GitConfig::load(dir.path()).unwrap();
+
+        for (value, expected) in [("true", true), ("Yes", true), ("off", false), ("0", false)] {
+            config
+                .set(
+                    "section.flag",
+                    value.to_string(),
+                    config::ConfigSource::Local,
+                )
+                .unwrap();
+            assert_eq!(
+                Some(expected),
+                config
+                    .get_bool("section.flag", config::ConfigSource::Local)
+                    .unwrap()
+            );
+        }
+
+        config
+            .set(
+                "section.flag",
+                "maybe".to_string(),
+                config::ConfigSource::Local,
+            )
+            .unwrap();
+        assert!(config
+            .get_bool("section.flag", config::ConfigSource::Local)
+            .is_err());
+        assert_eq!(
+            None,
+            config
+                .get_bool("section.missing", config::ConfigSource::Local)
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_subsection() {
+        let dir = tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("config"),
+            "[diff]\n\trenames = copies\n[diff \"jupyter\"]\n\ttextconv = nbconvert\n",
+        )
+        .unwrap();
+        let mut config = GitConfig::load(dir.path()).unwrap();
+
+        assert_eq!(
+            Some("nbconvert"),
+            config
+                .get("diff.jupyter.textconv", config::ConfigSource::Local)
+                .unwrap()
+        );
+        assert_eq!(
+            Some("copies"),
+            config
+                .get("diff.renames", config::ConfigSource::Local)
+                .unwrap()
+        );
+
+        config
+            .set(
+                "diff.image.command",
+                "imgdiff".to_string(),
+                config::ConfigSource::Local,
+            )
+            .unwrap();
+        let text = std::fs::read_to_string(dir.path().join("config")).unwrap();
+        assert!(text.contains("[diff \"image\"]\ncommand=imgdiff"));
+        let config = GitConfig::load(dir.path()).unwrap();
+        assert_eq!(
+            Some("imgdiff"),
+            config
+                .get("diff.image.command", config::ConfigSource::Local)
+                .unwrap()
+        );
+
+        let keys: Vec<String> = config
+            .list(config::ConfigSource::Local)
+            .into_iter()
+            .map(|entry| format!("{}={}", entry.key, entry.value))
+            .collect();
+        assert_eq!(
+            vec![
+                "diff.image.command=imgdiff",
+                "diff.jupyter.textconv=nbconvert",
+                "diff.renames=copies"
+            ],
+            keys
+        );
+    }
+
+    #[test]
+    fn test_preserve_comments() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("config");
+        std::fs::write(
+            &path,
+            "# Written by hand\n[user]\n\tname = Someone\n\n; Remotes\n[remote \"origin\"]\n\turl = /tmp/origin\n",
+        )
+        .unwrap();
+        let mut config = GitConfig::load(dir.path()).unwrap();
+
+        config
+            .set(
+                "user.email",
+                "someone@example.com".to_string(),
+                config::ConfigSource::Local,
+            )
+            .unwrap();
+        config
+            .set(
+                "remote.origin.url",
+                "/tmp/other".to_string(),
+                config::ConfigSource::Local,
+            )
+            .unwrap();
+        config
+            .set(
+                "core.path",
+                "C:\\work".to_string(),
+                config::ConfigSource::Local,
+            )
+            .unwrap();
+        assert_eq!(
+            "# Written by hand\n[user]\n\tname = Someone\n\temail = someone@example.com\n\n; Remotes\n[remote \"origin\"]\n\turl = /tmp/other\n[core]\npath=C:\\\\work\n",
+            std::fs::read_to_string(&path).unwrap()
+        );
+        let reloaded = GitConfig::load(dir.path()).unwrap();
+        assert_eq!(
+            Some("C:\\work"),
+            reloaded
+                .get("core.path", config::ConfigSource::Local)
+                .unwrap()
+        );
+
+        // Unsetting the last variable of a section removes the section
+        config
+            .unset("core.path", config::ConfigSource::Local)
+            .unwrap();
+        config
+            .unset("user.email", config::ConfigSource::Local)
+            .unwrap();
+        assert!(config
+            .unset("user.email", config::ConfigSource::Local)
+            .is_err());
+        assert_eq!(
+            "# Written by hand\n[user]\n\tname = Someone\n\n; Remotes\n[remote \"origin\"]\n\turl = /tmp/other\n",
+            std::fs::read_to_string(&path).unwrap()
+        );
+        assert_eq!(
+            None,
+            config
+                .get("core.path", config::ConfigSource::Local)
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_rename_section() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("config");
+        std::fs::write(
+            &path,
+            "# The main remote\n[remote \"origin\"]\n\turl = /tmp/origin\n[branch \"main\"]\n\tremote = origin\n",
+        )
+        .unwrap();
+        let mut config = GitConfig::load(dir.path()).unwrap();
+
+        config
+            .rename_section(
+                "remote.origin",
+                "remote.upstream",
+                config::ConfigSource::Local,
+            )
+            .unwrap();
+        assert_eq!(
+            "# The main remote\n[remote \"upstream\"]\n\turl = /tmp/origin\n[branch \"main\"]\n\tremote = origin\n",
+            std::fs::read_to_string(&path).unwrap()
+        );
+        assert_eq!(
+            Some("/tmp/origin"),
+            config
+                .get("remote.upstream.url", config::ConfigSource::Local)
+                .unwrap()
+        );
+        assert_eq!(
+            None,
+            config
+                .get("remote.origin.url", config::ConfigSource::Local)
+                .unwrap()
+        );
+
+        assert!(config
+            .rename_section("remote.origin", "remote.other", config::ConfigSource::Local)
+            .is_err());
+        assert!(config
+            .rename_section("branch.main", "bad name.main", config::ConfigSource::Local)
+            .is_err());
+    }
 }
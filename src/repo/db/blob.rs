@@ -1,8 +1,11 @@
 use rusqlite::{params, OptionalExtension};
 use serde::{Deserialize, Serialize};
-use sha1::Digest;
 
-use super::{hash::Hashable, object::Object, IdType, NoId, Sha1Id};
+use super::{
+    hash::{HashAlgorithm, Hashable, Hasher},
+    object::Object,
+    IdType, NoId, Sha1Id,
+};
 
 /// [`Blob`] represents a snapshot of a file in the gitqlite repository
 #[derive(Debug, Serialize, Deserialize)]
@@ -12,9 +15,9 @@ pub struct Blob<ID: IdType<ID>> {
 }
 
 impl Hashable for Blob<NoId> {
-    fn hash(&self, mut sha: sha1::Sha1) -> super::Sha1Id {
-        sha.update(&self.data);
-        Sha1Id(sha.finalize().into())
+    fn hash(&self, mut hasher: impl Hasher) -> super::Sha1Id {
+        hasher.update(&self.data);
+        Sha1Id(hasher.finish())
     }
 }
 
@@ -26,8 +29,8 @@ impl Blob<NoId> {
         }
     }
 
-    pub fn with_id(self) -> Blob<Sha1Id> {
-        let id = self.hash(sha1::Sha1::new());
+    pub fn with_id(self, algorithm: HashAlgorithm) -> Blob<Sha1Id> {
+        let id = self.hash(algorithm.hasher());
         Blob {
             blob_id: id,
             data: self.data,
@@ -42,7 +45,7 @@ impl Object for Blob<Sha1Id> {
         super::object::ObjectType::Blob
     }
 
-    fn create_table(txn: &rusqlite::Transaction) -> crate::Result<()> {
+    fn create_table(txn: &rusqlite::Connection) -> crate::Result<()> {
         txn.execute(
             "CREATE TABLE Blobs (blob_id TEXT PRIMARY KEY, data BLOB NOT NULL);",
             (),
@@ -50,7 +53,7 @@ impl Object for Blob<Sha1Id> {
         Ok(())
     }
 
-    fn read_by_id(txn: &rusqlite::Transaction, id: Self::Id) -> crate::Result<Option<Self>> {
+    fn read_by_id(txn: &rusqlite::Connection, id: Self::Id) -> crate::Result<Option<Self>> {
         txn.query_row_and_then(
             "SELECT blob_id, data FROM Blobs WHERE blob_id = ?1;",
             [id],
@@ -64,7 +67,7 @@ impl Object for Blob<Sha1Id> {
         .map_err(anyhow::Error::from)
     }
 
-    fn persist(&self, txn: &rusqlite::Transaction) -> crate::Result<()> {
+    fn persist(&self, txn: &rusqlite::Connection) -> crate::Result<()> {
         txn.execute(
             "INSERT OR IGNORE INTO Blobs (blob_id, data) VALUES (?1, ?2);",
             params![&self.blob_id, &self.data],
@@ -86,7 +89,7 @@ mod tests {
         Blob::<Sha1Id>::create_table(&txn).unwrap();
 
         let data = vec![1, 2, 3, 4, 5];
-        let blob = Blob::new(data.clone()).with_id();
+        let blob = Blob::new(data.clone()).with_id(HashAlgorithm::Sha1);
 
         blob.persist(&txn).unwrap();
 
@@ -108,7 +111,13 @@ mod tests {
         let blob2 = Blob::new(data2);
         let blob3 = Blob::new(data3);
 
-        assert_eq!(blob1.hash(sha1::Sha1::new()), blob2.hash(sha1::Sha1::new()));
-        assert_ne!(blob1.hash(sha1::Sha1::new()), blob3.hash(sha1::Sha1::new()));
+        assert_eq!(
+            blob1.hash(HashAlgorithm::Sha1.hasher()),
+            blob2.hash(HashAlgorithm::Sha1.hasher())
+        );
+        assert_ne!(
+            blob1.hash(HashAlgorithm::Sha1.hasher()),
+            blob3.hash(HashAlgorithm::Sha1.hasher())
+        );
     }
 }
@@ -0,0 +1,169 @@
+use std::io::{self, Read, Write};
+
+use anyhow::anyhow;
+use rusqlite::{Connection, DatabaseName, OptionalExtension};
+
+use super::{
+    hash::{HashAlgorithm, Hasher, ObjectHasher},
+    Sha1Id,
+};
+
+/// Holds the content of blobs being written by [`BlobWriter`] until their id is known. It is a
+/// temporary table, so it is private to the connection and never reaches the repository file.
+const CREATE_BLOB_UPLOAD_TABLE: &str =
+    "CREATE TEMP TABLE IF NOT EXISTS BlobUpload (data BLOB NOT NULL);";
+
+/// [`BlobReader`] reads the content of a blob straight from the database, a chunk at a time,
+/// so that a large file never has to be held in memory at once
+pub struct BlobReader<'conn> {
+    blob: rusqlite::blob::Blob<'conn>,
+}
+
+impl<'conn> BlobReader<'conn> {
+    pub fn open(conn: &'conn Connection, id: Sha1Id) -> crate::Result<BlobReader<'conn>> {
+        let rowid: i64 = conn
+            .query_row(
+                "SELECT rowid FROM Blobs WHERE blob_id = ?1 LIMIT 1",
+                [id],
+                |row| row.get(0),
+            )
+            .optional()?
+            .ok_or_else(|| anyhow!("fatal: blob {} not found", id))?;
+        let blob = conn.blob_open(DatabaseName::Main, "Blobs", "data", rowid, true)?;
+        Ok(BlobReader { blob })
+    }
+
+    /// Size of the blob in bytes
+    pub fn len(&self) -> usize {
+        self.blob.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blob.is_empty()
+    }
+}
+
+impl Read for BlobReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.blob.read(buf)
+    }
+}
+
+/// [`BlobWriter`] stores a blob of a size known in advance from content written a chunk at a
+/// time, hashing it on the way. The blob is added to the repository by
+/// [`BlobWriter::finish`]; a writer dropped before that leaves the repository untouched.
+pub struct BlobWriter<'conn> {
+    conn: &'conn Connection,
+    blob: rusqlite::blob::Blob<'conn>,
+    rowid: i64,
+    hasher: ObjectHasher,
+    remaining: usize,
+}
+
+impl<'conn> BlobWriter<'conn> {
+    pub fn new(
+        conn: &'conn Connection,
+        size: usize,
+        algorithm: HashAlgorithm,
+    ) -> crate::Result<BlobWriter<'conn>> {
+        conn.execute(CREATE_BLOB_UPLOAD_TABLE, ())?;
+        conn.execute(
+            "INSERT INTO temp.BlobUpload (data) VALUES (zeroblob(?1));",
+            [size as i64],
+        )?;
+        let rowid = conn.last_insert_rowid();
+        let blob = conn.blob_open(DatabaseName::Temp, "BlobUpload", "data", rowid, false)?;
+        Ok(BlobWriter {
+            conn,
+            blob,
+            rowid,
+            hasher: algorithm.hasher(),
+            remaining: size,
+        })
+    }
+
+    /// Add the blob to the repository, unless it already has it, and return its id. Fails if
+    /// less content was written than the size given to [`BlobWriter::new`].
+    pub fn finish(self) -> crate::Result<Sha1Id> {
+        if self.remaining > 0 {
+            return Err(anyhow!(
+                "fatal: blob is missing {} bytes of content",
+                self.remaining
+            ));
+        }
+        let BlobWriter {
+            conn,
+            blob,
+            rowid,
+            hasher,
+            ..
+        } = self;
+        blob.close()?;
+
+        let id = Sha1Id(hasher.finish());
+        conn.execute(
+            "INSERT INTO Blobs (blob_id, data) SELECT ?1, data FROM temp.BlobUpload WHERE rowid = ?2 AND NOT EXISTS (SELECT 1 FROM Blobs WHERE blob_id = ?1);",
+            rusqlite::params![id, rowid],
+        )?;
+        conn.execute("DELETE FROM temp.BlobUpload WHERE rowid = ?1;", [rowid])?;
+        Ok(id)
+    }
+}
+
+impl Write for BlobWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.blob.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        self.remaining -= written;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blob_stream() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute("CREATE TABLE Blobs (blob_id TEXT, data BLOB NOT NULL);", ())
+            .unwrap();
+
+        let content = b"streamed content\n".repeat(1000);
+        let mut writer = BlobWriter::new(&conn, content.len(), HashAlgorithm::Sha1).unwrap();
+        for chunk in content.chunks(4096) {
+            writer.write_all(chunk).unwrap();
+        }
+        // Writing past the announced size fails
+        assert!(writer.write_all(b"extra").is_err());
+        let id = writer.finish().unwrap();
+
+        let mut hasher = HashAlgorithm::Sha1.hasher();
+        hasher.update(&content);
+        assert_eq!(Sha1Id(hasher.finish()), id);
+
+        let mut reader = BlobReader::open(&conn, id).unwrap();
+        assert_eq!(content.len(), reader.len());
+        let mut read = Vec::new();
+        reader.read_to_end(&mut read).unwrap();
+        assert_eq!(content, read);
+
+        // The same content is stored once
+        let mut again = BlobWriter::new(&conn, content.len(), HashAlgorithm::Sha1).unwrap();
+        again.write_all(&content).unwrap();
+        assert_eq!(id, again.finish().unwrap());
+        let count: i64 = conn
+            .query_row("SELECT count(*) FROM Blobs", (), |row| row.get(0))
+            .unwrap();
+        assert_eq!(1, count);
+
+        let mut short = BlobWriter::new(&conn, 10, HashAlgorithm::Sha1).unwrap();
+        short.write_all(b"short").unwrap();
+        assert!(short.finish().is_err());
+        assert!(BlobReader::open(&conn, Sha1Id([0; 20])).is_err());
+    }
+}
@@ -0,0 +1,87 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    hash::Hash,
+};
+
+/// [`LruCache`] keeps up to `capacity` values, evicting the least recently used one when full
+#[derive(Debug)]
+pub struct LruCache<K, V> {
+    capacity: usize,
+    /// Key -> (value, tick of its last use)
+    entries: HashMap<K, (V, u64)>,
+    /// Tick of last use -> key, the first entry being the least recently used
+    recency: BTreeMap<u64, K>,
+    tick: u64,
+}
+
+impl<K: Hash + Eq + Clone, V> LruCache<K, V> {
+    pub fn new(capacity: usize) -> LruCache<K, V> {
+        assert!(capacity > 0, "LRU cache capacity must be positive");
+        LruCache {
+            capacity,
+            entries: HashMap::with_capacity(capacity),
+            recency: BTreeMap::new(),
+            tick: 0,
+        }
+    }
+
+    /// Look up a value, marking it as the most recently used
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        let tick = self.next_tick();
+        let (value, last_used) = self.entries.get_mut(key)?;
+        self.recency.remove(last_used);
+        self.recency.insert(tick, key.clone());
+        *last_used = tick;
+        Some(value)
+    }
+
+    /// Insert a value, evicting the least recently used one if the cache is full
+    pub fn insert(&mut self, key: K, value: V) {
+        let tick = self.next_tick();
+        if let Some((_, last_used)) = self.entries.remove(&key) {
+            self.recency.remove(&last_used);
+        } else if self.entries.len() == self.capacity {
+            if let Some((_, evicted)) = self.recency.pop_first() {
+                self.entries.remove(&evicted);
+            }
+        }
+        self.recency.insert(tick, key.clone());
+        self.entries.insert(key, (value, tick));
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lru_eviction() {
+        let mut cache = LruCache::new(2);
+        cache.insert(1, "one");
+        cache.insert(2, "two");
+        // Using 1 makes 2 the least recently used entry
+        assert_eq!(Some(&"one"), cache.get(&1));
+        cache.insert(3, "three");
+        assert_eq!(None, cache.get(&2));
+        assert_eq!(Some(&"one"), cache.get(&1));
+        assert_eq!(Some(&"three"), cache.get(&3));
+
+        // Replacing a value does not evict anything
+        cache.insert(3, "trois");
+        assert_eq!(2, cache.len());
+        assert_eq!(Some(&"trois"), cache.get(&3));
+    }
+}
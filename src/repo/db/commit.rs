@@ -1,8 +1,11 @@
-use rusqlite::{params, OptionalExtension, Transaction};
+use rusqlite::{params, Connection, OptionalExtension};
 use serde::{Deserialize, Serialize};
-use sha1::Digest;
 
-use super::{hash::Hashable, object::Object, IdType, NoId, Sha1Id};
+use super::{
+    hash::{HashAlgorithm, Hashable, Hasher},
+    object::Object,
+    IdType, NoId, Sha1Id,
+};
 
 #[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Commit<ID: IdType<ID>> {
@@ -38,9 +41,8 @@ impl Commit<NoId> {
         }
     }
 
-    pub fn with_id(self) -> Commit<Sha1Id> {
-        let sha = sha1::Sha1::new();
-        let id = self.hash(sha);
+    pub fn with_id(self, algorithm: HashAlgorithm) -> Commit<Sha1Id> {
+        let id = self.hash(algorithm.hasher());
         Commit {
             commit_id: id,
             tree_id: self.tree_id,
@@ -55,10 +57,10 @@ impl Commit<NoId> {
 }
 
 impl Hashable for Commit<NoId> {
-    fn hash(&self, mut sha: sha1::Sha1) -> Sha1Id {
+    fn hash(&self, mut hasher: impl Hasher) -> Sha1Id {
         let s = serde_json::to_vec(self).expect("Serialize commit failed");
-        sha.update(s);
-        Sha1Id(sha.finalize().into())
+        hasher.update(s);
+        Sha1Id(hasher.finish())
     }
 }
 
@@ -69,7 +71,7 @@ impl Object for Commit<Sha1Id> {
         super::object::ObjectType::Commit
     }
 
-    fn create_table(txn: &Transaction) -> crate::Result<()> {
+    fn create_table(txn: &Connection) -> crate::Result<()> {
         txn.execute(
             "CREATE TABLE Commits (
                 commit_id BLOB PRIMARY KEY,
@@ -86,7 +88,7 @@ impl Object for Commit<Sha1Id> {
         Ok(())
     }
 
-    fn read_by_id(txn: &Transaction, id: Sha1Id) -> crate::Result<Option<Commit<Sha1Id>>> {
+    fn read_by_id(txn: &Connection, id: Sha1Id) -> crate::Result<Option<Commit<Sha1Id>>> {
         txn.query_row_and_then(
           "SELECT commit_id, tree_id, parent_ids, author_name, author_email, committer_name, committer_email, message FROM Commits WHERE commit_id = ?1;",
           [id],
@@ -124,7 +126,7 @@ impl Object for Commit<Sha1Id> {
           .map_err(anyhow::Error::from)
     }
 
-    fn persist(&self, txn: &Transaction) -> crate::Result<()> {
+    fn persist(&self, txn: &Connection) -> crate::Result<()> {
         let parent_ids: Vec<u8> = self
             .parent_ids
             .iter()
@@ -175,7 +177,7 @@ mod tests {
             "jane@example.com".to_string(),
             "Test commit".to_string(),
         )
-        .with_id();
+        .with_id(HashAlgorithm::Sha1);
 
         commit.persist(&txn).unwrap();
 
@@ -228,12 +230,12 @@ mod tests {
         );
 
         assert_eq!(
-            commit1.hash(sha1::Sha1::new()),
-            commit2.hash(sha1::Sha1::new())
+            commit1.hash(HashAlgorithm::Sha1.hasher()),
+            commit2.hash(HashAlgorithm::Sha1.hasher())
         );
         assert_ne!(
-            commit1.hash(sha1::Sha1::new()),
-            commit3.hash(sha1::Sha1::new())
+            commit1.hash(HashAlgorithm::Sha1.hasher()),
+            commit3.hash(HashAlgorithm::Sha1.hasher())
         );
     }
 }
@@ -1,6 +1,170 @@
+use std::fmt;
+
+use anyhow::anyhow;
+use rusqlite::{Connection, OptionalExtension};
+use sha1::Digest;
+
 use super::Sha1Id;
 
+/// ObjectFormat records the [`HashAlgorithm`] computing the ids of the objects of the
+/// repository, in its single row. Upgrading the schema of a repository created before it
+/// records SHA-1.
+pub const CREATE_OBJECT_FORMAT_TABLE: &str = "CREATE TABLE ObjectFormat (algorithm TEXT NOT NULL);";
+
 /// Generic trait describing any git object that could be hashed and get an ID for.
 pub trait Hashable {
-    fn hash(&self, sha: sha1::Sha1) -> Sha1Id;
+    fn hash(&self, hasher: impl Hasher) -> Sha1Id;
+}
+
+/// [`Hasher`] computes object ids from the content of the objects. Ids are 20 bytes long, so an
+/// algorithm with a longer digest has it truncated.
+pub trait Hasher {
+    fn update(&mut self, data: impl AsRef<[u8]>);
+
+    fn finish(self) -> [u8; 20];
+}
+
+impl Hasher for sha1::Sha1 {
+    fn update(&mut self, data: impl AsRef<[u8]>) {
+        Digest::update(self, data);
+    }
+
+    fn finish(self) -> [u8; 20] {
+        self.finalize().into()
+    }
+}
+
+impl Hasher for blake3::Hasher {
+    fn update(&mut self, data: impl AsRef<[u8]>) {
+        blake3::Hasher::update(self, data.as_ref());
+    }
+
+    fn finish(self) -> [u8; 20] {
+        let mut id = [0; 20];
+        id.copy_from_slice(&self.finalize().as_bytes()[..20]);
+        id
+    }
+}
+
+/// [`HashAlgorithm`] is the algorithm a repository computes object ids with, chosen by
+/// `gitqlite init --object-format`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    #[default]
+    Sha1,
+    /// BLAKE3, its digest truncated to the 20 bytes of an id
+    Blake3,
+}
+
+impl HashAlgorithm {
+    pub const ALL: [HashAlgorithm; 2] = [HashAlgorithm::Sha1, HashAlgorithm::Blake3];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            HashAlgorithm::Sha1 => "sha1",
+            HashAlgorithm::Blake3 => "blake3",
+        }
+    }
+
+    /// A fresh hasher for one object
+    pub fn hasher(self) -> ObjectHasher {
+        match self {
+            HashAlgorithm::Sha1 => ObjectHasher::Sha1(sha1::Sha1::new()),
+            HashAlgorithm::Blake3 => ObjectHasher::Blake3(Box::default()),
+        }
+    }
+
+    /// Record the algorithm of a new repository
+    pub fn persist(self, conn: &Connection) -> crate::Result<()> {
+        conn.execute(CREATE_OBJECT_FORMAT_TABLE, ())?;
+        conn.execute(
+            "INSERT INTO ObjectFormat (algorithm) VALUES (?1);",
+            [self.name()],
+        )?;
+        Ok(())
+    }
+
+    /// The algorithm of the repository, failing if this build does not implement it, so that
+    /// no object is ever written with the wrong id
+    pub fn read(conn: &Connection) -> crate::Result<HashAlgorithm> {
+        let name: Option<String> = conn
+            .query_row("SELECT algorithm FROM ObjectFormat", (), |row| row.get(0))
+            .optional()?;
+        match name {
+            Some(name) => HashAlgorithm::try_from(name.as_str()),
+            None => Ok(HashAlgorithm::Sha1),
+        }
+    }
+}
+
+impl fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl TryFrom<&str> for HashAlgorithm {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        HashAlgorithm::ALL
+            .into_iter()
+            .find(|algorithm| algorithm.name() == value)
+            .ok_or_else(|| anyhow!("fatal: unknown object format '{}'", value))
+    }
+}
+
+/// [`ObjectHasher`] is a [`Hasher`] for the algorithm chosen at runtime
+#[derive(Debug, Clone)]
+pub enum ObjectHasher {
+    Sha1(sha1::Sha1),
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl Hasher for ObjectHasher {
+    fn update(&mut self, data: impl AsRef<[u8]>) {
+        match self {
+            ObjectHasher::Sha1(sha) => Hasher::update(sha, data),
+            ObjectHasher::Blake3(blake) => Hasher::update(&mut **blake, data),
+        }
+    }
+
+    fn finish(self) -> [u8; 20] {
+        match self {
+            ObjectHasher::Sha1(sha) => sha.finish(),
+            ObjectHasher::Blake3(blake) => blake.finish(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_algorithm() {
+        let conn = Connection::open_in_memory().unwrap();
+        HashAlgorithm::Sha1.persist(&conn).unwrap();
+        assert_eq!(HashAlgorithm::Sha1, HashAlgorithm::read(&conn).unwrap());
+
+        conn.execute("UPDATE ObjectFormat SET algorithm = 'blake3'", ())
+            .unwrap();
+        assert_eq!(HashAlgorithm::Blake3, HashAlgorithm::read(&conn).unwrap());
+
+        // A repository made with an algorithm this build lacks is refused
+        conn.execute("UPDATE ObjectFormat SET algorithm = 'sha256'", ())
+            .unwrap();
+        let err = HashAlgorithm::read(&conn).unwrap_err();
+        assert_eq!("fatal: unknown object format 'sha256'", err.to_string());
+
+        let mut hasher = HashAlgorithm::Sha1.hasher();
+        hasher.update(b"hello");
+        let mut sha = sha1::Sha1::new();
+        Digest::update(&mut sha, b"hello");
+        assert_eq!(<[u8; 20]>::from(sha.finalize()), hasher.finish());
+
+        let mut hasher = HashAlgorithm::Blake3.hasher();
+        hasher.update(b"hello");
+        assert_eq!(blake3::hash(b"hello").as_bytes()[..20], hasher.finish());
+    }
 }
@@ -1,5 +1,5 @@
 use anyhow::anyhow;
-use rusqlite::{params, Transaction};
+use rusqlite::{params, Connection, OptionalExtension};
 use serde::{Deserialize, Serialize};
 
 use super::{object::Object, Sha1Id};
@@ -20,21 +20,103 @@ impl Object for Head {
         super::object::ObjectType::Head
     }
 
-    fn create_table(txn: &Transaction) -> crate::Result<()> {
+    fn create_table(txn: &Connection) -> crate::Result<()> {
         txn.execute("CREATE TABLE Head (head JSON);", ())?;
         Ok(())
     }
 
-    fn read_by_id(txn: &Transaction, _id: Self::Id) -> crate::Result<Option<Self>> {
+    fn read_by_id(txn: &Connection, _id: Self::Id) -> crate::Result<Option<Self>> {
         let s: String = txn.query_row("SELECT head from Head;", (), |row| row.get(0))?;
-        let head = serde_json::from_str(&s).map_err(|e| anyhow!("Invalid head string: {}", s))?;
+        let head =
+            serde_json::from_str(&s).map_err(|e| anyhow!("Invalid head string {}: {}", s, e))?;
         Ok(head)
     }
 
-    fn persist(&self, txn: &Transaction) -> crate::Result<()> {
+    fn persist(&self, txn: &Connection) -> crate::Result<()> {
         txn.execute("DELETE FROM Head;", ())?;
         let s = serde_json::to_string(self)?;
         txn.execute("INSERT INTO Head (head) values (?1);", params![s])?;
         Ok(())
     }
 }
+
+/// [`HeadState`] is what HEAD points to, with the branch resolved: a freshly initialized
+/// repository, or one switched to an orphan branch, is on a branch without commits.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HeadState<ID> {
+    /// On the branch of the given full name, which has no commit yet
+    Unborn(String),
+    /// On the branch of the given full name, at the commit
+    Branch(String, ID),
+    /// Detached at the commit
+    Detached(ID),
+}
+
+impl<ID: Copy> HeadState<ID> {
+    /// The commit HEAD points to, None on an unborn branch
+    pub fn commit_id(&self) -> Option<ID> {
+        match self {
+            HeadState::Unborn(_) => None,
+            HeadState::Branch(_, id) | HeadState::Detached(id) => Some(*id),
+        }
+    }
+}
+
+/// Read HEAD and resolve the branch it is on
+pub fn read_head_state(conn: &Connection) -> crate::Result<HeadState<Sha1Id>> {
+    let s: String = conn.query_row("SELECT head from Head;", (), |row| row.get(0))?;
+    let head: Head =
+        serde_json::from_str(&s).map_err(|e| anyhow!("Invalid head string {}: {}", s, e))?;
+    Ok(match head {
+        Head::Branch(name) => {
+            let commit_id: Option<Sha1Id> = conn
+                .query_row(
+                    "SELECT commit_id FROM Refs WHERE ref_name = ?1;",
+                    [&name],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            match commit_id {
+                Some(commit_id) => HeadState::Branch(name, commit_id),
+                None => HeadState::Unborn(name),
+            }
+        }
+        Head::Commit(commit_id) => HeadState::Detached(commit_id),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::db::Ref;
+
+    #[test]
+    fn test_read_head_state() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        let txn = conn.transaction().unwrap();
+        Head::create_table(&txn).unwrap();
+        Ref::create_table(&txn).unwrap();
+
+        let branch = "refs/head/main".to_string();
+        Head::Branch(branch.clone()).persist(&txn).unwrap();
+        assert_eq!(
+            HeadState::Unborn(branch.clone()),
+            read_head_state(&txn).unwrap()
+        );
+        assert_eq!(None, read_head_state(&txn).unwrap().commit_id());
+
+        let id = Sha1Id([1; 20]);
+        txn.execute(
+            "INSERT INTO Refs (ref_name, commit_id) VALUES (?1, ?2)",
+            params![branch, id],
+        )
+        .unwrap();
+        assert_eq!(
+            HeadState::Branch(branch, id),
+            read_head_state(&txn).unwrap()
+        );
+
+        Head::Commit(id).persist(&txn).unwrap();
+        assert_eq!(Some(id), read_head_state(&txn).unwrap().commit_id());
+    }
+}
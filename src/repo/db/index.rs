@@ -4,7 +4,7 @@ use std::{
 };
 
 use anyhow::anyhow;
-use rusqlite::{params, OptionalExtension, Transaction};
+use rusqlite::{params, Connection, OptionalExtension};
 use serde::{Deserialize, Serialize};
 
 use super::{
@@ -66,7 +66,7 @@ pub struct IndexEntry {
 }
 
 impl Index {
-    fn new() -> Index {
+    pub fn new() -> Index {
         Index {
             entries: BTreeMap::new(),
         }
@@ -93,13 +93,13 @@ impl Index {
 impl Object for Index {
     type Id = ();
     /// Create index table and return an empty index
-    fn create_table(txn: &Transaction) -> crate::Result<()> {
+    fn create_table(txn: &Connection) -> crate::Result<()> {
         txn.execute("CREATE TABLE Index_ (index_ JSON);", ())?;
         Ok(())
     }
 
     /// Read an existing index from the database
-    fn read_by_id(txn: &Transaction, _id: Self::Id) -> crate::Result<Option<Index>> {
+    fn read_by_id(txn: &Connection, _id: Self::Id) -> crate::Result<Option<Index>> {
         let s: Option<String> = txn
             .query_row("SELECT index_ FROM Index_;", (), |row| row.get(0))
             .optional()?;
@@ -107,12 +107,12 @@ impl Object for Index {
         let Some(s) = s else { return Ok(None) };
 
         let index =
-            serde_json::from_str(&*s).map_err(|_e| anyhow!("Invalid index string: {}", s))?;
+            serde_json::from_str(&s).map_err(|_e| anyhow!("Invalid index string: {}", s))?;
         Ok(Some(index))
     }
 
     /// Persist the index to database. Ensure that the table contains a single row
-    fn persist(&self, txn: &Transaction) -> crate::Result<()> {
+    fn persist(&self, txn: &Connection) -> crate::Result<()> {
         txn.execute("DELETE FROM Index_;", ())?;
         let s = serde_json::to_string(self)?;
         txn.execute("INSERT INTO Index_ (index_) values (?1);", params![s])?;
@@ -9,10 +9,17 @@
 //! - `Index`: Represents the staging area of the Git repository.
 //! - `Head`: Represents the current HEAD of the repository.
 //! - `Ref`: Represents a Git reference (e.g., branches, tags).
+//! - `RefTransaction`: Updates several references at once, all or nothing.
+//! - `ReflogEntry`: Records a change of a reference.
 //! - `Commit`: Represents a Git commit.
 //! - `Tree`: Represents a Git tree object.
+//! - `TreeBuilder`: Builds the nested trees of a set of files given by their full paths.
 //! - `Blob`: Represents a Git blob object.
+//! - `Tag`: Represents an annotated tag.
+//! - `BlobReader`, `BlobWriter`: Stream the content of blobs without holding it in memory.
 //! - `Sha1Id`: Represents a SHA1 hash used as an identifier for Git objects.
+//! - `LruCache`: Keeps the most recently used values, e.g. objects read from the database.
+//! - `CommitWalk`: Visits the history of commits, the most recent first.
 //!
 //! ## Database Schema
 //!
@@ -24,10 +31,14 @@
 //! - `Commits`: Stores commit information.
 //! - `Trees`: Stores tree object data.
 //! - `Blobs`: Stores blob object data.
+//! - `Tags`: Stores the annotations of annotated tags.
+//! - `Reflog`: Stores the changes of the references.
+//! - `ObjectFormat`: Stores the algorithm computing the object ids.
 //!
 //! ## Traits
 //!
 //! - `Hashable`: A trait for objects that can be hashed to generate an ID.
+//! - `Hasher`: A trait for the hash algorithms computing the IDs, see `HashAlgorithm`.
 //! - `IdType`: A trait for handling different ID types (e.g., `NoId`, `Sha1Id`).
 //!
 //! ## Usage
@@ -37,17 +48,40 @@
 //! managing the staging area.
 //!
 //! Note: This module relies on the `rusqlite` crate for SQLite database operations
-//! and the `sha1` crate for hash computations.
+//! and the `sha1` and `blake3` crates for hash computations.
 //!
 
 mod blob;
+mod blob_stream;
+mod cache;
 mod commit;
 mod hash;
 mod head;
 mod index;
 mod object;
+mod ref_transaction;
 mod reference;
+mod reflog;
+mod tag;
 mod tree;
+mod walk;
+
+pub use blob::Blob;
+pub use blob_stream::{BlobReader, BlobWriter};
+pub use cache::LruCache;
+pub use commit::Commit;
+pub use hash::{HashAlgorithm, Hashable, Hasher, ObjectHasher, CREATE_OBJECT_FORMAT_TABLE};
+pub use head::{read_head_state, Head, HeadState};
+pub use index::{Index, IndexEntry, MergeStage};
+pub use object::{
+    for_each_object, object_exists, read_object_type, table_exists, FileType, Object, ObjectType,
+};
+pub use ref_transaction::{OldValue, RefTransaction};
+pub use reference::Ref;
+pub use reflog::{ReflogEntry, CREATE_REFLOG_TABLE};
+pub use tag::{Tag, CREATE_TAGS_TABLE};
+pub use tree::{Tree, TreeBuilder, TreeEntry};
+pub use walk::{CommitWalk, WalkedCommit};
 
 use std::fmt;
 
@@ -56,7 +90,8 @@ use serde::{Deserialize, Serialize};
 
 /// [`IdType`] represents a possible ID state of any object. In reality, object come from two sources:
 /// 1. Top-down from querying the database (e.g., `gitqlite ls-file`).
-/// 2. Bottom-up construction from the index, or arbitrary files/data (e.g., `git hash-object`)  
+/// 2. Bottom-up construction from the index, or arbitrary files/data (e.g., `git hash-object`)
+///
 /// In case 1, the object always has a valid [`Sha1Id`], whereas in case 2, the object has [`NoId`], and needs
 /// to be hashed before it could be persisted into the database.
 pub trait IdType<T>: Copy + fmt::Display {
@@ -86,7 +121,7 @@ impl<T> IdType<T> for NoId {
 }
 
 /// [`Sha1Id`] represents a sha1 hash
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct Sha1Id([u8; 20]);
 
 impl fmt::Display for Sha1Id {
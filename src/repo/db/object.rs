@@ -1,6 +1,24 @@
-use rusqlite::Transaction;
+use rusqlite::{Connection, OptionalExtension};
 use serde::{Deserialize, Serialize};
 
+use super::Sha1Id;
+
+/// Each object table is probed by its primary key, all in one statement
+const READ_OBJECT_TYPE: &str = "SELECT 'commit' FROM Commits WHERE commit_id = ?1 \
+     UNION ALL SELECT 'tree' FROM Trees WHERE tree_id = ?1 \
+     UNION ALL SELECT 'blob' FROM Blobs WHERE blob_id = ?1 \
+     UNION ALL SELECT 'tag' FROM Tags WHERE tag_id = ?1 LIMIT 1";
+const HAS_TABLE: &str =
+    "SELECT count(*) > 0 FROM sqlite_master WHERE type IN ('table', 'view') AND name = ?1";
+/// Every object with the size of its content, ranked by type in the order of git's type numbers.
+/// A blob may be stored more than once, so its copies are grouped.
+const READ_ALL_OBJECTS: &str =
+    "SELECT commit_id, 'commit', length(CAST(message AS BLOB)), 1 FROM Commits \
+     UNION ALL SELECT tree_id, 'tree', length(CAST(data AS BLOB)), 2 FROM Trees \
+     UNION ALL SELECT blob_id, 'blob', max(length(data)), 3 FROM Blobs GROUP BY blob_id \
+     UNION ALL SELECT tag_id, 'tag', length(CAST(message AS BLOB)), 4 FROM Tags \
+     ORDER BY 4, 1";
+
 /// [`FileType`] represents a file type on the file system
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum FileType {
@@ -26,6 +44,54 @@ pub enum ObjectType {
     Commit,
     Tree,
     Blob,
+    Tag,
+}
+
+/// The type of the object with the given id, None if the database does not have it
+pub fn read_object_type(conn: &Connection, id: Sha1Id) -> crate::Result<Option<ObjectType>> {
+    let type_: Option<String> = conn
+        .prepare_cached(READ_OBJECT_TYPE)?
+        .query_row([id], |row| row.get(0))
+        .optional()?;
+    Ok(type_.map(|type_| match type_.as_str() {
+        "commit" => ObjectType::Commit,
+        "tree" => ObjectType::Tree,
+        "tag" => ObjectType::Tag,
+        _ => ObjectType::Blob,
+    }))
+}
+
+/// Whether the database has an object with the given id, whatever its type
+pub fn object_exists(conn: &Connection, id: Sha1Id) -> crate::Result<bool> {
+    Ok(read_object_type(conn, id)?.is_some())
+}
+
+/// Stream every object of the database to `f` with its type and the size of its content (the
+/// data of a blob, the encoded entries of a tree, the message of a commit or tag), ordered by
+/// type and then by id, in a single query
+pub fn for_each_object(
+    conn: &Connection,
+    mut f: impl FnMut(Sha1Id, ObjectType, u64) -> crate::Result<()>,
+) -> crate::Result<()> {
+    let mut stmt = conn.prepare_cached(READ_ALL_OBJECTS)?;
+    let mut rows = stmt.query(())?;
+    while let Some(row) = rows.next()? {
+        let type_ = match row.get_ref(1)?.as_str()? {
+            "commit" => ObjectType::Commit,
+            "tree" => ObjectType::Tree,
+            "tag" => ObjectType::Tag,
+            _ => ObjectType::Blob,
+        };
+        f(row.get(0)?, type_, row.get(2)?)?;
+    }
+    Ok(())
+}
+
+/// Whether the database has a table or view named `name`
+pub fn table_exists(conn: &Connection, name: &str) -> crate::Result<bool> {
+    Ok(conn
+        .prepare_cached(HAS_TABLE)?
+        .query_row([name], |row| row.get(0))?)
 }
 
 /// [`Object`] represents a generic object in gitqlite database
@@ -35,11 +101,88 @@ pub trait Object: Sized {
     fn type_(&self) -> ObjectType;
 
     /// Initialize table in database
-    fn create_table(txn: &Transaction) -> crate::Result<()>;
+    fn create_table(txn: &Connection) -> crate::Result<()>;
 
     /// Read one instance by Id
-    fn read_by_id(txn: &Transaction, id: Self::Id) -> crate::Result<Option<Self>>;
+    fn read_by_id(txn: &Connection, id: Self::Id) -> crate::Result<Option<Self>>;
 
     /// Persist on instance
-    fn persist(&self, txn: &Transaction) -> crate::Result<()>;
+    fn persist(&self, txn: &Connection) -> crate::Result<()>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::db::{Blob, Commit, HashAlgorithm, Tag, Tree};
+
+    #[test]
+    fn test_read_object_type() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        let txn = conn.transaction().unwrap();
+        Blob::<Sha1Id>::create_table(&txn).unwrap();
+        Tree::<Sha1Id>::create_table(&txn).unwrap();
+        Commit::<Sha1Id>::create_table(&txn).unwrap();
+        Tag::<Sha1Id>::create_table(&txn).unwrap();
+
+        let blob = Blob::new(vec![1, 2, 3]).with_id(HashAlgorithm::Sha1);
+        blob.persist(&txn).unwrap();
+
+        assert_eq!(
+            Some(ObjectType::Blob),
+            read_object_type(&txn, blob.blob_id).unwrap()
+        );
+        assert!(object_exists(&txn, blob.blob_id).unwrap());
+        assert!(!object_exists(&txn, Sha1Id([7; 20])).unwrap());
+
+        let tag = Tag::new(
+            "v1.0".to_string(),
+            blob.blob_id,
+            ("A".to_string(), "a@example.com".to_string()),
+            0,
+            "release".to_string(),
+        )
+        .with_id(HashAlgorithm::Sha1);
+        tag.persist(&txn).unwrap();
+        assert_eq!(
+            Some(ObjectType::Tag),
+            read_object_type(&txn, tag.tag_id).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_for_each_object() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        let txn = conn.transaction().unwrap();
+        Blob::<Sha1Id>::create_table(&txn).unwrap();
+        Tree::<Sha1Id>::create_table(&txn).unwrap();
+        Commit::<Sha1Id>::create_table(&txn).unwrap();
+        Tag::<Sha1Id>::create_table(&txn).unwrap();
+
+        let small = Blob::new(vec![1, 2, 3]).with_id(HashAlgorithm::Sha1);
+        let large = Blob::new(vec![4; 10]).with_id(HashAlgorithm::Sha1);
+        large.persist(&txn).unwrap();
+        small.persist(&txn).unwrap();
+        small.persist(&txn).unwrap();
+        // Trees come before blobs whatever their ids
+        txn.execute(
+            "INSERT INTO Trees (tree_id, data) VALUES (?1, ?2)",
+            (Sha1Id([0xff; 20]), "{}"),
+        )
+        .unwrap();
+
+        let mut objects = Vec::new();
+        for_each_object(&txn, |id, type_, size| {
+            objects.push((id, type_, size));
+            Ok(())
+        })
+        .unwrap();
+
+        let mut expected = vec![
+            (small.blob_id, ObjectType::Blob, 3),
+            (large.blob_id, ObjectType::Blob, 10),
+        ];
+        expected.sort_by_key(|(id, _, _)| id.to_string());
+        expected.insert(0, (Sha1Id([0xff; 20]), ObjectType::Tree, 2));
+        assert_eq!(expected, objects);
+    }
 }
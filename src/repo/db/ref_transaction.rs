@@ -0,0 +1,183 @@
+use std::collections::HashSet;
+
+use anyhow::anyhow;
+use rusqlite::Connection;
+
+use super::{Ref, ReflogEntry, Sha1Id};
+
+/// [`OldValue`] is what a ref must point at for an update of a [`RefTransaction`] to apply
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OldValue {
+    /// Whatever the ref points at, if it exists at all
+    Any,
+    /// The ref must not exist
+    Missing,
+    /// The ref must point at the commit
+    Is(Sha1Id),
+}
+
+/// [`RefUpdate`] moves a ref to `new`, None deleting the ref
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct RefUpdate {
+    name: String,
+    old: OldValue,
+    new: Option<Sha1Id>,
+}
+
+/// [`RefTransaction`] batches updates of several refs which are applied all or nothing: each
+/// ref is checked against the value it is expected to have, and if any check fails, no ref is
+/// changed. Each change is recorded in the reflog with the message of the transaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RefTransaction {
+    message: String,
+    updates: Vec<RefUpdate>,
+}
+
+impl RefTransaction {
+    pub fn new(message: impl Into<String>) -> RefTransaction {
+        RefTransaction {
+            message: message.into(),
+            updates: Vec::new(),
+        }
+    }
+
+    /// Move the ref `name` to `new` if it is at `old`
+    pub fn update(&mut self, name: impl Into<String>, old: OldValue, new: Sha1Id) -> &mut Self {
+        self.updates.push(RefUpdate {
+            name: name.into(),
+            old,
+            new: Some(new),
+        });
+        self
+    }
+
+    /// Delete the ref `name` if it is at `old`
+    pub fn delete(&mut self, name: impl Into<String>, old: OldValue) -> &mut Self {
+        self.updates.push(RefUpdate {
+            name: name.into(),
+            old,
+            new: None,
+        });
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.updates.is_empty()
+    }
+
+    /// Check and apply every update in `txn`. Every ref is checked before any is written, so
+    /// a failed check leaves the refs untouched; `txn` must be rolled back on other errors.
+    pub fn commit(self, txn: &Connection) -> crate::Result<()> {
+        let mut names = HashSet::new();
+        let mut current = Vec::with_capacity(self.updates.len());
+        for update in &self.updates {
+            if !names.insert(update.name.as_str()) {
+                return Err(anyhow!(
+                    "fatal: multiple updates for ref '{}' not allowed",
+                    update.name
+                ));
+            }
+            let id = Ref::read_from_with_name(txn, &update.name)?.map(|r| r.commit_id);
+            match (update.old, id) {
+                (OldValue::Any, _) => {}
+                (OldValue::Missing, Some(_)) => {
+                    return Err(anyhow!(
+                        "fatal: cannot lock ref '{}': reference already exists",
+                        update.name
+                    ))
+                }
+                (OldValue::Is(expected), None) => {
+                    return Err(anyhow!(
+                        "fatal: cannot lock ref '{}': unable to resolve reference, expected {}",
+                        update.name,
+                        expected
+                    ))
+                }
+                (OldValue::Is(expected), Some(id)) if id != expected => {
+                    return Err(anyhow!(
+                        "fatal: cannot lock ref '{}': is at {} but expected {}",
+                        update.name,
+                        id,
+                        expected
+                    ))
+                }
+                _ => {}
+            }
+            current.push(id);
+        }
+
+        for (update, old_id) in self.updates.iter().zip(current) {
+            match update.new {
+                Some(commit_id) => Ref {
+                    name: update.name.clone(),
+                    commit_id,
+                }
+                .persist(txn)?,
+                None => Ref::delete(txn, &update.name)?,
+            }
+            if old_id != update.new {
+                ReflogEntry::append(txn, &update.name, old_id, update.new, &self.message)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::Connection;
+
+    #[test]
+    fn test_ref_transaction() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        let txn = conn.transaction().unwrap();
+        Ref::create_table(&txn).unwrap();
+        ReflogEntry::create_table(&txn).unwrap();
+
+        let (a, b) = (Sha1Id([1; 20]), Sha1Id([2; 20]));
+        let mut create = RefTransaction::new("push");
+        create
+            .update("refs/heads/main", OldValue::Missing, a)
+            .update("refs/heads/topic", OldValue::Any, b);
+        create.commit(&txn).unwrap();
+
+        // A stale expectation fails the whole transaction
+        let mut stale = RefTransaction::new("push");
+        stale.update("refs/heads/topic", OldValue::Is(b), a).update(
+            "refs/heads/main",
+            OldValue::Is(b),
+            b,
+        );
+        let err = stale.commit(&txn).unwrap_err();
+        assert!(err.to_string().contains("is at"));
+        let read = |name: &str| Ref::read_from_with_name(&txn, name).unwrap();
+        assert_eq!(b, read("refs/heads/topic").unwrap().commit_id);
+
+        let mut duplicate = RefTransaction::new("push");
+        duplicate
+            .update("refs/heads/main", OldValue::Any, b)
+            .delete("refs/heads/main", OldValue::Any);
+        assert!(duplicate.commit(&txn).is_err());
+
+        let mut update = RefTransaction::new("rewind");
+        update
+            .update("refs/heads/main", OldValue::Is(a), b)
+            .delete("refs/heads/topic", OldValue::Is(b));
+        update.commit(&txn).unwrap();
+        assert_eq!(b, read("refs/heads/main").unwrap().commit_id);
+        assert_eq!(None, read("refs/heads/topic"));
+
+        let log = ReflogEntry::read_for_ref(&txn, "refs/heads/main").unwrap();
+        let changes: Vec<_> = log
+            .iter()
+            .map(|entry| (entry.old_id, entry.new_id, entry.message.as_str()))
+            .collect();
+        assert_eq!(
+            vec![(Some(a), Some(b), "rewind"), (None, Some(a), "push")],
+            changes
+        );
+        let log = ReflogEntry::read_for_ref(&txn, "refs/heads/topic").unwrap();
+        assert_eq!((Some(b), None), (log[0].old_id, log[0].new_id));
+    }
+}
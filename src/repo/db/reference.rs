@@ -1,4 +1,4 @@
-use rusqlite::{params, OptionalExtension, Transaction};
+use rusqlite::{params, Connection, OptionalExtension};
 use serde::{Deserialize, Serialize};
 
 use super::Sha1Id;
@@ -10,7 +10,7 @@ pub struct Ref {
 }
 
 impl Ref {
-    pub fn create_table(txn: &Transaction) -> crate::Result<()> {
+    pub fn create_table(txn: &Connection) -> crate::Result<()> {
         txn.execute(
             "CREATE TABLE Refs (ref_name TEXT PRIMARY KEY, commit_id BLOB NOT NULL);",
             (),
@@ -18,7 +18,7 @@ impl Ref {
         Ok(())
     }
 
-    pub fn read_from_with_name(txn: &Transaction, name: &str) -> crate::Result<Option<Ref>> {
+    pub fn read_from_with_name(txn: &Connection, name: &str) -> crate::Result<Option<Ref>> {
         txn.query_row_and_then(
             "SELECT ref_name, commit_id FROM Refs WHERE ref_name = ?1",
             [name],
@@ -33,13 +33,18 @@ impl Ref {
         .map_err(anyhow::Error::from)
     }
 
-    pub fn persist(&self, txn: &Transaction) -> crate::Result<()> {
+    pub fn persist(&self, txn: &Connection) -> crate::Result<()> {
         txn.execute(
             "INSERT OR REPLACE INTO Refs (ref_name, commit_id) VALUES (?1, ?2);",
             params![self.name, self.commit_id],
         )?;
         Ok(())
     }
+
+    pub fn delete(txn: &Connection, name: &str) -> crate::Result<()> {
+        txn.execute("DELETE FROM Refs WHERE ref_name = ?1", [name])?;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
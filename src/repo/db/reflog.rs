@@ -0,0 +1,60 @@
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+
+use super::Sha1Id;
+
+/// Reflog records every change of a ref made through a [`super::RefTransaction`]
+pub const CREATE_REFLOG_TABLE: &str = "CREATE TABLE Reflog (entry_id INTEGER PRIMARY KEY, ref_name TEXT NOT NULL, old_id BLOB, new_id BLOB, message TEXT NOT NULL, created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now')));";
+
+/// [`ReflogEntry`] is one change of a ref. A None id stands for a missing ref, so a None
+/// `old_id` records the creation of the ref and a None `new_id` its deletion.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReflogEntry {
+    pub ref_name: String,
+    pub old_id: Option<Sha1Id>,
+    pub new_id: Option<Sha1Id>,
+    pub message: String,
+    /// Unix time of the change
+    pub created_at: i64,
+}
+
+impl ReflogEntry {
+    pub fn create_table(txn: &Connection) -> crate::Result<()> {
+        txn.execute(CREATE_REFLOG_TABLE, ())?;
+        Ok(())
+    }
+
+    /// The changes of a ref, the most recent first
+    pub fn read_for_ref(txn: &Connection, ref_name: &str) -> crate::Result<Vec<ReflogEntry>> {
+        let mut stmt = txn.prepare(
+            "SELECT ref_name, old_id, new_id, message, created_at FROM Reflog WHERE ref_name = ?1 ORDER BY entry_id DESC",
+        )?;
+        let entries = stmt
+            .query_map([ref_name], |row| {
+                Ok(ReflogEntry {
+                    ref_name: row.get(0)?,
+                    old_id: row.get(1)?,
+                    new_id: row.get(2)?,
+                    message: row.get(3)?,
+                    created_at: row.get(4)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(entries)
+    }
+
+    /// Record a change of a ref made now
+    pub fn append(
+        txn: &Connection,
+        ref_name: &str,
+        old_id: Option<Sha1Id>,
+        new_id: Option<Sha1Id>,
+        message: &str,
+    ) -> crate::Result<()> {
+        txn.execute(
+            "INSERT INTO Reflog (ref_name, old_id, new_id, message) VALUES (?1, ?2, ?3, ?4);",
+            params![ref_name, old_id, new_id, message],
+        )?;
+        Ok(())
+    }
+}
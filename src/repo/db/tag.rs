@@ -0,0 +1,210 @@
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    hash::{HashAlgorithm, Hashable, Hasher},
+    object::{Object, ObjectType},
+    IdType, NoId, Sha1Id,
+};
+
+/// Tags stores the annotations of annotated tags, whose refs point at the tagged commits
+pub const CREATE_TAGS_TABLE: &str = "CREATE TABLE Tags (tag_id BLOB PRIMARY KEY, name TEXT NOT NULL UNIQUE, object_id BLOB NOT NULL, tagger_name TEXT NOT NULL, tagger_email TEXT NOT NULL, tagged_at INTEGER NOT NULL, message TEXT NOT NULL);";
+const READ_TAG_FOR_NAME: &str = "SELECT tag_id, name, object_id, tagger_name, tagger_email, tagged_at, message FROM Tags WHERE name = ?1";
+const READ_TAG_FOR_ID: &str = "SELECT tag_id, name, object_id, tagger_name, tagger_email, tagged_at, message FROM Tags WHERE tag_id = ?1";
+const INSERT_OR_REPLACE_TAG: &str = "INSERT OR REPLACE INTO Tags (tag_id, name, object_id, tagger_name, tagger_email, tagged_at, message) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7);";
+const DELETE_TAG_FOR_NAME: &str = "DELETE FROM Tags WHERE name = ?1;";
+
+/// [`Tag`] is an annotated tag: a named pointer to an object, along with who tagged it, when,
+/// and why. Lightweight tags are only refs under `refs/tags/` and have no [`Tag`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Tag<ID: IdType<ID>> {
+    pub tag_id: ID,
+    /// Name of the tag, without the `refs/tags/` prefix
+    pub name: String,
+    /// The tagged object
+    pub object_id: Sha1Id,
+    pub tagger_name: String,
+    pub tagger_email: String,
+    /// Seconds since the epoch
+    pub tagged_at: i64,
+    pub message: String,
+}
+
+impl Hashable for Tag<NoId> {
+    fn hash(&self, mut hasher: impl Hasher) -> Sha1Id {
+        // the hash of the tag is the hash of its fields in the form
+        // <object_id>
+        // <name>
+        // <tagger_name> <tagger_email> <tagged_at>
+        // [empty line]
+        // <message>
+        hasher.update(self.object_id.0);
+        hasher.update("\n");
+        hasher.update(&self.name);
+        hasher.update("\n");
+        hasher.update(&self.tagger_name);
+        hasher.update(" ");
+        hasher.update(&self.tagger_email);
+        hasher.update(" ");
+        hasher.update(self.tagged_at.to_string());
+        hasher.update("\n");
+        hasher.update("\n");
+        hasher.update(&self.message);
+        hasher.update("\n");
+        Sha1Id(hasher.finish())
+    }
+}
+
+impl Tag<NoId> {
+    pub fn new(
+        name: String,
+        object_id: Sha1Id,
+        (tagger_name, tagger_email): (String, String),
+        tagged_at: i64,
+        message: String,
+    ) -> Tag<NoId> {
+        Tag {
+            tag_id: NoId,
+            name,
+            object_id,
+            tagger_name,
+            tagger_email,
+            tagged_at,
+            message,
+        }
+    }
+
+    pub fn with_id(self, algorithm: HashAlgorithm) -> Tag<Sha1Id> {
+        let id = self.hash(algorithm.hasher());
+        Tag {
+            tag_id: id,
+            name: self.name,
+            object_id: self.object_id,
+            tagger_name: self.tagger_name,
+            tagger_email: self.tagger_email,
+            tagged_at: self.tagged_at,
+            message: self.message,
+        }
+    }
+}
+
+impl Tag<Sha1Id> {
+    fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Tag<Sha1Id>> {
+        Ok(Tag {
+            tag_id: row.get(0)?,
+            name: row.get(1)?,
+            object_id: row.get(2)?,
+            tagger_name: row.get(3)?,
+            tagger_email: row.get(4)?,
+            tagged_at: row.get(5)?,
+            message: row.get(6)?,
+        })
+    }
+
+    /// Read the annotation of the tag `name`, None for a lightweight tag
+    pub fn read_by_name(conn: &Connection, name: &str) -> crate::Result<Option<Tag<Sha1Id>>> {
+        Ok(conn
+            .query_row(READ_TAG_FOR_NAME, [name], Tag::from_row)
+            .optional()?)
+    }
+
+    /// Remove the annotation of the tag `name`, if any
+    pub fn delete(conn: &Connection, name: &str) -> crate::Result<()> {
+        conn.execute(DELETE_TAG_FOR_NAME, [name])?;
+        Ok(())
+    }
+}
+
+impl Object for Tag<Sha1Id> {
+    type Id = Sha1Id;
+
+    fn type_(&self) -> ObjectType {
+        ObjectType::Tag
+    }
+
+    fn create_table(txn: &Connection) -> crate::Result<()> {
+        txn.execute(CREATE_TAGS_TABLE, ())?;
+        Ok(())
+    }
+
+    fn read_by_id(txn: &Connection, id: Sha1Id) -> crate::Result<Option<Tag<Sha1Id>>> {
+        Ok(txn
+            .query_row(READ_TAG_FOR_ID, [id], Tag::from_row)
+            .optional()?)
+    }
+
+    fn persist(&self, txn: &Connection) -> crate::Result<()> {
+        // A tag name has a single annotation, so retagging replaces it
+        txn.execute(
+            INSERT_OR_REPLACE_TAG,
+            params![
+                self.tag_id,
+                self.name,
+                self.object_id,
+                self.tagger_name,
+                self.tagger_email,
+                self.tagged_at,
+                self.message
+            ],
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rusqlite::Connection;
+
+    use super::*;
+    use crate::repo::db::{read_object_type, Blob, Commit, Tree};
+
+    #[test]
+    fn test_tag_persistence() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        let txn = conn.transaction().unwrap();
+        Blob::<Sha1Id>::create_table(&txn).unwrap();
+        Tree::<Sha1Id>::create_table(&txn).unwrap();
+        Commit::<Sha1Id>::create_table(&txn).unwrap();
+        Tag::<Sha1Id>::create_table(&txn).unwrap();
+
+        let tagger = ("A U Thor".to_string(), "author@example.com".to_string());
+        let tag = Tag::new(
+            "v1.0".to_string(),
+            Sha1Id([1; 20]),
+            tagger.clone(),
+            1_700_000_000,
+            "Release 1.0".to_string(),
+        )
+        .with_id(HashAlgorithm::Sha1);
+        tag.persist(&txn).unwrap();
+        assert_eq!(
+            Some(&tag),
+            Tag::read_by_id(&txn, tag.tag_id).unwrap().as_ref()
+        );
+        assert_eq!(
+            Some(ObjectType::Tag),
+            read_object_type(&txn, tag.tag_id).unwrap()
+        );
+
+        // Retagging replaces the annotation, which has another id
+        let retagged = Tag::new(
+            "v1.0".to_string(),
+            Sha1Id([1; 20]),
+            tagger,
+            1_700_000_000,
+            "Release 1.0, again".to_string(),
+        )
+        .with_id(HashAlgorithm::Sha1);
+        assert_ne!(tag.tag_id, retagged.tag_id);
+        retagged.persist(&txn).unwrap();
+        assert_eq!(None, Tag::read_by_id(&txn, tag.tag_id).unwrap());
+        assert_eq!(None, read_object_type(&txn, tag.tag_id).unwrap());
+        assert_eq!(
+            Some(&retagged),
+            Tag::read_by_name(&txn, "v1.0").unwrap().as_ref()
+        );
+
+        Tag::delete(&txn, "v1.0").unwrap();
+        assert_eq!(None, Tag::read_by_name(&txn, "v1.0").unwrap());
+    }
+}
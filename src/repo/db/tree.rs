@@ -1,11 +1,14 @@
-use std::{collections::BTreeMap, path::PathBuf};
+use std::{
+    collections::BTreeMap,
+    path::{Component, Path, PathBuf},
+};
 
-use rusqlite::{params, OptionalExtension};
+use anyhow::anyhow;
+use rusqlite::{params, Connection, OptionalExtension};
 use serde::{Deserialize, Serialize};
-use sha1::Digest as _;
 
 use super::{
-    hash::Hashable,
+    hash::{HashAlgorithm, Hashable, Hasher},
     object::{FileType, Object, ObjectType},
     IdType, NoId, Sha1Id,
 };
@@ -23,10 +26,10 @@ pub struct Tree<ID: IdType<ID>> {
     /// Then the tree representing the directory would be like:
     ///
     /// - a's tree entries:
-    /// b -> [an entry for b]
+    ///   b -> [an entry for b]
     ///
     /// - b's tree entries:
-    /// 1.txt -> [an entry for 1.txt]
+    ///   1.txt -> [an entry for 1.txt]
     pub entries: BTreeMap<PathBuf, TreeEntry>,
 }
 
@@ -47,16 +50,16 @@ pub struct TreeEntry {
 }
 
 impl Hashable for Tree<NoId> {
-    fn hash(&self, mut sha: sha1::Sha1) -> Sha1Id {
+    fn hash(&self, mut hasher: impl Hasher) -> Sha1Id {
         let s = serde_json::to_vec(self).expect("Serialize tree failed");
-        sha.update(s);
-        Sha1Id(sha.finalize().into())
+        hasher.update(s);
+        Sha1Id(hasher.finish())
     }
 }
 
 impl Tree<NoId> {
-    pub fn with_id(self) -> Tree<Sha1Id> {
-        let id = self.hash(sha1::Sha1::new());
+    pub fn with_id(self, algorithm: HashAlgorithm) -> Tree<Sha1Id> {
+        let id = self.hash(algorithm.hasher());
         Tree {
             tree_id: id,
             entries: self.entries,
@@ -64,6 +67,124 @@ impl Tree<NoId> {
     }
 }
 
+/// [`TreeBuilder`] collects files by their full path, inserted in any order, and builds the
+/// nested [`Tree`]s holding them: one tree per directory containing a file, and no other.
+#[derive(Debug, Clone, Default)]
+pub struct TreeBuilder {
+    /// Full path -> blob id and mode
+    files: BTreeMap<PathBuf, (Sha1Id, u32)>,
+}
+
+impl TreeBuilder {
+    pub fn new() -> TreeBuilder {
+        TreeBuilder::default()
+    }
+
+    /// Add the file at `path`, relative to the root of the tree, replacing any file inserted
+    /// there before. The mode holds the file type and permissions, e.g. `0o100644`.
+    pub fn insert(
+        &mut self,
+        path: impl AsRef<Path>,
+        blob_id: Sha1Id,
+        mode: u32,
+    ) -> crate::Result<&mut Self> {
+        let path = path.as_ref();
+        if path.as_os_str().is_empty()
+            || !path
+                .components()
+                .all(|component| matches!(component, Component::Normal(_)))
+        {
+            return Err(anyhow!("fatal: invalid path '{}'", path.display()));
+        }
+        if file_type(mode).is_none() {
+            return Err(anyhow!(
+                "fatal: invalid mode {:o} for '{}'",
+                mode,
+                path.display()
+            ));
+        }
+        self.files.insert(path.to_path_buf(), (blob_id, mode));
+        Ok(self)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Build the trees, the root tree last. Fails if a path is both a file and a directory.
+    pub fn build(&self, algorithm: HashAlgorithm) -> crate::Result<Vec<Tree<Sha1Id>>> {
+        // Directory -> its entries, every ancestor of a file being a directory
+        let mut dirs: BTreeMap<PathBuf, BTreeMap<PathBuf, TreeEntry>> = BTreeMap::new();
+        dirs.insert(PathBuf::new(), BTreeMap::new());
+        for (path, &(id, mode)) in &self.files {
+            let parent = path.parent().unwrap_or(Path::new(""));
+            for dir in parent.ancestors() {
+                dirs.entry(dir.to_path_buf()).or_default();
+            }
+            let entry = TreeEntry {
+                object_type: ObjectType::Blob,
+                file_type: file_type(mode).expect("mode checked on insert"),
+                id,
+                perms: mode & 0o777,
+            };
+            let name = PathBuf::from(path.file_name().expect("path checked on insert"));
+            dirs.get_mut(parent)
+                .expect("parent inserted above")
+                .insert(name, entry);
+        }
+        if let Some(path) = self.files.keys().find(|path| dirs.contains_key(*path)) {
+            return Err(anyhow!(
+                "fatal: '{}' is both a file and a directory",
+                path.display()
+            ));
+        }
+
+        // Build the deepest directories first, so that each subtree is known when its parent
+        // is built
+        let mut order: Vec<PathBuf> = dirs.keys().cloned().collect();
+        order.sort_by_key(|dir| std::cmp::Reverse(dir.components().count()));
+        let mut trees = Vec::with_capacity(order.len());
+        for dir in order {
+            let entries = dirs.remove(&dir).expect("every directory is built once");
+            let tree = Tree {
+                tree_id: NoId,
+                entries,
+            }
+            .with_id(algorithm);
+            if let Some(parent) = dir.parent() {
+                let name = PathBuf::from(dir.file_name().expect("not the root"));
+                let entry = TreeEntry {
+                    object_type: ObjectType::Tree,
+                    file_type: FileType::Directory,
+                    id: tree.tree_id,
+                    perms: 0,
+                };
+                dirs.get_mut(parent)
+                    .expect("parents are built after children")
+                    .insert(name, entry);
+            }
+            trees.push(tree);
+        }
+        Ok(trees)
+    }
+
+    /// Build and persist the trees, returning the id of the root tree
+    pub fn write(&self, txn: &Connection) -> crate::Result<Sha1Id> {
+        let trees = self.build(HashAlgorithm::read(txn)?)?;
+        for tree in &trees {
+            tree.persist(txn)?;
+        }
+        Ok(trees.last().expect("the root tree is always built").tree_id)
+    }
+}
+
+/// The type of the file a mode describes, None unless it is a regular file or symlink
+fn file_type(mode: u32) -> Option<FileType> {
+    [FileType::Regular, FileType::Symlink]
+        .into_iter()
+        .find(|file_type| mode >> 9 == file_type.num())
+}
+
 impl Object for Tree<Sha1Id> {
     type Id = Sha1Id;
 
@@ -71,7 +192,7 @@ impl Object for Tree<Sha1Id> {
         ObjectType::Tree
     }
 
-    fn create_table(txn: &rusqlite::Transaction) -> crate::Result<()> {
+    fn create_table(txn: &rusqlite::Connection) -> crate::Result<()> {
         txn.execute(
             "CREATE TABLE Trees (tree_id BLOB PRIMARY KEY, data JSON NOT NULL);",
             (),
@@ -79,7 +200,7 @@ impl Object for Tree<Sha1Id> {
         Ok(())
     }
 
-    fn read_by_id(txn: &rusqlite::Transaction, id: Self::Id) -> crate::Result<Option<Self>> {
+    fn read_by_id(txn: &rusqlite::Connection, id: Self::Id) -> crate::Result<Option<Self>> {
         txn.query_row_and_then(
             "SELECT tree_id, data FROM Trees WHERE tree_id = (?1);",
             [id],
@@ -95,7 +216,7 @@ impl Object for Tree<Sha1Id> {
         .map_err(anyhow::Error::from)
     }
 
-    fn persist(&self, txn: &rusqlite::Transaction) -> crate::Result<()> {
+    fn persist(&self, txn: &rusqlite::Connection) -> crate::Result<()> {
         let id = self.tree_id;
         let s = serde_json::to_string(&self.entries).expect("Failed to serialize tree");
         txn.execute(
@@ -133,7 +254,7 @@ mod tests {
             tree_id: NoId,
             entries,
         }
-        .with_id();
+        .with_id(HashAlgorithm::Sha1);
 
         tree.persist(&txn).unwrap();
 
@@ -158,7 +279,7 @@ mod tests {
             },
         );
 
-        let mut entries2 = entries1.clone();
+        let entries2 = entries1.clone();
 
         let mut entries3 = BTreeMap::new();
         entries3.insert(
@@ -184,7 +305,67 @@ mod tests {
             entries: entries3,
         };
 
-        assert_eq!(tree1.hash(sha1::Sha1::new()), tree2.hash(sha1::Sha1::new()));
-        assert_ne!(tree1.hash(sha1::Sha1::new()), tree3.hash(sha1::Sha1::new()));
+        assert_eq!(
+            tree1.hash(HashAlgorithm::Sha1.hasher()),
+            tree2.hash(HashAlgorithm::Sha1.hasher())
+        );
+        assert_ne!(
+            tree1.hash(HashAlgorithm::Sha1.hasher()),
+            tree3.hash(HashAlgorithm::Sha1.hasher())
+        );
+    }
+
+    #[test]
+    fn test_tree_builder() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        let txn = conn.transaction().unwrap();
+        Tree::<Sha1Id>::create_table(&txn).unwrap();
+        HashAlgorithm::Sha1.persist(&txn).unwrap();
+
+        let files = [
+            ("src/git/mod.rs", Sha1Id([1; 20]), 0o100644),
+            ("README", Sha1Id([2; 20]), 0o100644),
+            ("src/main.rs", Sha1Id([3; 20]), 0o100755),
+            ("src/git/link", Sha1Id([4; 20]), 0o120000),
+        ];
+        let mut forward = TreeBuilder::new();
+        for (path, id, mode) in files {
+            forward.insert(path, id, mode).unwrap();
+        }
+        let mut backward = TreeBuilder::new();
+        for (path, id, mode) in files.into_iter().rev() {
+            backward.insert(path, id, mode).unwrap();
+        }
+
+        // One tree per directory, whatever the insertion order
+        let trees = forward.build(HashAlgorithm::Sha1).unwrap();
+        assert_eq!(3, trees.len());
+        let root_id = forward.write(&txn).unwrap();
+        assert_eq!(root_id, backward.write(&txn).unwrap());
+
+        let root = Tree::read_by_id(&txn, root_id).unwrap().unwrap();
+        assert_eq!(2, root.entries.len());
+        let src = &root.entries[Path::new("src")];
+        assert_eq!(ObjectType::Tree, src.object_type);
+        let src = Tree::read_by_id(&txn, src.id).unwrap().unwrap();
+        let main = &src.entries[Path::new("main.rs")];
+        assert_eq!((FileType::Regular, 0o755), (main.file_type, main.perms));
+        let git = Tree::read_by_id(&txn, src.entries[Path::new("git")].id)
+            .unwrap()
+            .unwrap();
+        assert_eq!(FileType::Symlink, git.entries[Path::new("link")].file_type);
+
+        assert!(forward
+            .insert("../escape", Sha1Id([5; 20]), 0o100644)
+            .is_err());
+        assert!(forward.insert("dir", Sha1Id([5; 20]), 0o040000).is_err());
+        forward.insert("src", Sha1Id([5; 20]), 0o100644).unwrap();
+        assert!(forward.build(HashAlgorithm::Sha1).is_err());
+
+        // An empty builder makes an empty root tree
+        assert_eq!(
+            1,
+            TreeBuilder::new().build(HashAlgorithm::Sha1).unwrap().len()
+        );
     }
 }
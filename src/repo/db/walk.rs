@@ -0,0 +1,211 @@
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::{BinaryHeap, HashSet},
+    fmt,
+    hash::Hash,
+};
+
+use anyhow::anyhow;
+use rusqlite::{types::FromSql, Connection, OptionalExtension, ToSql};
+
+const HAS_CREATED_AT: &str =
+    "SELECT count(*) > 0 FROM pragma_table_info('Commits') WHERE name = 'created_at'";
+const READ_WALKED_COMMIT: &str = "SELECT parent_ids, created_at FROM Commits WHERE commit_id = ?1";
+const READ_LEGACY_WALKED_COMMIT: &str = "SELECT parent_ids, 0 FROM Commits WHERE commit_id = ?1";
+
+/// [`WalkedCommit`] is a commit visited by a [`CommitWalk`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WalkedCommit<ID> {
+    pub commit_id: ID,
+    pub parent_ids: Vec<ID>,
+    /// Unix time the commit was written to the database, 0 for databases created by older
+    /// versions which do not record it
+    pub created_at: i64,
+}
+
+/// A commit waiting to be visited, the most recent one first and, between commits written
+/// at the same time, the one found first
+struct Pending<ID> {
+    created_at: i64,
+    order: usize,
+    commit_id: ID,
+}
+
+impl<ID> Pending<ID> {
+    fn key(&self) -> (i64, Reverse<usize>) {
+        (self.created_at, Reverse(self.order))
+    }
+}
+
+impl<ID> PartialEq for Pending<ID> {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl<ID> Eq for Pending<ID> {}
+
+impl<ID> PartialOrd for Pending<ID> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<ID> Ord for Pending<ID> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+/// [`CommitWalk`] visits the commits reachable from some starting commits by following
+/// `parent_ids` in the `Commits` table, each commit once, the most recently written first.
+/// It is generic over the id type so that both the database and git layers can walk history.
+pub struct CommitWalk<'a, ID> {
+    conn: &'a Connection,
+    query: &'static str,
+    pending: BinaryHeap<Pending<ID>>,
+    seen: HashSet<ID>,
+    found: usize,
+}
+
+impl<'a, ID> CommitWalk<'a, ID>
+where
+    ID: Copy + Eq + Hash + fmt::Display + ToSql + FromSql + TryFrom<Vec<u8>, Error = anyhow::Error>,
+{
+    /// Walk the history of the given commits
+    pub fn new(
+        conn: &'a Connection,
+        starts: impl IntoIterator<Item = ID>,
+    ) -> crate::Result<CommitWalk<'a, ID>> {
+        let has_created_at: bool = conn.query_row(HAS_CREATED_AT, (), |row| row.get(0))?;
+        let mut walk = CommitWalk {
+            conn,
+            query: if has_created_at {
+                READ_WALKED_COMMIT
+            } else {
+                READ_LEGACY_WALKED_COMMIT
+            },
+            pending: BinaryHeap::new(),
+            seen: HashSet::new(),
+            found: 0,
+        };
+        for id in starts {
+            walk.push(id)?;
+        }
+        Ok(walk)
+    }
+
+    fn read(&self, id: ID) -> crate::Result<(Vec<u8>, i64)> {
+        self.conn
+            .prepare_cached(self.query)?
+            .query_row([id], |row| Ok((row.get(0)?, row.get(1)?)))
+            .optional()?
+            .ok_or_else(|| anyhow!("fatal: bad object {}", id))
+    }
+
+    fn push(&mut self, id: ID) -> crate::Result<()> {
+        if !self.seen.insert(id) {
+            return Ok(());
+        }
+        let (_, created_at) = self.read(id)?;
+        self.pending.push(Pending {
+            created_at,
+            order: self.found,
+            commit_id: id,
+        });
+        self.found += 1;
+        Ok(())
+    }
+
+    fn visit(&mut self, id: ID) -> crate::Result<WalkedCommit<ID>> {
+        let (parents, created_at) = self.read(id)?;
+        let parent_ids = parents
+            .chunks(20)
+            .map(|chunk| ID::try_from(chunk.to_vec()))
+            .collect::<crate::Result<Vec<ID>>>()?;
+        for parent in &parent_ids {
+            self.push(*parent)?;
+        }
+        Ok(WalkedCommit {
+            commit_id: id,
+            parent_ids,
+            created_at,
+        })
+    }
+}
+
+impl<ID> Iterator for CommitWalk<'_, ID>
+where
+    ID: Copy + Eq + Hash + fmt::Display + ToSql + FromSql + TryFrom<Vec<u8>, Error = anyhow::Error>,
+{
+    type Item = crate::Result<WalkedCommit<ID>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let Pending { commit_id, .. } = self.pending.pop()?;
+        Some(self.visit(commit_id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::repo::db::Sha1Id;
+
+    fn insert_commit(conn: &Connection, id: u8, parents: &[u8], created_at: i64) -> Sha1Id {
+        let parent_ids: Vec<u8> = parents.iter().flat_map(|p| [*p; 20]).collect();
+        conn.execute(
+            "INSERT INTO Commits (commit_id, parent_ids, created_at) VALUES (?1, ?2, ?3)",
+            rusqlite::params![Sha1Id([id; 20]), parent_ids, created_at],
+        )
+        .unwrap();
+        Sha1Id([id; 20])
+    }
+
+    fn walk(conn: &Connection, starts: &[Sha1Id]) -> Vec<Sha1Id> {
+        CommitWalk::new(conn, starts.iter().copied())
+            .unwrap()
+            .map(|commit| commit.unwrap().commit_id)
+            .collect()
+    }
+
+    #[test]
+    fn test_commit_walk() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute(
+            "CREATE TABLE Commits (commit_id BLOB PRIMARY KEY, parent_ids BLOB NOT NULL, created_at INTEGER NOT NULL)",
+            (),
+        )
+        .unwrap();
+
+        //   1 - 2 - 4 - 5
+        //        \     /
+        //          3 --
+        let root = insert_commit(&conn, 1, &[], 100);
+        let second = insert_commit(&conn, 2, &[1], 200);
+        let side = insert_commit(&conn, 3, &[2], 400);
+        let main = insert_commit(&conn, 4, &[2], 300);
+        let merge = insert_commit(&conn, 5, &[4, 3], 500);
+
+        assert_eq!(vec![merge, side, main, second, root], walk(&conn, &[merge]));
+        assert_eq!(vec![side, main, second, root], walk(&conn, &[main, side]));
+
+        // Commits written at the same time come in the order they are found
+        conn.execute("UPDATE Commits SET created_at = 0", ())
+            .unwrap();
+        assert_eq!(vec![merge, main, side, second, root], walk(&conn, &[merge]));
+
+        let visited = CommitWalk::new(&conn, [merge])
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap();
+        assert_eq!(vec![main, side], visited.parent_ids);
+
+        let missing = CommitWalk::new(&conn, [Sha1Id([9; 20])]).err().unwrap();
+        assert_eq!(
+            format!("fatal: bad object {}", Sha1Id([9; 20])),
+            missing.to_string()
+        );
+    }
+}
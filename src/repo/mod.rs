@@ -1,17 +1,80 @@
 use std::path::{Path, PathBuf};
 
 use anyhow::anyhow;
+use rusqlite::Connection;
+
+use crate::git::utils::{find_gitqlite_root, open_gitqlite_connection};
+
+use self::db::{BlobReader, BlobWriter, HashAlgorithm, HeadState, ObjectType, Sha1Id};
 
 pub mod config;
 pub mod db;
+mod pool;
+
+pub use pool::{ReadConnection, ReadPool};
 
 /// [`Repository`] manages the lifetime of a gitqlite repository
 pub struct Repository {
     /// Repo root directory
     root: PathBuf,
+    /// The single connection writing to the database
+    conn: Connection,
+    readers: ReadPool,
 }
 
 impl Repository {
+    /// Open the repository containing `path`
+    pub fn open(path: impl AsRef<Path>) -> crate::Result<Repository> {
+        let root = find_gitqlite_root(dunce::canonicalize(path)?)?;
+        let conn = open_gitqlite_connection(&root)?;
+        let readers = ReadPool::new(&root);
+        Ok(Repository {
+            root,
+            conn,
+            readers,
+        })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn connection(&self) -> &Connection {
+        &self.conn
+    }
+
+    /// A read-only connection, which neither waits for the writes of [`Self::connection`] nor
+    /// makes them wait, seeing what they committed so far
+    pub fn read_conn(&self) -> crate::Result<ReadConnection<'_>> {
+        self.readers.get()
+    }
+
+    /// Read the content of a blob a chunk at a time
+    pub fn blob_reader(&self, id: Sha1Id) -> crate::Result<BlobReader<'_>> {
+        BlobReader::open(&self.conn, id)
+    }
+
+    /// What HEAD points to, telling a branch without commits from one with commits
+    pub fn head_state(&self) -> crate::Result<HeadState<Sha1Id>> {
+        db::read_head_state(&self.conn)
+    }
+
+    /// Whether the repository has an object with the given id, whatever its type
+    pub fn object_exists(&self, id: Sha1Id) -> crate::Result<bool> {
+        db::object_exists(&self.conn, id)
+    }
+
+    /// The type of the object with the given id, None if the repository does not have it
+    pub fn object_type(&self, id: Sha1Id) -> crate::Result<Option<ObjectType>> {
+        db::read_object_type(&self.conn, id)
+    }
+
+    /// Store a blob of `size` bytes from the content written to the returned writer, see
+    /// [`BlobWriter::finish`]
+    pub fn blob_writer(&self, size: usize) -> crate::Result<BlobWriter<'_>> {
+        BlobWriter::new(&self.conn, size, HashAlgorithm::read(&self.conn)?)
+    }
+
     /// Return the path relative to the repo root
     pub fn relative_path(&self, path: impl AsRef<Path>) -> crate::Result<PathBuf> {
         let path = dunce::canonicalize(path)?;
@@ -0,0 +1,114 @@
+use std::{ops::Deref, path::PathBuf, sync::Mutex};
+
+use rusqlite::Connection;
+
+use crate::git::utils::open_gitqlite_read_connection;
+
+/// Number of idle connections a [`ReadPool`] keeps for reuse
+const MAX_IDLE_READERS: usize = 4;
+
+/// [`ReadPool`] hands out read-only connections to the database of a repository, so that
+/// long reads run alongside the connection writing to it. Connections are opened on demand
+/// and kept for reuse once dropped.
+pub struct ReadPool {
+    root: PathBuf,
+    idle: Mutex<Vec<Connection>>,
+}
+
+impl ReadPool {
+    pub fn new(root: impl Into<PathBuf>) -> ReadPool {
+        ReadPool {
+            root: root.into(),
+            idle: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// A read-only connection, reusing an idle one if any
+    pub fn get(&self) -> crate::Result<ReadConnection<'_>> {
+        let idle = self.idle.lock().expect("read pool lock poisoned").pop();
+        let conn = match idle {
+            Some(conn) => conn,
+            None => open_gitqlite_read_connection(&self.root)?,
+        };
+        Ok(ReadConnection {
+            pool: self,
+            conn: Some(conn),
+        })
+    }
+
+    fn release(&self, conn: Connection) {
+        if let Ok(mut idle) = self.idle.lock() {
+            if idle.len() < MAX_IDLE_READERS {
+                idle.push(conn);
+            }
+        }
+    }
+}
+
+/// [`ReadConnection`] is a read-only connection borrowed from a [`ReadPool`], given back
+/// when dropped
+pub struct ReadConnection<'a> {
+    pool: &'a ReadPool,
+    conn: Option<Connection>,
+}
+
+impl Deref for ReadConnection<'_> {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        self.conn
+            .as_ref()
+            .expect("connection is only taken on drop")
+    }
+}
+
+impl Drop for ReadConnection<'_> {
+    fn drop(&mut self) {
+        if let Some(conn) = self.conn.take() {
+            self.pool.release(conn);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::git::utils::open_gitqlite_connection;
+
+    #[test]
+    fn test_read_pool() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join(".gitqlite")).unwrap();
+        // The database is in WAL mode by default
+        let writer = open_gitqlite_connection(dir.path()).unwrap();
+        writer
+            .execute_batch("CREATE TABLE Refs (ref_name TEXT); INSERT INTO Refs VALUES ('a');")
+            .unwrap();
+
+        let pool = ReadPool::new(dir.path());
+        let count = |conn: &Connection| -> i64 {
+            conn.query_row("SELECT count(*) FROM Refs", (), |row| row.get(0))
+                .unwrap()
+        };
+        let reader = pool.get().unwrap();
+        assert!(reader.execute("INSERT INTO Refs VALUES ('b')", ()).is_err());
+
+        // A reader in the middle of a transaction neither blocks the writer nor sees its
+        // writes until it starts over
+        reader.execute_batch("BEGIN").unwrap();
+        assert_eq!(1, count(&reader));
+        writer.execute("INSERT INTO Refs VALUES ('b')", ()).unwrap();
+        assert_eq!(1, count(&reader));
+        reader.execute_batch("COMMIT").unwrap();
+        assert_eq!(2, count(&reader));
+
+        // Dropped connections are reused
+        drop(reader);
+        assert_eq!(1, pool.idle.lock().unwrap().len());
+        let (first, second) = (pool.get().unwrap(), pool.get().unwrap());
+        assert!(pool.idle.lock().unwrap().is_empty());
+        assert_eq!((2, 2), (count(&first), count(&second)));
+    }
+}
@@ -0,0 +1,193 @@
+//! Runs the commands writing to the repository with `--dry-run`, checking that they leave the
+//! database and the working tree alone and report what they would have changed.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use tempfile::TempDir;
+
+/// A repository in a temporary directory, with a home directory of its own holding the
+/// identity of the committer
+struct Repo {
+    home: TempDir,
+    root: TempDir,
+}
+
+impl Repo {
+    fn new() -> Repo {
+        let home = tempfile::tempdir().unwrap();
+        fs::write(
+            home.path().join(".gitconfig"),
+            "[user]\nname = Tester\nemail = tester@example.com\n[init]\ndefaultBranch = main\n",
+        )
+        .unwrap();
+        let repo = Repo {
+            home,
+            root: tempfile::tempdir().unwrap(),
+        };
+        repo.run(&["init"]);
+        repo
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.root.path().join(name)
+    }
+
+    fn write(&self, name: &str, content: &str) {
+        fs::write(self.path(name), content).unwrap();
+    }
+
+    /// Run gitqlite in the repository, returning its output
+    fn run(&self, args: &[&str]) -> String {
+        let output = Command::new(env!("CARGO_BIN_EXE_gitqlite"))
+            .args(args)
+            .current_dir(self.root.path())
+            .env("HOME", self.home.path())
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "gitqlite {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    /// Run gitqlite with `--dry-run`, checking that nothing changed, and return the changes it
+    /// reports
+    fn dry_run(&self, args: &[&str]) -> Vec<String> {
+        let before = self.state();
+        let output = self.run(&[&["--dry-run"], args].concat());
+        assert_eq!(before, self.state(), "gitqlite {} wrote", args.join(" "));
+        output
+            .lines()
+            .filter_map(|line| line.strip_prefix("(dry run) would "))
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// The content of the database and of every file of the working tree
+    fn state(&self) -> BTreeMap<PathBuf, Vec<u8>> {
+        let mut state = BTreeMap::new();
+        let db = self.path(".gitqlite/gitqlite.db");
+        state.insert(db.clone(), fs::read(&db).unwrap());
+        read_files(self.root.path(), &mut state);
+        state
+    }
+}
+
+fn read_files(dir: &Path, files: &mut BTreeMap<PathBuf, Vec<u8>>) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.ends_with(".gitqlite") {
+            continue;
+        }
+        if path.is_dir() {
+            read_files(&path, files);
+        } else {
+            files.insert(path.clone(), fs::read(&path).unwrap());
+        }
+    }
+}
+
+/// A repository with `a` and `b` committed on main, `c` committed on the topic branch, the
+/// objects of a deleted branch, and an untracked file `d`
+fn setup() -> Repo {
+    let repo = Repo::new();
+    repo.write("a", "a\n");
+    repo.write("b", "b\n");
+    repo.run(&["add", "a"]);
+    repo.run(&["add", "b"]);
+    repo.run(&["commit", "-m", "first"]);
+
+    repo.run(&["branch", "topic"]);
+    repo.run(&["switch", "topic"]);
+    repo.write("c", "c\n");
+    repo.run(&["add", "c"]);
+    repo.run(&["commit", "-m", "topic"]);
+
+    repo.run(&["switch", "main"]);
+    repo.run(&["branch", "scratch"]);
+    repo.run(&["switch", "scratch"]);
+    repo.write("e", "e\n");
+    repo.run(&["add", "e"]);
+    repo.run(&["commit", "-m", "scratch"]);
+    repo.run(&["switch", "main"]);
+    repo.run(&["branch", "-D", "scratch"]);
+
+    repo.write("d", "d\n");
+    repo
+}
+
+/// The change moving a ref, with the ids left out
+fn ref_update(change: &str) -> &str {
+    change.split(": ").next().unwrap()
+}
+
+#[test]
+fn test_dry_run_add_and_rm() {
+    let repo = setup();
+    assert_eq!(vec!["stage d", "write 1 blob"], repo.dry_run(&["add", "d"]));
+    assert_eq!(
+        vec![
+            "unstage b".to_string(),
+            format!("remove {}", repo.path("b").display())
+        ],
+        repo.dry_run(&["rm", "b"])
+    );
+}
+
+#[test]
+fn test_dry_run_commit() {
+    let repo = setup();
+    repo.run(&["add", "d"]);
+    let changes = repo.dry_run(&["commit", "-m", "second"]);
+    assert_eq!(3, changes.len());
+    assert_eq!("update ref refs/head/main", ref_update(&changes[0]));
+    assert_eq!(["write 1 commit", "write 1 tree"], changes[1..]);
+}
+
+#[test]
+fn test_dry_run_merge() {
+    let repo = setup();
+    let changes = repo.dry_run(&["merge", "topic"]);
+    assert_eq!(3, changes.len());
+    assert_eq!("update ref refs/head/main", ref_update(&changes[0]));
+    assert_eq!(
+        [
+            "stage c".to_string(),
+            format!("write {}", repo.path("c").display())
+        ],
+        changes[1..]
+    );
+}
+
+#[test]
+fn test_dry_run_gc() {
+    let repo = setup();
+    // The commit of the deleted branch, its tree and the blob of `e`, which are still there
+    // for the next run
+    let unreachable = vec!["remove 1 commit", "remove 1 tree", "remove 1 blob"];
+    assert_eq!(unreachable, repo.dry_run(&["gc"]));
+    assert_eq!(unreachable, repo.dry_run(&["gc"]));
+}
+
+#[test]
+fn test_dry_run_refused() {
+    let repo = setup();
+    let before = repo.state();
+    let output = Command::new(env!("CARGO_BIN_EXE_gitqlite"))
+        .args(["--dry-run", "init"])
+        .current_dir(repo.root.path())
+        .env("HOME", repo.home.path())
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("init does not support --dry-run"));
+    assert_eq!(before, repo.state());
+}